@@ -0,0 +1,88 @@
+//! Context acknowledgements (`ClientConfig::ack_contexts`). A session asking for
+//! them with `x-ack-contexts` metadata gets an `AckContext` control message on the
+//! response stream for every Context the server accepted, echoing its position in
+//! the channel and any normalization the server applied before matching. A Context
+//! still unacknowledged when the server ends the stream never reached it, which is
+//! how drops by a fault-injecting proxy or a broken middlebox show up.
+
+use std::collections::{BTreeSet, HashMap};
+
+use tracing::{debug, warn};
+
+use crate::ads::AckContext;
+
+/// What became of the Contexts sent, across sessions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AckStats {
+    pub acknowledged: u64,
+    /// Unacknowledged although the server ended the stream
+    pub dropped: u64,
+    /// Unacknowledged when the session stopped listening (timeout, early exit, error)
+    pub unconfirmed: u64,
+}
+
+impl AckStats {
+    pub fn merge(&mut self, other: &AckStats) {
+        self.acknowledged += other.acknowledged;
+        self.dropped += other.dropped;
+        self.unconfirmed += other.unconfirmed;
+    }
+}
+
+/// Contexts sent on one stream and the acknowledgements received for them
+#[derive(Debug, Default)]
+pub struct AckTracker {
+    sent_per_channel: HashMap<u32, u32>,
+    // (channel_id, sequence)
+    sent: BTreeSet<(u32, u32)>,
+    acknowledged: BTreeSet<(u32, u32)>,
+}
+
+impl AckTracker {
+    /// Count a Context sent on `channel_id`; returns its sequence number
+    pub fn sent(&mut self, channel_id: u32) -> u32 {
+        let sequence = self.sent_per_channel.entry(channel_id).or_default();
+        *sequence += 1;
+        self.sent.insert((channel_id, *sequence));
+        *sequence
+    }
+
+    pub fn acknowledge(&mut self, channel_id: u32, ack: &AckContext) {
+        let key = (channel_id, ack.sequence);
+        if !self.sent.contains(&key) {
+            warn!(channel_id = channel_id, sequence = ack.sequence, "Acknowledgement of a Context never sent");
+            return;
+        }
+        if !self.acknowledged.insert(key) {
+            warn!(channel_id = channel_id, sequence = ack.sequence, "Context acknowledged twice");
+            return;
+        }
+        debug!(
+            channel_id = channel_id,
+            sequence = ack.sequence,
+            normalizations = ?ack.normalizations,
+            query = %ack.query,
+            understanding_length = ack.understanding.len(),
+            "Context acknowledged"
+        );
+    }
+
+    /// Tally the stream; `completed` when the server ended it, so every Context it
+    /// did not acknowledge was lost on the way
+    pub fn finish(&self, completed: bool) -> AckStats {
+        let missing: Vec<(u32, u32)> = self.sent.difference(&self.acknowledged).copied().collect();
+        if completed && !missing.is_empty() {
+            warn!(
+                missing = ?missing,
+                sent = self.sent.len(),
+                "Contexts never acknowledged by the server - dropped on the way"
+            );
+        }
+        let missing = missing.len() as u64;
+        AckStats {
+            acknowledged: self.acknowledged.len() as u64,
+            dropped: if completed { missing } else { 0 },
+            unconfirmed: if completed { 0 } else { missing },
+        }
+    }
+}
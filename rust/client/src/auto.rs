@@ -0,0 +1,173 @@
+//! `--auto` RPC shape selection: probe bidi, server-streaming and unary calls for
+//! each request class, then keep using the fastest, re-probing now and then so the
+//! choice follows changing network conditions.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::ads::RequestType;
+
+/// The RPC shapes the ads service offers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RpcShape {
+    /// GetAds: two Contexts, progressive versions
+    Bidi,
+    /// GetAdsServerStreaming: one fully-informed Context, progressive versions
+    ServerStreaming,
+    /// GetAdsUnary: one fully-informed Context, final version only
+    Unary,
+}
+
+impl RpcShape {
+    pub const ALL: [RpcShape; 3] = [RpcShape::Bidi, RpcShape::ServerStreaming, RpcShape::Unary];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RpcShape::Bidi => "bidi",
+            RpcShape::ServerStreaming => "server_streaming",
+            RpcShape::Unary => "unary",
+        }
+    }
+}
+
+impl fmt::Display for RpcShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Requests that are expected to behave alike: same mode, batched or not
+pub fn request_class(request_type: RequestType, batched: bool) -> String {
+    format!("{}{}", request_type.as_str_name(), if batched { "/batched" } else { "" })
+}
+
+#[derive(Debug, Clone)]
+pub struct AutoConfig {
+    /// Samples taken of every shape before the first decision
+    pub probes_per_shape: usize,
+    /// After the probe phase, every Nth request of a class re-probes another shape
+    pub reprobe_every: u64,
+    /// Latency samples kept per shape (sliding window)
+    pub window: usize,
+}
+
+impl Default for AutoConfig {
+    fn default() -> Self {
+        AutoConfig { probes_per_shape: 3, reprobe_every: 10, window: 20 }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ShapeStats {
+    latencies: VecDeque<Duration>,
+    failures: u64,
+}
+
+impl ShapeStats {
+    fn samples(&self) -> usize {
+        self.latencies.len() + self.failures as usize
+    }
+
+    /// Median latency of the successful samples; None if there are none
+    fn median(&self) -> Option<Duration> {
+        let mut sorted: Vec<Duration> = self.latencies.iter().copied().collect();
+        sorted.sort_unstable();
+        sorted.get(sorted.len() / 2).copied()
+    }
+}
+
+#[derive(Debug, Default)]
+struct ClassState {
+    shapes: HashMap<RpcShape, ShapeStats>,
+    requests: u64,
+    reprobes: u64,
+    chosen: Option<RpcShape>,
+}
+
+/// Per-request-class shape choice from observed latencies
+#[derive(Debug, Default)]
+pub struct AutoSelector {
+    config: AutoConfig,
+    classes: HashMap<String, ClassState>,
+}
+
+impl AutoSelector {
+    pub fn new(config: AutoConfig) -> Self {
+        AutoSelector { config, classes: HashMap::new() }
+    }
+
+    /// Shape to use for the next request of `class`
+    pub fn choose(&mut self, class: &str) -> RpcShape {
+        let config = &self.config;
+        let state = self.classes.entry(class.to_string()).or_default();
+        state.requests += 1;
+
+        // Probe phase: the least-sampled shape until each has enough samples
+        let least_sampled = RpcShape::ALL
+            .into_iter()
+            .min_by_key(|shape| state.shapes.get(shape).map_or(0, ShapeStats::samples))
+            .unwrap_or(RpcShape::Bidi);
+        if state.shapes.get(&least_sampled).map_or(0, ShapeStats::samples) < config.probes_per_shape {
+            info!(class = class, shape = %least_sampled, reason = "probe", "Auto RPC shape decision");
+            return least_sampled;
+        }
+
+        let best = RpcShape::ALL
+            .into_iter()
+            .filter_map(|shape| state.shapes.get(&shape).and_then(ShapeStats::median).map(|m| (shape, m)))
+            .min_by_key(|(_, median)| *median)
+            .map_or(RpcShape::Bidi, |(shape, _)| shape);
+
+        // Periodically re-probe the other shapes in turn
+        if config.reprobe_every > 0 && state.requests.is_multiple_of(config.reprobe_every) {
+            let others: Vec<RpcShape> = RpcShape::ALL.into_iter().filter(|shape| *shape != best).collect();
+            let shape = others[(state.reprobes as usize) % others.len()];
+            state.reprobes += 1;
+            info!(class = class, shape = %shape, best = %best, reason = "reprobe", "Auto RPC shape decision");
+            return shape;
+        }
+
+        if state.chosen != Some(best) {
+            let medians: Vec<String> = RpcShape::ALL
+                .into_iter()
+                .map(|shape| {
+                    let median = state.shapes.get(&shape).and_then(ShapeStats::median);
+                    format!("{}={}", shape, median.map_or("-".to_string(), |m| format!("{}ms", m.as_millis())))
+                })
+                .collect();
+            info!(
+                class = class,
+                shape = %best,
+                previous = ?state.chosen.map(|shape| shape.name()),
+                medians = %medians.join(" "),
+                reason = "best_median",
+                "Auto RPC shape decision"
+            );
+            state.chosen = Some(best);
+        }
+        best
+    }
+
+    /// Record the outcome of a request made with `shape`
+    pub fn record(&mut self, class: &str, shape: RpcShape, latency: Duration, ok: bool) {
+        let window = self.config.window.max(1);
+        let stats = self
+            .classes
+            .entry(class.to_string())
+            .or_default()
+            .shapes
+            .entry(shape)
+            .or_default();
+        if ok {
+            stats.latencies.push_back(latency);
+            while stats.latencies.len() > window {
+                stats.latencies.pop_front();
+            }
+        } else {
+            stats.failures += 1;
+        }
+    }
+}
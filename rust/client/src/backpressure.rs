@@ -0,0 +1,95 @@
+//! Bounded request channel with an explicit policy for when it is full.
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use tokio::sync::mpsc::{self, error::SendError, error::TrySendError};
+use tokio_stream::Stream;
+use tracing::warn;
+
+/// What sending a Context does when the request channel is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OverflowPolicy {
+    /// Wait until the transport drains the channel
+    #[default]
+    Block,
+    /// Discard the oldest queued Context to make room
+    DropOldest,
+    /// Fail the send (and with it the session)
+    Error,
+}
+
+impl OverflowPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OverflowPolicy::Block => "block",
+            OverflowPolicy::DropOldest => "drop_oldest",
+            OverflowPolicy::Error => "error",
+        }
+    }
+}
+
+/// Overflow occurrences across all sessions of a client
+#[derive(Debug, Default)]
+pub struct OverflowCounters {
+    /// Sends that found the channel full (under any policy)
+    pub overflows: AtomicU64,
+    /// Queued messages discarded by `DropOldest`
+    pub dropped: AtomicU64,
+}
+
+#[derive(Debug)]
+pub struct Sender<T> {
+    tx: mpsc::Sender<T>,
+    // Shared with the receiving stream so drop-oldest can evict from the sender side
+    rx: Arc<Mutex<mpsc::Receiver<T>>>,
+    policy: OverflowPolicy,
+    counters: Arc<OverflowCounters>,
+}
+
+#[derive(Debug)]
+pub struct Receiver<T> {
+    rx: Arc<Mutex<mpsc::Receiver<T>>>,
+}
+
+pub fn channel<T>(capacity: usize, policy: OverflowPolicy, counters: Arc<OverflowCounters>) -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = mpsc::channel(capacity.max(1));
+    let rx = Arc::new(Mutex::new(rx));
+    (Sender { tx, rx: rx.clone(), policy, counters }, Receiver { rx })
+}
+
+impl<T> Sender<T> {
+    pub async fn send(&self, item: T) -> Result<(), SendError<T>> {
+        let mut item = match self.tx.try_send(item) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Closed(item)) => return Err(SendError(item)),
+            Err(TrySendError::Full(item)) => item,
+        };
+        let overflows = self.counters.overflows.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!(policy = self.policy.name(), overflows_total = overflows, "Request channel full");
+        match self.policy {
+            OverflowPolicy::Block => self.tx.send(item).await,
+            OverflowPolicy::DropOldest => loop {
+                if self.rx.lock().unwrap().try_recv().is_ok() {
+                    self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                match self.tx.try_send(item) {
+                    Ok(()) => return Ok(()),
+                    Err(TrySendError::Closed(rejected)) => return Err(SendError(rejected)),
+                    Err(TrySendError::Full(rejected)) => item = rejected,
+                }
+            },
+            OverflowPolicy::Error => Err(SendError(item)),
+        }
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.rx.lock().unwrap().poll_recv(cx)
+    }
+}
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::Parser;
+use tracing::{info, warn};
+
+use ads_client::config::ClientConfig;
+use ads_client::eval::{EvalReport, Labels};
+use ads_client::AdsClient;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "ads-eval",
+    about = "Run a session per judged (query, ASIN) pair and report NDCG and MRR of every AdsList version"
+)]
+struct Args {
+    /// JSON-lines relevance judgments: {"query", "asin_id", optional "ad_id", "relevance"}
+    labels: PathBuf,
+
+    /// Server address
+    #[arg(default_value = "http://127.0.0.1:50051")]
+    server_addr: String,
+
+    /// Cut-off rank for NDCG
+    #[arg(long, default_value_t = 10)]
+    k: usize,
+
+    /// Sessions per judged pair
+    #[arg(long, default_value_t = 1)]
+    repeat: u32,
+
+    /// Session seed for reproducible server-side generation
+    #[arg(long, env = "ADS_SEED")]
+    seed: Option<u64>,
+
+    /// Refined understanding sent with the second Context
+    #[arg(long, default_value = "refined understanding based on query analysis")]
+    understanding: String,
+
+    /// Send the understanding in the first and only Context instead of the two-phase flow
+    #[arg(long, env = "ADS_SINGLE_CONTEXT")]
+    single_context: bool,
+
+    /// Interval between HTTP/2 keepalive PINGs in milliseconds
+    #[arg(long, env = "ADS_KEEPALIVE_INTERVAL_MS", default_value_t = 10_000)]
+    keepalive_interval_ms: u64,
+}
+
+#[tokio::main]
+async fn main() -> ads_common::Result<()> {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+
+    let labels = Labels::load(&args.labels)?;
+    let sessions = labels.sessions();
+    info!("Evaluating {} judged (query, ASIN) pairs from {}", sessions.len(), args.labels.display());
+    let config = ClientConfig {
+        keepalive_interval: Duration::from_millis(args.keepalive_interval_ms),
+        seed: args.seed,
+        single_context: args.single_context,
+        ..ClientConfig::default()
+    };
+    let mut client = AdsClient::new(&args.server_addr, &config).await?;
+
+    let mut report = EvalReport::new(args.k);
+    for (query, asin_id) in &sessions {
+        for _ in 0..args.repeat {
+            match client.get_ads(query.clone(), asin_id.clone(), args.understanding.clone()).await {
+                Ok(selected) => report.add_session(&labels, query, client.received_versions(), selected.as_ref()),
+                Err(e) => warn!(query = %query, asin_id = %asin_id, "Session failed: {}", e),
+            }
+        }
+    }
+    println!("{}", report.render());
+    Ok(())
+}
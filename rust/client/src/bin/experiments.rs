@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Instant;
+use clap::{Args, Parser, Subcommand};
+use tracing::{info, warn};
+
+use ads_client::config::ClientConfig;
+use ads_client::eval::{EvalReport, Labels};
+use ads_client::experiments::{Comparison, ExperimentRun, ExperimentStore};
+use ads_client::AdsClient;
+use ads_common::outcome::SessionOutcome;
+use ads_common::report;
+use ads_common::Error;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "ads-experiments",
+    about = "Record named experiment runs against a server and compare any two of them"
+)]
+struct Cli {
+    /// Directory the runs are stored in, one JSON file per run
+    #[arg(long, env = "ADS_EXPERIMENTS_DIR", default_value = "experiments")]
+    store: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run sessions against a server and record them under a name
+    Run(RunArgs),
+    /// List the stored runs, oldest first
+    List,
+    /// Print one stored run
+    Show { name: String },
+    /// Latency, error, outcome and quality deltas of run B against run A
+    Compare {
+        a: String,
+        b: String,
+        /// Write the comparison as JSON to this path
+        #[arg(long)]
+        report_json: Option<PathBuf>,
+    },
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
+    /// Name of the run
+    name: String,
+
+    /// Server address
+    #[arg(default_value = "http://127.0.0.1:50051")]
+    server_addr: String,
+
+    /// Sessions to run (per judged pair with --labels)
+    #[arg(long, default_value_t = 50)]
+    sessions: u32,
+
+    /// Search query of every session (without --labels)
+    #[arg(long, default_value = "coffee maker")]
+    query: String,
+
+    /// Product identifier of every session (without --labels)
+    #[arg(long, default_value = "B000123")]
+    asin_id: String,
+
+    /// Refined understanding sent with the second Context
+    #[arg(long, default_value = "refined understanding based on query analysis")]
+    understanding: String,
+
+    /// ads-eval relevance judgments; sessions then cover every judged (query, ASIN)
+    /// pair and the run records NDCG and MRR per version
+    #[arg(long)]
+    labels: Option<PathBuf>,
+
+    /// Cut-off rank for NDCG
+    #[arg(long, default_value_t = 10)]
+    k: usize,
+
+    /// Session seed for reproducible server-side generation
+    #[arg(long, env = "ADS_SEED")]
+    seed: Option<u64>,
+
+    /// Send the understanding in the first and only Context instead of the two-phase flow
+    #[arg(long, env = "ADS_SINGLE_CONTEXT")]
+    single_context: bool,
+
+    /// Setting KEY=VALUE the client cannot observe, e.g. server.quality_gate=ndcg
+    /// (repeatable); recorded with the run and part of its config hash
+    #[arg(long = "param", value_name = "KEY=VALUE", value_parser = parse_param)]
+    params: Vec<(String, String)>,
+
+    /// Replace a stored run of the same name
+    #[arg(long)]
+    force: bool,
+}
+
+fn parse_param(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got {:?}", s)),
+    }
+}
+
+impl RunArgs {
+    // Everything that shapes the run's measurements, hashed into its config hash
+    fn config(&self) -> BTreeMap<String, String> {
+        let mut config = BTreeMap::from([
+            ("client.server_addr".to_string(), self.server_addr.clone()),
+            ("client.understanding".to_string(), self.understanding.clone()),
+            ("client.seed".to_string(), self.seed.map_or("-".to_string(), |seed| seed.to_string())),
+            ("client.single_context".to_string(), self.single_context.to_string()),
+            ("client.sessions".to_string(), self.sessions.to_string()),
+        ]);
+        match &self.labels {
+            Some(labels) => {
+                config.insert("client.labels".to_string(), labels.display().to_string());
+                config.insert("client.k".to_string(), self.k.to_string());
+            }
+            None => {
+                config.insert("client.query".to_string(), self.query.clone());
+                config.insert("client.asin_id".to_string(), self.asin_id.clone());
+            }
+        }
+        config.extend(self.params.iter().cloned());
+        config
+    }
+}
+
+async fn run(store: &ExperimentStore, args: RunArgs) -> ads_common::Result<()> {
+    if store.contains(&args.name) && !args.force {
+        return Err(Error::config(format!("experiment run {:?} already exists (use --force to replace it)", args.name)));
+    }
+    let labels = args.labels.as_ref().map(|path| Labels::load(path)).transpose()?;
+    let pairs = match &labels {
+        Some(labels) => labels.sessions(),
+        None => vec![(args.query.clone(), args.asin_id.clone())],
+    };
+    let config = ClientConfig { seed: args.seed, single_context: args.single_context, ..ClientConfig::default() };
+    let mut client = AdsClient::new(&args.server_addr, &config).await?;
+
+    let mut experiment = ExperimentRun::new(&args.name, args.config());
+    let mut eval = EvalReport::new(args.k);
+    let mut latencies_ms = Vec::new();
+    info!(
+        name = %args.name,
+        config_hash = %experiment.config_hash,
+        sessions = pairs.len() as u64 * args.sessions as u64,
+        "Starting experiment run"
+    );
+    for (query, asin_id) in &pairs {
+        for _ in 0..args.sessions {
+            let start = Instant::now();
+            let result = client.get_ads(query.clone(), asin_id.clone(), args.understanding.clone()).await;
+            latencies_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+            experiment.sessions += 1;
+            match result {
+                Ok(selected) => {
+                    if let Some(labels) = &labels {
+                        eval.add_session(labels, query, client.received_versions(), selected.as_ref());
+                    }
+                }
+                Err(e) => {
+                    warn!(query = %query, asin_id = %asin_id, error = %e, "Session failed");
+                    *experiment.errors.entry(e.kind().to_string()).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    let outcomes = client.outcomes();
+    experiment.outcomes = SessionOutcome::ALL
+        .iter()
+        .map(|outcome| (outcome.name().to_string(), outcomes.count(*outcome)))
+        .filter(|(_, count)| *count > 0)
+        .collect();
+    latencies_ms.sort_by(f64::total_cmp);
+    if !latencies_ms.is_empty() {
+        let mean = latencies_ms.iter().sum::<f64>() / latencies_ms.len() as f64;
+        experiment.latency_ms.insert("mean".to_string(), mean);
+    }
+    for (q, ms) in report::quantiles(&latencies_ms) {
+        experiment.latency_ms.insert(format!("p{}", (q * 100.0).round()), ms);
+    }
+    if labels.is_some() {
+        experiment.quality = eval.metrics();
+    }
+    let path = store.save(&experiment)?;
+    print!("{}", experiment.render());
+    println!("Recorded to {}", path.display());
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ads_common::Result<()> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::WARN).init();
+    let cli = Cli::parse();
+    let store = ExperimentStore::open(&cli.store)?;
+
+    match cli.command {
+        Command::Run(args) => run(&store, args).await?,
+        Command::List => {
+            for run in store.list()? {
+                println!(
+                    "{:<24}  config={}  git={:<9}  recorded_unix_secs={}  sessions={}  p50={}",
+                    run.name,
+                    run.config_hash,
+                    run.git_rev.as_deref().unwrap_or("-"),
+                    run.recorded_unix_secs,
+                    run.sessions,
+                    run.latency_ms.get("p50").map_or("-".to_string(), |ms| format!("{:.1}ms", ms)),
+                );
+            }
+        }
+        Command::Show { name } => print!("{}", store.load(&name)?.render()),
+        Command::Compare { a, b, report_json } => {
+            let comparison = Comparison::compare(&store.load(&a)?, &store.load(&b)?);
+            print!("{}", comparison.render());
+            if let Some(path) = &report_json {
+                std::fs::write(path, serde_json::to_string_pretty(&comparison)?)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::Parser;
+
+use ads_client::ads::RequestType;
+use ads_client::config::ClientConfig;
+use ads_client::{connect, AdsClient};
+use ads_client::load::{self, LoadPlan, RampProfile, TrafficModel};
+use ads_client::replay::RecordedSession;
+use ads_proto::admin::admin_service_client::AdminServiceClient;
+use ads_proto::admin::CaptureProfileRequest;
+use tonic::transport::Channel;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "ads-load",
+    about = "Load-test a server with closed-loop (fixed concurrency) or open-loop (Poisson arrivals) traffic"
+)]
+struct Args {
+    /// Server address
+    #[arg(default_value = "http://127.0.0.1:50051")]
+    server_addr: String,
+
+    /// Closed: fixed concurrency, sessions back to back; open: Poisson arrivals at --rps
+    #[arg(long, value_enum, default_value = "closed")]
+    model: TrafficModel,
+
+    /// Sessions in flight at full load (closed loop)
+    #[arg(long, default_value_t = 8)]
+    concurrency: u32,
+
+    /// Session starts per second at full load (open loop)
+    #[arg(long, default_value_t = 20.0)]
+    rps: f64,
+
+    /// Length of the run in seconds
+    #[arg(long, default_value_t = 10)]
+    duration_secs: u64,
+
+    /// How load develops over the run
+    #[arg(long, value_enum, default_value = "constant")]
+    ramp: RampProfile,
+
+    /// Steps of the step ramp
+    #[arg(long, default_value_t = 4)]
+    steps: u32,
+
+    /// Load multiplier over the middle tenth of the run for the spike ramp
+    #[arg(long, default_value_t = 3.0)]
+    spike_factor: f64,
+
+    /// Open loop: drop (and count) arrivals while this many sessions are in flight
+    #[arg(long, default_value_t = 1000)]
+    max_in_flight: usize,
+
+    /// Seed of the open-loop arrival schedule
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+
+    /// Search query of every session
+    #[arg(long, default_value = "coffee maker")]
+    query: String,
+
+    /// Product identifier of every session
+    #[arg(long, default_value = "B000123")]
+    asin_id: String,
+
+    /// Send a traceparent with every session and write the latency histogram, with
+    /// trace-id exemplars of the slowest sessions, to this file as OpenMetrics text
+    #[arg(long)]
+    openmetrics: Option<PathBuf>,
+
+    /// Send the understanding in the first and only Context instead of the two-phase flow
+    #[arg(long, env = "ADS_SINGLE_CONTEXT")]
+    single_context: bool,
+
+    /// Priority class of every session (x-priority): interactive or batch
+    #[arg(long, env = "ADS_PRIORITY", value_parser = ["interactive", "batch"])]
+    priority: Option<String>,
+
+    /// Interval between HTTP/2 keepalive PINGs in milliseconds
+    #[arg(long, env = "ADS_KEEPALIVE_INTERVAL_MS", default_value_t = 10_000)]
+    keepalive_interval_ms: u64,
+
+    /// Handshakes, followed by one throwaway request, sent on the shared connection
+    /// before the run so the first sessions aren't measured cold (0 = no warm-up)
+    #[arg(long, default_value_t = 4)]
+    warm_up: u32,
+
+    /// Capture a server CPU flamegraph over this many seconds of the run through the
+    /// admin CaptureProfile RPC (0 = off; the server needs the `profiling` feature)
+    #[arg(long, default_value_t = 0)]
+    profile_secs: u64,
+
+    /// Seconds into the run the profile starts, so it can skip the ramp-up
+    #[arg(long, default_value_t = 0)]
+    profile_delay_secs: u64,
+
+    /// Where the flamegraph SVG is written
+    #[arg(long, default_value = "flamegraph.svg")]
+    profile_out: PathBuf,
+}
+
+// Profile the server while the run is under way and write the flamegraph
+async fn capture_profile(channel: Channel, delay: Duration, duration: Duration, out: PathBuf) {
+    tokio::time::sleep(delay).await;
+    let request = CaptureProfileRequest { duration_ms: duration.as_millis() as u32, frequency_hz: 0 };
+    match AdminServiceClient::new(channel).capture_profile(request).await {
+        Ok(response) => {
+            let profile = response.into_inner();
+            match std::fs::write(&out, &profile.flamegraph_svg) {
+                Ok(()) => eprintln!(
+                    "Server CPU profile: {} samples over {} ms written to {}",
+                    profile.samples,
+                    profile.duration_ms,
+                    out.display()
+                ),
+                Err(e) => tracing::warn!(path = %out.display(), error = %e, "Could not write flamegraph"),
+            }
+        }
+        Err(status) => tracing::warn!(code = ?status.code(), message = status.message(), "Server profile capture failed"),
+    }
+}
+
+#[tokio::main]
+async fn main() -> ads_common::Result<()> {
+    tracing_subscriber::fmt().with_max_level(tracing::Level::WARN).init();
+    let args = Args::parse();
+
+    let config = ClientConfig {
+        keepalive_interval: Duration::from_millis(args.keepalive_interval_ms),
+        trace_context: args.openmetrics.is_some(),
+        single_context: args.single_context,
+        priority: args.priority.clone(),
+        ..ClientConfig::default()
+    };
+    let plan = LoadPlan {
+        model: args.model,
+        concurrency: args.concurrency,
+        rps: args.rps,
+        duration: Duration::from_secs(args.duration_secs),
+        ramp: args.ramp,
+        steps: args.steps,
+        spike_factor: args.spike_factor,
+        max_in_flight: args.max_in_flight,
+        seed: args.seed,
+    };
+    let session = RecordedSession {
+        query: args.query,
+        asin_id: args.asin_id,
+        understanding: "refined understanding based on query analysis".to_string(),
+        seed: None,
+        request_type: RequestType::Keyword.as_str_name().to_string(),
+    };
+
+    let channel = connect(&args.server_addr, &config).await?;
+    if args.warm_up > 0 {
+        AdsClient::from_service(channel.clone(), &args.server_addr, &config).warm_up(args.warm_up, true).await;
+    }
+    let profile = (args.profile_secs > 0).then(|| {
+        tokio::spawn(capture_profile(
+            channel.clone(),
+            Duration::from_secs(args.profile_delay_secs),
+            Duration::from_secs(args.profile_secs),
+            args.profile_out.clone(),
+        ))
+    });
+    let report = load::run(channel, &args.server_addr, &config, &session, &plan).await;
+    print!("{}", report.render());
+    if let Some(profile) = profile {
+        let _ = profile.await;
+    }
+    if let Some(path) = &args.openmetrics {
+        std::fs::write(path, report.to_openmetrics())?;
+    }
+    Ok(())
+}
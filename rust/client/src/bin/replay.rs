@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::Parser;
+use tracing::info;
+
+use ads_client::config::ClientConfig;
+use ads_client::replay::{read_sessions, replay, ComparisonReport};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "ads-replay",
+    about = "Replay recorded sessions against two servers and compare rankings, latency and version wins"
+)]
+struct Args {
+    /// JSON-lines recording written by `ads-client --record`
+    sessions: PathBuf,
+
+    /// Baseline server address
+    baseline: String,
+
+    /// Candidate server address (another build, or the same build with a different config)
+    candidate: String,
+
+    /// Write the full report as JSON to this path
+    #[arg(long)]
+    report_json: Option<PathBuf>,
+
+    /// Interval between HTTP/2 keepalive PINGs in milliseconds
+    #[arg(long, env = "ADS_KEEPALIVE_INTERVAL_MS", default_value_t = 10_000)]
+    keepalive_interval_ms: u64,
+}
+
+#[tokio::main]
+async fn main() -> ads_common::Result<()> {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+
+    let sessions = read_sessions(&args.sessions)?;
+    info!("Replaying {} sessions from {}", sessions.len(), args.sessions.display());
+    let config = ClientConfig {
+        keepalive_interval: Duration::from_millis(args.keepalive_interval_ms),
+        ..ClientConfig::default()
+    };
+
+    let baseline = replay(&args.baseline, &sessions, &config).await?;
+    let candidate = replay(&args.candidate, &sessions, &config).await?;
+    let report = ComparisonReport::compare(&sessions, &args.baseline, &baseline, &args.candidate, &candidate);
+
+    print!("{}", report.render());
+    if let Some(path) = &args.report_json {
+        std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
+        info!("Wrote comparison report to {}", path.display());
+    }
+    Ok(())
+}
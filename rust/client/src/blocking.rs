@@ -0,0 +1,99 @@
+//! Synchronous facade over `AdsClient` for callers without an async runtime, such as
+//! build scripts, sync test harnesses or small scripting tools.
+//!
+//! `blocking::AdsClient` owns a current-thread tokio runtime and drives every call
+//! to completion on it, like reqwest's `blocking` module:
+//!
+//! ```no_run
+//! use ads_client::blocking;
+//! use ads_client::config::ClientConfig;
+//!
+//! let mut client = blocking::AdsClient::new("http://127.0.0.1:50051", &ClientConfig::default())?;
+//! let ads = client.get_ads("coffee maker", "B000123", "refined understanding")?;
+//! # Ok::<(), ads_client::error::AdsClientError>(())
+//! ```
+//!
+//! Its methods must not be called from within an async runtime: blocking a runtime
+//! thread on another runtime panics. Async code uses `ads_client::AdsClient` directly.
+
+use ads_common::outcome::OutcomeFunnel;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::ack::AckStats;
+use crate::ads::{AdsList, RequestType};
+use crate::breaker::BreakerStats;
+use crate::config::ClientConfig;
+use crate::error::AdsClientError;
+use crate::ordering::OrderingStats;
+use crate::signing::SignatureStats;
+
+/// `crate::AdsClient` with synchronous methods
+pub struct AdsClient {
+    inner: crate::AdsClient,
+    runtime: Runtime,
+}
+
+impl AdsClient {
+    /// Start the client's runtime and connect to `server_addr`
+    pub fn new(server_addr: &str, config: &ClientConfig) -> Result<Self, AdsClientError> {
+        let runtime = Builder::new_current_thread().enable_all().build().map_err(AdsClientError::Runtime)?;
+        let inner = runtime.block_on(crate::AdsClient::new(server_addr, config))?;
+        Ok(AdsClient { inner, runtime })
+    }
+
+    /// One bidirectional session, as `crate::AdsClient::get_ads`
+    pub fn get_ads(
+        &mut self,
+        query: impl Into<String>,
+        asin_id: impl Into<String>,
+        understanding: impl Into<String>,
+    ) -> Result<Option<AdsList>, AdsClientError> {
+        self.runtime.block_on(self.inner.get_ads(query.into(), asin_id.into(), understanding.into()))
+    }
+
+    /// One session with retries and maintenance redirects, as
+    /// `crate::AdsClient::get_ads_with_redirects`
+    pub fn get_ads_with_retry(
+        &mut self,
+        query: impl Into<String>,
+        asin_id: impl Into<String>,
+        understanding: impl Into<String>,
+    ) -> Result<Option<AdsList>, AdsClientError> {
+        self.runtime.block_on(self.inner.get_ads_with_redirects(query.into(), asin_id.into(), understanding.into()))
+    }
+
+    /// Seed and request type of subsequent sessions
+    pub fn configure_session(&mut self, seed: Option<u64>, request_type: RequestType) {
+        self.inner.configure_session(seed, request_type);
+    }
+
+    /// Every AdsList version received in the last session, by ascending version
+    pub fn received_versions(&self) -> &[AdsList] {
+        self.inner.received_versions()
+    }
+
+    pub fn ordering_stats(&self) -> OrderingStats {
+        self.inner.ordering_stats()
+    }
+
+    pub fn signature_stats(&self) -> SignatureStats {
+        self.inner.signature_stats()
+    }
+
+    pub fn ack_stats(&self) -> AckStats {
+        self.inner.ack_stats()
+    }
+
+    pub fn breaker_stats(&self) -> BreakerStats {
+        self.inner.breaker_stats()
+    }
+
+    pub fn outcomes(&self) -> OutcomeFunnel {
+        self.inner.outcomes()
+    }
+
+    /// The async client, for anything the facade does not wrap
+    pub fn inner(&self) -> &crate::AdsClient {
+        &self.inner
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Settings for the per-endpoint circuit breaker and the retry budget layered under it
+#[derive(Debug, Clone)]
+pub struct BreakerConfig {
+    /// Number of most recent session outcomes used to compute the error rate
+    pub window: usize,
+    /// Minimum outcomes in the window before the breaker may open
+    pub min_requests: usize,
+    /// Error rate (0.0..=1.0) at which the breaker opens
+    pub failure_threshold: f64,
+    /// How long the breaker stays open before letting probes through
+    pub open_duration: Duration,
+    /// Consecutive successful probes needed to close a half-open breaker
+    pub half_open_probes: u32,
+    /// Probes a half-open breaker lets through at once; later attempts are refused
+    /// until one reports back. A probe silent for `open_duration` counts as lost.
+    pub half_open_max_in_flight: u32,
+    /// Retries allowed as a fraction of first attempts (e.g. 0.2 = one retry per five sessions)
+    pub retry_budget_ratio: f64,
+    /// Retries always allowed regardless of the ratio, so a lone session can still retry
+    pub retry_budget_min: u64,
+    /// Maximum retries for a single session
+    pub max_retries: u32,
+    /// Backoff before the first retry; doubled on each subsequent retry
+    pub base_backoff: Duration,
+    /// Longest server retry-after hint honored; a session told to wait longer fails
+    /// instead of retrying
+    pub max_retry_after: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        BreakerConfig {
+            window: 20,
+            min_requests: 5,
+            failure_threshold: 0.5,
+            open_duration: Duration::from_secs(5),
+            half_open_probes: 2,
+            half_open_max_in_flight: 1,
+            retry_budget_ratio: 0.2,
+            retry_budget_min: 3,
+            max_retries: 2,
+            base_backoff: Duration::from_millis(20),
+            max_retry_after: Duration::from_secs(2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    Closed,
+    Open { until: Instant },
+    HalfOpen { successes: u32, in_flight: u32, last_probe: Instant },
+}
+
+impl BreakerState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open { .. } => "open",
+            BreakerState::HalfOpen { .. } => "half_open",
+        }
+    }
+}
+
+/// State changes and refused attempts of one breaker
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BreakerStats {
+    pub opened: u64,
+    pub half_opened: u64,
+    pub closed: u64,
+    /// Attempts refused while open
+    pub rejected_open: u64,
+    /// Attempts refused while half-open with every probe slot taken
+    pub rejected_half_open: u64,
+}
+
+impl BreakerStats {
+    pub fn rejected(&self) -> u64 {
+        self.rejected_open + self.rejected_half_open
+    }
+
+    pub fn merge(&mut self, other: &BreakerStats) {
+        self.opened += other.opened;
+        self.half_opened += other.half_opened;
+        self.closed += other.closed;
+        self.rejected_open += other.rejected_open;
+        self.rejected_half_open += other.rejected_half_open;
+    }
+}
+
+/// Circuit breaker tracking the rolling error rate of one endpoint, plus the retry
+/// budget that caps how many retries may be issued relative to first attempts.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    endpoint: String,
+    config: BreakerConfig,
+    outcomes: VecDeque<bool>,
+    state: BreakerState,
+    attempts: u64,
+    retries: u64,
+    stats: BreakerStats,
+}
+
+impl CircuitBreaker {
+    pub fn new(endpoint: &str, config: BreakerConfig) -> Self {
+        CircuitBreaker {
+            endpoint: endpoint.to_string(),
+            config,
+            outcomes: VecDeque::new(),
+            state: BreakerState::Closed,
+            attempts: 0,
+            retries: 0,
+            stats: BreakerStats::default(),
+        }
+    }
+
+    pub fn config(&self) -> &BreakerConfig {
+        &self.config
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.state
+    }
+
+    pub fn stats(&self) -> BreakerStats {
+        self.stats
+    }
+
+    /// Whether a new attempt may be sent to the endpoint right now; a half-open
+    /// breaker admits it as a probe only while a probe slot is free
+    pub fn allow(&mut self) -> bool {
+        let now = Instant::now();
+        match self.state {
+            BreakerState::Closed => true,
+            BreakerState::HalfOpen { successes, in_flight, last_probe } => {
+                let lost = now >= last_probe + self.config.open_duration;
+                if in_flight >= self.config.half_open_max_in_flight && !lost {
+                    self.stats.rejected_half_open += 1;
+                    return false;
+                }
+                let in_flight = if lost { 1 } else { in_flight + 1 };
+                self.state = BreakerState::HalfOpen { successes, in_flight, last_probe: now };
+                true
+            }
+            BreakerState::Open { until } if now >= until => {
+                self.transition(BreakerState::HalfOpen { successes: 0, in_flight: 1, last_probe: now });
+                true
+            }
+            BreakerState::Open { .. } => {
+                self.stats.rejected_open += 1;
+                false
+            }
+        }
+    }
+
+    /// Record a first attempt (as opposed to a retry) for budget accounting
+    pub fn record_attempt(&mut self) {
+        self.attempts += 1;
+    }
+
+    /// Consume one retry from the budget if any is left
+    pub fn try_acquire_retry(&mut self) -> bool {
+        let allowed = self.config.retry_budget_min
+            + (self.attempts as f64 * self.config.retry_budget_ratio) as u64;
+        if self.retries >= allowed {
+            warn!(
+                endpoint = %self.endpoint,
+                retries = self.retries,
+                attempts = self.attempts,
+                "Retry budget exhausted"
+            );
+            return false;
+        }
+        self.retries += 1;
+        true
+    }
+
+    pub fn record(&mut self, success: bool) {
+        self.outcomes.push_back(success);
+        while self.outcomes.len() > self.config.window {
+            self.outcomes.pop_front();
+        }
+
+        match self.state {
+            BreakerState::HalfOpen { successes, in_flight, last_probe } if success => {
+                if successes + 1 >= self.config.half_open_probes {
+                    self.outcomes.clear();
+                    self.transition(BreakerState::Closed);
+                } else {
+                    let in_flight = in_flight.saturating_sub(1);
+                    self.state = BreakerState::HalfOpen { successes: successes + 1, in_flight, last_probe };
+                }
+            }
+            BreakerState::HalfOpen { .. } => self.trip(),
+            BreakerState::Closed if self.outcomes.len() >= self.config.min_requests
+                && self.error_rate() >= self.config.failure_threshold =>
+            {
+                self.trip()
+            }
+            _ => {}
+        }
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        if self.outcomes.is_empty() {
+            return 0.0;
+        }
+        let failures = self.outcomes.iter().filter(|ok| !**ok).count();
+        failures as f64 / self.outcomes.len() as f64
+    }
+
+    fn trip(&mut self) {
+        self.transition(BreakerState::Open {
+            until: Instant::now() + self.config.open_duration,
+        });
+    }
+
+    fn transition(&mut self, next: BreakerState) {
+        let level_warn = matches!(next, BreakerState::Open { .. });
+        if level_warn {
+            warn!(
+                endpoint = %self.endpoint,
+                from = self.state.name(),
+                to = next.name(),
+                error_rate = format!("{:.2}", self.error_rate()),
+                open_ms = self.config.open_duration.as_millis() as u64,
+                "Circuit breaker state change"
+            );
+        } else {
+            info!(
+                endpoint = %self.endpoint,
+                from = self.state.name(),
+                to = next.name(),
+                error_rate = format!("{:.2}", self.error_rate()),
+                "Circuit breaker state change"
+            );
+        }
+        match next {
+            BreakerState::Closed => self.stats.closed += 1,
+            BreakerState::Open { .. } => self.stats.opened += 1,
+            BreakerState::HalfOpen { .. } => self.stats.half_opened += 1,
+        }
+        self.state = next;
+    }
+}
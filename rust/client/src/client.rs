@@ -0,0 +1,1364 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::{sleep, timeout};
+use tonic::body::BoxBody;
+use tonic::client::GrpcService;
+use tonic::codec::CompressionEncoding;
+use ads_common::limits::FINAL_VERSION;
+use ads_common::outcome::{OutcomeFunnel, SessionEnd, SessionOutcome};
+use ads_proto::score::{sanitize_list, TieBreak};
+use ads_proto::{
+    unix_us, ACK_CONTEXTS_METADATA_KEY, DEBUG_SESSION_METADATA_KEY, GENERATOR_METADATA_KEY, IDEMPOTENCY_KEY_METADATA_KEY, LABEL_METADATA_PREFIX, LIMIT_WARNINGS_METADATA_KEY, PRIORITY_METADATA_KEY, REQUEST_ID_METADATA_KEY,
+    RESUME_TOKEN_METADATA_KEY, SESSION_TOKEN_METADATA_KEY, TRACEPARENT_METADATA_KEY,
+};
+use prost::Message;
+use tonic::codegen::{Body, Bytes, StdError};
+use tonic::metadata::{MetadataKey, MetadataMap, MetadataValue};
+use tonic::{transport::{Certificate, Channel, ClientTlsConfig, Endpoint}, Request, Status};
+use rand::Rng;
+use tracing::{info, warn, error, debug, span, Level};
+
+use crate::ads::{ads_list, ads_service_client::AdsServiceClient, Ad, AdsList, Context, HandshakeRequest, NoFillReason, Placement, RequestType, ScoreNormalization, SessionFeedback};
+use crate::ack::{AckStats, AckTracker};
+use crate::auto::RpcShape;
+use crate::backpressure::{self, OverflowCounters, OverflowPolicy};
+use crate::breaker::{BreakerState, BreakerStats, CircuitBreaker};
+use crate::clock::ClockSkew;
+use crate::compression::{self, Compression};
+use crate::config::{ClientConfig, ClientTls};
+use crate::context::{self, ContextBuilder};
+use crate::dial::{self, HappyEyeballs};
+use crate::error::{is_connection_lost, AdsClientError};
+use crate::limits::ResponseLimits;
+use crate::ordering::{OrderTracker, OrderingStats, Resolution, VersionConflictPolicy};
+use crate::rerank::RerankHook;
+use crate::retry::{RetryChannel, ServiceConfig};
+use crate::selection::{merge_versions, EarlyExit, SelectionStats, SelectionStrategy};
+use crate::signing::{ResponseVerifier, SignatureStats};
+use crate::understanding::UnderstandingSim;
+
+/// Ads client over any gRPC transport: a TCP channel from `AdsClient::new` (retrying
+/// per the configured service config), or any tower service via
+/// `AdsClient::from_service` (e.g. an in-process `AdsServiceServer` for deterministic
+/// tests and single-binary simulations)
+pub struct AdsClient<T = RetryChannel> {
+    client: AdsServiceClient<T>,
+    endpoint: String,
+    seed: Option<u64>,
+    request_type: RequestType,
+    batch_queries: Vec<String>,
+    placements: Vec<Placement>,
+    selection: SelectionStrategy,
+    early_exit: Option<EarlyExit>,
+    selection_timeout: Option<Duration>,
+    renormalize: ScoreNormalization,
+    tie_break: TieBreak,
+    rerank: Option<RerankHook>,
+    selection_stats: SelectionStats,
+    breaker: CircuitBreaker,
+    idempotency_keys: bool,
+    labels: Vec<(String, String)>,
+    generator_variant: Option<String>,
+    priority: Option<String>,
+    debug_session: bool,
+    follow_redirects: bool,
+    keepalive_interval: Duration,
+    keepalive_timeout: Duration,
+    happy_eyeballs: HappyEyeballs,
+    tls: Option<ClientTls>,
+    service_config: Option<ServiceConfig>,
+    request_channel_capacity: usize,
+    request_overflow: OverflowPolicy,
+    overflow_counters: Arc<OverflowCounters>,
+    ordering_stats: OrderingStats,
+    version_conflict: VersionConflictPolicy,
+    verifier: Option<ResponseVerifier>,
+    signature_stats: SignatureStats,
+    ack_contexts: bool,
+    ack_stats: AckStats,
+    report_feedback: bool,
+    // x-request-id of the last session, until its feedback is sent
+    last_request_id: Option<String>,
+    outcomes: OutcomeFunnel,
+    last_outcome: Option<SessionOutcome>,
+    // How the last bidirectional stream's receive loop ended
+    stream_end: SessionEnd,
+    compression: Option<Compression>,
+    clock_probes: u32,
+    clock_skew: Option<ClockSkew>,
+    received_versions: Vec<AdsList>,
+    // Token of the last bidirectional stream on a server checkpointing sessions
+    session_token: Option<String>,
+    understanding_sim: Option<UnderstandingSim>,
+    single_context: bool,
+    trace_context: bool,
+    last_trace_id: Option<String>,
+    // Set once the server has advertised the configured encoding in grpc-accept-encoding;
+    // until then Contexts go out uncompressed so an old server never sees an unknown encoding
+    compression_negotiated: bool,
+    stall_gap: Option<Duration>,
+    stall_flush: bool,
+    locale: String,
+    response_limits: ResponseLimits,
+}
+
+/// Maintenance redirects followed for one session before giving up (guards against loops)
+const MAX_REDIRECTS: u32 = 3;
+
+/// How long a ReportFeedback call may hold up the next session
+const FEEDBACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Label marking the throwaway session of `warm_up`, so the server side can leave it out
+const WARM_UP_LABEL: &str = "warm-up";
+
+/// What `AdsClient::warm_up` did before the first measured session
+#[derive(Debug, Clone, Default)]
+pub struct WarmUpReport {
+    /// Handshakes answered by the server
+    pub handshakes: u32,
+    /// Round trips of the first and the last handshake; their gap is the setup cost
+    /// the measured sessions no longer pay
+    pub first_rtt: Option<Duration>,
+    pub last_rtt: Option<Duration>,
+    /// Whether the throwaway unary request succeeded
+    pub throwaway_ok: Option<bool>,
+    pub elapsed: Duration,
+}
+
+/// Random result selection timeout between 30-120ms with jitter
+fn random_selection_timeout_ms() -> u64 {
+    let mut rng = rand::thread_rng();
+    let base_timeout: i64 = rng.gen_range(30..=120);
+    let jitter = rng.gen_range(-5..=5);
+    (base_timeout + jitter).clamp(30, 120) as u64
+}
+
+/// Milliseconds of `budget` left since `start`
+fn remaining_ms(budget: Duration, start: Instant) -> u32 {
+    budget.saturating_sub(start.elapsed()).as_millis() as u32
+}
+
+/// Split the budget spent on the Context a version answers into server time (as
+/// reported through remaining_budget_ms) and transport time (the rest of what the
+/// client observed). Version 1 answers the first Context, later versions the second.
+fn log_budget_consumption(ads_list: &AdsList, budget_at_send: [(Instant, u32); 2]) {
+    let (sent_at, budget_ms) = budget_at_send[if ads_list.version <= 1 { 0 } else { 1 }];
+    if budget_ms == 0 {
+        return;
+    }
+    let observed_ms = sent_at.elapsed().as_millis() as u64;
+    let server_ms = budget_ms.saturating_sub(ads_list.remaining_budget_ms) as u64;
+    info!(
+        version = ads_list.version,
+        budget_ms = budget_ms,
+        server_ms = server_ms,
+        transport_ms = observed_ms.saturating_sub(server_ms),
+        remaining_budget_ms = (budget_ms as u64).saturating_sub(observed_ms),
+        budget_exhausted = ads_list.budget_exhausted,
+        "Latency budget consumed"
+    );
+}
+
+// How a stream that ended with `status` counts towards the session outcome
+fn stream_end(status: &Status) -> SessionEnd {
+    if status.code() == tonic::Code::Cancelled {
+        SessionEnd::Cancelled
+    } else {
+        SessionEnd::Failed
+    }
+}
+
+/// An explicitly empty list: the server had no ad worth showing
+fn is_no_fill(ads_list: &AdsList) -> bool {
+    ads_list.no_fill_reason() != NoFillReason::Unspecified
+}
+
+/// Soft limits the server let the session go over, from its trailers (or the
+/// response metadata of a unary call)
+fn log_limit_warnings(metadata: &MetadataMap) {
+    if let Some(warnings) = metadata.get(LIMIT_WARNINGS_METADATA_KEY).and_then(|value| value.to_str().ok()) {
+        warn!(limit_warnings = %warnings, "Server reported exceeded soft limits");
+    }
+}
+
+/// Ads of the selected list as served, partitions in order, with their treatment tags
+fn served_ads(ads_list: &AdsList) -> Vec<Ad> {
+    ads_list
+        .ads
+        .iter()
+        .chain(ads_list.query_results.iter().flat_map(|partition| &partition.ads))
+        .chain(ads_list.placement_results.iter().flat_map(|partition| &partition.ads))
+        .cloned()
+        .collect()
+}
+
+/// Clamp invalid scores from a misbehaving server before they reach merging and
+/// output, where a NaN would otherwise rank arbitrarily
+fn sanitize_received(ads_list: &mut AdsList) {
+    for (ad_id, invalid) in sanitize_list(ads_list) {
+        warn!(ad_id = %ad_id, version = ads_list.version, reason = invalid.name(), "Clamped invalid score from server");
+    }
+}
+
+/// Open a channel to the server with the configured HTTP/2 keepalive settings. A
+/// server given by hostname is reached over whichever of its addresses answers
+/// first (see `dial`); reconnects of the channel race the addresses again. With
+/// TLS an `http://` address is dialed as `https://`.
+pub async fn connect(server_addr: &str, config: &ClientConfig) -> Result<Channel, AdsClientError> {
+    info!(
+        keepalive_interval_ms = config.keepalive_interval.as_millis() as u64,
+        keepalive_timeout_ms = config.keepalive_timeout.as_millis() as u64,
+        tls = config.tls.is_some(),
+        "Connecting to server at {}", server_addr
+    );
+    let server_addr = match (&config.tls, server_addr.strip_prefix("http://")) {
+        (Some(_), Some(rest)) => format!("https://{}", rest),
+        _ => server_addr.to_string(),
+    };
+    let mut endpoint = Endpoint::from_shared(server_addr)?
+        .http2_keep_alive_interval(config.keepalive_interval)
+        .keep_alive_timeout(config.keepalive_timeout)
+        .keep_alive_while_idle(true);
+    if let Some(tls) = &config.tls {
+        endpoint = endpoint.tls_config(tls_config(tls))?;
+    }
+    if dial::is_ip_literal(endpoint.uri()) {
+        return Ok(endpoint.connect().await?);
+    }
+    let happy_eyeballs = config.happy_eyeballs;
+    let channel = endpoint
+        .connect_with_connector(tower::service_fn(move |uri| happy_eyeballs.dial(uri)))
+        .await?;
+    Ok(channel)
+}
+
+fn tls_config(tls: &ClientTls) -> ClientTlsConfig {
+    let mut config = ClientTlsConfig::new();
+    if let Some(pem) = &tls.ca_certificate {
+        config = config.ca_certificate(Certificate::from_pem(pem));
+    }
+    if let Some(domain_name) = &tls.domain_name {
+        config = config.domain_name(domain_name.clone());
+    }
+    config
+}
+
+impl AdsClient<RetryChannel> {
+    /// Create a new AdsClient and connect to the server
+    pub async fn new(server_addr: &str, config: &ClientConfig) -> Result<Self, AdsClientError> {
+        let channel = RetryChannel::new(connect(server_addr, config).await?, config.service_config.clone());
+        let mut client = AdsClient::from_service(channel, server_addr, config);
+        client.estimate_clock_skew().await;
+        Ok(client)
+    }
+
+    /// `get_ads_with_retry`, moving to the alternate endpoint named by a server in
+    /// maintenance when `follow_redirects` is set. Later sessions stay on the new endpoint.
+    pub async fn get_ads_with_redirects(
+        &mut self,
+        query: String,
+        asin_id: String,
+        understanding: String,
+    ) -> Result<Option<AdsList>, AdsClientError> {
+        let mut redirects = 0;
+        loop {
+            let result = self.get_ads_with_retry(query.clone(), asin_id.clone(), understanding.clone()).await;
+            let Some(endpoint) = result.as_ref().err().and_then(AdsClientError::redirect_endpoint) else {
+                return result;
+            };
+            if !self.follow_redirects || redirects >= MAX_REDIRECTS {
+                warn!(
+                    redirect_endpoint = %endpoint,
+                    redirects = redirects,
+                    "Server in maintenance named an alternate endpoint - not following"
+                );
+                return result;
+            }
+            redirects += 1;
+            info!(from = %self.endpoint, to = %endpoint, "Following maintenance redirect");
+            self.reconnect(&endpoint).await?;
+        }
+    }
+
+    /// Point this client at another server; per-endpoint state starts over
+    async fn reconnect(&mut self, endpoint: &str) -> Result<(), AdsClientError> {
+        let config = ClientConfig {
+            keepalive_interval: self.keepalive_interval,
+            keepalive_timeout: self.keepalive_timeout,
+            happy_eyeballs: self.happy_eyeballs,
+            tls: self.tls.clone(),
+            ..ClientConfig::default()
+        };
+        let channel = RetryChannel::new(connect(endpoint, &config).await?, self.service_config.clone());
+        self.client = AdsServiceClient::new(channel)
+            .accept_compressed(CompressionEncoding::Gzip)
+            .max_decoding_message_size(self.response_limits.max_message_bytes);
+        self.breaker = CircuitBreaker::new(endpoint, self.breaker.config().clone());
+        self.endpoint = endpoint.to_string();
+        self.compression_negotiated = false;
+        self.estimate_clock_skew().await;
+        Ok(())
+    }
+}
+
+impl<T> AdsClient<T>
+where
+    T: GrpcService<BoxBody> + Clone,
+    T::Error: Into<StdError>,
+    T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+    <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+{
+    /// Build a client on an existing transport. `endpoint` only labels logs and the
+    /// circuit breaker; keepalive settings in `config` apply to `new` alone.
+    pub fn from_service(service: T, endpoint: &str, config: &ClientConfig) -> Self {
+        AdsClient {
+            client: AdsServiceClient::new(service)
+                .accept_compressed(CompressionEncoding::Gzip)
+                .max_decoding_message_size(config.response_limits.max_message_bytes),
+            endpoint: endpoint.to_string(),
+            seed: config.seed,
+            request_type: config.request_type,
+            batch_queries: config.batch_queries.clone(),
+            placements: config.placements.clone(),
+            selection: config.selection,
+            early_exit: config.early_exit,
+            selection_timeout: config.selection_timeout,
+            renormalize: config.renormalize,
+            tie_break: config.tie_break,
+            rerank: config.rerank.clone(),
+            selection_stats: SelectionStats::default(),
+            breaker: CircuitBreaker::new(endpoint, config.breaker.clone()),
+            idempotency_keys: config.idempotency_keys,
+            labels: config.labels.clone(),
+            generator_variant: config.generator_variant.clone(),
+            priority: config.priority.clone(),
+            debug_session: config.debug_session,
+            follow_redirects: config.follow_redirects,
+            keepalive_interval: config.keepalive_interval,
+            keepalive_timeout: config.keepalive_timeout,
+            happy_eyeballs: config.happy_eyeballs,
+            tls: config.tls.clone(),
+            service_config: config.service_config.clone(),
+            request_channel_capacity: config.request_channel_capacity,
+            request_overflow: config.request_overflow,
+            overflow_counters: Arc::new(OverflowCounters::default()),
+            ordering_stats: OrderingStats::default(),
+            version_conflict: config.version_conflict,
+            verifier: config.verify_key,
+            signature_stats: SignatureStats::default(),
+            ack_contexts: config.ack_contexts,
+            ack_stats: AckStats::default(),
+            report_feedback: config.report_feedback,
+            last_request_id: None,
+            outcomes: OutcomeFunnel::default(),
+            last_outcome: None,
+            stream_end: SessionEnd::Completed,
+            compression: config.compression,
+            clock_probes: config.clock_probes,
+            clock_skew: None,
+            received_versions: Vec::new(),
+            session_token: None,
+            understanding_sim: config.understanding_sim.clone(),
+            single_context: config.single_context,
+            trace_context: config.trace_context,
+            last_trace_id: None,
+            compression_negotiated: false,
+            stall_gap: config.stall_gap,
+            stall_flush: config.stall_flush,
+            locale: config.locale.clone().unwrap_or_default(),
+            response_limits: config.response_limits,
+        }
+    }
+
+    /// Enable request compression for later sessions once the server advertises it.
+    /// The current session's request stream is already open, so it stays uncompressed.
+    fn negotiate_compression(&mut self, metadata: &tonic::metadata::MetadataMap) {
+        let Some(compression) = self.compression else { return };
+        if self.compression_negotiated {
+            return;
+        }
+        if compression::server_accepts(metadata, compression) {
+            self.client = self.client.clone().send_compressed(compression.encoding());
+            self.compression_negotiated = true;
+            info!(encoding = compression.name(), "Server accepts compressed requests - enabling for later sessions");
+        } else {
+            debug!(encoding = compression.name(), "Server did not advertise encoding - sending uncompressed");
+        }
+    }
+
+    /// Estimate the server's clock offset from `clock_probes` handshakes, keeping the
+    /// sample with the shortest round trip. A server without the handshake RPC (or
+    /// zero probes) leaves the estimate unset and latencies uncorrected.
+    pub async fn estimate_clock_skew(&mut self) -> Option<ClockSkew> {
+        let mut samples = Vec::new();
+        for _ in 0..self.clock_probes {
+            let request = HandshakeRequest { client_send_unix_us: unix_us() };
+            match self.client.handshake(request).await {
+                Ok(response) => {
+                    let response = response.into_inner();
+                    samples.push(ClockSkew::from_exchange(
+                        response.client_send_unix_us,
+                        response.server_receive_unix_us,
+                        response.server_send_unix_us,
+                        unix_us(),
+                    ));
+                }
+                Err(e) => {
+                    warn!(error = %e, "Clock handshake failed - cross-process latencies stay uncorrected");
+                    break;
+                }
+            }
+        }
+        self.clock_skew = ClockSkew::best(samples);
+        if let Some(skew) = self.clock_skew {
+            info!(
+                offset_us = skew.offset_us,
+                rtt_us = skew.rtt_us,
+                probes = self.clock_probes,
+                "Clock skew estimated"
+            );
+        }
+        self.clock_skew
+    }
+
+    /// Drive `n` handshakes through the connection, and with `throwaway` one unary
+    /// request, so the first measured session pays neither connection nor HTTP/2
+    /// stream setup nor the server's first-request costs. The handshakes also settle
+    /// compression negotiation. A server without the handshake RPC still gets the
+    /// throwaway request; failures are logged, never returned.
+    pub async fn warm_up(&mut self, n: u32, throwaway: bool) -> WarmUpReport {
+        let start = Instant::now();
+        let mut report = WarmUpReport::default();
+        for _ in 0..n {
+            let sent = Instant::now();
+            match self.client.handshake(HandshakeRequest { client_send_unix_us: unix_us() }).await {
+                Ok(response) => {
+                    let rtt = sent.elapsed();
+                    report.handshakes += 1;
+                    report.first_rtt.get_or_insert(rtt);
+                    report.last_rtt = Some(rtt);
+                    self.negotiate_compression(response.metadata());
+                }
+                Err(e) => {
+                    warn!(error = %e, "Warm-up handshake failed");
+                    break;
+                }
+            }
+        }
+        if throwaway {
+            let context = ContextBuilder::new("warm up", "B000WARMUP")
+                .seed(self.seed)
+                .request_type(self.request_type)
+                .build_complete();
+            let result = match context {
+                Ok(context) => {
+                    let mut request = Request::new(context);
+                    self.attach_labels(&mut request);
+                    let label = format!("{}{}", LABEL_METADATA_PREFIX, WARM_UP_LABEL);
+                    if let Ok(name) = MetadataKey::from_bytes(label.as_bytes()) {
+                        request.metadata_mut().insert(name, "true".parse().unwrap());
+                    }
+                    self.client.get_ads_unary(request).await.map(|_| ()).map_err(AdsClientError::from)
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = &result {
+                warn!(error = %e, "Warm-up request failed");
+            }
+            report.throwaway_ok = Some(result.is_ok());
+        }
+        report.elapsed = start.elapsed();
+        info!(
+            handshakes = report.handshakes,
+            first_rtt_us = report.first_rtt.map(|rtt| rtt.as_micros() as u64),
+            last_rtt_us = report.last_rtt.map(|rtt| rtt.as_micros() as u64),
+            throwaway_ok = report.throwaway_ok,
+            elapsed_ms = report.elapsed.as_millis() as u64,
+            "Connection warmed up"
+        );
+        report
+    }
+
+    /// Every AdsList the last bidirectional session received before selection, by
+    /// ascending version, as sent by the server (before merging or re-ranking)
+    pub fn received_versions(&self) -> &[AdsList] {
+        &self.received_versions
+    }
+
+    /// Trace id sent with the last session, when trace context is enabled
+    pub fn last_trace_id(&self) -> Option<&str> {
+        self.last_trace_id.as_deref()
+    }
+
+    /// State of this endpoint's circuit breaker
+    pub fn breaker_state(&self) -> BreakerState {
+        self.breaker.state()
+    }
+
+    /// State changes and refused sessions of this endpoint's circuit breaker
+    pub fn breaker_stats(&self) -> BreakerStats {
+        self.breaker.stats()
+    }
+
+    /// Current estimate of the server clock's offset, if any
+    pub fn clock_skew(&self) -> Option<ClockSkew> {
+        self.clock_skew
+    }
+
+    /// Request channel overflows across all sessions of this client
+    pub fn overflow_counters(&self) -> &OverflowCounters {
+        &self.overflow_counters
+    }
+
+    /// AdsLists received out of version order across all sessions of this client
+    pub fn ordering_stats(&self) -> OrderingStats {
+        self.ordering_stats
+    }
+
+    /// Signature verdicts of received AdsLists across all sessions of this client
+    /// (all zero unless `ClientConfig::verify_key` is set)
+    pub fn signature_stats(&self) -> SignatureStats {
+        self.signature_stats
+    }
+
+    /// Acknowledgements of sent Contexts across all bidirectional sessions of this
+    /// client (all zero unless `ClientConfig::ack_contexts` is set)
+    pub fn ack_stats(&self) -> AckStats {
+        self.ack_stats
+    }
+
+    /// Outcomes of all sessions (each retry attempt counts) of this client
+    pub fn outcomes(&self) -> OutcomeFunnel {
+        self.outcomes
+    }
+
+    /// Outcome of the last session
+    pub fn last_outcome(&self) -> Option<SessionOutcome> {
+        self.last_outcome
+    }
+
+    /// `no_fill` when the list of `highest_version` was an explicit no-fill
+    fn record_outcome(&mut self, end: SessionEnd, highest_version: u32, no_fill: bool) {
+        let outcome = SessionOutcome::classify(end, highest_version, no_fill);
+        self.outcomes.record(outcome);
+        self.last_outcome = Some(outcome);
+        info!(outcome = outcome.name(), highest_version = highest_version, "Session outcome");
+    }
+
+    /// Install (or remove) the re-ranking applied to selected AdsLists of later sessions
+    pub fn set_rerank_hook(&mut self, hook: Option<RerankHook>) {
+        self.rerank = hook;
+    }
+
+    /// Change the seed and request type used for subsequent sessions on this connection
+    pub fn configure_session(&mut self, seed: Option<u64>, request_type: RequestType) {
+        self.seed = seed;
+        self.request_type = request_type;
+    }
+
+    /// Get ads, retrying retryable failures within the retry budget while the
+    /// endpoint's circuit breaker allows it
+    pub async fn get_ads_with_retry(
+        &mut self,
+        query: String,
+        asin_id: String,
+        understanding: String,
+    ) -> Result<Option<AdsList>, AdsClientError> {
+        self.breaker.record_attempt();
+        let idempotency_key = self.idempotency_keys.then(|| format!("{:016x}", rand::random::<u64>()));
+        let mut retries = 0;
+        // A retry of a stream the server issued a session token for resumes from its checkpoints
+        let mut resume_token: Option<String> = None;
+        loop {
+            if !self.breaker.allow() {
+                warn!(breaker_state = self.breaker.state().name(), "Circuit open - not sending session");
+                return Err(AdsClientError::CircuitOpen { endpoint: self.endpoint.clone() });
+            }
+            let result = self
+                .get_ads_keyed(
+                    query.clone(),
+                    asin_id.clone(),
+                    understanding.clone(),
+                    idempotency_key.as_deref(),
+                    resume_token.as_deref(),
+                )
+                .await;
+            self.breaker.record(result.is_ok());
+            let error = match result {
+                Ok(ads) => return Ok(ads),
+                Err(e) => e,
+            };
+            // Retrying a server in maintenance is pointless when its redirect will be followed
+            let redirected = self.follow_redirects && error.redirect_endpoint().is_some();
+            // A server that shed the session says how long to wait
+            let hint = error.backpressure_hint();
+            let retry_after = hint.and_then(|hint| hint.retry_after);
+            if retry_after.is_some_and(|after| after > self.breaker.config().max_retry_after) {
+                warn!(
+                    error = %error,
+                    retry_after_ms = retry_after.map(|after| after.as_millis() as u64),
+                    queue_depth = hint.and_then(|hint| hint.queue_depth),
+                    "Server asks to retry later than allowed - not retrying"
+                );
+                return Err(error);
+            }
+            if !error.is_retryable()
+                || redirected
+                || retries >= self.breaker.config().max_retries
+                || !self.breaker.try_acquire_retry()
+            {
+                return Err(error);
+            }
+            retries += 1;
+            if let Some(token) = self.session_token.take() {
+                resume_token = Some(token);
+            }
+            let backoff = retry_after.unwrap_or(self.breaker.config().base_backoff * 2u32.pow(retries - 1));
+            warn!(
+                layer = "application",
+                error = %error,
+                retry = retries,
+                backoff_ms = backoff.as_millis() as u64,
+                backoff_source = if retry_after.is_some() { "server_hint" } else { "exponential" },
+                queue_depth = hint.and_then(|hint| hint.queue_depth),
+                breaker_state = self.breaker.state().name(),
+                breaker_error_rate = format!("{:.2}", self.breaker.error_rate()),
+                "Retrying session"
+            );
+            sleep(backoff).await;
+        }
+    }
+
+    /// Result selection timeout of the next session
+    fn selection_timeout(&self) -> Duration {
+        self.selection_timeout
+            .unwrap_or_else(|| Duration::from_millis(random_selection_timeout_ms()))
+    }
+
+    // A decoder status refusing an oversized list becomes a limit violation
+    fn stream_error(&self, status: Status) -> AdsClientError {
+        match self.response_limits.violation_of(&status) {
+            Some(violation) => AdsClientError::ResponseLimit(violation),
+            None => status.into(),
+        }
+    }
+
+    /// Experiment labels travel as `x-label-*` request metadata, the requested
+    /// generator variant as `x-generator` and the priority class as `x-priority`
+    fn attach_labels<R>(&self, request: &mut Request<R>) {
+        if let Some(variant) = &self.generator_variant {
+            match variant.parse() {
+                Ok(value) => {
+                    request.metadata_mut().insert(GENERATOR_METADATA_KEY, value);
+                }
+                Err(_) => warn!(generator = %variant, "Skipping generator variant not representable as metadata"),
+            }
+        }
+        if let Some(priority) = &self.priority {
+            match priority.parse() {
+                Ok(value) => {
+                    request.metadata_mut().insert(PRIORITY_METADATA_KEY, value);
+                }
+                Err(_) => warn!(priority = %priority, "Skipping priority not representable as metadata"),
+            }
+        }
+        if self.debug_session {
+            request.metadata_mut().insert(DEBUG_SESSION_METADATA_KEY, MetadataValue::from_static("true"));
+        }
+        for (key, value) in &self.labels {
+            let name = format!("{}{}", LABEL_METADATA_PREFIX, key.to_ascii_lowercase());
+            match (MetadataKey::from_bytes(name.as_bytes()), value.parse()) {
+                (Ok(name), Ok(value)) => {
+                    request.metadata_mut().insert(name, value);
+                }
+                _ => warn!(label = %key, "Skipping label not representable as metadata"),
+            }
+        }
+    }
+
+    /// Start a new trace for the session of `request` with a `traceparent` header
+    fn attach_trace<R>(&mut self, request: &mut Request<R>) {
+        self.last_trace_id = None;
+        if !self.trace_context {
+            return;
+        }
+        let mut rng = rand::thread_rng();
+        let trace_id = format!("{:032x}", rng.gen::<u128>() | 1);
+        let traceparent = format!("00-{}-{:016x}-01", trace_id, rng.gen::<u64>() | 1);
+        if let Ok(value) = traceparent.parse() {
+            request.metadata_mut().insert(TRACEPARENT_METADATA_KEY, value);
+            self.last_trace_id = Some(trace_id);
+        }
+    }
+
+    /// Get ads with the given RPC shape. The single-Context shapes send one
+    /// fully-informed Context and observe the same random selection timeout.
+    pub async fn get_ads_with_shape(
+        &mut self,
+        shape: RpcShape,
+        query: String,
+        asin_id: String,
+        understanding: String,
+    ) -> Result<Option<AdsList>, AdsClientError> {
+        if shape == RpcShape::Bidi {
+            return self.get_ads(query, asin_id, understanding).await;
+        }
+        self.last_request_id = None;
+        let result = self.single_context_session(shape, query, asin_id, understanding).await;
+        if self.report_feedback {
+            let latest = result.as_ref().ok().and_then(Option::as_ref);
+            let served = latest.map_or_else(Vec::new, served_ads);
+            self.send_feedback(latest.map_or(0, |l| l.version), served).await;
+        }
+        result
+    }
+
+    /// One server-streaming or unary session
+    async fn single_context_session(
+        &mut self,
+        shape: RpcShape,
+        query: String,
+        asin_id: String,
+        understanding: String,
+    ) -> Result<Option<AdsList>, AdsClientError> {
+        let timeout_duration = self.selection_timeout();
+        let mut context = ContextBuilder::new(query, asin_id)
+            .understanding(understanding)
+            .seed(self.seed)
+            .request_type(self.request_type)
+            .queries(self.batch_queries.clone())
+            .placements(self.placements.clone())
+            .locale(self.locale.clone())
+            .build_refined()?;
+        context.latency_budget_ms = timeout_duration.as_millis() as u32;
+        let mut request = Request::new(context);
+        let request_id = format!("{:016x}", rand::random::<u64>());
+        if let Ok(value) = request_id.parse() {
+            request.metadata_mut().insert(REQUEST_ID_METADATA_KEY, value);
+        }
+        self.last_request_id = Some(request_id);
+        self.attach_labels(&mut request);
+        self.attach_trace(&mut request);
+        let start = Instant::now();
+
+        if shape == RpcShape::Unary {
+            return match timeout(timeout_duration, self.client.get_ads_unary(request)).await {
+                Ok(Err(status)) => {
+                    self.record_outcome(stream_end(&status), 0, false);
+                    Err(self.stream_error(status))
+                }
+                Ok(Ok(response)) => {
+                    log_limit_warnings(response.metadata());
+                    let mut ads_list = response.into_inner();
+                    if let Err(violation) = self.response_limits.check(&ads_list) {
+                        self.record_outcome(SessionEnd::Failed, 0, false);
+                        return Err(AdsClientError::ResponseLimit(violation));
+                    }
+                    self.record_outcome(SessionEnd::Completed, ads_list.version, is_no_fill(&ads_list));
+                    if let Some(verifier) = &self.verifier {
+                        verifier.check(&ads_list, &mut self.signature_stats);
+                    }
+                    sanitize_received(&mut ads_list);
+                    info!(
+                        shape = shape.name(),
+                        version = ads_list.version,
+                        elapsed_ms = start.elapsed().as_millis() as u64,
+                        "Received AdsList"
+                    );
+                    Ok(Some(ads_list))
+                }
+                Err(_) => {
+                    warn!(shape = shape.name(), timeout_ms = timeout_duration.as_millis() as u64, "FINAL RESULT: No AdsList received within timeout");
+                    self.record_outcome(SessionEnd::TimedOut, 0, false);
+                    Ok(None)
+                }
+            };
+        }
+
+        let mut stream = match self.client.get_ads_server_streaming(request).await {
+            Ok(response) => response.into_inner(),
+            Err(status) => {
+                self.record_outcome(stream_end(&status), 0, false);
+                return Err(status.into());
+            }
+        };
+        let mut latest: Option<AdsList> = None;
+        let verifier = self.verifier;
+        let mut signatures = SignatureStats::default();
+        let response_limits = self.response_limits;
+        let mut over_limit = None;
+        let receive = async {
+            while let Some(mut ads_list) = stream.message().await? {
+                if let Err(violation) = response_limits.check(&ads_list) {
+                    over_limit = Some(violation);
+                    break;
+                }
+                if let Some(verifier) = &verifier {
+                    verifier.check(&ads_list, &mut signatures);
+                }
+                sanitize_received(&mut ads_list);
+                info!(
+                    shape = shape.name(),
+                    version = ads_list.version,
+                    elapsed_ms = start.elapsed().as_millis() as u64,
+                    "Received AdsList"
+                );
+                if latest.as_ref().is_none_or(|l| ads_list.version > l.version) {
+                    latest = Some(ads_list);
+                }
+            }
+            Ok::<(), Status>(())
+        };
+        let result = timeout(timeout_duration, receive).await;
+        self.signature_stats.merge(&signatures);
+        let highest_version = latest.as_ref().map_or(0, |l| l.version);
+        let no_fill = latest.as_ref().is_some_and(is_no_fill);
+        if let Ok(Err(e)) = &result {
+            over_limit = over_limit.or_else(|| self.response_limits.violation_of(e));
+        }
+        let end = match &result {
+            _ if over_limit.is_some() => SessionEnd::Failed,
+            Ok(Ok(())) => SessionEnd::Completed,
+            Ok(Err(e)) => stream_end(e),
+            Err(_) => SessionEnd::TimedOut,
+        };
+        self.record_outcome(end, highest_version, no_fill);
+        if let Some(violation) = over_limit {
+            return Err(AdsClientError::ResponseLimit(violation));
+        }
+        if let Ok(Ok(())) = result {
+            if let Ok(Some(trailers)) = stream.trailers().await {
+                log_limit_warnings(&trailers);
+            }
+        }
+        match result {
+            Ok(Err(e)) if latest.is_none() => return Err(e.into()),
+            Ok(Err(e)) => warn!(error = %e, "Stream error occurred"),
+            Ok(Ok(())) | Err(_) => {}
+        }
+        Ok(latest)
+    }
+
+    /// Get ads using bidirectional streaming with the specified context
+    pub async fn get_ads(
+        &mut self,
+        query: String,
+        asin_id: String,
+        understanding: String,
+    ) -> Result<Option<AdsList>, AdsClientError> {
+        self.get_ads_keyed(query, asin_id, understanding, None, None).await
+    }
+
+    async fn get_ads_keyed(
+        &mut self,
+        query: String,
+        asin_id: String,
+        understanding: String,
+        idempotency_key: Option<&str>,
+        resume_token: Option<&str>,
+    ) -> Result<Option<AdsList>, AdsClientError> {
+        self.stream_end = SessionEnd::Completed;
+        self.last_request_id = None;
+        let result = self.bidi_session(query, asin_id, understanding, idempotency_key, resume_token).await;
+        let latest = self.received_versions.last();
+        let (end, highest_version, no_fill) = match &result {
+            Ok(_) => (self.stream_end, latest.map_or(0, |l| l.version), latest.is_some_and(is_no_fill)),
+            Err(AdsClientError::Status(status)) => (stream_end(status), 0, false),
+            Err(_) => (SessionEnd::Failed, 0, false),
+        };
+        self.record_outcome(end, highest_version, no_fill);
+        if self.report_feedback {
+            let served = result.as_ref().ok().and_then(Option::as_ref).map_or_else(Vec::new, served_ads);
+            self.send_feedback(highest_version, served).await;
+        }
+        result
+    }
+
+    /// Tell the server the version the last session used (0 = none), so
+    /// the generation of later versions counts as wasted, along with the ads served
+    /// and the session outcome for its per-treatment metrics
+    async fn send_feedback(&mut self, selected_version: u32, served_ads: Vec<Ad>) {
+        let Some(request_id) = self.last_request_id.take() else { return };
+        let feedback = SessionFeedback {
+            request_id: request_id.clone(),
+            selected_version,
+            served_ads,
+            outcome: self.last_outcome.map_or_else(String::new, |outcome| outcome.name().to_string()),
+        };
+        match timeout(FEEDBACK_TIMEOUT, self.client.report_feedback(feedback)).await {
+            Ok(Ok(response)) => debug!(
+                request_id = %request_id,
+                selected_version = selected_version,
+                matched = response.into_inner().matched,
+                "Reported session feedback"
+            ),
+            Ok(Err(status)) => warn!(request_id = %request_id, error = %status, "Session feedback failed"),
+            Err(_) => warn!(request_id = %request_id, "Session feedback timed out"),
+        }
+    }
+
+    async fn bidi_session(
+        &mut self,
+        query: String,
+        asin_id: String,
+        understanding: String,
+        idempotency_key: Option<&str>,
+        resume_token: Option<&str>,
+    ) -> Result<Option<AdsList>, AdsClientError> {
+        let overall_start = Instant::now();
+        self.received_versions.clear();
+        self.session_token = None;
+        // A simulated understanding replaces the given one, arriving after its own delay
+        let (understanding_delay, understanding) = match &mut self.understanding_sim {
+            Some(sim) => sim.simulate(&query),
+            None => (context::DEFAULT_UNDERSTANDING_DELAY, understanding),
+        };
+        let request_id = format!("{:016x}", rand::random::<u64>());
+        self.last_request_id = Some(request_id.clone());
+        let span = span!(Level::INFO, "bidirectional_stream", 
+                        request_id = %request_id,
+                        query = %query, 
+                        asin_id = %asin_id, 
+                        request_type = ?self.request_type,
+                        understanding_provided = !understanding.is_empty());
+        let _enter = span.enter();
+        
+        info!(
+            request_id = %request_id,
+            idempotency_key = idempotency_key,
+            resume_token = resume_token,
+            query = %query,
+            asin_id = %asin_id,
+            understanding_provided = !understanding.is_empty(),
+            understanding_delay_ms = understanding_delay.as_millis() as u64,
+            single_context = self.single_context,
+            "Starting bidirectional stream"
+        );
+        
+        // Build and validate both Contexts before opening the stream; in single-context
+        // mode the first one is already fully informed and the second is never sent
+        let contexts = ContextBuilder::new(query.clone(), asin_id.clone())
+            .with_understanding_after(understanding.clone(), understanding_delay)
+            .seed(self.seed)
+            .request_type(self.request_type)
+            .queries(self.batch_queries.clone())
+            .placements(self.placements.clone())
+            .locale(self.locale.clone());
+        let mut first_context =
+            if self.single_context { contexts.build_complete()? } else { contexts.build_initial()? };
+        let mut second_context = contexts.build_refined()?;
+        
+        // Random timeout between 30-120ms with jitter, unless one is configured
+        let timeout_duration = self.selection_timeout();
+        let timeout_ms = timeout_duration.as_millis() as u64;
+        
+        info!(
+            timeout_ms = timeout_ms,
+            fixed = self.selection_timeout.is_some(),
+            "Result selection timeout"
+        );
+        
+        // The session's end-to-end budget covers the understanding delay plus result
+        // selection; each Context carries what is left of it when sent
+        let latency_budget = contexts.understanding_delay() + timeout_duration;
+        let mut budget_at_send: [(Instant, u32); 2] = [(overall_start, 0); 2];
+        
+        // Create a channel for sending Context messages
+        let (tx, request_stream) = backpressure::channel(
+            self.request_channel_capacity,
+            self.request_overflow,
+            self.overflow_counters.clone(),
+        );
+        
+        // Start the bidirectional stream
+        let mut request = Request::new(request_stream);
+        if let Ok(value) = request_id.parse() {
+            request.metadata_mut().insert(REQUEST_ID_METADATA_KEY, value);
+        }
+        if let Some(value) = idempotency_key.and_then(|key| key.parse().ok()) {
+            request.metadata_mut().insert(IDEMPOTENCY_KEY_METADATA_KEY, value);
+        }
+        if let Some(value) = resume_token.and_then(|token| token.parse().ok()) {
+            request.metadata_mut().insert(RESUME_TOKEN_METADATA_KEY, value);
+        }
+        if self.ack_contexts {
+            request.metadata_mut().insert(ACK_CONTEXTS_METADATA_KEY, MetadataValue::from_static("true"));
+        }
+        self.attach_labels(&mut request);
+        self.attach_trace(&mut request);
+        if let Some(trace_id) = &self.last_trace_id {
+            info!(request_id = %request_id, trace_id = %trace_id, "Session traced");
+        }
+        let response = self.client
+            .get_ads(request)
+            .await?;
+        self.negotiate_compression(response.metadata());
+        self.session_token = response
+            .metadata()
+            .get(SESSION_TOKEN_METADATA_KEY)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let mut response_stream = response.into_inner();
+        let active_compression = self.compression.filter(|_| self.compression_negotiated);
+        
+        // Buffer for AdsList messages by version
+        let mut ads_buffer: HashMap<u32, AdsList> = HashMap::new();
+        let mut acks = AckTracker::default();
+        
+        // The understanding is only ready after its delay, so a single Context waits for it
+        let understanding_delay = contexts.understanding_delay();
+        if self.single_context {
+            debug!(delay_ms = understanding_delay.as_millis() as u64, "Waiting for understanding before the only Context");
+            sleep(understanding_delay).await;
+        }
+        
+        // Send first Context message
+        info!(
+            context_number = 1,
+            understanding_empty = first_context.understanding.is_empty(),
+            seed = ?self.seed,
+            elapsed_ms = overall_start.elapsed().as_millis() as u64,
+            "Sending Context message"
+        );
+        first_context.latency_budget_ms = remaining_ms(latency_budget, overall_start);
+        budget_at_send[0] = (Instant::now(), first_context.latency_budget_ms);
+        log_context_size(&first_context, active_compression);
+        acks.sent(first_context.channel_id);
+        tx.send(first_context).await
+            .map_err(|e| AdsClientError::Send(format!("Failed to send first context: {}", e)))?;
+        
+        if self.single_context {
+            // Every version answers the one Context
+            budget_at_send[1] = budget_at_send[0];
+        } else {
+            // Wait before sending second Context
+            debug!(delay_ms = understanding_delay.as_millis() as u64, "Waiting before second Context message");
+            sleep(understanding_delay).await;
+            
+            // Send second Context message with understanding
+            info!(
+                context_number = 2,
+                understanding_length = understanding.len(),
+                elapsed_ms = overall_start.elapsed().as_millis() as u64,
+                "Sending Context message"
+            );
+            second_context.latency_budget_ms = remaining_ms(latency_budget, overall_start);
+            budget_at_send[1] = (Instant::now(), second_context.latency_budget_ms);
+            log_context_size(&second_context, active_compression);
+            acks.sent(second_context.channel_id);
+            tx.send(second_context).await
+                .map_err(|e| AdsClientError::Send(format!("Failed to send second context: {}", e)))?;
+        }
+        
+        // Close the sending side (half-close). A stall watchdog that flushes keeps it
+        // open for the flush until the final version arrives
+        let mut flush_tx = None;
+        if self.stall_flush && self.stall_gap.is_some() {
+            flush_tx = Some(tx);
+        } else {
+            drop(tx);
+            info!(
+                elapsed_ms = overall_start.elapsed().as_millis() as u64,
+                "Half-closed client stream"
+            );
+        }
+        
+        // Track when the stream last showed signs of life so a dead connection can be
+        // told apart from a slow server
+        let mut last_activity = Instant::now();
+        let mut exited_early = false;
+        let early_exit = self.early_exit;
+        let receive_start = Instant::now();
+        let mut order_tracker = OrderTracker::default();
+        let version_conflict = self.version_conflict;
+        let mut conflict = None;
+        let mut clock_skew = self.clock_skew;
+        let verifier = self.verifier;
+        let mut signatures = SignatureStats::default();
+        let stall_gap = self.stall_gap;
+        let mut stalled = false;
+        let response_limits = self.response_limits;
+        let mut over_limit = None;
+        
+        // Start receiving responses and apply timeout
+        let receive_task = async {
+            loop {
+                // The stall watchdog arms once a list has arrived and fires once per session
+                let next = match stall_gap.filter(|_| !ads_buffer.is_empty() && !stalled) {
+                    Some(gap) => match timeout(gap, response_stream.message()).await {
+                        Ok(next) => next?,
+                        Err(_) => {
+                            stalled = true;
+                            warn!(
+                                gap_ms = gap.as_millis() as u64,
+                                versions_received = ads_buffer.len(),
+                                elapsed_ms = overall_start.elapsed().as_millis() as u64,
+                                flush = flush_tx.is_some(),
+                                "Stream stalled - no AdsList within the stall gap"
+                            );
+                            if let Some(tx) = &flush_tx {
+                                let flush = Context { flush: true, ..Default::default() };
+                                if tx.send(flush).await.is_err() {
+                                    debug!("Flush not sent - request stream already closed");
+                                }
+                            }
+                            continue;
+                        }
+                    },
+                    None => response_stream.message().await?,
+                };
+                let Some(mut response) = next else { break };
+                if let Err(violation) = response_limits.check(&response) {
+                    over_limit = Some(violation);
+                    break;
+                }
+                // Control messages are not versions
+                if let Some(ads_list::Control::Ack(ack)) = &response.control {
+                    last_activity = Instant::now();
+                    acks.acknowledge(response.channel_id, ack);
+                    continue;
+                }
+                // Before sanitizing, which may clamp scores and so change the signed bytes
+                if let Some(verifier) = &verifier {
+                    verifier.check(&response, &mut signatures);
+                }
+                sanitize_received(&mut response);
+                last_activity = Instant::now();
+                let received_us = unix_us();
+                let version = response.version;
+                let ads_count = response.ads.len();
+                let elapsed_ms = overall_start.elapsed().as_millis() as u64;
+                let is_replacement = ads_buffer.contains_key(&version);
+                let resolution = order_tracker.admit(response.channel_id, version, version_conflict);
+                
+                if let Some(skew) = clock_skew.as_mut() {
+                    if skew.observe(response.server_sent_unix_us, received_us) {
+                        info!(offset_us = skew.offset_us, rtt_us = skew.rtt_us, version = version, "Clock skew adjusted");
+                    }
+                }
+                // One-way latency from the server's send stamp, once the clocks are related
+                let downlink_us = clock_skew
+                    .filter(|_| response.server_sent_unix_us != 0)
+                    .map(|skew| received_us as i64 - skew.to_client_us(response.server_sent_unix_us));
+                
+                info!(
+                    version = version,
+                    ads_count = ads_count,
+                    elapsed_ms = elapsed_ms,
+                    is_replacement = is_replacement,
+                    downlink_us = downlink_us,
+                    "Received AdsList"
+                );
+                log_budget_consumption(&response, budget_at_send);
+                
+                match resolution {
+                    Resolution::Keep => {}
+                    Resolution::Discard => {
+                        debug!(version = version, policy = version_conflict.name(), "Discarded conflicting AdsList");
+                        continue;
+                    }
+                    Resolution::Fail(arrival) => {
+                        conflict = Some(AdsClientError::VersionConflict { version, arrival });
+                        break;
+                    }
+                }
+                
+                // Log debug details about the ads if debug level is enabled
+                for (i, ad) in response.ads.iter().enumerate() {
+                    debug!(
+                        version = version,
+                        ad_index = i,
+                        ad = %ad,
+                        "Ad details"
+                    );
+                }
+                
+                let acceptable = early_exit.is_some_and(|rule| rule.is_satisfied_by(&response));
+                if version >= FINAL_VERSION && flush_tx.take().is_some() {
+                    info!(
+                        elapsed_ms = overall_start.elapsed().as_millis() as u64,
+                        "Half-closed client stream"
+                    );
+                }
+                
+                // Buffer the response, replacing older versions if they exist
+                if let Some(old_ads) = ads_buffer.insert(version, response) {
+                    debug!(
+                        version = version,
+                        old_ads_count = old_ads.ads.len(),
+                        new_ads_count = ads_count,
+                        "Replaced AdsList in buffer"
+                    );
+                } else {
+                    debug!(
+                        version = version,
+                        ads_count = ads_count,
+                        "Added new AdsList to buffer"
+                    );
+                }
+                
+                if acceptable {
+                    exited_early = true;
+                    break;
+                }
+            }
+            Ok::<(), Status>(())
+        };
+        
+        // Apply timeout to the receiving process
+        let mut stream_completed = false;
+        match timeout(timeout_duration, receive_task).await {
+            Ok(Ok(())) if exited_early => {
+                info!(
+                    elapsed_ms = overall_start.elapsed().as_millis() as u64,
+                    versions_received = ads_buffer.len(),
+                    min_version = early_exit.map(|rule| rule.min_version),
+                    min_ads = early_exit.map(|rule| rule.min_ads),
+                    "Acceptable version received - exiting early"
+                );
+            }
+            Ok(Ok(())) => {
+                stream_completed = true;
+                info!(
+                    elapsed_ms = overall_start.elapsed().as_millis() as u64,
+                    versions_received = ads_buffer.len(),
+                    "Stream completed normally before timeout"
+                );
+                if let Ok(Some(trailers)) = response_stream.trailers().await {
+                    log_limit_warnings(&trailers);
+                }
+            }
+            Ok(Err(e)) if is_connection_lost(&e) => {
+                let silent_for = last_activity.elapsed();
+                error!(
+                    error = %e,
+                    silent_ms = silent_for.as_millis() as u64,
+                    versions_received = ads_buffer.len(),
+                    elapsed_ms = overall_start.elapsed().as_millis() as u64,
+                    "Connection lost mid-stream"
+                );
+                return Err(AdsClientError::ConnectionLost { silent_for, status: e });
+            }
+            Ok(Err(e)) => {
+                warn!(
+                    error = %e,
+                    elapsed_ms = overall_start.elapsed().as_millis() as u64,
+                    "Stream error occurred"
+                );
+                self.stream_end = stream_end(&e);
+                over_limit = over_limit.or_else(|| self.response_limits.violation_of(&e));
+            }
+            Err(_) => {
+                self.stream_end = SessionEnd::TimedOut;
+                info!(
+                    timeout_ms = timeout_ms,
+                    elapsed_ms = overall_start.elapsed().as_millis() as u64,
+                    versions_received = ads_buffer.len(),
+                    "Client timeout reached - proceeding with available results"
+                );
+            }
+        }
+        
+        if stalled && matches!(self.stream_end, SessionEnd::Completed | SessionEnd::TimedOut) {
+            self.stream_end = SessionEnd::Stalled;
+        }
+        self.ordering_stats.merge(&order_tracker.stats);
+        self.signature_stats.merge(&signatures);
+        if self.ack_contexts {
+            self.ack_stats.merge(&acks.finish(stream_completed && conflict.is_none() && over_limit.is_none()));
+        }
+        self.clock_skew = clock_skew;
+        if let Some(e) = conflict {
+            error!(error = %e, policy = version_conflict.name(), "AdsList version conflict - failing session");
+            return Err(e);
+        }
+        if let Some(violation) = over_limit {
+            error!(error = %violation, "AdsList over the response limits - failing session");
+            return Err(AdsClientError::ResponseLimit(violation));
+        }
+        
+        // Budget left unspent when the stream ended (early exit or normal completion) before the timeout
+        let budget_saved = timeout_duration.saturating_sub(receive_start.elapsed());
+        
+        self.received_versions = ads_buffer.values().cloned().collect();
+        self.received_versions.sort_by_key(|ads| ads.version);
+        
+        // Log buffer state for debugging
+        let mut versions: Vec<u32> = ads_buffer.keys().cloned().collect();
+        versions.sort();
+        debug!(
+            buffer_size = ads_buffer.len(),
+            available_versions = ?versions,
+            elapsed_ms = overall_start.elapsed().as_millis() as u64,
+            "Buffer state at timeout"
+        );
+        
+        // Return the most recent AdsList (highest version number), or the union of
+        // all versions under the merge strategy
+        let latest = ads_buffer.values().max_by_key(|ads| ads.version).cloned();
+        let selected = match self.selection {
+            SelectionStrategy::LatestVersion => latest,
+            SelectionStrategy::MergeVersions => latest.map(|latest| {
+                let merged = merge_versions(&ads_buffer, self.renormalize, self.tie_break, self.seed.unwrap_or(0)).unwrap_or_else(|| latest.clone());
+                self.selection_stats.record_merge(&merged, &latest);
+                merged
+            }),
+        };
+        if let Some(mut latest_ads) = selected {
+            if let Some(hook) = &self.rerank {
+                hook.apply(&mut latest_ads);
+            }
+            let total_duration_ms = overall_start.elapsed().as_millis() as u64;
+            
+            info!(
+                selected_version = latest_ads.version,
+                ads_count = latest_ads.ads.len(),
+                query_partitions = latest_ads.query_results.len(),
+                placements = latest_ads.placement_results.len(),
+                no_fill_reason = ?latest_ads.no_fill_reason(),
+                total_duration_ms = total_duration_ms,
+                versions_considered = ads_buffer.len(),
+                selection = ?self.selection,
+                "FINAL RESULT: Selected AdsList"
+            );
+            
+            // Log performance summary
+            info!(
+                operation = "bidirectional_stream",
+                total_duration_ms = total_duration_ms,
+                timeout_used_ms = timeout_ms,
+                budget_saved_ms = budget_saved.as_millis() as u64,
+                exited_early = exited_early,
+                versions_received = ads_buffer.len(),
+                final_version = latest_ads.version,
+                out_of_order_total = self.ordering_stats.out_of_order,
+                duplicates_total = self.ordering_stats.duplicates,
+                "Performance summary"
+            );
+            
+            Ok(Some(latest_ads))
+        } else {
+            let total_duration_ms = overall_start.elapsed().as_millis() as u64;
+            warn!(
+                total_duration_ms = total_duration_ms,
+                timeout_ms = timeout_ms,
+                buffer_size = ads_buffer.len(),
+                "FINAL RESULT: No AdsList received within timeout"
+            );
+            Ok(None)
+        }
+    }
+}
+
+fn log_context_size(context: &crate::ads::Context, compression: Option<Compression>) {
+    if let Some(compression) = compression {
+        let encoded = context.encode_to_vec();
+        info!(
+            encoding = compression.name(),
+            size_before = encoded.len(),
+            size_after = compression.compressed_len(&encoded),
+            "Context compression"
+        );
+    }
+}
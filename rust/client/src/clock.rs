@@ -0,0 +1,51 @@
+//! Client/server clock-skew estimation. Handshake probes give NTP-style samples;
+//! the one with the shortest round trip bounds the offset most tightly. AdsList send
+//! timestamps then keep the estimate honest: a list cannot arrive before the server
+//! sent it. The estimate is logged so logsum can correct cross-process latencies.
+
+/// Offset of the server clock from the client clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSkew {
+    /// Server clock minus client clock, in microseconds
+    pub offset_us: i64,
+    /// Round trip of the probe the offset came from; the offset is accurate to half of it
+    pub rtt_us: u64,
+}
+
+impl ClockSkew {
+    /// One exchange: client send (t0), server receive (t1), server send (t2) and
+    /// client receive (t3), the client times on the client clock and the server
+    /// times on the server clock
+    pub fn from_exchange(t0: u64, t1: u64, t2: u64, t3: u64) -> Self {
+        let (t0, t1, t2, t3) = (t0 as i64, t1 as i64, t2 as i64, t3 as i64);
+        ClockSkew {
+            offset_us: ((t1 - t0) + (t2 - t3)) / 2,
+            rtt_us: ((t3 - t0) - (t2 - t1)).max(0) as u64,
+        }
+    }
+
+    /// The sample with the shortest round trip
+    pub fn best(samples: impl IntoIterator<Item = ClockSkew>) -> Option<ClockSkew> {
+        samples.into_iter().min_by_key(|sample| sample.rtt_us)
+    }
+
+    /// Account for an AdsList stamped `server_sent_us` by the server and received at
+    /// `received_us` on the client: the offset is at least their difference. Returns
+    /// whether the estimate had to move.
+    pub fn observe(&mut self, server_sent_us: u64, received_us: u64) -> bool {
+        if server_sent_us == 0 {
+            return false;
+        }
+        let min_offset = server_sent_us as i64 - received_us as i64;
+        if self.offset_us >= min_offset {
+            return false;
+        }
+        self.offset_us = min_offset;
+        true
+    }
+
+    /// A server timestamp on the client clock
+    pub fn to_client_us(&self, server_us: u64) -> i64 {
+        server_us as i64 - self.offset_us
+    }
+}
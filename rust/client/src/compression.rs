@@ -0,0 +1,47 @@
+use std::io::Write;
+
+use tonic::codec::CompressionEncoding;
+use tonic::metadata::MetadataMap;
+
+/// Header in which gRPC servers list the message encodings they accept
+pub const ACCEPT_ENCODING_HEADER: &str = "grpc-accept-encoding";
+
+/// Compression for outgoing Context messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compression {
+    Gzip,
+}
+
+impl Compression {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+        }
+    }
+
+    pub fn encoding(&self) -> CompressionEncoding {
+        match self {
+            Compression::Gzip => CompressionEncoding::Gzip,
+        }
+    }
+
+    /// Size `bytes` would have on the wire with this encoding, at tonic's default levels.
+    /// Only used for logging; tonic does the real compression.
+    pub fn compressed_len(&self, bytes: &[u8]) -> usize {
+        match self {
+            Compression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(6));
+                let _ = encoder.write_all(bytes);
+                encoder.finish().map_or(bytes.len(), |out| out.len())
+            }
+        }
+    }
+}
+
+/// Whether response metadata advertises that the server accepts `compression`
+pub fn server_accepts(metadata: &MetadataMap, compression: Compression) -> bool {
+    metadata
+        .get(ACCEPT_ENCODING_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|encoding| encoding.trim() == compression.name()))
+}
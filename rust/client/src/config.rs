@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+use ads_proto::score::TieBreak;
+
+use crate::ads::{Placement, RequestType, ScoreNormalization};
+use crate::backpressure::OverflowPolicy;
+use crate::breaker::BreakerConfig;
+use crate::compression::Compression;
+use crate::dial::HappyEyeballs;
+use crate::limits::ResponseLimits;
+use crate::ordering::VersionConflictPolicy;
+use crate::rerank::RerankHook;
+use crate::retry::ServiceConfig;
+use crate::selection::{EarlyExit, SelectionStrategy};
+use crate::signing::ResponseVerifier;
+use crate::understanding::UnderstandingSim;
+
+/// Connection and stream settings for `AdsClient`
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// Interval between HTTP/2 keepalive PINGs sent on the channel. Keepalive watches
+    /// the connection across sessions; at the defaults (10 s + 5 s) it cannot fire
+    /// within one 30-120 ms selection timeout, which ends a stuck session first.
+    pub keepalive_interval: Duration,
+    /// How long to wait for a PING acknowledgement before declaring the connection dead
+    pub keepalive_timeout: Duration,
+    /// Connection racing over the addresses of a server given by hostname
+    pub happy_eyeballs: HappyEyeballs,
+    /// Connect over TLS (None = plaintext)
+    pub tls: Option<ClientTls>,
+    /// Retry policies applied transparently by the channel of `AdsClient::new`
+    /// (None = no channel-level retries)
+    pub service_config: Option<ServiceConfig>,
+    /// Seed sent in the first Context so the server's generation is reproducible
+    pub seed: Option<u64>,
+    /// Retrieval/ranking mode requested from the server
+    pub request_type: RequestType,
+    /// Queries batched into every Context (empty = single-query sessions)
+    pub batch_queries: Vec<String>,
+    /// Placements to fill, each ranked from its own pool (empty = one flat list)
+    pub placements: Vec<Placement>,
+    /// How the final AdsList is chosen from the received versions
+    pub selection: SelectionStrategy,
+    /// Stop waiting as soon as an acceptable version arrives (None = use the full timeout)
+    pub early_exit: Option<EarlyExit>,
+    /// Fixed result selection timeout (None = a random 30-120ms per session)
+    pub selection_timeout: Option<Duration>,
+    /// Normalization applied to every version before merging (None = compare raw scores)
+    pub renormalize: ScoreNormalization,
+    /// Order of equal scores when merging versions (seeded order uses `seed`, or 0)
+    pub tie_break: TieBreak,
+    /// Re-ranking applied to the selected AdsList before it is returned
+    pub rerank: Option<RerankHook>,
+    /// Compress outgoing Contexts once the server has advertised support for the encoding
+    pub compression: Option<Compression>,
+    /// Retry budget and circuit breaker settings
+    pub breaker: BreakerConfig,
+    /// Capacity of the channel feeding Contexts into the request stream
+    pub request_channel_capacity: usize,
+    /// What sending a Context does when that channel is full
+    pub request_overflow: OverflowPolicy,
+    /// Send one idempotency key for a session and all its retries, so a retry can
+    /// attach to the original session if it is still running on the server
+    pub idempotency_keys: bool,
+    /// Experiment labels (key, value) sent with every session as x-label-<key> metadata
+    pub labels: Vec<(String, String)>,
+    /// Generator variant requested from the server with x-generator metadata
+    pub generator_variant: Option<String>,
+    /// Priority class (interactive, batch) sent with every session as x-priority metadata
+    pub priority: Option<String>,
+    /// Ask the server for DEBUG-level logs of every session (x-debug-session metadata)
+    pub debug_session: bool,
+    /// Reconnect to the endpoint a server in maintenance redirects to and resend the session
+    pub follow_redirects: bool,
+    /// Handshakes used to estimate the server's clock offset on connect (0 = none)
+    pub clock_probes: u32,
+    /// Synthesize each session's understanding and its delay instead of sending the
+    /// given string after the default delay
+    pub understanding_sim: Option<UnderstandingSim>,
+    /// Send the understanding (and seed) in the first and only Context, once it is
+    /// ready, instead of an uninformed Context followed by a refined one
+    pub single_context: bool,
+    /// Send a W3C traceparent with every session so the server can link its latency
+    /// observations to the session's trace
+    pub trace_context: bool,
+    /// What to do with an AdsList version received twice or after a higher one
+    pub version_conflict: VersionConflictPolicy,
+    /// Verify every AdsList's signature against this server public key and flag
+    /// lists that fail (None = signatures are ignored)
+    pub verify_key: Option<ResponseVerifier>,
+    /// Flag a session as stalled when no AdsList arrives for this long after one
+    /// did (None = no stall watchdog)
+    pub stall_gap: Option<Duration>,
+    /// Keep the request stream open until the final version and send a flush control
+    /// message when the session stalls
+    pub stall_flush: bool,
+    /// BCP 47 language tag sent with every Context (None = the server's default locale)
+    pub locale: Option<String>,
+    /// Caps on received AdsLists; a list over any of them fails the session
+    pub response_limits: ResponseLimits,
+    /// Ask the server to acknowledge every Context and warn about Contexts it never
+    /// acknowledged
+    pub ack_contexts: bool,
+    /// Tell the server which version each session used (ReportFeedback),
+    /// so it can account for generation wasted on later versions, with the ads served
+    /// and the session outcome for its per-treatment metrics
+    pub report_feedback: bool,
+}
+
+/// TLS settings of the channel to the server
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientTls {
+    /// PEM CA certificate the server's certificate must chain to (None = the
+    /// system's trusted roots)
+    pub ca_certificate: Option<Vec<u8>>,
+    /// Name the server's certificate must be valid for (None = the host of the
+    /// server address)
+    pub domain_name: Option<String>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            keepalive_interval: Duration::from_secs(10),
+            keepalive_timeout: Duration::from_secs(5),
+            happy_eyeballs: HappyEyeballs::default(),
+            tls: None,
+            service_config: None,
+            seed: None,
+            request_type: RequestType::Keyword,
+            batch_queries: Vec::new(),
+            placements: Vec::new(),
+            selection: SelectionStrategy::default(),
+            early_exit: None,
+            selection_timeout: None,
+            renormalize: ScoreNormalization::None,
+            tie_break: TieBreak::AdId,
+            rerank: None,
+            compression: None,
+            breaker: BreakerConfig::default(),
+            request_channel_capacity: 10,
+            request_overflow: OverflowPolicy::Block,
+            idempotency_keys: false,
+            labels: Vec::new(),
+            generator_variant: None,
+            priority: None,
+            debug_session: false,
+            follow_redirects: false,
+            clock_probes: 0,
+            understanding_sim: None,
+            single_context: false,
+            trace_context: false,
+            version_conflict: VersionConflictPolicy::default(),
+            verify_key: None,
+            stall_gap: None,
+            stall_flush: false,
+            locale: None,
+            response_limits: ResponseLimits::default(),
+            ack_contexts: false,
+            report_feedback: false,
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use ads_common::limits::validate_asin;
+use ads_common::Error;
+
+use crate::ads::{Context, Placement, RequestType};
+use crate::error::AdsClientError;
+
+/// Delay between the initial and the refined Context in the scripted send flow
+pub const DEFAULT_UNDERSTANDING_DELAY: Duration = Duration::from_millis(50);
+
+/// Validating builder for the Context messages of one session.
+///
+/// The playground flow sends two Contexts: an initial one with empty understanding
+/// (carrying the session seed), then a refined one with the understanding after a
+/// short delay. `build_initial` and `build_refined` produce exactly those messages.
+#[derive(Debug, Clone)]
+pub struct ContextBuilder {
+    query: String,
+    queries: Vec<String>,
+    placements: Vec<Placement>,
+    asin_id: String,
+    understanding: String,
+    understanding_delay: Duration,
+    seed: u64,
+    request_type: RequestType,
+    channel_id: u32,
+    locale: String,
+}
+
+impl ContextBuilder {
+    pub fn new(query: impl Into<String>, asin_id: impl Into<String>) -> Self {
+        ContextBuilder {
+            query: query.into(),
+            queries: Vec::new(),
+            placements: Vec::new(),
+            asin_id: asin_id.into(),
+            understanding: String::new(),
+            understanding_delay: DEFAULT_UNDERSTANDING_DELAY,
+            seed: 0,
+            request_type: RequestType::Keyword,
+            channel_id: 0,
+            locale: String::new(),
+        }
+    }
+
+    /// Batch several queries into each Context; the server answers with one
+    /// partition per query and the single `query` is ignored
+    pub fn queries(mut self, queries: Vec<String>) -> Self {
+        self.queries = queries;
+        self
+    }
+
+    /// Ask for ads per placement; the server ranks a pool for each and fills at most
+    /// the placement's slots
+    pub fn placements(mut self, placements: Vec<Placement>) -> Self {
+        self.placements = placements;
+        self
+    }
+
+    pub fn understanding(mut self, understanding: impl Into<String>) -> Self {
+        self.understanding = understanding.into();
+        self
+    }
+
+    /// Send `understanding` in a refined Context `delay` after the initial one
+    pub fn with_understanding_after(mut self, understanding: impl Into<String>, delay: Duration) -> Self {
+        self.understanding = understanding.into();
+        self.understanding_delay = delay;
+        self
+    }
+
+    pub fn seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed.unwrap_or(0);
+        self
+    }
+
+    pub fn request_type(mut self, request_type: RequestType) -> Self {
+        self.request_type = request_type;
+        self
+    }
+
+    pub fn channel_id(mut self, channel_id: u32) -> Self {
+        self.channel_id = channel_id;
+        self
+    }
+
+    /// Language of the query and understanding as a BCP 47 tag, e.g. "de-DE"; the
+    /// server normalizes text for it instead of its default locale
+    pub fn locale(mut self, locale: impl Into<String>) -> Self {
+        self.locale = locale.into();
+        self
+    }
+
+    pub fn understanding_delay(&self) -> Duration {
+        self.understanding_delay
+    }
+
+    /// First Context of the session: no understanding yet, carries the seed
+    pub fn build_initial(&self) -> Result<Context, AdsClientError> {
+        self.validate()?;
+        Ok(Context {
+            query: self.query.clone(),
+            asin_id: self.asin_id.clone(),
+            understanding: String::new(),
+            seed: self.seed,
+            request_type: self.request_type as i32,
+            channel_id: self.channel_id,
+            queries: self.queries.clone(),
+            latency_budget_ms: 0,
+            placements: self.placements.iter().map(|placement| *placement as i32).collect(),
+            flush: false,
+            locale: self.locale.clone(),
+        })
+    }
+
+    /// Second Context of the session: carries the understanding, seed is only honored on the first
+    pub fn build_refined(&self) -> Result<Context, AdsClientError> {
+        self.validate()?;
+        Ok(Context {
+            query: self.query.clone(),
+            asin_id: self.asin_id.clone(),
+            understanding: self.understanding.clone(),
+            seed: 0,
+            request_type: self.request_type as i32,
+            channel_id: self.channel_id,
+            queries: self.queries.clone(),
+            latency_budget_ms: 0,
+            placements: self.placements.iter().map(|placement| *placement as i32).collect(),
+            flush: false,
+            locale: self.locale.clone(),
+        })
+    }
+
+    /// Single fully-informed Context (understanding and seed together), for RPC shapes
+    /// that send only one Context per session
+    pub fn build_complete(&self) -> Result<Context, AdsClientError> {
+        let mut context = self.build_refined()?;
+        context.seed = self.seed;
+        Ok(context)
+    }
+
+    fn validate(&self) -> Result<(), AdsClientError> {
+        // Category browse ignores query tokens, so an empty query is legitimate there
+        let needs_query = self.request_type != RequestType::CategoryBrowse;
+        if self.queries.is_empty() {
+            if needs_query && self.query.trim().is_empty() {
+                return Err(AdsClientError::InvalidContext("query must not be empty".to_string()));
+            }
+        } else if needs_query && self.queries.iter().any(|q| q.trim().is_empty()) {
+            return Err(AdsClientError::InvalidContext("batched queries must not be empty".to_string()));
+        }
+        validate_asin(&self.asin_id).map_err(|e| {
+            AdsClientError::InvalidContext(match e {
+                Error::Invalid(msg) => msg,
+                e => e.to_string(),
+            })
+        })
+    }
+}
@@ -0,0 +1,132 @@
+//! Happy-eyeballs (RFC 8305) connection setup for server addresses given as a
+//! hostname. Every A and AAAA record is resolved, the addresses are interleaved by
+//! family, and connection attempts start one after another `attempt_delay` apart
+//! (or as soon as the previous attempt fails), each bounded by `address_timeout`.
+//! The first connection to succeed is used and the rest are dropped, so a dead or
+//! unreachable family costs one attempt delay instead of a full connect timeout.
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, timeout};
+use tonic::transport::Uri;
+use tracing::{debug, info};
+
+/// Timing of happy-eyeballs connection attempts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HappyEyeballs {
+    /// Head start of each attempt over the next one
+    pub attempt_delay: Duration,
+    /// How long a single address may take to accept the connection
+    pub address_timeout: Duration,
+}
+
+impl Default for HappyEyeballs {
+    fn default() -> Self {
+        HappyEyeballs { attempt_delay: Duration::from_millis(250), address_timeout: Duration::from_secs(2) }
+    }
+}
+
+/// Whether `uri` names its host by IP address rather than by hostname
+pub fn is_ip_literal(uri: &Uri) -> bool {
+    uri.host().is_some_and(|host| host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().is_ok())
+}
+
+impl HappyEyeballs {
+    /// Resolve the host of `uri` and race connections to its addresses
+    pub async fn dial(self, uri: Uri) -> io::Result<TcpStream> {
+        let host = uri
+            .host()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} has no host", uri)))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = uri.port_u16().unwrap_or(if uri.scheme_str() == Some("https") { 443 } else { 80 });
+        let addrs = interleave(tokio::net::lookup_host((host.as_str(), port)).await?.collect());
+        if addrs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} resolved to no addresses", host)));
+        }
+        debug!(host = %host, addresses = ?addrs, "Resolved server addresses");
+
+        let start = Instant::now();
+        let candidates = addrs.len();
+        let mut queue = VecDeque::from(addrs);
+        let mut attempts = JoinSet::new();
+        let mut last_error = None;
+        self.launch(&mut attempts, &mut queue);
+        while !attempts.is_empty() {
+            tokio::select! {
+                Some(joined) = attempts.join_next() => {
+                    let (addr, result) = match joined {
+                        Ok(attempt) => attempt,
+                        Err(e) => {
+                            last_error = Some(io::Error::other(e));
+                            self.launch(&mut attempts, &mut queue);
+                            continue;
+                        }
+                    };
+                    match result {
+                        Ok(stream) => {
+                            attempts.abort_all();
+                            info!(
+                                host = %host,
+                                address = %addr,
+                                candidates = candidates,
+                                elapsed_ms = start.elapsed().as_millis() as u64,
+                                "Connected to server address"
+                            );
+                            return Ok(stream);
+                        }
+                        Err(e) => {
+                            debug!(host = %host, address = %addr, error = %e, "Connection attempt failed");
+                            last_error = Some(e);
+                            self.launch(&mut attempts, &mut queue);
+                        }
+                    }
+                }
+                _ = sleep(self.attempt_delay), if !queue.is_empty() => self.launch(&mut attempts, &mut queue),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "no connection attempt made")))
+    }
+
+    // Start an attempt on the next address, if any is left
+    fn launch(self, attempts: &mut JoinSet<(SocketAddr, io::Result<TcpStream>)>, queue: &mut VecDeque<SocketAddr>) {
+        let Some(addr) = queue.pop_front() else {
+            return;
+        };
+        attempts.spawn(async move {
+            let result = match timeout(self.address_timeout, TcpStream::connect(addr)).await {
+                Ok(Ok(stream)) => stream.set_nodelay(true).map(|()| stream),
+                Ok(Err(e)) => Err(e),
+                Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, format!("no answer within {:?}", self.address_timeout))),
+            };
+            (addr, result)
+        });
+    }
+}
+
+// Alternate address families, starting with the family of the resolver's first
+// answer, so one unreachable family cannot delay every early attempt
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let mut unique = Vec::with_capacity(addrs.len());
+    for addr in addrs {
+        if !unique.contains(&addr) {
+            unique.push(addr);
+        }
+    }
+    let first_v6 = unique.first().is_some_and(SocketAddr::is_ipv6);
+    let (mut preferred, mut other): (VecDeque<_>, VecDeque<_>) =
+        unique.into_iter().partition(|addr| addr.is_ipv6() == first_v6);
+    let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+    loop {
+        match (preferred.pop_front(), other.pop_front()) {
+            (None, None) => return ordered,
+            (first, second) => ordered.extend(first.into_iter().chain(second)),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Schema-agnostic client mode: the service descriptor is fetched through gRPC
+//! reflection and requests are built from JSON, so no compile-time proto types
+//! are involved. Useful for poking at servers built from a newer ads.proto.
+
+use std::str::FromStr;
+
+use prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, MethodDescriptor};
+use prost_types::{FileDescriptorProto, FileDescriptorSet};
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::Channel;
+use tonic::{Request, Status};
+use tonic_reflection::pb::server_reflection_client::ServerReflectionClient;
+use tonic_reflection::pb::server_reflection_request::MessageRequest;
+use tonic_reflection::pb::server_reflection_response::MessageResponse;
+use tonic_reflection::pb::ServerReflectionRequest;
+use tracing::{debug, info};
+
+use crate::error::AdsClientError;
+
+/// Service looked up when the method name is not fully qualified
+pub const DEFAULT_SERVICE: &str = "ads.AdsService";
+
+/// Fetch the descriptors of `service` (and its dependencies) from the server's reflection service
+pub async fn fetch_descriptor_pool(channel: Channel, service: &str) -> Result<DescriptorPool, AdsClientError> {
+    let mut client = ServerReflectionClient::new(channel);
+    let request = ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(MessageRequest::FileContainingSymbol(service.to_string())),
+    };
+    let mut responses = client
+        .server_reflection_info(Request::new(tokio_stream::once(request)))
+        .await?
+        .into_inner();
+
+    let mut files = Vec::new();
+    while let Some(response) = responses.message().await? {
+        match response.message_response {
+            Some(MessageResponse::FileDescriptorResponse(descriptors)) => {
+                for encoded in descriptors.file_descriptor_proto {
+                    let file = FileDescriptorProto::decode(encoded.as_slice())
+                        .map_err(|e| Status::internal(format!("invalid file descriptor: {}", e)))?;
+                    debug!(file = ?file.name, "Received file descriptor via reflection");
+                    files.push(file);
+                }
+            }
+            Some(MessageResponse::ErrorResponse(error)) => {
+                return Err(Status::not_found(format!("reflection: {}", error.error_message)).into());
+            }
+            _ => {}
+        }
+    }
+    DescriptorPool::from_file_descriptor_set(FileDescriptorSet { file: files })
+        .map_err(|e| Status::internal(format!("invalid descriptor set: {}", e)).into())
+}
+
+/// Resolve "Method" (on `DEFAULT_SERVICE`) or "package.Service/Method"
+pub fn find_method(pool: &DescriptorPool, name: &str) -> Result<MethodDescriptor, AdsClientError> {
+    let (service_name, method_name) = name.rsplit_once('/').unwrap_or((DEFAULT_SERVICE, name));
+    pool.get_service_by_name(service_name)
+        .and_then(|service| service.methods().find(|m| m.name() == method_name))
+        .ok_or_else(|| AdsClientError::InvalidContext(format!("unknown method {}/{}", service_name, method_name)))
+}
+
+/// Build request messages from JSON: one object, or an array of objects for
+/// client-streaming methods
+pub fn parse_requests(method: &MethodDescriptor, json: &str) -> Result<Vec<DynamicMessage>, AdsClientError> {
+    let value: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| AdsClientError::InvalidContext(format!("request JSON: {}", e)))?;
+    let values = match value {
+        serde_json::Value::Array(values) if method.is_client_streaming() => values,
+        value => vec![value],
+    };
+    values
+        .into_iter()
+        .map(|value| {
+            DynamicMessage::deserialize(method.input(), value)
+                .map_err(|e| AdsClientError::InvalidContext(format!("{}: {}", method.input().full_name(), e)))
+        })
+        .collect()
+}
+
+/// Call `method` with `requests` and return every response rendered as JSON.
+/// All four RPC shapes share the same framing, so one streaming call covers them.
+pub async fn call(
+    channel: Channel,
+    method: &MethodDescriptor,
+    requests: Vec<DynamicMessage>,
+) -> Result<Vec<String>, AdsClientError> {
+    let path = PathAndQuery::from_str(&format!("/{}/{}", method.parent_service().full_name(), method.name()))
+        .map_err(|e| AdsClientError::InvalidContext(format!("method path: {}", e)))?;
+    info!(method = %path, requests = requests.len(), "Calling method dynamically");
+
+    let mut grpc = tonic::client::Grpc::new(channel);
+    grpc.ready()
+        .await
+        .map_err(|e| Status::unavailable(format!("service not ready: {}", e)))?;
+    let codec = DynamicCodec { output: method.output() };
+    let mut responses = grpc
+        .streaming(Request::new(tokio_stream::iter(requests)), path, codec)
+        .await?
+        .into_inner();
+
+    let mut rendered = Vec::new();
+    while let Some(message) = responses.message().await? {
+        rendered.push(
+            serde_json::to_string(&message).map_err(|e| Status::internal(format!("response JSON: {}", e)))?,
+        );
+    }
+    Ok(rendered)
+}
+
+/// Codec for messages known only through their runtime descriptor
+#[derive(Debug, Clone)]
+struct DynamicCodec {
+    output: MessageDescriptor,
+}
+
+impl Codec for DynamicCodec {
+    type Encode = DynamicMessage;
+    type Decode = DynamicMessage;
+    type Encoder = DynamicEncoder;
+    type Decoder = DynamicDecoder;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        DynamicEncoder
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        DynamicDecoder { output: self.output.clone() }
+    }
+}
+
+#[derive(Debug)]
+struct DynamicEncoder;
+
+impl Encoder for DynamicEncoder {
+    type Item = DynamicMessage;
+    type Error = Status;
+
+    fn encode(&mut self, item: DynamicMessage, dst: &mut EncodeBuf<'_>) -> Result<(), Status> {
+        item.encode(dst).map_err(|e| Status::internal(e.to_string()))
+    }
+}
+
+#[derive(Debug)]
+struct DynamicDecoder {
+    output: MessageDescriptor,
+}
+
+impl Decoder for DynamicDecoder {
+    type Item = DynamicMessage;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<DynamicMessage>, Status> {
+        DynamicMessage::decode(self.output.clone(), src)
+            .map(Some)
+            .map_err(|e| Status::internal(e.to_string()))
+    }
+}
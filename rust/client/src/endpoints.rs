@@ -0,0 +1,219 @@
+//! Balancing sessions over several server endpoints. Round robin takes each endpoint
+//! in turn; healthiest scores every endpoint from its own recent sessions and sends
+//! the next one to the best score, so a slow or failing server sheds load without
+//! being dropped.
+//!
+//! An endpoint's score is its EWMA session latency, inflated by its recent error
+//! rate and by how often it missed the latency SLO. Lower is better. Endpoints not
+//! measured yet go first, and every `PROBE_EVERY` sessions the least recently used
+//! endpoint is picked regardless of score so a recovered server can win traffic back.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info, warn};
+
+use crate::ads::AdsList;
+use crate::breaker::BreakerState;
+use crate::client::AdsClient;
+use crate::config::ClientConfig;
+use crate::error::AdsClientError;
+
+/// Weight of the newest session latency in the EWMA
+const EWMA_ALPHA: f64 = 0.3;
+
+/// Sessions per endpoint in the rolling error and SLO window
+const OUTCOME_WINDOW: usize = 50;
+
+/// Score multiplier per unit of error rate; a 50% error rate triples the score
+const ERROR_PENALTY: f64 = 4.0;
+
+/// Every this many sessions the least recently used endpoint is probed
+const PROBE_EVERY: u64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum BalancePolicy {
+    /// Each endpoint in turn
+    RoundRobin,
+    /// Lowest score first (EWMA latency weighted by errors and SLO misses)
+    Healthiest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    WithinSlo,
+    /// Completed, but slower than the SLO or without an AdsList
+    SloMiss,
+    Error,
+}
+
+/// Rolling latency and error statistics of one endpoint
+#[derive(Debug, Clone)]
+pub struct EndpointStats {
+    pub endpoint: String,
+    pub sessions: u64,
+    /// None until a session has completed
+    pub ewma_latency_ms: Option<f64>,
+    outcomes: VecDeque<Outcome>,
+    // Pick sequence number of the last session sent here
+    last_used: u64,
+}
+
+impl EndpointStats {
+    fn new(endpoint: &str) -> Self {
+        EndpointStats {
+            endpoint: endpoint.to_string(),
+            sessions: 0,
+            ewma_latency_ms: None,
+            outcomes: VecDeque::new(),
+            last_used: 0,
+        }
+    }
+
+    fn rate(&self, outcome: Outcome) -> f64 {
+        if self.outcomes.is_empty() {
+            return 0.0;
+        }
+        self.outcomes.iter().filter(|o| **o == outcome).count() as f64 / self.outcomes.len() as f64
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        self.rate(Outcome::Error)
+    }
+
+    /// Fraction of recent sessions that returned an AdsList within the SLO
+    pub fn slo_attainment(&self) -> Option<f64> {
+        (!self.outcomes.is_empty()).then(|| self.rate(Outcome::WithinSlo))
+    }
+
+    /// Lower is better; None for an endpoint without a completed session
+    pub fn score(&self) -> Option<f64> {
+        let latency = self.ewma_latency_ms?;
+        Some(latency * (1.0 + ERROR_PENALTY * self.error_rate()) * (1.0 + self.rate(Outcome::SloMiss)))
+    }
+}
+
+/// Connected clients of every endpoint with their statistics
+pub struct EndpointPool {
+    clients: Vec<AdsClient>,
+    stats: Vec<EndpointStats>,
+    policy: BalancePolicy,
+    latency_slo: Duration,
+    picks: u64,
+}
+
+impl EndpointPool {
+    /// Connect to every endpoint, leaving out (with a warning) those that cannot be reached
+    pub async fn connect(
+        endpoints: &[String],
+        config: &ClientConfig,
+        policy: BalancePolicy,
+        latency_slo: Duration,
+    ) -> Result<Self, AdsClientError> {
+        let mut clients = Vec::new();
+        let mut stats = Vec::new();
+        let mut last_error = None;
+        for endpoint in endpoints {
+            match AdsClient::new(endpoint, config).await {
+                Ok(client) => {
+                    clients.push(client);
+                    stats.push(EndpointStats::new(endpoint));
+                }
+                Err(e) => {
+                    warn!(endpoint = %endpoint, error = %e, "Leaving out unreachable endpoint");
+                    last_error = Some(e);
+                }
+            }
+        }
+        if clients.is_empty() {
+            return Err(last_error.unwrap_or_else(|| AdsClientError::Send("no endpoints given".to_string())));
+        }
+        info!(endpoints = clients.len(), policy = ?policy, "Balancing sessions over endpoints");
+        Ok(EndpointPool { clients, stats, policy, latency_slo, picks: 0 })
+    }
+
+    /// Index of the endpoint for the next session
+    pub fn pick(&mut self) -> usize {
+        self.picks += 1;
+        let available: Vec<usize> = (0..self.clients.len())
+            .filter(|&i| !matches!(self.clients[i].breaker_state(), BreakerState::Open { until } if until > Instant::now()))
+            .collect();
+        // With every breaker open, let the endpoints' own breakers refuse the session
+        let candidates = if available.is_empty() { (0..self.clients.len()).collect() } else { available };
+        let least_recent = || *candidates.iter().min_by_key(|&&i| self.stats[i].last_used).unwrap();
+        let index = match self.policy {
+            BalancePolicy::RoundRobin => candidates[(self.picks as usize - 1) % candidates.len()],
+            BalancePolicy::Healthiest if self.picks.is_multiple_of(PROBE_EVERY) => least_recent(),
+            BalancePolicy::Healthiest => match candidates.iter().find(|&&i| self.stats[i].score().is_none()) {
+                Some(&unmeasured) => unmeasured,
+                None => *candidates
+                    .iter()
+                    .min_by(|&&a, &&b| self.stats[a].score().unwrap().total_cmp(&self.stats[b].score().unwrap()))
+                    .unwrap(),
+            },
+        };
+        self.stats[index].last_used = self.picks;
+        debug!(endpoint = %self.stats[index].endpoint, score = ?self.stats[index].score(), "Picked endpoint");
+        index
+    }
+
+    pub fn client(&mut self, index: usize) -> &mut AdsClient {
+        &mut self.clients[index]
+    }
+
+    /// Account a finished session of endpoint `index`
+    pub fn record(&mut self, index: usize, latency: Duration, result: &Result<Option<AdsList>, AdsClientError>) {
+        let latency_slo = self.latency_slo;
+        let stats = &mut self.stats[index];
+        stats.sessions += 1;
+        let outcome = match result {
+            Err(_) => Outcome::Error,
+            Ok(Some(_)) if latency <= latency_slo => Outcome::WithinSlo,
+            Ok(_) => Outcome::SloMiss,
+        };
+        if outcome != Outcome::Error {
+            let latency_ms = latency.as_secs_f64() * 1000.0;
+            stats.ewma_latency_ms =
+                Some(stats.ewma_latency_ms.map_or(latency_ms, |ewma| EWMA_ALPHA * latency_ms + (1.0 - EWMA_ALPHA) * ewma));
+        }
+        stats.outcomes.push_back(outcome);
+        if stats.outcomes.len() > OUTCOME_WINDOW {
+            stats.outcomes.pop_front();
+        }
+    }
+
+    pub fn stats(&self) -> &[EndpointStats] {
+        &self.stats
+    }
+
+    /// Score table of all endpoints, best first
+    pub fn render_scores(&self) -> String {
+        let mut rows: Vec<usize> = (0..self.stats.len()).collect();
+        rows.sort_by(|&a, &b| {
+            let score = |i: usize| self.stats[i].score().unwrap_or(f64::INFINITY);
+            score(a).total_cmp(&score(b))
+        });
+        let mut out = format!(
+            "{:<32}  {:>8}  {:>10}  {:>6}  {:>7}  {:>8}  {:>9}\n",
+            "ENDPOINT", "SESSIONS", "EWMA_MS", "ERR%", "SLO%", "SCORE", "BREAKER"
+        );
+        let optional = |value: Option<f64>, scale: f64| value.map_or("-".to_string(), |v| format!("{:.1}", v * scale));
+        for i in rows {
+            let stats = &self.stats[i];
+            let _ = writeln!(
+                out,
+                "{:<32}  {:>8}  {:>10}  {:>6.1}  {:>7}  {:>8}  {:>9}",
+                stats.endpoint,
+                stats.sessions,
+                optional(stats.ewma_latency_ms, 1.0),
+                stats.error_rate() * 100.0,
+                optional(stats.slo_attainment(), 100.0),
+                optional(stats.score(), 1.0),
+                self.clients[i].breaker_state().name(),
+            );
+        }
+        let _ = write!(out, "latency SLO {}ms", self.latency_slo.as_millis());
+        out
+    }
+}
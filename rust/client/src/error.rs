@@ -0,0 +1,189 @@
+use std::fmt;
+use std::time::Duration;
+use ads_proto::status::{BackpressureHint, StatusClass};
+use tonic::{Code, Status};
+
+use crate::limits::LimitViolation;
+use crate::ordering::Arrival;
+
+/// Errors surfaced by `AdsClient`
+#[derive(Debug)]
+pub enum AdsClientError {
+    /// Failed to establish the channel to the server
+    #[cfg(not(target_arch = "wasm32"))]
+    Transport(tonic::transport::Error),
+    /// The blocking client could not start its runtime
+    #[cfg(not(target_arch = "wasm32"))]
+    Runtime(std::io::Error),
+    /// The server (or the transport) terminated the stream with a status
+    Status(Status),
+    /// The HTTP/2 connection died mid-stream (e.g. keepalive PING not acknowledged)
+    ConnectionLost {
+        /// How long the stream had been silent before the connection was declared dead
+        silent_for: Duration,
+        status: Status,
+    },
+    /// Failed to push a Context message onto the outgoing stream
+    Send(String),
+    /// The circuit breaker for the endpoint is open; no request was sent
+    CircuitOpen { endpoint: String },
+    /// A Context failed validation before being sent
+    InvalidContext(String),
+    /// An AdsList version arrived twice or after a higher one, under the error policy
+    VersionConflict { version: u32, arrival: Arrival },
+    /// The server sent an AdsList over the client's response limits
+    ResponseLimit(LimitViolation),
+}
+
+impl fmt::Display for AdsClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            AdsClientError::Transport(e) => write!(f, "transport error: {}", e),
+            #[cfg(not(target_arch = "wasm32"))]
+            AdsClientError::Runtime(e) => write!(f, "runtime error: {}", e),
+            AdsClientError::Status(s) => write!(f, "stream error: {}", s),
+            AdsClientError::ConnectionLost { silent_for, status } => write!(
+                f,
+                "connection lost after {}ms of silence: {}",
+                silent_for.as_millis(),
+                status.message()
+            ),
+            AdsClientError::Send(msg) => write!(f, "send error: {}", msg),
+            AdsClientError::CircuitOpen { endpoint } => write!(f, "circuit open for {}", endpoint),
+            AdsClientError::InvalidContext(msg) => write!(f, "invalid context: {}", msg),
+            AdsClientError::VersionConflict { version, arrival: Arrival::Stale { highest_seen } } => {
+                write!(f, "stale AdsList version {} after version {}", version, highest_seen)
+            }
+            AdsClientError::VersionConflict { version, .. } => write!(f, "duplicate AdsList version {}", version),
+            AdsClientError::ResponseLimit(violation) => write!(f, "response over limit: {}", violation),
+        }
+    }
+}
+
+impl std::error::Error for AdsClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            AdsClientError::Transport(e) => Some(e),
+            #[cfg(not(target_arch = "wasm32"))]
+            AdsClientError::Runtime(e) => Some(e),
+            AdsClientError::Status(s) => Some(s),
+            AdsClientError::ConnectionLost { status, .. } => Some(status),
+            AdsClientError::Send(_)
+            | AdsClientError::CircuitOpen { .. }
+            | AdsClientError::InvalidContext(_)
+            | AdsClientError::VersionConflict { .. }
+            | AdsClientError::ResponseLimit(_) => None,
+        }
+    }
+}
+
+impl AdsClientError {
+    /// Alternate endpoint named by a server refusing the session for maintenance
+    pub fn redirect_endpoint(&self) -> Option<String> {
+        let AdsClientError::Status(status) = self else { return None };
+        if status.code() != Code::Unavailable {
+            return None;
+        }
+        status
+            .metadata()
+            .get(ads_proto::REDIRECT_METADATA_KEY)
+            .and_then(|value| value.to_str().ok())
+            .filter(|endpoint| !endpoint.is_empty())
+            .map(str::to_string)
+    }
+
+    /// Retry-after and queue depth hints of a server that shed the session
+    pub fn backpressure_hint(&self) -> Option<BackpressureHint> {
+        match self {
+            AdsClientError::Status(status) => BackpressureHint::from_status(status),
+            _ => None,
+        }
+    }
+
+    /// Short cause name for reports, e.g. `status` or `connection_lost`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            AdsClientError::Transport(_) => "transport",
+            #[cfg(not(target_arch = "wasm32"))]
+            AdsClientError::Runtime(_) => "runtime",
+            AdsClientError::Status(_) => "status",
+            AdsClientError::ConnectionLost { .. } => "connection_lost",
+            AdsClientError::Send(_) => "send",
+            AdsClientError::CircuitOpen { .. } => "circuit_open",
+            AdsClientError::InvalidContext(_) => "invalid_context",
+            AdsClientError::VersionConflict { .. } => "version_conflict",
+            AdsClientError::ResponseLimit(_) => "response_limit",
+        }
+    }
+
+    /// Whether retrying the session could plausibly succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            AdsClientError::Transport(_) => true,
+            #[cfg(not(target_arch = "wasm32"))]
+            AdsClientError::Runtime(_) => false,
+            AdsClientError::ConnectionLost { .. } => true,
+            AdsClientError::Status(s) => StatusClass::of(s).is_retryable(),
+            AdsClientError::Send(_)
+            | AdsClientError::CircuitOpen { .. }
+            | AdsClientError::InvalidContext(_)
+            | AdsClientError::VersionConflict { .. }
+            | AdsClientError::ResponseLimit(_) => false,
+        }
+    }
+}
+
+/// For binaries and tools that report every failure as the workspace error type
+#[cfg(not(target_arch = "wasm32"))]
+impl From<AdsClientError> for ads_common::Error {
+    fn from(e: AdsClientError) -> Self {
+        match e {
+            AdsClientError::Transport(e) => ads_common::Error::Transport(e),
+            AdsClientError::Runtime(e) => ads_common::Error::Io(e),
+            AdsClientError::Status(s) => ads_common::Error::from(s),
+            AdsClientError::InvalidContext(msg) => ads_common::Error::Invalid(msg),
+            e => ads_common::Error::Session(e.to_string()),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl From<tonic::transport::Error> for AdsClientError {
+    fn from(e: tonic::transport::Error) -> Self {
+        AdsClientError::Transport(e)
+    }
+}
+
+impl From<Status> for AdsClientError {
+    fn from(s: Status) -> Self {
+        AdsClientError::Status(s)
+    }
+}
+
+/// Whether a stream status indicates the underlying connection went away rather than
+/// an application-level failure reported by the server. Only statuses tonic builds
+/// from a transport error (a keepalive timeout, a reset HTTP/2 stream) carry that
+/// error as their source; a status the server sent in its trailers has none, so a
+/// server-sent UNAVAILABLE (a downstream outage, a maintenance redirect) is not one.
+pub fn is_connection_lost(status: &Status) -> bool {
+    if status.metadata().contains_key(ads_proto::INJECTED_FAULT_METADATA_KEY) {
+        return false;
+    }
+    if std::error::Error::source(status).is_none() {
+        return false;
+    }
+    if status.code() == Code::Unavailable {
+        return true;
+    }
+    // hyper reports keepalive failures and broken connections as transport errors
+    // that tonic surfaces with Code::Unknown
+    let message = status.message().to_ascii_lowercase();
+    status.code() == Code::Unknown
+        && (message.contains("transport")
+            || message.contains("connection")
+            || message.contains("keep-alive"))
+}
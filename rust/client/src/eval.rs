@@ -0,0 +1,204 @@
+//! Ranking quality of returned AdsLists against relevance judgments, so the claim
+//! that later versions are better can be measured instead of assumed.
+//!
+//! A labels file is JSON lines, one judgment per line:
+//! `{"query": "coffee maker", "asin_id": "B000123", "relevance": 2}`. A judgment may
+//! name an `ad_id` to grade a single creative; otherwise it grades every ad for that
+//! ASIN under the query. Unjudged ads count as irrelevant (grade 0).
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::ads::{Ad, AdsList};
+
+/// One relevance judgment
+#[derive(Debug, Clone, Deserialize)]
+pub struct Judgment {
+    pub query: String,
+    pub asin_id: String,
+    #[serde(default)]
+    pub ad_id: Option<String>,
+    /// Graded relevance, 0 = irrelevant
+    pub relevance: u32,
+}
+
+/// Judgments indexed by query
+#[derive(Debug, Default)]
+pub struct Labels {
+    by_query: HashMap<String, Vec<Judgment>>,
+}
+
+impl Labels {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut labels = Labels::default();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let judgment: Judgment = serde_json::from_str(&line).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: {}", path.display(), i + 1, e))
+            })?;
+            labels.by_query.entry(judgment.query.clone()).or_default().push(judgment);
+        }
+        Ok(labels)
+    }
+
+    /// Distinct (query, asin_id) pairs judged, i.e. the sessions worth running
+    pub fn sessions(&self) -> Vec<(String, String)> {
+        let mut sessions: Vec<(String, String)> = self
+            .by_query
+            .values()
+            .flatten()
+            .map(|judgment| (judgment.query.clone(), judgment.asin_id.clone()))
+            .collect();
+        sessions.sort();
+        sessions.dedup();
+        sessions
+    }
+
+    /// Grade of `ad` under `query`; an ad_id judgment wins over its ASIN's
+    pub fn grade(&self, query: &str, ad: &Ad) -> u32 {
+        let Some(judgments) = self.by_query.get(query) else { return 0 };
+        judgments
+            .iter()
+            .find(|j| j.ad_id.as_deref() == Some(ad.ad_id.as_str()))
+            .or_else(|| judgments.iter().find(|j| j.ad_id.is_none() && j.asin_id == ad.asin_id))
+            .map_or(0, |j| j.relevance)
+    }
+
+    /// Best possible grades for `query`, highest first
+    fn ideal_grades(&self, query: &str) -> Vec<u32> {
+        let mut grades: Vec<u32> =
+            self.by_query.get(query).map(|js| js.iter().map(|j| j.relevance).collect()).unwrap_or_default();
+        grades.sort_unstable_by(|a, b| b.cmp(a));
+        grades
+    }
+}
+
+fn dcg(grades: impl Iterator<Item = u32>) -> f64 {
+    grades
+        .enumerate()
+        .map(|(i, grade)| (2f64.powi(grade as i32) - 1.0) / (i as f64 + 2.0).log2())
+        .sum()
+}
+
+/// Quality of one ranked list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quality {
+    /// NDCG over the top `k` ads (exponential gain); None when nothing relevant is judged for the query
+    pub ndcg: Option<f64>,
+    /// Reciprocal rank of the first relevant ad, 0 if none is returned
+    pub reciprocal_rank: f64,
+}
+
+pub fn quality(labels: &Labels, query: &str, ads: &[Ad], k: usize) -> Quality {
+    let grades: Vec<u32> = ads.iter().take(k).map(|ad| labels.grade(query, ad)).collect();
+    let ideal = dcg(labels.ideal_grades(query).into_iter().take(k));
+    let ndcg = (ideal > 0.0).then(|| dcg(grades.iter().copied()) / ideal);
+    let reciprocal_rank = ads
+        .iter()
+        .position(|ad| labels.grade(query, ad) > 0)
+        .map_or(0.0, |i| 1.0 / (i as f64 + 1.0));
+    Quality { ndcg, reciprocal_rank }
+}
+
+/// Ranked lists of an AdsList with the query each answers: the partitions of a
+/// batched list, otherwise its ads under `query`
+pub fn ranked_lists<'a>(ads_list: &'a AdsList, query: &'a str) -> Vec<(&'a str, &'a [Ad])> {
+    if ads_list.query_results.is_empty() {
+        vec![(query, &ads_list.ads)]
+    } else {
+        ads_list.query_results.iter().map(|p| (p.query.as_str(), p.ads.as_slice())).collect()
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Totals {
+    lists: usize,
+    ndcg_sum: f64,
+    ndcg_lists: usize,
+    reciprocal_rank_sum: f64,
+}
+
+/// Mean NDCG@k and MRR per version (and for the lists the client finally selected)
+#[derive(Debug)]
+pub struct EvalReport {
+    k: usize,
+    by_version: BTreeMap<u32, Totals>,
+    selected: Totals,
+    sessions_without_result: usize,
+}
+
+impl EvalReport {
+    pub fn new(k: usize) -> Self {
+        EvalReport { k, by_version: BTreeMap::new(), selected: Totals::default(), sessions_without_result: 0 }
+    }
+
+    /// Account one session: every version received, and the list selected (None = no result)
+    pub fn add_session(&mut self, labels: &Labels, query: &str, versions: &[AdsList], selected: Option<&AdsList>) {
+        for ads_list in versions {
+            let totals = self.by_version.entry(ads_list.version).or_default();
+            for (query, ads) in ranked_lists(ads_list, query) {
+                add(totals, quality(labels, query, ads, self.k));
+            }
+        }
+        match selected {
+            Some(ads_list) => {
+                for (query, ads) in ranked_lists(ads_list, query) {
+                    add(&mut self.selected, quality(labels, query, ads, self.k));
+                }
+            }
+            None => self.sessions_without_result += 1,
+        }
+    }
+
+    /// Mean NDCG and MRR by name (`v1_ndcg`, ..., `selected_mrr`); NDCG is left out
+    /// where no list had judgments
+    pub fn metrics(&self) -> BTreeMap<String, f64> {
+        let mut metrics = BTreeMap::new();
+        let labelled = self.by_version.iter().map(|(version, totals)| (format!("v{}", version), totals));
+        for (label, totals) in labelled.chain([("selected".to_string(), &self.selected)]) {
+            if totals.ndcg_lists > 0 {
+                metrics.insert(format!("{}_ndcg", label), totals.ndcg_sum / totals.ndcg_lists as f64);
+            }
+            metrics.insert(format!("{}_mrr", label), totals.reciprocal_rank_sum / totals.lists.max(1) as f64);
+        }
+        metrics
+    }
+
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!("{:<9}  {:>6}  {:>8}  {:>7}", "VERSION", "LISTS", format!("NDCG@{}", self.k), "MRR")];
+        let row = |label: String, totals: &Totals| {
+            let ndcg = if totals.ndcg_lists == 0 {
+                "-".to_string()
+            } else {
+                format!("{:.4}", totals.ndcg_sum / totals.ndcg_lists as f64)
+            };
+            let mrr = totals.reciprocal_rank_sum / totals.lists.max(1) as f64;
+            format!("{:<9}  {:>6}  {:>8}  {:>7.4}", label, totals.lists, ndcg, mrr)
+        };
+        for (version, totals) in &self.by_version {
+            lines.push(row(format!("v{}", version), totals));
+        }
+        lines.push(row("selected".to_string(), &self.selected));
+        if self.sessions_without_result > 0 {
+            lines.push(format!("{} sessions returned no AdsList", self.sessions_without_result));
+        }
+        lines.join("\n")
+    }
+}
+
+fn add(totals: &mut Totals, quality: Quality) {
+    totals.lists += 1;
+    totals.reciprocal_rank_sum += quality.reciprocal_rank;
+    if let Some(ndcg) = quality.ndcg {
+        totals.ndcg_sum += ndcg;
+        totals.ndcg_lists += 1;
+    }
+}
@@ -0,0 +1,267 @@
+//! Named experiment runs and their comparison. A run records the settings it was made
+//! with (hashed, so runs of one configuration can be recognized), the git revision
+//! and time, and what it measured: session latency percentiles, errors, the outcome
+//! funnel and, with relevance judgments, NDCG and MRR per version. Runs are stored
+//! as one JSON file each in a local directory, so any two can be compared later.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentRun {
+    pub name: String,
+    /// Hash of `config`, equal for runs of the same settings
+    pub config_hash: String,
+    /// Revision of the working tree the run was made from, if it is a git checkout
+    #[serde(default)]
+    pub git_rev: Option<String>,
+    pub recorded_unix_secs: u64,
+    /// Client settings plus any `--param` given for what the client cannot see
+    /// (e.g. the server's flags)
+    pub config: BTreeMap<String, String>,
+    pub sessions: u64,
+    /// Failed sessions by error kind
+    pub errors: BTreeMap<String, u64>,
+    /// Sessions per outcome name
+    pub outcomes: BTreeMap<String, u64>,
+    /// Session latency (ms) by statistic: mean, p50, p90, p99
+    pub latency_ms: BTreeMap<String, f64>,
+    /// Ranking quality by metric, e.g. `v3_ndcg` or `selected_mrr`; empty without judgments
+    #[serde(default)]
+    pub quality: BTreeMap<String, f64>,
+}
+
+impl ExperimentRun {
+    /// A run of `config` stamped with its hash, the current git revision and time
+    pub fn new(name: &str, config: BTreeMap<String, String>) -> Self {
+        ExperimentRun {
+            name: name.to_string(),
+            config_hash: config_hash(&config),
+            git_rev: git_rev(),
+            recorded_unix_secs: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            config,
+            sessions: 0,
+            errors: BTreeMap::new(),
+            outcomes: BTreeMap::new(),
+            latency_ms: BTreeMap::new(),
+            quality: BTreeMap::new(),
+        }
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        self.errors.values().sum::<u64>() as f64 / self.sessions.max(1) as f64
+    }
+
+    /// Share of the sessions that ended with `outcome`
+    pub fn outcome_share(&self, outcome: &str) -> f64 {
+        self.outcomes.get(outcome).copied().unwrap_or(0) as f64 / self.sessions.max(1) as f64
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "{}  config={}  git={}  recorded_unix_secs={}\n",
+            self.name,
+            self.config_hash,
+            self.git_rev.as_deref().unwrap_or("-"),
+            self.recorded_unix_secs
+        );
+        for (key, value) in &self.config {
+            out.push_str(&format!("  {} = {}\n", key, value));
+        }
+        out.push_str(&format!("  sessions={} error_rate={:.4}\n", self.sessions, self.error_rate()));
+        for (statistic, ms) in &self.latency_ms {
+            out.push_str(&format!("  latency {} = {:.1}ms\n", statistic, ms));
+        }
+        for (outcome, count) in &self.outcomes {
+            out.push_str(&format!("  outcome {} = {}\n", outcome, count));
+        }
+        for (metric, value) in &self.quality {
+            out.push_str(&format!("  quality {} = {:.4}\n", metric, value));
+        }
+        out
+    }
+}
+
+/// Stable FNV-1a hash of the settings, so it survives toolchain upgrades
+pub fn config_hash(config: &BTreeMap<String, String>) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for (key, value) in config {
+        for byte in key.bytes().chain([b'=']).chain(value.bytes()).chain([b'\n']) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+fn git_rev() -> Option<String> {
+    let output = Command::new("git").args(["rev-parse", "--short", "HEAD"]).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Directory of runs, one `<name>.json` each
+#[derive(Debug, Clone)]
+pub struct ExperimentStore {
+    dir: PathBuf,
+}
+
+impl ExperimentStore {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(ExperimentStore { dir: dir.to_path_buf() })
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.path(name).exists()
+    }
+
+    pub fn save(&self, run: &ExperimentRun) -> io::Result<PathBuf> {
+        let path = self.path(&run.name);
+        std::fs::write(&path, serde_json::to_string_pretty(run)? + "\n")?;
+        Ok(path)
+    }
+
+    pub fn load(&self, name: &str) -> io::Result<ExperimentRun> {
+        let path = self.path(name);
+        let json = std::fs::read_to_string(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("no experiment run {:?} in {}", name, self.dir.display())))?;
+        serde_json::from_str(&json)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+    }
+
+    /// Every stored run, oldest first
+    pub fn list(&self) -> io::Result<Vec<ExperimentRun>> {
+        let mut runs = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    runs.push(self.load(name)?);
+                }
+            }
+        }
+        runs.sort_by(|a, b| (a.recorded_unix_secs, &a.name).cmp(&(b.recorded_unix_secs, &b.name)));
+        Ok(runs)
+    }
+}
+
+/// One compared metric of two runs
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricDelta {
+    pub metric: String,
+    pub a: Option<f64>,
+    pub b: Option<f64>,
+}
+
+impl MetricDelta {
+    fn new(metric: String, a: Option<f64>, b: Option<f64>) -> Self {
+        MetricDelta { metric, a, b }
+    }
+
+    pub fn delta(&self) -> Option<f64> {
+        Some(self.b? - self.a?)
+    }
+
+    /// Relative change from `a`; None when `a` is missing or zero
+    pub fn relative(&self) -> Option<f64> {
+        let a = self.a.filter(|a| *a != 0.0)?;
+        Some((self.b? - a) / a)
+    }
+}
+
+/// Run B against run A: settings that differ, and latency, error, outcome and quality deltas
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub a: String,
+    pub b: String,
+    pub same_config: bool,
+    /// (key, value in A, value in B) of every setting that differs
+    pub config_changes: Vec<(String, Option<String>, Option<String>)>,
+    pub latency: Vec<MetricDelta>,
+    pub errors: Vec<MetricDelta>,
+    pub outcomes: Vec<MetricDelta>,
+    pub quality: Vec<MetricDelta>,
+}
+
+impl Comparison {
+    pub fn compare(a: &ExperimentRun, b: &ExperimentRun) -> Self {
+        let keys: BTreeSet<&String> = a.config.keys().chain(b.config.keys()).collect();
+        let config_changes = keys
+            .into_iter()
+            .filter(|key| a.config.get(*key) != b.config.get(*key))
+            .map(|key| (key.clone(), a.config.get(key).cloned(), b.config.get(key).cloned()))
+            .collect();
+        let outcomes: BTreeSet<&String> = a.outcomes.keys().chain(b.outcomes.keys()).collect();
+        Comparison {
+            a: a.name.clone(),
+            b: b.name.clone(),
+            same_config: a.config_hash == b.config_hash,
+            config_changes,
+            latency: union(&a.latency_ms, &b.latency_ms, "ms"),
+            errors: vec![
+                MetricDelta::new("sessions".to_string(), Some(a.sessions as f64), Some(b.sessions as f64)),
+                MetricDelta::new("error_rate".to_string(), Some(a.error_rate()), Some(b.error_rate())),
+            ],
+            outcomes: outcomes
+                .into_iter()
+                .map(|outcome| {
+                    MetricDelta::new(
+                        format!("{} share", outcome),
+                        Some(a.outcome_share(outcome)),
+                        Some(b.outcome_share(outcome)),
+                    )
+                })
+                .collect(),
+            quality: union(&a.quality, &b.quality, ""),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = format!("A = {}  B = {}\n", self.a, self.b);
+        if self.same_config {
+            out.push_str("Same configuration\n");
+        }
+        for (key, a, b) in &self.config_changes {
+            out.push_str(&format!(
+                "  {}: {} -> {}\n",
+                key,
+                a.as_deref().unwrap_or("(unset)"),
+                b.as_deref().unwrap_or("(unset)")
+            ));
+        }
+        out.push_str(&format!("{:<24}  {:>10}  {:>10}  {:>10}  {:>8}\n", "METRIC", "A", "B", "DELTA", "CHANGE"));
+        let value = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{:.4}", v));
+        for delta in self.latency.iter().chain(&self.errors).chain(&self.outcomes).chain(&self.quality) {
+            out.push_str(&format!(
+                "{:<24}  {:>10}  {:>10}  {:>10}  {:>8}\n",
+                delta.metric,
+                value(delta.a),
+                value(delta.b),
+                delta.delta().map_or("-".to_string(), |d| format!("{:+.4}", d)),
+                delta.relative().map_or("-".to_string(), |r| format!("{:+.1}%", r * 100.0)),
+            ));
+        }
+        out
+    }
+}
+
+// A delta per metric of either run, named with an optional unit suffix
+fn union(a: &BTreeMap<String, f64>, b: &BTreeMap<String, f64>, unit: &str) -> Vec<MetricDelta> {
+    let metrics: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    metrics
+        .into_iter()
+        .map(|metric| {
+            let name = if unit.is_empty() { metric.clone() } else { format!("{} {}", metric, unit) };
+            MetricDelta::new(name, a.get(metric).copied(), b.get(metric).copied())
+        })
+        .collect()
+}
@@ -0,0 +1,218 @@
+//! Multi-ASIN fan-out: ads for several products at once, as a detail-page widget
+//! showing sponsored products next to each of a set of items would request them.
+//! Each ASIN gets its own session, either concurrent sessions on one channel or
+//! channels of one multiplexed stream, all under a single overall deadline. The
+//! result holds the best AdsList per ASIN plus the reason each remaining ASIN has
+//! none, so a widget can render what arrived in time.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use tokio::task::{self, LocalSet};
+use tokio::time::timeout;
+use tonic::transport::Channel;
+use tracing::{info, warn};
+
+use crate::ads::AdsList;
+use crate::config::ClientConfig;
+use crate::context::DEFAULT_UNDERSTANDING_DELAY;
+use crate::error::AdsClientError;
+use crate::multiplexed::{LogicalSession, MultiplexedAdsClient};
+use crate::{connect, AdsClient};
+
+/// How the sessions of a fan-out share the connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FanOutMode {
+    /// One bidirectional stream per ASIN, all in flight at once
+    #[default]
+    Concurrent,
+    /// One multiplexed stream carrying a channel per ASIN
+    Multiplexed,
+}
+
+impl FanOutMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FanOutMode::Concurrent => "concurrent",
+            FanOutMode::Multiplexed => "multiplexed",
+        }
+    }
+}
+
+/// Why an ASIN of a fan-out has no AdsList
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsinFailure {
+    /// The session ended without a usable version
+    NoResult,
+    /// The overall deadline passed before the session finished
+    DeadlineExceeded,
+    /// The session failed; the error as displayed
+    Error(String),
+}
+
+impl AsinFailure {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AsinFailure::NoResult => "no_result",
+            AsinFailure::DeadlineExceeded => "deadline_exceeded",
+            AsinFailure::Error(_) => "error",
+        }
+    }
+}
+
+/// Outcome of `FanOutClient::get_ads_for_asins`; every requested ASIN is in
+/// exactly one of the maps
+#[derive(Debug, Default)]
+pub struct FanOutResult {
+    pub ads: BTreeMap<String, AdsList>,
+    pub failures: BTreeMap<String, AsinFailure>,
+    pub elapsed: Duration,
+}
+
+impl FanOutResult {
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Client for fan-out requests over one channel to a server
+pub struct FanOutClient {
+    channel: Channel,
+    endpoint: String,
+    config: ClientConfig,
+    mode: FanOutMode,
+    deadline: Duration,
+}
+
+impl FanOutClient {
+    pub async fn new(
+        server_addr: &str,
+        config: &ClientConfig,
+        mode: FanOutMode,
+        deadline: Duration,
+    ) -> Result<Self, AdsClientError> {
+        let channel = connect(server_addr, config).await?;
+        Ok(FanOutClient { channel, endpoint: server_addr.to_string(), config: config.clone(), mode, deadline })
+    }
+
+    /// Ads for every ASIN (duplicates are requested once) for the same query and
+    /// understanding, returned once all sessions have finished or the deadline passed
+    pub async fn get_ads_for_asins(&self, query: &str, asins: &[String], understanding: &str) -> FanOutResult {
+        let start = Instant::now();
+        let mut seen = BTreeSet::new();
+        let asins: Vec<String> = asins.iter().filter(|asin| seen.insert(asin.as_str())).cloned().collect();
+        info!(
+            asins = asins.len(),
+            mode = self.mode.name(),
+            deadline_ms = self.deadline.as_millis() as u64,
+            "Starting multi-ASIN fan-out"
+        );
+        let mut result = match self.mode {
+            FanOutMode::Concurrent => self.concurrent(query, &asins, understanding).await,
+            FanOutMode::Multiplexed => self.multiplexed(query, &asins, understanding).await,
+        };
+        result.elapsed = start.elapsed();
+        for (asin, failure) in &result.failures {
+            warn!(asin = %asin, failure = failure.name(), detail = ?failure, "No ads for ASIN");
+        }
+        info!(
+            asins = asins.len(),
+            with_ads = result.ads.len(),
+            failed = result.failures.len(),
+            elapsed_ms = result.elapsed.as_millis() as u64,
+            "Multi-ASIN fan-out finished"
+        );
+        result
+    }
+
+    // A session per ASIN on the shared channel; sessions still running at the
+    // deadline are dropped, which cancels their streams
+    async fn concurrent(&self, query: &str, asins: &[String], understanding: &str) -> FanOutResult {
+        let deadline = tokio::time::Instant::now() + self.deadline;
+        let finished = Rc::new(RefCell::new(FanOutResult::default()));
+        let local = LocalSet::new();
+        local
+            .run_until(async {
+                let mut tasks = Vec::new();
+                for asin in asins {
+                    let mut client = AdsClient::from_service(self.channel.clone(), &self.endpoint, &self.config);
+                    let (query, asin, understanding) = (query.to_string(), asin.clone(), understanding.to_string());
+                    let finished = finished.clone();
+                    tasks.push(task::spawn_local(async move {
+                        let session = client.get_ads_with_retry(query, asin.clone(), understanding);
+                        let outcome = match tokio::time::timeout_at(deadline, session).await {
+                            Ok(Ok(Some(ads_list))) => Ok(ads_list),
+                            Ok(Ok(None)) => Err(AsinFailure::NoResult),
+                            Ok(Err(e)) => Err(AsinFailure::Error(e.to_string())),
+                            Err(_) => Err(AsinFailure::DeadlineExceeded),
+                        };
+                        let mut finished = finished.borrow_mut();
+                        match outcome {
+                            Ok(ads_list) => {
+                                finished.ads.insert(asin, ads_list);
+                            }
+                            Err(failure) => {
+                                finished.failures.insert(asin, failure);
+                            }
+                        }
+                    }));
+                }
+                for task in tasks {
+                    let _ = task.await;
+                }
+            })
+            .await;
+        let mut result = finished.take();
+        // A session task that panicked recorded nothing
+        for asin in asins {
+            if !result.ads.contains_key(asin) && !result.failures.contains_key(asin) {
+                result.failures.insert(asin.clone(), AsinFailure::Error("session task failed".to_string()));
+            }
+        }
+        result
+    }
+
+    // Channel i + 1 of one multiplexed stream per ASIN; a failure of the stream fails every ASIN
+    async fn multiplexed(&self, query: &str, asins: &[String], understanding: &str) -> FanOutResult {
+        let mut result = FanOutResult::default();
+        let mut client = MultiplexedAdsClient::from_channel(self.channel.clone(), &self.config);
+        let sessions = asins
+            .iter()
+            .map(|asin| LogicalSession {
+                query: query.to_string(),
+                asin_id: asin.clone(),
+                understanding: understanding.to_string(),
+            })
+            .collect();
+        // The refined Contexts go out after the understanding delay; the rest of the
+        // deadline is the receive timeout, and the outer timeout guards the sends
+        let receive_timeout = self.deadline.saturating_sub(DEFAULT_UNDERSTANDING_DELAY);
+        match timeout(self.deadline, client.get_ads(sessions, receive_timeout)).await {
+            Ok(Ok(mut lists)) => {
+                for (i, asin) in asins.iter().enumerate() {
+                    match lists.remove(&(i as u32 + 1)).flatten() {
+                        Some(ads_list) => {
+                            result.ads.insert(asin.clone(), ads_list);
+                        }
+                        None => {
+                            result.failures.insert(asin.clone(), AsinFailure::NoResult);
+                        }
+                    }
+                }
+            }
+            Ok(Err(e)) => {
+                for asin in asins {
+                    result.failures.insert(asin.clone(), AsinFailure::Error(e.to_string()));
+                }
+            }
+            Err(_) => {
+                for asin in asins {
+                    result.failures.insert(asin.clone(), AsinFailure::DeadlineExceeded);
+                }
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,68 @@
+//! Rust client library for the ads bidirectional streaming service.
+//!
+//! The native clients (`AdsClient`, `MultiplexedAdsClient`) run on tokio over
+//! HTTP/2. Context construction, validation and version selection do not depend on
+//! the transport and also compile to `wasm32-unknown-unknown`, where the `web`
+//! feature adds a grpc-web client for the browser demo. `blocking::AdsClient` wraps
+//! the native client for synchronous callers.
+
+// tonic::Status is large, and Result<_, Status> (or an error wrapping it) is what
+// every handler and client call returns; boxing it at each call site buys nothing
+#![allow(clippy::result_large_err)]
+
+pub use ads_proto::ads;
+
+pub mod ack;
+pub mod context;
+pub mod error;
+pub mod limits;
+pub mod ordering;
+pub mod rerank;
+pub mod selection;
+pub mod signing;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod auto;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod backpressure;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod blocking;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod breaker;
+#[cfg(not(target_arch = "wasm32"))]
+mod client;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod clock;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod compression;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod config;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod dial;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod dynamic;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod endpoints;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod eval;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod experiments;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod fanout;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod load;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod multiplexed;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod output;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod replay;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod retry;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod understanding;
+#[cfg(all(feature = "web", target_arch = "wasm32"))]
+pub mod web;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use client::{connect, AdsClient, WarmUpReport};
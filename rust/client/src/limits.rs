@@ -0,0 +1,105 @@
+//! Caps on what a server may send the client, so a misbehaving server build cannot
+//! exhaust client memory during fuzz and chaos runs. The encoded size of every
+//! message is enforced by the decoder, before anything is allocated for it; ad
+//! counts, partition counts and string lengths are checked right after decoding.
+//! A list breaking any of them fails the session with
+//! `AdsClientError::ResponseLimit` instead of being used.
+
+use std::fmt;
+
+use tonic::{Code, Status};
+
+use crate::ads::{Ad, AdsList};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseLimits {
+    /// Largest encoded AdsList accepted, in bytes
+    pub max_message_bytes: usize,
+    /// Ads in a list or in any one partition, and partitions per list
+    pub max_ads_per_list: usize,
+    /// Longest accepted string or bytes field, in bytes
+    pub max_field_bytes: usize,
+}
+
+impl Default for ResponseLimits {
+    fn default() -> Self {
+        ResponseLimits { max_message_bytes: 4 * 1024 * 1024, max_ads_per_list: 1000, max_field_bytes: 1024 }
+    }
+}
+
+/// The first limit a response broke
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitViolation {
+    /// The decoder refused the encoded message
+    MessageTooLarge { limit: usize },
+    TooManyAds { count: usize, limit: usize },
+    TooManyPartitions { count: usize, limit: usize },
+    FieldTooLong { field: &'static str, len: usize, limit: usize },
+}
+
+impl fmt::Display for LimitViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitViolation::MessageTooLarge { limit } => write!(f, "AdsList larger than {} bytes", limit),
+            LimitViolation::TooManyAds { count, limit } => write!(f, "{} ads in one list (limit {})", count, limit),
+            LimitViolation::TooManyPartitions { count, limit } => {
+                write!(f, "{} partitions in one list (limit {})", count, limit)
+            }
+            LimitViolation::FieldTooLong { field, len, limit } => {
+                write!(f, "{} of {} bytes (limit {})", field, len, limit)
+            }
+        }
+    }
+}
+
+impl ResponseLimits {
+    /// Check a decoded AdsList against the count and length caps
+    pub fn check(&self, ads_list: &AdsList) -> Result<(), LimitViolation> {
+        let partitions = ads_list.query_results.len() + ads_list.placement_results.len();
+        if partitions > self.max_ads_per_list {
+            return Err(LimitViolation::TooManyPartitions { count: partitions, limit: self.max_ads_per_list });
+        }
+        self.check_field("signature", ads_list.signature.len())?;
+        self.check_ads(&ads_list.ads)?;
+        self.check_ads(&ads_list.original_ads)?;
+        for partition in &ads_list.query_results {
+            self.check_field("query", partition.query.len())?;
+            self.check_ads(&partition.ads)?;
+            self.check_ads(&partition.original_ads)?;
+        }
+        for partition in &ads_list.placement_results {
+            self.check_ads(&partition.ads)?;
+            self.check_ads(&partition.original_ads)?;
+        }
+        Ok(())
+    }
+
+    /// The violation behind a decoder status refusing an oversized message, if it is one
+    pub fn violation_of(&self, status: &Status) -> Option<LimitViolation> {
+        // tonic reports messages over max_decoding_message_size this way
+        (status.code() == Code::OutOfRange && status.message().contains("message length too large"))
+            .then_some(LimitViolation::MessageTooLarge { limit: self.max_message_bytes })
+    }
+
+    fn check_ads(&self, ads: &[Ad]) -> Result<(), LimitViolation> {
+        if ads.len() > self.max_ads_per_list {
+            return Err(LimitViolation::TooManyAds { count: ads.len(), limit: self.max_ads_per_list });
+        }
+        for ad in ads {
+            self.check_field("asin_id", ad.asin_id.len())?;
+            self.check_field("ad_id", ad.ad_id.len())?;
+            self.check_field("advertiser_id", ad.advertiser_id.len())?;
+            self.check_field("category", ad.category.len())?;
+            self.check_field("experiment_arm", ad.experiment_arm.len())?;
+            self.check_field("pipeline_version", ad.pipeline_version.len())?;
+        }
+        Ok(())
+    }
+
+    fn check_field(&self, field: &'static str, len: usize) -> Result<(), LimitViolation> {
+        if len > self.max_field_bytes {
+            return Err(LimitViolation::FieldTooLong { field, len, limit: self.max_field_bytes });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,406 @@
+//! Load generation for `ads-load` under two traffic models. Closed loop keeps a
+//! fixed number of sessions in flight, each worker starting its next session when
+//! the previous one ends, so a slow server throttles the offered load with it. Open
+//! loop starts sessions at Poisson arrival times for a target rate whatever the
+//! server does, so queueing shows up as latency instead of as lower throughput.
+//!
+//! Either model can follow a ramp profile. Arrivals come from a seeded RNG, so the
+//! same plan always produces the same schedule.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::task::{self, LocalSet};
+use tokio::time::sleep;
+use tonic::transport::Channel;
+use tracing::info;
+
+use ads_common::outcome::{OutcomeFunnel, SessionOutcome};
+
+use crate::client::AdsClient;
+use crate::config::ClientConfig;
+use crate::replay::RecordedSession;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TrafficModel {
+    /// Fixed concurrency: each worker runs sessions back to back
+    Closed,
+    /// Poisson arrivals at a target rate, independent of completions
+    Open,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RampProfile {
+    /// Full load for the whole run
+    Constant,
+    /// Load rises to full in equal steps
+    Step,
+    /// Load rises linearly from zero to full
+    Linear,
+    /// Full load with a burst of `spike_factor` times it over the middle tenth of the run
+    Spike,
+}
+
+/// What to send and how hard
+#[derive(Debug, Clone)]
+pub struct LoadPlan {
+    pub model: TrafficModel,
+    /// Sessions in flight at full load (closed loop)
+    pub concurrency: u32,
+    /// Session starts per second at full load (open loop)
+    pub rps: f64,
+    pub duration: Duration,
+    pub ramp: RampProfile,
+    /// Number of steps of the step ramp
+    pub steps: u32,
+    /// Load multiplier during the spike of the spike ramp
+    pub spike_factor: f64,
+    /// Open loop: arrivals finding this many sessions in flight are dropped and counted
+    pub max_in_flight: usize,
+    /// Seed of the arrival schedule
+    pub seed: u64,
+}
+
+impl LoadPlan {
+    /// Fraction of full load at `elapsed` into the run
+    pub fn level(&self, elapsed: Duration) -> f64 {
+        let progress = (elapsed.as_secs_f64() / self.duration.as_secs_f64().max(f64::EPSILON)).clamp(0.0, 1.0);
+        match self.ramp {
+            RampProfile::Constant => 1.0,
+            RampProfile::Step => {
+                let steps = self.steps.max(1) as f64;
+                ((progress * steps).floor() + 1.0).min(steps) / steps
+            }
+            RampProfile::Linear => progress,
+            RampProfile::Spike if (0.45..0.55).contains(&progress) => self.spike_factor,
+            RampProfile::Spike => 1.0,
+        }
+    }
+
+    fn peak_level(&self) -> f64 {
+        match self.ramp {
+            RampProfile::Spike => self.spike_factor.max(1.0),
+            _ => 1.0,
+        }
+    }
+
+    /// Closed-loop workers that should be running at `elapsed` (at least one)
+    pub fn active_workers(&self, elapsed: Duration) -> u32 {
+        ((self.concurrency as f64 * self.level(elapsed)).ceil() as u32).max(1)
+    }
+
+    /// Open-loop start offsets: a Poisson process whose rate follows the ramp, drawn
+    /// by thinning a process at the peak rate
+    pub fn arrivals(&self) -> Vec<Duration> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let peak_rps = self.rps * self.peak_level();
+        let mut arrivals = Vec::new();
+        if peak_rps <= 0.0 {
+            return arrivals;
+        }
+        let mut at_secs = 0.0;
+        loop {
+            // Exponential inter-arrival gap; 1 - U keeps ln away from 0
+            at_secs += -(1.0 - rng.gen::<f64>()).ln() / peak_rps;
+            if at_secs >= self.duration.as_secs_f64() {
+                return arrivals;
+            }
+            let at = Duration::from_secs_f64(at_secs);
+            if rng.gen::<f64>() * self.peak_level() < self.level(at) {
+                arrivals.push(at);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionResult {
+    Ads,
+    NoResult,
+    Error,
+    /// Open loop only: not started because `max_in_flight` sessions were running
+    Dropped,
+}
+
+/// One session of a run
+#[derive(Debug, Clone)]
+pub struct Sample {
+    /// When the session was due to start, from the start of the run
+    pub due: Duration,
+    /// From `due` to the session's end. Open-loop latency includes any lag in
+    /// starting the session, so a stalled generator cannot hide queueing.
+    pub latency: Duration,
+    pub result: SessionResult,
+    /// How far the session got through the versions (not for dropped arrivals)
+    pub outcome: Option<SessionOutcome>,
+    /// Trace id sent with the session (not for dropped arrivals)
+    pub trace_id: Option<String>,
+}
+
+/// Run `plan` against `channel`, every session sending the Contexts of `session`.
+/// Sessions share the channel and run as local tasks of one thread.
+pub async fn run(
+    channel: Channel,
+    endpoint: &str,
+    config: &ClientConfig,
+    session: &RecordedSession,
+    plan: &LoadPlan,
+) -> LoadReport {
+    let samples = Rc::new(RefCell::new(Vec::new()));
+    let new_client = || {
+        let mut client = AdsClient::from_service(channel.clone(), endpoint, config);
+        client.configure_session(session.seed, session.request_type());
+        client
+    };
+    let local = LocalSet::new();
+    local
+        .run_until(async {
+            let start = Instant::now();
+            let mut tasks = Vec::new();
+            match plan.model {
+                TrafficModel::Closed => {
+                    let workers = (plan.concurrency as f64 * plan.peak_level()).ceil() as u32;
+                    info!(workers = workers, duration_ms = plan.duration.as_millis() as u64, "Starting closed-loop load");
+                    for worker in 0..workers.max(1) {
+                        let mut client = new_client();
+                        let (session, plan, samples) = (session.clone(), plan.clone(), samples.clone());
+                        tasks.push(task::spawn_local(async move {
+                            while start.elapsed() < plan.duration {
+                                if worker >= plan.active_workers(start.elapsed()) {
+                                    sleep(Duration::from_millis(10)).await;
+                                    continue;
+                                }
+                                let due = start.elapsed();
+                                let result = client
+                                    .get_ads(session.query.clone(), session.asin_id.clone(), session.understanding.clone())
+                                    .await;
+                                samples.borrow_mut().push(Sample {
+                                    due,
+                                    latency: start.elapsed() - due,
+                                    result: classify(&result),
+                                    outcome: client.last_outcome(),
+                                    trace_id: client.last_trace_id().map(str::to_string),
+                                });
+                            }
+                        }));
+                    }
+                }
+                TrafficModel::Open => {
+                    let arrivals = plan.arrivals();
+                    info!(arrivals = arrivals.len(), duration_ms = plan.duration.as_millis() as u64, "Starting open-loop load");
+                    let in_flight = Rc::new(Cell::new(0usize));
+                    for due in arrivals {
+                        sleep((start + due).saturating_duration_since(Instant::now())).await;
+                        if in_flight.get() >= plan.max_in_flight {
+                            samples.borrow_mut().push(Sample {
+                                due,
+                                latency: Duration::ZERO,
+                                result: SessionResult::Dropped,
+                                outcome: None,
+                                trace_id: None,
+                            });
+                            continue;
+                        }
+                        in_flight.set(in_flight.get() + 1);
+                        let mut client = new_client();
+                        let (session, samples, in_flight) = (session.clone(), samples.clone(), in_flight.clone());
+                        tasks.push(task::spawn_local(async move {
+                            let result = client
+                                .get_ads(session.query.clone(), session.asin_id.clone(), session.understanding.clone())
+                                .await;
+                            in_flight.set(in_flight.get() - 1);
+                            samples.borrow_mut().push(Sample {
+                                due,
+                                latency: start.elapsed().saturating_sub(due),
+                                result: classify(&result),
+                                outcome: client.last_outcome(),
+                                trace_id: client.last_trace_id().map(str::to_string),
+                            });
+                        }));
+                    }
+                }
+            }
+            for task in tasks {
+                let _ = task.await;
+            }
+        })
+        .await;
+    let mut samples = samples.take();
+    samples.sort_by_key(|sample| sample.due);
+    LoadReport { plan: plan.clone(), samples }
+}
+
+/// Upper bounds (ms) of the latency histogram in the OpenMetrics output, as on the server
+const LATENCY_BUCKETS_MS: [f64; 10] = [1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0];
+
+fn classify<T, E>(result: &Result<Option<T>, E>) -> SessionResult {
+    match result {
+        Ok(Some(_)) => SessionResult::Ads,
+        Ok(None) => SessionResult::NoResult,
+        Err(_) => SessionResult::Error,
+    }
+}
+
+/// Samples of a finished run, rendered per second of the run and in total
+#[derive(Debug)]
+pub struct LoadReport {
+    pub plan: LoadPlan,
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Debug, Default)]
+struct Bucket {
+    ads: usize,
+    no_result: usize,
+    errors: usize,
+    dropped: usize,
+    latencies_ms: Vec<f64>,
+}
+
+impl Bucket {
+    fn add(&mut self, sample: &Sample) {
+        match sample.result {
+            SessionResult::Ads => self.ads += 1,
+            SessionResult::NoResult => self.no_result += 1,
+            SessionResult::Error => self.errors += 1,
+            SessionResult::Dropped => {
+                self.dropped += 1;
+                return;
+            }
+        }
+        self.latencies_ms.push(sample.latency.as_secs_f64() * 1000.0);
+    }
+
+    fn row(&mut self, label: &str, level: &str) -> String {
+        self.latencies_ms.sort_by(|a, b| a.total_cmp(b));
+        let percentile = |q: f64| {
+            if self.latencies_ms.is_empty() {
+                return "-".to_string();
+            }
+            format!("{:.1}", self.latencies_ms[((self.latencies_ms.len() - 1) as f64 * q).round() as usize])
+        };
+        format!(
+            "{:<6}  {:>5}  {:>7}  {:>6}  {:>6}  {:>5}  {:>7}  {:>8}  {:>8}  {:>8}",
+            label,
+            level,
+            self.ads + self.no_result + self.errors,
+            self.ads,
+            self.no_result,
+            self.errors,
+            self.dropped,
+            percentile(0.5),
+            percentile(0.95),
+            percentile(0.99),
+        )
+    }
+}
+
+impl LoadReport {
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let plan = &self.plan;
+        let _ = match plan.model {
+            TrafficModel::Closed => writeln!(
+                out,
+                "closed loop: {} sessions in flight, {:?} ramp, {:.1}s",
+                plan.concurrency,
+                plan.ramp,
+                plan.duration.as_secs_f64()
+            ),
+            TrafficModel::Open => writeln!(
+                out,
+                "open loop: {} sessions/s (Poisson, seed {}), {:?} ramp, {:.1}s",
+                plan.rps,
+                plan.seed,
+                plan.ramp,
+                plan.duration.as_secs_f64()
+            ),
+        };
+        let _ = writeln!(
+            out,
+            "\n{:<6}  {:>5}  {:>7}  {:>6}  {:>6}  {:>5}  {:>7}  {:>8}  {:>8}  {:>8}",
+            "SECOND", "LEVEL", "STARTED", "ADS", "NONE", "ERR", "DROPPED", "P50_MS", "P95_MS", "P99_MS"
+        );
+        let mut seconds: BTreeMap<u64, Bucket> = BTreeMap::new();
+        let mut total = Bucket::default();
+        for sample in &self.samples {
+            seconds.entry(sample.due.as_secs()).or_default().add(sample);
+            total.add(sample);
+        }
+        for (second, bucket) in &mut seconds {
+            let level = format!("{:.2}", plan.level(Duration::from_secs(*second)));
+            let _ = writeln!(out, "{}", bucket.row(&second.to_string(), &level));
+        }
+        let _ = writeln!(out, "{}", total.row("total", ""));
+        let completed = total.ads + total.no_result + total.errors;
+        let _ = writeln!(
+            out,
+            "\n{} sessions in {:.1}s ({:.1}/s achieved)",
+            completed,
+            plan.duration.as_secs_f64(),
+            completed as f64 / plan.duration.as_secs_f64().max(f64::EPSILON)
+        );
+        let mut outcomes = OutcomeFunnel::default();
+        for outcome in self.samples.iter().filter_map(|sample| sample.outcome) {
+            outcomes.record(outcome);
+        }
+        let _ = writeln!(out, "outcomes: {}", outcomes.render());
+        out
+    }
+
+    /// Session latency histogram per result as OpenMetrics text. Each bucket's
+    /// exemplar is the slowest traced session in it, so a slow bucket names a trace
+    /// to look up on the server.
+    pub fn to_openmetrics(&self) -> String {
+        let mut out = String::from("# TYPE ads_load_session_latency_ms histogram\n");
+        for result in [SessionResult::Ads, SessionResult::NoResult, SessionResult::Error] {
+            let samples: Vec<&Sample> = self.samples.iter().filter(|s| s.result == result).collect();
+            if samples.is_empty() {
+                continue;
+            }
+            let label = match result {
+                SessionResult::Ads => "ads",
+                SessionResult::NoResult => "no_result",
+                _ => "error",
+            };
+            let latency_ms = |sample: &Sample| sample.latency.as_secs_f64() * 1000.0;
+            let mut cumulative = 0;
+            for i in 0..=LATENCY_BUCKETS_MS.len() {
+                let lower = if i == 0 { f64::NEG_INFINITY } else { LATENCY_BUCKETS_MS[i - 1] };
+                let upper = LATENCY_BUCKETS_MS.get(i).copied().unwrap_or(f64::INFINITY);
+                let in_bucket: Vec<&&Sample> =
+                    samples.iter().filter(|s| latency_ms(s) > lower && latency_ms(s) <= upper).collect();
+                cumulative += in_bucket.len();
+                let le = LATENCY_BUCKETS_MS.get(i).map_or("+Inf".to_string(), |bound| format!("{:?}", bound));
+                let _ = write!(
+                    out,
+                    "ads_load_session_latency_ms_bucket{{result=\"{}\",le=\"{}\"}} {}",
+                    label, le, cumulative
+                );
+                let slowest = in_bucket
+                    .iter()
+                    .filter(|s| s.trace_id.is_some())
+                    .max_by(|a, b| latency_ms(a).total_cmp(&latency_ms(b)));
+                if let Some(sample) = slowest {
+                    let _ = write!(
+                        out,
+                        " # {{trace_id=\"{}\"}} {:.3}",
+                        sample.trace_id.as_deref().unwrap_or_default(),
+                        latency_ms(sample)
+                    );
+                }
+                out.push('\n');
+            }
+            let sum: f64 = samples.iter().map(|s| latency_ms(s)).sum();
+            let _ = writeln!(out, "ads_load_session_latency_ms_count{{result=\"{}\"}} {}", label, samples.len());
+            let _ = writeln!(out, "ads_load_session_latency_ms_sum{{result=\"{}\"}} {}", label, sum);
+        }
+        out.push_str("# EOF\n");
+        out
+    }
+}
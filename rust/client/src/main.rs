@@ -1,282 +1,793 @@
-use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::time::{sleep, timeout};
-use tokio_stream::wrappers::ReceiverStream;
-use tonic::{transport::Channel, Request, Status};
-use rand::Rng;
-use tracing::{info, warn, error, debug, span, Level};
-
-// Include the generated protobuf code
-pub mod ads {
-    tonic::include_proto!("ads");
+use clap::{Parser, ValueEnum};
+use tracing::{info, warn, error};
+
+use ads_common::outcome::OutcomeFunnel;
+use ads_common::report::{self, ShutdownReport};
+use ads_client::ads::{Placement, RequestType, ScoreNormalization};
+use ads_client::auto::{self, AutoConfig, AutoSelector};
+use ads_client::backpressure::OverflowPolicy;
+use ads_client::breaker::BreakerConfig;
+use ads_client::compression::Compression;
+use ads_client::config::{ClientConfig, ClientTls};
+use ads_client::dial::HappyEyeballs;
+use ads_client::endpoints::{BalancePolicy, EndpointPool};
+use ads_client::limits::ResponseLimits;
+use ads_client::fanout::{FanOutClient, FanOutMode};
+use ads_client::{connect, dynamic};
+use ads_client::multiplexed::{LogicalSession, MultiplexedAdsClient};
+use ads_client::ordering::VersionConflictPolicy;
+use ads_client::output::OutputFormat;
+use ads_client::replay::{record_session, RecordedSession};
+use ads_client::rerank::RerankHook;
+use ads_client::retry::ServiceConfig;
+use ads_client::selection::{EarlyExit, SelectionStrategy};
+use ads_client::signing::ResponseVerifier;
+use ads_client::understanding::{DelayDistribution, UnderstandingSim, DEFAULT_TEMPLATE};
+use ads_proto::codec::{self, DictionaryMode};
+use ads_proto::score::TieBreak;
+
+/// CLI names for the proto RequestType values
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Mode {
+    Keyword,
+    AsinDetail,
+    CategoryBrowse,
 }
 
-use ads::{ads_service_client::AdsServiceClient, Context, AdsList};
+impl From<Mode> for RequestType {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Keyword => RequestType::Keyword,
+            Mode::AsinDetail => RequestType::AsinDetail,
+            Mode::CategoryBrowse => RequestType::CategoryBrowse,
+        }
+    }
+}
 
-pub struct AdsClient {
-    client: AdsServiceClient<Channel>,
+/// CLI names for the proto Placement values
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum PlacementArg {
+    TopBanner,
+    Sidebar,
+    Footer,
 }
 
-impl AdsClient {
-    /// Create a new AdsClient and connect to the server
-    pub async fn new(server_addr: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        info!("Connecting to server at {}", server_addr);
-        let client = AdsServiceClient::connect(server_addr.to_string()).await?;
-        Ok(AdsClient { client })
+impl From<PlacementArg> for Placement {
+    fn from(placement: PlacementArg) -> Self {
+        match placement {
+            PlacementArg::TopBanner => Placement::TopBanner,
+            PlacementArg::Sidebar => Placement::Sidebar,
+            PlacementArg::Footer => Placement::Footer,
+        }
     }
+}
 
-    /// Get ads using bidirectional streaming with the specified context
-    pub async fn get_ads(
-        &mut self,
-        query: String,
-        asin_id: String,
-        understanding: String,
-    ) -> Result<Option<AdsList>, Box<dyn std::error::Error>> {
-        let overall_start = Instant::now();
-        let span = span!(Level::INFO, "bidirectional_stream", 
-                        query = %query, 
-                        asin_id = %asin_id, 
-                        understanding_provided = !understanding.is_empty());
-        let _enter = span.enter();
-        
-        info!(
-            query = %query,
-            asin_id = %asin_id,
-            understanding_provided = !understanding.is_empty(),
-            "Starting bidirectional stream"
-        );
-        
-        // Create a channel for sending Context messages
-        let (tx, rx) = tokio::sync::mpsc::channel(10);
-        let request_stream = ReceiverStream::new(rx);
-        
-        // Start the bidirectional stream
-        let mut response_stream = self.client
-            .get_ads(Request::new(request_stream))
-            .await?
-            .into_inner();
-        
-        // Buffer for AdsList messages by version
-        let mut ads_buffer: HashMap<u32, AdsList> = HashMap::new();
-        
-        // Send first Context message
-        let first_context = Context {
-            query: query.clone(),
-            asin_id: asin_id.clone(),
-            understanding: "".to_string(), // Empty initially
-        };
-        
-        info!(
-            context_number = 1,
-            understanding_empty = true,
-            elapsed_ms = overall_start.elapsed().as_millis() as u64,
-            "Sending Context message"
-        );
-        tx.send(first_context).await.map_err(|e| format!("Failed to send first context: {}", e))?;
-        
-        // Wait 50ms before sending second Context
-        debug!("Waiting 50ms before second Context message");
-        sleep(Duration::from_millis(50)).await;
-        
-        // Send second Context message with understanding
-        let second_context = Context {
-            query: query.clone(),
-            asin_id: asin_id.clone(),
-            understanding: understanding.clone(),
-        };
-        
-        info!(
-            context_number = 2,
-            understanding_length = understanding.len(),
-            elapsed_ms = overall_start.elapsed().as_millis() as u64,
-            "Sending Context message"
-        );
-        tx.send(second_context).await.map_err(|e| format!("Failed to send second context: {}", e))?;
-        
-        // Close the sending side (half-close)
-        drop(tx);
-        info!(
-            elapsed_ms = overall_start.elapsed().as_millis() as u64,
-            "Half-closed client stream"
-        );
-        
-        // Generate random timeout between 30-120ms with jitter
-        let mut rng = rand::thread_rng();
-        let base_timeout = rng.gen_range(30..=120);
-        let jitter = rng.gen_range(-5..=5);
-        let timeout_ms = (base_timeout + jitter).max(30).min(120);
-        let timeout_duration = Duration::from_millis(timeout_ms as u64);
-        
-        info!(
-            timeout_ms = timeout_ms,
-            min_timeout = 30,
-            max_timeout = 120,
-            "Generated random timeout for result selection"
-        );
-        
-        // Start receiving responses and apply timeout
-        let receive_task = async {
-            while let Some(response) = response_stream.message().await? {
-                let version = response.version;
-                let ads_count = response.ads.len();
-                let elapsed_ms = overall_start.elapsed().as_millis() as u64;
-                let is_replacement = ads_buffer.contains_key(&version);
-                
-                info!(
-                    version = version,
-                    ads_count = ads_count,
-                    elapsed_ms = elapsed_ms,
-                    is_replacement = is_replacement,
-                    "Received AdsList"
-                );
-                
-                // Log debug details about the ads if debug level is enabled
-                for (i, ad) in response.ads.iter().enumerate() {
-                    debug!(
-                        version = version,
-                        ad_index = i,
-                        asin_id = %ad.asin_id,
-                        ad_id = %ad.ad_id,
-                        score = format!("{:.3}", ad.score),
-                        "Ad details"
-                    );
-                }
-                
-                // Buffer the response, replacing older versions if they exist
-                if let Some(old_ads) = ads_buffer.insert(version, response) {
-                    debug!(
-                        version = version,
-                        old_ads_count = old_ads.ads.len(),
-                        new_ads_count = ads_count,
-                        "Replaced AdsList in buffer"
-                    );
-                } else {
-                    debug!(
-                        version = version,
-                        ads_count = ads_count,
-                        "Added new AdsList to buffer"
-                    );
-                }
-            }
-            Ok::<(), Status>(())
-        };
-        
-        // Apply timeout to the receiving process
-        match timeout(timeout_duration, receive_task).await {
-            Ok(Ok(())) => {
-                info!(
-                    elapsed_ms = overall_start.elapsed().as_millis() as u64,
-                    versions_received = ads_buffer.len(),
-                    "Stream completed normally before timeout"
-                );
-            }
-            Ok(Err(e)) => {
-                warn!(
-                    error = %e,
-                    elapsed_ms = overall_start.elapsed().as_millis() as u64,
-                    "Stream error occurred"
-                );
-            }
-            Err(_) => {
-                info!(
-                    timeout_ms = timeout_ms,
-                    elapsed_ms = overall_start.elapsed().as_millis() as u64,
-                    versions_received = ads_buffer.len(),
-                    "Client timeout reached - proceeding with available results"
-                );
-            }
+/// CLI names for the score TieBreak policies
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum TieBreakPolicy {
+    AdId,
+    Recency,
+    Seeded,
+}
+
+impl From<TieBreakPolicy> for TieBreak {
+    fn from(policy: TieBreakPolicy) -> Self {
+        match policy {
+            TieBreakPolicy::AdId => TieBreak::AdId,
+            TieBreakPolicy::Recency => TieBreak::Recency,
+            TieBreakPolicy::Seeded => TieBreak::Seeded,
         }
-        
-        // Log buffer state for debugging
-        let mut versions: Vec<u32> = ads_buffer.keys().cloned().collect();
-        versions.sort();
-        debug!(
-            buffer_size = ads_buffer.len(),
-            available_versions = ?versions,
-            elapsed_ms = overall_start.elapsed().as_millis() as u64,
-            "Buffer state at timeout"
-        );
-        
-        // Return the most recent AdsList (highest version number)
-        if let Some(latest_ads) = ads_buffer.values().max_by_key(|ads| ads.version) {
-            let total_duration_ms = overall_start.elapsed().as_millis() as u64;
-            
-            info!(
-                selected_version = latest_ads.version,
-                ads_count = latest_ads.ads.len(),
-                total_duration_ms = total_duration_ms,
-                versions_considered = ads_buffer.len(),
-                "FINAL RESULT: Selected AdsList"
-            );
-            
-            // Log performance summary
-            info!(
-                operation = "bidirectional_stream",
-                total_duration_ms = total_duration_ms,
-                timeout_used_ms = timeout_ms,
-                versions_received = ads_buffer.len(),
-                final_version = latest_ads.version,
-                "Performance summary"
-            );
-            
-            Ok(Some(latest_ads.clone()))
-        } else {
-            let total_duration_ms = overall_start.elapsed().as_millis() as u64;
-            warn!(
-                total_duration_ms = total_duration_ms,
-                timeout_ms = timeout_ms,
-                buffer_size = ads_buffer.len(),
-                "FINAL RESULT: No AdsList received within timeout"
-            );
-            Ok(None)
+    }
+}
+
+/// CLI names for the proto ScoreNormalization values
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Normalization {
+    None,
+    MinMax,
+    Softmax,
+}
+
+/// CLI names for the client's VersionConflictPolicy
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum OnVersionConflict {
+    KeepFirst,
+    KeepLast,
+    Error,
+}
+
+impl From<OnVersionConflict> for VersionConflictPolicy {
+    fn from(policy: OnVersionConflict) -> Self {
+        match policy {
+            OnVersionConflict::KeepFirst => VersionConflictPolicy::KeepFirst,
+            OnVersionConflict::KeepLast => VersionConflictPolicy::KeepLast,
+            OnVersionConflict::Error => VersionConflictPolicy::Error,
         }
     }
 }
 
+impl From<Normalization> for ScoreNormalization {
+    fn from(normalization: Normalization) -> Self {
+        match normalization {
+            Normalization::None => ScoreNormalization::None,
+            Normalization::MinMax => ScoreNormalization::MinMax,
+            Normalization::Softmax => ScoreNormalization::Softmax,
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "ads-client", about = "Rust Ads bidirectional streaming client")]
+struct Args {
+    /// Server address; sessions are balanced over several comma-separated addresses
+    #[arg(default_value = "http://127.0.0.1:50051")]
+    server_addr: String,
+
+    /// Search query
+    #[arg(default_value = "coffee maker")]
+    query: String,
+
+    /// Product identifier
+    #[arg(default_value = "B000123")]
+    asin_id: String,
+
+    /// Refined understanding sent with the second Context
+    #[arg(default_value = "refined understanding based on query analysis")]
+    understanding: String,
+
+    /// Synthesize the understanding per session (ignoring the positional one) and send
+    /// it after a delay drawn from --understanding-delay
+    #[arg(long)]
+    simulate_understanding: bool,
+
+    /// Write the shutdown report (sessions, errors, outcomes, latency percentiles,
+    /// uptime) to this JSON file on exit, including Ctrl-C; it is logged either way
+    #[arg(long, env = "ADS_SHUTDOWN_REPORT")]
+    shutdown_report: Option<PathBuf>,
+
+    /// Send the understanding in the first and only Context, once it is ready, instead
+    /// of an empty first Context followed by a refined one
+    #[arg(long, env = "ADS_SINGLE_CONTEXT")]
+    single_context: bool,
+
+    /// Delay distribution of simulated understanding: fixed:MS, uniform:MIN-MAX or lognormal:MEDIAN,SIGMA
+    #[arg(long, default_value = "lognormal:50,0.4", requires = "simulate_understanding")]
+    understanding_delay: DelayDistribution,
+
+    /// Template of simulated understanding; {query}, {tokens}, {expanded}, {intent} and {category} are filled in
+    #[arg(long, default_value = DEFAULT_TEMPLATE, requires = "simulate_understanding")]
+    understanding_template: String,
+
+    /// Related terms added per recognized query token in simulated understanding
+    #[arg(long, default_value_t = 2, requires = "simulate_understanding")]
+    expand_tokens: usize,
+
+    /// Seed of simulated understanding delays and expansions (random if unset)
+    #[arg(long, requires = "simulate_understanding")]
+    understanding_seed: Option<u64>,
+
+    /// Interval between HTTP/2 keepalive PINGs in milliseconds; detects connections
+    /// that die between sessions, not within one selection timeout
+    #[arg(long, env = "ADS_KEEPALIVE_INTERVAL_MS", default_value_t = 10_000)]
+    keepalive_interval_ms: u64,
+
+    /// Time to wait for a keepalive PING acknowledgement in milliseconds
+    #[arg(long, env = "ADS_KEEPALIVE_TIMEOUT_MS", default_value_t = 5_000)]
+    keepalive_timeout_ms: u64,
+
+    /// Head start (ms) of each connection attempt over the next when a hostname
+    /// server address resolves to several addresses (happy eyeballs)
+    #[arg(long, env = "ADS_CONNECT_ATTEMPT_DELAY_MS", default_value_t = 250)]
+    connect_attempt_delay_ms: u64,
+
+    /// Time (ms) one resolved address may take to accept the connection
+    #[arg(long, env = "ADS_CONNECT_TIMEOUT_MS", default_value_t = 2_000)]
+    connect_timeout_ms: u64,
+
+    /// Connect over TLS; `http://` server addresses are dialed as `https://`
+    #[arg(long, env = "ADS_TLS")]
+    tls: bool,
+
+    /// PEM CA certificate to verify the server's certificate with, e.g. the one that
+    /// signed a self-signed test certificate (default: the system's trusted roots)
+    #[arg(long, env = "ADS_TLS_CA_CERT", requires = "tls")]
+    tls_ca_cert: Option<PathBuf>,
+
+    /// Name to verify the server's certificate for when it differs from the host
+    /// of the server address, e.g. `localhost` when dialing 127.0.0.1
+    #[arg(long, env = "ADS_TLS_DOMAIN", requires = "tls")]
+    tls_domain: Option<String>,
+
+    /// gRPC service config with retry policies (inline JSON or a file path); unary and
+    /// server-streaming calls failing with a retryable code before any response are
+    /// retried transparently by the channel, under the per-session retries
+    #[arg(long, env = "ADS_SERVICE_CONFIG", value_name = "JSON|FILE")]
+    service_config: Option<ServiceConfig>,
+
+    /// Session seed for reproducible server-side generation
+    #[arg(long, env = "ADS_SEED")]
+    seed: Option<u64>,
+
+    /// Batch these queries into one Context (repeatable); results are printed per query
+    #[arg(long = "batch-query")]
+    batch_queries: Vec<String>,
+
+    /// Fill this placement (repeatable); each is ranked from its own pool and results
+    /// are printed per placement
+    #[arg(long = "placement", value_enum)]
+    placements: Vec<PlacementArg>,
+
+    /// Retrieval/ranking mode for the request
+    #[arg(long, value_enum, env = "ADS_MODE", default_value = "keyword")]
+    mode: Mode,
+
+    /// Host this many logical sessions on a single multiplexed stream
+    #[arg(long)]
+    multiplex: Option<u32>,
+
+    /// Result selection timeout for multiplexed sessions in milliseconds
+    #[arg(long, default_value_t = 120)]
+    multiplex_timeout_ms: u64,
+
+    /// Request ads for each of these ASINs at once (comma-separated), as a detail-page
+    /// widget would, instead of --asin-id; results and failures are printed per ASIN
+    #[arg(long, env = "ADS_ASINS", value_delimiter = ',')]
+    asins: Vec<String>,
+
+    /// Overall deadline (ms) of an --asins fan-out
+    #[arg(long, env = "ADS_FANOUT_DEADLINE_MS", default_value_t = 300)]
+    fanout_deadline_ms: u64,
+
+    /// Carry an --asins fan-out on one multiplexed stream instead of a stream per ASIN
+    #[arg(long)]
+    fanout_multiplexed: bool,
+
+    /// Union ads across all received versions instead of keeping only the latest
+    #[arg(long)]
+    merge_versions: bool,
+
+    /// Re-normalize each version's scores before merging so versions compare fairly
+    #[arg(long, value_enum, default_value = "none")]
+    renormalize: Normalization,
+
+    /// Order of equal scores when merging versions: ad_id, latest version first, or
+    /// pseudo-random from --seed
+    #[arg(long, value_enum, default_value = "ad-id")]
+    tie_break: TieBreakPolicy,
+
+    /// Boost ads from this advertiser on the client (repeatable); the server's order is shown as WAS
+    #[arg(long = "boost-advertiser")]
+    boost_advertisers: Vec<String>,
+
+    /// Score multiplier for --boost-advertiser
+    #[arg(long, default_value_t = 1.5)]
+    boost_factor: f64,
+
+    /// Stop waiting as soon as a version >= this arrives with at least --min-ads ads
+    #[arg(long, env = "ADS_MIN_ACCEPTABLE_VERSION")]
+    min_acceptable_version: Option<u32>,
+
+    /// Fixed result selection timeout instead of a random 30-120ms per session
+    #[arg(long, env = "ADS_SELECTION_TIMEOUT_MS")]
+    selection_timeout_ms: Option<u64>,
+
+    /// Minimum ads required by --min-acceptable-version
+    #[arg(long, default_value_t = 1)]
+    min_ads: usize,
+
+    /// Compress outgoing Contexts (enabled after the server advertises the encoding)
+    #[arg(long, value_enum, env = "ADS_COMPRESS")]
+    compress: Option<Compression>,
+
+    /// Compress every message with this zstd dictionary (see `ads-server train-dictionary`);
+    /// the server must load the same dictionary
+    #[arg(long, env = "ADS_ZSTD_DICTIONARY")]
+    zstd_dictionary: Option<PathBuf>,
+
+    /// Output for the final AdsList: table, json, csv, quiet, or a per-ad template
+    /// such as "{ad_id}\t{score:.2}\t{rank_reason}"
+    #[arg(long, default_value = "table")]
+    format: OutputFormat,
+
+    /// Colorize the printed AdsList table
+    #[arg(long)]
+    color: bool,
+
+    /// Number of sessions to run sequentially on the same connection
+    #[arg(long, default_value_t = 1)]
+    sessions: u32,
+
+    /// Capacity of the channel feeding Contexts into the request stream
+    #[arg(long, env = "ADS_REQUEST_CHANNEL_CAPACITY", default_value_t = 10)]
+    request_channel_capacity: usize,
+
+    /// What sending a Context does when the request channel is full
+    #[arg(long, value_enum, env = "ADS_REQUEST_OVERFLOW", default_value = "block")]
+    request_overflow: OverflowPolicy,
+
+    /// What to do with an AdsList version received twice or after a higher one:
+    /// keep the first copy, keep the last, or fail the session to surface the protocol bug
+    #[arg(long, value_enum, env = "ADS_ON_VERSION_CONFLICT", default_value = "keep-last")]
+    on_version_conflict: OnVersionConflict,
+
+    /// Server public key (64 hex digits, logged by a server started with --signing-key);
+    /// AdsLists whose signature does not verify against it are flagged as tampered
+    #[arg(long, env = "ADS_VERIFY_KEY", value_name = "HEX")]
+    verify_key: Option<ResponseVerifier>,
+
+    /// Flag a session as stalled (outcome `stalled`) when no AdsList arrives for this
+    /// many milliseconds after one did, independent of the selection timeout
+    #[arg(long, env = "ADS_STALL_GAP_MS")]
+    stall_gap_ms: Option<u64>,
+
+    /// On a stall, send the server a flush control message asking for its pending
+    /// refinements; keeps the request stream open until the final version arrives
+    #[arg(long, requires = "stall_gap_ms")]
+    stall_flush: bool,
+
+    /// Ask the server to acknowledge every Context (x-ack-contexts) and warn about
+    /// Contexts it never acknowledged, e.g. dropped by a fault-injecting proxy
+    #[arg(long, env = "ADS_ACK_CONTEXTS")]
+    ack_contexts: bool,
+
+    /// Report the version each session used, the ads served and the outcome back to
+    /// the server (ReportFeedback) for its wasted-work and per-treatment accounting
+    #[arg(long, env = "ADS_REPORT_FEEDBACK")]
+    report_feedback: bool,
+
+    /// BCP 47 language tag of the query and understanding, e.g. de-DE; the server
+    /// normalizes them for it (default: the server's --default-locale)
+    #[arg(long, env = "ADS_LOCALE")]
+    locale: Option<String>,
+
+    /// Largest encoded AdsList accepted, in bytes; larger ones fail the session
+    #[arg(long, env = "ADS_MAX_RESPONSE_BYTES", default_value_t = ResponseLimits::default().max_message_bytes)]
+    max_response_bytes: usize,
+
+    /// Most ads accepted in one AdsList or partition (and partitions per list)
+    #[arg(long, env = "ADS_MAX_ADS_PER_LIST", default_value_t = ResponseLimits::default().max_ads_per_list)]
+    max_ads_per_list: usize,
+
+    /// Longest string or bytes field accepted in an AdsList, in bytes
+    #[arg(long, env = "ADS_MAX_FIELD_BYTES", default_value_t = ResponseLimits::default().max_field_bytes)]
+    max_field_bytes: usize,
+
+    /// Experiment label KEY=VALUE attached to every session (repeatable), e.g. scenario=cold-cache
+    #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+    labels: Vec<(String, String)>,
+
+    /// Ask the server for this generator variant (mock, catalog, embedding) via
+    /// x-generator metadata; the server ignores variants it has not enabled
+    #[arg(long, env = "ADS_GENERATOR")]
+    generator: Option<String>,
+
+    /// Priority class of the sessions (x-priority): interactive or batch; under load
+    /// the server sheds and queues batch sessions first
+    #[arg(long, env = "ADS_PRIORITY", value_parser = ["interactive", "batch"])]
+    priority: Option<String>,
+
+    /// Ask the server to log these sessions at DEBUG level (x-debug-session); the
+    /// server grants a limited number of such sessions per minute
+    #[arg(long, env = "ADS_DEBUG_SESSION")]
+    debug_session: bool,
+
+    /// Send an idempotency key per session so retries can attach to a still-running original
+    #[arg(long)]
+    idempotent: bool,
+
+    /// Follow the alternate endpoint named by a server refusing sessions for maintenance
+    #[arg(long, env = "ADS_FOLLOW_REDIRECTS")]
+    follow_redirects: bool,
+
+    /// Handshakes used to estimate the server's clock offset on connect, logged so
+    /// logsum can correct one-way latencies (0 = skip)
+    #[arg(long, env = "ADS_CLOCK_PROBES", default_value_t = 4)]
+    clock_probes: u32,
+
+    /// Handshakes sent on every endpoint before the first session, so measured
+    /// sessions don't pay connection and HTTP/2 setup costs (0 = no warm-up)
+    #[arg(long, env = "ADS_WARM_UP", default_value_t = 0)]
+    warm_up: u32,
+
+    /// Also send one throwaway unary request (labeled warm-up=true) per endpoint
+    #[arg(long)]
+    warm_up_request: bool,
+
+    /// Send a W3C traceparent with every session; the server keeps its trace id as an
+    /// exemplar of the session's latency observations
+    #[arg(long, env = "ADS_TRACE_CONTEXT")]
+    trace_context: bool,
+
+    /// Maximum retries per session for retryable failures
+    #[arg(long, env = "ADS_MAX_RETRIES", default_value_t = 2)]
+    max_retries: u32,
+
+    /// Longest server retry-after hint (ms) to wait out; sessions told to wait longer
+    /// fail instead of retrying
+    #[arg(long, env = "ADS_MAX_RETRY_AFTER_MS", default_value_t = 2000)]
+    max_retry_after_ms: u64,
+
+    /// Call this method ("Method" or "package.Service/Method") using descriptors fetched
+    /// through server reflection instead of compiled proto types
+    #[arg(long, value_name = "METHOD")]
+    dynamic: Option<String>,
+
+    /// JSON request for --dynamic (an array for client-streaming methods); defaults to
+    /// a Context built from the positional arguments
+    #[arg(long, requires = "dynamic")]
+    request_json: Option<String>,
+
+    /// Probe bidi, server-streaming and unary calls and use the fastest per request class
+    #[arg(long)]
+    auto: bool,
+
+    /// How sessions are spread over several server addresses
+    #[arg(long, value_enum, env = "ADS_BALANCE", default_value = "healthiest")]
+    balance: BalancePolicy,
+
+    /// Session latency objective per endpoint in milliseconds; misses worsen the endpoint's score
+    #[arg(long, env = "ADS_LATENCY_SLO_MS", default_value_t = 120)]
+    latency_slo_ms: u64,
+
+    /// Print each endpoint's latency, error rate, SLO attainment and score after the sessions
+    #[arg(long)]
+    endpoint_scores: bool,
+
+    /// Append each session to this JSON-lines file for later replay with ads-replay
+    #[arg(long)]
+    record: Option<PathBuf>,
+}
+
+fn parse_label(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got {:?}", s)),
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing
-    tracing_subscriber::fmt::init();
+async fn main() -> ads_common::Result<()> {
+    // Initialize tracing; ADS_LOG_FORMAT=json emits one JSON object per line for logsum
+    if std::env::var("ADS_LOG_FORMAT").as_deref() == Ok("json") {
+        tracing_subscriber::fmt().json().init();
+    } else {
+        tracing_subscriber::fmt::init();
+    }
 
     // Parse command line arguments or use defaults
-    let server_addr = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| "http://127.0.0.1:50051".to_string());
-    
-    let query = std::env::args()
-        .nth(2)
-        .unwrap_or_else(|| "coffee maker".to_string());
-    
-    let asin_id = std::env::args()
-        .nth(3)
-        .unwrap_or_else(|| "B000123".to_string());
+    let started = Instant::now();
+    let args = Args::parse();
+    if let Some(path) = &args.zstd_dictionary {
+        codec::install_dictionary(&std::fs::read(path)?, DictionaryMode::Initiate)?;
+        info!(path = %path.display(), "Compressing messages with zstd dictionary");
+    }
+    // Dynamic calls and multiplexed streams use the first address only
+    let endpoints: Vec<String> = args.server_addr.split(',').map(str::to_string).collect();
+    let server_addr = endpoints[0].clone();
+    let query = args.query;
+    let asin_id = args.asin_id;
+    let tls = if args.tls {
+        Some(ClientTls {
+            ca_certificate: args.tls_ca_cert.as_deref().map(std::fs::read).transpose()?,
+            domain_name: args.tls_domain.clone(),
+        })
+    } else {
+        None
+    };
+    let config = ClientConfig {
+        keepalive_interval: Duration::from_millis(args.keepalive_interval_ms),
+        keepalive_timeout: Duration::from_millis(args.keepalive_timeout_ms),
+        happy_eyeballs: HappyEyeballs {
+            attempt_delay: Duration::from_millis(args.connect_attempt_delay_ms),
+            address_timeout: Duration::from_millis(args.connect_timeout_ms),
+        },
+        tls,
+        service_config: args.service_config.clone(),
+        seed: args.seed,
+        request_type: args.mode.into(),
+        batch_queries: args.batch_queries.clone(),
+        placements: args.placements.iter().map(|&placement| placement.into()).collect(),
+        selection: if args.merge_versions {
+            SelectionStrategy::MergeVersions
+        } else {
+            SelectionStrategy::LatestVersion
+        },
+        renormalize: args.renormalize.into(),
+        tie_break: args.tie_break.into(),
+        rerank: (!args.boost_advertisers.is_empty())
+            .then(|| RerankHook::boost_advertisers(args.boost_advertisers.clone(), args.boost_factor)),
+        early_exit: args.min_acceptable_version.map(|min_version| EarlyExit {
+            min_version,
+            min_ads: args.min_ads,
+        }),
+        selection_timeout: args.selection_timeout_ms.map(Duration::from_millis),
+        compression: args.compress,
+        breaker: BreakerConfig {
+            max_retries: args.max_retries,
+            max_retry_after: Duration::from_millis(args.max_retry_after_ms),
+            ..BreakerConfig::default()
+        },
+        idempotency_keys: args.idempotent,
+        labels: args.labels.clone(),
+        generator_variant: args.generator.clone(),
+        priority: args.priority.clone(),
+        debug_session: args.debug_session,
+        follow_redirects: args.follow_redirects,
+        clock_probes: args.clock_probes,
+        trace_context: args.trace_context,
+        understanding_sim: args.simulate_understanding.then(|| {
+            UnderstandingSim::new(
+                args.understanding_delay,
+                args.understanding_template.clone(),
+                args.expand_tokens,
+                args.understanding_seed,
+            )
+        }),
+        single_context: args.single_context,
+        request_channel_capacity: args.request_channel_capacity,
+        request_overflow: args.request_overflow,
+        version_conflict: args.on_version_conflict.into(),
+        verify_key: args.verify_key,
+        stall_gap: args.stall_gap_ms.map(Duration::from_millis),
+        stall_flush: args.stall_flush,
+        locale: args.locale.clone(),
+        response_limits: ResponseLimits {
+            max_message_bytes: args.max_response_bytes,
+            max_ads_per_list: args.max_ads_per_list,
+            max_field_bytes: args.max_field_bytes,
+        },
+        ack_contexts: args.ack_contexts,
+        report_feedback: args.report_feedback,
+    };
 
     info!("Starting Rust ADS client");
-    info!("Server address: {}", server_addr);
+    info!("Server address: {}", endpoints.join(", "));
     info!("Query: {}", query);
     info!("ASIN ID: {}", asin_id);
 
-    // Create client and connect
-    let mut client = AdsClient::new(&server_addr).await?;
+    if let Some(method_name) = &args.dynamic {
+        let channel = connect(&server_addr, &config).await?;
+        let pool = dynamic::fetch_descriptor_pool(channel.clone(), dynamic::DEFAULT_SERVICE).await?;
+        let method = dynamic::find_method(&pool, method_name)?;
+        let request_json = args.request_json.clone().unwrap_or_else(|| {
+            serde_json::json!({
+                "query": query,
+                "asin_id": asin_id,
+                "understanding": args.understanding,
+            })
+            .to_string()
+        });
+        let requests = dynamic::parse_requests(&method, &request_json)?;
+        for response in dynamic::call(channel, &method, requests).await? {
+            println!("{}", response);
+        }
+        return Ok(());
+    }
+
+    if let Some(channels) = args.multiplex {
+        let mut client = MultiplexedAdsClient::new(&server_addr, &config).await?;
+        let sessions = (0..channels)
+            .map(|_| LogicalSession {
+                query: query.clone(),
+                asin_id: asin_id.clone(),
+                understanding: args.understanding.clone(),
+            })
+            .collect();
+        let results = client
+            .get_ads(sessions, Duration::from_millis(args.multiplex_timeout_ms))
+            .await?;
+        let mut channel_ids: Vec<&u32> = results.keys().collect();
+        channel_ids.sort();
+        for channel_id in channel_ids {
+            match &results[channel_id] {
+                Some(ads_list) => {
+                    if let Some(output) = args.format.render(ads_list, args.color) {
+                        println!("{}", output);
+                    }
+                }
+                None => warn!("Channel {}: no AdsList received within timeout", channel_id),
+            }
+        }
+        return Ok(());
+    }
+
+    if !args.asins.is_empty() {
+        let mode = if args.fanout_multiplexed { FanOutMode::Multiplexed } else { FanOutMode::Concurrent };
+        let client = FanOutClient::new(&server_addr, &config, mode, Duration::from_millis(args.fanout_deadline_ms)).await?;
+        let result = client.get_ads_for_asins(&query, &args.asins, &args.understanding).await;
+        // Failures are logged by the fan-out itself
+        for (asin, ads_list) in &result.ads {
+            info!(asin = %asin, version = ads_list.version, ads = ads_list.ads.len(), "Ads for ASIN");
+            if let Some(output) = args.format.render(ads_list, args.color) {
+                println!("{}", output);
+            }
+        }
+        return Ok(());
+    }
+
+    // Create a client per endpoint and connect
+    let mut pool = EndpointPool::connect(&endpoints, &config, args.balance, Duration::from_millis(args.latency_slo_ms)).await?;
+    if args.warm_up > 0 || args.warm_up_request {
+        for index in 0..pool.stats().len() {
+            pool.client(index).warm_up(args.warm_up, args.warm_up_request).await;
+        }
+    }
+
+    // Ctrl-C stops the run after the current session, so the shutdown report still
+    // covers it; a second Ctrl-C exits at once
+    let interrupted = Arc::new(AtomicBool::new(false));
+    {
+        let interrupted = interrupted.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                warn!("Interrupted - stopping after the current session (Ctrl-C again to exit now)");
+                interrupted.store(true, Ordering::SeqCst);
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    std::process::exit(130);
+                }
+            }
+        });
+    }
 
     // Get ads using bidirectional streaming
-    let understanding = "refined understanding based on query analysis".to_string();
-    match client.get_ads(query, asin_id, understanding).await {
-        Ok(Some(ads_list)) => {
-            info!("SUCCESS: Final result is AdsList version {} containing {} ads", 
-                  ads_list.version, ads_list.ads.len());
-            for (i, ad) in ads_list.ads.iter().enumerate() {
-                info!("  Ad {}: asin_id={}, ad_id={}, score={:.3}", 
-                      i + 1, ad.asin_id, ad.ad_id, ad.score);
+    let understanding = args.understanding;
+    let mut last_error = None;
+    let mut latencies_ms: Vec<f64> = Vec::new();
+    let mut errors = std::collections::BTreeMap::new();
+    let mut selector = AutoSelector::new(AutoConfig::default());
+    let class = auto::request_class(config.request_type, !config.batch_queries.is_empty());
+    for _ in 0..args.sessions {
+        if interrupted.load(Ordering::SeqCst) {
+            break;
+        }
+        if let Some(path) = &args.record {
+            let session = RecordedSession {
+                query: query.clone(),
+                asin_id: asin_id.clone(),
+                understanding: understanding.clone(),
+                seed: config.seed,
+                request_type: config.request_type.as_str_name().to_string(),
+            };
+            if let Err(e) = record_session(path, &session) {
+                warn!("Failed to record session to {}: {}", path.display(), e);
             }
         }
-        Ok(None) => {
-            warn!("FAILURE: No AdsList received within timeout - no final result available");
+        let endpoint = pool.pick();
+        let client = pool.client(endpoint);
+        let session_start = Instant::now();
+        let result = if args.auto {
+            let shape = selector.choose(&class);
+            let start = Instant::now();
+            let result = client
+                .get_ads_with_shape(shape, query.clone(), asin_id.clone(), understanding.clone())
+                .await;
+            selector.record(&class, shape, start.elapsed(), matches!(result, Ok(Some(_))));
+            result
+        } else {
+            client.get_ads_with_redirects(query.clone(), asin_id.clone(), understanding.clone()).await
+        };
+        pool.record(endpoint, session_start.elapsed(), &result);
+        latencies_ms.push(session_start.elapsed().as_secs_f64() * 1000.0);
+        match result {
+            Ok(Some(ads_list)) => {
+                info!("SUCCESS: Final result is AdsList version {} containing {} ads", 
+                      ads_list.version, ads_list.ads.len());
+                if let Some(output) = args.format.render(&ads_list, args.color) {
+                    println!("{}", output);
+                }
+            }
+            Ok(None) => {
+                warn!("FAILURE: No AdsList received within timeout - no final result available");
+                *errors.entry("no_result".to_string()).or_insert(0) += 1;
+            }
+            Err(e) => {
+                error!("ERROR: Failed to get ads: {}", e);
+                *errors.entry(e.kind().to_string()).or_insert(0) += 1;
+                last_error = Some(e);
+            }
         }
-        Err(e) => {
-            error!("ERROR: Failed to get ads: {}", e);
-            return Err(e);
+    }
+    let mut outcomes = OutcomeFunnel::default();
+    for index in 0..pool.stats().len() {
+        outcomes.merge(&pool.client(index).outcomes());
+    }
+    info!(sessions = outcomes.total(), outcomes = %outcomes.render(), "Session outcomes");
+    if args.zstd_dictionary.is_some() {
+        let wire = codec::wire_stats();
+        info!(
+            messages = wire.messages,
+            raw_bytes = wire.raw_bytes,
+            wire_bytes = wire.wire_bytes,
+            ratio = %format!("{:.3}", wire.ratio()),
+            "zstd dictionary wire size"
+        );
+    }
+    for index in 0..pool.stats().len() {
+        let endpoint = pool.stats()[index].endpoint.clone();
+        let ordering = pool.client(index).ordering_stats();
+        if ordering.duplicates + ordering.out_of_order > 0 {
+            warn!(
+                endpoint = %endpoint,
+                received = ordering.received,
+                duplicates = ordering.duplicates,
+                stale = ordering.out_of_order,
+                discarded = ordering.discarded,
+                replaced = ordering.replaced,
+                conflict_errors = ordering.conflict_errors,
+                "AdsList version conflicts"
+            );
         }
+        let signatures = pool.client(index).signature_stats();
+        if signatures.unsigned + signatures.invalid > 0 {
+            warn!(
+                endpoint = %endpoint,
+                verified = signatures.verified,
+                unsigned = signatures.unsigned,
+                invalid = signatures.invalid,
+                "AdsLists failed signature verification"
+            );
+        } else if signatures.verified > 0 {
+            info!(endpoint = %endpoint, verified = signatures.verified, "All AdsList signatures verified");
+        }
+        let acks = pool.client(index).ack_stats();
+        if acks.dropped > 0 {
+            warn!(
+                endpoint = %endpoint,
+                acknowledged = acks.acknowledged,
+                dropped = acks.dropped,
+                unconfirmed = acks.unconfirmed,
+                "Contexts dropped before reaching the server"
+            );
+        } else if acks.acknowledged > 0 {
+            info!(
+                endpoint = %endpoint,
+                acknowledged = acks.acknowledged,
+                unconfirmed = acks.unconfirmed,
+                "Context acknowledgements"
+            );
+        }
+        let breaker = pool.client(index).breaker_stats();
+        if breaker.opened + breaker.rejected() > 0 {
+            warn!(
+                endpoint = %endpoint,
+                opened = breaker.opened,
+                half_opened = breaker.half_opened,
+                closed = breaker.closed,
+                rejected_open = breaker.rejected_open,
+                rejected_half_open = breaker.rejected_half_open,
+                "Circuit breaker tripped"
+            );
+        }
+    }
+    if args.endpoint_scores {
+        println!("{}", pool.render_scores());
+    }
+    let exit = if interrupted.load(Ordering::SeqCst) {
+        "signal"
+    } else if last_error.is_some() {
+        "error"
+    } else {
+        "completed"
+    };
+    let mut shutdown_report = ShutdownReport::new("ads-client", exit, started.elapsed());
+    shutdown_report.sessions = latencies_ms.len() as u64;
+    shutdown_report.errors = errors;
+    shutdown_report.outcomes = outcomes;
+    latencies_ms.sort_by(f64::total_cmp);
+    shutdown_report.latency_ms = report::quantiles(&latencies_ms);
+    info!(report = %shutdown_report.to_json(), "Shutdown report");
+    if let Some(path) = &args.shutdown_report {
+        match shutdown_report.write(path) {
+            Ok(()) => info!(path = %path.display(), "Wrote shutdown report"),
+            Err(e) => warn!(path = %path.display(), error = %e, "Could not write shutdown report"),
+        }
+    }
+    if let Some(e) = last_error {
+        return Err(e.into());
     }
 
     info!("Client completed successfully");
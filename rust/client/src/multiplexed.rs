@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::time::{sleep, timeout};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{transport::Channel, Request, Status};
+use tracing::{debug, info, warn};
+
+use crate::ads::{ads_service_client::AdsServiceClient, AdsList, RequestType};
+use crate::config::ClientConfig;
+use crate::context::{ContextBuilder, DEFAULT_UNDERSTANDING_DELAY};
+use crate::error::AdsClientError;
+use crate::ordering::{OrderTracker, Resolution, VersionConflictPolicy};
+use crate::signing::{ResponseVerifier, SignatureStats};
+
+/// One logical ads session hosted on a multiplexed stream
+#[derive(Debug, Clone)]
+pub struct LogicalSession {
+    pub query: String,
+    pub asin_id: String,
+    pub understanding: String,
+}
+
+/// Client that hosts several logical ads sessions on a single bidirectional gRPC
+/// stream, tagging every Context with a channel_id and demultiplexing the
+/// AdsLists by the channel_id the server echoes back. Because all sessions share
+/// one HTTP/2 stream, a slow response for one channel delays the others, which
+/// makes head-of-line blocking observable in the per-channel arrival logs.
+pub struct MultiplexedAdsClient {
+    client: AdsServiceClient<Channel>,
+    seed: Option<u64>,
+    request_type: RequestType,
+    version_conflict: VersionConflictPolicy,
+    verifier: Option<ResponseVerifier>,
+}
+
+impl MultiplexedAdsClient {
+    /// Create a new MultiplexedAdsClient and connect to the server
+    pub async fn new(server_addr: &str, config: &ClientConfig) -> Result<Self, AdsClientError> {
+        let channel = crate::connect(server_addr, config).await?;
+        Ok(Self::from_channel(channel, config))
+    }
+
+    /// Build a client on an already connected channel
+    pub fn from_channel(channel: Channel, config: &ClientConfig) -> Self {
+        MultiplexedAdsClient {
+            client: AdsServiceClient::new(channel),
+            seed: config.seed,
+            request_type: config.request_type,
+            version_conflict: config.version_conflict,
+            verifier: config.verify_key,
+        }
+    }
+
+    /// Run all sessions over one stream and return the latest AdsList per channel.
+    /// Channel ids are assigned 1..=n in the order the sessions are given.
+    pub async fn get_ads(
+        &mut self,
+        sessions: Vec<LogicalSession>,
+        timeout_duration: Duration,
+    ) -> Result<HashMap<u32, Option<AdsList>>, AdsClientError> {
+        let overall_start = Instant::now();
+        info!(
+            channels = sessions.len(),
+            timeout_ms = timeout_duration.as_millis() as u64,
+            "Starting multiplexed bidirectional stream"
+        );
+
+        // Every Context is built (and validated) up front, so an invalid session fails
+        // the call before the shared stream is opened for any of them
+        let contexts = sessions
+            .iter()
+            .enumerate()
+            .map(|(i, session)| {
+                let builder = ContextBuilder::new(session.query.clone(), session.asin_id.clone())
+                    .understanding(session.understanding.clone())
+                    .seed(self.seed)
+                    .request_type(self.request_type)
+                    .channel_id(i as u32 + 1);
+                Ok((builder.build_initial()?, builder.build_refined()?))
+            })
+            .collect::<Result<Vec<_>, AdsClientError>>()?;
+
+        let (tx, rx) = tokio::sync::mpsc::channel(sessions.len() * 2 + 1);
+        let mut response_stream = self
+            .client
+            .get_ads(Request::new(ReceiverStream::new(rx)))
+            .await?
+            .into_inner();
+
+        // First Context for every channel, then the refined Context for every channel
+        for (phase, understanding_phase) in [(1, false), (2, true)] {
+            if understanding_phase {
+                sleep(DEFAULT_UNDERSTANDING_DELAY).await;
+            }
+            for (i, (initial, refined)) in contexts.iter().enumerate() {
+                let channel_id = i as u32 + 1;
+                let context = if understanding_phase { refined.clone() } else { initial.clone() };
+                debug!(
+                    channel_id = channel_id,
+                    context_number = phase,
+                    elapsed_ms = overall_start.elapsed().as_millis() as u64,
+                    "Sending Context message"
+                );
+                tx.send(context).await.map_err(|e| {
+                    AdsClientError::Send(format!("Failed to send context on channel {}: {}", channel_id, e))
+                })?;
+            }
+        }
+        drop(tx);
+        info!(
+            elapsed_ms = overall_start.elapsed().as_millis() as u64,
+            "Half-closed multiplexed stream"
+        );
+
+        let mut latest: HashMap<u32, AdsList> = HashMap::new();
+        let mut order_tracker = OrderTracker::default();
+        let version_conflict = self.version_conflict;
+        let mut conflict = None;
+        let verifier = self.verifier;
+        let mut signatures = SignatureStats::default();
+        let receive_task = async {
+            while let Some(response) = response_stream.message().await? {
+                if let Some(verifier) = &verifier {
+                    verifier.check(&response, &mut signatures);
+                }
+                let channel_id = response.channel_id;
+                info!(
+                    channel_id = channel_id,
+                    version = response.version,
+                    ads_count = response.ads.len(),
+                    elapsed_ms = overall_start.elapsed().as_millis() as u64,
+                    "Received AdsList"
+                );
+                let version = response.version;
+                match order_tracker.admit(channel_id, version, version_conflict) {
+                    Resolution::Keep => {
+                        // A stale list kept under keep-last does not displace a newer one
+                        let newer = latest.get(&channel_id).is_none_or(|current| version >= current.version);
+                        if newer {
+                            latest.insert(channel_id, response);
+                        }
+                    }
+                    Resolution::Discard => {}
+                    Resolution::Fail(arrival) => {
+                        conflict = Some(AdsClientError::VersionConflict { version, arrival });
+                        break;
+                    }
+                }
+            }
+            Ok::<(), Status>(())
+        };
+
+        match timeout(timeout_duration, receive_task).await {
+            Ok(Ok(())) => info!(
+                elapsed_ms = overall_start.elapsed().as_millis() as u64,
+                "Multiplexed stream completed normally before timeout"
+            ),
+            Ok(Err(e)) => warn!(error = %e, "Multiplexed stream error occurred"),
+            Err(_) => info!(
+                timeout_ms = timeout_duration.as_millis() as u64,
+                channels_with_results = latest.len(),
+                "Client timeout reached - proceeding with available results"
+            ),
+        }
+
+        if order_tracker.stats.out_of_order + order_tracker.stats.duplicates > 0 {
+            warn!(
+                received = order_tracker.stats.received,
+                out_of_order = order_tracker.stats.out_of_order,
+                duplicates = order_tracker.stats.duplicates,
+                discarded = order_tracker.stats.discarded,
+                policy = version_conflict.name(),
+                "Multiplexed stream delivered AdsLists out of version order"
+            );
+        }
+        if signatures.unsigned + signatures.invalid > 0 {
+            warn!(
+                verified = signatures.verified,
+                unsigned = signatures.unsigned,
+                invalid = signatures.invalid,
+                "Multiplexed stream delivered AdsLists failing signature verification"
+            );
+        }
+        if let Some(e) = conflict {
+            return Err(e);
+        }
+
+        let results: HashMap<u32, Option<AdsList>> = (1..=sessions.len() as u32)
+            .map(|channel_id| (channel_id, latest.remove(&channel_id)))
+            .collect();
+        for (channel_id, result) in &results {
+            info!(
+                channel_id = channel_id,
+                selected_version = result.as_ref().map(|ads| ads.version),
+                "FINAL RESULT: Selected AdsList for channel"
+            );
+        }
+        Ok(results)
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::{HashMap, HashSet};
+use tracing::warn;
+
+/// What the client does with an AdsList whose version already arrived on its channel
+/// (a duplicate) or that is lower than one already received (stale). Either means
+/// the server broke the protocol, so the choice is how loudly to find out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VersionConflictPolicy {
+    /// Keep what arrived first and discard the conflicting list
+    KeepFirst,
+    /// Take the conflicting list, replacing a duplicate version
+    #[default]
+    KeepLast,
+    /// Fail the session with `AdsClientError::VersionConflict`
+    Error,
+}
+
+impl VersionConflictPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            VersionConflictPolicy::KeepFirst => "keep_first",
+            VersionConflictPolicy::KeepLast => "keep_last",
+            VersionConflictPolicy::Error => "error",
+        }
+    }
+}
+
+/// How an AdsList relates to those already received on its channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arrival {
+    /// Higher than every version seen so far
+    InOrder,
+    /// The same version arrived before
+    Duplicate,
+    /// Lower than `highest_seen`, and not received before
+    Stale { highest_seen: u32 },
+}
+
+/// What to do with an arriving AdsList under a `VersionConflictPolicy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Keep,
+    Discard,
+    Fail(Arrival),
+}
+
+/// Running counts of AdsLists that arrived out of version order
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OrderingStats {
+    pub received: u64,
+    /// A lower version arrived after a higher one on the same channel
+    pub out_of_order: u64,
+    /// The same version arrived twice on the same channel
+    pub duplicates: u64,
+    /// Conflicting AdsLists dropped under keep-first
+    pub discarded: u64,
+    /// Duplicate versions that replaced the earlier copy under keep-last
+    pub replaced: u64,
+    /// Sessions failed by a conflict under the error policy
+    pub conflict_errors: u64,
+}
+
+impl OrderingStats {
+    pub fn merge(&mut self, other: &OrderingStats) {
+        self.received += other.received;
+        self.out_of_order += other.out_of_order;
+        self.duplicates += other.duplicates;
+        self.discarded += other.discarded;
+        self.replaced += other.replaced;
+        self.conflict_errors += other.conflict_errors;
+    }
+}
+
+/// Tracks the versions seen per channel on one response stream
+#[derive(Debug, Default)]
+pub struct OrderTracker {
+    highest: HashMap<u32, u32>,
+    seen: HashMap<u32, HashSet<u32>>,
+    pub stats: OrderingStats,
+}
+
+impl OrderTracker {
+    /// Record an arrival and classify it against the earlier ones of its channel
+    pub fn observe(&mut self, channel_id: u32, version: u32) -> Arrival {
+        self.stats.received += 1;
+        let first_arrival = self.seen.entry(channel_id).or_default().insert(version);
+        let highest = self.highest.entry(channel_id).or_insert(0);
+        let arrival = if !first_arrival {
+            self.stats.duplicates += 1;
+            Arrival::Duplicate
+        } else if version > *highest {
+            *highest = version;
+            return Arrival::InOrder;
+        } else {
+            self.stats.out_of_order += 1;
+            Arrival::Stale { highest_seen: *highest }
+        };
+        warn!(
+            channel_id = channel_id,
+            version = version,
+            highest_seen = *highest,
+            out_of_order_total = self.stats.out_of_order,
+            duplicates_total = self.stats.duplicates,
+            "AdsList arrived out of version order"
+        );
+        arrival
+    }
+
+    /// `observe` the arrival and decide whether to keep it under `policy`
+    pub fn admit(&mut self, channel_id: u32, version: u32, policy: VersionConflictPolicy) -> Resolution {
+        let arrival = self.observe(channel_id, version);
+        match (arrival, policy) {
+            (Arrival::InOrder, _) => Resolution::Keep,
+            (_, VersionConflictPolicy::KeepFirst) => {
+                self.stats.discarded += 1;
+                Resolution::Discard
+            }
+            (Arrival::Duplicate, VersionConflictPolicy::KeepLast) => {
+                self.stats.replaced += 1;
+                Resolution::Keep
+            }
+            (_, VersionConflictPolicy::KeepLast) => Resolution::Keep,
+            (_, VersionConflictPolicy::Error) => {
+                self.stats.conflict_errors += 1;
+                Resolution::Fail(arrival)
+            }
+        }
+    }
+}
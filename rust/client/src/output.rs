@@ -0,0 +1,321 @@
+//! Rendering of the final AdsList for `--format`: the built-in presets (`table`,
+//! `json`, `csv`, `quiet`) or a template such as `"{ad_id}\t{score:.2}\t{rank_reason}"`
+//! applied to every ad, so other scripts can consume the client's stdout directly.
+//!
+//! Template fields: `rank`, `ad_id`, `asin_id`, `score`, `advertiser_id`, `category`,
+//! `version`, `channel_id`, `query` (the partition's query for batched lists),
+//! `placement` (the placement slug for lists filled per placement), `was`
+//! (rank before a client re-rank, empty if none), `rank_reason` (`server`,
+//! `promoted`, `demoted`, `unchanged` or `added`) and the ad's treatment tags
+//! `experiment_arm` and `pipeline_version`. A spec after `:` takes an optional
+//! `<`/`>` alignment, a width and a `.precision` for scores. `{{`, `}}`, `\t` and `\n`
+//! are literal braces, tab and newline.
+
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+use ads_proto::fmt::PrettyPrint;
+
+use crate::ads::{Ad, AdsList};
+
+const FIELDS: [&str; 14] = [
+    "rank", "ad_id", "asin_id", "score", "advertiser_id", "category", "version", "channel_id", "query", "placement",
+    "was", "rank_reason", "experiment_arm", "pipeline_version",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputFormat {
+    /// Aligned table (the default)
+    Table,
+    /// The whole AdsList as one JSON object
+    Json,
+    /// Header plus one row per ad
+    Csv,
+    /// Print nothing; the exit status and logs still report the outcome
+    Quiet,
+    /// One rendered template line per ad
+    Template(Template),
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "quiet" => Ok(OutputFormat::Quiet),
+            template => Template::parse(template).map(OutputFormat::Template),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// The rendered list, or None when nothing should be printed
+    pub fn render(&self, list: &AdsList, color: bool) -> Option<String> {
+        match self {
+            OutputFormat::Table => Some(list.pretty(color).to_string()),
+            OutputFormat::Json => Some(render_json(list)),
+            OutputFormat::Csv => Some(render_csv(list)),
+            OutputFormat::Quiet => None,
+            OutputFormat::Template(template) => Some(template.render(list)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Field {
+        name: String,
+        align: Option<char>,
+        width: Option<usize>,
+        precision: Option<usize>,
+    },
+}
+
+/// A parsed `--format` template
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '\\' if matches!(chars.peek(), Some(&('t' | 'n' | '\\'))) => {
+                    literal.push(match chars.next() {
+                        Some('t') => '\t',
+                        Some('n') => '\n',
+                        _ => '\\',
+                    });
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(format!("unclosed placeholder {{{}", placeholder)),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(parse_field(&placeholder)?);
+                }
+                '}' => return Err("unmatched '}' (use '}}' for a literal brace)".to_string()),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        if !segments.iter().any(|segment| matches!(segment, Segment::Field { .. })) {
+            return Err(format!(
+                "{:?} is neither a preset (table, json, csv, quiet) nor a template with {{field}} placeholders",
+                template
+            ));
+        }
+        Ok(Template { segments })
+    }
+
+    /// One line per ad, across all partitions of a batched list
+    pub fn render(&self, list: &AdsList) -> String {
+        let mut lines = Vec::new();
+        for_each_row(list, |row| {
+            let mut line = String::new();
+            for segment in &self.segments {
+                match segment {
+                    Segment::Literal(text) => line.push_str(text),
+                    Segment::Field { name, align, width, precision } => {
+                        let value = match (name.as_str(), precision) {
+                            ("score", Some(precision)) => format!("{:.*}", *precision, row.ad.score),
+                            (name, _) => row.field(name),
+                        };
+                        let width = width.unwrap_or(0);
+                        let _ = match align {
+                            Some('>') => write!(line, "{:>width$}", value),
+                            _ => write!(line, "{:<width$}", value),
+                        };
+                    }
+                }
+            }
+            lines.push(line);
+        });
+        lines.join("\n")
+    }
+}
+
+fn parse_field(placeholder: &str) -> Result<Segment, String> {
+    let (name, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+    if !FIELDS.contains(&name) {
+        return Err(format!("unknown field {{{}}}; expected one of {}", name, FIELDS.join(", ")));
+    }
+    let invalid = || format!("invalid format spec {:?} for {{{}}}", spec, name);
+    let mut spec = spec;
+    let align = spec.chars().next().filter(|c| *c == '<' || *c == '>');
+    if align.is_some() {
+        spec = &spec[1..];
+    }
+    let (width, precision) = spec.split_once('.').unwrap_or((spec, ""));
+    let width = (!width.is_empty()).then(|| width.parse().map_err(|_| invalid())).transpose()?;
+    let precision = (!precision.is_empty()).then(|| precision.parse().map_err(|_| invalid())).transpose()?;
+    if spec.ends_with('.') || (precision.is_some() && name != "score") {
+        return Err(invalid());
+    }
+    Ok(Segment::Field { name: name.to_string(), align, width, precision })
+}
+
+/// One ad in its list position, with what a row can show about it
+struct Row<'a> {
+    list: &'a AdsList,
+    query: &'a str,
+    placement: &'static str,
+    rank: usize,
+    ad: &'a Ad,
+    /// 1-based rank in the server ranking, when the list was re-ranked on the client
+    was: Option<Option<usize>>,
+}
+
+impl Row<'_> {
+    fn field(&self, name: &str) -> String {
+        match name {
+            "rank" => self.rank.to_string(),
+            "ad_id" => self.ad.ad_id.clone(),
+            "asin_id" => self.ad.asin_id.clone(),
+            "score" => format!("{:.3}", self.ad.score),
+            "advertiser_id" => self.ad.advertiser_id.clone(),
+            "category" => self.ad.category.clone(),
+            "version" => self.list.version.to_string(),
+            "channel_id" => self.list.channel_id.to_string(),
+            "query" => self.query.to_string(),
+            "placement" => self.placement.to_string(),
+            "was" => self.was.flatten().map(|was| was.to_string()).unwrap_or_default(),
+            "rank_reason" => self.rank_reason().to_string(),
+            "experiment_arm" => self.ad.experiment_arm.clone(),
+            "pipeline_version" => self.ad.pipeline_version.clone(),
+            _ => String::new(),
+        }
+    }
+
+    fn rank_reason(&self) -> &'static str {
+        match self.was {
+            None => "server",
+            Some(None) => "added",
+            Some(Some(was)) if was > self.rank => "promoted",
+            Some(Some(was)) if was < self.rank => "demoted",
+            Some(Some(_)) => "unchanged",
+        }
+    }
+}
+
+fn for_each_row<'a>(list: &'a AdsList, mut visit: impl FnMut(Row<'a>)) {
+    let mut visit_ads = |query: &'a str, placement: &'static str, ads: &'a [Ad], original: &'a [Ad]| {
+        for (i, ad) in ads.iter().enumerate() {
+            let was = (!original.is_empty())
+                .then(|| original.iter().position(|o| o.ad_id == ad.ad_id).map(|was| was + 1));
+            visit(Row { list, query, placement, rank: i + 1, ad, was });
+        }
+    };
+    if list.query_results.is_empty() && list.placement_results.is_empty() {
+        visit_ads("", "", &list.ads, &list.original_ads);
+    }
+    for partition in &list.query_results {
+        visit_ads(&partition.query, "", &partition.ads, &partition.original_ads);
+    }
+    for placement in &list.placement_results {
+        visit_ads("", placement.placement().slug(), &placement.ads, &placement.original_ads);
+    }
+}
+
+fn ad_json(ad: &Ad) -> serde_json::Value {
+    serde_json::json!({
+        "ad_id": ad.ad_id,
+        "asin_id": ad.asin_id,
+        "score": ad.score,
+        "advertiser_id": ad.advertiser_id,
+        "category": ad.category,
+        "experiment_arm": ad.experiment_arm,
+        "pipeline_version": ad.pipeline_version,
+    })
+}
+
+fn render_json(list: &AdsList) -> String {
+    let ads = |ads: &[Ad]| ads.iter().map(ad_json).collect::<Vec<_>>();
+    let mut json = serde_json::json!({
+        "version": list.version,
+        "channel_id": list.channel_id,
+        "normalization": list.normalization().as_str_name(),
+    });
+    if !list.placement_results.is_empty() {
+        json["placement_results"] = list
+            .placement_results
+            .iter()
+            .map(|placement| {
+                let mut json = serde_json::json!({ "placement": placement.placement().slug(), "ads": ads(&placement.ads) });
+                if !placement.original_ads.is_empty() {
+                    json["original_ads"] = ads(&placement.original_ads).into();
+                }
+                json
+            })
+            .collect::<Vec<_>>()
+            .into();
+    } else if list.query_results.is_empty() {
+        json["ads"] = ads(&list.ads).into();
+        if !list.original_ads.is_empty() {
+            json["original_ads"] = ads(&list.original_ads).into();
+        }
+    } else {
+        json["query_results"] = list
+            .query_results
+            .iter()
+            .map(|partition| {
+                let mut json = serde_json::json!({ "query": partition.query, "ads": ads(&partition.ads) });
+                if !partition.original_ads.is_empty() {
+                    json["original_ads"] = ads(&partition.original_ads).into();
+                }
+                json
+            })
+            .collect::<Vec<_>>()
+            .into();
+    }
+    json.to_string()
+}
+
+fn csv_field(value: String) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn render_csv(list: &AdsList) -> String {
+    let columns = ["version", "query", "placement", "rank", "ad_id", "asin_id", "score", "advertiser_id", "category", "was"];
+    let mut lines = vec![columns.join(",")];
+    for_each_row(list, |row| {
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|column| match *column {
+                "score" => row.ad.score.to_string(),
+                column => csv_field(row.field(column)),
+            })
+            .collect();
+        lines.push(fields.join(","));
+    });
+    lines.join("\n")
+}
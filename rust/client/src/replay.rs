@@ -0,0 +1,250 @@
+//! Record sessions and replay them against two servers for regression analysis.
+//!
+//! Recordings are JSON lines, one `RecordedSession` per line, written by
+//! `ads-client --record`. `replay` runs every recorded session against one
+//! endpoint; `ComparisonReport::compare` lines up two such runs (two server
+//! builds, or the same build started with two generator configs).
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::ads::RequestType;
+use crate::client::AdsClient;
+use crate::config::ClientConfig;
+use crate::error::AdsClientError;
+
+/// One captured session: everything needed to send the same Contexts again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedSession {
+    pub query: String,
+    pub asin_id: String,
+    pub understanding: String,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Proto enum name, e.g. "REQUEST_TYPE_KEYWORD"
+    #[serde(default = "default_request_type")]
+    pub request_type: String,
+}
+
+fn default_request_type() -> String {
+    RequestType::Keyword.as_str_name().to_string()
+}
+
+impl RecordedSession {
+    pub fn request_type(&self) -> RequestType {
+        RequestType::from_str_name(&self.request_type).unwrap_or(RequestType::Keyword)
+    }
+}
+
+/// Append a session to a recording file
+pub fn record_session(path: &Path, session: &RecordedSession) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let line = serde_json::to_string(session).map_err(io::Error::other)?;
+    writeln!(file, "{}", line)
+}
+
+/// Read a recording file, skipping blank lines
+pub fn read_sessions(path: &Path) -> io::Result<Vec<RecordedSession>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut sessions = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let session = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, e))
+        })?;
+        sessions.push(session);
+    }
+    Ok(sessions)
+}
+
+/// Result of replaying one session against one endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionOutcome {
+    /// Version of the selected AdsList, None if nothing arrived in time or the session failed
+    pub version: Option<u32>,
+    /// Selected ad_ids in ranked order
+    pub ad_ids: Vec<String>,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+/// Replay every session sequentially on one connection to `endpoint`
+pub async fn replay(
+    endpoint: &str,
+    sessions: &[RecordedSession],
+    config: &ClientConfig,
+) -> Result<Vec<SessionOutcome>, AdsClientError> {
+    let mut client = AdsClient::new(endpoint, config).await?;
+    let mut outcomes = Vec::with_capacity(sessions.len());
+    for (i, session) in sessions.iter().enumerate() {
+        client.configure_session(session.seed, session.request_type());
+        let start = Instant::now();
+        let result = client
+            .get_ads(session.query.clone(), session.asin_id.clone(), session.understanding.clone())
+            .await;
+        let latency_ms = start.elapsed().as_millis() as u64;
+        let outcome = match result {
+            Ok(ads_list) => SessionOutcome {
+                version: ads_list.as_ref().map(|list| list.version),
+                ad_ids: ads_list
+                    .map(|list| list.ads.into_iter().map(|ad| ad.ad_id).collect())
+                    .unwrap_or_default(),
+                latency_ms,
+                error: None,
+            },
+            Err(e) => {
+                warn!(endpoint = endpoint, session_index = i, error = %e, "Replayed session failed");
+                SessionOutcome { version: None, ad_ids: Vec::new(), latency_ms, error: Some(e.to_string()) }
+            }
+        };
+        outcomes.push(outcome);
+    }
+    info!(endpoint = endpoint, sessions = outcomes.len(), "Replay complete");
+    Ok(outcomes)
+}
+
+/// Summary of one side of a comparison
+#[derive(Debug, Default, Serialize)]
+pub struct RunSummary {
+    pub endpoint: String,
+    pub errors: usize,
+    pub mean_latency_ms: f64,
+    pub p50_latency_ms: u64,
+    pub p95_latency_ms: u64,
+    /// Fraction of sessions whose final AdsList was each version ("none" = no result)
+    pub version_win_rate: BTreeMap<String, f64>,
+}
+
+impl RunSummary {
+    fn from_outcomes(endpoint: &str, outcomes: &[SessionOutcome]) -> Self {
+        let mut latencies: Vec<u64> = outcomes.iter().map(|o| o.latency_ms).collect();
+        latencies.sort_unstable();
+        let percentile = |q: f64| -> u64 {
+            if latencies.is_empty() {
+                return 0;
+            }
+            let idx = ((latencies.len() - 1) as f64 * q).round() as usize;
+            latencies[idx]
+        };
+        let total = outcomes.len().max(1) as f64;
+        let mut version_win_rate = BTreeMap::new();
+        for outcome in outcomes {
+            let key = outcome.version.map_or("none".to_string(), |v| format!("v{}", v));
+            *version_win_rate.entry(key).or_insert(0.0) += 1.0 / total;
+        }
+        RunSummary {
+            endpoint: endpoint.to_string(),
+            errors: outcomes.iter().filter(|o| o.error.is_some()).count(),
+            mean_latency_ms: latencies.iter().sum::<u64>() as f64 / total,
+            p50_latency_ms: percentile(0.5),
+            p95_latency_ms: percentile(0.95),
+            version_win_rate,
+        }
+    }
+}
+
+/// Ranking difference for one session that changed between the two runs
+#[derive(Debug, Serialize)]
+pub struct RankingDiff {
+    pub session_index: usize,
+    pub query: String,
+    pub asin_id: String,
+    pub baseline_version: Option<u32>,
+    pub candidate_version: Option<u32>,
+    pub baseline_ad_ids: Vec<String>,
+    pub candidate_ad_ids: Vec<String>,
+    /// Fraction of the baseline ads still present in the candidate ranking
+    pub overlap: f64,
+    pub latency_delta_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComparisonReport {
+    pub sessions: usize,
+    pub baseline: RunSummary,
+    pub candidate: RunSummary,
+    /// Sessions whose selected ranking differs (order or membership)
+    pub rankings_changed: usize,
+    /// Sessions whose top-ranked ad differs
+    pub top_ad_changed: usize,
+    pub mean_latency_delta_ms: f64,
+    pub diffs: Vec<RankingDiff>,
+}
+
+impl ComparisonReport {
+    pub fn compare(
+        sessions: &[RecordedSession],
+        baseline_endpoint: &str,
+        baseline: &[SessionOutcome],
+        candidate_endpoint: &str,
+        candidate: &[SessionOutcome],
+    ) -> Self {
+        let mut diffs = Vec::new();
+        let mut top_ad_changed = 0;
+        for (i, ((session, base), cand)) in sessions.iter().zip(baseline).zip(candidate).enumerate() {
+            if base.ad_ids.first() != cand.ad_ids.first() {
+                top_ad_changed += 1;
+            }
+            if base.ad_ids == cand.ad_ids && base.version == cand.version {
+                continue;
+            }
+            let kept = base.ad_ids.iter().filter(|id| cand.ad_ids.contains(id)).count();
+            diffs.push(RankingDiff {
+                session_index: i,
+                query: session.query.clone(),
+                asin_id: session.asin_id.clone(),
+                baseline_version: base.version,
+                candidate_version: cand.version,
+                baseline_ad_ids: base.ad_ids.clone(),
+                candidate_ad_ids: cand.ad_ids.clone(),
+                overlap: kept as f64 / base.ad_ids.len().max(1) as f64,
+                latency_delta_ms: cand.latency_ms as i64 - base.latency_ms as i64,
+            });
+        }
+        let baseline_summary = RunSummary::from_outcomes(baseline_endpoint, baseline);
+        let candidate_summary = RunSummary::from_outcomes(candidate_endpoint, candidate);
+        ComparisonReport {
+            sessions: sessions.len(),
+            rankings_changed: diffs.iter().filter(|d| d.baseline_ad_ids != d.candidate_ad_ids).count(),
+            top_ad_changed,
+            mean_latency_delta_ms: candidate_summary.mean_latency_ms - baseline_summary.mean_latency_ms,
+            baseline: baseline_summary,
+            candidate: candidate_summary,
+            diffs,
+        }
+    }
+
+    /// Plain-text report for the terminal
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("Replayed {} sessions\n", self.sessions));
+        for (label, run) in [("baseline", &self.baseline), ("candidate", &self.candidate)] {
+            out.push_str(&format!(
+                "{:<9} {}  errors={} latency mean={:.1}ms p50={}ms p95={}ms wins={:?}\n",
+                label, run.endpoint, run.errors, run.mean_latency_ms, run.p50_latency_ms,
+                run.p95_latency_ms, run.version_win_rate,
+            ));
+        }
+        out.push_str(&format!(
+            "rankings changed: {}  top ad changed: {}  mean latency delta: {:+.1}ms\n",
+            self.rankings_changed, self.top_ad_changed, self.mean_latency_delta_ms,
+        ));
+        for diff in &self.diffs {
+            out.push_str(&format!(
+                "  #{} query=\"{}\" asin_id={} v{:?}->v{:?} overlap={:.2} latency {:+}ms\n",
+                diff.session_index, diff.query, diff.asin_id, diff.baseline_version,
+                diff.candidate_version, diff.overlap, diff.latency_delta_ms,
+            ));
+        }
+        out
+    }
+}
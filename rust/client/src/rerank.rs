@@ -0,0 +1,62 @@
+//! Caller-supplied re-ranking of the selected AdsList, e.g. to boost ads matching
+//! context only the client knows about. The server's ranking is kept in
+//! `original_ads` so both orders can be compared.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use ads_proto::score::{sort_ads, TieBreak};
+
+use crate::ads::{Ad, AdsList};
+
+/// Re-ranks one list of ads in place (reorder, rescore or drop ads)
+pub type RerankFn = dyn Fn(&mut Vec<Ad>) + Send + Sync;
+
+#[derive(Clone)]
+pub struct RerankHook {
+    rerank: Arc<RerankFn>,
+}
+
+impl fmt::Debug for RerankHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RerankHook")
+    }
+}
+
+impl RerankHook {
+    pub fn new<F>(rerank: F) -> Self
+    where
+        F: Fn(&mut Vec<Ad>) + Send + Sync + 'static,
+    {
+        RerankHook { rerank: Arc::new(rerank) }
+    }
+
+    /// Multiply the score of ads from `advertiser_ids` by `factor` and re-sort by score
+    pub fn boost_advertisers(advertiser_ids: impl IntoIterator<Item = String>, factor: f64) -> Self {
+        let boosted: HashSet<String> = advertiser_ids.into_iter().collect();
+        RerankHook::new(move |ads| {
+            for ad in ads.iter_mut().filter(|ad| boosted.contains(&ad.advertiser_id)) {
+                ad.score *= factor;
+            }
+            sort_ads(ads, TieBreak::AdId, 0, |_| 0);
+        })
+    }
+
+    /// Re-rank the list (each partition of a batched list and each placement
+    /// separately), saving the incoming order in `original_ads`
+    pub fn apply(&self, ads_list: &mut AdsList) {
+        if ads_list.query_results.is_empty() && ads_list.placement_results.is_empty() {
+            ads_list.original_ads = ads_list.ads.clone();
+            (self.rerank)(&mut ads_list.ads);
+        }
+        for partition in &mut ads_list.query_results {
+            partition.original_ads = partition.ads.clone();
+            (self.rerank)(&mut partition.ads);
+        }
+        for placement in &mut ads_list.placement_results {
+            placement.original_ads = placement.ads.clone();
+            (self.rerank)(&mut placement.ads);
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Transparent channel-level retries configured by a gRPC service config: the
+//! `methodConfig[].retryPolicy` JSON of gRFC A6 (max attempts, exponential backoff
+//! with jitter, retryable status codes), per service or per method. They happen
+//! under the generated client, unseen by the session logic: a call is retried while
+//! the server answers with a Trailers-Only status (it failed before sending any
+//! response headers) or the transport fails to deliver it, and is committed once
+//! response headers arrive. Only methods sending a single request message are
+//! retried, since their body can be buffered and replayed; a policy covering the
+//! bidirectional `GetAds` does not apply to it. The application-level session
+//! retries of `get_ads_with_retry` are separate and sit above this layer. Both log
+//! every retry with a `layer` field (`channel` or `application`) so the two
+//! mechanisms can be compared.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Deserializer};
+use tokio::time::sleep;
+use tonic::body::BoxBody;
+use tonic::codegen::http::{self, HeaderMap};
+use tonic::codegen::{Body, Bytes, StdError};
+use tonic::transport::Channel;
+use tonic::{Code, Status};
+use tower::{Service, ServiceExt};
+use tracing::{info, warn};
+
+/// Attempts above this are treated as this many, as gRPC implementations do
+const MAX_ATTEMPTS_CAP: u32 = 5;
+
+/// Methods whose request is a stream; their calls are never buffered for retrying
+const STREAMING_REQUEST_METHODS: [(&str, &str); 1] = [("ads.AdsService", "GetAds")];
+
+/// gRPC service config; only the retry policies are used
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceConfig {
+    #[serde(default)]
+    pub method_config: Vec<MethodConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MethodConfig {
+    #[serde(default)]
+    pub name: Vec<MethodName>,
+    pub retry_policy: Option<RetryPolicy>,
+}
+
+/// A service (empty = every service) and one of its methods (empty = all of them)
+#[derive(Debug, Clone, Deserialize)]
+pub struct MethodName {
+    #[serde(default)]
+    pub service: String,
+    #[serde(default)]
+    pub method: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryPolicy {
+    /// Attempts including the original call (2..=5)
+    pub max_attempts: u32,
+    /// Upper bound of the first backoff; each retry waits a random time below the bound
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub initial_backoff: Duration,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub max_backoff: Duration,
+    pub backoff_multiplier: f64,
+    #[serde(deserialize_with = "deserialize_codes")]
+    pub retryable_status_codes: Vec<Code>,
+}
+
+// Durations are written as in the protobuf JSON mapping, e.g. "0.1s"
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    text.strip_suffix('s')
+        .and_then(|secs| secs.parse::<f64>().ok())
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid duration {:?}, expected e.g. \"0.1s\"", text)))
+}
+
+// Codes by name ("UNAVAILABLE") or number (14)
+fn deserialize_codes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Code>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum CodeRepr {
+        Name(String),
+        Number(i32),
+    }
+    Vec::<CodeRepr>::deserialize(deserializer)?
+        .into_iter()
+        .map(|code| match code {
+            CodeRepr::Name(name) => code_from_name(&name)
+                .ok_or_else(|| serde::de::Error::custom(format!("unknown status code {:?}", name))),
+            CodeRepr::Number(number) => Ok(Code::from_i32(number)),
+        })
+        .collect()
+}
+
+fn code_from_name(name: &str) -> Option<Code> {
+    let code = match name.to_ascii_uppercase().as_str() {
+        "OK" => Code::Ok,
+        "CANCELLED" => Code::Cancelled,
+        "UNKNOWN" => Code::Unknown,
+        "INVALID_ARGUMENT" => Code::InvalidArgument,
+        "DEADLINE_EXCEEDED" => Code::DeadlineExceeded,
+        "NOT_FOUND" => Code::NotFound,
+        "ALREADY_EXISTS" => Code::AlreadyExists,
+        "PERMISSION_DENIED" => Code::PermissionDenied,
+        "RESOURCE_EXHAUSTED" => Code::ResourceExhausted,
+        "FAILED_PRECONDITION" => Code::FailedPrecondition,
+        "ABORTED" => Code::Aborted,
+        "OUT_OF_RANGE" => Code::OutOfRange,
+        "UNIMPLEMENTED" => Code::Unimplemented,
+        "INTERNAL" => Code::Internal,
+        "UNAVAILABLE" => Code::Unavailable,
+        "DATA_LOSS" => Code::DataLoss,
+        "UNAUTHENTICATED" => Code::Unauthenticated,
+        _ => return None,
+    };
+    Some(code)
+}
+
+impl ServiceConfig {
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let config: ServiceConfig = serde_json::from_str(json).map_err(|e| format!("service config: {}", e))?;
+        for policy in config.method_config.iter().filter_map(|method| method.retry_policy.as_ref()) {
+            if policy.max_attempts < 2 {
+                return Err("retryPolicy.maxAttempts must be at least 2".to_string());
+            }
+            if policy.initial_backoff.is_zero() || policy.max_backoff.is_zero() {
+                return Err("retryPolicy backoffs must be greater than 0s".to_string());
+            }
+            if !(policy.backoff_multiplier > 0.0 && policy.backoff_multiplier.is_finite()) {
+                return Err("retryPolicy.backoffMultiplier must be greater than 0".to_string());
+            }
+            if policy.retryable_status_codes.is_empty() {
+                return Err("retryPolicy.retryableStatusCodes must not be empty".to_string());
+            }
+        }
+        Ok(config)
+    }
+
+    /// Policy for `service`/`method`: the method's own entry, else its service's,
+    /// else the default entry naming no service
+    pub fn retry_policy(&self, service: &str, method: &str) -> Option<&RetryPolicy> {
+        let find = |matches: &dyn Fn(&MethodName) -> bool| {
+            self.method_config
+                .iter()
+                .find(|config| config.name.iter().any(matches))
+                .and_then(|config| config.retry_policy.as_ref())
+        };
+        find(&|name| name.service == service && name.method == method)
+            .or_else(|| find(&|name| name.service == service && name.method.is_empty()))
+            .or_else(|| find(&|name| name.service.is_empty()))
+    }
+}
+
+/// Inline JSON (starting with `{`) or the path of a JSON file
+impl FromStr for ServiceConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim_start().starts_with('{') {
+            return ServiceConfig::parse(s);
+        }
+        let json = std::fs::read_to_string(s).map_err(|e| format!("service config {}: {}", s, e))?;
+        ServiceConfig::parse(&json)
+    }
+}
+
+/// `Channel` retrying calls transparently per its service config (none = a plain channel)
+#[derive(Debug, Clone)]
+pub struct RetryChannel {
+    inner: Channel,
+    config: Option<Arc<ServiceConfig>>,
+}
+
+impl RetryChannel {
+    pub fn new(inner: Channel, config: Option<ServiceConfig>) -> Self {
+        if let Some(config) = &config {
+            for (service, method) in STREAMING_REQUEST_METHODS {
+                if config.retry_policy(service, method).is_some() {
+                    warn!(
+                        method = %format!("{}/{}", service, method),
+                        "Service config retry policy does not apply to a streaming-request method"
+                    );
+                }
+            }
+        }
+        RetryChannel { inner, config: config.map(Arc::new) }
+    }
+
+    /// Policy applying to a call of `path` ("/package.Service/Method")
+    fn policy_for(&self, path: &str) -> Option<RetryPolicy> {
+        let (service, method) = path.trim_start_matches('/').split_once('/')?;
+        if STREAMING_REQUEST_METHODS.contains(&(service, method)) {
+            return None;
+        }
+        self.config.as_ref()?.retry_policy(service, method).cloned()
+    }
+}
+
+impl Service<http::Request<BoxBody>> for RetryChannel {
+    type Response = http::Response<tonic::transport::Body>;
+    type Error = StdError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+        let Some(policy) = self.policy_for(request.uri().path()) else {
+            let response = self.inner.call(request);
+            return Box::pin(async move { response.await.map_err(StdError::from) });
+        };
+        // The channel made ready by poll_ready takes the first attempt
+        let clone = self.inner.clone();
+        let ready = std::mem::replace(&mut self.inner, clone);
+        Box::pin(call_with_retries(ready, request, policy))
+    }
+}
+
+async fn call_with_retries(
+    mut channel: Channel,
+    request: http::Request<BoxBody>,
+    policy: RetryPolicy,
+) -> Result<http::Response<tonic::transport::Body>, StdError> {
+    let (parts, body) = request.into_parts();
+    let body = buffer(body).await?;
+    let max_attempts = policy.max_attempts.min(MAX_ATTEMPTS_CAP);
+    let mut backoff = policy.initial_backoff;
+    let mut attempt = 1;
+    loop {
+        let mut request = http::Request::new(Replay(Some(body.clone())).boxed_unsync());
+        *request.method_mut() = parts.method.clone();
+        *request.uri_mut() = parts.uri.clone();
+        *request.version_mut() = parts.version;
+        *request.headers_mut() = parts.headers.clone();
+        let result = if attempt == 1 {
+            channel.call(request).await
+        } else {
+            ServiceExt::<http::Request<BoxBody>>::ready(&mut channel).await?.call(request).await
+        };
+        let code = match &result {
+            Ok(response) => trailers_only_code(response.headers()),
+            // tonic reports undeliverable calls as UNAVAILABLE
+            Err(_) => Some(Code::Unavailable),
+        };
+        let retry = code.filter(|code| attempt < max_attempts && policy.retryable_status_codes.contains(code));
+        let Some(code) = retry else {
+            return result.map_err(Into::into);
+        };
+        let delay = backoff.mul_f64(rand::thread_rng().gen_range(0.0..=1.0));
+        attempt += 1;
+        info!(
+            layer = "channel",
+            method = %parts.uri.path(),
+            attempt = attempt,
+            max_attempts = max_attempts,
+            code = ?code,
+            backoff_ms = delay.as_millis() as u64,
+            "Retrying call"
+        );
+        sleep(delay).await;
+        backoff = backoff.mul_f64(policy.backoff_multiplier).min(policy.max_backoff);
+    }
+}
+
+// A status in the response headers means the server answered Trailers-Only, before
+// committing to a response
+fn trailers_only_code(headers: &HeaderMap) -> Option<Code> {
+    let status = headers.get("grpc-status")?.to_str().ok()?.parse::<i32>().ok()?;
+    Some(Code::from_i32(status))
+}
+
+async fn buffer(mut body: BoxBody) -> Result<Bytes, Status> {
+    let mut buffered = Vec::new();
+    while let Some(chunk) = std::future::poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await {
+        buffered.extend_from_slice(&chunk?);
+    }
+    Ok(Bytes::from(buffered))
+}
+
+/// A buffered request body, replayed for every attempt
+struct Replay(Option<Bytes>);
+
+impl Body for Replay {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Status>>> {
+        Poll::Ready(self.get_mut().0.take().map(Ok))
+    }
+
+    fn poll_trailers(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<Option<HeaderMap>, Status>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_none()
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use tracing::info;
+
+use std::borrow::Cow;
+
+use ads_proto::score::{merge_ads, normalize_list, TieBreak};
+
+use crate::ads::{Ad, AdsList, PlacementAds, QueryAds, ScoreNormalization};
+
+/// How the final AdsList is chosen from the versions received before the timeout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    /// Keep only the highest version received (the protocol default)
+    #[default]
+    LatestVersion,
+    /// Union the ads of every received version, keeping the latest copy of each ad_id
+    MergeVersions,
+}
+
+/// Stop waiting for later versions once one is good enough, instead of always
+/// spending the whole selection timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EarlyExit {
+    pub min_version: u32,
+    /// Minimum ads in the list (in every partition for batched lists, and in every
+    /// placement up to its slot count)
+    pub min_ads: usize,
+}
+
+impl EarlyExit {
+    pub fn is_satisfied_by(&self, ads_list: &AdsList) -> bool {
+        let enough_ads = if !ads_list.placement_results.is_empty() {
+            ads_list.placement_results.iter().all(|p| p.ads.len() >= self.min_ads.min(p.placement().slots()))
+        } else if ads_list.query_results.is_empty() {
+            ads_list.ads.len() >= self.min_ads
+        } else {
+            ads_list.query_results.iter().all(|p| p.ads.len() >= self.min_ads)
+        };
+        ads_list.version >= self.min_version && enough_ads
+    }
+}
+
+/// Running counters used to judge whether merging actually changes outcomes
+#[derive(Debug, Default)]
+pub struct SelectionStats {
+    pub merged_sessions: u64,
+    pub merged_differs_from_latest: u64,
+}
+
+impl SelectionStats {
+    pub fn record_merge(&mut self, merged: &AdsList, latest: &AdsList) {
+        self.merged_sessions += 1;
+        let same_ads = |m: &[Ad], l: &[Ad]| m.len() == l.len() && m.iter().zip(l).all(|(m, l)| m.ad_id == l.ad_id);
+        let differs = !same_ads(&merged.ads, &latest.ads)
+            || merged.query_results.len() != latest.query_results.len()
+            || merged
+                .query_results
+                .iter()
+                .zip(&latest.query_results)
+                .any(|(m, l)| !same_ads(&m.ads, &l.ads))
+            || merged.placement_results.len() != latest.placement_results.len()
+            || merged
+                .placement_results
+                .iter()
+                .zip(&latest.placement_results)
+                .any(|(m, l)| !same_ads(&m.ads, &l.ads));
+        if differs {
+            self.merged_differs_from_latest += 1;
+        }
+        info!(
+            merged_differs = differs,
+            merged_ads = merged.ads.len(),
+            latest_ads = latest.ads.len(),
+            merged_sessions_total = self.merged_sessions,
+            merged_differs_total = self.merged_differs_from_latest,
+            "Merge selection stats"
+        );
+    }
+}
+
+/// Merge all buffered versions into one AdsList carrying the highest version number.
+/// When an ad_id appears in several versions its latest copy wins. Batched lists
+/// are merged partition by partition, in the query order of the latest version, and
+/// lists filled per placement placement by placement, each cut back to the number of
+/// slots the latest version filled.
+///
+/// Raw scores carry a per-version multiplier, so unless `renormalize` is `None`
+/// every version is first normalized with it to make the scores comparable. Equal
+/// scores are ordered by `tie_break`, with the later version counting as more recent
+/// (see `score::merge_ads`).
+pub fn merge_versions(
+    buffer: &HashMap<u32, AdsList>,
+    renormalize: ScoreNormalization,
+    tie_break: TieBreak,
+    seed: u64,
+) -> Option<AdsList> {
+    let version = buffer.keys().max().copied()?;
+    let buffer: HashMap<u32, Cow<'_, AdsList>> = buffer
+        .iter()
+        .map(|(&version, ads_list)| {
+            if renormalize == ScoreNormalization::None || ads_list.normalization() == renormalize {
+                (version, Cow::Borrowed(ads_list))
+            } else {
+                let mut ads_list = ads_list.clone();
+                normalize_list(&mut ads_list, renormalize);
+                (version, Cow::Owned(ads_list))
+            }
+        })
+        .collect();
+    let buffer = &buffer;
+    let mut versions: Vec<u32> = buffer.keys().copied().collect();
+    versions.sort_unstable();
+    let ads = merge_ads(
+        versions.iter().map(|v| (*v, buffer[v].ads.as_slice())),
+        tie_break,
+        seed,
+    );
+    let query_results = buffer[&version]
+        .query_results
+        .iter()
+        .map(|partition| QueryAds {
+            query: partition.query.clone(),
+            ads: merge_ads(
+                versions.iter().flat_map(move |v| {
+                    buffer[v]
+                        .query_results
+                        .iter()
+                        .filter(move |p| p.query == partition.query)
+                        .map(move |p| (*v, p.ads.as_slice()))
+                }),
+                tie_break,
+                seed,
+            ),
+            ..Default::default()
+        })
+        .collect();
+    let placement_results = buffer[&version]
+        .placement_results
+        .iter()
+        .map(|latest| {
+            let mut ads = merge_ads(
+                versions.iter().flat_map(move |v| {
+                    buffer[v]
+                        .placement_results
+                        .iter()
+                        .filter(move |p| p.placement == latest.placement)
+                        .map(move |p| (*v, p.ads.as_slice()))
+                }),
+                tie_break,
+                seed,
+            );
+            ads.truncate(latest.ads.len());
+            PlacementAds {
+                placement: latest.placement,
+                ads,
+                ..Default::default()
+            }
+        })
+        .collect();
+    let normalization = buffer[&version].normalization;
+    Some(AdsList {
+        ads,
+        version,
+        query_results,
+        placement_results,
+        normalization,
+        ..Default::default()
+    })
+}
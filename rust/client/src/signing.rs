@@ -0,0 +1,103 @@
+//! Verification of AdsList signatures from a server started with `--signing-key`.
+//! A list whose signature does not match was altered after the server sent it (by
+//! a proxy, or by the server's `tamper` fault action); the client flags it and
+//! counts it, but still uses it.
+
+use std::str::FromStr;
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use tracing::warn;
+
+use crate::ads::AdsList;
+
+/// How a received AdsList checked out against the configured public key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Valid,
+    /// The list carries no signature
+    Unsigned,
+    /// The signature does not match the list or the key
+    Invalid,
+}
+
+impl Verdict {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Verdict::Valid => "valid",
+            Verdict::Unsigned => "unsigned",
+            Verdict::Invalid => "invalid",
+        }
+    }
+}
+
+/// Running counts of signature verdicts
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureStats {
+    pub verified: u64,
+    pub unsigned: u64,
+    pub invalid: u64,
+}
+
+impl SignatureStats {
+    pub fn record(&mut self, verdict: Verdict) {
+        match verdict {
+            Verdict::Valid => self.verified += 1,
+            Verdict::Unsigned => self.unsigned += 1,
+            Verdict::Invalid => self.invalid += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &SignatureStats) {
+        self.verified += other.verified;
+        self.unsigned += other.unsigned;
+        self.invalid += other.invalid;
+    }
+}
+
+/// Server public key AdsLists are verified against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseVerifier {
+    key: VerifyingKey,
+}
+
+impl ResponseVerifier {
+    pub fn verify(&self, ads_list: &AdsList) -> Verdict {
+        if ads_list.signature.is_empty() {
+            return Verdict::Unsigned;
+        }
+        match Signature::from_slice(&ads_list.signature) {
+            Ok(signature) if self.key.verify(&ads_list.signed_bytes(), &signature).is_ok() => Verdict::Valid,
+            _ => Verdict::Invalid,
+        }
+    }
+
+    /// `verify` the list, count the verdict in `stats` and warn unless it is valid
+    pub fn check(&self, ads_list: &AdsList, stats: &mut SignatureStats) -> Verdict {
+        let verdict = self.verify(ads_list);
+        stats.record(verdict);
+        if verdict != Verdict::Valid {
+            warn!(
+                channel_id = ads_list.channel_id,
+                version = ads_list.version,
+                verdict = verdict.name(),
+                invalid_total = stats.invalid,
+                "AdsList failed signature verification - possible tampering"
+            );
+        }
+        verdict
+    }
+}
+
+/// A public key as 64 hex digits, as the server logs it at startup
+impl FromStr for ResponseVerifier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes: [u8; 32] = hex::decode(s.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| "expected an ed25519 public key as 64 hex digits".to_string())?;
+        let key = VerifyingKey::from_bytes(&bytes).map_err(|e| format!("invalid ed25519 public key: {}", e))?;
+        Ok(ResponseVerifier { key })
+    }
+}
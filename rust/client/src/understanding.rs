@@ -0,0 +1,172 @@
+//! Simulated query understanding for the two-phase send pattern. Instead of a fixed
+//! string after a fixed 50ms, each session draws how long "understanding" the query
+//! took from a delay distribution and synthesizes the understanding from a template:
+//! the query's tokens, an expansion of them with related terms, and a guessed intent
+//! and category. Seeded, so a run can be repeated exactly.
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// Longest simulated understanding delay, so a long-tailed distribution cannot stall a session
+pub const MAX_UNDERSTANDING_DELAY: Duration = Duration::from_secs(1);
+
+/// Template used when none is configured
+pub const DEFAULT_TEMPLATE: &str = "intent={intent}; category={category}; terms={expanded}";
+
+/// How long understanding a query takes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DelayDistribution {
+    /// Always the same delay
+    Fixed(Duration),
+    /// Uniform between two delays
+    Uniform { min: Duration, max: Duration },
+    /// Log-normal with the given median; `sigma` is the standard deviation of the log
+    LogNormal { median: Duration, sigma: f64 },
+}
+
+impl DelayDistribution {
+    pub fn sample(&self, rng: &mut impl Rng) -> Duration {
+        let delay = match *self {
+            DelayDistribution::Fixed(delay) => delay,
+            DelayDistribution::Uniform { min, max } if max > min => rng.gen_range(min..=max),
+            DelayDistribution::Uniform { min, .. } => min,
+            DelayDistribution::LogNormal { median, sigma } => {
+                // Box-Muller; 1 - U keeps ln away from 0
+                let z = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt() * (2.0 * PI * rng.gen::<f64>()).cos();
+                median.mul_f64((sigma * z).exp())
+            }
+        };
+        delay.min(MAX_UNDERSTANDING_DELAY)
+    }
+}
+
+/// `fixed:50`, `uniform:20-80` or `lognormal:50,0.5`, in milliseconds
+impl FromStr for DelayDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let ms = |v: &str| {
+            v.trim().parse::<u64>().map(Duration::from_millis).map_err(|_| format!("invalid milliseconds {:?}", v))
+        };
+        let (kind, params) = s.split_once(':').ok_or_else(|| format!("expected KIND:PARAMS, got {:?}", s))?;
+        match kind {
+            "fixed" => Ok(DelayDistribution::Fixed(ms(params)?)),
+            "uniform" => {
+                let (min, max) = params.split_once('-').ok_or("uniform takes MIN-MAX")?;
+                Ok(DelayDistribution::Uniform { min: ms(min)?, max: ms(max)? })
+            }
+            "lognormal" => {
+                let (median, sigma) = params.split_once(',').ok_or("lognormal takes MEDIAN,SIGMA")?;
+                let sigma = sigma.trim().parse::<f64>().map_err(|_| format!("invalid sigma {:?}", sigma))?;
+                if sigma < 0.0 || !sigma.is_finite() {
+                    return Err(format!("sigma must be finite and non-negative, got {}", sigma));
+                }
+                Ok(DelayDistribution::LogNormal { median: ms(median)?, sigma })
+            }
+            other => Err(format!("unknown delay distribution {:?} (fixed, uniform, lognormal)", other)),
+        }
+    }
+}
+
+impl fmt::Display for DelayDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DelayDistribution::Fixed(delay) => write!(f, "fixed:{}", delay.as_millis()),
+            DelayDistribution::Uniform { min, max } => write!(f, "uniform:{}-{}", min.as_millis(), max.as_millis()),
+            DelayDistribution::LogNormal { median, sigma } => write!(f, "lognormal:{},{}", median.as_millis(), sigma),
+        }
+    }
+}
+
+/// Related terms a token expands to
+const EXPANSIONS: &[(&str, &[&str])] = &[
+    ("coffee", &["espresso", "brew", "beans", "roast"]),
+    ("maker", &["machine", "brewer", "appliance"]),
+    ("laptop", &["notebook", "computer", "ultrabook"]),
+    ("phone", &["smartphone", "mobile", "handset"]),
+    ("shoes", &["sneakers", "footwear", "trainers"]),
+    ("headphones", &["earbuds", "headset", "audio"]),
+    ("cheap", &["budget", "affordable", "discount"]),
+    ("best", &["top", "rated", "premium"]),
+    ("wireless", &["bluetooth", "cordless"]),
+    ("kids", &["children", "toddler", "youth"]),
+];
+
+/// Category guessed from the first token found here
+const CATEGORIES: &[(&str, &str)] = &[
+    ("coffee", "kitchen"),
+    ("maker", "kitchen"),
+    ("blender", "kitchen"),
+    ("laptop", "electronics"),
+    ("phone", "electronics"),
+    ("headphones", "electronics"),
+    ("shoes", "apparel"),
+    ("shirt", "apparel"),
+    ("toy", "toys"),
+    ("kids", "toys"),
+];
+
+/// Intent guessed from the first token found here; anything else is "browse"
+const INTENTS: &[(&str, &str)] = &[
+    ("buy", "purchase"),
+    ("cheap", "purchase"),
+    ("deal", "purchase"),
+    ("best", "research"),
+    ("vs", "research"),
+    ("review", "research"),
+];
+
+/// Per-session understanding: the delay before the refined Context and its content
+#[derive(Debug, Clone)]
+pub struct UnderstandingSim {
+    delay: DelayDistribution,
+    /// `{query}`, `{tokens}`, `{expanded}`, `{intent}` and `{category}` are filled in
+    template: String,
+    /// Related terms added per known token
+    expansions_per_token: usize,
+    rng: StdRng,
+}
+
+impl UnderstandingSim {
+    pub fn new(delay: DelayDistribution, template: impl Into<String>, expansions_per_token: usize, seed: Option<u64>) -> Self {
+        UnderstandingSim {
+            delay,
+            template: template.into(),
+            expansions_per_token,
+            rng: seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+        }
+    }
+
+    /// Understanding of `query` and how long it took to compute
+    pub fn simulate(&mut self, query: &str) -> (Duration, String) {
+        let delay = self.delay.sample(&mut self.rng);
+        let tokens: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        let mut expanded = tokens.clone();
+        for token in &tokens {
+            if let Some((_, related)) = EXPANSIONS.iter().find(|(t, _)| *t == token.as_str()) {
+                let picked = related.choose_multiple(&mut self.rng, self.expansions_per_token);
+                expanded.extend(picked.map(|term| term.to_string()));
+            }
+        }
+        let lookup = |table: &[(&str, &'static str)], default: &'static str| {
+            tokens
+                .iter()
+                .find_map(|token| table.iter().find(|(t, _)| *t == token.as_str()).map(|(_, v)| *v))
+                .unwrap_or(default)
+        };
+        let understanding = self
+            .template
+            .replace("{query}", query)
+            .replace("{tokens}", &tokens.join(" "))
+            .replace("{expanded}", &expanded.join(" "))
+            .replace("{intent}", lookup(INTENTS, "browse"))
+            .replace("{category}", lookup(CATEGORIES, "general"));
+        (delay, understanding)
+    }
+}
@@ -0,0 +1,75 @@
+//! Browser client over grpc-web (`--features web`, target `wasm32-unknown-unknown`).
+//!
+//! grpc-web has no client streaming, so this client uses the server-streaming
+//! `GetAdsServerStreaming` RPC: one fully-informed Context is sent and the server
+//! streams all versions back. Version selection is the same as the native client.
+//! The server must be started with grpc-web enabled (it is by default).
+
+use std::collections::HashMap;
+use std::time::Duration;
+use futures_util::future::{select, Either};
+use futures_util::pin_mut;
+use gloo_timers::future::TimeoutFuture;
+use tonic::Status;
+use tonic_web_wasm_client::Client;
+use tracing::{info, warn};
+
+use ads_proto::score::TieBreak;
+
+use crate::ads::{ads_service_client::AdsServiceClient, AdsList, ScoreNormalization};
+use crate::context::ContextBuilder;
+use crate::error::AdsClientError;
+use crate::selection::{merge_versions, SelectionStrategy};
+
+pub struct WebAdsClient {
+    client: AdsServiceClient<Client>,
+    selection: SelectionStrategy,
+}
+
+impl WebAdsClient {
+    /// Create a client for a grpc-web endpoint such as `http://127.0.0.1:50051`
+    pub fn new(base_url: impl Into<String>, selection: SelectionStrategy) -> Self {
+        WebAdsClient {
+            client: AdsServiceClient::new(Client::new(base_url.into())),
+            selection,
+        }
+    }
+
+    /// Request ads and select a result from the versions received within `timeout`
+    pub async fn get_ads(
+        &mut self,
+        contexts: &ContextBuilder,
+        timeout: Duration,
+    ) -> Result<Option<AdsList>, AdsClientError> {
+        let context = contexts.build_complete()?;
+        let mut stream = self.client.get_ads_server_streaming(context).await?.into_inner();
+        let mut ads_buffer: HashMap<u32, AdsList> = HashMap::new();
+
+        {
+            let receive = async {
+                while let Some(response) = stream.message().await? {
+                    info!(version = response.version, ads_count = response.ads.len(), "Received AdsList");
+                    ads_buffer.insert(response.version, response);
+                }
+                Ok::<(), Status>(())
+            };
+            let deadline = TimeoutFuture::new(timeout.as_millis() as u32);
+            pin_mut!(receive);
+            match select(receive, deadline).await {
+                Either::Left((Err(e), _)) => warn!(error = %e, "Stream error occurred"),
+                Either::Left((Ok(()), _)) => info!("Stream completed normally before timeout"),
+                Either::Right(_) => info!(
+                    timeout_ms = timeout.as_millis() as u64,
+                    versions_received = ads_buffer.len(),
+                    "Client timeout reached - proceeding with available results"
+                ),
+            }
+        }
+
+        let selected = match self.selection {
+            SelectionStrategy::LatestVersion => ads_buffer.values().max_by_key(|ads| ads.version).cloned(),
+            SelectionStrategy::MergeVersions => merge_versions(&ads_buffer, ScoreNormalization::None, TieBreak::AdId, 0),
+        };
+        Ok(selected)
+    }
+}
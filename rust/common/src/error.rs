@@ -0,0 +1,112 @@
+use std::fmt;
+use std::io;
+use std::net::AddrParseError;
+
+use tonic::Status;
+
+/// Failure of a server, client or tool operation
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// Failed to bind, serve or connect
+    #[cfg(feature = "transport")]
+    Transport(tonic::transport::Error),
+    /// A status returned by, or destined for, a gRPC peer; boxed, since a Status
+    /// alone would make every `Result` of the workspace several times larger
+    Status(Box<Status>),
+    /// Input that failed validation: a Context, a file row, a command-line value
+    Invalid(String),
+    /// Configuration or environment the process cannot run with: a config file,
+    /// a generator plugin, process limits
+    Config(String),
+    /// A session that failed on the client side without a status from the server
+    Session(String),
+}
+
+/// Result of an operation failing with `Error`
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Error::Invalid(message.into())
+    }
+
+    pub fn config(message: impl Into<String>) -> Self {
+        Error::Config(message.into())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Json(e) => write!(f, "JSON error: {}", e),
+            #[cfg(feature = "transport")]
+            Error::Transport(e) => write!(f, "transport error: {}", e),
+            Error::Status(s) => write!(f, "{}: {}", s.code(), s.message()),
+            Error::Invalid(msg) => write!(f, "invalid input: {}", msg),
+            Error::Config(msg) => write!(f, "configuration error: {}", msg),
+            Error::Session(msg) => write!(f, "session failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Json(e) => Some(e),
+            #[cfg(feature = "transport")]
+            Error::Transport(e) => Some(e),
+            Error::Status(s) => Some(s.as_ref()),
+            Error::Invalid(_) | Error::Config(_) | Error::Session(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+#[cfg(feature = "transport")]
+impl From<tonic::transport::Error> for Error {
+    fn from(e: tonic::transport::Error) -> Self {
+        Error::Transport(e)
+    }
+}
+
+impl From<Status> for Error {
+    fn from(s: Status) -> Self {
+        Error::Status(Box::new(s))
+    }
+}
+
+impl From<AddrParseError> for Error {
+    fn from(e: AddrParseError) -> Self {
+        Error::Invalid(e.to_string())
+    }
+}
+
+/// The status a server handler answers with: invalid input is the caller's fault,
+/// everything else is the server's
+impl From<Error> for Status {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Status(status) => *status,
+            Error::Invalid(msg) => Status::invalid_argument(msg),
+            Error::Config(msg) => Status::failed_precondition(msg),
+            #[cfg(feature = "transport")]
+            Error::Transport(e) => Status::unavailable(e.to_string()),
+            e => Status::internal(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,10 @@
+//! Error and result types, conversions and protocol constants shared by the Rust
+//! server, client and tools, so every crate reports failures the same way instead
+//! of mixing `Box<dyn Error>`, bare `Status` values and error strings.
+
+pub mod error;
+pub mod limits;
+pub mod outcome;
+pub mod report;
+
+pub use error::{Error, Result};
@@ -0,0 +1,28 @@
+//! Constants of the playground protocol every implementation agrees on
+
+use crate::error::{Error, Result};
+
+/// AdsList answering the first Context of a session (no understanding yet)
+pub const INITIAL_VERSION: u32 = 1;
+/// AdsList answering the second Context, which carries the understanding
+pub const REFINED_VERSION: u32 = 2;
+/// Delayed AdsList the server sends after the refined one
+pub const FINAL_VERSION: u32 = 3;
+
+/// Longest accepted ASIN (Amazon Standard Identification Numbers are 10 characters)
+pub const MAX_ASIN_LEN: usize = 10;
+
+/// Check that `asin_id` is 1 to `MAX_ASIN_LEN` uppercase letters or digits
+pub fn validate_asin(asin_id: &str) -> Result<()> {
+    let asin_ok = !asin_id.is_empty()
+        && asin_id.len() <= MAX_ASIN_LEN
+        && asin_id.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+    if asin_ok {
+        Ok(())
+    } else {
+        Err(Error::invalid(format!(
+            "asin_id {:?} must be 1-{} uppercase letters or digits",
+            asin_id, MAX_ASIN_LEN
+        )))
+    }
+}
@@ -0,0 +1,147 @@
+//! How a session of the refinement protocol ended, classified the same way by the
+//! client and the server: how far through the versions it got before it settled,
+//! or why it ended without settling. Counted over a run, the outcomes form the
+//! protocol's funnel.
+
+use std::fmt;
+
+use crate::limits::{FINAL_VERSION, INITIAL_VERSION, REFINED_VERSION};
+
+/// How the session's stream ended, before looking at the versions it delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEnd {
+    /// The stream finished, or the client stopped waiting once it had what it needed
+    Completed,
+    /// The client's timeout expired
+    TimedOut,
+    /// The peer went away: the client cancelled, or the server saw the client drop the stream
+    Cancelled,
+    /// The stream failed with an error
+    Failed,
+    /// The client saw no AdsList for its stall gap after receiving one
+    Stalled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SessionOutcome {
+    /// The client timed out before any AdsList arrived
+    TimeoutBeforeResult,
+    /// The session ended holding version 1 as its latest list
+    SettledOnV1,
+    /// The session ended holding version 2 as its latest list
+    SettledOnV2,
+    /// The session received the final version 3 (or later)
+    GotFinal,
+    /// The latest list the session got was an explicit no-fill: the server had no ad
+    /// worth showing, as opposed to no answer in time
+    NoFill,
+    /// The stream stalled after delivering a list and the final version never came
+    Stalled,
+    Error,
+    /// Cancelled before any AdsList arrived
+    Cancelled,
+}
+
+impl SessionOutcome {
+    /// Every outcome, in funnel order
+    pub const ALL: [SessionOutcome; 8] = [
+        SessionOutcome::TimeoutBeforeResult,
+        SessionOutcome::SettledOnV1,
+        SessionOutcome::SettledOnV2,
+        SessionOutcome::GotFinal,
+        SessionOutcome::NoFill,
+        SessionOutcome::Stalled,
+        SessionOutcome::Error,
+        SessionOutcome::Cancelled,
+    ];
+
+    /// Classify a session from how it ended, the highest version it delivered (0
+    /// when none) and whether that version was an explicit no-fill. An error trumps
+    /// the versions received, and a no-fill list is not settled on; a stall counts
+    /// unless the final version still arrived; otherwise a session that got any list
+    /// settled on the highest one.
+    pub fn classify(end: SessionEnd, highest_version: u32, no_fill: bool) -> Self {
+        match (end, highest_version) {
+            (SessionEnd::Failed, _) => SessionOutcome::Error,
+            (_, version) if version >= INITIAL_VERSION && no_fill => SessionOutcome::NoFill,
+            (_, version) if version >= FINAL_VERSION => SessionOutcome::GotFinal,
+            (SessionEnd::Stalled, _) => SessionOutcome::Stalled,
+            (_, version) if version >= REFINED_VERSION => SessionOutcome::SettledOnV2,
+            (_, version) if version >= INITIAL_VERSION => SessionOutcome::SettledOnV1,
+            (SessionEnd::TimedOut, _) => SessionOutcome::TimeoutBeforeResult,
+            (SessionEnd::Cancelled, _) => SessionOutcome::Cancelled,
+            // A stream that finished without a single list failed the client
+            (SessionEnd::Completed, _) => SessionOutcome::Error,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SessionOutcome::TimeoutBeforeResult => "timeout_before_result",
+            SessionOutcome::SettledOnV1 => "settled_v1",
+            SessionOutcome::SettledOnV2 => "settled_v2",
+            SessionOutcome::GotFinal => "final_v3",
+            SessionOutcome::NoFill => "no_fill",
+            SessionOutcome::Stalled => "stalled",
+            SessionOutcome::Error => "error",
+            SessionOutcome::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        SessionOutcome::ALL.into_iter().find(|outcome| outcome.name() == name)
+    }
+
+    fn index(self) -> usize {
+        SessionOutcome::ALL.iter().position(|outcome| *outcome == self).unwrap_or(0)
+    }
+}
+
+impl fmt::Display for SessionOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Session counts per outcome
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OutcomeFunnel {
+    counts: [u64; 8],
+}
+
+impl OutcomeFunnel {
+    pub fn record(&mut self, outcome: SessionOutcome) {
+        self.add(outcome, 1);
+    }
+
+    pub fn add(&mut self, outcome: SessionOutcome, count: u64) {
+        self.counts[outcome.index()] += count;
+    }
+
+    pub fn merge(&mut self, other: &OutcomeFunnel) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+    }
+
+    pub fn count(&self, outcome: SessionOutcome) -> u64 {
+        self.counts[outcome.index()]
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// One line of `outcome=count (share%)` in funnel order, e.g. for a run summary
+    pub fn render(&self) -> String {
+        let total = self.total().max(1) as f64;
+        SessionOutcome::ALL
+            .iter()
+            .map(|outcome| {
+                let count = self.count(*outcome);
+                format!("{}={} ({:.1}%)", outcome.name(), count, count as f64 * 100.0 / total)
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    }
+}
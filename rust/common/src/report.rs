@@ -0,0 +1,136 @@
+//! Final report the server and client emit when they exit, normally or on a
+//! signal: sessions, errors by cause, the outcome funnel, session latency
+//! percentiles and uptime. It is logged as one JSON line and can be written to a
+//! file, so every run leaves a machine-readable summary even without a journal.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+
+use crate::outcome::{OutcomeFunnel, SessionOutcome};
+
+/// Session latency quantiles every report carries
+pub const REPORT_QUANTILES: [f64; 3] = [0.5, 0.9, 0.99];
+
+#[derive(Debug, Clone)]
+pub struct ShutdownReport {
+    /// Binary that wrote the report, e.g. `ads-server`
+    pub binary: String,
+    /// Why the process is exiting: `completed`, `signal` or `error`
+    pub exit: String,
+    pub uptime: Duration,
+    pub sessions: u64,
+    /// Failed or refused sessions by cause
+    pub errors: BTreeMap<String, u64>,
+    pub outcomes: OutcomeFunnel,
+    /// Session latency (ms) at each of `REPORT_QUANTILES`; empty without sessions
+    pub latency_ms: Vec<(f64, f64)>,
+    /// Generation work by whether clients used it; servers only
+    pub work: Option<WorkReport>,
+}
+
+/// One quantity of generation work, split by what became of the versions it went into
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorkSplit {
+    /// Versions the client reported using (or superseded by one it used)
+    pub used: u64,
+    /// Versions after the one the client used, or never delivered
+    pub wasted: u64,
+    /// Versions of sessions without client feedback
+    pub unreported: u64,
+}
+
+impl WorkSplit {
+    /// Share of the work with feedback that was wasted; None without any
+    pub fn wasted_ratio(&self) -> Option<f64> {
+        let reported = self.used + self.wasted;
+        (reported > 0).then(|| self.wasted as f64 / reported as f64)
+    }
+
+    fn to_json(self) -> Value {
+        json!({
+            "used": self.used,
+            "wasted": self.wasted,
+            "unreported": self.unreported,
+            "wasted_ratio": self.wasted_ratio(),
+        })
+    }
+}
+
+/// Generation wall time and candidates spent over a run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorkReport {
+    pub generation_us: WorkSplit,
+    pub candidates: WorkSplit,
+}
+
+impl ShutdownReport {
+    pub fn new(binary: &str, exit: &str, uptime: Duration) -> Self {
+        ShutdownReport {
+            binary: binary.to_string(),
+            exit: exit.to_string(),
+            uptime,
+            sessions: 0,
+            errors: BTreeMap::new(),
+            outcomes: OutcomeFunnel::default(),
+            latency_ms: Vec::new(),
+            work: None,
+        }
+    }
+
+    pub fn total_errors(&self) -> u64 {
+        self.errors.values().sum()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let outcomes: Map<String, Value> = SessionOutcome::ALL
+            .iter()
+            .map(|outcome| (outcome.name().to_string(), self.outcomes.count(*outcome).into()))
+            .collect();
+        let latency: Map<String, Value> = self
+            .latency_ms
+            .iter()
+            .map(|(q, ms)| (format!("p{}", (q * 100.0).round()), json!(ms)))
+            .collect();
+        let mut report = json!({
+            "binary": self.binary,
+            "exit": self.exit,
+            "uptime_secs": self.uptime.as_secs_f64(),
+            "sessions": self.sessions,
+            "errors": self.total_errors(),
+            "errors_by_cause": self.errors,
+            "outcomes": outcomes,
+            "latency_ms": latency,
+        });
+        if let Some(work) = &self.work {
+            report["wasted_work"] = json!({
+                "generation_us": work.generation_us.to_json(),
+                "candidates": work.candidates.to_json(),
+            });
+        }
+        report
+    }
+
+    /// Write the report as pretty-printed JSON
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.to_json())?;
+        std::fs::write(path, json + "\n")
+    }
+}
+
+/// Exact quantiles of `REPORT_QUANTILES` over latencies sorted ascending
+pub fn quantiles(sorted_ms: &[f64]) -> Vec<(f64, f64)> {
+    if sorted_ms.is_empty() {
+        return Vec::new();
+    }
+    REPORT_QUANTILES
+        .iter()
+        .map(|q| {
+            let rank = ((q * sorted_ms.len() as f64).ceil() as usize).clamp(1, sorted_ms.len());
+            (*q, sorted_ms[rank - 1])
+        })
+        .collect()
+}
@@ -0,0 +1,133 @@
+//! Sequence diagrams of one session's timing, built from the same attributed
+//! client/server events as the latency roll-up.
+
+use std::fmt::Write;
+
+use crate::events::LogEvent;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Mermaid,
+    Plantuml,
+}
+
+enum Step {
+    /// Arrow between participants; `reply` uses the dashed style
+    Message { from: &'static str, to: &'static str, label: String, reply: bool },
+    Note { over: &'static str, text: String },
+}
+
+const CLIENT: &str = "Client";
+const SERVER: &str = "Server";
+
+fn ms(event: &LogEvent, start_us: i64) -> String {
+    format!("{:.1}ms", (event.timestamp_us - start_us) as f64 / 1000.0)
+}
+
+/// Steps of `request_id` in time order. Sends become arrows; receipts, half-close
+/// and how result selection ended become notes. Times are relative to the first event.
+fn steps(attributed: &[(String, &LogEvent)], request_id: &str) -> Vec<Step> {
+    let mut events: Vec<&LogEvent> = attributed
+        .iter()
+        .filter(|(id, _)| id == request_id)
+        .map(|(_, event)| *event)
+        .collect();
+    events.sort_by_key(|event| event.timestamp_us);
+    let Some(start_us) = events.first().map(|event| event.timestamp_us) else { return Vec::new() };
+
+    let mut steps = Vec::new();
+    for event in events {
+        let at = ms(event, start_us);
+        let context = event.u64("context_number").unwrap_or(0);
+        let version = event.u64("version").unwrap_or(0);
+        let step = match event.message.as_str() {
+            "Sending Context message" => Step::Message {
+                from: CLIENT,
+                to: SERVER,
+                label: format!("Context {} @ {}", context, at),
+                reply: false,
+            },
+            "Received Context message" => Step::Note { over: SERVER, text: format!("received Context {} @ {}", context, at) },
+            "Sending AdsList" => Step::Message {
+                from: SERVER,
+                to: CLIENT,
+                label: format!("AdsList v{} @ {}", version, at),
+                reply: true,
+            },
+            "Sending delayed AdsList" => Step::Message {
+                from: SERVER,
+                to: CLIENT,
+                label: format!("AdsList v{} (delayed) @ {}", version, at),
+                reply: true,
+            },
+            "Received AdsList" => Step::Note { over: CLIENT, text: format!("received v{} @ {}", version, at) },
+            "Half-closed client stream" => Step::Message {
+                from: CLIENT,
+                to: SERVER,
+                label: format!("half-close @ {}", at),
+                reply: false,
+            },
+            "Client timeout reached - proceeding with available results" => Step::Note {
+                over: CLIENT,
+                text: format!("timeout {}ms reached @ {}", event.u64("timeout_ms").unwrap_or(0), at),
+            },
+            "Acceptable version received - exiting early" => {
+                Step::Note { over: CLIENT, text: format!("early exit @ {}", at) }
+            }
+            "Stream completed normally before timeout" => {
+                Step::Note { over: CLIENT, text: format!("stream completed @ {}", at) }
+            }
+            "FINAL RESULT: Selected AdsList" => Step::Note {
+                over: CLIENT,
+                text: format!("selected v{} @ {}", event.u64("selected_version").unwrap_or(0), at),
+            },
+            "FINAL RESULT: No AdsList received within timeout" => {
+                Step::Note { over: CLIENT, text: format!("no result @ {}", at) }
+            }
+            _ => continue,
+        };
+        steps.push(step);
+    }
+    steps
+}
+
+/// Render the session of `request_id`, or None if no events were attributed to it
+pub fn render(attributed: &[(String, &LogEvent)], request_id: &str, format: Format) -> Option<String> {
+    let steps = steps(attributed, request_id);
+    if steps.is_empty() {
+        return None;
+    }
+    let mut out = String::new();
+    match format {
+        Format::Mermaid => {
+            let _ = writeln!(out, "sequenceDiagram");
+            let _ = writeln!(out, "    title Session {}", request_id);
+            let _ = writeln!(out, "    participant {}", CLIENT);
+            let _ = writeln!(out, "    participant {}", SERVER);
+            for step in steps {
+                let _ = match step {
+                    Step::Message { from, to, label, reply } => {
+                        writeln!(out, "    {}{}{}: {}", from, if reply { "-->>" } else { "->>" }, to, label)
+                    }
+                    Step::Note { over, text } => writeln!(out, "    Note over {}: {}", over, text),
+                };
+            }
+        }
+        Format::Plantuml => {
+            let _ = writeln!(out, "@startuml");
+            let _ = writeln!(out, "title Session {}", request_id);
+            let _ = writeln!(out, "participant {}", CLIENT);
+            let _ = writeln!(out, "participant {}", SERVER);
+            for step in steps {
+                let _ = match step {
+                    Step::Message { from, to, label, reply } => {
+                        writeln!(out, "{} {} {} : {}", from, if reply { "-->" } else { "->" }, to, label)
+                    }
+                    Step::Note { over, text } => writeln!(out, "note over {} : {}", over, text),
+                };
+            }
+            let _ = writeln!(out, "@enduml");
+        }
+    }
+    Some(out)
+}
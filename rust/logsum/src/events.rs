@@ -0,0 +1,86 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use chrono::DateTime;
+use serde_json::{Map, Value};
+
+/// One line of a JSON log written with ADS_LOG_FORMAT=json
+#[derive(Debug, Clone)]
+pub struct LogEvent {
+    /// Index of the input file, so per-process ids (session_id) stay distinct across runs
+    pub file: usize,
+    pub timestamp_us: i64,
+    pub message: String,
+    /// Event fields merged over the fields of the enclosing spans
+    pub fields: Map<String, Value>,
+}
+
+impl LogEvent {
+    pub fn u64(&self, key: &str) -> Option<u64> {
+        match self.fields.get(key)? {
+            Value::Number(n) => n.as_u64(),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn i64(&self, key: &str) -> Option<i64> {
+        match self.fields.get(key)? {
+            Value::Number(n) => n.as_i64(),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn str(&self, key: &str) -> Option<&str> {
+        self.fields.get(key)?.as_str().filter(|s| !s.is_empty())
+    }
+
+    fn parse(file: usize, line: &str) -> Option<LogEvent> {
+        let value: Value = serde_json::from_str(line).ok()?;
+        let timestamp = DateTime::parse_from_rfc3339(value.get("timestamp")?.as_str()?).ok()?;
+        let mut fields = Map::new();
+        // Outermost span first so inner spans and the event itself win on conflicts
+        if let Some(Value::Array(spans)) = value.get("spans") {
+            for span in spans {
+                if let Value::Object(span) = span {
+                    fields.extend(span.clone());
+                }
+            }
+        }
+        let mut event_fields = match value.get("fields") {
+            Some(Value::Object(event_fields)) => event_fields.clone(),
+            _ => Map::new(),
+        };
+        let message = match event_fields.remove("message") {
+            Some(Value::String(message)) => message,
+            _ => String::new(),
+        };
+        fields.extend(event_fields);
+        Some(LogEvent {
+            file,
+            timestamp_us: timestamp.timestamp_micros(),
+            message,
+            fields,
+        })
+    }
+}
+
+/// Read every parseable JSON line; returns the events and the number of skipped lines
+pub fn read_events(path: &Path, file: usize) -> io::Result<(Vec<LogEvent>, usize)> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut events = Vec::new();
+    let mut skipped = 0;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match LogEvent::parse(file, &line) {
+            Some(event) => events.push(event),
+            None => skipped += 1,
+        }
+    }
+    Ok((events, skipped))
+}
@@ -0,0 +1,160 @@
+//! Roll-up of JSON logs (ADS_LOG_FORMAT=json) from client and server runs: joins
+//! both sides by request id and prints per-version latency breakdowns
+//! (client send -> server receive -> generation -> client receive). Server
+//! timestamps are shifted by the clock skew the client estimated and logged, so
+//! uplink and downlink hold up across machines. With `--diagram` it instead renders
+//! one session as a Mermaid or PlantUML sequence diagram.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use ads_common::Error;
+use clap::Parser;
+
+mod diagram;
+mod events;
+mod timeline;
+
+use timeline::Breakdown;
+
+#[derive(Parser, Debug)]
+#[command(name = "logsum", about = "Join client and server JSON logs and summarize cross-process latency")]
+struct Args {
+    /// Client and server log files, in any order
+    #[arg(required = true)]
+    logs: Vec<PathBuf>,
+
+    /// Print only the per-version aggregate, not every request
+    #[arg(long)]
+    summary_only: bool,
+
+    /// Render this request id ("first" = earliest session in the logs) as a sequence diagram
+    #[arg(long, value_name = "REQUEST_ID")]
+    diagram: Option<String>,
+
+    /// Diagram syntax for --diagram
+    #[arg(long, value_enum, default_value = "mermaid")]
+    diagram_format: diagram::Format,
+
+    /// Write the diagram to this file instead of stdout
+    #[arg(long, requires = "diagram")]
+    out: Option<PathBuf>,
+
+    /// Use raw server timestamps even when the client logged a clock-skew estimate
+    #[arg(long)]
+    no_skew_correction: bool,
+}
+
+fn fmt_ms(value: Option<f64>) -> String {
+    value.map_or("-".to_string(), |ms| format!("{:.2}", ms))
+}
+
+/// A latency segment of a breakdown, by name
+type Segment = (&'static str, fn(&Breakdown) -> Option<f64>);
+
+fn percentile(sorted: &[f64], q: f64) -> f64 {
+    sorted[((sorted.len() - 1) as f64 * q).round() as usize]
+}
+
+fn main() -> ads_common::Result<()> {
+    let args = Args::parse();
+
+    let mut events = Vec::new();
+    for (file, path) in args.logs.iter().enumerate() {
+        let (file_events, skipped) = events::read_events(path, file)?;
+        eprintln!("{}: {} events, {} non-JSON lines skipped", path.display(), file_events.len(), skipped);
+        events.extend(file_events);
+    }
+
+    if let Some(request_id) = &args.diagram {
+        let attributed = timeline::attribute(&events);
+        let request_id = if request_id == "first" {
+            attributed
+                .iter()
+                .min_by_key(|(_, event)| event.timestamp_us)
+                .map(|(id, _)| id.clone())
+                .ok_or_else(|| Error::invalid("no sessions found in the logs"))?
+        } else {
+            request_id.clone()
+        };
+        let rendered = diagram::render(&attributed, &request_id, args.diagram_format)
+            .ok_or_else(|| Error::invalid(format!("no events for request id {}", request_id)))?;
+        match &args.out {
+            Some(path) => {
+                std::fs::write(path, rendered)?;
+                eprintln!("Wrote diagram of {} to {}", request_id, path.display());
+            }
+            None => print!("{}", rendered),
+        }
+        return Ok(());
+    }
+
+    let timelines = timeline::join(&events, !args.no_skew_correction);
+    let joined = timelines
+        .values()
+        .filter(|t| !t.client_send.is_empty() && !t.server_recv.is_empty())
+        .count();
+    println!("{} requests, {} seen by both client and server", timelines.len(), joined);
+    let offsets: Vec<i64> = timelines.values().filter_map(|t| t.clock_offset_us).collect();
+    if let (Some(min), Some(max)) = (offsets.iter().min(), offsets.iter().max()) {
+        println!(
+            "{} requests corrected for clock skew (offset {:.2}..{:.2} ms)",
+            offsets.len(),
+            *min as f64 / 1000.0,
+            *max as f64 / 1000.0
+        );
+    }
+
+    let header = format!(
+        "{:<16}  {:>3}  {:>10}  {:>10}  {:>11}  {:>9}",
+        "REQUEST_ID", "VER", "UPLINK_MS", "SERVER_MS", "DOWNLINK_MS", "TOTAL_MS"
+    );
+    let mut by_version: BTreeMap<u32, Vec<Breakdown>> = BTreeMap::new();
+    if !args.summary_only {
+        println!("\n{}", header);
+    }
+    for (request_id, timeline) in &timelines {
+        for (version, breakdown) in timeline.breakdown() {
+            if !args.summary_only {
+                println!(
+                    "{:<16}  {:>3}  {:>10}  {:>10}  {:>11}  {:>9}",
+                    request_id,
+                    version,
+                    fmt_ms(breakdown.uplink_ms),
+                    fmt_ms(breakdown.server_ms),
+                    fmt_ms(breakdown.downlink_ms),
+                    fmt_ms(breakdown.total_ms),
+                );
+            }
+            by_version.entry(version).or_default().push(breakdown);
+        }
+    }
+
+    println!("\n{:<3}  {:<11}  {:>5}  {:>9}  {:>9}  {:>9}", "VER", "SEGMENT", "N", "MEAN_MS", "P50_MS", "P95_MS");
+    for (version, breakdowns) in &by_version {
+        let segments: [Segment; 4] = [
+            ("uplink", |b| b.uplink_ms),
+            ("server", |b| b.server_ms),
+            ("downlink", |b| b.downlink_ms),
+            ("total", |b| b.total_ms),
+        ];
+        for (name, segment) in segments {
+            let mut values: Vec<f64> = breakdowns.iter().filter_map(segment).collect();
+            if values.is_empty() {
+                continue;
+            }
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            println!(
+                "{:<3}  {:<11}  {:>5}  {:>9.2}  {:>9.2}  {:>9.2}",
+                version,
+                name,
+                values.len(),
+                mean,
+                percentile(&values, 0.5),
+                percentile(&values, 0.95),
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::events::LogEvent;
+
+/// Timestamps (µs) of one session as seen from both processes, keyed by
+/// Context number (sends/receives of Contexts) or AdsList version
+#[derive(Debug, Default)]
+pub struct Timeline {
+    pub client_send: BTreeMap<u32, i64>,
+    pub server_recv: BTreeMap<u32, i64>,
+    pub server_send: BTreeMap<u32, i64>,
+    pub client_recv: BTreeMap<u32, i64>,
+    /// Server clock minus client clock (µs) already taken out of the server timestamps
+    pub clock_offset_us: Option<i64>,
+}
+
+/// Latency segments (ms) for one AdsList version
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Breakdown {
+    /// Client sends the Context -> server receives it
+    pub uplink_ms: Option<f64>,
+    /// Server receives the Context -> server sends the AdsList (generation and delays)
+    pub server_ms: Option<f64>,
+    /// Server sends the AdsList -> client receives it
+    pub downlink_ms: Option<f64>,
+    /// Client sends the Context -> client receives the AdsList
+    pub total_ms: Option<f64>,
+}
+
+fn delta_ms(from: Option<&i64>, to: Option<&i64>) -> Option<f64> {
+    Some((*to? - *from?) as f64 / 1000.0)
+}
+
+impl Timeline {
+    /// Versions seen on either side, with their latency breakdown. Version N answers
+    /// Context N; versions past the last Context (the delayed refinement) answer it.
+    pub fn breakdown(&self) -> Vec<(u32, Breakdown)> {
+        let mut versions: Vec<u32> = self.server_send.keys().chain(self.client_recv.keys()).copied().collect();
+        versions.sort_unstable();
+        versions.dedup();
+        let last_context = self.client_send.keys().chain(self.server_recv.keys()).max().copied().unwrap_or(1);
+        versions
+            .into_iter()
+            .map(|version| {
+                let context = version.min(last_context);
+                let breakdown = Breakdown {
+                    uplink_ms: delta_ms(self.client_send.get(&context), self.server_recv.get(&context)),
+                    server_ms: delta_ms(self.server_recv.get(&context), self.server_send.get(&version)),
+                    downlink_ms: delta_ms(self.server_send.get(&version), self.client_recv.get(&version)),
+                    total_ms: delta_ms(self.client_send.get(&context), self.client_recv.get(&version)),
+                };
+                (version, breakdown)
+            })
+            .collect()
+    }
+}
+
+/// Client log messages that belong to the request currently open in their file
+const CLIENT_MESSAGES: &[&str] = &[
+    "Sending Context message",
+    "Half-closed client stream",
+    "Received AdsList",
+    "Acceptable version received - exiting early",
+    "Stream completed normally before timeout",
+    "Client timeout reached - proceeding with available results",
+    "FINAL RESULT: Selected AdsList",
+    "FINAL RESULT: No AdsList received within timeout",
+];
+
+/// Server log messages of an open session
+const SERVER_MESSAGES: &[&str] = &["Received Context message", "Sending AdsList", "Sending delayed AdsList"];
+
+/// Attribute client and server events to request ids, ordered by (file, time).
+///
+/// Client events belong to the request opened by the latest "Starting bidirectional
+/// stream" of their file (client sessions run sequentially) unless they carry a
+/// request_id themselves. Server events are mapped through session_id, which the
+/// server ties to the request id when the stream opens.
+pub fn attribute(events: &[LogEvent]) -> Vec<(String, &LogEvent)> {
+    let mut attributed = Vec::new();
+    let mut current_client_request: HashMap<usize, String> = HashMap::new();
+    let mut server_sessions: HashMap<(usize, u64), String> = HashMap::new();
+
+    let mut ordered: Vec<&LogEvent> = events.iter().collect();
+    ordered.sort_by_key(|event| (event.file, event.timestamp_us));
+
+    for event in ordered {
+        let message = event.message.as_str();
+        if message == "Starting bidirectional stream" {
+            if let Some(request_id) = event.str("request_id") {
+                current_client_request.insert(event.file, request_id.to_string());
+            }
+        } else if message == "New bidirectional stream opened" {
+            if let (Some(session_id), Some(request_id)) = (event.u64("session_id"), event.str("request_id")) {
+                server_sessions.insert((event.file, session_id), request_id.to_string());
+            }
+        } else if CLIENT_MESSAGES.contains(&message) {
+            let request_id = event
+                .str("request_id")
+                .map(str::to_string)
+                .or_else(|| current_client_request.get(&event.file).cloned());
+            if let Some(request_id) = request_id {
+                attributed.push((request_id, event));
+            }
+        } else if SERVER_MESSAGES.contains(&message) {
+            let request_id = event
+                .u64("session_id")
+                .and_then(|session_id| server_sessions.get(&(event.file, session_id)));
+            if let Some(request_id) = request_id {
+                attributed.push((request_id.clone(), event));
+            }
+        }
+    }
+    attributed
+}
+
+/// Clock offsets (server minus client, µs) logged by each client file, in time order
+fn clock_offsets(events: &[LogEvent]) -> HashMap<usize, Vec<(i64, i64)>> {
+    let mut offsets: HashMap<usize, Vec<(i64, i64)>> = HashMap::new();
+    for event in events {
+        if !CLOCK_MESSAGES.contains(&event.message.as_str()) {
+            continue;
+        }
+        if let Some(offset_us) = event.i64("offset_us") {
+            offsets.entry(event.file).or_default().push((event.timestamp_us, offset_us));
+        }
+    }
+    for file_offsets in offsets.values_mut() {
+        file_offsets.sort_unstable();
+    }
+    offsets
+}
+
+/// Client messages carrying a clock-skew estimate
+const CLOCK_MESSAGES: &[&str] = &["Clock skew estimated", "Clock skew adjusted"];
+
+/// Join client and server events into one timeline per request id. With
+/// `correct_skew`, server timestamps are moved onto the client's clock using the
+/// latest estimate its log had when the request started, so uplink and downlink
+/// stay meaningful between machines whose clocks drift apart.
+pub fn join(events: &[LogEvent], correct_skew: bool) -> BTreeMap<String, Timeline> {
+    let mut timelines: BTreeMap<String, Timeline> = BTreeMap::new();
+    // First client event of each request: (file, timestamp)
+    let mut client_start: HashMap<String, (usize, i64)> = HashMap::new();
+    for (request_id, event) in attribute(events) {
+        if CLIENT_MESSAGES.contains(&event.message.as_str()) {
+            let start = client_start.entry(request_id.clone()).or_insert((event.file, event.timestamp_us));
+            start.1 = start.1.min(event.timestamp_us);
+        }
+        let timeline = timelines.entry(request_id).or_default();
+        let context = event.u64("context_number").map(|n| n as u32);
+        let version = event.u64("version").map(|n| n as u32);
+        let (map, key) = match (event.message.as_str(), context, version) {
+            ("Sending Context message", Some(context), _) => (&mut timeline.client_send, context),
+            ("Received Context message", Some(context), _) => (&mut timeline.server_recv, context),
+            ("Sending AdsList" | "Sending delayed AdsList", _, Some(version)) => (&mut timeline.server_send, version),
+            ("Received AdsList", _, Some(version)) => (&mut timeline.client_recv, version),
+            _ => continue,
+        };
+        map.entry(key).or_insert(event.timestamp_us);
+    }
+    if !correct_skew {
+        return timelines;
+    }
+    let offsets = clock_offsets(events);
+    for (request_id, timeline) in &mut timelines {
+        let Some((file, started_us)) = client_start.get(request_id) else { continue };
+        let Some(file_offsets) = offsets.get(file) else { continue };
+        // Estimates are logged on connect, before the first request, and only move later
+        let offset_us = file_offsets
+            .iter()
+            .take_while(|(at_us, _)| at_us <= started_us)
+            .last()
+            .or(file_offsets.first())
+            .map(|(_, offset_us)| *offset_us);
+        let Some(offset_us) = offset_us else { continue };
+        for timestamp_us in timeline.server_recv.values_mut().chain(timeline.server_send.values_mut()) {
+            *timestamp_us -= offset_us;
+        }
+        timeline.clock_offset_us = Some(offset_us);
+    }
+    timelines
+}
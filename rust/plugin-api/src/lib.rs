@@ -0,0 +1,104 @@
+//! Stable ABI between the Rust server and dynamically loaded ad generator plugins.
+//!
+//! A plugin is a `cdylib` exporting four C functions. Contexts and AdsLists cross
+//! the boundary as protobuf-encoded bytes, so the ABI only depends on ads.proto
+//! (which evolves compatibly) and not on Rust layout, compiler version or even the
+//! plugin's implementation language:
+//!
+//! - `ads_plugin_abi_version() -> u32`: must return [`ABI_VERSION`]
+//! - `ads_plugin_name() -> *const c_char`: NUL-terminated static name for logs
+//! - `ads_plugin_generate(context, context_len, version, seed, out, out_len) -> i32`:
+//!   decode a Context, write an encoded AdsList to `*out`/`*out_len`, return a `STATUS_*`
+//! - `ads_plugin_free(ptr, len)`: release a buffer returned by `ads_plugin_generate`
+//!
+//! Rust plugins implement a plain function and call [`export_generator!`].
+
+use std::os::raw::c_char;
+
+pub use ads_proto;
+pub use prost;
+
+/// Bumped on any incompatible change to the exported functions
+pub const ABI_VERSION: u32 = 1;
+
+pub const ABI_VERSION_SYMBOL: &[u8] = b"ads_plugin_abi_version\0";
+pub const NAME_SYMBOL: &[u8] = b"ads_plugin_name\0";
+pub const GENERATE_SYMBOL: &[u8] = b"ads_plugin_generate\0";
+pub const FREE_SYMBOL: &[u8] = b"ads_plugin_free\0";
+
+/// The AdsList was written to the output buffer
+pub const STATUS_OK: i32 = 0;
+/// The Context bytes could not be decoded
+pub const STATUS_DECODE_ERROR: i32 = 1;
+/// The generator panicked; the panic was caught inside the plugin
+pub const STATUS_PANIC: i32 = 2;
+
+pub type AbiVersionFn = unsafe extern "C" fn() -> u32;
+pub type NameFn = unsafe extern "C" fn() -> *const c_char;
+pub type GenerateFn = unsafe extern "C" fn(
+    context: *const u8,
+    context_len: usize,
+    version: u32,
+    seed: u64,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> i32;
+pub type FreeFn = unsafe extern "C" fn(ptr: *mut u8, len: usize);
+
+/// Export `$generate: fn(&Context, u32, u64) -> AdsList` under the plugin ABI.
+///
+/// ```ignore
+/// fn generate(context: &Context, version: u32, seed: u64) -> AdsList { ... }
+/// ads_plugin_api::export_generator!("my-generator", generate);
+/// ```
+#[macro_export]
+macro_rules! export_generator {
+    ($name:literal, $generate:path) => {
+        #[no_mangle]
+        pub extern "C" fn ads_plugin_abi_version() -> u32 {
+            $crate::ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "C" fn ads_plugin_name() -> *const ::std::os::raw::c_char {
+            concat!($name, "\0").as_ptr() as *const ::std::os::raw::c_char
+        }
+
+        /// # Safety
+        /// `context` must point to `context_len` readable bytes; `out` and `out_len` must be writable
+        #[no_mangle]
+        pub unsafe extern "C" fn ads_plugin_generate(
+            context: *const u8,
+            context_len: usize,
+            version: u32,
+            seed: u64,
+            out: *mut *mut u8,
+            out_len: *mut usize,
+        ) -> i32 {
+            use $crate::prost::Message;
+            let bytes = ::std::slice::from_raw_parts(context, context_len);
+            let context = match $crate::ads_proto::ads::Context::decode(bytes) {
+                Ok(context) => context,
+                Err(_) => return $crate::STATUS_DECODE_ERROR,
+            };
+            let result = ::std::panic::catch_unwind(|| $generate(&context, version, seed));
+            let ads_list: $crate::ads_proto::ads::AdsList = match result {
+                Ok(ads_list) => ads_list,
+                Err(_) => return $crate::STATUS_PANIC,
+            };
+            let encoded = ads_list.encode_to_vec().into_boxed_slice();
+            *out_len = encoded.len();
+            *out = ::std::boxed::Box::into_raw(encoded) as *mut u8;
+            $crate::STATUS_OK
+        }
+
+        /// # Safety
+        /// `ptr`/`len` must come from one `ads_plugin_generate` call and be freed once
+        #[no_mangle]
+        pub unsafe extern "C" fn ads_plugin_free(ptr: *mut u8, len: usize) {
+            if !ptr.is_null() {
+                drop(::std::boxed::Box::from_raw(::std::ptr::slice_from_raw_parts_mut(ptr, len)));
+            }
+        }
+    };
+}
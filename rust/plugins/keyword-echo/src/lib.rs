@@ -0,0 +1,55 @@
+//! Sample generator plugin: one ad per query token, so it is obvious from the
+//! results that the plugin (and not the built-in generator) served a session.
+//!
+//! Build with `cargo build -p keyword-echo-plugin` and start the server with
+//! `--generator-plugin target/debug/libkeyword_echo_plugin.so`.
+
+use ads_plugin_api::ads_proto::ads::{Ad, AdsList, Context, QueryAds};
+
+fn echo_ads(query: &str, asin_id: &str, version: u32, seed: u64) -> Vec<Ad> {
+    let tokens: Vec<&str> = query.split_whitespace().collect();
+    let mut ads: Vec<Ad> = tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| {
+            // Earlier tokens rank higher; later versions and the seed nudge scores so
+            // refinement is still visible, while staying within [0, 1]
+            let jitter = (seed.wrapping_add(i as u64) % 100) as f64 / 1000.0;
+            Ad {
+                ad_id: format!("echo-{}-v{}", token.to_lowercase(), version),
+                asin_id: asin_id.to_string(),
+                advertiser_id: "keyword-echo".to_string(),
+                category: "sponsored_products".to_string(),
+                score: (1.0 - i as f64 / (tokens.len() as f64 + 1.0)) * 0.85 + version as f64 * 0.01 + jitter,
+                ..Default::default()
+            }
+        })
+        .collect();
+    ads.sort_by(|a, b| b.score.total_cmp(&a.score));
+    ads
+}
+
+fn generate(context: &Context, version: u32, seed: u64) -> AdsList {
+    if !context.queries.is_empty() {
+        return AdsList {
+            version,
+            query_results: context
+                .queries
+                .iter()
+                .map(|query| QueryAds {
+                    query: query.clone(),
+                    ads: echo_ads(query, &context.asin_id, version, seed),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+    }
+    AdsList {
+        ads: echo_ads(&context.query, &context.asin_id, version, seed),
+        version,
+        ..Default::default()
+    }
+}
+
+ads_plugin_api::export_generator!("keyword-echo", generate);
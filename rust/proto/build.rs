@@ -0,0 +1,21 @@
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+    tonic_build::configure()
+        .build_transport(false)
+        // Served through gRPC reflection for schema-agnostic clients
+        .file_descriptor_set_path(out_dir.join("ads_descriptor.bin"))
+        .compile(
+            &["../../proto/ads.proto", "../../proto/admin.proto"],
+            &["../../proto"],
+        )?;
+    // tonic-build 0.10 always generates services with ProstCodec; swap in the codec
+    // that adds optional zstd dictionary compression, see src/codec.rs
+    for package in ["ads", "admin"] {
+        let path = out_dir.join(format!("{}.rs", package));
+        let generated = std::fs::read_to_string(&path)?;
+        std::fs::write(&path, generated.replace("tonic::codec::ProstCodec", "crate::codec::AdsCodec"))?;
+    }
+    Ok(())
+}
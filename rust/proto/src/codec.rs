@@ -0,0 +1,199 @@
+//! Message codec of the generated services: prost encoding, optionally compressed
+//! with zstd against a trained dictionary. Per-message zstd compresses every
+//! message on its own, which gains little on messages as small as a Context or an
+//! AdsList; a dictionary trained on recorded traffic (`ads-server
+//! train-dictionary`) supplies the repeated field names, ids and queries up front.
+//!
+//! A dictionary-compressed message is the byte 0x00 followed by a zstd frame. No
+//! protobuf message starts with 0x00 (it is not a valid field key), so plain and
+//! compressed messages can share a stream and a decoder accepts both. The
+//! dictionary is installed once per process with `install_dictionary`: the
+//! initiating side (the client) compresses every message it sends, the responding
+//! side (the server) compresses a call's messages only once the peer has sent it a
+//! compressed one, so peers without the dictionary keep getting plain protobuf.
+
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use prost::bytes::{Buf, BufMut};
+use prost::Message;
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::Status;
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+/// First byte of a dictionary-compressed message
+const DICTIONARY_MARKER: u8 = 0x00;
+
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Which side of a call a process is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DictionaryMode {
+    /// Compress every outgoing message
+    Initiate,
+    /// Compress a call's outgoing messages once the peer sent a compressed one
+    Respond,
+}
+
+struct Dictionary {
+    encoder: EncoderDictionary<'static>,
+    decoder: DecoderDictionary<'static>,
+    mode: DictionaryMode,
+}
+
+impl Dictionary {
+    fn compress(&self, raw: &[u8]) -> io::Result<Vec<u8>> {
+        let mut encoder = zstd::stream::write::Encoder::with_prepared_dictionary(Vec::new(), &self.encoder)?;
+        encoder.write_all(raw)?;
+        encoder.finish()
+    }
+
+    fn decompress(&self, compressed: &[u8]) -> io::Result<Vec<u8>> {
+        let mut raw = Vec::new();
+        zstd::stream::read::Decoder::with_prepared_dictionary(compressed, &self.decoder)?.read_to_end(&mut raw)?;
+        Ok(raw)
+    }
+}
+
+static DICTIONARY: OnceLock<Dictionary> = OnceLock::new();
+
+/// Compress messages of every call of this process with the zstd dictionary
+/// `bytes`; fails if a dictionary is already installed
+pub fn install_dictionary(bytes: &[u8], mode: DictionaryMode) -> io::Result<()> {
+    if bytes.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "zstd dictionary is empty"));
+    }
+    let dictionary = Dictionary {
+        encoder: EncoderDictionary::copy(bytes, COMPRESSION_LEVEL),
+        decoder: DecoderDictionary::copy(bytes),
+        mode,
+    };
+    DICTIONARY
+        .set(dictionary)
+        .map_err(|_| io::Error::new(io::ErrorKind::AlreadyExists, "a zstd dictionary is already installed"))
+}
+
+/// Sizes of the dictionary-compressed messages this process has sent
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WireStats {
+    pub messages: u64,
+    /// Their protobuf size
+    pub raw_bytes: u64,
+    /// Their size on the wire, marker included
+    pub wire_bytes: u64,
+}
+
+impl WireStats {
+    /// Wire size as a share of the protobuf size
+    pub fn ratio(&self) -> f64 {
+        if self.raw_bytes == 0 {
+            return 1.0;
+        }
+        self.wire_bytes as f64 / self.raw_bytes as f64
+    }
+}
+
+static MESSAGES: AtomicU64 = AtomicU64::new(0);
+static RAW_BYTES: AtomicU64 = AtomicU64::new(0);
+static WIRE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+pub fn wire_stats() -> WireStats {
+    WireStats {
+        messages: MESSAGES.load(Ordering::Relaxed),
+        raw_bytes: RAW_BYTES.load(Ordering::Relaxed),
+        wire_bytes: WIRE_BYTES.load(Ordering::Relaxed),
+    }
+}
+
+/// Codec of every generated client and server; the encoder and decoder of one
+/// call share whether the peer has sent a dictionary-compressed message
+#[derive(Debug)]
+pub struct AdsCodec<T, U> {
+    peer_uses_dictionary: Arc<AtomicBool>,
+    _pd: PhantomData<(T, U)>,
+}
+
+impl<T, U> Default for AdsCodec<T, U> {
+    fn default() -> Self {
+        AdsCodec { peer_uses_dictionary: Arc::new(AtomicBool::new(false)), _pd: PhantomData }
+    }
+}
+
+impl<T, U> Codec for AdsCodec<T, U>
+where
+    T: Message + Send + 'static,
+    U: Message + Default + Send + 'static,
+{
+    type Encode = T;
+    type Decode = U;
+    type Encoder = AdsEncoder<T>;
+    type Decoder = AdsDecoder<U>;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        AdsEncoder { peer_uses_dictionary: self.peer_uses_dictionary.clone(), _pd: PhantomData }
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        AdsDecoder { peer_uses_dictionary: self.peer_uses_dictionary.clone(), _pd: PhantomData }
+    }
+}
+
+#[derive(Debug)]
+pub struct AdsEncoder<T> {
+    peer_uses_dictionary: Arc<AtomicBool>,
+    _pd: PhantomData<T>,
+}
+
+impl<T: Message> Encoder for AdsEncoder<T> {
+    type Item = T;
+    type Error = Status;
+
+    fn encode(&mut self, item: T, buf: &mut EncodeBuf<'_>) -> Result<(), Status> {
+        let dictionary = DICTIONARY.get().filter(|dictionary| {
+            dictionary.mode == DictionaryMode::Initiate || self.peer_uses_dictionary.load(Ordering::Relaxed)
+        });
+        let Some(dictionary) = dictionary else {
+            item.encode(buf).expect("Message only errors if not enough space");
+            return Ok(());
+        };
+        let raw = item.encode_to_vec();
+        let compressed = dictionary
+            .compress(&raw)
+            .map_err(|e| Status::internal(format!("zstd dictionary compression: {}", e)))?;
+        buf.put_u8(DICTIONARY_MARKER);
+        buf.put_slice(&compressed);
+        MESSAGES.fetch_add(1, Ordering::Relaxed);
+        RAW_BYTES.fetch_add(raw.len() as u64, Ordering::Relaxed);
+        WIRE_BYTES.fetch_add(compressed.len() as u64 + 1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct AdsDecoder<U> {
+    peer_uses_dictionary: Arc<AtomicBool>,
+    _pd: PhantomData<U>,
+}
+
+impl<U: Message + Default> Decoder for AdsDecoder<U> {
+    type Item = U;
+    type Error = Status;
+
+    fn decode(&mut self, buf: &mut DecodeBuf<'_>) -> Result<Option<U>, Status> {
+        if !buf.has_remaining() || buf.chunk()[0] != DICTIONARY_MARKER {
+            return U::decode(buf).map(Some).map_err(|e| Status::internal(e.to_string()));
+        }
+        let dictionary = DICTIONARY.get().ok_or_else(|| {
+            Status::failed_precondition("received a dictionary-compressed message but no zstd dictionary is loaded")
+        })?;
+        buf.advance(1);
+        let compressed = buf.copy_to_bytes(buf.remaining());
+        let raw = dictionary
+            .decompress(&compressed)
+            .map_err(|e| Status::data_loss(format!("zstd dictionary decompression: {}", e)))?;
+        self.peer_uses_dictionary.store(true, Ordering::Relaxed);
+        U::decode(raw.as_slice()).map(Some).map_err(|e| Status::internal(e.to_string()))
+    }
+}
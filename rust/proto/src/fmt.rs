@@ -0,0 +1,139 @@
+//! Human-readable rendering of the ads messages.
+//!
+//! `Display` gives a compact single-line form for Context and Ad and an aligned
+//! table for AdsList (one section per query for batched lists); `PrettyPrint::pretty(true)` adds ANSI colors to the table.
+//! Lists re-ranked on the client get a WAS column with each ad's original rank.
+
+use std::fmt;
+
+use crate::ads::{Ad, AdsList, Context};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.queries.is_empty() {
+            write!(f, "query=\"{}\" asin_id={}", self.query, self.asin_id)?;
+        } else {
+            write!(f, "queries={:?} asin_id={}", self.queries, self.asin_id)?;
+        }
+        if self.understanding.is_empty() {
+            write!(f, " understanding=<empty>")?;
+        } else {
+            write!(f, " understanding=\"{}\"", self.understanding)?;
+        }
+        write!(f, " request_type={:?}", self.request_type())?;
+        if self.seed != 0 {
+            write!(f, " seed={}", self.seed)?;
+        }
+        if self.channel_id != 0 {
+            write!(f, " channel_id={}", self.channel_id)?;
+        }
+        if !self.placements.is_empty() {
+            let placements: Vec<&str> = self.placements().map(|placement| placement.slug()).collect();
+            write!(f, " placements={}", placements.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Ad {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "asin_id={}, ad_id={}, score={:.3}", self.asin_id, self.ad_id, self.score)
+    }
+}
+
+impl fmt::Display for AdsList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_table(f, self, false)
+    }
+}
+
+/// Wrapper rendering a message with optional ANSI colors
+pub struct Pretty<'a, T: ?Sized> {
+    value: &'a T,
+    color: bool,
+}
+
+pub trait PrettyPrint {
+    fn pretty(&self, color: bool) -> Pretty<'_, Self> {
+        Pretty { value: self, color }
+    }
+}
+
+impl PrettyPrint for AdsList {}
+
+impl fmt::Display for Pretty<'_, AdsList> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_table(f, self.value, self.color)
+    }
+}
+
+fn score_color(score: f64) -> &'static str {
+    if score >= 0.7 {
+        GREEN
+    } else if score >= 0.4 {
+        YELLOW
+    } else {
+        RED
+    }
+}
+
+fn write_table(f: &mut fmt::Formatter<'_>, list: &AdsList, color: bool) -> fmt::Result {
+    let (bold, reset) = if color { (BOLD, RESET) } else { ("", "") };
+    if !list.query_results.is_empty() {
+        write!(f, "{}AdsList v{} ({} queries){}", bold, list.version, list.query_results.len(), reset)?;
+    } else if !list.placement_results.is_empty() {
+        write!(f, "{}AdsList v{} ({} placements){}", bold, list.version, list.placement_results.len(), reset)?;
+    } else {
+        write!(f, "{}AdsList v{} ({} ads){}", bold, list.version, list.ads.len(), reset)?;
+    }
+    if list.channel_id != 0 {
+        write!(f, " channel_id={}", list.channel_id)?;
+    }
+    for placement in &list.placement_results {
+        write!(f, "\n{}placement={} ({} ads){}", bold, placement.placement().slug(), placement.ads.len(), reset)?;
+        write_rows(f, &placement.ads, &placement.original_ads, color)?;
+    }
+    if list.query_results.is_empty() && list.placement_results.is_empty() {
+        return write_rows(f, &list.ads, &list.original_ads, color);
+    }
+    for partition in &list.query_results {
+        write!(f, "\n{}query=\"{}\" ({} ads){}", bold, partition.query, partition.ads.len(), reset)?;
+        write_rows(f, &partition.ads, &partition.original_ads, color)?;
+    }
+    Ok(())
+}
+
+fn write_rows(f: &mut fmt::Formatter<'_>, ads: &[Ad], original: &[Ad], color: bool) -> fmt::Result {
+    let (bold, reset) = if color { (BOLD, RESET) } else { ("", "") };
+    let ad_id_width = ads.iter().map(|ad| ad.ad_id.len()).max().unwrap_or(0).max("AD_ID".len());
+    let asin_width = ads.iter().map(|ad| ad.asin_id.len()).max().unwrap_or(0).max("ASIN_ID".len());
+    write!(
+        f,
+        "\n{}{:>4}  {:<ad_id_width$}  {:<asin_width$}  {:>6}{}",
+        bold, "RANK", "AD_ID", "ASIN_ID", "SCORE", reset,
+    )?;
+    if !original.is_empty() {
+        write!(f, "{}  {:>3}{}", bold, "WAS", reset)?;
+    }
+    for (i, ad) in ads.iter().enumerate() {
+        let (score_on, score_off) = if color { (score_color(ad.score), RESET) } else { ("", "") };
+        write!(
+            f,
+            "\n{:>4}  {:<ad_id_width$}  {:<asin_width$}  {}{:>6.3}{}",
+            i + 1, ad.ad_id, ad.asin_id, score_on, ad.score, score_off,
+        )?;
+        if !original.is_empty() {
+            match original.iter().position(|o| o.ad_id == ad.ad_id) {
+                Some(was) => write!(f, "  {:>3}", was + 1)?,
+                None => write!(f, "  {:>3}", "-")?,
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Generated protobuf types for the ads and admin services, shared by the Rust client and server
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod codec;
+pub mod fmt;
+pub mod score;
+pub mod status;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+/// Browser builds have no dictionary support and use plain prost encoding
+#[cfg(target_arch = "wasm32")]
+pub mod codec {
+    pub type AdsCodec<T, U> = tonic::codec::ProstCodec<T, U>;
+}
+
+// Include the generated protobuf code
+pub mod ads {
+    tonic::include_proto!("ads");
+}
+
+pub mod admin {
+    tonic::include_proto!("admin");
+}
+
+/// Encoded FileDescriptorSet of ads.proto and admin.proto, registered with the
+/// server's reflection service
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("ads_descriptor");
+
+/// Request metadata carrying a client-generated id, logged on both sides so client
+/// and server logs of one session can be joined
+pub const REQUEST_ID_METADATA_KEY: &str = "x-request-id";
+
+/// Request metadata naming a logical session across client retries; a retry that
+/// arrives while the original session is still active can be attached to it
+pub const IDEMPOTENCY_KEY_METADATA_KEY: &str = "x-idempotency-key";
+
+/// Prefix of request metadata carrying client experiment labels, e.g.
+/// `x-label-scenario: cold-cache`; the server attaches them to the session's logs,
+/// metrics and journal entry
+pub const LABEL_METADATA_PREFIX: &str = "x-label-";
+
+/// Request metadata naming the generator variant a session asks for (`mock`,
+/// `catalog` or `embedding`); the server falls back to its default unless the
+/// variant is enabled
+pub const GENERATOR_METADATA_KEY: &str = "x-generator";
+
+/// Request metadata naming a session's priority class (`interactive` or `batch`);
+/// under load the server admits, schedules and keeps interactive sessions first
+pub const PRIORITY_METADATA_KEY: &str = "x-priority";
+
+/// Request metadata asking for DEBUG-level server logs of this one session
+/// (`true`); servers grant it up to a rate limit
+pub const DEBUG_SESSION_METADATA_KEY: &str = "x-debug-session";
+
+/// Request metadata asking the server to acknowledge every Context of a
+/// bidirectional session (`true`) with an `AckContext` control message
+pub const ACK_CONTEXTS_METADATA_KEY: &str = "x-ack-contexts";
+
+/// Metadata on the UNAVAILABLE status of a session refused during maintenance,
+/// naming the endpoint the client should use instead
+pub const REDIRECT_METADATA_KEY: &str = "x-redirect-endpoint";
+
+/// Response metadata of a bidirectional stream on a server checkpointing sessions:
+/// the token to resume the session with if the stream breaks
+pub const SESSION_TOKEN_METADATA_KEY: &str = "x-session-token";
+
+/// Request metadata carrying a session token, asking the server to resume that
+/// session from its checkpoints
+pub const RESUME_TOKEN_METADATA_KEY: &str = "x-resume-token";
+
+/// Trailers of a stream (response metadata of a unary call) naming the soft limits
+/// the server let it go over, e.g. `ads_per_list;observed=14;max=10;count=3`, one
+/// comma-separated entry per limit
+pub const LIMIT_WARNINGS_METADATA_KEY: &str = "x-limit-warnings";
+
+/// Trailers of a stream the server failed on purpose (an admin `FAIL` fault rule),
+/// naming the version it failed at; clients must not mistake it for a lost connection
+pub const INJECTED_FAULT_METADATA_KEY: &str = "x-injected-fault";
+
+/// W3C trace context request metadata, `00-<trace-id>-<parent-id>-<flags>`; its trace
+/// id is attached as an exemplar to the latency observations of the session
+pub const TRACEPARENT_METADATA_KEY: &str = "traceparent";
+
+/// Trace id of a `traceparent` value; None if it is malformed or the id is all zeros
+pub fn trace_id(traceparent: &str) -> Option<&str> {
+    let hex = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    let parts: Vec<&str> = traceparent.trim().split('-').collect();
+    match parts[..] {
+        [version, trace_id, parent_id, flags]
+            if hex(version, 2) && hex(trace_id, 32) && hex(parent_id, 16) && hex(flags, 2) =>
+        {
+            trace_id.bytes().any(|b| b != b'0').then_some(trace_id)
+        }
+        _ => None,
+    }
+}
+
+impl ads::Placement {
+    /// Short name used by CLI flags, logs and tables, e.g. `top-banner`
+    pub fn slug(&self) -> &'static str {
+        match self {
+            ads::Placement::Unspecified => "unspecified",
+            ads::Placement::TopBanner => "top-banner",
+            ads::Placement::Sidebar => "sidebar",
+            ads::Placement::Footer => "footer",
+        }
+    }
+
+    /// Number of ad slots the placement shows, as documented in ads.proto
+    pub fn slots(&self) -> usize {
+        match self {
+            ads::Placement::Unspecified => 0,
+            ads::Placement::TopBanner => 1,
+            ads::Placement::Sidebar => 3,
+            ads::Placement::Footer => 5,
+        }
+    }
+}
+
+impl ads::AdsList {
+    /// Bytes covered by `signature`: the list encoded with `signature` and the
+    /// client-side `original_ads` cleared. prost encodes fields in tag order, so
+    /// server and client derive the same bytes from the same list.
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        let mut unsigned = self.clone();
+        unsigned.signature.clear();
+        unsigned.original_ads.clear();
+        prost::Message::encode_to_vec(&unsigned)
+    }
+}
+
+/// Wall-clock Unix time in microseconds, as carried by handshakes and AdsList timestamps
+pub fn unix_us() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_micros() as u64)
+}
@@ -0,0 +1,337 @@
+//! Score normalization shared by the server (per AdsList) and the client
+//! (re-normalizing buffered versions before comparing them), score validation, and
+//! the ranking order of ads with equal scores.
+//!
+//! Raw scores are multiplied by a per-version factor, so a v3 score is not
+//! comparable with a v1 score until both lists are normalized the same way.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::ads::{Ad, AdsList, ScoreNormalization};
+
+/// A score that may go on the wire: finite and within [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Score(f64);
+
+/// Why a raw value is not a valid `Score`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidScore {
+    NaN,
+    Infinite,
+    OutOfRange,
+}
+
+impl InvalidScore {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InvalidScore::NaN => "nan",
+            InvalidScore::Infinite => "infinite",
+            InvalidScore::OutOfRange => "out_of_range",
+        }
+    }
+}
+
+impl Score {
+    pub const MIN: Score = Score(0.0);
+    pub const MAX: Score = Score(1.0);
+
+    pub fn new(value: f64) -> Result<Self, InvalidScore> {
+        if value.is_nan() {
+            Err(InvalidScore::NaN)
+        } else if value.is_infinite() {
+            Err(InvalidScore::Infinite)
+        } else if !(0.0..=1.0).contains(&value) {
+            Err(InvalidScore::OutOfRange)
+        } else {
+            Ok(Score(value))
+        }
+    }
+
+    /// The nearest valid score plus what was wrong with `value`, if anything:
+    /// out-of-range and infinite values clamp to the bounds, NaN falls back to `MIN`
+    pub fn clamped(value: f64) -> (Self, Option<InvalidScore>) {
+        match Score::new(value) {
+            Ok(score) => (score, None),
+            Err(InvalidScore::NaN) => (Score::MIN, Some(InvalidScore::NaN)),
+            Err(invalid) => (Score(value.clamp(0.0, 1.0)), Some(invalid)),
+        }
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl From<Score> for f64 {
+    fn from(score: Score) -> f64 {
+        score.0
+    }
+}
+
+/// Replace every invalid score in `list` (all partitions, including `original_ads`)
+/// with `Score::clamped`, returning the ad_id and problem of each replaced score.
+/// Ranking order is left as is.
+pub fn sanitize_list(list: &mut AdsList) -> Vec<(String, InvalidScore)> {
+    let mut issues = Vec::new();
+    let mut sanitize = |ads: &mut [Ad]| {
+        for ad in ads {
+            let (score, invalid) = Score::clamped(ad.score);
+            if let Some(invalid) = invalid {
+                ad.score = score.get();
+                issues.push((ad.ad_id.clone(), invalid));
+            }
+        }
+    };
+    sanitize(&mut list.ads);
+    sanitize(&mut list.original_ads);
+    for partition in &mut list.query_results {
+        sanitize(&mut partition.ads);
+        sanitize(&mut partition.original_ads);
+    }
+    for placement in &mut list.placement_results {
+        sanitize(&mut placement.ads);
+        sanitize(&mut placement.original_ads);
+    }
+    issues
+}
+
+/// Order of ads with equal scores. Every policy is total, so a ranking never depends
+/// on input order, hash map iteration or sort stability.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// Ascending ad_id
+    #[default]
+    AdId,
+    /// Most recent first, as defined by the caller's recency key (e.g. catalog
+    /// revision on the server, AdsList version on the client), then ascending ad_id
+    Recency,
+    /// Pseudo-random order derived from the seed and ad_id: shuffled between seeds,
+    /// identical across runs (and implementations) with the same seed
+    Seeded,
+}
+
+/// splitmix64 finalizer over FNV-1a of the ad_id, so the order is portable across
+/// languages and Rust versions (unlike std's hashers)
+fn seeded_key(seed: u64, ad_id: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in ad_id.bytes() {
+        hash = (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    let mut z = hash ^ seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Ranking order: descending score, then `tie_break`. `recency` is only consulted
+/// by `TieBreak::Recency` (higher = more recent).
+pub fn compare_ads(a: &Ad, b: &Ad, tie_break: TieBreak, seed: u64, recency: impl Fn(&Ad) -> u64) -> Ordering {
+    b.score
+        .total_cmp(&a.score)
+        .then_with(|| match tie_break {
+            TieBreak::AdId => Ordering::Equal,
+            TieBreak::Recency => recency(b).cmp(&recency(a)),
+            TieBreak::Seeded => seeded_key(seed, &a.ad_id).cmp(&seeded_key(seed, &b.ad_id)),
+        })
+        .then_with(|| a.ad_id.cmp(&b.ad_id))
+}
+
+/// Sort one ranked list in place by `compare_ads`
+pub fn sort_ads(ads: &mut [Ad], tie_break: TieBreak, seed: u64, recency: impl Fn(&Ad) -> u64) {
+    ads.sort_by(|a, b| compare_ads(a, b, tie_break, seed, &recency));
+}
+
+/// The `k` best of `ads` in `compare_ads` order, the same as `sort_ads` followed by
+/// a truncation to `k`, in O(n log k) instead of O(n log n): a bounded heap keeps
+/// the best `k` seen so far with the worst of them on top. For large candidate sets
+/// where only the first slots are shown.
+pub fn top_k_ads(ads: Vec<Ad>, k: usize, tie_break: TieBreak, seed: u64, recency: impl Fn(&Ad) -> u64) -> Vec<Ad> {
+    if k == 0 {
+        return Vec::new();
+    }
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for ad in ads {
+        // Everything compare_ads looks at, so heap order matches it exactly
+        let tie = match tie_break {
+            TieBreak::AdId => 0,
+            TieBreak::Recency => u64::MAX - recency(&ad),
+            TieBreak::Seeded => seeded_key(seed, &ad.ad_id),
+        };
+        heap.push(Ranked { tie, ad });
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec().into_iter().map(|ranked| ranked.ad).collect()
+}
+
+/// Union of the ads of several versions of one list, ranked by `compare_ads`. An
+/// ad_id seen in several versions keeps its copy from the latest of them, the best
+/// estimate of that ad; with `TieBreak::Recency` the later version counts as more
+/// recent. Ad ids must not depend on the version for this to recognize an ad.
+pub fn merge_ads<'a>(versions: impl Iterator<Item = (u32, &'a [Ad])>, tie_break: TieBreak, seed: u64) -> Vec<Ad> {
+    let mut latest: HashMap<&str, (u32, &Ad)> = HashMap::new();
+    for (version, ads) in versions {
+        for ad in ads {
+            let current = latest.entry(ad.ad_id.as_str()).or_insert((version, ad));
+            if version >= current.0 {
+                *current = (version, ad);
+            }
+        }
+    }
+    let recency: HashMap<&str, u32> = latest.iter().map(|(ad_id, (version, _))| (*ad_id, *version)).collect();
+    let mut ads: Vec<Ad> = latest.values().map(|(_, ad)| (*ad).clone()).collect();
+    sort_ads(&mut ads, tie_break, seed, |ad| recency.get(ad.ad_id.as_str()).copied().unwrap_or(0) as u64);
+    ads
+}
+
+// An ad with its precomputed tie-break key; `Less` ranks first
+struct Ranked {
+    tie: u64,
+    ad: Ad,
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .ad
+            .score
+            .total_cmp(&self.ad.score)
+            .then_with(|| self.tie.cmp(&other.tie))
+            .then_with(|| self.ad.ad_id.cmp(&other.ad.ad_id))
+    }
+}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+/// Normalize the scores of one ranked list in place; ranking order is unchanged
+pub fn normalize_ads(ads: &mut [Ad], method: ScoreNormalization) {
+    if ads.is_empty() {
+        return;
+    }
+    match method {
+        ScoreNormalization::None => {}
+        ScoreNormalization::MinMax => {
+            let min = ads.iter().map(|ad| ad.score).fold(f64::INFINITY, f64::min);
+            let max = ads.iter().map(|ad| ad.score).fold(f64::NEG_INFINITY, f64::max);
+            let range = max - min;
+            for ad in ads.iter_mut() {
+                ad.score = if range > 0.0 { (ad.score - min) / range } else { 1.0 };
+            }
+        }
+        ScoreNormalization::Softmax => {
+            let max = ads.iter().map(|ad| ad.score).fold(f64::NEG_INFINITY, f64::max);
+            let sum: f64 = ads.iter().map(|ad| (ad.score - max).exp()).sum();
+            for ad in ads.iter_mut() {
+                ad.score = (ad.score - max).exp() / sum;
+            }
+        }
+    }
+}
+
+/// Normalize every list of an AdsList (each partition or placement separately) and
+/// record the method. Lists already carrying `method` are left alone, since softmax
+/// is not idempotent; `None` never undoes a normalization.
+pub fn normalize_list(ads_list: &mut AdsList, method: ScoreNormalization) {
+    if method == ScoreNormalization::None || ads_list.normalization() == method {
+        return;
+    }
+    normalize_ads(&mut ads_list.ads, method);
+    for partition in &mut ads_list.query_results {
+        normalize_ads(&mut partition.ads, method);
+    }
+    for placement in &mut ads_list.placement_results {
+        normalize_ads(&mut placement.ads, method);
+    }
+    ads_list.set_normalization(method);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{AdFixture, AdsListFixture};
+
+    const TIE_BREAKS: [TieBreak; 3] = [TieBreak::AdId, TieBreak::Recency, TieBreak::Seeded];
+
+    fn tied(n: usize) -> Vec<Ad> {
+        (0..n).map(|i| AdFixture::new().id(format!("tie_{}", i)).score(0.5).build()).collect()
+    }
+
+    fn ids(ads: &[Ad]) -> Vec<&str> {
+        ads.iter().map(|ad| ad.ad_id.as_str()).collect()
+    }
+
+    // Stand-in for catalog revisions: the trailing digit of the ad_id
+    fn recency(ad: &Ad) -> u64 {
+        ad.ad_id.bytes().last().map_or(0, |b| (b - b'0') as u64)
+    }
+
+    #[test]
+    fn tied_ads_rank_the_same_whatever_the_input_order() {
+        for tie_break in TIE_BREAKS {
+            let mut forward = tied(8);
+            let mut reverse: Vec<Ad> = tied(8).into_iter().rev().collect();
+            sort_ads(&mut forward, tie_break, 42, recency);
+            sort_ads(&mut reverse, tie_break, 42, recency);
+            assert_eq!(forward, reverse, "{:?}", tie_break);
+        }
+    }
+
+    #[test]
+    fn ad_id_tie_break_is_ascending() {
+        let mut ads: Vec<Ad> = tied(4).into_iter().rev().collect();
+        sort_ads(&mut ads, TieBreak::AdId, 42, recency);
+        assert_eq!(ids(&ads), ["tie_0", "tie_1", "tie_2", "tie_3"]);
+    }
+
+    #[test]
+    fn recency_tie_break_puts_most_recent_first() {
+        let mut ads = tied(4);
+        sort_ads(&mut ads, TieBreak::Recency, 42, recency);
+        assert_eq!(ids(&ads), ["tie_3", "tie_2", "tie_1", "tie_0"]);
+    }
+
+    #[test]
+    fn seeded_tie_break_shuffles_between_seeds_only() {
+        let sorted = |seed| {
+            let mut ads = tied(16);
+            sort_ads(&mut ads, TieBreak::Seeded, seed, recency);
+            ads
+        };
+        assert_eq!(sorted(42), sorted(42));
+        assert_ne!(sorted(42), sorted(43));
+    }
+
+    #[test]
+    fn score_decides_before_the_tie_break() {
+        let mut ads = tied(3);
+        ads[2].score = 0.9;
+        for tie_break in TIE_BREAKS {
+            let mut ranked = ads.clone();
+            sort_ads(&mut ranked, tie_break, 42, recency);
+            assert_eq!(ranked[0].ad_id, "tie_2", "{:?}", tie_break);
+        }
+    }
+
+    #[test]
+    fn merge_keeps_the_latest_copy_of_each_ad() {
+        let v1 = AdsListFixture::with_n_ads(3).build();
+        let v2 = AdsListFixture::new().version(2).ad(AdFixture::new().id("ad_3").score(0.95)).build();
+        let merged = merge_ads([(1, v1.ads.as_slice()), (2, v2.ads.as_slice())].into_iter(), TieBreak::AdId, 42);
+        assert_eq!(ids(&merged), ["ad_1", "ad_3", "ad_2"]);
+        assert_eq!(merged[1].score, 0.95);
+    }
+}
@@ -0,0 +1,91 @@
+//! Status taxonomy shared by the server and client: which session failures are
+//! worth retrying, and the backpressure hints a server attaches to the status of a
+//! session it sheds, so clients wait as long as the server asks instead of backing
+//! off blindly.
+
+use std::time::Duration;
+
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Status};
+
+/// Metadata on the status of a shed session: milliseconds the client should wait
+/// before retrying
+pub const RETRY_AFTER_METADATA_KEY: &str = "x-retry-after-ms";
+
+/// Metadata on the status of a shed session: sessions the server was handling when
+/// it refused this one
+pub const QUEUE_DEPTH_METADATA_KEY: &str = "x-queue-depth";
+
+/// How a session's failure status should be treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusClass {
+    /// The server shed the session (RESOURCE_EXHAUSTED); retry after its hint
+    Backpressure,
+    /// The server or the connection is unavailable (UNAVAILABLE)
+    Unavailable,
+    /// A server-side failure that may not repeat (INTERNAL, UNKNOWN)
+    Transient,
+    /// The same request would fail the same way
+    Permanent,
+}
+
+impl StatusClass {
+    pub fn of(status: &Status) -> Self {
+        match status.code() {
+            Code::ResourceExhausted => StatusClass::Backpressure,
+            Code::Unavailable => StatusClass::Unavailable,
+            Code::Internal | Code::Unknown => StatusClass::Transient,
+            _ => StatusClass::Permanent,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatusClass::Backpressure => "backpressure",
+            StatusClass::Unavailable => "unavailable",
+            StatusClass::Transient => "transient",
+            StatusClass::Permanent => "permanent",
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        *self != StatusClass::Permanent
+    }
+}
+
+/// Machine-readable backpressure hints carried as status metadata
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BackpressureHint {
+    pub retry_after: Option<Duration>,
+    pub queue_depth: Option<u64>,
+}
+
+impl BackpressureHint {
+    /// Hints of a status; None if it carries neither
+    pub fn from_status(status: &Status) -> Option<Self> {
+        let value = |key: &str| {
+            status.metadata().get(key).and_then(|value| value.to_str().ok()).and_then(|value| value.parse::<u64>().ok())
+        };
+        let hint = BackpressureHint {
+            retry_after: value(RETRY_AFTER_METADATA_KEY).map(Duration::from_millis),
+            queue_depth: value(QUEUE_DEPTH_METADATA_KEY),
+        };
+        (hint != BackpressureHint::default()).then_some(hint)
+    }
+
+    pub fn to_metadata(&self) -> MetadataMap {
+        let mut metadata = MetadataMap::new();
+        if let Some(retry_after) = self.retry_after {
+            metadata.insert(RETRY_AFTER_METADATA_KEY, (retry_after.as_millis() as u64).into());
+        }
+        if let Some(queue_depth) = self.queue_depth {
+            metadata.insert(QUEUE_DEPTH_METADATA_KEY, queue_depth.into());
+        }
+        metadata
+    }
+}
+
+/// RESOURCE_EXHAUSTED status of a shed session, carrying `hint`
+pub fn shed(message: impl Into<String>, hint: BackpressureHint) -> Status {
+    Status::with_metadata(Code::ResourceExhausted, message, hint.to_metadata())
+}
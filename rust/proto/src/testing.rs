@@ -0,0 +1,240 @@
+//! Fixture builders for the ads messages, for this crate's tests and, through the
+//! `testing` feature, for other crates' tests and mocks. They build Ads, AdsLists
+//! and Contexts from defaults plus the fields a test cares about instead of spelling
+//! out whole message literals:
+//!
+//! ```ignore
+//! let ad = AdFixture::new().score(0.9).advertiser("adv_2").build();
+//! let list = AdsListFixture::with_n_ads(7).version(3).build();
+//! let context = ContextFixture::new().understanding("espresso").build();
+//! ```
+
+use crate::ads::{Ad, AdsList, Context, Placement, RequestType, ScoreNormalization};
+
+/// ASIN every fixture uses unless told otherwise
+pub const FIXTURE_ASIN: &str = "B000123";
+
+/// Query of `ContextFixture` unless told otherwise
+pub const FIXTURE_QUERY: &str = "coffee maker";
+
+/// Builder of an `Ad`; defaults to `ad_1` of `FIXTURE_ASIN`, score 0.5, `adv_1`,
+/// `sponsored_products`
+#[derive(Debug, Clone)]
+pub struct AdFixture {
+    ad: Ad,
+}
+
+impl Default for AdFixture {
+    fn default() -> Self {
+        AdFixture::new()
+    }
+}
+
+impl AdFixture {
+    pub fn new() -> Self {
+        AdFixture {
+            ad: Ad {
+                asin_id: FIXTURE_ASIN.to_string(),
+                ad_id: "ad_1".to_string(),
+                score: 0.5,
+                advertiser_id: "adv_1".to_string(),
+                category: "sponsored_products".to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn asin(mut self, asin_id: impl Into<String>) -> Self {
+        self.ad.asin_id = asin_id.into();
+        self
+    }
+
+    pub fn id(mut self, ad_id: impl Into<String>) -> Self {
+        self.ad.ad_id = ad_id.into();
+        self
+    }
+
+    pub fn score(mut self, score: f64) -> Self {
+        self.ad.score = score;
+        self
+    }
+
+    pub fn advertiser(mut self, advertiser_id: impl Into<String>) -> Self {
+        self.ad.advertiser_id = advertiser_id.into();
+        self
+    }
+
+    pub fn category(mut self, category: impl Into<String>) -> Self {
+        self.ad.category = category.into();
+        self
+    }
+
+    pub fn build(self) -> Ad {
+        self.ad
+    }
+}
+
+impl From<AdFixture> for Ad {
+    fn from(fixture: AdFixture) -> Self {
+        fixture.build()
+    }
+}
+
+/// Builder of an `AdsList`; defaults to version 1 of channel 0 without ads
+#[derive(Debug, Clone)]
+pub struct AdsListFixture {
+    list: AdsList,
+}
+
+impl Default for AdsListFixture {
+    fn default() -> Self {
+        AdsListFixture::new()
+    }
+}
+
+impl AdsListFixture {
+    pub fn new() -> Self {
+        AdsListFixture { list: AdsList { version: 1, ..Default::default() } }
+    }
+
+    /// `n` ads `ad_1`..`ad_n` ranked by descending score, spread evenly over (0, 1],
+    /// with advertisers cycling through `adv_1`..`adv_4`
+    pub fn with_n_ads(n: usize) -> Self {
+        let ads = (0..n)
+            .map(|i| {
+                AdFixture::new()
+                    .id(format!("ad_{}", i + 1))
+                    .score((n - i) as f64 / n as f64)
+                    .advertiser(format!("adv_{}", i % 4 + 1))
+                    .build()
+            })
+            .collect();
+        AdsListFixture::new().ads(ads)
+    }
+
+    pub fn version(mut self, version: u32) -> Self {
+        self.list.version = version;
+        self
+    }
+
+    pub fn channel(mut self, channel_id: u32) -> Self {
+        self.list.channel_id = channel_id;
+        self
+    }
+
+    pub fn ads(mut self, ads: Vec<Ad>) -> Self {
+        self.list.ads = ads;
+        self
+    }
+
+    /// Append one ad
+    pub fn ad(mut self, ad: impl Into<Ad>) -> Self {
+        self.list.ads.push(ad.into());
+        self
+    }
+
+    pub fn normalization(mut self, normalization: ScoreNormalization) -> Self {
+        self.list.set_normalization(normalization);
+        self
+    }
+
+    pub fn sent_at(mut self, server_sent_unix_us: u64) -> Self {
+        self.list.server_sent_unix_us = server_sent_unix_us;
+        self
+    }
+
+    pub fn build(self) -> AdsList {
+        self.list
+    }
+}
+
+impl From<AdsListFixture> for AdsList {
+    fn from(fixture: AdsListFixture) -> Self {
+        fixture.build()
+    }
+}
+
+/// Builder of a `Context`; defaults to the first Context of a session for
+/// `FIXTURE_QUERY` on `FIXTURE_ASIN`
+#[derive(Debug, Clone)]
+pub struct ContextFixture {
+    context: Context,
+}
+
+impl Default for ContextFixture {
+    fn default() -> Self {
+        ContextFixture::new()
+    }
+}
+
+impl ContextFixture {
+    pub fn new() -> Self {
+        ContextFixture {
+            context: Context {
+                query: FIXTURE_QUERY.to_string(),
+                asin_id: FIXTURE_ASIN.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn query(mut self, query: impl Into<String>) -> Self {
+        self.context.query = query.into();
+        self
+    }
+
+    pub fn asin(mut self, asin_id: impl Into<String>) -> Self {
+        self.context.asin_id = asin_id.into();
+        self
+    }
+
+    pub fn understanding(mut self, understanding: impl Into<String>) -> Self {
+        self.context.understanding = understanding.into();
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.context.seed = seed;
+        self
+    }
+
+    pub fn request_type(mut self, request_type: RequestType) -> Self {
+        self.context.set_request_type(request_type);
+        self
+    }
+
+    pub fn channel(mut self, channel_id: u32) -> Self {
+        self.context.channel_id = channel_id;
+        self
+    }
+
+    pub fn queries<I, S>(mut self, queries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.context.queries = queries.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn latency_budget_ms(mut self, latency_budget_ms: u32) -> Self {
+        self.context.latency_budget_ms = latency_budget_ms;
+        self
+    }
+
+    /// Append one placement
+    pub fn placement(mut self, placement: Placement) -> Self {
+        self.context.placements.push(placement as i32);
+        self
+    }
+
+    pub fn build(self) -> Context {
+        self.context
+    }
+}
+
+impl From<ContextFixture> for Context {
+    fn from(fixture: ContextFixture) -> Self {
+        fixture.build()
+    }
+}
@@ -0,0 +1,242 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::metadata::AsciiMetadataValue;
+use tonic::{Request, Response, Status};
+
+use crate::catalog::{self, Catalog};
+use crate::faults::{self, FaultInjector};
+use crate::maintenance::Maintenance;
+use crate::profiling::Profiler;
+use crate::runtime_config::ConfigStore;
+use ads_proto::admin::{
+    admin_service_server::AdminService, AddFaultRuleRequest, AddFaultRuleResponse, CaptureProfileRequest,
+    CaptureProfileResponse, CatalogEntry,
+    ConfigChange, ConfigReload, CreateCatalogEntryRequest, CreateCatalogEntryResponse,
+    DeleteCatalogEntryRequest, DeleteCatalogEntryResponse, FaultAction, FaultRule,
+    GetConfigAuditRequest, GetConfigAuditResponse, GetMaintenanceRequest, ListCatalogEntriesRequest,
+    ListCatalogEntriesResponse, ListFaultRulesRequest, ListFaultRulesResponse, MaintenanceStatus,
+    RemoveFaultRuleRequest, RemoveFaultRuleResponse, SetMaintenanceRequest, UpdateCatalogEntryRequest,
+    UpdateCatalogEntryResponse,
+};
+
+/// Runtime administration RPCs, served on the same port as the ads service
+#[derive(Debug)]
+pub struct AdminServiceImpl {
+    config_store: Arc<ConfigStore>,
+    faults: Arc<FaultInjector>,
+    catalog: Arc<Catalog>,
+    maintenance: Arc<Maintenance>,
+    active_sessions: Arc<AtomicUsize>,
+    profiler: Profiler,
+}
+
+impl AdminServiceImpl {
+    pub fn new(
+        config_store: Arc<ConfigStore>,
+        faults: Arc<FaultInjector>,
+        catalog: Arc<Catalog>,
+        maintenance: Arc<Maintenance>,
+        active_sessions: Arc<AtomicUsize>,
+    ) -> Self {
+        AdminServiceImpl { config_store, faults, catalog, maintenance, active_sessions, profiler: Profiler::default() }
+    }
+
+    fn maintenance_status(&self) -> MaintenanceStatus {
+        let active_sessions = self.active_sessions.load(Ordering::SeqCst) as u64;
+        match self.maintenance.status() {
+            Some((enabled_for, redirect_endpoint, refused_sessions)) => MaintenanceStatus {
+                enabled: true,
+                redirect_endpoint,
+                active_sessions,
+                enabled_for_ms: enabled_for.as_millis().max(1) as u64,
+                refused_sessions,
+            },
+            None => MaintenanceStatus { active_sessions, ..Default::default() },
+        }
+    }
+}
+
+fn catalog_entry_to_proto(entry: catalog::CatalogEntry) -> CatalogEntry {
+    CatalogEntry {
+        ad_id: entry.ad_id,
+        asin_id: entry.asin_id,
+        advertiser_id: entry.advertiser_id,
+        category: entry.category,
+        boost: entry.boost,
+        revision: entry.revision,
+    }
+}
+
+fn catalog_entry_from_proto(entry: Option<CatalogEntry>) -> Result<catalog::CatalogEntry, Status> {
+    let entry = entry.ok_or_else(|| Status::invalid_argument("entry is required"))?;
+    Ok(catalog::CatalogEntry {
+        ad_id: entry.ad_id,
+        asin_id: entry.asin_id,
+        advertiser_id: entry.advertiser_id,
+        category: entry.category,
+        boost: entry.boost,
+        revision: 0,
+        embedding: None,
+    })
+}
+
+fn fault_rule_to_proto(rule: &faults::FaultRule) -> FaultRule {
+    let (action, delay_ms) = match rule.action {
+        faults::FaultAction::Fail => (FaultAction::Fail, 0),
+        faults::FaultAction::Delay(d) => (FaultAction::Delay, d.as_millis() as u32),
+        faults::FaultAction::Tamper => (FaultAction::Tamper, 0),
+    };
+    FaultRule {
+        id: rule.id,
+        version: rule.version,
+        action: action as i32,
+        probability: rule.probability,
+        delay_ms,
+        remaining_ms: rule.remaining().map_or(0, |d| d.as_millis().max(1) as u64),
+    }
+}
+
+#[tonic::async_trait]
+impl AdminService for AdminServiceImpl {
+    async fn get_config_audit(
+        &self,
+        _request: Request<GetConfigAuditRequest>,
+    ) -> Result<Response<GetConfigAuditResponse>, Status> {
+        let reloads = self
+            .config_store
+            .audit_trail()
+            .into_iter()
+            .map(|record| ConfigReload {
+                timestamp_ms: record.timestamp_ms,
+                source: record.source,
+                changes: record
+                    .changes
+                    .into_iter()
+                    .map(|c| ConfigChange {
+                        key: c.key,
+                        old_value: c.old_value,
+                        new_value: c.new_value,
+                    })
+                    .collect(),
+            })
+            .collect();
+        let current_config_json = serde_json::to_string(&self.config_store.current())
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(GetConfigAuditResponse {
+            reloads,
+            current_config_json,
+        }))
+    }
+
+    async fn add_fault_rule(
+        &self,
+        request: Request<AddFaultRuleRequest>,
+    ) -> Result<Response<AddFaultRuleResponse>, Status> {
+        let request = request.into_inner();
+        if !(0.0..=1.0).contains(&request.probability) {
+            return Err(Status::invalid_argument("probability must be between 0.0 and 1.0"));
+        }
+        let action = match request.action() {
+            FaultAction::Fail => faults::FaultAction::Fail,
+            FaultAction::Delay => faults::FaultAction::Delay(Duration::from_millis(request.delay_ms as u64)),
+            FaultAction::Tamper => faults::FaultAction::Tamper,
+        };
+        let duration = (request.duration_ms > 0).then(|| Duration::from_millis(request.duration_ms));
+        let rule = self.faults.add(request.version, action, request.probability, duration);
+        Ok(Response::new(AddFaultRuleResponse {
+            rule: Some(fault_rule_to_proto(&rule)),
+        }))
+    }
+
+    async fn remove_fault_rule(
+        &self,
+        request: Request<RemoveFaultRuleRequest>,
+    ) -> Result<Response<RemoveFaultRuleResponse>, Status> {
+        let removed = self.faults.remove(request.into_inner().id);
+        Ok(Response::new(RemoveFaultRuleResponse { removed }))
+    }
+
+    async fn list_fault_rules(
+        &self,
+        _request: Request<ListFaultRulesRequest>,
+    ) -> Result<Response<ListFaultRulesResponse>, Status> {
+        let rules = self.faults.list().iter().map(fault_rule_to_proto).collect();
+        Ok(Response::new(ListFaultRulesResponse { rules }))
+    }
+
+    async fn create_catalog_entry(
+        &self,
+        request: Request<CreateCatalogEntryRequest>,
+    ) -> Result<Response<CreateCatalogEntryResponse>, Status> {
+        let entry = catalog_entry_from_proto(request.into_inner().entry)?;
+        let entry = self.catalog.create(entry)?;
+        Ok(Response::new(CreateCatalogEntryResponse {
+            entry: Some(catalog_entry_to_proto(entry)),
+        }))
+    }
+
+    async fn update_catalog_entry(
+        &self,
+        request: Request<UpdateCatalogEntryRequest>,
+    ) -> Result<Response<UpdateCatalogEntryResponse>, Status> {
+        let request = request.into_inner();
+        let entry = catalog_entry_from_proto(request.entry)?;
+        let entry = self.catalog.update(entry, request.expected_revision)?;
+        Ok(Response::new(UpdateCatalogEntryResponse {
+            entry: Some(catalog_entry_to_proto(entry)),
+        }))
+    }
+
+    async fn delete_catalog_entry(
+        &self,
+        request: Request<DeleteCatalogEntryRequest>,
+    ) -> Result<Response<DeleteCatalogEntryResponse>, Status> {
+        let request = request.into_inner();
+        self.catalog.delete(&request.ad_id, request.expected_revision)?;
+        Ok(Response::new(DeleteCatalogEntryResponse {}))
+    }
+
+    async fn list_catalog_entries(
+        &self,
+        _request: Request<ListCatalogEntriesRequest>,
+    ) -> Result<Response<ListCatalogEntriesResponse>, Status> {
+        let entries = self.catalog.list().into_iter().map(catalog_entry_to_proto).collect();
+        Ok(Response::new(ListCatalogEntriesResponse { entries }))
+    }
+
+    async fn set_maintenance(
+        &self,
+        request: Request<SetMaintenanceRequest>,
+    ) -> Result<Response<MaintenanceStatus>, Status> {
+        let request = request.into_inner();
+        if request.redirect_endpoint.parse::<AsciiMetadataValue>().is_err() {
+            return Err(Status::invalid_argument("redirect_endpoint must be printable ASCII"));
+        }
+        self.maintenance.set(request.enabled, request.redirect_endpoint);
+        Ok(Response::new(self.maintenance_status()))
+    }
+
+    async fn get_maintenance(
+        &self,
+        _request: Request<GetMaintenanceRequest>,
+    ) -> Result<Response<MaintenanceStatus>, Status> {
+        Ok(Response::new(self.maintenance_status()))
+    }
+
+    async fn capture_profile(
+        &self,
+        request: Request<CaptureProfileRequest>,
+    ) -> Result<Response<CaptureProfileResponse>, Status> {
+        let request = request.into_inner();
+        let profile = self
+            .profiler
+            .capture(Duration::from_millis(request.duration_ms as u64), request.frequency_hz)
+            .await?;
+        Ok(Response::new(CaptureProfileResponse {
+            flamegraph_svg: profile.flamegraph_svg,
+            samples: profile.samples,
+            duration_ms: profile.duration.as_millis() as u32,
+        }))
+    }
+}
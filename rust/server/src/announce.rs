@@ -0,0 +1,30 @@
+use std::fs;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+
+use tracing::info;
+
+/// Environment-style key printed on stdout once the listener is bound
+pub const PORT_ANNOUNCEMENT_KEY: &str = "ADS_SERVER_PORT";
+
+/// Publish the port the server actually bound (the kernel's choice when started
+/// with port 0) so harnesses running many instances can discover it.
+///
+/// A single `ADS_SERVER_PORT=<port>` line goes to stdout (logs go to stderr, so
+/// `eval`/`grep` on stdout is safe), and with `port_file` the bare port number is
+/// written there. The file is written to a temporary name and renamed, so a
+/// watcher never reads a partial value.
+pub fn announce_port(addr: SocketAddr, port_file: Option<&Path>) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}={}", PORT_ANNOUNCEMENT_KEY, addr.port())?;
+    stdout.flush()?;
+
+    if let Some(path) = port_file {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, format!("{}\n", addr.port()))?;
+        fs::rename(&tmp, path)?;
+        info!(path = %path.display(), port = addr.port(), "Wrote port file");
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use clap::ValueEnum;
+use tokio::sync::mpsc::{self, error::SendError, error::TrySendError};
+use tokio_stream::Stream;
+use tonic::Status;
+use tracing::warn;
+
+use crate::ads::AdsList;
+use crate::containment::guard_scores;
+use crate::faults::FaultInjector;
+use crate::metrics::Metrics;
+use crate::signing::ResponseSigner;
+
+pub type AdsItem = Result<AdsList, Status>;
+
+/// What a session does when its output channel to the client is full
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for the client to drain the channel (backpressure reaches generation)
+    Block,
+    /// Discard the oldest queued message to make room; the client sees a gap
+    DropOldest,
+    /// End the session with RESOURCE_EXHAUSTED once queued messages are delivered
+    Error,
+}
+
+impl OverflowPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OverflowPolicy::Block => "block",
+            OverflowPolicy::DropOldest => "drop_oldest",
+            OverflowPolicy::Error => "error",
+        }
+    }
+}
+
+/// Sending half of a session's output channel; every send applies the overflow policy
+#[derive(Debug, Clone)]
+pub struct AdsSender {
+    tx: mpsc::Sender<AdsItem>,
+    // Shared with the receiving stream so drop-oldest can evict from the sender side
+    rx: Arc<Mutex<mpsc::Receiver<AdsItem>>>,
+    policy: OverflowPolicy,
+    metrics: Arc<Metrics>,
+    signer: Option<Arc<ResponseSigner>>,
+    faults: Arc<FaultInjector>,
+}
+
+/// Receiving half of a session's output channel, streamed to the client
+#[derive(Debug)]
+pub struct AdsReceiver {
+    rx: Arc<Mutex<mpsc::Receiver<AdsItem>>>,
+}
+
+pub fn channel(
+    capacity: usize,
+    policy: OverflowPolicy,
+    metrics: Arc<Metrics>,
+    signer: Option<Arc<ResponseSigner>>,
+    faults: Arc<FaultInjector>,
+) -> (AdsSender, AdsReceiver) {
+    let (tx, rx) = mpsc::channel(capacity.max(1));
+    let rx = Arc::new(Mutex::new(rx));
+    (AdsSender { tx, rx: rx.clone(), policy, metrics, signer, faults }, AdsReceiver { rx })
+}
+
+impl AdsSender {
+    /// Whether the client's end of the stream is gone
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// Resolves once the client's end of the stream is gone
+    pub async fn closed(&self) {
+        self.tx.closed().await
+    }
+
+    /// Queue `item` for the client under the overflow policy. This is the last stop
+    /// before the wire for every streaming RPC, so scores are guarded, the send time
+    /// is stamped and the list is signed here. Tamper faults run after signing.
+    pub async fn send(&self, mut item: AdsItem) -> Result<(), SendError<AdsItem>> {
+        if let Ok(ads_list) = &mut item {
+            guard_scores(ads_list, "send", &self.metrics);
+            ads_list.server_sent_unix_us = ads_proto::unix_us();
+            if let Some(signer) = &self.signer {
+                signer.sign(ads_list);
+            }
+            self.faults.tamper(ads_list);
+        }
+        let item = match self.tx.try_send(item) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Closed(item)) => return Err(SendError(item)),
+            Err(TrySendError::Full(item)) => item,
+        };
+        self.metrics.inc("output_channel_overflows_total", &[("policy", self.policy.name())]);
+        match self.policy {
+            OverflowPolicy::Block => self.tx.send(item).await,
+            OverflowPolicy::DropOldest => {
+                let mut item = item;
+                loop {
+                    if let Ok(dropped) = self.rx.lock().unwrap().try_recv() {
+                        self.metrics.inc("output_channel_dropped_total", &[]);
+                        warn!(
+                            dropped_version = dropped.as_ref().map(|list| list.version).ok(),
+                            "Output channel full - dropped oldest queued message"
+                        );
+                    }
+                    match self.tx.try_send(item) {
+                        Ok(()) => return Ok(()),
+                        Err(TrySendError::Closed(item)) => return Err(SendError(item)),
+                        // Another task refilled the freed slot first
+                        Err(TrySendError::Full(rejected)) => item = rejected,
+                    }
+                }
+            }
+            OverflowPolicy::Error => {
+                warn!("Output channel full - ending session with RESOURCE_EXHAUSTED");
+                let tx = self.tx.clone();
+                tokio::spawn(async move {
+                    let _ = tx.send(Err(Status::resource_exhausted("output channel overflow"))).await;
+                });
+                Err(SendError(item))
+            }
+        }
+    }
+}
+
+// Senders keep the receiver for drop-oldest, so the channel only closes when the
+// stream to the client is dropped if it is closed explicitly
+impl Drop for AdsReceiver {
+    fn drop(&mut self) {
+        self.rx.lock().unwrap().close();
+    }
+}
+
+impl Stream for AdsReceiver {
+    type Item = AdsItem;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<AdsItem>> {
+        self.rx.lock().unwrap().poll_recv(cx)
+    }
+}
@@ -0,0 +1,276 @@
+//! `score-batch`: run the generation pipeline over a file of Contexts without gRPC,
+//! so a scoring change can be evaluated offline over many requests. Each input row
+//! is scored at every requested version the way a session would score it (version 1
+//! without the understanding, later versions with it), then normalized and
+//! slot-constrained as configured. Input is read and output written one row at a
+//! time, so files of any size stream through.
+//!
+//! Input is NDJSON, one Context per line (`{"query", "asin_id", "understanding",
+//! "seed", "request_type", "queries"}`), or CSV with a header naming the same
+//! columns, `queries` separated by `|`. Output is NDJSON, one AdsList per input row
+//! and version, or an error record for a row that could not be scored.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use ads_common::{Error, Result};
+use ads_proto::score::normalize_list;
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::ads::{Ad, AdsList, Context, RequestType, ScoreNormalization};
+use crate::catalog::Catalog;
+use crate::config::ScoreBatchArgs;
+use crate::constraints::SlotConstraints;
+use crate::containment;
+use crate::metrics::Metrics;
+use crate::plugin::GeneratorPlugin;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Ndjson,
+    Csv,
+}
+
+impl InputFormat {
+    /// Format implied by the file extension; anything but `.csv` is NDJSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => InputFormat::Csv,
+            _ => InputFormat::Ndjson,
+        }
+    }
+}
+
+/// One input row
+#[derive(Debug, Default, Deserialize)]
+struct InputContext {
+    #[serde(default)]
+    query: String,
+    asin_id: String,
+    #[serde(default)]
+    understanding: String,
+    // Captures written by ads-client --record carry `"seed": null` when unseeded
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    request_type: Option<String>,
+    #[serde(default)]
+    queries: Vec<String>,
+}
+
+impl InputContext {
+    fn into_context(self) -> Result<Context> {
+        let request_type = match &self.request_type {
+            None => RequestType::Keyword,
+            Some(name) => parse_request_type(name)?,
+        };
+        if self.asin_id.is_empty() {
+            return Err(Error::invalid("asin_id is required"));
+        }
+        Ok(Context {
+            query: self.query,
+            asin_id: self.asin_id,
+            understanding: self.understanding,
+            seed: self.seed.unwrap_or(0),
+            request_type: request_type as i32,
+            channel_id: 0,
+            queries: self.queries,
+            latency_budget_ms: 0,
+            placements: Vec::new(),
+            flush: false,
+            locale: String::new(),
+        })
+    }
+}
+
+/// Context of one NDJSON input row (the `score-batch` format, which ads-client
+/// --record captures also follow)
+pub fn parse_context(line: &str) -> Result<Context> {
+    serde_json::from_str::<InputContext>(line).map_err(Error::from).and_then(InputContext::into_context)
+}
+
+/// `keyword`, `asin-detail`, `ASIN_DETAIL` or `REQUEST_TYPE_ASIN_DETAIL`
+fn parse_request_type(name: &str) -> Result<RequestType> {
+    let upper = name.trim().to_ascii_uppercase().replace('-', "_");
+    let full = if upper.starts_with("REQUEST_TYPE_") { upper } else { format!("REQUEST_TYPE_{}", upper) };
+    RequestType::from_str_name(&full).ok_or_else(|| Error::invalid(format!("unknown request_type {:?}", name)))
+}
+
+/// Split a CSV record, honoring double-quoted fields with `""` escapes
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn csv_row(header: &[String], line: &str) -> Result<InputContext> {
+    let mut row = InputContext::default();
+    for (column, value) in header.iter().zip(csv_fields(line)) {
+        match column.as_str() {
+            "query" => row.query = value,
+            "asin_id" => row.asin_id = value,
+            "understanding" => row.understanding = value,
+            "seed" if !value.is_empty() => {
+                row.seed = Some(value.parse().map_err(|_| Error::invalid(format!("invalid seed {:?}", value)))?);
+            }
+            "request_type" if !value.is_empty() => row.request_type = Some(value),
+            "queries" if !value.is_empty() => row.queries = value.split('|').map(str::to_string).collect(),
+            _ => {}
+        }
+    }
+    Ok(row)
+}
+
+fn ad_json(ad: &Ad) -> serde_json::Value {
+    json!({
+        "ad_id": ad.ad_id,
+        "asin_id": ad.asin_id,
+        "score": ad.score,
+        "advertiser_id": ad.advertiser_id,
+        "category": ad.category,
+    })
+}
+
+fn list_json(row: usize, context: &Context, ads_list: &AdsList) -> serde_json::Value {
+    let ads = |ads: &[Ad]| ads.iter().map(ad_json).collect::<Vec<_>>();
+    let mut record = json!({
+        "row": row,
+        "version": ads_list.version,
+        "query": context.query,
+        "asin_id": context.asin_id,
+        "request_type": context.request_type().as_str_name(),
+        "normalization": ads_list.normalization().as_str_name(),
+    });
+    if ads_list.query_results.is_empty() {
+        record["ads"] = ads(&ads_list.ads).into();
+    } else {
+        record["query_results"] = ads_list
+            .query_results
+            .iter()
+            .map(|partition| json!({ "query": partition.query, "ads": ads(&partition.ads) }))
+            .collect::<Vec<_>>()
+            .into();
+    }
+    record
+}
+
+/// Counts of one batch run
+#[derive(Debug, Default)]
+pub struct BatchSummary {
+    pub rows: usize,
+    pub lists: usize,
+    /// Rows or versions written as error records
+    pub errors: usize,
+}
+
+/// Score every row of `args.input` into `args.output`
+pub fn run(args: &ScoreBatchArgs) -> Result<BatchSummary> {
+    let plugin = match &args.generator_plugin {
+        Some(path) => Some(GeneratorPlugin::load(path)?),
+        None => None,
+    };
+    let format = args.format.unwrap_or_else(|| InputFormat::from_path(&args.input));
+    let normalization: ScoreNormalization = args.score_normalization.into();
+    let constraints = args.slot_constraints.then(SlotConstraints::default);
+    let catalog = Catalog::default().snapshot();
+    let metrics = Metrics::default();
+
+    let reader = BufReader::new(File::open(&args.input)?);
+    let mut out = BufWriter::new(File::create(&args.output)?);
+    let mut summary = BatchSummary::default();
+    let mut header: Option<Vec<String>> = None;
+    let start = Instant::now();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row = match format {
+            InputFormat::Ndjson => serde_json::from_str::<InputContext>(&line).map_err(Error::from),
+            InputFormat::Csv => match &header {
+                None => {
+                    header = Some(csv_fields(&line).into_iter().map(|c| c.trim().to_string()).collect());
+                    continue;
+                }
+                Some(header) => csv_row(header, &line),
+            },
+        };
+        let row_number = i + 1;
+        summary.rows += 1;
+        let context = match row.and_then(InputContext::into_context) {
+            Ok(context) => context,
+            Err(e) => {
+                summary.errors += 1;
+                writeln!(out, "{}", json!({ "row": row_number, "error": e.to_string() }))?;
+                continue;
+            }
+        };
+        for &version in &args.versions {
+            // Version 1 answers the initial Context of a session, which has no understanding yet
+            let mut versioned = context.clone();
+            if version == 1 {
+                versioned.understanding.clear();
+            }
+            let generated = containment::generate_contained(
+                &versioned,
+                &[],
+                version,
+                context.seed,
+                &catalog,
+                plugin.as_ref(),
+                row_number as u64,
+                &metrics,
+                None,
+                args.ranking(),
+                args.generator_variant,
+            );
+            let mut ads_list = match generated {
+                Ok(ads_list) => ads_list,
+                Err(status) => {
+                    summary.errors += 1;
+                    let error = json!({ "row": row_number, "version": version, "error": status.message() });
+                    writeln!(out, "{}", error)?;
+                    continue;
+                }
+            };
+            normalize_list(&mut ads_list, normalization);
+            if let Some(constraints) = &constraints {
+                constraints.apply_list(&mut ads_list, row_number as u64, version);
+            }
+            writeln!(out, "{}", list_json(row_number, &context, &ads_list))?;
+            summary.lists += 1;
+        }
+    }
+    out.flush()?;
+    let elapsed = start.elapsed().as_secs_f64();
+    eprintln!(
+        "Scored {} rows into {} AdsLists ({} errors) in {:.2}s ({:.0} rows/s)",
+        summary.rows,
+        summary.lists,
+        summary.errors,
+        elapsed,
+        summary.rows as f64 / elapsed.max(f64::EPSILON)
+    );
+    for (series, count) in metrics.snapshot().counters {
+        eprintln!("  {} {}", series, count);
+    }
+    Ok(summary)
+}
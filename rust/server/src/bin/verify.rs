@@ -0,0 +1,12 @@
+use clap::Parser;
+use tracing_subscriber::EnvFilter;
+
+use ads_server::config::VerifyArgs;
+use ads_server::verify;
+
+#[tokio::main]
+async fn main() -> ads_common::Result<()> {
+    // The in-process server only logs when asked to with RUST_LOG
+    tracing_subscriber::fmt().with_env_filter(EnvFilter::from_default_env()).init();
+    verify::run(&VerifyArgs::parse()).await
+}
@@ -0,0 +1,62 @@
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+use crate::ads::{AdsList, Context};
+use crate::metrics::Metrics;
+
+/// End-to-end latency budget carried by a Context, anchored when the server
+/// received it. Every stage the server spends on the Context is charged against
+/// it, and each AdsList echoes what is left so the client can see where the time went.
+#[derive(Debug, Clone, Copy)]
+pub struct LatencyBudget {
+    total: Duration,
+    received_at: Instant,
+}
+
+impl LatencyBudget {
+    /// None when the client did not send a budget
+    pub fn from_context(context: &Context, received_at: Instant) -> Option<Self> {
+        (context.latency_budget_ms > 0).then(|| LatencyBudget {
+            total: Duration::from_millis(context.latency_budget_ms as u64),
+            received_at,
+        })
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.total.saturating_sub(self.received_at.elapsed())
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.received_at.elapsed() >= self.total
+    }
+
+    /// Log and count time spent in one server stage
+    pub fn charge(&self, metrics: &Metrics, session_id: u64, version: u32, stage: &'static str, spent: Duration) {
+        metrics.observe_ms("budget_stage_ms", &[("stage", stage)], spent);
+        info!(
+            session_id = session_id,
+            version = version,
+            stage = stage,
+            spent_ms = spent.as_millis() as u64,
+            remaining_budget_ms = self.remaining().as_millis() as u64,
+            budget_ms = self.total.as_millis() as u64,
+            "Latency budget consumed"
+        );
+    }
+
+    /// Stamp the remaining budget on an outgoing AdsList
+    pub fn annotate(&self, metrics: &Metrics, session_id: u64, ads_list: &mut AdsList) {
+        ads_list.remaining_budget_ms = self.remaining().as_millis() as u32;
+        ads_list.budget_exhausted = self.is_exhausted();
+        if ads_list.budget_exhausted {
+            metrics.inc("budget_exhausted_total", &[]);
+            warn!(
+                session_id = session_id,
+                version = ads_list.version,
+                overrun_ms = (self.received_at.elapsed() - self.total).as_millis() as u64,
+                "Latency budget exhausted before sending AdsList"
+            );
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Debug bundles (`--debug-bundle-dir`). A bidirectional session that fails leaves
+//! one JSON file with what it went through, so the failure can be stepped through
+//! after the fact: every Context it received (as matched, after sanitizing and
+//! normalization), every version generated with its ad ids, when each happened
+//! relative to the session start, the error, and the scheduler state at the moment
+//! of failure (active sessions, outstanding refinements, overload and the
+//! generation queue). The error logged for the session names the file, and
+//! `ads-server debug-bundle inspect <file>` prints it as a timeline.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tonic::Status;
+
+use crate::ads::{AdsList, Context};
+use crate::coalesce::Coalescer;
+use crate::history::{HistorySummary, Transition};
+use crate::overload::OverloadController;
+use crate::priority::PriorityClass;
+
+/// Ad ids listed per version when rendering; the file keeps all of them
+const RENDERED_AD_IDS: usize = 10;
+
+/// Everything recorded about one failed session
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DebugBundle {
+    pub session_id: u64,
+    pub request_id: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub priority: String,
+    pub started_at_unix_ms: u64,
+    pub duration_ms: u64,
+    /// gRPC code and message of the first error
+    pub error: String,
+    pub failed_at_ms: u64,
+    pub scheduler: SchedulerSnapshot,
+    pub contexts: Vec<ContextRecord>,
+    pub versions: Vec<VersionRecord>,
+    pub highest_version_sent: u32,
+    #[serde(default)]
+    pub history: Vec<HistorySummary>,
+}
+
+/// Server-wide scheduling state when the session failed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SchedulerSnapshot {
+    pub active_sessions: usize,
+    pub refinement_tasks_outstanding: usize,
+    pub overloaded: bool,
+    pub shedding_batch: bool,
+    /// Unset without `--generation-workers`
+    pub generation_workers_busy: Option<usize>,
+    pub generation_queued_interactive: usize,
+    pub generation_queued_batch: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContextRecord {
+    pub channel_id: u32,
+    /// Position in the channel, as answered (an informed start counts as 2)
+    pub number: u32,
+    pub at_ms: u64,
+    pub query: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub queries: Vec<String>,
+    pub asin_id: String,
+    pub understanding: String,
+    pub locale: String,
+    pub request_type: String,
+    pub placements: usize,
+    pub seed: u64,
+    pub latency_budget_ms: u32,
+    pub transition: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VersionRecord {
+    pub channel_id: u32,
+    pub version: u32,
+    /// When generation finished
+    pub at_ms: u64,
+    pub generation_ms: u64,
+    pub partitions: usize,
+    pub ad_ids: Vec<String>,
+}
+
+/// Directory failed sessions write their bundles to
+#[derive(Debug)]
+pub struct DebugBundles {
+    dir: PathBuf,
+}
+
+impl DebugBundles {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(DebugBundles { dir: dir.to_path_buf() })
+    }
+
+    fn write(&self, bundle: &DebugBundle) -> io::Result<PathBuf> {
+        let path = self.dir.join(format!("session-{}-{}.json", bundle.started_at_unix_ms, bundle.session_id));
+        fs::write(&path, serde_json::to_vec_pretty(bundle)?)?;
+        Ok(path)
+    }
+}
+
+/// The shared state a failing session snapshots
+#[derive(Debug, Clone)]
+pub struct SchedulerProbe {
+    pub active_sessions: Arc<AtomicUsize>,
+    pub refinement_tasks: Arc<AtomicUsize>,
+    pub overload: Arc<OverloadController>,
+    pub coalescer: Arc<Coalescer>,
+}
+
+impl SchedulerProbe {
+    fn snapshot(&self) -> SchedulerSnapshot {
+        let (busy, queued_interactive, queued_batch) = match self.coalescer.scheduler_load() {
+            Some((busy, interactive, batch)) => (Some(busy), interactive, batch),
+            None => (None, 0, 0),
+        };
+        SchedulerSnapshot {
+            active_sessions: self.active_sessions.load(Ordering::SeqCst),
+            refinement_tasks_outstanding: self.refinement_tasks.load(Ordering::SeqCst),
+            overloaded: self.overload.is_overloaded(),
+            shedding_batch: self.overload.sheds(PriorityClass::Batch),
+            generation_workers_busy: busy,
+            generation_queued_interactive: queued_interactive,
+            generation_queued_batch: queued_batch,
+        }
+    }
+}
+
+/// Recorder of one session, kept until it ends
+#[derive(Debug)]
+pub struct SessionTrace {
+    bundles: Arc<DebugBundles>,
+    probe: SchedulerProbe,
+    priority: PriorityClass,
+    start: Instant,
+    state: Mutex<TraceState>,
+}
+
+#[derive(Debug, Default)]
+struct TraceState {
+    contexts: Vec<ContextRecord>,
+    versions: Vec<VersionRecord>,
+    // First error, when it happened and the scheduler state then
+    failure: Option<(String, u64, SchedulerSnapshot)>,
+}
+
+impl SessionTrace {
+    pub fn new(bundles: Arc<DebugBundles>, probe: SchedulerProbe, priority: PriorityClass) -> Self {
+        SessionTrace { bundles, probe, priority, start: Instant::now(), state: Mutex::default() }
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    pub fn context(&self, context: &Context, number: u32, transition: Transition) {
+        let record = ContextRecord {
+            channel_id: context.channel_id,
+            number,
+            at_ms: self.elapsed_ms(),
+            query: context.query.clone(),
+            queries: context.queries.clone(),
+            asin_id: context.asin_id.clone(),
+            understanding: context.understanding.clone(),
+            locale: context.locale.clone(),
+            request_type: format!("{:?}", context.request_type()),
+            placements: context.placements.len(),
+            seed: context.seed,
+            latency_budget_ms: context.latency_budget_ms,
+            transition: transition.name().to_string(),
+        };
+        self.state.lock().unwrap().contexts.push(record);
+    }
+
+    pub fn version(&self, ads_list: &AdsList, generation_ms: u64) {
+        let record = VersionRecord {
+            channel_id: ads_list.channel_id,
+            version: ads_list.version,
+            at_ms: self.elapsed_ms(),
+            generation_ms,
+            partitions: ads_list.query_results.len() + ads_list.placement_results.len(),
+            ad_ids: ads_list.ads.iter().map(|ad| ad.ad_id.clone()).collect(),
+        };
+        self.state.lock().unwrap().versions.push(record);
+    }
+
+    /// Record the session's error; only the first one is kept
+    pub fn fail(&self, status: &Status) {
+        let mut state = self.state.lock().unwrap();
+        if state.failure.is_none() {
+            let error = format!("{:?}: {}", status.code(), status.message());
+            state.failure = Some((error, self.elapsed_ms(), self.probe.snapshot()));
+        }
+    }
+
+    /// The recorded part of the bundle, or None if the session never failed
+    pub fn bundle(&self, session_id: u64) -> Option<DebugBundle> {
+        let mut state = self.state.lock().unwrap();
+        let (error, failed_at_ms, scheduler) = state.failure.take()?;
+        Some(DebugBundle {
+            session_id,
+            priority: self.priority.name().to_string(),
+            error,
+            failed_at_ms,
+            scheduler,
+            contexts: std::mem::take(&mut state.contexts),
+            versions: std::mem::take(&mut state.versions),
+            ..Default::default()
+        })
+    }
+
+    pub fn write(&self, bundle: &DebugBundle) -> io::Result<PathBuf> {
+        self.bundles.write(bundle)
+    }
+}
+
+impl DebugBundle {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Header, scheduler state and a timeline of Contexts, versions and the error
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "session {} request_id={} priority={} started_at_unix_ms={} duration={}ms\n",
+            self.session_id,
+            if self.request_id.is_empty() { "-" } else { &self.request_id },
+            self.priority,
+            self.started_at_unix_ms,
+            self.duration_ms
+        );
+        if !self.labels.is_empty() {
+            let labels: Vec<String> = self.labels.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+            out.push_str(&format!("labels: {}\n", labels.join(" ")));
+        }
+        out.push_str(&format!("error: {}\n", self.error));
+        let scheduler = &self.scheduler;
+        out.push_str(&format!(
+            "scheduler: active_sessions={} refinement_tasks={} overloaded={} shedding_batch={}",
+            scheduler.active_sessions,
+            scheduler.refinement_tasks_outstanding,
+            scheduler.overloaded,
+            scheduler.shedding_batch
+        ));
+        if let Some(busy) = scheduler.generation_workers_busy {
+            out.push_str(&format!(
+                " generation_busy={} queued_interactive={} queued_batch={}",
+                busy, scheduler.generation_queued_interactive, scheduler.generation_queued_batch
+            ));
+        }
+        out.push('\n');
+
+        // Contexts before versions generated in the same millisecond, the error last
+        let mut timeline: Vec<(u64, u8, String)> = Vec::new();
+        for context in &self.contexts {
+            let mut line = format!(
+                "context  ch{} #{} query={:?} asin_id={} understanding={:?}",
+                context.channel_id, context.number, context.query, context.asin_id, context.understanding
+            );
+            if !context.queries.is_empty() {
+                line.push_str(&format!(" queries={:?}", context.queries));
+            }
+            if !context.locale.is_empty() {
+                line.push_str(&format!(" locale={}", context.locale));
+            }
+            if context.placements > 0 {
+                line.push_str(&format!(" placements={}", context.placements));
+            }
+            if context.latency_budget_ms > 0 {
+                line.push_str(&format!(" budget={}ms", context.latency_budget_ms));
+            }
+            line.push_str(&format!(" type={} transition={}", context.request_type, context.transition));
+            timeline.push((context.at_ms, 0, line));
+        }
+        for version in &self.versions {
+            let mut ad_ids = version.ad_ids.iter().take(RENDERED_AD_IDS).cloned().collect::<Vec<_>>().join(",");
+            if version.ad_ids.len() > RENDERED_AD_IDS {
+                ad_ids.push_str(",...");
+            }
+            let mut line = format!(
+                "version  ch{} v{} ads={} generation={}ms",
+                version.channel_id,
+                version.version,
+                version.ad_ids.len(),
+                version.generation_ms
+            );
+            if version.partitions > 0 {
+                line.push_str(&format!(" partitions={}", version.partitions));
+            }
+            line.push_str(&format!(" [{}]", ad_ids));
+            timeline.push((version.at_ms, 1, line));
+        }
+        timeline.push((self.failed_at_ms, 2, format!("error    {}", self.error)));
+        timeline.sort_by_key(|(at_ms, order, _)| (*at_ms, *order));
+        out.push_str("timeline:\n");
+        for (at_ms, _, line) in timeline {
+            out.push_str(&format!("  {:>7}ms  {}\n", at_ms, line));
+        }
+
+        out.push_str(&format!("highest version sent: {}\n", self.highest_version_sent));
+        for summary in &self.history {
+            out.push_str(&format!(
+                "history ch{}: contexts={} refinements={} pivots={} queries={:?}\n",
+                summary.channel_id, summary.contexts, summary.refinements, summary.pivots, summary.queries
+            ));
+        }
+        out
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use ads_common::limits::validate_asin;
+use tonic::Status;
+use tracing::info;
+
+use crate::constraints::SPONSORED_BRANDS;
+use crate::generator::{self, EMBEDDING_DIM};
+
+const CATEGORIES: [&str; 2] = ["sponsored_products", SPONSORED_BRANDS];
+
+/// Inventory entry ranked alongside the synthetic candidates of every request
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogEntry {
+    pub ad_id: String,
+    pub asin_id: String,
+    pub advertiser_id: String,
+    pub category: String,
+    /// Added to the entry's relevance score before the version multiplier (-1.0..=1.0)
+    pub boost: f64,
+    /// Bumped on every write; callers pass the revision they read to update or delete
+    pub revision: u64,
+    /// `generator::catalog_embedding` of the ad id, set when the entry is written
+    pub embedding: Option<[f64; EMBEDDING_DIM]>,
+}
+
+impl CatalogEntry {
+    pub fn validate(&self) -> Result<(), Status> {
+        if self.ad_id.trim().is_empty() {
+            return Err(Status::invalid_argument("ad_id must not be empty"));
+        }
+        validate_asin(&self.asin_id)?;
+        if self.advertiser_id.trim().is_empty() {
+            return Err(Status::invalid_argument("advertiser_id must not be empty"));
+        }
+        if !CATEGORIES.contains(&self.category.as_str()) {
+            return Err(Status::invalid_argument(format!(
+                "category {:?} must be one of {:?}",
+                self.category, CATEGORIES
+            )));
+        }
+        if !(-1.0..=1.0).contains(&self.boost) {
+            return Err(Status::invalid_argument("boost must be between -1.0 and 1.0"));
+        }
+        Ok(())
+    }
+
+    /// Precompute what generation would otherwise derive per request
+    pub fn index(&mut self) {
+        self.embedding = Some(generator::catalog_embedding(&self.ad_id));
+    }
+}
+
+/// Runtime-mutable ad inventory with optimistic concurrency: every write must name
+/// the revision it was based on, and a stale revision fails with `ABORTED`.
+/// Readers take a cheap snapshot per generation call, so changes show up in the
+/// next AdsList of already-running sessions.
+#[derive(Debug, Default)]
+pub struct Catalog {
+    entries: RwLock<Arc<BTreeMap<String, CatalogEntry>>>,
+    next_revision: RwLock<u64>,
+}
+
+impl Catalog {
+    pub fn snapshot(&self) -> Arc<BTreeMap<String, CatalogEntry>> {
+        self.entries.read().unwrap().clone()
+    }
+
+    pub fn list(&self) -> Vec<CatalogEntry> {
+        self.snapshot().values().cloned().collect()
+    }
+
+    /// Replace the inventory with validated, indexed `entries`, revisioned in order
+    pub fn load(&self, entries: Vec<CatalogEntry>) {
+        let mut loaded = BTreeMap::new();
+        for mut entry in entries {
+            entry.revision = self.bump_revision();
+            loaded.insert(entry.ad_id.clone(), entry);
+        }
+        let count = loaded.len();
+        *self.entries.write().unwrap() = Arc::new(loaded);
+        info!(entries = count, "Catalog loaded");
+    }
+
+    pub fn create(&self, mut entry: CatalogEntry) -> Result<CatalogEntry, Status> {
+        entry.validate()?;
+        entry.index();
+        let mut entries = self.entries.write().unwrap();
+        if entries.contains_key(&entry.ad_id) {
+            return Err(Status::already_exists(format!("catalog entry {} already exists", entry.ad_id)));
+        }
+        entry.revision = self.bump_revision();
+        Arc::make_mut(&mut entries).insert(entry.ad_id.clone(), entry.clone());
+        info!(ad_id = %entry.ad_id, revision = entry.revision, "Catalog entry created");
+        Ok(entry)
+    }
+
+    pub fn update(&self, mut entry: CatalogEntry, expected_revision: u64) -> Result<CatalogEntry, Status> {
+        entry.validate()?;
+        entry.index();
+        let mut entries = self.entries.write().unwrap();
+        check_revision(&entries, &entry.ad_id, expected_revision)?;
+        entry.revision = self.bump_revision();
+        Arc::make_mut(&mut entries).insert(entry.ad_id.clone(), entry.clone());
+        info!(ad_id = %entry.ad_id, revision = entry.revision, "Catalog entry updated");
+        Ok(entry)
+    }
+
+    pub fn delete(&self, ad_id: &str, expected_revision: u64) -> Result<(), Status> {
+        let mut entries = self.entries.write().unwrap();
+        check_revision(&entries, ad_id, expected_revision)?;
+        Arc::make_mut(&mut entries).remove(ad_id);
+        info!(ad_id = %ad_id, "Catalog entry deleted");
+        Ok(())
+    }
+
+    fn bump_revision(&self) -> u64 {
+        let mut next = self.next_revision.write().unwrap();
+        *next += 1;
+        *next
+    }
+}
+
+fn check_revision(entries: &BTreeMap<String, CatalogEntry>, ad_id: &str, expected: u64) -> Result<(), Status> {
+    let current = entries
+        .get(ad_id)
+        .ok_or_else(|| Status::not_found(format!("catalog entry {} not found", ad_id)))?;
+    if current.revision != expected {
+        return Err(Status::aborted(format!(
+            "catalog entry {} is at revision {}, not {}",
+            ad_id, current.revision, expected
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,351 @@
+//! Startup catalog loading (`--catalog-file`). The file is NDJSON, one entry per
+//! line (`{"ad_id", "asin_id", "advertiser_id", "category", "boost"}`), parsed,
+//! validated and indexed on `--catalog-load-threads` threads while progress and
+//! resident memory are logged every second. Indexing precomputes each entry's
+//! embedding for the `embedding` generator variant; catalog keyword relevance is a
+//! hash of query and ad id, so there are no terms to build an inverted index from.
+//! With `--catalog-index-cache`, the indexed entries are also written to a binary
+//! cache keyed by the catalog file's size and modification time, and a later start
+//! with an unchanged file reads the cache instead of parsing and indexing again.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use ads_common::{Error, Result};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::catalog::CatalogEntry;
+use crate::generator::EMBEDDING_DIM;
+use crate::metrics::Metrics;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the loading thread checks on the workers
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Start of every cache file; the last byte is the format version (2: embeddings
+/// hashed with SipHash-2-4, see `generator::stable_hasher`)
+const CACHE_MAGIC: &[u8; 8] = b"ADSCATX\x02";
+
+#[derive(Debug, Deserialize)]
+struct FileEntry {
+    ad_id: String,
+    asin_id: String,
+    advertiser_id: String,
+    category: String,
+    #[serde(default)]
+    boost: f64,
+}
+
+/// What a cache was built from; any change to the catalog file invalidates it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SourceKey {
+    len: u64,
+    modified_ns: u64,
+}
+
+impl SourceKey {
+    fn of(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified_ns = metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        Ok(SourceKey { len: metadata.len(), modified_ns })
+    }
+}
+
+/// Validated and indexed entries of `path`, from `cache` when it is current
+pub fn load(path: &Path, threads: usize, cache: Option<&Path>, metrics: &Metrics) -> Result<Vec<CatalogEntry>> {
+    let start = Instant::now();
+    let key = SourceKey::of(path)?;
+    if let Some(cache) = cache {
+        match read_cache(cache, key) {
+            Ok(Some(entries)) => {
+                report(&entries, "cache", start, metrics);
+                return Ok(entries);
+            }
+            Ok(None) => info!(cache = %cache.display(), "Catalog index cache missing or stale - indexing"),
+            Err(e) => warn!(cache = %cache.display(), error = %e, "Ignoring unreadable catalog index cache"),
+        }
+    }
+
+    let text = fs::read_to_string(path)?;
+    let lines: Vec<(usize, &str)> =
+        text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()).map(|(i, line)| (i + 1, line)).collect();
+    let threads = match threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    info!(path = %path.display(), entries = lines.len(), threads = threads, "Loading catalog");
+    let entries = index_parallel(&lines, threads)?;
+    let mut seen = HashSet::with_capacity(entries.len());
+    if let Some(duplicate) = entries.iter().find(|entry| !seen.insert(entry.ad_id.as_str())) {
+        return Err(Error::invalid(format!("catalog entry {} appears more than once", duplicate.ad_id)));
+    }
+
+    if let Some(cache) = cache {
+        match write_cache(cache, key, &entries) {
+            Ok(()) => info!(cache = %cache.display(), "Wrote catalog index cache"),
+            Err(e) => warn!(cache = %cache.display(), error = %e, "Failed to write catalog index cache"),
+        }
+    }
+    report(&entries, "file", start, metrics);
+    Ok(entries)
+}
+
+fn index_parallel(lines: &[(usize, &str)], threads: usize) -> Result<Vec<CatalogEntry>> {
+    let done = AtomicUsize::new(0);
+    let chunk_size = lines.len().div_ceil(threads).max(1);
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        let done = &done;
+        let handles: Vec<_> = lines.chunks(chunk_size).map(|chunk| scope.spawn(move || index_chunk(chunk, done))).collect();
+        let mut last_tick = Instant::now();
+        while !handles.iter().all(|handle| handle.is_finished()) {
+            std::thread::sleep(POLL_INTERVAL);
+            if last_tick.elapsed() >= PROGRESS_INTERVAL {
+                last_tick = Instant::now();
+                let indexed = done.load(Ordering::Relaxed);
+                info!(
+                    indexed = indexed,
+                    total = lines.len(),
+                    percent = (indexed * 100 / lines.len()) as u64,
+                    entries_per_sec = (indexed as f64 / start.elapsed().as_secs_f64()) as u64,
+                    rss_mb = resident_bytes().map(|bytes| bytes / (1024 * 1024)),
+                    "Indexing catalog"
+                );
+            }
+        }
+        // Chunks are joined in file order, so the first bad line is the one reported
+        let mut entries = Vec::with_capacity(lines.len());
+        for handle in handles {
+            entries.extend(handle.join().expect("catalog indexing panicked")?);
+        }
+        Ok(entries)
+    })
+}
+
+fn index_chunk(lines: &[(usize, &str)], done: &AtomicUsize) -> Result<Vec<CatalogEntry>> {
+    let mut entries = Vec::with_capacity(lines.len());
+    for &(line_number, line) in lines {
+        let parsed: FileEntry = serde_json::from_str(line)
+            .map_err(|e| Error::invalid(format!("catalog line {}: {}", line_number, e)))?;
+        let mut entry = CatalogEntry {
+            ad_id: parsed.ad_id,
+            asin_id: parsed.asin_id,
+            advertiser_id: parsed.advertiser_id,
+            category: parsed.category,
+            boost: parsed.boost,
+            revision: 0,
+            embedding: None,
+        };
+        entry
+            .validate()
+            .map_err(|status| Error::invalid(format!("catalog line {}: {}", line_number, status.message())))?;
+        entry.index();
+        entries.push(entry);
+        done.fetch_add(1, Ordering::Relaxed);
+    }
+    Ok(entries)
+}
+
+fn report(entries: &[CatalogEntry], source: &str, start: Instant, metrics: &Metrics) {
+    let index_bytes = index_bytes(entries);
+    metrics.observe_ms("catalog_load_ms", &[("source", source)], start.elapsed());
+    metrics.set_gauge("catalog_entries", &[], entries.len() as i64);
+    metrics.set_gauge("catalog_index_bytes", &[], index_bytes as i64);
+    info!(
+        entries = entries.len(),
+        source = source,
+        load_ms = start.elapsed().as_millis() as u64,
+        index_mb = (index_bytes / (1024 * 1024)) as u64,
+        rss_mb = resident_bytes().map(|bytes| bytes / (1024 * 1024)),
+        "Catalog ready"
+    );
+}
+
+/// Approximate heap and inline size of the loaded entries
+fn index_bytes(entries: &[CatalogEntry]) -> usize {
+    entries
+        .iter()
+        .map(|entry| {
+            std::mem::size_of::<CatalogEntry>()
+                + entry.ad_id.len()
+                + entry.asin_id.len()
+                + entry.advertiser_id.len()
+                + entry.category.len()
+        })
+        .sum()
+}
+
+/// Resident set size of the process, where /proc reports it
+fn resident_bytes() -> Option<u64> {
+    let statm = fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    (page_size > 0).then(|| pages * page_size as u64)
+}
+
+// Cache layout, little-endian: magic, source length and mtime (u64 each), entry
+// count (u64), then per entry ad_id, asin_id, advertiser_id and category (u32
+// length and UTF-8 bytes each), boost (f64) and the embedding (EMBEDDING_DIM f64)
+
+fn write_cache(path: &Path, key: SourceKey, entries: &[CatalogEntry]) -> io::Result<()> {
+    // Written next to the cache and renamed, so a crash never leaves a torn cache
+    let partial = path.with_extension("partial");
+    let mut out = BufWriter::new(File::create(&partial)?);
+    encode(&mut out, key, entries)?;
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&partial, path)
+}
+
+fn encode(out: &mut impl Write, key: SourceKey, entries: &[CatalogEntry]) -> io::Result<()> {
+    out.write_all(CACHE_MAGIC)?;
+    for value in [key.len, key.modified_ns, entries.len() as u64] {
+        out.write_all(&value.to_le_bytes())?;
+    }
+    for entry in entries {
+        for field in [&entry.ad_id, &entry.asin_id, &entry.advertiser_id, &entry.category] {
+            out.write_all(&(field.len() as u32).to_le_bytes())?;
+            out.write_all(field.as_bytes())?;
+        }
+        out.write_all(&entry.boost.to_le_bytes())?;
+        for value in entry.embedding.unwrap_or([0.0; EMBEDDING_DIM]) {
+            out.write_all(&value.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Cached entries, or None when there is no cache or it was built from another file
+fn read_cache(path: &Path, key: SourceKey) -> io::Result<Option<Vec<CatalogEntry>>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    decode(&bytes, key)
+}
+
+fn decode(bytes: &[u8], key: SourceKey) -> io::Result<Option<Vec<CatalogEntry>>> {
+    let mut reader = CacheReader { bytes };
+    if reader.take(CACHE_MAGIC.len())? != CACHE_MAGIC {
+        return Ok(None);
+    }
+    if (SourceKey { len: reader.u64()?, modified_ns: reader.u64()? }) != key {
+        return Ok(None);
+    }
+    let count = reader.u64()? as usize;
+    // Every entry takes at least its four length prefixes, boost and embedding
+    let min_entry_bytes = 4 * 4 + 8 + 8 * EMBEDDING_DIM;
+    if count > reader.bytes.len() / min_entry_bytes {
+        return Err(truncated());
+    }
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut entry = CatalogEntry {
+            ad_id: reader.string()?,
+            asin_id: reader.string()?,
+            advertiser_id: reader.string()?,
+            category: reader.string()?,
+            boost: f64::from_bits(reader.u64()?),
+            revision: 0,
+            embedding: None,
+        };
+        let mut embedding = [0.0; EMBEDDING_DIM];
+        for value in &mut embedding {
+            *value = f64::from_bits(reader.u64()?);
+        }
+        entry.embedding = Some(embedding);
+        entries.push(entry);
+    }
+    if !reader.bytes.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "trailing bytes after the last entry"));
+    }
+    Ok(Some(entries))
+}
+
+struct CacheReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> CacheReader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            return Err(truncated());
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "catalog index cache is truncated")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: SourceKey = SourceKey { len: 1, modified_ns: 2 };
+
+    fn entry() -> CatalogEntry {
+        let mut entry = CatalogEntry {
+            ad_id: "cached-ad".to_string(),
+            asin_id: "B000123".to_string(),
+            advertiser_id: "advertiser-ü".to_string(),
+            category: "sponsored_products".to_string(),
+            boost: -0.25,
+            revision: 0,
+            embedding: None,
+        };
+        entry.index();
+        entry
+    }
+
+    fn cache_bytes(entries: &[CatalogEntry]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        encode(&mut bytes, KEY, entries).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn cache_round_trips_indexed_entries() {
+        let entries = vec![entry(), CatalogEntry { ad_id: "other-ad".to_string(), ..entry() }];
+        assert_eq!(decode(&cache_bytes(&entries), KEY).unwrap(), Some(entries));
+    }
+
+    #[test]
+    fn cache_of_a_changed_catalog_file_is_ignored() {
+        let bytes = cache_bytes(&[entry()]);
+        assert_eq!(decode(&bytes, SourceKey { len: 1, modified_ns: 3 }).unwrap(), None);
+        assert_eq!(decode(&bytes, SourceKey { len: 2, modified_ns: 2 }).unwrap(), None);
+    }
+
+    #[test]
+    fn cache_of_another_format_is_ignored() {
+        let mut bytes = cache_bytes(&[entry()]);
+        bytes[CACHE_MAGIC.len() - 1] ^= 0xff;
+        assert_eq!(decode(&bytes, KEY).unwrap(), None);
+    }
+
+    #[test]
+    fn truncated_cache_is_an_error() {
+        let bytes = cache_bytes(&[entry()]);
+        let error = decode(&bytes[..bytes.len() - 1], KEY).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,171 @@
+//! Checkpoints of bidirectional sessions, so a client whose stream broke off can open
+//! a new one with its session token and pick up where it was instead of having every
+//! version generated again.
+//!
+//! Each channel of a session periodically records the last version it sent, the list
+//! itself and a hash of the inputs that decide the candidate set. On a resumed stream
+//! the Contexts the client sends again are matched against the checkpoint: versions
+//! older than the checkpoint are skipped, the checkpointed version is resent as
+//! stored, and anything newer is generated as usual. A changed candidate set (another
+//! query, another seed, an edited catalog) drops the checkpoint.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use tracing::info;
+
+use crate::ads::{AdsList, Context};
+use crate::catalog::CatalogEntry;
+use crate::metrics::Metrics;
+
+/// Minimal state of one channel, enough to skip work already done for it
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub last_version: u32,
+    pub seed: u64,
+    /// Hash of everything that decides the candidate ads, see [`candidate_hash`]
+    pub candidate_hash: u64,
+    /// The AdsList sent as `last_version`
+    pub ads_list: AdsList,
+    saved_at: Instant,
+}
+
+/// What a resumed channel does with a Context the client sent again
+#[derive(Debug)]
+pub enum Replay {
+    /// The client already had a newer version; nothing is generated or sent
+    Skip,
+    /// Send the checkpointed list instead of generating it again
+    Resend(Box<AdsList>),
+    /// Beyond the checkpoint; generate as usual
+    Generate,
+    /// The candidate set changed since the checkpoint, which no longer applies
+    Stale,
+}
+
+impl Checkpoint {
+    pub fn replay(&self, version: u32, candidate_hash: u64) -> Replay {
+        if candidate_hash != self.candidate_hash {
+            Replay::Stale
+        } else if version < self.last_version {
+            Replay::Skip
+        } else if version == self.last_version {
+            Replay::Resend(Box::new(self.ads_list.clone()))
+        } else {
+            Replay::Generate
+        }
+    }
+}
+
+/// Hash of the generation inputs that decide which ads are candidates: the queries,
+/// ASIN, request type and placements of `context`, the session seed and every catalog revision.
+/// The understanding only changes scores, so it is left out.
+pub fn candidate_hash(context: &Context, seed: u64, catalog: &BTreeMap<String, CatalogEntry>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    context.query.hash(&mut hasher);
+    context.queries.hash(&mut hasher);
+    context.asin_id.hash(&mut hasher);
+    context.request_type.hash(&mut hasher);
+    context.placements.hash(&mut hasher);
+    seed.hash(&mut hasher);
+    for (ad_id, entry) in catalog {
+        ad_id.hash(&mut hasher);
+        entry.revision.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Checkpoints by (session token, channel_id)
+#[derive(Debug)]
+pub struct CheckpointStore {
+    checkpoints: Mutex<HashMap<(String, u32), Checkpoint>>,
+    /// Minimum time between two checkpoints of a channel; zero checkpoints every version
+    interval: Duration,
+    /// Checkpoints older than this are not resumed and get evicted
+    max_age: Duration,
+    metrics: Arc<Metrics>,
+}
+
+impl CheckpointStore {
+    pub fn new(interval: Duration, max_age: Duration, metrics: Arc<Metrics>) -> Self {
+        CheckpointStore { checkpoints: Mutex::new(HashMap::new()), interval, max_age, metrics }
+    }
+
+    /// Token of a new stream: the resume token when checkpoints exist under it, so a
+    /// stream can be resumed more than once, otherwise a fresh one
+    pub fn session_token(&self, resume_token: Option<&str>) -> String {
+        if let Some(token) = resume_token {
+            if self.checkpoints.lock().unwrap().keys().any(|(t, _)| t == token) {
+                return token.to_string();
+            }
+        }
+        format!("{:032x}", rand::thread_rng().gen::<u128>())
+    }
+
+    /// Checkpoint of a channel being resumed, if there is one young enough
+    pub fn resume(&self, token: &str, channel_id: u32) -> Option<Checkpoint> {
+        let mut checkpoints = self.checkpoints.lock().unwrap();
+        let key = (token.to_string(), channel_id);
+        let (outcome, checkpoint) = match checkpoints.get(&key) {
+            None => ("unknown", None),
+            Some(checkpoint) if checkpoint.saved_at.elapsed() > self.max_age => {
+                checkpoints.remove(&key);
+                ("expired", None)
+            }
+            Some(checkpoint) => ("resumed", Some(checkpoint.clone())),
+        };
+        self.metrics.inc("session_resumes_total", &[("outcome", outcome)]);
+        if let Some(checkpoint) = &checkpoint {
+            info!(
+                channel_id = channel_id,
+                last_version = checkpoint.last_version,
+                age_ms = checkpoint.saved_at.elapsed().as_millis() as u64,
+                "Resuming channel from checkpoint"
+            );
+        }
+        checkpoint
+    }
+
+    /// Record `ads_list` as the latest version sent on a channel, unless the channel
+    /// was checkpointed less than the interval ago
+    pub fn save(&self, token: &str, channel_id: u32, seed: u64, candidate_hash: u64, ads_list: &AdsList) {
+        self.insert(token, channel_id, seed, candidate_hash, ads_list, false);
+    }
+
+    /// `save` regardless of the interval, for a version the client never received
+    pub fn save_undelivered(&self, token: &str, channel_id: u32, seed: u64, candidate_hash: u64, ads_list: &AdsList) {
+        self.insert(token, channel_id, seed, candidate_hash, ads_list, true);
+    }
+
+    fn insert(&self, token: &str, channel_id: u32, seed: u64, candidate_hash: u64, ads_list: &AdsList, force: bool) {
+        let mut checkpoints = self.checkpoints.lock().unwrap();
+        let key = (token.to_string(), channel_id);
+        if !force && checkpoints.get(&key).is_some_and(|previous| previous.saved_at.elapsed() < self.interval) {
+            return;
+        }
+        checkpoints.insert(
+            key,
+            Checkpoint {
+                last_version: ads_list.version,
+                seed,
+                candidate_hash,
+                ads_list: ads_list.clone(),
+                saved_at: Instant::now(),
+            },
+        );
+        checkpoints.retain(|_, checkpoint| checkpoint.saved_at.elapsed() <= self.max_age);
+        self.metrics.inc("session_checkpoints_saved_total", &[]);
+        self.metrics.set_gauge("session_checkpoints", &[], checkpoints.len() as i64);
+    }
+
+    /// Forget a channel's checkpoint once its candidate set changed
+    pub fn discard(&self, token: &str, channel_id: u32) {
+        let mut checkpoints = self.checkpoints.lock().unwrap();
+        checkpoints.remove(&(token.to_string(), channel_id));
+        self.metrics.set_gauge("session_checkpoints", &[], checkpoints.len() as i64);
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+use tonic::{Code, Status};
+use tracing::debug;
+
+use crate::ads::{AdsList, Context};
+use crate::catalog::CatalogEntry;
+use crate::containment;
+use crate::downstream::Downstream;
+use crate::features::FeatureLog;
+use crate::generator::Ranking;
+use crate::metrics::Metrics;
+use crate::plugin::GeneratorPlugin;
+use crate::priority::{GenerationScheduler, GenerationSlot, PriorityClass};
+use crate::variant::GeneratorVariant;
+
+type Snapshot = Arc<BTreeMap<String, CatalogEntry>>;
+type Outcome = Result<AdsList, (Code, String)>;
+
+/// Everything generation output depends on. The catalog snapshot is identified by
+/// address; its flight keeps it alive so the address cannot be reused meanwhile.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct FlightKey {
+    query: String,
+    queries: Vec<String>,
+    asin_id: String,
+    understanding: String,
+    trajectory: Vec<String>,
+    placements: Vec<i32>,
+    request_type: i32,
+    variant: GeneratorVariant,
+    session_seed: u64,
+    version: u32,
+    catalog: usize,
+}
+
+#[derive(Debug)]
+struct Flight {
+    started: Instant,
+    outcome: Arc<OnceCell<Outcome>>,
+    _catalog: Snapshot,
+}
+
+/// Singleflight for ad generation: sessions asking for the same (Context, trajectory,
+/// variant, version, seed, catalog) within `window` of each other share one generation call instead
+/// of repeating it. A zero window disables coalescing. Every call ranks ties with
+/// the server's `ranking` (tie-break policy and top-K cut), after calling the `downstream` dependencies
+/// (once per flight when coalesced). With a scheduler, every call that actually
+/// generates holds one of its slots meanwhile; followers of a flight take none.
+#[derive(Debug)]
+pub struct Coalescer {
+    window: Duration,
+    ranking: Ranking,
+    downstream: Downstream,
+    flights: Mutex<HashMap<FlightKey, Flight>>,
+    scheduler: Option<Arc<GenerationScheduler>>,
+    metrics: Arc<Metrics>,
+}
+
+impl Coalescer {
+    pub fn new(window: Duration, ranking: Ranking, downstream: Downstream, metrics: Arc<Metrics>) -> Self {
+        Coalescer { window, ranking, downstream, flights: Mutex::new(HashMap::new()), scheduler: None, metrics }
+    }
+
+    /// Tie-break and top-K cut of every generated list
+    pub fn ranking(&self) -> Ranking {
+        self.ranking
+    }
+
+    /// Bound generation to the slots of `scheduler`, handed out by priority class
+    pub fn with_scheduler(mut self, scheduler: Option<Arc<GenerationScheduler>>) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// `GenerationScheduler::load` of the generation slots, if generation is bounded
+    pub fn scheduler_load(&self) -> Option<(usize, usize, usize)> {
+        self.scheduler.as_ref().map(|scheduler| scheduler.load())
+    }
+
+    async fn slot(&self, priority: PriorityClass) -> Option<GenerationSlot> {
+        match &self.scheduler {
+            Some(scheduler) => Some(scheduler.acquire(priority).await),
+            None => None,
+        }
+    }
+
+    /// `containment::generate_contained`, shared with identical concurrent requests.
+    /// Sessions sampled by `feature_log` always generate on their own so their
+    /// features get recorded.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn generate(
+        &self,
+        context: &Context,
+        trajectory: &[String],
+        version: u32,
+        session_seed: u64,
+        catalog: Snapshot,
+        plugin: Option<&GeneratorPlugin>,
+        session_id: u64,
+        feature_log: Option<&FeatureLog>,
+        variant: GeneratorVariant,
+        priority: PriorityClass,
+    ) -> Result<AdsList, Status> {
+        let sampled = feature_log.is_some_and(|log| log.samples(session_id));
+        if self.window.is_zero() || sampled {
+            let _slot = self.slot(priority).await;
+            self.downstream.call_all(session_id, version).await?;
+            return containment::generate_contained(
+                context, trajectory, version, session_seed, &catalog, plugin, session_id, &self.metrics, feature_log, self.ranking, variant,
+            );
+        }
+
+        let key = FlightKey {
+            query: context.query.clone(),
+            queries: context.queries.clone(),
+            asin_id: context.asin_id.clone(),
+            understanding: context.understanding.clone(),
+            trajectory: trajectory.to_vec(),
+            placements: context.placements.clone(),
+            request_type: context.request_type,
+            variant,
+            session_seed,
+            version,
+            catalog: Arc::as_ptr(&catalog) as usize,
+        };
+        let outcome = {
+            let mut flights = self.flights.lock().unwrap();
+            let window = self.window;
+            flights.retain(|_, flight| flight.started.elapsed() < window);
+            flights
+                .entry(key)
+                .or_insert_with(|| Flight {
+                    started: Instant::now(),
+                    outcome: Arc::new(OnceCell::new()),
+                    _catalog: catalog.clone(),
+                })
+                .outcome
+                .clone()
+        };
+
+        let mut leader = false;
+        let leader_flag = &mut leader;
+        let result = outcome
+            .get_or_init(move || async move {
+                *leader_flag = true;
+                let _slot = self.slot(priority).await;
+                let generated = match self.downstream.call_all(session_id, version).await {
+                    Ok(()) => containment::generate_contained(
+                        context, trajectory, version, session_seed, &catalog, plugin, session_id, &self.metrics, None, self.ranking, variant,
+                    ),
+                    Err(status) => Err(status),
+                };
+                generated.map_err(|status| (status.code(), status.message().to_string()))
+            })
+            .await;
+        let role = if leader { "leader" } else { "follower" };
+        self.metrics.inc("generation_coalesce_total", &[("role", role)]);
+        if !leader {
+            debug!(session_id = session_id, version = version, "Reused coalesced generation");
+        }
+        result.clone().map_err(|(code, message)| Status::new(code, message))
+    }
+}
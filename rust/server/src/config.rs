@@ -0,0 +1,706 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use ads_proto::ads::ScoreNormalization;
+use ads_proto::score::TieBreak;
+
+use crate::backpressure::OverflowPolicy;
+use crate::batch::InputFormat;
+use crate::dedupe::DuplicatePolicy;
+use crate::disconnect::DisconnectPolicy;
+use crate::downstream::{Dependency, FailureMode};
+use crate::features::FeatureLogFormat;
+use crate::generator::Ranking;
+use crate::quality::{QualityGate, QualityMetric};
+use crate::priority::PriorityClass;
+use crate::sanitize::SanitizeRule;
+use crate::softlimit::Limit;
+use crate::textnorm::StemLanguage;
+use crate::transport_bench::{BenchRpc, Transport};
+use crate::variant::GeneratorVariant;
+
+#[derive(Parser, Debug)]
+#[command(name = "ads-server", about = "Rust Ads bidirectional streaming server")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub config: ServerConfig,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Compare two metrics snapshots written with --metrics-dump
+    MetricsDiff {
+        before: PathBuf,
+        after: PathBuf,
+    },
+    /// Export, import and inspect session journal archives
+    #[command(subcommand)]
+    Journal(JournalCommand),
+    /// Inspect debug bundles written with --debug-bundle-dir
+    #[command(subcommand)]
+    DebugBundle(DebugBundleCommand),
+    /// Run the generation pipeline over a file of Contexts, without gRPC
+    ScoreBatch(ScoreBatchArgs),
+    /// Time top-K candidate selection against a full sort of a synthetic pool
+    BenchRanking(BenchRankingArgs),
+    /// Compare latency and throughput of loopback TCP, UDS and in-process transports
+    BenchTransport(BenchTransportArgs),
+    /// Train a zstd dictionary for --zstd-dictionary from ads-client --record captures
+    TrainDictionary(TrainDictionaryArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct BenchTransportArgs {
+    /// Transports to compare, in report order; throughput is reported relative to the first
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "tcp,uds,in-process")]
+    pub transports: Vec<Transport>,
+
+    /// Sessions measured per transport
+    #[arg(long, default_value_t = 2000)]
+    pub sessions: usize,
+
+    /// Sessions run on each transport before measuring
+    #[arg(long, default_value_t = 100)]
+    pub warm_up: usize,
+
+    /// Sessions in flight at once
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+
+    /// RPC each session makes
+    #[arg(long, value_enum, default_value = "bidi")]
+    pub rpc: BenchRpc,
+
+    /// Ads in each AdsList of the built-in fixture
+    #[arg(long, default_value_t = 10)]
+    pub ads: usize,
+
+    /// Serve this stub fixture file (see --stub) instead of the built-in fixture
+    #[arg(long)]
+    pub fixtures: Option<PathBuf>,
+
+    /// Query of the benchmark Context, matched against --fixtures
+    #[arg(long, default_value = "running shoes")]
+    pub query: String,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    name = "ads-verify",
+    about = "Lint the proto, run conformance scenarios against an in-process server, the generator \
+             property tests and the config schema checks"
+)]
+pub struct VerifyArgs {
+    /// Config file (see --config-file) to validate against the current schema; repeatable
+    #[arg(long = "config-file")]
+    pub config_files: Vec<PathBuf>,
+
+    /// Time (ms) each conformance scenario has before it counts as failed
+    #[arg(long, default_value_t = 5000)]
+    pub scenario_timeout_ms: u64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TrainDictionaryArgs {
+    /// Capture file written with ads-client --record
+    #[arg(long)]
+    pub captures: PathBuf,
+
+    /// Dictionary file to write
+    #[arg(long)]
+    pub output: PathBuf,
+
+    /// Largest dictionary size in bytes
+    #[arg(long, default_value_t = 16 * 1024)]
+    pub max_size: usize,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct BenchRankingArgs {
+    /// Candidates in the pool, as in a large catalog
+    #[arg(long, default_value_t = 50_000)]
+    pub candidates: usize,
+
+    /// Candidates kept
+    #[arg(long, default_value_t = 10)]
+    pub top_k: usize,
+
+    /// Pools ranked by each method
+    #[arg(long, default_value_t = 20)]
+    pub iterations: usize,
+
+    /// Order of ads with equal scores
+    #[arg(long, value_enum, default_value = "ad-id")]
+    pub tie_break: TieBreakPolicy,
+
+    /// Seed of the synthetic pools (and of seeded tie-breaking)
+    #[arg(long, default_value_t = 42)]
+    pub seed: u64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ScoreBatchArgs {
+    /// Contexts as NDJSON, or CSV with a header row (query, asin_id, understanding, seed, request_type, queries)
+    #[arg(long)]
+    pub input: PathBuf,
+
+    /// NDJSON file receiving one AdsList per input row and version
+    #[arg(long)]
+    pub output: PathBuf,
+
+    /// Input format; inferred from the extension when omitted (.csv, otherwise NDJSON)
+    #[arg(long, value_enum)]
+    pub format: Option<InputFormat>,
+
+    /// Versions to score each row at
+    #[arg(long, value_delimiter = ',', default_value = "1,2,3")]
+    pub versions: Vec<u32>,
+
+    /// Load the ad generator from this shared library instead of the built-in one
+    #[arg(long, env = "ADS_GENERATOR_PLUGIN")]
+    pub generator_plugin: Option<PathBuf>,
+
+    /// Order of ads with equal scores
+    #[arg(long, value_enum, env = "ADS_TIE_BREAK", default_value = "ad-id")]
+    pub tie_break: TieBreakPolicy,
+
+    /// Keep only the best K candidates of each pool, selected without a full sort (0 = keep all)
+    #[arg(long, env = "ADS_RANKING_TOP_K", default_value_t = 0)]
+    pub ranking_top_k: usize,
+
+    /// Built-in generator variant to score with
+    #[arg(long, value_enum, default_value = "catalog")]
+    pub generator_variant: GeneratorVariant,
+
+    /// Normalize scores within each AdsList
+    #[arg(long, value_enum, env = "ADS_SCORE_NORMALIZATION", default_value = "none")]
+    pub score_normalization: Normalization,
+
+    /// Apply slot constraints after ranking
+    #[arg(long, env = "ADS_SLOT_CONSTRAINTS")]
+    pub slot_constraints: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum JournalCommand {
+    /// Pack a journal (and optionally ads-client --record captures) into a gzip archive
+    Export {
+        /// Journal written with --session-journal
+        #[arg(long)]
+        journal: PathBuf,
+        /// Capture file written with ads-client --record
+        #[arg(long)]
+        captures: Option<PathBuf>,
+        /// Archive to create
+        out: PathBuf,
+    },
+    /// Append an archive's sessions to a journal and its captures to a capture file
+    Import {
+        archive: PathBuf,
+        #[arg(long)]
+        journal: PathBuf,
+        /// Where to write captures (skipped if omitted)
+        #[arg(long)]
+        captures: Option<PathBuf>,
+    },
+    /// Print an archive's header and a per-label summary of its sessions
+    Inspect {
+        archive: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DebugBundleCommand {
+    /// Print a bundle's session, error, scheduler state and timeline
+    Inspect {
+        bundle: PathBuf,
+    },
+}
+
+/// Command line / environment configuration for the Rust Ads server
+#[derive(Args, Debug, Clone)]
+pub struct ServerConfig {
+    /// Load config, config file and plugin, print the effective configuration, run
+    /// generator self-tests and exit (non-zero on any problem) without binding the port
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Serve scripted AdsLists from this YAML fixture file instead of generating
+    /// them (stub mode); every other generation setting is ignored
+    #[arg(long, env = "ADS_STUB", value_name = "FIXTURES")]
+    pub stub: Option<PathBuf>,
+
+    /// Port to listen on; 0 lets the OS pick a free port (announced on stdout as ADS_SERVER_PORT=<port>)
+    #[arg(default_value_t = 50051)]
+    pub port: u16,
+
+    /// Write the bound port to this file once listening (removed on clean shutdown)
+    #[arg(long, env = "ADS_PORT_FILE")]
+    pub port_file: Option<PathBuf>,
+
+    /// PEM certificate chain to serve TLS with, together with --tls-key (unset =
+    /// plaintext)
+    #[arg(long, env = "ADS_TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key of --tls-cert
+    #[arg(long, env = "ADS_TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Generation sojourn target (ms) above which the server is considered overloaded
+    #[arg(long, env = "ADS_OVERLOAD_TARGET_MS", default_value_t = 5)]
+    pub overload_target_ms: u64,
+
+    /// Window (ms) over which the minimum sojourn time is compared to the target
+    #[arg(long, env = "ADS_OVERLOAD_INTERVAL_MS", default_value_t = 100)]
+    pub overload_interval_ms: u64,
+
+    /// How often (s) to log a metrics snapshot, 0 to disable
+    #[arg(long, env = "ADS_METRICS_INTERVAL_SECS", default_value_t = 30)]
+    pub metrics_interval_secs: u64,
+
+    /// Honor `x-test-case` request metadata (scripted behaviors for integration tests)
+    #[arg(long, env = "ADS_ENABLE_TEST_HOOKS")]
+    pub enable_test_hooks: bool,
+
+    /// Fail any bidirectional stream whose channels break the canonical contract
+    /// (exactly two Contexts, understanding only on the second, within the window),
+    /// listing the violations; for validating client implementations
+    #[arg(long, env = "ADS_STRICT_PROTOCOL")]
+    pub strict_protocol: bool,
+
+    /// Longest gap (ms) strict mode allows between a channel's two Contexts
+    #[arg(long, env = "ADS_STRICT_WINDOW_MS", default_value_t = 1000)]
+    pub strict_window_ms: u64,
+
+    /// Maximum number of concurrent sessions; new sessions beyond it are rejected
+    #[arg(long, env = "ADS_MAX_CONCURRENT_SESSIONS", default_value_t = 1024)]
+    pub max_concurrent_sessions: u64,
+
+    /// Retry-after hint (ms) on sessions refused at the concurrent session limit;
+    /// sessions shed for overload are told the rest of the overload interval instead
+    #[arg(long, env = "ADS_SESSION_LIMIT_RETRY_AFTER_MS", default_value_t = 100)]
+    pub session_limit_retry_after_ms: u64,
+
+    /// Priority class of sessions without (or with an unknown) x-priority metadata
+    #[arg(long, value_enum, env = "ADS_DEFAULT_PRIORITY", default_value = "interactive")]
+    pub default_priority: PriorityClass,
+
+    /// Share of --max-concurrent-sessions batch sessions may take; the rest is kept
+    /// for interactive ones
+    #[arg(long, env = "ADS_BATCH_SESSION_SHARE", default_value_t = 0.75)]
+    pub batch_session_share: f64,
+
+    /// Fraction of --overload-target-ms above which batch sessions and refinements are
+    /// already shed
+    #[arg(long, env = "ADS_BATCH_OVERLOAD_TARGET_FRACTION", default_value_t = 0.5)]
+    pub batch_overload_target_fraction: f64,
+
+    /// Generations running at once; further ones queue, interactive before batch
+    /// (0 = unbounded)
+    #[arg(long, env = "ADS_GENERATION_WORKERS", default_value_t = 0)]
+    pub generation_workers: usize,
+
+    /// Open connections a single peer IP may hold; further ones are closed on accept
+    /// (0 = unlimited)
+    #[arg(long, env = "ADS_MAX_CONNECTIONS_PER_PEER", default_value_t = 0)]
+    pub max_connections_per_peer: usize,
+
+    /// Close connections that move no bytes for this many seconds (0 = never)
+    #[arg(long, env = "ADS_IDLE_CONNECTION_TIMEOUT_SECS", default_value_t = 300)]
+    pub idle_connection_timeout_secs: u64,
+
+    /// Delayed version 3 tasks one bidirectional session may have outstanding (one per
+    /// refined channel); refinements beyond it are skipped
+    #[arg(long, env = "ADS_MAX_REFINEMENT_TASKS", default_value_t = 16)]
+    pub max_refinement_tasks: usize,
+
+    /// What a pending refinement does when the client disconnects before it is
+    /// delivered: cancel it, finish it and journal the result, or finish it within
+    /// --disconnect-grace-ms and checkpoint it for a reconnect (needs --session-checkpoints)
+    #[arg(long, value_enum, env = "ADS_DISCONNECT_POLICY", default_value = "cancel")]
+    pub disconnect_policy: DisconnectPolicy,
+
+    /// How long (ms) the grace policy keeps a refinement going after a disconnect
+    #[arg(long, env = "ADS_DISCONNECT_GRACE_MS", default_value_t = 2000)]
+    pub disconnect_grace_ms: u64,
+
+    /// How long (s) a finished stream's generation work waits for the client's
+    /// ReportFeedback before it counts as unreported in the wasted-work totals
+    #[arg(long, env = "ADS_FEEDBACK_WAIT_SECS", default_value_t = 30)]
+    pub feedback_wait_secs: u64,
+
+    /// Fail startup instead of warning when fd/backlog limits are too low; unrelated
+    /// to `--strict-protocol`
+    #[arg(long, env = "ADS_STRICT_LIMITS")]
+    pub strict_limits: bool,
+
+    /// Raise the file descriptor soft limit to cover max concurrent sessions
+    #[arg(long)]
+    pub raise_fd_limit: bool,
+
+    /// Write the full metrics registry as JSON to this file on shutdown
+    #[arg(long, env = "ADS_METRICS_DUMP")]
+    pub metrics_dump: Option<PathBuf>,
+
+    /// Format of --metrics-dump; OpenMetrics text carries trace-id exemplars of
+    /// sessions whose client sent a traceparent
+    #[arg(long, value_enum, env = "ADS_METRICS_DUMP_FORMAT", default_value = "json")]
+    pub metrics_dump_format: MetricsFormat,
+
+    /// Apply slot constraints after ranking (sponsored brands first, max 2 per advertiser in top 5)
+    #[arg(long, env = "ADS_SLOT_CONSTRAINTS")]
+    pub slot_constraints: bool,
+
+    /// Gaps between a session's Contexts shorter than this (ms) are flagged as too fast
+    #[arg(long, env = "ADS_MIN_CONTEXT_GAP_MS", default_value_t = 10)]
+    pub min_context_gap_ms: u64,
+
+    /// Gaps between a session's Contexts longer than this (ms) are flagged as too slow
+    #[arg(long, env = "ADS_MAX_CONTEXT_GAP_MS", default_value_t = 1000)]
+    pub max_context_gap_ms: u64,
+
+    /// JSON file overriding hot-reloadable settings; re-read when it changes
+    #[arg(long, env = "ADS_CONFIG_FILE")]
+    pub config_file: Option<PathBuf>,
+
+    /// Number of config reloads kept in the audit trail
+    #[arg(long, default_value_t = 20)]
+    pub config_audit_size: usize,
+
+    /// First-version latency (ms) a session must beat to count as good for the latency SLO
+    #[arg(long, env = "ADS_SLO_FIRST_VERSION_MS", default_value_t = 20)]
+    pub slo_first_version_ms: u64,
+
+    /// Fraction of sessions that must meet --slo-first-version-ms
+    #[arg(long, env = "ADS_SLO_LATENCY_TARGET", default_value_t = 0.99)]
+    pub slo_latency_target: f64,
+
+    /// Fraction of sessions that must complete without an error status
+    #[arg(long, env = "ADS_SLO_SUCCESS_TARGET", default_value_t = 0.99)]
+    pub slo_success_target: f64,
+
+    /// Rolling window (seconds) for SLO compliance; burn-rate alerts also check 1/12 of it
+    #[arg(long, env = "ADS_SLO_WINDOW_SECS", default_value_t = 300)]
+    pub slo_window_secs: u64,
+
+    /// Burn rate (1.0 = spending the error budget exactly on schedule) above which an SLO alert is logged
+    #[arg(long, env = "ADS_SLO_BURN_RATE_ALERT", default_value_t = 10.0)]
+    pub slo_burn_rate_alert: f64,
+
+    /// Interval between SLO evaluations in seconds (0 disables evaluation)
+    #[arg(long, default_value_t = 10)]
+    pub slo_eval_interval_secs: u64,
+
+    /// Track the distribution of sent scores per version and alert when it drifts
+    #[arg(long, env = "ADS_SCORE_DRIFT")]
+    pub score_drift: bool,
+
+    /// Scores per version in the drift baseline and in the rolling window compared with it
+    #[arg(long, env = "ADS_SCORE_DRIFT_WINDOW", default_value_t = 1000)]
+    pub score_drift_window: usize,
+
+    /// KS distance between a version's rolling window and its baseline that raises a drift alert
+    #[arg(long, env = "ADS_SCORE_DRIFT_MAX_KS", default_value_t = 0.2)]
+    pub score_drift_max_ks: f64,
+
+    /// Shift of a version's mean score, in baseline standard deviations, that raises a drift alert
+    #[arg(long, env = "ADS_SCORE_DRIFT_MAX_MEAN_SHIFT", default_value_t = 0.5)]
+    pub score_drift_max_mean_shift: f64,
+
+    /// Interval between score drift evaluations in seconds
+    #[arg(long, default_value_t = 30)]
+    pub score_drift_eval_interval_secs: u64,
+
+    /// Shared library implementing the ads-plugin-api ABI; replaces the built-in ad generator
+    #[arg(long, env = "ADS_GENERATOR_PLUGIN")]
+    pub generator_plugin: Option<PathBuf>,
+
+    /// Load the ad catalog from this NDJSON file at startup (one entry per line)
+    #[arg(long, env = "ADS_CATALOG_FILE")]
+    pub catalog_file: Option<PathBuf>,
+
+    /// Threads parsing and indexing --catalog-file (0 = one per CPU)
+    #[arg(long, env = "ADS_CATALOG_LOAD_THREADS", default_value_t = 0)]
+    pub catalog_load_threads: usize,
+
+    /// Binary cache of the indexed --catalog-file; reused while the file is unchanged
+    #[arg(long, env = "ADS_CATALOG_INDEX_CACHE")]
+    pub catalog_index_cache: Option<PathBuf>,
+
+    /// Generator variant of sessions that request none (or one that is not enabled)
+    #[arg(long, value_enum, env = "ADS_DEFAULT_GENERATOR_VARIANT", default_value = "catalog")]
+    pub default_generator_variant: GeneratorVariant,
+
+    /// Variants clients may request per session with `x-generator` metadata, on top of the default
+    #[arg(long, value_enum, env = "ADS_GENERATOR_VARIANTS", value_delimiter = ',')]
+    pub generator_variants: Vec<GeneratorVariant>,
+
+    /// Sessions per minute granted DEBUG logs through `x-debug-session: true`
+    /// metadata; further requests log normally (0 ignores the metadata)
+    #[arg(long, env = "ADS_DEBUG_SESSION_RATE", default_value_t = 6)]
+    pub debug_session_rate: u32,
+
+    /// Sign every AdsList with the ed25519 key whose seed this file holds as hex;
+    /// the public key clients verify with is logged at startup
+    #[arg(long, env = "ADS_SIGNING_KEY")]
+    pub signing_key: Option<PathBuf>,
+
+    /// zstd dictionary (from `train-dictionary`) for clients that compress with it;
+    /// a call's responses are compressed once its client sends a compressed message
+    #[arg(long, env = "ADS_ZSTD_DICTIONARY")]
+    pub zstd_dictionary: Option<PathBuf>,
+
+    /// Capacity of each session's AdsList output channel
+    #[arg(long, env = "ADS_OUTPUT_CHANNEL_CAPACITY", default_value_t = 128)]
+    pub output_channel_capacity: usize,
+
+    /// What a session does when its output channel is full
+    #[arg(long, value_enum, env = "ADS_OVERFLOW_POLICY", default_value = "block")]
+    pub overflow_policy: OverflowPolicy,
+
+    /// Scrub rules applied to every incoming Context before generation and logging:
+    /// normalize, email, phone, truncate (comma-separated; unset = none)
+    #[arg(long, value_enum, env = "ADS_SANITIZE", value_delimiter = ',')]
+    pub sanitize: Vec<SanitizeRule>,
+
+    /// Characters a query keeps under the `truncate` rule
+    #[arg(long, env = "ADS_SANITIZE_MAX_QUERY_CHARS", default_value_t = 256)]
+    pub sanitize_max_query_chars: usize,
+
+    /// Characters an understanding keeps under the `truncate` rule
+    #[arg(long, env = "ADS_SANITIZE_MAX_UNDERSTANDING_CHARS", default_value_t = 2048)]
+    pub sanitize_max_understanding_chars: usize,
+
+    /// Normalize the query and understanding of every Context for its locale before
+    /// matching: NFKC, locale-aware lowercasing and tokenization
+    #[arg(long, env = "ADS_NORMALIZE_TEXT")]
+    pub normalize_text: bool,
+
+    /// Locale (BCP 47 tag) of Contexts that carry none
+    #[arg(long, env = "ADS_DEFAULT_LOCALE", default_value = "en")]
+    pub default_locale: String,
+
+    /// Languages whose tokens are also stemmed under --normalize-text: en, de
+    /// (comma-separated; unset = none)
+    #[arg(long, value_enum, env = "ADS_STEM", value_delimiter = ',')]
+    pub stem: Vec<StemLanguage>,
+
+    /// Client label keys (sent as x-label-<key> metadata) that become metric labels
+    #[arg(long, env = "ADS_METRIC_LABEL_KEYS", value_delimiter = ',', default_value = "run_id,scenario,arm")]
+    pub metric_label_keys: Vec<String>,
+
+    /// Distinct values admitted per metric label key before further values report as "other"
+    #[arg(long, default_value_t = 32)]
+    pub max_label_values: usize,
+
+    /// Append a JSON line per finished session (ids, labels, duration, outcome) to this file
+    #[arg(long, env = "ADS_SESSION_JOURNAL")]
+    pub session_journal: Option<PathBuf>,
+
+    /// Write a debug bundle (Contexts, versions, timings, scheduler state) for every
+    /// failed bidirectional session to this directory
+    #[arg(long, env = "ADS_DEBUG_BUNDLE_DIR")]
+    pub debug_bundle_dir: Option<PathBuf>,
+
+    /// Write the shutdown report (sessions, errors, outcomes, latency percentiles,
+    /// uptime) to this JSON file on exit; it is logged either way
+    #[arg(long, env = "ADS_SHUTDOWN_REPORT")]
+    pub shutdown_report: Option<PathBuf>,
+
+    /// Sessions requesting an identical Context within this window (ms) share one
+    /// generation call; 0 disables coalescing
+    #[arg(long, env = "ADS_COALESCE_WINDOW_MS", default_value_t = 0)]
+    pub coalesce_window_ms: u64,
+
+    /// Simulated downstream dependency called before every generation, as
+    /// NAME:LATENCY_MS[:JITTER_MS[:FAILURE_RATE]] (repeatable), e.g. budget:20:10:0.01
+    #[arg(long, env = "ADS_DOWNSTREAM", value_delimiter = ',', value_name = "SPEC")]
+    pub downstream: Vec<Dependency>,
+
+    /// Timeout of each downstream dependency call (ms)
+    #[arg(long, env = "ADS_DOWNSTREAM_TIMEOUT_MS", default_value_t = 100)]
+    pub downstream_timeout_ms: u64,
+
+    /// Call the downstream dependencies one after another instead of in parallel
+    #[arg(long)]
+    pub downstream_sequential: bool,
+
+    /// What generation does when a downstream call fails or times out
+    #[arg(long, value_enum, env = "ADS_DOWNSTREAM_ON_FAILURE", default_value = "fail")]
+    pub downstream_on_failure: FailureMode,
+
+    /// Send a refinement version only when it improves on the channel's previous
+    /// version by at least --quality-gate-min-delta under this metric (unset = send all)
+    #[arg(long, value_enum, env = "ADS_QUALITY_GATE")]
+    pub quality_gate: Option<QualityMetric>,
+
+    /// Smallest improvement of the gate metric that lets a version through
+    #[arg(long, env = "ADS_QUALITY_GATE_MIN_DELTA", default_value_t = 0.01)]
+    pub quality_gate_min_delta: f64,
+
+    /// Cut-off rank of the ndcg gate metric
+    #[arg(long, env = "ADS_QUALITY_GATE_K", default_value_t = 10)]
+    pub quality_gate_k: usize,
+
+    /// Append the score inputs and final score of every generated ad to this file
+    #[arg(long, env = "ADS_FEATURE_LOG")]
+    pub feature_log: Option<PathBuf>,
+
+    /// Record layout of --feature-log
+    #[arg(long, value_enum, env = "ADS_FEATURE_LOG_FORMAT", default_value = "jsonl")]
+    pub feature_log_format: FeatureLogFormat,
+
+    /// Fraction of sessions whose ads are written to --feature-log
+    #[arg(long, env = "ADS_FEATURE_LOG_SAMPLE_RATE", default_value_t = 1.0)]
+    pub feature_log_sample_rate: f64,
+
+    /// Handling of a stream whose idempotency key matches a still-active session
+    #[arg(long, value_enum, env = "ADS_DUPLICATE_SESSION_POLICY", default_value = "attach")]
+    pub duplicate_session_policy: DuplicatePolicy,
+
+    /// Checkpoint bidirectional sessions so a client reconnecting with its session
+    /// token resumes without regenerating the versions it already had
+    #[arg(long, env = "ADS_SESSION_CHECKPOINTS")]
+    pub session_checkpoints: bool,
+
+    /// Minimum time between two checkpoints of a session channel (ms); 0 checkpoints every version
+    #[arg(long, env = "ADS_CHECKPOINT_INTERVAL_MS", default_value_t = 0)]
+    pub checkpoint_interval_ms: u64,
+
+    /// Checkpoints older than this are not resumed
+    #[arg(long, env = "ADS_CHECKPOINT_MAX_AGE_SECS", default_value_t = 300)]
+    pub checkpoint_max_age_secs: u64,
+
+    /// Contexts of a bidirectional session channel kept as session memory; scoring boosts
+    /// ads relevant to the earlier queries of a refinement. 0 scores each Context alone
+    #[arg(long, env = "ADS_CONTEXT_HISTORY_WINDOW", default_value_t = 8)]
+    pub context_history_window: usize,
+
+    /// Order of ads with equal scores: ascending ad_id, newest catalog revision first,
+    /// or a pseudo-random order derived from the session seed
+    #[arg(long, value_enum, env = "ADS_TIE_BREAK", default_value = "ad-id")]
+    pub tie_break: TieBreakPolicy,
+
+    /// Keep only the best K candidates of each pool, selected with a bounded heap
+    /// instead of sorting the whole pool; for large catalogs (0 = keep all)
+    #[arg(long, env = "ADS_RANKING_TOP_K", default_value_t = 0)]
+    pub ranking_top_k: usize,
+
+    /// Normalize scores within each AdsList so they are comparable across versions
+    #[arg(long, value_enum, env = "ADS_SCORE_NORMALIZATION", default_value = "none")]
+    pub score_normalization: Normalization,
+
+    /// Drop ads whose score (before normalization) is below this; a list left empty
+    /// is sent as an explicit no-fill instead of being padded (unset = keep all ads)
+    #[arg(long, env = "ADS_MIN_SCORE")]
+    pub min_score: Option<f64>,
+
+    /// Most ads a list, or each of its partitions, may carry; longer ones keep their
+    /// best ads (0 = no limit)
+    #[arg(long, env = "ADS_MAX_ADS_PER_LIST", default_value_t = 0)]
+    pub max_ads_per_list: usize,
+
+    /// Longest understanding (characters, after sanitizing) a Context may carry;
+    /// longer ones fail the stream with INVALID_ARGUMENT (0 = no limit)
+    #[arg(long, env = "ADS_MAX_UNDERSTANDING_CHARS", default_value_t = 0)]
+    pub max_understanding_chars: usize,
+
+    /// Most Contexts a stream may send; the next one fails it with INVALID_ARGUMENT
+    /// (0 = no limit)
+    #[arg(long, env = "ADS_MAX_CONTEXTS_PER_SESSION", default_value_t = 0)]
+    pub max_contexts_per_session: usize,
+
+    /// Limits that only warn, in `x-limit-warnings` trailers and
+    /// `limit_exceeded_total`, instead of being enforced: ads-per-list,
+    /// understanding-chars, contexts-per-session (comma-separated)
+    #[arg(long, value_enum, env = "ADS_SOFT_LIMITS", value_delimiter = ',')]
+    pub soft_limits: Vec<Limit>,
+}
+
+/// File formats of --metrics-dump
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricsFormat {
+    /// Metrics snapshot readable by `metrics-diff`
+    Json,
+    /// OpenMetrics text exposition with exemplars
+    Openmetrics,
+}
+
+/// CLI names for the proto ScoreNormalization values
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Normalization {
+    None,
+    MinMax,
+    Softmax,
+}
+
+impl From<Normalization> for ScoreNormalization {
+    fn from(normalization: Normalization) -> Self {
+        match normalization {
+            Normalization::None => ScoreNormalization::None,
+            Normalization::MinMax => ScoreNormalization::MinMax,
+            Normalization::Softmax => ScoreNormalization::Softmax,
+        }
+    }
+}
+
+/// CLI names for the score TieBreak policies
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum TieBreakPolicy {
+    AdId,
+    Recency,
+    Seeded,
+}
+
+impl From<TieBreakPolicy> for TieBreak {
+    fn from(policy: TieBreakPolicy) -> Self {
+        match policy {
+            TieBreakPolicy::AdId => TieBreak::AdId,
+            TieBreakPolicy::Recency => TieBreak::Recency,
+            TieBreakPolicy::Seeded => TieBreak::Seeded,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Concurrent sessions batch traffic may hold, at least one
+    pub fn batch_session_limit(&self) -> usize {
+        ((self.max_concurrent_sessions as f64 * self.batch_session_share).ceil() as usize).max(1)
+    }
+
+    pub fn overload_target(&self) -> Duration {
+        Duration::from_millis(self.overload_target_ms)
+    }
+
+    pub fn overload_interval(&self) -> Duration {
+        Duration::from_millis(self.overload_interval_ms)
+    }
+
+    pub fn ranking(&self) -> Ranking {
+        ranking(self.tie_break, self.ranking_top_k)
+    }
+
+    pub fn quality_gate(&self) -> Option<QualityGate> {
+        self.quality_gate.map(|metric| QualityGate {
+            metric,
+            min_delta: self.quality_gate_min_delta,
+            k: self.quality_gate_k,
+        })
+    }
+}
+
+impl ScoreBatchArgs {
+    pub fn ranking(&self) -> Ranking {
+        ranking(self.tie_break, self.ranking_top_k)
+    }
+}
+
+fn ranking(tie_break: TieBreakPolicy, top_k: usize) -> Ranking {
+    Ranking { tie_break: tie_break.into(), top_k: (top_k > 0).then_some(top_k) }
+}
@@ -0,0 +1,236 @@
+//! Schema versioning and validation of `--config-file`. A file names the schema it
+//! was written for in `schema_version`; files for an older schema are migrated to
+//! the current one as they are read, so config files from earlier revisions keep
+//! working. Validation reports every problem in a file at once, each with its key
+//! path and, where one is obvious, a suggested fix.
+
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+/// Schema written by this revision
+pub const CONFIG_SCHEMA_VERSION: u64 = 2;
+
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Upgrades from each older schema to the next: `MIGRATIONS[0]` turns a schema 1
+/// file into schema 2, and so on. A schema change appends a step here and bumps
+/// `CONFIG_SCHEMA_VERSION`.
+const MIGRATIONS: [fn(&mut Map<String, Value>); 1] = [migrate_v1_to_v2];
+
+// Schema 1 is every file from before versioning. Its keys are unchanged in
+// schema 2, which only added `schema_version`.
+fn migrate_v1_to_v2(_file: &mut Map<String, Value>) {}
+
+/// One problem found in a config file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIssue {
+    /// Location of the offending value, e.g. `$.max_context_gap_ms`
+    pub path: String,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl ConfigIssue {
+    fn new(key: &str, message: impl Into<String>, suggestion: Option<String>) -> Self {
+        ConfigIssue { path: format!("$.{}", key), message: message.into(), suggestion }
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " ({})", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// A config file brought to the current schema
+#[derive(Debug)]
+pub struct ConfigFile {
+    /// Schema the file was written for
+    pub schema_version: u64,
+    /// The settings it overrides, without `schema_version`
+    pub overrides: Map<String, Value>,
+}
+
+impl ConfigFile {
+    pub fn migrated(&self) -> bool {
+        self.schema_version < CONFIG_SCHEMA_VERSION
+    }
+}
+
+/// Migrate a parsed config file to the current schema and validate it against
+/// `defaults`, the full set of settings with their command-line values: every key
+/// must be one of them and hold a value of the same JSON type
+pub fn load(file_json: Value, defaults: &Map<String, Value>) -> Result<ConfigFile, Vec<ConfigIssue>> {
+    let Value::Object(mut file) = file_json else {
+        return Err(vec![ConfigIssue {
+            path: "$".to_string(),
+            message: "config file must contain a JSON object".to_string(),
+            suggestion: None,
+        }]);
+    };
+    let schema_version = match file.remove(SCHEMA_VERSION_KEY) {
+        None => 1,
+        Some(value) => match value.as_u64() {
+            Some(version) if (1..=CONFIG_SCHEMA_VERSION).contains(&version) => version,
+            Some(version) if version > CONFIG_SCHEMA_VERSION => {
+                return Err(vec![ConfigIssue::new(
+                    SCHEMA_VERSION_KEY,
+                    format!("schema {} is newer than this server supports", version),
+                    Some(format!("this server reads schema {} and older", CONFIG_SCHEMA_VERSION)),
+                )])
+            }
+            _ => {
+                return Err(vec![ConfigIssue::new(
+                    SCHEMA_VERSION_KEY,
+                    format!("expected a schema number from 1 to {}, found {}", CONFIG_SCHEMA_VERSION, value),
+                    None,
+                )])
+            }
+        },
+    };
+    for migrate in &MIGRATIONS[schema_version as usize - 1..] {
+        migrate(&mut file);
+    }
+
+    let mut issues = Vec::new();
+    for (key, value) in &file {
+        match defaults.get(key) {
+            None => {
+                let suggestion = closest_key(key, defaults).map(|known| format!("did you mean {:?}?", known));
+                issues.push(ConfigIssue::new(key, "unknown setting", suggestion));
+            }
+            Some(default) => {
+                if let Some(expected) = type_mismatch(default, value) {
+                    let message = format!("expected {}, found {}", expected, value);
+                    issues.push(ConfigIssue::new(key, message, retype_suggestion(default, value)));
+                }
+            }
+        }
+    }
+    if issues.is_empty() {
+        Ok(ConfigFile { schema_version, overrides: file })
+    } else {
+        Err(issues)
+    }
+}
+
+/// Files written for every schema, with every setting at its default, must load
+/// unchanged (migrated when older), and files the current schema does not describe
+/// must be rejected
+pub fn check_schema(defaults: &Map<String, Value>) -> Vec<String> {
+    let mut failures = Vec::new();
+    if MIGRATIONS.len() as u64 + 1 != CONFIG_SCHEMA_VERSION {
+        failures.push(format!(
+            "{} migration(s) for schema {}; every older schema needs one",
+            MIGRATIONS.len(),
+            CONFIG_SCHEMA_VERSION
+        ));
+    }
+    // Schema 1 also covers files without schema_version
+    let versions = (1..=CONFIG_SCHEMA_VERSION).map(Some).chain([None]);
+    for version in versions {
+        let mut file = defaults.clone();
+        if let Some(version) = version {
+            file.insert(SCHEMA_VERSION_KEY.to_string(), Value::from(version));
+        }
+        let version = version.unwrap_or(1);
+        match load(Value::Object(file), defaults) {
+            Ok(loaded) if loaded.schema_version != version => {
+                failures.push(format!("schema {} file read as schema {}", version, loaded.schema_version))
+            }
+            Ok(loaded) if loaded.overrides != *defaults => {
+                failures.push(format!("schema {} file changes its settings on load", version))
+            }
+            Ok(_) => {}
+            Err(issues) => failures.push(format!("schema {} file rejected: {}", version, join_issues(&issues))),
+        }
+    }
+
+    let mut newer = Map::new();
+    newer.insert(SCHEMA_VERSION_KEY.to_string(), Value::from(CONFIG_SCHEMA_VERSION + 1));
+    let mut unknown = Map::new();
+    unknown.insert("no_such_setting".to_string(), Value::Bool(true));
+    let mut rejected = vec![("a newer schema", newer), ("an unknown setting", unknown)];
+    if let Some((key, default)) = defaults.iter().next() {
+        let retyped = if default.is_string() { Value::Bool(true) } else { Value::String("not a setting".to_string()) };
+        let mut file = Map::new();
+        file.insert(key.clone(), retyped);
+        rejected.push(("a value of the wrong type", file));
+    }
+    for (problem, file) in rejected {
+        if load(Value::Object(file), defaults).is_ok() {
+            failures.push(format!("file with {} accepted", problem));
+        }
+    }
+    // A near miss of a known key must point at it
+    if let Some(key) = defaults.keys().next() {
+        let mut file = Map::new();
+        file.insert(format!("{}x", key), defaults[key].clone());
+        let suggested = load(Value::Object(file), defaults)
+            .err()
+            .and_then(|issues| issues.into_iter().next())
+            .and_then(|issue| issue.suggestion)
+            .is_some_and(|suggestion| suggestion.contains(key.as_str()));
+        if !suggested {
+            failures.push(format!("misspelt {:?} gets no suggestion", key));
+        }
+    }
+    failures
+}
+
+fn join_issues(issues: &[ConfigIssue]) -> String {
+    issues.iter().map(ConfigIssue::to_string).collect::<Vec<_>>().join("; ")
+}
+
+// What `value` should have been, if it is not the JSON type of `default`
+fn type_mismatch(default: &Value, value: &Value) -> Option<&'static str> {
+    match default {
+        Value::Bool(_) if !value.is_boolean() => Some("true or false"),
+        Value::Number(n) if n.is_u64() && !value.is_u64() => Some("a non-negative integer"),
+        Value::Number(_) if !value.is_number() => Some("a number"),
+        Value::String(_) if !value.is_string() => Some("a string"),
+        _ => None,
+    }
+}
+
+// A quoted boolean or number gets a pointer at the unquoted form
+fn retype_suggestion(default: &Value, value: &Value) -> Option<String> {
+    let text = value.as_str()?.trim();
+    let fits = match default {
+        Value::Bool(_) => text.parse::<bool>().is_ok(),
+        Value::Number(n) if n.is_u64() => text.parse::<u64>().is_ok(),
+        Value::Number(_) => text.parse::<f64>().is_ok(),
+        _ => false,
+    };
+    fits.then(|| format!("write {} without quotes", text))
+}
+
+// The known key nearest to a misspelt one, if any is close enough to be the intent
+fn closest_key<'a>(key: &str, defaults: &'a Map<String, Value>) -> Option<&'a str> {
+    defaults
+        .keys()
+        .map(|known| (edit_distance(key, known), known.as_str()))
+        .filter(|(distance, _)| *distance <= 3.max(key.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
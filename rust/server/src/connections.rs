@@ -0,0 +1,206 @@
+//! Per-peer connection limits and idle connection reaping. Every accepted TCP
+//! connection is counted against its peer IP; once a peer holds
+//! `--max-connections-per-peer` connections, further ones are closed on accept and
+//! count in `connections_rejected_total{reason="peer_limit"}`. A connection that
+//! moves no bytes either way for `--idle-connection-timeout-secs` is closed and
+//! counts in `connections_reaped_total`. Together they keep a load generator that
+//! leaks connections from exhausting the server's file descriptors.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{Instant, Sleep};
+use tokio_stream::wrappers::TcpListenerStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::server::{Connected, TcpConnectInfo};
+use tracing::{debug, warn};
+
+use crate::config::ServerConfig;
+use crate::metrics::Metrics;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionLimits {
+    /// Open connections a single peer IP may hold (0 = unlimited)
+    pub max_per_peer: usize,
+    /// Time without traffic after which a connection is closed (None = never)
+    pub idle_timeout: Option<Duration>,
+}
+
+impl ConnectionLimits {
+    pub fn from_server_config(config: &ServerConfig) -> Self {
+        ConnectionLimits {
+            max_per_peer: config.max_connections_per_peer,
+            idle_timeout: (config.idle_connection_timeout_secs > 0)
+                .then(|| Duration::from_secs(config.idle_connection_timeout_secs)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConnectionTracker {
+    limits: ConnectionLimits,
+    peers: Mutex<HashMap<IpAddr, usize>>,
+    metrics: Arc<Metrics>,
+}
+
+impl ConnectionTracker {
+    pub fn new(limits: ConnectionLimits, metrics: Arc<Metrics>) -> Arc<Self> {
+        Arc::new(ConnectionTracker { limits, peers: Mutex::new(HashMap::new()), metrics })
+    }
+
+    /// The listener's connections, less those refused at the per-peer limit
+    pub fn incoming(self: Arc<Self>, listener: TcpListener) -> impl Stream<Item = io::Result<TrackedConnection>> {
+        TcpListenerStream::new(listener).filter_map(move |accepted| match accepted {
+            Ok(stream) => self.clone().admit(stream).map(Ok),
+            Err(e) => Some(Err(e)),
+        })
+    }
+
+    fn admit(self: Arc<Self>, stream: TcpStream) -> Option<TrackedConnection> {
+        let peer = stream.peer_addr().ok().map(|addr| addr.ip());
+        if let Some(ip) = peer {
+            let mut peers = self.peers.lock().unwrap();
+            let open = peers.entry(ip).or_default();
+            if self.limits.max_per_peer > 0 && *open >= self.limits.max_per_peer {
+                let open = *open;
+                drop(peers);
+                self.metrics.inc("connections_rejected_total", &[("reason", "peer_limit")]);
+                warn!(
+                    peer = %ip,
+                    open = open,
+                    limit = self.limits.max_per_peer,
+                    "Refusing connection - peer at its connection limit"
+                );
+                return None;
+            }
+            *open += 1;
+            self.export(&peers);
+        }
+        self.metrics.inc("connections_accepted_total", &[]);
+        let idle = self.limits.idle_timeout.map(|timeout| Box::pin(tokio::time::sleep(timeout)));
+        Some(TrackedConnection { inner: stream, peer, tracker: self, idle, reaped: false })
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut peers = self.peers.lock().unwrap();
+        if let Some(open) = peers.get_mut(&ip) {
+            *open -= 1;
+            if *open == 0 {
+                peers.remove(&ip);
+            }
+        }
+        self.export(&peers);
+    }
+
+    fn export(&self, peers: &HashMap<IpAddr, usize>) {
+        self.metrics.set_gauge("connections_open", &[], peers.values().sum::<usize>() as i64);
+        self.metrics.set_gauge("connection_peers", &[], peers.len() as i64);
+    }
+}
+
+/// An accepted connection, counted against its peer until dropped
+pub struct TrackedConnection {
+    inner: TcpStream,
+    peer: Option<IpAddr>,
+    tracker: Arc<ConnectionTracker>,
+    // Deadline pushed back by every read or write that moves bytes
+    idle: Option<Pin<Box<Sleep>>>,
+    reaped: bool,
+}
+
+impl TrackedConnection {
+    fn touch(&mut self) {
+        if let (Some(idle), Some(timeout)) = (&mut self.idle, self.tracker.limits.idle_timeout) {
+            idle.as_mut().reset(Instant::now() + timeout);
+        }
+    }
+
+    // Registers the idle deadline with the task, so a connection nobody reads from
+    // is still woken up to be reaped
+    fn idle_expired(&mut self, cx: &mut Context<'_>) -> bool {
+        let expired = self.idle.as_mut().is_some_and(|idle| idle.as_mut().poll(cx).is_ready());
+        if expired && !self.reaped {
+            self.reaped = true;
+            self.tracker.metrics.inc("connections_reaped_total", &[]);
+            debug!(peer = ?self.peer, "Reaping idle connection");
+        }
+        expired
+    }
+}
+
+impl Drop for TrackedConnection {
+    fn drop(&mut self) {
+        if let Some(ip) = self.peer {
+            self.tracker.release(ip);
+        }
+    }
+}
+
+impl Connected for TrackedConnection {
+    type ConnectInfo = TcpConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.inner.connect_info()
+    }
+}
+
+impl AsyncRead for TrackedConnection {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let before = buf.filled().len();
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) if buf.filled().len() > before => {
+                this.touch();
+                Poll::Ready(Ok(()))
+            }
+            Poll::Pending if this.idle_expired(cx) => {
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::TimedOut, "connection idle")))
+            }
+            other => other,
+        }
+    }
+}
+
+impl AsyncWrite for TrackedConnection {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if matches!(result, Poll::Ready(Ok(written)) if written > 0) {
+            this.touch();
+        }
+        result
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let result = Pin::new(&mut this.inner).poll_write_vectored(cx, bufs);
+        if matches!(result, Poll::Ready(Ok(written)) if written > 0) {
+            this.touch();
+        }
+        result
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
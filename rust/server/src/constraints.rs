@@ -0,0 +1,96 @@
+use tracing::info;
+
+use crate::ads::{Ad, AdsList};
+
+/// Category that must occupy the first slot when slot constraints are enabled
+pub const SPONSORED_BRANDS: &str = "sponsored_brands";
+
+/// Slot rules applied after ranking. Reordering is greedy and stable: ads keep
+/// their ranked order except where a rule forces a move.
+#[derive(Debug, Clone)]
+pub struct SlotConstraints {
+    /// Category required in position 1 (if any candidate has it)
+    pub first_slot_category: String,
+    /// Maximum ads from one advertiser within the top `advertiser_window` positions
+    pub max_per_advertiser: usize,
+    pub advertiser_window: usize,
+}
+
+impl Default for SlotConstraints {
+    fn default() -> Self {
+        SlotConstraints {
+            first_slot_category: SPONSORED_BRANDS.to_string(),
+            max_per_advertiser: 2,
+            advertiser_window: 5,
+        }
+    }
+}
+
+impl SlotConstraints {
+    /// Apply the slot rules to an AdsList, per query partition or placement
+    pub fn apply_list(&self, ads_list: &mut AdsList, session_id: u64, version: u32) {
+        self.apply(&mut ads_list.ads, session_id, version);
+        for partition in &mut ads_list.query_results {
+            self.apply(&mut partition.ads, session_id, version);
+        }
+        for placement in &mut ads_list.placement_results {
+            self.apply(&mut placement.ads, session_id, version);
+        }
+    }
+
+    /// Reorder ranked ads to satisfy the slot rules, logging every constraint-induced move
+    pub fn apply(&self, ads: &mut Vec<Ad>, session_id: u64, version: u32) {
+        let original: Vec<String> = ads.iter().map(|ad| ad.ad_id.clone()).collect();
+        let mut remaining: Vec<Ad> = std::mem::take(ads);
+        let mut placed: Vec<Ad> = Vec::with_capacity(remaining.len());
+
+        // Rule 1: the best ad of the required category takes position 1
+        match remaining.iter().position(|ad| ad.category == self.first_slot_category) {
+            Some(idx) => placed.push(remaining.remove(idx)),
+            None => info!(
+                session_id = session_id,
+                version = version,
+                category = %self.first_slot_category,
+                "Slot constraint unsatisfiable - no candidate for position 1"
+            ),
+        }
+
+        // Rule 2: cap ads per advertiser within the top window, deferring the overflow
+        let mut deferred: Vec<Ad> = Vec::new();
+        for ad in remaining {
+            let in_window = placed.len() < self.advertiser_window;
+            let same_advertiser = placed
+                .iter()
+                .take(self.advertiser_window)
+                .filter(|p| p.advertiser_id == ad.advertiser_id)
+                .count();
+            if in_window && same_advertiser >= self.max_per_advertiser {
+                deferred.push(ad);
+            } else {
+                placed.push(ad);
+            }
+            // Deferred ads fill in as soon as the window has passed
+            if placed.len() >= self.advertiser_window && !deferred.is_empty() {
+                placed.append(&mut deferred);
+            }
+        }
+        placed.append(&mut deferred);
+        *ads = placed;
+
+        for (new_pos, ad) in ads.iter().enumerate() {
+            let old_pos = original.iter().position(|id| *id == ad.ad_id).unwrap_or(new_pos);
+            if old_pos != new_pos {
+                info!(
+                    session_id = session_id,
+                    version = version,
+                    ad_id = %ad.ad_id,
+                    advertiser_id = %ad.advertiser_id,
+                    category = %ad.category,
+                    from_position = old_pos + 1,
+                    to_position = new_pos + 1,
+                    "Slot constraint reordered ad"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use tokio::task::{JoinError, JoinSet};
+use tonic::Status;
+use tracing::{error, warn, Instrument};
+
+use ads_proto::score::sanitize_list;
+
+use crate::ads::{AdsList, Context};
+use crate::backpressure::AdsSender;
+use crate::catalog::CatalogEntry;
+use crate::features::FeatureLog;
+use crate::generator::{generate_ads, generate_ads_with_features, Ranking};
+use crate::metrics::Metrics;
+use crate::plugin::GeneratorPlugin;
+use crate::treatment::Treatment;
+use crate::variant::GeneratorVariant;
+
+/// Spawn a per-session task so that a panic inside it ends only that stream: the
+/// client receives `Status::internal` carrying a panic id that is also logged,
+/// instead of a silently dead task and a client waiting for its timeout. The task
+/// runs in the caller's current span, so it keeps the session's log fields and
+/// debug level.
+pub fn spawn_session_task<F>(session_id: u64, tx: AdsSender, metrics: Arc<Metrics>, task: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let handle = tokio::spawn(task.in_current_span());
+    tokio::spawn(async move {
+        match handle.await {
+            Ok(()) => {}
+            Err(e) if e.is_panic() => {
+                let status = panic_status(session_id, "session_task", e.into_panic(), &metrics);
+                let _ = tx.send(Err(status)).await;
+            }
+            Err(e) => warn!(session_id = session_id, error = %e, "Session task cancelled"),
+        }
+    });
+}
+
+/// Spawn a task the session tracks in `tasks` and awaits before it ends, instead of
+/// leaving it detached like `spawn_session_task`; pass each joined result to
+/// `session_task_joined`, which contains a panic the same way.
+pub fn spawn_tracked_session_task<F>(tasks: &mut JoinSet<()>, task: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    tasks.spawn(task.in_current_span());
+}
+
+/// Handle a task of `spawn_tracked_session_task` that has been joined: a panic
+/// fails the stream with `Status::internal`, an aborted task is expected
+pub async fn session_task_joined(session_id: u64, joined: Result<(), JoinError>, tx: &AdsSender, metrics: &Metrics) {
+    match joined {
+        Ok(()) => {}
+        Err(e) if e.is_panic() => {
+            let status = panic_status(session_id, "session_task", e.into_panic(), metrics);
+            let _ = tx.send(Err(status)).await;
+        }
+        Err(_) => {}
+    }
+}
+
+/// `generate_ads` with `variant` (or the generator plugin, when one is loaded) with a panic in
+/// generation converted to `Status::internal`. Plugins catch their own panics at
+/// the ABI boundary and report them as errors instead, and never see `trajectory`,
+/// the earlier queries of the session. Sessions sampled by
+/// `feature_log` have the built-in generator's score inputs recorded there. Invalid
+/// scores in the output are clamped by `guard_scores`, and every ad is tagged with
+/// its experiment treatment.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_contained(
+    context: &Context,
+    trajectory: &[String],
+    version: u32,
+    session_seed: u64,
+    catalog: &BTreeMap<String, CatalogEntry>,
+    plugin: Option<&GeneratorPlugin>,
+    session_id: u64,
+    metrics: &Metrics,
+    feature_log: Option<&FeatureLog>,
+    ranking: Ranking,
+    variant: GeneratorVariant,
+) -> Result<AdsList, Status> {
+    if let Some(plugin) = plugin {
+        let mut ads_list = plugin.generate(context, version, session_seed).inspect_err(|status| {
+            metrics.inc("plugin_errors_total", &[("plugin", plugin.name())]);
+            error!(session_id = session_id, error = %status.message(), "Generator plugin failed");
+        })?;
+        guard_scores(&mut ads_list, "plugin", metrics);
+        Treatment::new(Some(plugin), variant, ranking).tag(&mut ads_list);
+        return Ok(ads_list);
+    }
+    let Some(feature_log) = feature_log.filter(|log| log.samples(session_id)) else {
+        let mut ads_list = panic::catch_unwind(AssertUnwindSafe(|| {
+            generate_ads(context, trajectory, version, session_seed, catalog, ranking, variant)
+        }))
+        .map_err(|payload| panic_status(session_id, "generator", payload, metrics))?;
+        guard_scores(&mut ads_list, "generator", metrics);
+        Treatment::new(None, variant, ranking).tag(&mut ads_list);
+        return Ok(ads_list);
+    };
+    let (mut ads_list, features) =
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            generate_ads_with_features(context, trajectory, version, session_seed, catalog, ranking, variant)
+        }))
+        .map_err(|payload| panic_status(session_id, "generator", payload, metrics))?;
+    feature_log.record(session_id, version, context, &features);
+    metrics.inc("feature_records_total", &[]);
+    guard_scores(&mut ads_list, "generator", metrics);
+    Treatment::new(None, variant, ranking).tag(&mut ads_list);
+    Ok(ads_list)
+}
+
+/// Clamp every NaN, infinite or out-of-range score in `ads_list` so it never reaches
+/// the wire, counting each in `invalid_scores_total{stage, reason}`. `stage` names
+/// where the list came from: the generator, a plugin, or the final send.
+pub fn guard_scores(ads_list: &mut AdsList, stage: &'static str, metrics: &Metrics) {
+    for (ad_id, invalid) in sanitize_list(ads_list) {
+        metrics.inc("invalid_scores_total", &[("stage", stage), ("reason", invalid.name())]);
+        warn!(
+            ad_id = %ad_id,
+            version = ads_list.version,
+            stage = stage,
+            reason = invalid.name(),
+            "Clamped invalid score"
+        );
+    }
+}
+
+fn panic_status(session_id: u64, site: &'static str, payload: Box<dyn Any + Send>, metrics: &Metrics) -> Status {
+    let panic_id = format!("{:016x}", rand::random::<u64>());
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "<non-string panic payload>".to_string());
+    metrics.inc("session_panics_total", &[("site", site)]);
+    error!(
+        session_id = session_id,
+        panic_id = %panic_id,
+        site = site,
+        panic_message = %message,
+        "Panic contained - aborting session stream"
+    );
+    Status::internal(format!("internal error (panic_id={})", panic_id))
+}
@@ -0,0 +1,69 @@
+//! Per-session debug logging requested by the client with `x-debug-session: true`.
+//! A granted session's `session` span carries `debug_session = true`, and the log
+//! filter lets DEBUG events through inside such spans, so one problematic request
+//! can be traced in detail while the rest of the run stays at the normal level.
+//! Grants are rate limited so a client cannot turn a busy server into a log flood.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tonic::metadata::MetadataMap;
+use tracing::{info, warn};
+
+use ads_proto::DEBUG_SESSION_METADATA_KEY;
+
+use crate::metrics::Metrics;
+
+/// Log filter directive raising sessions with a granted debug request to DEBUG
+pub const DEBUG_SESSION_DIRECTIVE: &str = "[session{debug_session=true}]=debug";
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Grants at most `per_minute` debug sessions in any fixed one-minute window
+#[derive(Debug)]
+pub struct DebugSessionGate {
+    per_minute: u32,
+    // Start of the current window and the grants made in it
+    window: Mutex<(Instant, u32)>,
+    metrics: Arc<Metrics>,
+}
+
+impl DebugSessionGate {
+    pub fn new(per_minute: u32, metrics: Arc<Metrics>) -> Self {
+        DebugSessionGate { per_minute, window: Mutex::new((Instant::now(), 0)), metrics }
+    }
+
+    /// Whether the session with request `metadata` gets debug logging
+    pub fn admit(&self, session_id: u64, metadata: &MetadataMap) -> bool {
+        let requested = metadata
+            .get(DEBUG_SESSION_METADATA_KEY)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| matches!(value.trim().to_ascii_lowercase().as_str(), "true" | "1"));
+        if !requested {
+            return false;
+        }
+        let granted = {
+            let mut window = self.window.lock().unwrap();
+            if window.0.elapsed() >= WINDOW {
+                *window = (Instant::now(), 0);
+            }
+            let granted = window.1 < self.per_minute;
+            if granted {
+                window.1 += 1;
+            }
+            granted
+        };
+        let outcome = if granted { "granted" } else { "rate_limited" };
+        self.metrics.inc("debug_sessions_total", &[("outcome", outcome)]);
+        if granted {
+            info!(session_id = session_id, "Debug logging enabled for session");
+        } else {
+            warn!(
+                session_id = session_id,
+                per_minute = self.per_minute,
+                "Debug session request over the rate limit - logging normally"
+            );
+        }
+        granted
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use clap::ValueEnum;
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use tonic::Status;
+use tracing::info;
+
+use crate::ads::AdsList;
+use crate::backpressure::AdsReceiver;
+
+type Item = Result<AdsList, Status>;
+
+/// What to do when a stream arrives with the idempotency key of a session that is still active
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Stream the original session's AdsLists (already sent ones first) instead of regenerating
+    Attach,
+    /// Ignore the key and run an independent session
+    Regenerate,
+    /// Fail the duplicate with ALREADY_EXISTS
+    Reject,
+}
+
+impl DuplicatePolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DuplicatePolicy::Attach => "attach",
+            DuplicatePolicy::Regenerate => "regenerate",
+            DuplicatePolicy::Reject => "reject",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SharedState {
+    history: Vec<Item>,
+    subscribers: Vec<mpsc::Sender<Item>>,
+    done: bool,
+}
+
+/// Output of one keyed session, fanned out to the original stream and any attached retries
+#[derive(Debug, Default)]
+pub struct SharedSession {
+    state: Mutex<SharedState>,
+}
+
+impl SharedSession {
+    /// A receiver that first replays everything published so far, then follows the session
+    pub fn subscribe(&self) -> mpsc::Receiver<Item> {
+        let mut state = self.state.lock().unwrap();
+        let (tx, rx) = mpsc::channel(state.history.len() + 128);
+        for item in &state.history {
+            let _ = tx.try_send(item.clone());
+        }
+        if !state.done {
+            state.subscribers.push(tx);
+        }
+        rx
+    }
+
+    fn publish(&self, item: Item) {
+        let mut state = self.state.lock().unwrap();
+        // Subscribers that went away (or fell 128 items behind) are dropped
+        state.subscribers.retain(|tx| tx.try_send(item.clone()).is_ok());
+        state.history.push(item);
+    }
+
+    fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.done = true;
+        state.subscribers.clear();
+    }
+}
+
+pub enum Registration {
+    /// First stream with this key; the session's output is now published through `shared`
+    New(Arc<SharedSession>),
+    /// A session with this key became active first; `output` is handed back unused
+    Existing(AdsReceiver),
+}
+
+/// Active sessions by client idempotency key
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    sessions: Mutex<HashMap<String, Arc<SharedSession>>>,
+}
+
+impl SessionRegistry {
+    /// The still-active session registered under `key`, if any
+    pub fn active(&self, key: &str) -> Option<Arc<SharedSession>> {
+        self.sessions.lock().unwrap().get(key).cloned()
+    }
+
+    /// Look up `key`, registering a new shared session if none is active. For a new
+    /// session a forwarder publishes everything arriving on `output`; the entry is
+    /// removed once every sender of the session is gone (including its delayed
+    /// refinement tasks), so the session outlives a disconnected original stream.
+    pub fn register(self: &Arc<Self>, key: &str, output: AdsReceiver) -> Registration {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.contains_key(key) {
+            return Registration::Existing(output);
+        }
+        let shared = Arc::new(SharedSession::default());
+        sessions.insert(key.to_string(), shared.clone());
+        drop(sessions);
+
+        let mut rx = output;
+        let registry = self.clone();
+        let forwarded = shared.clone();
+        let key = key.to_string();
+        tokio::spawn(async move {
+            while let Some(item) = rx.next().await {
+                forwarded.publish(item);
+            }
+            forwarded.finish();
+            let mut sessions = registry.sessions.lock().unwrap();
+            if sessions.get(&key).is_some_and(|s| Arc::ptr_eq(s, &forwarded)) {
+                sessions.remove(&key);
+            }
+            info!(idempotency_key = %key, "Keyed session finished");
+        });
+        Registration::New(shared)
+    }
+}
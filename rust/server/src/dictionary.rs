@@ -0,0 +1,127 @@
+//! `train-dictionary`: train a zstd dictionary for `--zstd-dictionary` (see
+//! `ads_proto::codec`) from sessions captured with `ads-client --record`. Every
+//! captured Context is encoded as the client sends it, at version 1 without the
+//! understanding and at later versions with it, and the built-in generator's
+//! AdsLists for versions 1 to 3 are encoded as the server would send them; the
+//! dictionary is trained over those messages. A size comparison of the messages
+//! without compression, with per-message zstd and with the dictionary is printed
+//! afterwards. It is measured on the training messages themselves, so expect
+//! somewhat larger sizes on traffic the dictionary has not seen.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use ads_common::limits::{FINAL_VERSION, INITIAL_VERSION};
+use ads_common::{Error, Result};
+use prost::Message;
+
+use crate::batch;
+use crate::catalog::Catalog;
+use crate::config::TrainDictionaryArgs;
+use crate::containment;
+use crate::generator::Ranking;
+use crate::metrics::Metrics;
+use crate::variant::GeneratorVariant;
+
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Encoded messages of one kind
+#[derive(Debug, Default)]
+struct Samples {
+    kind: &'static str,
+    messages: Vec<Vec<u8>>,
+}
+
+pub fn train(args: &TrainDictionaryArgs) -> Result<()> {
+    let catalog = Catalog::default().snapshot();
+    let metrics = Metrics::default();
+    let mut contexts = Samples { kind: "Context", messages: Vec::new() };
+    let mut lists = Samples { kind: "AdsList", messages: Vec::new() };
+    let (mut captures, mut skipped) = (0, 0);
+
+    let reader = BufReader::new(File::open(&args.captures)?);
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let context = match batch::parse_context(&line) {
+            Ok(context) => context,
+            Err(e) => {
+                eprintln!("Skipping capture line {}: {}", i + 1, e);
+                skipped += 1;
+                continue;
+            }
+        };
+        captures += 1;
+        for version in INITIAL_VERSION..=FINAL_VERSION {
+            let mut versioned = context.clone();
+            if version == INITIAL_VERSION {
+                versioned.understanding.clear();
+            }
+            contexts.messages.push(versioned.encode_to_vec());
+            let generated = containment::generate_contained(
+                &versioned,
+                &[],
+                version,
+                context.seed,
+                &catalog,
+                None,
+                i as u64 + 1,
+                &metrics,
+                None,
+                Ranking::default(),
+                GeneratorVariant::Catalog,
+            );
+            if let Ok(ads_list) = generated {
+                lists.messages.push(ads_list.encode_to_vec());
+            }
+        }
+    }
+    if contexts.messages.is_empty() {
+        return Err(Error::invalid(format!("{}: no usable captures", args.captures.display())));
+    }
+
+    let samples: Vec<&Vec<u8>> = contexts.messages.iter().chain(&lists.messages).collect();
+    let dictionary = zstd::dict::from_samples(&samples, args.max_size).map_err(|e| {
+        Error::invalid(format!("training on {} messages failed ({}); record more sessions", samples.len(), e))
+    })?;
+    std::fs::write(&args.output, &dictionary)?;
+    println!(
+        "Trained a {} byte dictionary on {} messages from {} captures ({} skipped) into {}",
+        dictionary.len(),
+        samples.len(),
+        captures,
+        skipped,
+        args.output.display()
+    );
+
+    println!("{:<8} {:>9} {:>12} {:>12} {:>14}", "message", "count", "raw_bytes", "zstd_bytes", "zstd+dict_bytes");
+    for samples in [&contexts, &lists] {
+        let (raw, plain, dict) = sizes(samples, &dictionary)?;
+        println!(
+            "{:<8} {:>9} {:>12} {:>12} {:>14}   ({:.1}% of raw, {:.1}% of zstd)",
+            samples.kind,
+            samples.messages.len(),
+            raw,
+            plain,
+            dict,
+            dict as f64 * 100.0 / raw.max(1) as f64,
+            dict as f64 * 100.0 / plain.max(1) as f64,
+        );
+    }
+    Ok(())
+}
+
+// Total raw, per-message zstd and dictionary-compressed sizes of the samples, the
+// last one including the codec's marker byte
+fn sizes(samples: &Samples, dictionary: &[u8]) -> Result<(usize, usize, usize)> {
+    let mut compressor = zstd::bulk::Compressor::with_dictionary(COMPRESSION_LEVEL, dictionary)?;
+    let (mut raw_bytes, mut zstd_bytes, mut dict_bytes) = (0, 0, 0);
+    for message in &samples.messages {
+        raw_bytes += message.len();
+        zstd_bytes += zstd::bulk::compress(message, COMPRESSION_LEVEL)?.len();
+        dict_bytes += compressor.compress(message)?.len() + 1;
+    }
+    Ok((raw_bytes, zstd_bytes, dict_bytes))
+}
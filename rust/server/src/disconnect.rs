@@ -0,0 +1,69 @@
+//! What happens to a bidirectional session's pending refinement when the client
+//! disconnects before it is delivered (`--disconnect-policy`). `cancel` stops it at
+//! once. `finish` generates it anyway and records the undelivered version in the
+//! session journal, for analysis of what clients dropping early miss. `grace` keeps
+//! generating for up to `--disconnect-grace-ms` and checkpoints the result, so a
+//! client reconnecting with its session token is sent the version it missed
+//! instead of nothing. Every such disconnect counts in
+//! `early_disconnects_total{policy}`.
+
+use std::future::Future;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use tokio::time::timeout;
+use tracing::info;
+
+use crate::backpressure::AdsSender;
+use crate::metrics::Metrics;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisconnectPolicy {
+    #[default]
+    Cancel,
+    Finish,
+    Grace,
+}
+
+impl DisconnectPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisconnectPolicy::Cancel => "cancel",
+            DisconnectPolicy::Finish => "finish",
+            DisconnectPolicy::Grace => "grace",
+        }
+    }
+
+    /// Run `refinement`, applying the policy if the client's end of `tx` goes away first
+    pub async fn watch<F>(&self, grace: Duration, tx: &AdsSender, metrics: &Metrics, session_id: u64, refinement: F)
+    where
+        F: Future<Output = ()>,
+    {
+        tokio::pin!(refinement);
+        tokio::select! {
+            _ = &mut refinement => return,
+            _ = tx.closed() => {}
+        }
+        metrics.inc("early_disconnects_total", &[("policy", self.name())]);
+        match self {
+            DisconnectPolicy::Cancel => {
+                info!(session_id = session_id, "Client disconnected mid-refinement - cancelling it");
+            }
+            DisconnectPolicy::Finish => {
+                info!(session_id = session_id, "Client disconnected mid-refinement - finishing it for the journal");
+                refinement.await;
+            }
+            DisconnectPolicy::Grace => {
+                info!(
+                    session_id = session_id,
+                    grace_ms = grace.as_millis() as u64,
+                    "Client disconnected mid-refinement - finishing it for a reconnect"
+                );
+                if timeout(grace, refinement).await.is_err() {
+                    metrics.inc("disconnect_grace_expired_total", &[]);
+                    info!(session_id = session_id, "Disconnect grace period expired - refinement cancelled");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Simulated downstream dependencies (a budget service, a policy service, ...) that
+//! generation calls for every AdsList version before ranking, so server latency
+//! includes a realistic fan-out with its own tail and failures instead of only
+//! local compute. Each call sleeps for its latency and may fail; calls run in
+//! parallel by default and each is bounded by the downstream timeout.
+
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, timeout};
+use tonic::Status;
+use tracing::{debug, warn};
+
+use crate::metrics::Metrics;
+
+/// One simulated dependency, given as `NAME:LATENCY_MS[:JITTER_MS[:FAILURE_RATE]]`,
+/// e.g. `budget:20:10:0.01`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    pub name: String,
+    pub latency: Duration,
+    /// Extra latency drawn uniformly from 0..=jitter per call
+    pub jitter: Duration,
+    /// Chance (0.0..=1.0) that a call fails after its latency
+    pub failure_rate: f64,
+}
+
+impl FromStr for Dependency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').map(str::trim).collect();
+        let (name, rest) = match parts.split_first() {
+            Some((name, rest)) if !name.is_empty() && (1..=3).contains(&rest.len()) => (*name, rest),
+            _ => return Err(format!("{}: expected NAME:LATENCY_MS[:JITTER_MS[:FAILURE_RATE]]", s)),
+        };
+        let millis = |value: &str| {
+            value.parse::<u64>().map(Duration::from_millis).map_err(|_| format!("{}: invalid milliseconds {:?}", s, value))
+        };
+        let latency = millis(rest[0])?;
+        let jitter = rest.get(1).map_or(Ok(Duration::ZERO), |value| millis(value))?;
+        let failure_rate = match rest.get(2) {
+            Some(value) => value
+                .parse::<f64>()
+                .ok()
+                .filter(|rate| (0.0..=1.0).contains(rate))
+                .ok_or_else(|| format!("{}: failure rate must be between 0.0 and 1.0", s))?,
+            None => 0.0,
+        };
+        Ok(Dependency { name: name.to_string(), latency, jitter, failure_rate })
+    }
+}
+
+/// What generation does when a dependency call fails or times out
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailureMode {
+    /// Fail the version with UNAVAILABLE (or DEADLINE_EXCEEDED on a timeout)
+    #[default]
+    Fail,
+    /// Log the failure and generate without the dependency
+    Degrade,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Ok,
+    Error,
+    Timeout,
+}
+
+impl Outcome {
+    fn name(self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::Error => "error",
+            Outcome::Timeout => "timeout",
+        }
+    }
+}
+
+impl Dependency {
+    async fn call(&self, call_timeout: Duration) -> Outcome {
+        let jitter_ms = self.jitter.as_millis() as u64;
+        let extra_ms = if jitter_ms > 0 { rand::random::<u64>() % (jitter_ms + 1) } else { 0 };
+        let latency = self.latency + Duration::from_millis(extra_ms);
+        let call = async {
+            sleep(latency).await;
+            if rand::random::<f64>() < self.failure_rate {
+                Outcome::Error
+            } else {
+                Outcome::Ok
+            }
+        };
+        timeout(call_timeout, call).await.unwrap_or(Outcome::Timeout)
+    }
+}
+
+/// The dependencies called before every generation
+#[derive(Debug)]
+pub struct Downstream {
+    dependencies: Vec<Dependency>,
+    timeout: Duration,
+    parallel: bool,
+    on_failure: FailureMode,
+    metrics: Arc<Metrics>,
+}
+
+impl Downstream {
+    pub fn new(
+        dependencies: Vec<Dependency>,
+        timeout: Duration,
+        parallel: bool,
+        on_failure: FailureMode,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Downstream { dependencies, timeout, parallel, on_failure, metrics }
+    }
+
+    /// Call every dependency for `version` of a session. Under `FailureMode::Fail`
+    /// the first failed dependency (in configuration order) fails the version.
+    pub async fn call_all(&self, session_id: u64, version: u32) -> Result<(), Status> {
+        if self.dependencies.is_empty() {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let outcomes: Vec<(usize, Outcome)> = if self.parallel {
+            let mut calls = JoinSet::new();
+            for (index, dependency) in self.dependencies.iter().cloned().enumerate() {
+                let call_timeout = self.timeout;
+                calls.spawn(async move { (index, dependency.call(call_timeout).await) });
+            }
+            let mut outcomes = Vec::with_capacity(self.dependencies.len());
+            while let Some(joined) = calls.join_next().await {
+                outcomes.push(joined.unwrap_or((usize::MAX, Outcome::Error)));
+            }
+            outcomes.sort_by_key(|(index, _)| *index);
+            outcomes
+        } else {
+            let mut outcomes = Vec::with_capacity(self.dependencies.len());
+            for (index, dependency) in self.dependencies.iter().enumerate() {
+                outcomes.push((index, dependency.call(self.timeout).await));
+            }
+            outcomes
+        };
+        self.metrics.observe_ms("downstream_fanout_ms", &[], start.elapsed());
+
+        let mut failure = None;
+        for (index, outcome) in outcomes {
+            let name = self.dependencies.get(index).map_or("unknown", |dependency| dependency.name.as_str());
+            self.metrics.inc("downstream_calls_total", &[("dependency", name), ("outcome", outcome.name())]);
+            if outcome == Outcome::Ok {
+                continue;
+            }
+            warn!(
+                session_id = session_id,
+                version = version,
+                dependency = name,
+                outcome = outcome.name(),
+                on_failure = ?self.on_failure,
+                "Downstream dependency call failed"
+            );
+            if failure.is_none() {
+                failure = Some(match outcome {
+                    Outcome::Timeout => Status::deadline_exceeded(format!("downstream {} timed out", name)),
+                    _ => Status::unavailable(format!("downstream {} unavailable", name)),
+                });
+            }
+        }
+        debug!(
+            session_id = session_id,
+            version = version,
+            dependencies = self.dependencies.len(),
+            fanout_ms = start.elapsed().as_millis() as u64,
+            "Downstream fan-out done"
+        );
+        match (failure, self.on_failure) {
+            (Some(status), FailureMode::Fail) => Err(status),
+            _ => Ok(()),
+        }
+    }
+}
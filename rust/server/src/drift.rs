@@ -0,0 +1,172 @@
+//! Score drift detection (`--score-drift`). Every score the server sends is recorded
+//! against its AdsList version. The first `--score-drift-window` scores of a version
+//! are frozen as its baseline, and the most recent `--score-drift-window` scores form
+//! a rolling window. Each evaluation exports the rolling mean, variance and KS
+//! distance from the baseline per version, plus the KS distance between consecutive
+//! versions. It alerts when a version drifts from its baseline beyond
+//! `--score-drift-max-ks` or its mean moves more than `--score-drift-max-mean-shift`
+//! baseline standard deviations, which catches scoring regressions while a generator
+//! is being experimented with.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::ads::AdsList;
+use crate::config::ServerConfig;
+use crate::metrics::Metrics;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DriftConfig {
+    /// Scores in the baseline and in the rolling window of each version
+    pub window: usize,
+    /// KS distance from the baseline above which a version alerts
+    pub max_ks: f64,
+    /// Mean shift, in baseline standard deviations, above which a version alerts
+    pub max_mean_shift: f64,
+}
+
+impl DriftConfig {
+    pub fn from_server_config(config: &ServerConfig) -> Self {
+        DriftConfig {
+            window: config.score_drift_window,
+            max_ks: config.score_drift_max_ks,
+            max_mean_shift: config.score_drift_max_mean_shift,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct VersionScores {
+    baseline: Vec<f64>,
+    recent: VecDeque<f64>,
+}
+
+/// Mean and variance of one sample
+#[derive(Debug, Clone, Copy)]
+struct Moments {
+    mean: f64,
+    variance: f64,
+}
+
+impl Moments {
+    fn of(scores: &[f64]) -> Self {
+        let n = scores.len() as f64;
+        let mean = scores.iter().sum::<f64>() / n;
+        let variance = scores.iter().map(|score| (score - mean).powi(2)).sum::<f64>() / n;
+        Moments { mean, variance }
+    }
+}
+
+#[derive(Debug)]
+pub struct ScoreDriftMonitor {
+    config: DriftConfig,
+    versions: Mutex<BTreeMap<u32, VersionScores>>,
+    metrics: Arc<Metrics>,
+}
+
+impl ScoreDriftMonitor {
+    pub fn new(config: DriftConfig, metrics: Arc<Metrics>) -> Self {
+        ScoreDriftMonitor { config, versions: Mutex::new(BTreeMap::new()), metrics }
+    }
+
+    /// Record the scores of a sent AdsList; non-finite scores are left to the score guard
+    pub fn record(&self, ads_list: &AdsList) {
+        let mut versions = self.versions.lock().unwrap();
+        let scores = versions.entry(ads_list.version).or_default();
+        for score in ads_list.ads.iter().map(|ad| ad.score).filter(|score| score.is_finite()) {
+            if scores.baseline.len() < self.config.window {
+                scores.baseline.push(score);
+            }
+            scores.recent.push_back(score);
+            if scores.recent.len() > self.config.window {
+                scores.recent.pop_front();
+            }
+        }
+    }
+
+    /// Export the statistics of every version as gauges and log drift alerts
+    pub fn evaluate(&self) {
+        let versions = self.versions.lock().unwrap();
+        let mut previous: Option<(u32, Vec<f64>)> = None;
+        for (version, scores) in versions.iter() {
+            let recent: Vec<f64> = scores.recent.iter().copied().collect();
+            if recent.is_empty() {
+                continue;
+            }
+            let version_label = version.to_string();
+            let labels = [("version", version_label.as_str())];
+            let moments = Moments::of(&recent);
+            self.metrics.set_gauge("score_mean_milli", &labels, (moments.mean * 1000.0) as i64);
+            self.metrics.set_gauge("score_variance_milli", &labels, (moments.variance * 1000.0) as i64);
+            if let Some((previous_version, previous_recent)) = &previous {
+                let ks = ks_distance(previous_recent, &recent);
+                self.metrics.set_gauge("score_version_ks_milli", &labels, (ks * 1000.0) as i64);
+                info!(
+                    version = version,
+                    previous_version = previous_version,
+                    ks = format!("{:.3}", ks),
+                    "Score distribution vs previous version"
+                );
+            }
+
+            // Drift over time is only judged once the window has moved past the baseline
+            if scores.baseline.len() == self.config.window && recent.len() == self.config.window {
+                let baseline = Moments::of(&scores.baseline);
+                let ks = ks_distance(&scores.baseline, &recent);
+                let shift = (moments.mean - baseline.mean).abs() / baseline.variance.sqrt().max(f64::EPSILON);
+                self.metrics.set_gauge("score_drift_ks_milli", &labels, (ks * 1000.0) as i64);
+                let alerts = [("ks", ks > self.config.max_ks), ("mean", shift > self.config.max_mean_shift)];
+                for (reason, drifted) in alerts {
+                    if drifted {
+                        let alert_labels = [("version", version_label.as_str()), ("reason", reason)];
+                        self.metrics.inc("score_drift_alerts_total", &alert_labels);
+                        warn!(
+                            version = version,
+                            reason = reason,
+                            ks = format!("{:.3}", ks),
+                            max_ks = self.config.max_ks,
+                            mean = format!("{:.4}", moments.mean),
+                            baseline_mean = format!("{:.4}", baseline.mean),
+                            mean_shift_stddevs = format!("{:.2}", shift),
+                            max_mean_shift = self.config.max_mean_shift,
+                            "Score distribution drifted from its baseline"
+                        );
+                    }
+                }
+            }
+            previous = Some((*version, recent));
+        }
+    }
+
+    pub fn spawn_evaluator(self: Arc<Self>, interval: Duration) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                self.evaluate();
+            }
+        });
+    }
+}
+
+/// Two-sample Kolmogorov-Smirnov distance: the largest gap between the empirical CDFs
+fn ks_distance(a: &[f64], b: &[f64]) -> f64 {
+    let mut a = a.to_vec();
+    let mut b = b.to_vec();
+    a.sort_by(f64::total_cmp);
+    b.sort_by(f64::total_cmp);
+    let (mut i, mut j, mut distance) = (0, 0, 0.0f64);
+    while i < a.len() && j < b.len() {
+        let x = a[i].min(b[j]);
+        while i < a.len() && a[i] <= x {
+            i += 1;
+        }
+        while j < b.len() && b[j] <= x {
+            j += 1;
+        }
+        distance = distance.max((i as f64 / a.len() as f64 - j as f64 / b.len() as f64).abs());
+    }
+    distance
+}
@@ -0,0 +1,280 @@
+use std::path::Path;
+
+use ads_common::{Error, Result};
+use clap::ValueEnum;
+
+use crate::ads::{AdsList, Context, RequestType};
+use crate::catalog::Catalog;
+use crate::catalog_load;
+use crate::config::ServerConfig;
+use crate::containment;
+use crate::disconnect::DisconnectPolicy;
+use crate::generator::Ranking;
+use crate::limits;
+use crate::metrics::Metrics;
+use crate::plugin::GeneratorPlugin;
+use crate::runtime_config::{ConfigStore, RuntimeConfig};
+use crate::signing::ResponseSigner;
+use crate::softlimit::Limit;
+use crate::stub;
+use crate::variant::GeneratorVariant;
+
+/// `--dry-run`: resolve everything a real start would (config file, plugin, limits),
+/// print the effective configuration and run generator self-tests, without binding
+/// the port or creating any output files. Returns every problem found.
+pub fn run(config: &ServerConfig) -> Result<()> {
+    let mut problems = Vec::new();
+
+    println!("Effective server configuration:");
+    println!("{:#?}", config);
+    problems.extend(check_config(config));
+
+    let config_store = ConfigStore::new(RuntimeConfig::from_server_config(config), config.config_audit_size);
+    if let Some(path) = &config.config_file {
+        if let Err(e) = config_store.reload_from_file(path) {
+            problems.push(format!("config file {}: {}", path.display(), e));
+        }
+    }
+    let runtime = config_store.current();
+    println!("Effective runtime configuration:");
+    println!("{}", serde_json::to_string_pretty(&runtime).unwrap_or_default());
+    if runtime.min_context_gap_ms > runtime.max_context_gap_ms {
+        problems.push(format!(
+            "min_context_gap_ms {} exceeds max_context_gap_ms {}",
+            runtime.min_context_gap_ms, runtime.max_context_gap_ms
+        ));
+    }
+
+    // Limit shortfalls only fail a real start under --strict-limits, so the same holds here
+    if let Err(e) = limits::check_startup_limits(config.max_concurrent_sessions, config.strict_limits, config.raise_fd_limit) {
+        problems.push(format!("startup limits: {}", e));
+    }
+
+    let plugin = match &config.generator_plugin {
+        Some(path) => match GeneratorPlugin::load(path) {
+            Ok(plugin) => Some(plugin),
+            Err(e) => {
+                problems.push(e.to_string());
+                None
+            }
+        },
+        None => None,
+    };
+    // Parsed and validated like a real start, but the index cache is left alone
+    if let Some(path) = &config.catalog_file {
+        match catalog_load::load(path, config.catalog_load_threads, None, &Metrics::default()) {
+            Ok(entries) => println!("Catalog: {} entries from {}", entries.len(), path.display()),
+            Err(e) => problems.push(format!("catalog file {}: {}", path.display(), e)),
+        }
+    }
+    if let Some(path) = &config.signing_key {
+        match ResponseSigner::load(path) {
+            Ok(signer) => println!("Signing AdsLists with public key {}", signer.public_key_hex()),
+            Err(e) => problems.push(e.to_string()),
+        }
+    }
+    if let Some(path) = &config.stub {
+        match stub::load(path) {
+            Ok(fixtures) => println!("Stub mode: {} fixtures from {}", fixtures.len(), path.display()),
+            Err(e) => problems.push(e.to_string()),
+        }
+    }
+    if let Some(path) = &config.zstd_dictionary {
+        match std::fs::metadata(path) {
+            Ok(meta) if meta.len() > 0 => println!("zstd dictionary: {} ({} bytes)", path.display(), meta.len()),
+            Ok(_) => problems.push(format!("zstd_dictionary {} is empty", path.display())),
+            Err(e) => problems.push(format!("zstd_dictionary {}: {}", path.display(), e)),
+        }
+    }
+
+    let generator = plugin.as_ref().map_or("built-in", |plugin| plugin.name());
+    if config.generator_plugin.is_none() || plugin.is_some() {
+        let failures = self_test(plugin.as_ref(), config.ranking());
+        println!("Generator self-tests ({}): {}", generator, if failures.is_empty() { "passed" } else { "FAILED" });
+        problems.extend(failures.into_iter().map(|failure| format!("generator self-test: {}", failure)));
+    }
+
+    if problems.is_empty() {
+        println!("Dry run OK");
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("PROBLEM: {}", problem);
+    }
+    Err(Error::config(format!("dry run found {} problem(s)", problems.len())))
+}
+
+fn check_config(config: &ServerConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (name, target) in [
+        ("slo_latency_target", config.slo_latency_target),
+        ("slo_success_target", config.slo_success_target),
+    ] {
+        if !(target > 0.0 && target <= 1.0) {
+            problems.push(format!("{} {} must be in (0, 1]", name, target));
+        }
+    }
+    if !(0.0..=1.0).contains(&config.feature_log_sample_rate) {
+        problems.push(format!("feature_log_sample_rate {} must be in [0, 1]", config.feature_log_sample_rate));
+    }
+    if config.output_channel_capacity == 0 {
+        problems.push("output_channel_capacity must be at least 1".to_string());
+    }
+    if config.max_concurrent_sessions == 0 {
+        problems.push("max_concurrent_sessions must be at least 1".to_string());
+    }
+    if config.sanitize_max_query_chars == 0 || config.sanitize_max_understanding_chars == 0 {
+        problems.push("sanitize_max_query_chars and sanitize_max_understanding_chars must be at least 1".to_string());
+    }
+    if !(config.batch_session_share > 0.0 && config.batch_session_share <= 1.0) {
+        problems.push(format!("batch_session_share {} must be in (0, 1]", config.batch_session_share));
+    }
+    if !(config.batch_overload_target_fraction > 0.0 && config.batch_overload_target_fraction <= 1.0) {
+        problems.push(format!(
+            "batch_overload_target_fraction {} must be in (0, 1]",
+            config.batch_overload_target_fraction
+        ));
+    }
+    if !config.stem.is_empty() && !config.normalize_text {
+        problems.push("stem only applies with normalize_text".to_string());
+    }
+    if config.disconnect_policy == DisconnectPolicy::Grace && !config.session_checkpoints {
+        problems.push("disconnect_policy grace needs session_checkpoints for the reconnect to resume from".to_string());
+    }
+    if config.max_refinement_tasks == 0 {
+        problems.push("max_refinement_tasks must be at least 1".to_string());
+    }
+    if config.score_drift
+        && (config.score_drift_window < 2
+            || !(config.score_drift_max_ks > 0.0 && config.score_drift_max_ks <= 1.0)
+            || !(config.score_drift_max_mean_shift > 0.0 && config.score_drift_max_mean_shift.is_finite())
+            || config.score_drift_eval_interval_secs == 0)
+    {
+        problems.push(
+            "score_drift needs score_drift_window >= 2, score_drift_max_ks in (0, 1], a positive finite \
+             score_drift_max_mean_shift and score_drift_eval_interval_secs >= 1"
+                .to_string(),
+        );
+    }
+    if config.catalog_index_cache.is_some() && config.catalog_file.is_none() {
+        problems.push("catalog_index_cache requires catalog_file".to_string());
+    }
+    if config.quality_gate.is_some() && (!config.quality_gate_min_delta.is_finite() || config.quality_gate_k == 0) {
+        problems.push("quality_gate_min_delta must be finite and quality_gate_k at least 1".to_string());
+    }
+    if config.min_score.is_some_and(|min_score| !min_score.is_finite()) {
+        problems.push("min_score must be finite".to_string());
+    }
+    for limit in &config.soft_limits {
+        let max = match limit {
+            Limit::AdsPerList => config.max_ads_per_list,
+            Limit::UnderstandingChars => config.max_understanding_chars,
+            Limit::ContextsPerSession => config.max_contexts_per_session,
+        };
+        if max == 0 {
+            problems.push(format!("soft limit {} has no maximum set (max_{})", limit.name(), limit.name()));
+        }
+    }
+    for (name, path) in [("tls_cert", &config.tls_cert), ("tls_key", &config.tls_key)] {
+        if let Some(path) = path {
+            if !path.is_file() {
+                problems.push(format!("{} {}: no such file", name, path.display()));
+            }
+        }
+    }
+    for (name, path) in [
+        ("port_file", &config.port_file),
+        ("metrics_dump", &config.metrics_dump),
+        ("session_journal", &config.session_journal),
+        ("shutdown_report", &config.shutdown_report),
+        ("feature_log", &config.feature_log),
+        ("catalog_index_cache", &config.catalog_index_cache),
+    ] {
+        if let Some(path) = path {
+            if !parent_exists(path) {
+                problems.push(format!("{} {}: parent directory does not exist", name, path.display()));
+            }
+        }
+    }
+    problems
+}
+
+fn parent_exists(path: &Path) -> bool {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.is_dir(),
+        _ => true,
+    }
+}
+
+/// Generate every version for each request type, plain and batched, with the
+/// configured plugin (or each built-in variant) and ranking, as a start would serve
+/// them: each must succeed with the right version, one partition per batched query
+/// and scores in [0, 1]. The generator's own invariants are covered by its unit tests.
+pub fn self_test(plugin: Option<&GeneratorPlugin>, ranking: Ranking) -> Vec<String> {
+    let metrics = Metrics::default();
+    let catalog = Catalog::default().snapshot();
+    let mut failures = Vec::new();
+    // A plugin replaces every variant, so it is exercised once
+    let variants = if plugin.is_some() { &[GeneratorVariant::Catalog][..] } else { GeneratorVariant::value_variants() };
+    for &variant in variants {
+        for request_type in [RequestType::Keyword, RequestType::AsinDetail, RequestType::CategoryBrowse] {
+            for queries in [Vec::new(), vec!["coffee maker".to_string(), "espresso".to_string()]] {
+                let context = Context {
+                    query: "coffee maker".to_string(),
+                    asin_id: "B000123".to_string(),
+                    understanding: "refined understanding based on query analysis".to_string(),
+                    seed: 42,
+                    request_type: request_type as i32,
+                    queries,
+                    ..Default::default()
+                };
+                let case = format!(
+                    "{}{}/{} ",
+                    request_type.as_str_name(),
+                    if context.queries.is_empty() { "" } else { "/batched" },
+                    variant.name()
+                );
+                for version in 1..=3 {
+                    let generated = containment::generate_contained(
+                        &context, &[], version, context.seed, &catalog, plugin, 0, &metrics, None, ranking, variant,
+                    );
+                    let failure = match generated {
+                        Ok(ads_list) => check_list(&context, version, &ads_list).err(),
+                        Err(status) => Some(status.message().to_string()),
+                    };
+                    if let Some(failure) = failure {
+                        failures.push(format!("{}v{}: {}", case, version, failure));
+                    }
+                }
+            }
+        }
+    }
+    failures
+}
+
+fn check_list(context: &Context, version: u32, ads_list: &AdsList) -> Result<(), String> {
+    if ads_list.version != version {
+        return Err(format!("AdsList version {} (expected {})", ads_list.version, version));
+    }
+    let partitions: Vec<_> = if context.queries.is_empty() {
+        vec![&ads_list.ads]
+    } else {
+        if ads_list.query_results.len() != context.queries.len() {
+            return Err(format!(
+                "{} query partitions for {} queries",
+                ads_list.query_results.len(),
+                context.queries.len()
+            ));
+        }
+        ads_list.query_results.iter().map(|partition| &partition.ads).collect()
+    };
+    for ads in partitions {
+        if ads.is_empty() {
+            return Err("no ads generated".to_string());
+        }
+        if let Some(ad) = ads.iter().find(|ad| !(0.0..=1.0).contains(&ad.score)) {
+            return Err(format!("ad {} has score {} outside [0, 1]", ad.ad_id, ad.score));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tonic::Status;
+use tracing::{info, warn};
+
+use crate::ads::{Ad, AdsList};
+use crate::metrics::Metrics;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FaultAction {
+    /// Fail the stream instead of sending the version
+    Fail,
+    /// Hold the version back before sending it
+    Delay(Duration),
+    /// Alter the AdsList after it is signed, so a verifying client flags it
+    Tamper,
+}
+
+impl FaultAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FaultAction::Fail => "fail",
+            FaultAction::Delay(_) => "delay",
+            FaultAction::Tamper => "tamper",
+        }
+    }
+}
+
+/// Status failing a stream at `version` for a `Fail` rule. INTERNAL rather than
+/// UNAVAILABLE: clients read UNAVAILABLE as a lost connection, which an injected fault
+/// is not, and the `x-injected-fault` trailer tells them apart from a real failure.
+pub fn injected_failure(version: u32) -> Status {
+    let mut status = Status::internal("injected fault");
+    status.metadata_mut().insert(ads_proto::INJECTED_FAULT_METADATA_KEY, version.into());
+    status
+}
+
+/// One runtime fault rule, e.g. "fail 20% of version-2 sends for the next 30 s"
+#[derive(Debug, Clone)]
+pub struct FaultRule {
+    pub id: u64,
+    /// Version the rule applies to; 0 matches every version
+    pub version: u32,
+    pub action: FaultAction,
+    /// Chance (0.0..=1.0) that a matching send is affected
+    pub probability: f64,
+    /// When the rule stops applying; None keeps it until removed
+    pub expires_at: Option<Instant>,
+}
+
+impl FaultRule {
+    pub fn remaining(&self) -> Option<Duration> {
+        self.expires_at.map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    fn expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|at| now >= at)
+    }
+}
+
+/// Fault rules that can be added and removed through the admin service while the
+/// server is running. Consulted before every AdsList send.
+#[derive(Debug)]
+pub struct FaultInjector {
+    rules: Mutex<Vec<FaultRule>>,
+    next_id: AtomicU64,
+    metrics: Arc<Metrics>,
+}
+
+impl FaultInjector {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        FaultInjector {
+            rules: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+            metrics,
+        }
+    }
+
+    pub fn add(&self, version: u32, action: FaultAction, probability: f64, duration: Option<Duration>) -> FaultRule {
+        let rule = FaultRule {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            version,
+            action,
+            probability: probability.clamp(0.0, 1.0),
+            expires_at: duration.map(|d| Instant::now() + d),
+        };
+        info!(
+            rule_id = rule.id,
+            version = version,
+            action = action.name(),
+            probability = rule.probability,
+            duration_ms = duration.map(|d| d.as_millis() as u64),
+            "Fault rule added"
+        );
+        self.rules.lock().unwrap().push(rule.clone());
+        rule
+    }
+
+    pub fn remove(&self, id: u64) -> bool {
+        let mut rules = self.rules.lock().unwrap();
+        let before = rules.len();
+        rules.retain(|rule| rule.id != id);
+        let removed = rules.len() != before;
+        if removed {
+            info!(rule_id = id, "Fault rule removed");
+        }
+        removed
+    }
+
+    /// Currently active rules, dropping any that have expired
+    pub fn list(&self) -> Vec<FaultRule> {
+        let mut rules = self.rules.lock().unwrap();
+        let now = Instant::now();
+        rules.retain(|rule| !rule.expired(now));
+        rules.clone()
+    }
+
+    /// Decide whether the send of `version` should be faulted. Tamper rules are left
+    /// to `tamper`, which runs after the list is signed.
+    pub fn decide(&self, session_id: u64, version: u32) -> Option<FaultAction> {
+        let rule = self.pick(version, false)?;
+        warn!(
+            session_id = session_id,
+            rule_id = rule.id,
+            version = version,
+            action = rule.action.name(),
+            "Injecting fault"
+        );
+        Some(rule.action)
+    }
+
+    /// Apply a matching tamper rule to a signed AdsList about to be sent: its last ad
+    /// is moved to the top of each list with the best score. Returns whether it did.
+    pub fn tamper(&self, ads_list: &mut AdsList) -> bool {
+        let Some(rule) = self.pick(ads_list.version, true) else {
+            return false;
+        };
+        promote_last(&mut ads_list.ads);
+        for partition in &mut ads_list.query_results {
+            promote_last(&mut partition.ads);
+        }
+        for partition in &mut ads_list.placement_results {
+            promote_last(&mut partition.ads);
+        }
+        warn!(
+            rule_id = rule.id,
+            channel_id = ads_list.channel_id,
+            version = ads_list.version,
+            "Tampering with signed AdsList"
+        );
+        true
+    }
+
+    // First active rule matching `version` that fires, counted as injected
+    fn pick(&self, version: u32, tamper: bool) -> Option<FaultRule> {
+        let rules = self.rules.lock().unwrap();
+        if rules.is_empty() {
+            return None;
+        }
+        let now = Instant::now();
+        let rule = rules.iter().find(|rule| {
+            !rule.expired(now)
+                && (rule.action == FaultAction::Tamper) == tamper
+                && (rule.version == 0 || rule.version == version)
+                && rand::random::<f64>() < rule.probability
+        })?;
+        let version_label = version.to_string();
+        self.metrics.inc(
+            "faults_injected_total",
+            &[("action", rule.action.name()), ("version", &version_label)],
+        );
+        Some(rule.clone())
+    }
+}
+
+fn promote_last(ads: &mut Vec<Ad>) {
+    if ads.len() < 2 {
+        return;
+    }
+    let mut ad = ads.pop().unwrap();
+    ad.score = ads[0].score + 1.0;
+    ads.insert(0, ad);
+}
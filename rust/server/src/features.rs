@@ -0,0 +1,226 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+use prost::Message;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::ads::Context;
+
+/// Inputs of one ad's score as computed by the built-in generator. The score is
+/// `clamp((relevance [*0.8 + similar_product_affinity] + understanding_boost +
+/// history_boost + catalog_boost) * version_multiplier + noise)`, before any normalization.
+#[derive(Debug, Clone, Serialize)]
+pub struct AdFeatures {
+    pub ad_id: String,
+    /// Query the ad was ranked for (the partition's query for batched Contexts)
+    pub query: String,
+    /// "synthetic" candidate or admin "catalog" entry
+    pub source: &'static str,
+    pub candidate_index: u32,
+    pub advertiser_id: String,
+    pub category: String,
+    pub relevance: f64,
+    pub similar_product_affinity: f64,
+    pub understanding_boost: f64,
+    /// Relevance to the earlier queries of the session's refinement trajectory
+    pub history_boost: f64,
+    pub catalog_boost: f64,
+    pub version_multiplier: f64,
+    pub noise: f64,
+    pub score: f64,
+    /// 1-based position in the generated ranking
+    pub rank: u32,
+}
+
+/// One line (or record) of the feature log
+#[derive(Debug, Serialize)]
+struct FeatureRecord<'a> {
+    timestamp_ms: u64,
+    session_id: u64,
+    version: u32,
+    request_type: &'static str,
+    asin_id: &'a str,
+    has_understanding: bool,
+    #[serde(flatten)]
+    features: &'a AdFeatures,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureLogFormat {
+    /// One JSON object per ad and line
+    Jsonl,
+    /// TFRecord framing around tf.train.Example protos, readable by tf.data.TFRecordDataset
+    Tfrecord,
+}
+
+/// Sampled per-ad feature vectors and final scores, written as training data for
+/// learned rankers. Sampling is per session, so a sampled session logs every version.
+#[derive(Debug)]
+pub struct FeatureLog {
+    file: Mutex<File>,
+    format: FeatureLogFormat,
+    sample_rate: f64,
+}
+
+impl FeatureLog {
+    pub fn open(path: &Path, format: FeatureLogFormat, sample_rate: f64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FeatureLog { file: Mutex::new(file), format, sample_rate: sample_rate.clamp(0.0, 1.0) })
+    }
+
+    /// Whether `session_id` falls into the sample; stable for the whole session
+    pub fn samples(&self, session_id: u64) -> bool {
+        let mut hasher = DefaultHasher::new();
+        session_id.hash(&mut hasher);
+        (hasher.finish() as f64 / u64::MAX as f64) < self.sample_rate
+    }
+
+    pub fn record(&self, session_id: u64, version: u32, context: &Context, features: &[AdFeatures]) {
+        let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        let mut out = Vec::new();
+        for features in features {
+            let record = FeatureRecord {
+                timestamp_ms,
+                session_id,
+                version,
+                request_type: context.request_type().as_str_name(),
+                asin_id: &context.asin_id,
+                has_understanding: !context.understanding.is_empty(),
+                features,
+            };
+            let encoded = match self.format {
+                FeatureLogFormat::Jsonl => serde_json::to_string(&record).map(|mut line| {
+                    line.push('\n');
+                    line.into_bytes()
+                }),
+                FeatureLogFormat::Tfrecord => serde_json::to_value(&record).map(|value| tfrecord(&example(value))),
+            };
+            match encoded {
+                Ok(bytes) => out.extend_from_slice(&bytes),
+                Err(e) => warn!(session_id = session_id, error = %e, "Failed to serialize feature record"),
+            }
+        }
+        if let Err(e) = self.file.lock().unwrap().write_all(&out) {
+            warn!(session_id = session_id, error = %e, "Failed to write feature records");
+        }
+    }
+}
+
+// Minimal tf.train.Example schema (tensorflow/core/example/{example,feature}.proto)
+
+#[derive(Clone, PartialEq, Message)]
+struct Example {
+    #[prost(message, optional, tag = "1")]
+    features: Option<Features>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Features {
+    #[prost(map = "string, message", tag = "1")]
+    feature: HashMap<String, Feature>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Feature {
+    #[prost(oneof = "Kind", tags = "1, 2, 3")]
+    kind: Option<Kind>,
+}
+
+// Variant names follow the tf.train.Feature oneof
+#[derive(Clone, PartialEq, prost::Oneof)]
+#[allow(clippy::enum_variant_names)]
+enum Kind {
+    #[prost(message, tag = "1")]
+    BytesList(BytesList),
+    #[prost(message, tag = "2")]
+    FloatList(FloatList),
+    #[prost(message, tag = "3")]
+    Int64List(Int64List),
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct BytesList {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    value: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FloatList {
+    #[prost(float, repeated, tag = "1")]
+    value: Vec<f32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Int64List {
+    #[prost(int64, repeated, tag = "1")]
+    value: Vec<i64>,
+}
+
+/// Strings become bytes features, integers and booleans int64 features and other
+/// numbers float features
+fn example(record: serde_json::Value) -> Example {
+    let serde_json::Value::Object(fields) = record else {
+        return Example::default();
+    };
+    let feature = fields
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let kind = match value {
+                serde_json::Value::String(s) => Kind::BytesList(BytesList { value: vec![s.into_bytes()] }),
+                serde_json::Value::Bool(b) => Kind::Int64List(Int64List { value: vec![b as i64] }),
+                serde_json::Value::Number(n) => match n.as_i64() {
+                    Some(i) => Kind::Int64List(Int64List { value: vec![i] }),
+                    None => Kind::FloatList(FloatList { value: vec![n.as_f64().unwrap_or_default() as f32] }),
+                },
+                _ => return None,
+            };
+            Some((name, Feature { kind: Some(kind) }))
+        })
+        .collect();
+    Example { features: Some(Features { feature }) }
+}
+
+/// TFRecord framing: length, masked CRC32C of the length, data, masked CRC32C of the data
+fn tfrecord(example: &Example) -> Vec<u8> {
+    let data = example.encode_to_vec();
+    let length = (data.len() as u64).to_le_bytes();
+    let mut out = Vec::with_capacity(data.len() + 16);
+    out.extend_from_slice(&length);
+    out.extend_from_slice(&masked_crc32c(&length).to_le_bytes());
+    out.extend_from_slice(&data);
+    out.extend_from_slice(&masked_crc32c(&data).to_le_bytes());
+    out
+}
+
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x82f6_3b78 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn masked_crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc = CRC32C_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    let crc = !crc;
+    crc.rotate_right(15).wrapping_add(0xa282_ead8)
+}
@@ -0,0 +1,640 @@
+use std::collections::{BTreeMap, HashSet};
+use std::hash::{Hash, Hasher};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use siphasher::sip::SipHasher24;
+use ads_proto::score::{sort_ads, top_k_ads, Score, TieBreak};
+
+use crate::ads::{Ad, AdsList, Context, Placement, PlacementAds, QueryAds, RequestType};
+use crate::catalog::CatalogEntry;
+use crate::constraints::SPONSORED_BRANDS;
+use crate::features::AdFeatures;
+use crate::variant::GeneratorVariant;
+
+/// Weight of a candidate's mean relevance to the earlier queries of the session
+const HISTORY_WEIGHT: f64 = 0.15;
+
+/// Dimensions of the pseudo-embeddings the `embedding` variant compares
+pub const EMBEDDING_DIM: usize = 16;
+
+/// How each candidate pool is ordered and cut
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ranking {
+    pub tie_break: TieBreak,
+    /// Keep only the best `k` candidates of a pool, selected with a bounded heap
+    /// instead of sorting the whole pool (None = sort and keep all)
+    pub top_k: Option<usize>,
+}
+
+/// Revision of the built-in scoring; bump it with any change to how candidates are
+/// scored, so the `pipeline_version` tags of ads scored before and after differ
+const SCORING_REVISION: u32 = 2;
+
+impl Ranking {
+    /// `pipeline_version` tag of the ads this ranking orders, e.g. `s1-seeded-top50`
+    pub fn pipeline_version(&self) -> String {
+        let tie_break = match self.tie_break {
+            TieBreak::AdId => "ad_id",
+            TieBreak::Recency => "recency",
+            TieBreak::Seeded => "seeded",
+        };
+        match self.top_k {
+            Some(k) => format!("s{}-{}-top{}", SCORING_REVISION, tie_break, k),
+            None => format!("s{}-{}", SCORING_REVISION, tie_break),
+        }
+    }
+}
+
+impl From<TieBreak> for Ranking {
+    fn from(tie_break: TieBreak) -> Self {
+        Ranking { tie_break, top_k: None }
+    }
+}
+
+// Mock ad generation with Context-based scoring and progressive refinement.
+// A non-zero session seed is mixed into the RNG seed so a client can reproduce
+// (or vary) the exact AdsLists of a session regardless of its implementation language.
+// Catalog entries added through the admin service compete with the synthetic candidates.
+// Equal scores are ordered by `ranking.tie_break`; catalog revisions define recency (synthetic
+// candidates count as oldest) and the session seed drives seeded tie-breaking.
+// `trajectory` holds the earlier queries of the session (see `history`); candidates
+// relevant to them get a boost, so an empty trajectory scores the Context alone.
+// `variant` picks the candidate pool and relevance model (see `variant`).
+#[allow(clippy::too_many_arguments)]
+pub fn generate_ads(
+    context: &Context,
+    trajectory: &[String],
+    version: u32,
+    session_seed: u64,
+    catalog: &BTreeMap<String, CatalogEntry>,
+    ranking: Ranking,
+    variant: GeneratorVariant,
+) -> AdsList {
+    generate(context, trajectory, version, session_seed, catalog, ranking, variant, false).0
+}
+
+/// `generate_ads` plus the inputs of every ad's score, for the feature log
+#[allow(clippy::too_many_arguments)]
+pub fn generate_ads_with_features(
+    context: &Context,
+    trajectory: &[String],
+    version: u32,
+    session_seed: u64,
+    catalog: &BTreeMap<String, CatalogEntry>,
+    ranking: Ranking,
+    variant: GeneratorVariant,
+) -> (AdsList, Vec<AdFeatures>) {
+    generate(context, trajectory, version, session_seed, catalog, ranking, variant, true)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn generate(
+    context: &Context,
+    trajectory: &[String],
+    version: u32,
+    session_seed: u64,
+    catalog: &BTreeMap<String, CatalogEntry>,
+    ranking: Ranking,
+    variant: GeneratorVariant,
+    with_features: bool,
+) -> (AdsList, Vec<AdFeatures>) {
+    let no_catalog = BTreeMap::new();
+    let catalog = if variant == GeneratorVariant::Mock { &no_catalog } else { catalog };
+    if !context.queries.is_empty() {
+        return generate_batch(context, trajectory, version, session_seed, catalog, ranking, variant, with_features);
+    }
+    if !context.placements.is_empty() {
+        return generate_placements(context, trajectory, version, session_seed, catalog, ranking, variant, with_features);
+    }
+    let (ads, features) = rank_ads(context, trajectory, &context.query, None, version, session_seed, catalog, ranking, variant, with_features);
+    let ads_list = AdsList {
+        ads,
+        version,
+        ..Default::default()
+    };
+    (ads_list, features)
+}
+
+// A batched Context ranks every query independently and in parallel under the same
+// session seed, so each partition matches what a single-query Context would get.
+#[allow(clippy::too_many_arguments)]
+fn generate_batch(
+    context: &Context,
+    trajectory: &[String],
+    version: u32,
+    session_seed: u64,
+    catalog: &BTreeMap<String, CatalogEntry>,
+    ranking: Ranking,
+    variant: GeneratorVariant,
+    with_features: bool,
+) -> (AdsList, Vec<AdFeatures>) {
+    let ranked: Vec<(QueryAds, Vec<AdFeatures>)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = context
+            .queries
+            .iter()
+            .map(|query| scope.spawn(move || {
+                let (ads, features) = rank_ads(context, trajectory, query, None, version, session_seed, catalog, ranking, variant, with_features);
+                let partition = QueryAds {
+                    query: query.clone(),
+                    ads,
+                    ..Default::default()
+                };
+                (partition, features)
+            }))
+            .collect();
+        handles.into_iter().map(|handle| handle.join().expect("ad generation panicked")).collect()
+    });
+    let (query_results, features): (Vec<QueryAds>, Vec<Vec<AdFeatures>>) = ranked.into_iter().unzip();
+    let ads_list = AdsList {
+        version,
+        query_results,
+        ..Default::default()
+    };
+    (ads_list, features.into_iter().flatten().collect())
+}
+
+// Every placement ranks its own candidate pool and keeps its slot count, most
+// prominent placement first. Catalog entries compete in every pool, but one shown in
+// a more prominent placement is left out of the others.
+#[allow(clippy::too_many_arguments)]
+fn generate_placements(
+    context: &Context,
+    trajectory: &[String],
+    version: u32,
+    session_seed: u64,
+    catalog: &BTreeMap<String, CatalogEntry>,
+    ranking: Ranking,
+    variant: GeneratorVariant,
+    with_features: bool,
+) -> (AdsList, Vec<AdFeatures>) {
+    let mut placements: Vec<Placement> =
+        context.placements().filter(|placement| *placement != Placement::Unspecified).collect();
+    placements.sort();
+    placements.dedup();
+    let mut shown: HashSet<String> = HashSet::new();
+    let mut placement_results = Vec::with_capacity(placements.len());
+    let mut all_features = Vec::new();
+    for placement in placements {
+        let (mut ads, features) = rank_ads(
+            context, trajectory, &context.query, Some(placement), version, session_seed, catalog, ranking, variant, with_features,
+        );
+        ads.retain(|ad| !shown.contains(&ad.ad_id));
+        ads.truncate(placement.slots());
+        shown.extend(ads.iter().map(|ad| ad.ad_id.clone()));
+        all_features.extend(features.into_iter().filter(|f| ads.iter().any(|ad| ad.ad_id == f.ad_id)));
+        placement_results.push(PlacementAds {
+            placement: placement as i32,
+            ads,
+            ..Default::default()
+        });
+    }
+    let ads_list = AdsList {
+        version,
+        placement_results,
+        ..Default::default()
+    };
+    (ads_list, all_features)
+}
+
+// `pool` names the placement whose candidate pool is ranked; None ranks the one
+// pool of a Context without placements
+#[allow(clippy::too_many_arguments)]
+fn rank_ads(
+    context: &Context,
+    trajectory: &[String],
+    query: &str,
+    pool: Option<Placement>,
+    version: u32,
+    session_seed: u64,
+    catalog: &BTreeMap<String, CatalogEntry>,
+    ranking: Ranking,
+    variant: GeneratorVariant,
+    with_features: bool,
+) -> (Vec<Ad>, Vec<AdFeatures>) {
+    let request_type = context.request_type();
+    // Category browse ranks purely on the category/product, ignoring query tokens
+    let use_query = request_type != RequestType::CategoryBrowse;
+    let query_embedding = (variant == GeneratorVariant::Embedding && use_query).then(|| embed_query(query));
+    
+    // Create a deterministic seed based on context for reproducible results
+    let mut hasher = stable_hasher();
+    if use_query {
+        query.hash(&mut hasher);
+    }
+    context.asin_id.hash(&mut hasher);
+    if let Some(pool) = pool {
+        pool.hash(&mut hasher);
+    }
+    let seed = hasher.finish() ^ session_seed;
+    let mut rng = StdRng::seed_from_u64(seed);
+    
+    // Generate 5-10 mock ads as per requirement 2.5
+    let num_ads = rng.gen_range(5..=10);
+    let mut ads = Vec::with_capacity(num_ads);
+    let mut features = Vec::new();
+    
+    for i in 0..num_ads {
+        // Base score calculation using hash of query + asin_id
+        let mut ad_hasher = stable_hasher();
+        if use_query {
+            query.hash(&mut ad_hasher);
+        }
+        context.asin_id.hash(&mut ad_hasher);
+        i.hash(&mut ad_hasher); // Add index for variation
+        if let Some(pool) = pool {
+            pool.hash(&mut ad_hasher);
+        }
+        let base_hash = ad_hasher.finish();
+        let relevance = match &query_embedding {
+            Some(query_embedding) => embedding_relevance(query_embedding, (&context.asin_id, i, pool)),
+            None => (base_hash % 1000) as f64 / 1000.0, // 0.0 to 1.0
+        };
+        let mut base_score = relevance;
+        
+        // ASIN detail pages favour products similar to the one being viewed;
+        // lower candidate indices model closer neighbours of the viewed ASIN
+        let mut similar_product_affinity = 0.0;
+        if request_type == RequestType::AsinDetail {
+            similar_product_affinity = 0.2 * (1.0 - i as f64 / num_ads as f64); // 0.0 to 0.2
+            base_score = base_score * 0.8 + similar_product_affinity;
+        }
+        
+        // Understanding boost - additional scoring when understanding is provided (requirement 2.6)
+        let mut understanding_boost = 0.0;
+        if !context.understanding.is_empty() {
+            let mut understanding_hasher = stable_hasher();
+            context.understanding.hash(&mut understanding_hasher);
+            understanding_boost = (understanding_hasher.finish() % 200) as f64 / 1000.0; // 0.0 to 0.2 boost
+            base_score += understanding_boost;
+        }
+        
+        // Session memory - candidates that were relevant to the earlier queries of the trajectory
+        let history_boost = if use_query { trajectory_boost(trajectory, query, (&context.asin_id, i)) } else { 0.0 };
+        base_score += history_boost;
+        
+        // Version refinement - progressive improvement across versions
+        base_score *= version_multiplier(version);
+        
+        // Add controlled randomness for realistic variation
+        let randomness = rng.gen_range(-0.1..=0.1);
+        base_score += randomness;
+        
+        // Clamp score to valid range [0.0, 1.0]
+        let (base_score, _) = Score::clamped(base_score);
+        
+        // Generate realistic ad_id; the same candidate keeps it in every version, so
+        // clients merging versions recognize it
+        let ad_id = match pool {
+            Some(pool) => format!("ad_{}_{}_{}", context.asin_id, pool.slug(), i + 1),
+            None => format!("ad_{}_{}", context.asin_id, i + 1),
+        };
+        
+        // Creative attributes derived from the ad hash so they don't disturb the score RNG
+        let advertiser_id = format!("adv_{}", (base_hash >> 16) % 4 + 1);
+        let category = if (base_hash >> 32) % 10 < 3 {
+            SPONSORED_BRANDS
+        } else {
+            "sponsored_products"
+        };
+        
+        if with_features {
+            features.push(AdFeatures {
+                ad_id: ad_id.clone(),
+                query: query.to_string(),
+                source: "synthetic",
+                candidate_index: i as u32,
+                advertiser_id: advertiser_id.clone(),
+                category: category.to_string(),
+                relevance,
+                similar_product_affinity,
+                understanding_boost,
+                history_boost,
+                catalog_boost: 0.0,
+                version_multiplier: version_multiplier(version),
+                noise: randomness,
+                score: base_score.get(),
+                rank: 0,
+            });
+        }
+        
+        ads.push(Ad {
+            asin_id: context.asin_id.clone(),
+            ad_id,
+            score: base_score.get(),
+            advertiser_id,
+            category: category.to_string(),
+            ..Default::default()
+        });
+    }
+    
+    // Catalog entries are scored from their ad_id instead of a candidate index and skip
+    // the RNG, so an empty catalog leaves the synthetic ranking untouched
+    for entry in catalog.values() {
+        let mut entry_hasher = stable_hasher();
+        if use_query {
+            query.hash(&mut entry_hasher);
+        }
+        entry.ad_id.hash(&mut entry_hasher);
+        if let Some(pool) = pool {
+            pool.hash(&mut entry_hasher);
+        }
+        let relevance = match (&query_embedding, &entry.embedding, pool) {
+            (Some(query_embedding), Some(embedding), None) => cosine_relevance(query_embedding, embedding),
+            (Some(query_embedding), _, _) => embedding_relevance(query_embedding, (&entry.ad_id, pool)),
+            (None, _, _) => (entry_hasher.finish() % 1000) as f64 / 1000.0,
+        };
+        let mut score = relevance + entry.boost;
+        let mut understanding_boost = 0.0;
+        if !context.understanding.is_empty() {
+            let mut understanding_hasher = stable_hasher();
+            context.understanding.hash(&mut understanding_hasher);
+            understanding_boost = (understanding_hasher.finish() % 200) as f64 / 1000.0;
+            score += understanding_boost;
+        }
+        let history_boost = if use_query { trajectory_boost(trajectory, query, &entry.ad_id) } else { 0.0 };
+        score += history_boost;
+        score *= version_multiplier(version);
+        let (score, _) = Score::clamped(score);
+        if with_features {
+            features.push(AdFeatures {
+                ad_id: entry.ad_id.clone(),
+                query: query.to_string(),
+                source: "catalog",
+                candidate_index: 0,
+                advertiser_id: entry.advertiser_id.clone(),
+                category: entry.category.clone(),
+                relevance,
+                similar_product_affinity: 0.0,
+                understanding_boost,
+                history_boost,
+                catalog_boost: entry.boost,
+                version_multiplier: version_multiplier(version),
+                noise: 0.0,
+                score: score.get(),
+                rank: 0,
+            });
+        }
+        ads.push(Ad {
+            asin_id: entry.asin_id.clone(),
+            ad_id: entry.ad_id.clone(),
+            score: score.get(),
+            advertiser_id: entry.advertiser_id.clone(),
+            category: entry.category.clone(),
+            ..Default::default()
+        });
+    }
+    
+    // Sort ads by score in descending order for better user experience; a large
+    // catalog is cut to the best `top_k` without sorting all of it
+    let recency = |ad: &Ad| catalog.get(&ad.ad_id).map_or(0, |entry| entry.revision);
+    let ads = match ranking.top_k {
+        Some(k) => top_k_ads(ads, k, ranking.tie_break, session_seed, recency),
+        None => {
+            sort_ads(&mut ads, ranking.tie_break, session_seed, recency);
+            ads
+        }
+    };
+    for feature in &mut features {
+        feature.rank = ads.iter().position(|ad| ad.ad_id == feature.ad_id).map_or(0, |i| i as u32 + 1);
+    }
+    (ads, features)
+}
+
+/// Mean relevance `candidate` had for the earlier queries of `trajectory`, scaled by
+/// `HISTORY_WEIGHT`; relevance is hashed the way `rank_ads` hashes it for the query
+fn trajectory_boost(trajectory: &[String], query: &str, candidate: impl Hash) -> f64 {
+    let earlier: Vec<&String> = trajectory.iter().filter(|earlier| earlier.as_str() != query).collect();
+    if earlier.is_empty() {
+        return 0.0;
+    }
+    let relevance: f64 = earlier
+        .iter()
+        .map(|earlier| {
+            let mut hasher = stable_hasher();
+            earlier.hash(&mut hasher);
+            candidate.hash(&mut hasher);
+            (hasher.finish() % 1000) as f64 / 1000.0
+        })
+        .sum();
+    HISTORY_WEIGHT * relevance / earlier.len() as f64 // 0.0 to 0.15 boost
+}
+
+/// Hasher behind every score, seed and embedding of the generator: SipHash-2-4 with
+/// fixed keys. std leaves `DefaultHasher`'s algorithm unspecified, so a seed would
+/// not be guaranteed to reproduce the same ads across Rust releases.
+fn stable_hasher() -> SipHasher24 {
+    SipHasher24::new_with_keys(0, 0)
+}
+
+/// Pseudo-embedding of `item`, drawn from an RNG seeded with its hash
+fn embed(item: impl Hash) -> [f64; EMBEDDING_DIM] {
+    let mut hasher = stable_hasher();
+    item.hash(&mut hasher);
+    let mut rng = StdRng::seed_from_u64(hasher.finish());
+    std::array::from_fn(|_| rng.gen_range(-1.0..=1.0))
+}
+
+/// Mean embedding of the query's lowercased tokens, so queries sharing tokens
+/// land close to each other
+fn embed_query(query: &str) -> [f64; EMBEDDING_DIM] {
+    let mut sum = [0.0; EMBEDDING_DIM];
+    for token in query.split_whitespace() {
+        for (total, value) in sum.iter_mut().zip(embed(token.to_lowercase())) {
+            *total += value;
+        }
+    }
+    sum
+}
+
+/// Embedding of a catalog entry in the pool of a Context without placements, as
+/// `rank_ads` computes it; the catalog stores it with the entry
+pub fn catalog_embedding(ad_id: &str) -> [f64; EMBEDDING_DIM] {
+    embed((ad_id, None::<Placement>))
+}
+
+/// Cosine similarity of the query embedding and `candidate`'s, mapped to 0.0..=1.0
+fn embedding_relevance(query_embedding: &[f64; EMBEDDING_DIM], candidate: impl Hash) -> f64 {
+    cosine_relevance(query_embedding, &embed(candidate))
+}
+
+fn cosine_relevance(query_embedding: &[f64; EMBEDDING_DIM], candidate: &[f64; EMBEDDING_DIM]) -> f64 {
+    let dot: f64 = query_embedding.iter().zip(candidate).map(|(q, c)| q * c).sum();
+    let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norms = norm(query_embedding) * norm(candidate);
+    if norms == 0.0 {
+        return 0.5;
+    }
+    (dot / norms + 1.0) / 2.0
+}
+
+fn version_multiplier(version: u32) -> f64 {
+    match version {
+        1 => 0.7, // Initial results are less refined
+        2 => 0.9, // Better results with complete context
+        3 => 1.1, // Best results after processing delay
+        _ => 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use ads_proto::score::{compare_ads, merge_ads};
+    use ads_proto::testing::ContextFixture;
+    use clap::ValueEnum;
+    use prost::Message;
+
+    use super::*;
+
+    /// Encoded AdsLists of `GOLDEN_SEED`, versions 1 to 3, one hex line each
+    const GOLDEN_LISTS: &str = include_str!("../golden/ads_list_seed42.hex");
+    const GOLDEN_SEED: u64 = 42;
+
+    const RANKING: Ranking = Ranking { tie_break: TieBreak::AdId, top_k: None };
+
+    /// The lists a fixed Context generates with `GOLDEN_SEED` must match the checked-in
+    /// bytes of `golden/ads_list_seed42.hex`. A mismatch prints the new bytes; if the
+    /// change is intended, replace the file with them and bump `SCORING_REVISION`.
+    #[test]
+    fn golden_lists_are_byte_identical() {
+        let context = ContextFixture::new()
+            .understanding("refined understanding based on query analysis")
+            .seed(GOLDEN_SEED)
+            .build();
+        let mut golden = GOLDEN_LISTS.lines();
+        for version in 1..=3 {
+            let ads_list =
+                generate_ads(&context, &[], version, GOLDEN_SEED, &BTreeMap::new(), RANKING, GeneratorVariant::Catalog);
+            let encoded = hex::encode(ads_list.encode_to_vec());
+            assert_eq!(golden.next(), Some(encoded.as_str()), "golden AdsList v{} for seed {} changed", version, GOLDEN_SEED);
+        }
+    }
+
+    #[test]
+    fn same_seed_generates_same_lists() {
+        for &variant in GeneratorVariant::value_variants() {
+            let context = ContextFixture::new().query("espresso").seed(7).build();
+            let generate = |seed| generate_ads(&context, &[], 2, seed, &BTreeMap::new(), RANKING, variant);
+            assert_eq!(generate(7), generate(7), "{}", variant.name());
+            assert_ne!(generate(7), generate(8), "{}: seed ignored", variant.name());
+        }
+    }
+
+    /// Every version of every variant and request type, plain and batched, under a
+    /// plain and a top-K ranking
+    fn each_session(mut check: impl FnMut(&Context, Ranking, GeneratorVariant, &[AdsList])) {
+        let rankings = [RANKING, Ranking { tie_break: TieBreak::Seeded, top_k: Some(3) }];
+        for &variant in GeneratorVariant::value_variants() {
+            for request_type in [RequestType::Keyword, RequestType::AsinDetail, RequestType::CategoryBrowse] {
+                for queries in [Vec::new(), vec!["coffee maker".to_string(), "espresso".to_string()]] {
+                    let context =
+                        ContextFixture::new().seed(GOLDEN_SEED).request_type(request_type).queries(queries).build();
+                    for ranking in rankings {
+                        let lists: Vec<AdsList> = (1..=3)
+                            .map(|version| {
+                                generate_ads(&context, &[], version, context.seed, &BTreeMap::new(), ranking, variant)
+                            })
+                            .collect();
+                        check(&context, ranking, variant, &lists);
+                    }
+                }
+            }
+        }
+    }
+
+    // Batched lists rank each query on its own
+    fn pools<'a>(context: &Context, list: &'a AdsList) -> Vec<&'a [Ad]> {
+        if context.queries.is_empty() {
+            vec![&list.ads]
+        } else {
+            list.query_results.iter().map(|partition| partition.ads.as_slice()).collect()
+        }
+    }
+
+    /// Scores in [0, 1] ranked best first with ties in tie-break order, at most
+    /// `top_k` ads per pool and one partition per batched query
+    #[test]
+    fn lists_are_ranked_and_cut_by_the_ranking() {
+        each_session(|context, ranking, variant, lists| {
+            for (version, list) in (1..).zip(lists) {
+                let case = format!("{}/{:?}/v{}", variant.name(), context.request_type(), version);
+                assert_eq!(list.version, version, "{}", case);
+                if !context.queries.is_empty() {
+                    assert_eq!(list.query_results.len(), context.queries.len(), "{}", case);
+                }
+                for ads in pools(context, list) {
+                    assert!(!ads.is_empty(), "{}: no ads", case);
+                    assert!(ranking.top_k.is_none_or(|k| ads.len() <= k), "{}: {} ads kept", case, ads.len());
+                    assert!(ads.iter().all(|ad| (0.0..=1.0).contains(&ad.score)), "{}: score outside [0, 1]", case);
+                    // The catalog is empty, so every ad has the same recency
+                    let ordered = ads.windows(2).all(|pair| {
+                        compare_ads(&pair[0], &pair[1], ranking.tie_break, context.seed, |_| 0) != Ordering::Greater
+                    });
+                    assert!(ordered, "{}: not in ranking order", case);
+                }
+            }
+        });
+    }
+
+    /// The same candidate keeps its ad_id from version to version, and merging the
+    /// versions keeps each ad's copy from the latest version that has it
+    #[test]
+    fn merged_versions_keep_the_latest_copy() {
+        each_session(|context, ranking, variant, lists| {
+            let (first, last) = (pools(context, &lists[0])[0], pools(context, &lists[2])[0]);
+            assert!(
+                first.iter().any(|ad| last.iter().any(|later| later.ad_id == ad.ad_id)),
+                "{}: no ad of v1 keeps its ad_id in v3",
+                variant.name()
+            );
+            let merged = merge_ads(
+                lists.iter().map(|list| (list.version, pools(context, list)[0])),
+                ranking.tie_break,
+                context.seed,
+            );
+            assert!(last.iter().all(|ad| merged.contains(ad)), "{}: merge lost a v3 copy", variant.name());
+        });
+    }
+
+    fn context(request_type: RequestType, query: &str) -> Context {
+        ContextFixture::new().query(query).seed(GOLDEN_SEED).request_type(request_type).build()
+    }
+
+    fn generate(context: &Context, variant: GeneratorVariant) -> (AdsList, Vec<AdFeatures>) {
+        let trajectory = ["espresso".to_string()];
+        generate_ads_with_features(context, &trajectory, 1, GOLDEN_SEED, &BTreeMap::new(), RANKING, variant)
+    }
+
+    /// Category browse ignores the query, and the trajectory of earlier queries
+    #[test]
+    fn category_browse_ignores_query() {
+        for &variant in GeneratorVariant::value_variants() {
+            let (coffee, _) = generate(&context(RequestType::CategoryBrowse, "coffee maker"), variant);
+            let (shoes, _) = generate(&context(RequestType::CategoryBrowse, "running shoes"), variant);
+            assert_eq!(coffee, shoes, "{}", variant.name());
+        }
+    }
+
+    /// ASIN detail adds a similar-product affinity that fades with the candidate
+    /// index; keyword requests get none
+    #[test]
+    fn asin_detail_weights_similar_products() {
+        for &variant in GeneratorVariant::value_variants() {
+            let (_, keyword) = generate(&context(RequestType::Keyword, "coffee maker"), variant);
+            let (_, detail) = generate(&context(RequestType::AsinDetail, "coffee maker"), variant);
+            assert!(keyword.iter().all(|f| f.similar_product_affinity == 0.0), "{}", variant.name());
+
+            let mut by_index: Vec<&AdFeatures> = detail.iter().collect();
+            by_index.sort_by_key(|f| f.candidate_index);
+            assert!(by_index.iter().all(|f| f.similar_product_affinity > 0.0), "{}", variant.name());
+            assert!(
+                by_index.windows(2).all(|pair| pair[0].similar_product_affinity > pair[1].similar_product_affinity),
+                "{}: affinity does not fade",
+                variant.name()
+            );
+            let rescored = detail.iter().any(|f| keyword.iter().any(|k| k.ad_id == f.ad_id && k.score != f.score));
+            assert!(rescored, "{}: affinity left every score unchanged", variant.name());
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Session memory: a bounded window of the Contexts a bidirectional channel received,
+//! so scoring can follow how the user got to the current query instead of seeing
+//! only the latest message.
+//!
+//! Consecutive queries are classified as a refinement when they share a token (the
+//! user narrowed or reworded the search) and as a pivot when they share none or the
+//! ASIN changed (the user started over). The trajectory handed to the generator is
+//! the distinct earlier queries since the last pivot, so ads relevant to the whole
+//! refinement path rank above ads relevant to the latest wording alone.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ads::Context;
+
+/// How a Context's query relates to the previous one of its channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    /// First Context, or the same query again (e.g. with the understanding)
+    Same,
+    Refinement,
+    Pivot,
+}
+
+impl Transition {
+    pub fn name(self) -> &'static str {
+        match self {
+            Transition::Same => "same",
+            Transition::Refinement => "refinement",
+            Transition::Pivot => "pivot",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    query: String,
+    asin_id: String,
+    // Whether a pivot separates this entry from the previous one
+    pivot: bool,
+}
+
+/// The last `window` Contexts of one channel; a zero window keeps nothing
+#[derive(Debug, Clone, Default)]
+pub struct ContextHistory {
+    window: usize,
+    entries: VecDeque<HistoryEntry>,
+    contexts: u32,
+    refinements: u32,
+    pivots: u32,
+}
+
+/// Trajectory of one channel, written to the session journal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistorySummary {
+    pub channel_id: u32,
+    /// Every Context of the channel, including those evicted from the window
+    pub contexts: u32,
+    pub refinements: u32,
+    pub pivots: u32,
+    /// Distinct queries still in the window, oldest first
+    pub queries: Vec<String>,
+}
+
+fn shares_token(a: &str, b: &str) -> bool {
+    a.split_whitespace().any(|token| b.split_whitespace().any(|other| other.eq_ignore_ascii_case(token)))
+}
+
+impl ContextHistory {
+    pub fn new(window: usize) -> Self {
+        ContextHistory { window, ..Default::default() }
+    }
+
+    /// Append `context`, evicting the oldest entry beyond the window
+    pub fn record(&mut self, context: &Context) -> Transition {
+        if self.window == 0 {
+            return Transition::Same;
+        }
+        self.contexts += 1;
+        let transition = match self.entries.back() {
+            None => Transition::Same,
+            Some(last) if last.asin_id != context.asin_id => Transition::Pivot,
+            Some(last) if last.query == context.query => Transition::Same,
+            Some(last) if shares_token(&last.query, &context.query) => Transition::Refinement,
+            Some(_) => Transition::Pivot,
+        };
+        match transition {
+            Transition::Refinement => self.refinements += 1,
+            Transition::Pivot => self.pivots += 1,
+            Transition::Same => {}
+        }
+        self.entries.push_back(HistoryEntry {
+            query: context.query.clone(),
+            asin_id: context.asin_id.clone(),
+            pivot: transition == Transition::Pivot,
+        });
+        if self.entries.len() > self.window {
+            self.entries.pop_front();
+        }
+        transition
+    }
+
+    /// Distinct queries that led to the latest one since the last pivot, oldest
+    /// first and without the latest query itself
+    pub fn trajectory(&self) -> Vec<String> {
+        let Some(latest) = self.entries.back() else { return Vec::new() };
+        let start = self.entries.iter().rposition(|entry| entry.pivot).unwrap_or(0);
+        let mut queries: Vec<String> = Vec::new();
+        for entry in self.entries.range(start..) {
+            if entry.query != latest.query && !entry.query.is_empty() && !queries.contains(&entry.query) {
+                queries.push(entry.query.clone());
+            }
+        }
+        queries
+    }
+
+    pub fn summary(&self, channel_id: u32) -> Option<HistorySummary> {
+        if self.contexts == 0 {
+            return None;
+        }
+        let mut queries: Vec<String> = Vec::new();
+        for entry in &self.entries {
+            if !queries.contains(&entry.query) {
+                queries.push(entry.query.clone());
+            }
+        }
+        Some(HistorySummary {
+            channel_id,
+            contexts: self.contexts,
+            refinements: self.refinements,
+            pivots: self.pivots,
+            queries,
+        })
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use ads_common::outcome::{OutcomeFunnel, SessionOutcome};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::history::HistorySummary;
+
+/// One finished session, written as a JSON line to the session journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub session_id: u64,
+    pub request_id: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub started_at_unix_ms: u64,
+    pub duration_ms: u64,
+    pub failed: bool,
+    /// `SessionOutcome` name; empty in journals written before outcomes were recorded
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub outcome: String,
+    /// Context history of each channel of a bidirectional session
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<HistorySummary>,
+    /// Version generated after the client disconnected and never delivered, with its
+    /// ad ids (`--disconnect-policy finish` or `grace`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undelivered_version: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub undelivered_ad_ids: Vec<String>,
+}
+
+/// Append-only record of sessions for later per-experiment analysis
+#[derive(Debug)]
+pub struct SessionJournal {
+    file: Mutex<File>,
+}
+
+impl SessionJournal {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(SessionJournal { file: Mutex::new(file) })
+    }
+
+    pub fn record(&self, entry: &JournalEntry) {
+        let line = match serde_json::to_string(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!(session_id = entry.session_id, error = %e, "Failed to serialize journal entry");
+                return;
+            }
+        };
+        if let Err(e) = writeln!(self.file.lock().unwrap(), "{}", line) {
+            warn!(session_id = entry.session_id, error = %e, "Failed to write journal entry");
+        }
+    }
+}
+
+/// Version of the archive layout written by `export_archive`; bumped on incompatible changes
+pub const ARCHIVE_SCHEMA_VERSION: u32 = 1;
+const ARCHIVE_KIND: &str = "ads-session-archive";
+
+/// First line of an archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveHeader {
+    pub kind: String,
+    pub schema_version: u32,
+    pub created_at_unix_ms: u64,
+    pub sessions: usize,
+    pub captures: usize,
+}
+
+/// Every following line: a journal entry, or a client capture (an `ads-client
+/// --record` line, kept verbatim so the archive does not depend on the client's types)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ArchiveRecord {
+    Session(JournalEntry),
+    Capture { capture: serde_json::Value },
+}
+
+#[derive(Debug, Default)]
+pub struct Archive {
+    pub header: Option<ArchiveHeader>,
+    pub sessions: Vec<JournalEntry>,
+    pub captures: Vec<serde_json::Value>,
+}
+
+fn invalid_data(e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn read_json_lines<T: serde::de::DeserializeOwned>(path: &Path) -> io::Result<Vec<T>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut out = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        out.push(serde_json::from_str(&line).map_err(|e| invalid_data(format!("{} line {}: {}", path.display(), i + 1, e)))?);
+    }
+    Ok(out)
+}
+
+/// Write a journal (and optionally a client capture file) to a gzip-compressed
+/// JSON-lines archive. Returns the header that was written.
+pub fn export_archive(journal: &Path, captures: Option<&Path>, out: &Path) -> io::Result<ArchiveHeader> {
+    let sessions: Vec<JournalEntry> = read_json_lines(journal)?;
+    let captures: Vec<serde_json::Value> = match captures {
+        Some(path) => read_json_lines(path)?,
+        None => Vec::new(),
+    };
+    let header = ArchiveHeader {
+        kind: ARCHIVE_KIND.to_string(),
+        schema_version: ARCHIVE_SCHEMA_VERSION,
+        created_at_unix_ms: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64),
+        sessions: sessions.len(),
+        captures: captures.len(),
+    };
+    let mut encoder = GzEncoder::new(File::create(out)?, flate2::Compression::default());
+    writeln!(encoder, "{}", serde_json::to_string(&header).map_err(invalid_data)?)?;
+    for entry in sessions {
+        writeln!(encoder, "{}", serde_json::to_string(&ArchiveRecord::Session(entry)).map_err(invalid_data)?)?;
+    }
+    for capture in captures {
+        writeln!(encoder, "{}", serde_json::to_string(&ArchiveRecord::Capture { capture }).map_err(invalid_data)?)?;
+    }
+    encoder.finish()?;
+    Ok(header)
+}
+
+/// Read an archive, rejecting other kinds and newer schema versions
+pub fn read_archive(path: &Path) -> io::Result<Archive> {
+    let reader = BufReader::new(GzDecoder::new(File::open(path)?));
+    let mut archive = Archive::default();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if archive.header.is_none() {
+            let header: ArchiveHeader = serde_json::from_str(&line).map_err(|e| invalid_data(format!("archive header: {}", e)))?;
+            if header.kind != ARCHIVE_KIND {
+                return Err(invalid_data(format!("not a session archive (kind {:?})", header.kind)));
+            }
+            if header.schema_version > ARCHIVE_SCHEMA_VERSION {
+                return Err(invalid_data(format!(
+                    "archive schema version {} is newer than supported version {}",
+                    header.schema_version, ARCHIVE_SCHEMA_VERSION
+                )));
+            }
+            archive.header = Some(header);
+            continue;
+        }
+        match serde_json::from_str(&line).map_err(|e| invalid_data(format!("archive line {}: {}", i + 1, e)))? {
+            ArchiveRecord::Session(entry) => archive.sessions.push(entry),
+            ArchiveRecord::Capture { capture } => archive.captures.push(capture),
+        }
+    }
+    if archive.header.is_none() {
+        return Err(invalid_data("empty archive"));
+    }
+    Ok(archive)
+}
+
+/// Append an archive's sessions to `journal` and its captures to `captures`
+/// (captures are skipped when no path is given). Returns (sessions, captures) written.
+pub fn import_archive(archive: &Archive, journal: &Path, captures: Option<&Path>) -> io::Result<(usize, usize)> {
+    let journal = SessionJournal::open(journal)?;
+    for entry in &archive.sessions {
+        journal.record(entry);
+    }
+    let mut written_captures = 0;
+    if let Some(path) = captures {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        for capture in &archive.captures {
+            writeln!(file, "{}", capture)?;
+        }
+        written_captures = archive.captures.len();
+    }
+    Ok((archive.sessions.len(), written_captures))
+}
+
+impl Archive {
+    /// Human-readable summary for `journal inspect`
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        if let Some(header) = &self.header {
+            out.push_str(&format!(
+                "{} schema v{} created_at_unix_ms={}\n",
+                header.kind, header.schema_version, header.created_at_unix_ms
+            ));
+        }
+        let failed = self.sessions.iter().filter(|e| e.failed).count();
+        out.push_str(&format!(
+            "sessions: {} ({} failed)  captures: {}\n",
+            self.sessions.len(),
+            failed,
+            self.captures.len()
+        ));
+        let mut durations: Vec<u64> = self.sessions.iter().map(|e| e.duration_ms).collect();
+        durations.sort_unstable();
+        if !durations.is_empty() {
+            let percentile = |q: f64| durations[((durations.len() - 1) as f64 * q).round() as usize];
+            out.push_str(&format!(
+                "duration: mean={:.1}ms p50={}ms p95={}ms\n",
+                durations.iter().sum::<u64>() as f64 / durations.len() as f64,
+                percentile(0.5),
+                percentile(0.95)
+            ));
+        }
+        let mut by_label: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+        for entry in &self.sessions {
+            for (key, value) in &entry.labels {
+                let counts = by_label.entry(format!("{}={}", key, value)).or_default();
+                counts.0 += 1;
+                counts.1 += entry.failed as usize;
+            }
+        }
+        for (label, (sessions, failed)) in by_label {
+            out.push_str(&format!("  {:<32} sessions={} failed={}\n", label, sessions, failed));
+        }
+        let mut funnel = OutcomeFunnel::default();
+        for outcome in self.sessions.iter().filter_map(|e| SessionOutcome::from_name(&e.outcome)) {
+            funnel.record(outcome);
+        }
+        if funnel.total() > 0 {
+            out.push_str(&format!("outcomes: {}\n", funnel.render()));
+        }
+        out
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::Mutex;
+
+use ads_proto::LABEL_METADATA_PREFIX;
+use tonic::metadata::MetadataMap;
+use tracing::warn;
+
+/// Labels beyond this many per session are ignored
+const MAX_LABELS: usize = 16;
+/// Longer label values are truncated
+const MAX_VALUE_LEN: usize = 64;
+/// Metric label value used once a key has seen `max_values` distinct values
+pub const OVERFLOW_VALUE: &str = "other";
+
+/// Experiment labels a client attached to its session as `x-label-<key>` metadata
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionLabels(pub BTreeMap<String, String>);
+
+impl SessionLabels {
+    pub fn from_metadata(metadata: &MetadataMap) -> Self {
+        let mut labels = BTreeMap::new();
+        for key in metadata.keys() {
+            let tonic::metadata::KeyRef::Ascii(key) = key else { continue };
+            let Some(name) = key.as_str().strip_prefix(LABEL_METADATA_PREFIX) else { continue };
+            if name.is_empty() || labels.len() >= MAX_LABELS {
+                continue;
+            }
+            if let Some(value) = metadata.get(key.as_str()).and_then(|v| v.to_str().ok()) {
+                let value: String = value.chars().take(MAX_VALUE_LEN).collect();
+                labels.insert(name.replace('-', "_"), value);
+            }
+        }
+        SessionLabels(labels)
+    }
+}
+
+impl fmt::Display for SessionLabels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decides which session labels become metric labels. Only allowlisted keys are
+/// used, and each key admits at most `max_values` distinct values before further
+/// values collapse into "other", so a client cannot blow up metric cardinality.
+#[derive(Debug)]
+pub struct LabelPolicy {
+    metric_keys: Vec<String>,
+    max_values: usize,
+    seen: Mutex<HashMap<String, HashSet<String>>>,
+}
+
+impl LabelPolicy {
+    pub fn new(metric_keys: Vec<String>, max_values: usize) -> Self {
+        LabelPolicy {
+            metric_keys,
+            max_values,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Metric labels for a session, in allowlist order (missing keys are omitted)
+    pub fn metric_labels(&self, labels: &SessionLabels) -> Vec<(String, String)> {
+        let mut seen = self.seen.lock().unwrap();
+        let mut out = Vec::new();
+        for key in &self.metric_keys {
+            let Some(value) = labels.0.get(key) else { continue };
+            let values = seen.entry(key.clone()).or_default();
+            let value = if values.contains(value) {
+                value.clone()
+            } else if values.len() < self.max_values {
+                values.insert(value.clone());
+                value.clone()
+            } else {
+                warn!(label = %key, value = %value, max_values = self.max_values, "Label value cap reached - reporting as other");
+                OVERFLOW_VALUE.to_string()
+            };
+            out.push((key.clone(), value));
+        }
+        out
+    }
+}
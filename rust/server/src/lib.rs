@@ -0,0 +1,1664 @@
+// tonic::Status is large, and Result<_, Status> (or an error wrapping it) is what
+// every handler and client call returns; boxing it at each call site buys nothing
+#![allow(clippy::result_large_err)]
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+use tokio::time::sleep;
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{info, warn, debug, error, span, Instrument, Level};
+
+pub use ads_proto::ads;
+
+pub mod admin;
+pub mod announce;
+pub mod backpressure;
+pub mod batch;
+pub mod budget;
+pub mod bundle;
+pub mod catalog;
+pub mod catalog_load;
+pub mod checkpoint;
+pub mod coalesce;
+pub mod config;
+pub mod config_schema;
+pub mod connections;
+pub mod constraints;
+pub mod containment;
+pub mod debugsession;
+pub mod dedupe;
+pub mod dictionary;
+pub mod disconnect;
+pub mod drift;
+pub mod downstream;
+pub mod dryrun;
+pub mod faults;
+pub mod features;
+pub mod generator;
+pub mod history;
+pub mod journal;
+pub mod labels;
+pub mod limits;
+pub mod maintenance;
+pub mod metrics;
+pub mod nofill;
+pub mod ordering;
+pub mod overload;
+pub mod plugin;
+pub mod priority;
+pub mod profiling;
+pub mod quality;
+pub mod runtime_config;
+pub mod sanitize;
+pub mod shutdown;
+pub mod signing;
+pub mod slo;
+pub mod softlimit;
+pub mod strict;
+pub mod stub;
+pub mod testhooks;
+pub mod textnorm;
+pub mod topk;
+pub mod transport_bench;
+pub mod treatment;
+pub mod variant;
+pub mod verify;
+pub mod waste;
+
+use ads::{ads_list, ads_service_server::AdsService, AckContext, AdsList, Context, HandshakeRequest, HandshakeResponse, ScoreNormalization, SessionFeedback, SessionFeedbackResponse};
+use ads_common::limits::{FINAL_VERSION, INITIAL_VERSION, REFINED_VERSION};
+use ads_common::outcome::{SessionEnd, SessionOutcome};
+use ads_proto::score::normalize_list;
+use ads_proto::status::{self as status_taxonomy, BackpressureHint};
+use backpressure::OverflowPolicy;
+use budget::LatencyBudget;
+use bundle::{DebugBundles, SchedulerProbe, SessionTrace};
+use catalog::Catalog;
+use checkpoint::{CheckpointStore, Replay};
+use coalesce::Coalescer;
+use config::ServerConfig;
+use constraints::SlotConstraints;
+use debugsession::DebugSessionGate;
+use dedupe::{DuplicatePolicy, Registration, SessionRegistry};
+use disconnect::DisconnectPolicy;
+use downstream::Downstream;
+use drift::ScoreDriftMonitor;
+use faults::{injected_failure, FaultAction, FaultInjector};
+use features::FeatureLog;
+use history::{ContextHistory, HistorySummary, Transition};
+use journal::{JournalEntry, SessionJournal};
+use labels::{LabelPolicy, SessionLabels};
+use maintenance::Maintenance;
+use metrics::Metrics;
+use ordering::OrderWatchdog;
+use overload::OverloadController;
+use plugin::GeneratorPlugin;
+use priority::{GenerationScheduler, PriorityClass};
+use quality::QualityGate;
+use runtime_config::{ConfigStore, RuntimeConfig};
+use sanitize::Sanitizer;
+use signing::ResponseSigner;
+use slo::SloTracker;
+use softlimit::{LimitWarnings, SessionLimits};
+use strict::ContractChecker;
+use testhooks::TestCase;
+use textnorm::QueryNormalizer;
+use treatment::Treatment;
+use variant::{GeneratorVariant, VariantPolicy};
+use waste::{VersionWork, WorkLedger};
+
+#[derive(Debug)]
+pub struct AdsServiceImpl {
+    session_counter: AtomicU64,
+    metrics: Arc<Metrics>,
+    overload: Arc<OverloadController>,
+    config_store: Arc<ConfigStore>,
+    faults: Arc<FaultInjector>,
+    catalog: Arc<Catalog>,
+    plugin: Option<Arc<GeneratorPlugin>>,
+    slo: Arc<SloTracker>,
+    maintenance: Arc<Maintenance>,
+    score_normalization: ScoreNormalization,
+    min_score: Option<f64>,
+    session_limits: Arc<SessionLimits>,
+    session_registry: Arc<SessionRegistry>,
+    duplicate_policy: DuplicatePolicy,
+    output_channel_capacity: usize,
+    overflow_policy: OverflowPolicy,
+    label_policy: Arc<LabelPolicy>,
+    journal: Option<Arc<SessionJournal>>,
+    debug_bundles: Option<Arc<DebugBundles>>,
+    feature_log: Option<Arc<FeatureLog>>,
+    checkpoints: Option<Arc<CheckpointStore>>,
+    score_drift: Option<Arc<ScoreDriftMonitor>>,
+    signer: Option<Arc<ResponseSigner>>,
+    coalescer: Arc<Coalescer>,
+    active_sessions: Arc<AtomicUsize>,
+    max_concurrent_sessions: usize,
+    session_limit_retry_after: Duration,
+    default_priority: PriorityClass,
+    // Concurrent sessions batch traffic may hold, the rest is kept for interactive ones
+    batch_session_limit: usize,
+    // Delayed version 3 tasks not yet finished, across sessions
+    refinement_tasks: Arc<AtomicUsize>,
+    max_refinement_tasks: usize,
+    disconnect_policy: DisconnectPolicy,
+    disconnect_grace: Duration,
+    context_history_window: usize,
+    // Window between a channel's two Contexts when strict protocol mode is on
+    strict_protocol: Option<Duration>,
+    variant_policy: VariantPolicy,
+    debug_sessions: DebugSessionGate,
+    quality_gate: Option<QualityGate>,
+    sanitizer: Option<Arc<Sanitizer>>,
+    normalizer: Option<Arc<QueryNormalizer>>,
+    work_ledger: Arc<WorkLedger>,
+}
+
+impl AdsServiceImpl {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        config: &ServerConfig,
+        metrics: Arc<Metrics>,
+        config_store: Arc<ConfigStore>,
+        faults: Arc<FaultInjector>,
+        catalog: Arc<Catalog>,
+        plugin: Option<Arc<GeneratorPlugin>>,
+        slo: Arc<SloTracker>,
+        maintenance: Arc<Maintenance>,
+    ) -> Self {
+        let overload = OverloadController::new(
+            config.overload_target(),
+            config.overload_interval(),
+            metrics.clone(),
+        )
+        .with_batch_target_fraction(config.batch_overload_target_fraction);
+        let downstream = Downstream::new(
+            config.downstream.clone(),
+            Duration::from_millis(config.downstream_timeout_ms),
+            !config.downstream_sequential,
+            config.downstream_on_failure,
+            metrics.clone(),
+        );
+        let debug_sessions = DebugSessionGate::new(config.debug_session_rate, metrics.clone());
+        let coalescer = Coalescer::new(
+            Duration::from_millis(config.coalesce_window_ms),
+            config.ranking(),
+            downstream,
+            metrics.clone(),
+        )
+        .with_scheduler(GenerationScheduler::new(config.generation_workers, metrics.clone()));
+        let sanitizer = Sanitizer::new(
+            &config.sanitize,
+            config.sanitize_max_query_chars,
+            config.sanitize_max_understanding_chars,
+            metrics.clone(),
+        )
+        .map(Arc::new);
+        let normalizer =
+            QueryNormalizer::new(config.normalize_text, &config.default_locale, &config.stem, metrics.clone())
+                .map(Arc::new);
+        let session_limits = Arc::new(SessionLimits::new(
+            config.max_ads_per_list,
+            config.max_understanding_chars,
+            config.max_contexts_per_session,
+            &config.soft_limits,
+            metrics.clone(),
+        ));
+        let work_ledger = Arc::new(WorkLedger::new(Duration::from_secs(config.feedback_wait_secs), metrics.clone()));
+        AdsServiceImpl {
+            session_counter: AtomicU64::new(0),
+            metrics,
+            overload: Arc::new(overload),
+            config_store,
+            faults,
+            catalog,
+            plugin,
+            slo,
+            maintenance,
+            score_normalization: config.score_normalization.into(),
+            min_score: config.min_score,
+            session_limits,
+            session_registry: Arc::new(SessionRegistry::default()),
+            duplicate_policy: config.duplicate_session_policy,
+            output_channel_capacity: config.output_channel_capacity,
+            overflow_policy: config.overflow_policy,
+            label_policy: Arc::new(LabelPolicy::new(config.metric_label_keys.clone(), config.max_label_values)),
+            journal: None,
+            debug_bundles: None,
+            feature_log: None,
+            checkpoints: None,
+            score_drift: None,
+            signer: None,
+            coalescer: Arc::new(coalescer),
+            active_sessions: Arc::new(AtomicUsize::new(0)),
+            max_concurrent_sessions: config.max_concurrent_sessions as usize,
+            session_limit_retry_after: Duration::from_millis(config.session_limit_retry_after_ms),
+            default_priority: config.default_priority,
+            batch_session_limit: config.batch_session_limit(),
+            refinement_tasks: Arc::new(AtomicUsize::new(0)),
+            max_refinement_tasks: config.max_refinement_tasks,
+            disconnect_policy: config.disconnect_policy,
+            disconnect_grace: Duration::from_millis(config.disconnect_grace_ms),
+            context_history_window: config.context_history_window,
+            strict_protocol: config.strict_protocol.then(|| Duration::from_millis(config.strict_window_ms)),
+            variant_policy: VariantPolicy::new(config.default_generator_variant, config.generator_variants.clone()),
+            debug_sessions,
+            quality_gate: config.quality_gate(),
+            sanitizer,
+            normalizer,
+            work_ledger,
+        }
+    }
+    
+    /// RESOURCE_EXHAUSTED for a session shed while overloaded, hinting when to retry
+    fn shed_overloaded(&self) -> Status {
+        let hint = BackpressureHint {
+            retry_after: Some(self.overload.retry_after()),
+            queue_depth: Some(self.active_sessions.load(Ordering::SeqCst) as u64),
+        };
+        status_taxonomy::shed("server overloaded, retry later", hint)
+    }
+
+    /// Sessions currently holding a slot; drained to zero before maintenance shutdown
+    pub fn active_sessions(&self) -> Arc<AtomicUsize> {
+        self.active_sessions.clone()
+    }
+
+    /// Generation work awaiting client feedback; settled before the shutdown report
+    pub fn work_ledger(&self) -> Arc<WorkLedger> {
+        self.work_ledger.clone()
+    }
+    
+    /// Append an entry for every finished session to `journal`
+    pub fn with_journal(mut self, journal: SessionJournal) -> Self {
+        self.journal = Some(Arc::new(journal));
+        self
+    }
+    
+    /// Write a debug bundle for every failed bidirectional session to `bundles`
+    pub fn with_debug_bundles(mut self, bundles: DebugBundles) -> Self {
+        self.debug_bundles = Some(Arc::new(bundles));
+        self
+    }
+    
+    /// Record the score inputs of every ad generated for sampled sessions
+    pub fn with_feature_log(mut self, feature_log: FeatureLog) -> Self {
+        self.feature_log = Some(Arc::new(feature_log));
+        self
+    }
+    
+    /// Checkpoint bidirectional sessions into `checkpoints` and resume them from it
+    pub fn with_checkpoints(mut self, checkpoints: CheckpointStore) -> Self {
+        self.checkpoints = Some(Arc::new(checkpoints));
+        self
+    }
+    
+    /// Record the scores of every AdsList sent in `score_drift`
+    pub fn with_score_drift(mut self, score_drift: Arc<ScoreDriftMonitor>) -> Self {
+        self.score_drift = Some(score_drift);
+        self
+    }
+    
+    /// Sign every AdsList sent with `signer`
+    pub fn with_signer(mut self, signer: ResponseSigner) -> Self {
+        self.signer = Some(Arc::new(signer));
+        self
+    }
+    
+    /// Admission shared by the GetAds calls: maintenance and overload refusals, then
+    /// a slot under the priority's concurrent session cap, held until the guard drops
+    fn admit_session(&self, priority: PriorityClass) -> Result<Arc<SessionGuard>, Status> {
+        if let Some(status) = self.maintenance.refusal() {
+            self.metrics.inc("sessions_rejected_total", &[("reason", "maintenance"), ("priority", priority.name())]);
+            return Err(status);
+        }
+        if self.overload.sheds(priority) {
+            self.metrics.inc("sessions_rejected_total", &[("reason", "overload"), ("priority", priority.name())]);
+            self.slo.record_session(false);
+            warn!(priority = priority.name(), "Rejecting new session - server overloaded");
+            return Err(self.shed_overloaded());
+        }
+        let active = self.active_sessions.fetch_add(1, Ordering::SeqCst) + 1;
+        let session_guard = Arc::new(SessionGuard {
+            active_sessions: self.active_sessions.clone(),
+            metrics: self.metrics.clone(),
+            slo: self.slo.clone(),
+            failed: AtomicBool::new(false),
+            failure: OnceLock::new(),
+            cancelled: AtomicBool::new(false),
+            highest_version: AtomicU32::new(0),
+            no_fill: AtomicBool::new(false),
+            journal: self.journal.clone(),
+            record: OnceLock::new(),
+            history: Mutex::new(Vec::new()),
+            undelivered: Mutex::new(None),
+            work: Mutex::new(Vec::new()),
+            work_ledger: self.work_ledger.clone(),
+            limit_warnings: LimitWarnings::default(),
+            trace: self.debug_bundles.clone().map(|bundles| {
+                let probe = SchedulerProbe {
+                    active_sessions: self.active_sessions.clone(),
+                    refinement_tasks: self.refinement_tasks.clone(),
+                    overload: self.overload.clone(),
+                    coalescer: self.coalescer.clone(),
+                };
+                SessionTrace::new(bundles, probe, priority)
+            }),
+        });
+        let session_limit = match priority {
+            PriorityClass::Interactive => self.max_concurrent_sessions,
+            PriorityClass::Batch => self.batch_session_limit,
+        };
+        if active > session_limit {
+            self.metrics.inc("sessions_rejected_total", &[("reason", "max_sessions"), ("priority", priority.name())]);
+            warn!(
+                active_sessions = active - 1,
+                session_limit = session_limit,
+                priority = priority.name(),
+                "Rejecting new session - concurrent session limit reached"
+            );
+            let hint = BackpressureHint {
+                retry_after: Some(self.session_limit_retry_after),
+                queue_depth: Some(active as u64 - 1),
+            };
+            let status = status_taxonomy::shed("too many concurrent sessions", hint);
+            session_guard.mark_failed(&status);
+            return Err(status);
+        }
+        self.metrics.set_gauge("active_sessions", &[], active as i64);
+        Ok(session_guard)
+    }
+    
+    /// Slot constraints, test hook and fault rules of a new session, from the
+    /// hot-reloadable config snapshot the session runs with
+    fn version_hooks(
+        &self,
+        runtime: &RuntimeConfig,
+        metadata: &tonic::metadata::MetadataMap,
+        session_id: u64,
+    ) -> Result<VersionHooks, Status> {
+        let test_case = if runtime.enable_test_hooks {
+            TestCase::from_metadata(metadata)?
+        } else {
+            if metadata.contains_key(testhooks::TEST_CASE_METADATA_KEY) {
+                debug!(session_id = session_id, "Ignoring x-test-case metadata - test hooks disabled");
+            }
+            None
+        };
+        if let Some(test_case) = test_case {
+            info!(session_id = session_id, test_case = test_case.name(), "Test hook active for session");
+        }
+        Ok(VersionHooks {
+            slot_constraints: runtime.slot_constraints.then(|| Arc::new(SlotConstraints::default())),
+            test_case,
+            faults: self.faults.clone(),
+        })
+    }
+    
+    /// Generator variant a new session runs, honoring its `x-generator` metadata
+    /// if the variant is enabled
+    fn session_variant(&self, session_id: u64, metadata: &tonic::metadata::MetadataMap) -> GeneratorVariant {
+        let (variant, choice) = self.variant_policy.resolve(metadata);
+        self.metrics.inc("generator_variant_sessions_total", &[("variant", variant.name()), ("choice", choice.name())]);
+        match choice {
+            variant::Choice::Disabled | variant::Choice::Unknown => warn!(
+                session_id = session_id,
+                requested = ?metadata.get(ads_proto::GENERATOR_METADATA_KEY),
+                reason = choice.name(),
+                generator_variant = variant.name(),
+                "Ignoring requested generator variant"
+            ),
+            _ => info!(
+                session_id = session_id,
+                generator_variant = variant.name(),
+                choice = choice.name(),
+                "Generator variant for session"
+            ),
+        }
+        variant
+    }
+}
+
+/// Identity of an admitted session, reported when it finishes
+#[derive(Debug)]
+struct SessionRecord {
+    session_id: u64,
+    request_id: String,
+    // From the client's traceparent; attached as exemplar to the session's latency observations
+    trace_id: Option<String>,
+    labels: SessionLabels,
+    metric_labels: Vec<(String, String)>,
+    started_at: SystemTime,
+    start: Instant,
+}
+
+impl SessionRecord {
+    fn new(
+        metadata: &tonic::metadata::MetadataMap,
+        session_id: u64,
+        labels: SessionLabels,
+        metric_labels: Vec<(String, String)>,
+        start: Instant,
+    ) -> Self {
+        let request_id = metadata
+            .get(ads_proto::REQUEST_ID_METADATA_KEY)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        let trace_id = metadata
+            .get(ads_proto::TRACEPARENT_METADATA_KEY)
+            .and_then(|value| value.to_str().ok())
+            .and_then(ads_proto::trace_id)
+            .map(str::to_string);
+        SessionRecord { session_id, request_id, trace_id, labels, metric_labels, started_at: SystemTime::now(), start }
+    }
+}
+
+/// Holds one slot of the concurrent session cap until every task of the session is
+/// done, then reports the session outcome to the success SLO, the labeled session
+/// metrics and the journal
+#[derive(Debug)]
+struct SessionGuard {
+    active_sessions: Arc<AtomicUsize>,
+    metrics: Arc<Metrics>,
+    slo: Arc<SloTracker>,
+    failed: AtomicBool,
+    // Message of the status that failed the session first
+    failure: OnceLock<String>,
+    // The client dropped the stream; not a server failure
+    cancelled: AtomicBool,
+    // Highest AdsList version that went out on any channel, and whether the last one
+    // sent of that version was a no-fill
+    highest_version: AtomicU32,
+    no_fill: AtomicBool,
+    journal: Option<Arc<SessionJournal>>,
+    // Unset for sessions rejected at admission
+    record: OnceLock<SessionRecord>,
+    // Context history of every channel, set once the client half-closes
+    history: Mutex<Vec<HistorySummary>>,
+    // Version (and its ad ids) finished after the client disconnected
+    undelivered: Mutex<Option<(u32, Vec<String>)>>,
+    // Set with --debug-bundle-dir
+    trace: Option<SessionTrace>,
+    // Generation of each version, filed in the work ledger when the session finishes
+    work: Mutex<Vec<VersionWork>>,
+    work_ledger: Arc<WorkLedger>,
+    // Soft limits the session went over, sent in its trailers
+    limit_warnings: LimitWarnings,
+}
+
+impl SessionGuard {
+    fn mark_failed(&self, status: &Status) {
+        self.failed.store(true, Ordering::SeqCst);
+        let _ = self.failure.set(status.message().to_string());
+        if let Some(trace) = &self.trace {
+            trace.fail(status);
+        }
+    }
+    
+    fn is_failed(&self) -> bool {
+        self.failed.load(Ordering::SeqCst)
+    }
+
+    fn mark_cancelled(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    fn record_sent(&self, version: u32, no_fill: bool) {
+        if self.highest_version.fetch_max(version, Ordering::SeqCst) <= version {
+            self.no_fill.store(no_fill, Ordering::SeqCst);
+        }
+    }
+
+    fn trace_context(&self, context: &Context, number: u32, transition: Transition) {
+        if let Some(trace) = &self.trace {
+            trace.context(context, number, transition);
+        }
+    }
+
+    fn trace_version(&self, ads_list: &AdsList, generation_ms: u64) {
+        if let Some(trace) = &self.trace {
+            trace.version(ads_list, generation_ms);
+        }
+    }
+
+    fn record_work(&self, version: u32, generation: Duration, candidates: usize, delivered: bool) {
+        self.work.lock().unwrap().push(VersionWork { version, generation, candidates: candidates as u64, delivered });
+    }
+
+    fn record_undelivered(&self, ads_list: &AdsList) {
+        let ad_ids = ads_list.ads.iter().map(|ad| ad.ad_id.clone()).collect();
+        *self.undelivered.lock().unwrap() = Some((ads_list.version, ad_ids));
+    }
+
+    fn outcome(&self) -> SessionOutcome {
+        let end = if self.is_failed() {
+            SessionEnd::Failed
+        } else if self.cancelled.load(Ordering::SeqCst) {
+            SessionEnd::Cancelled
+        } else {
+            SessionEnd::Completed
+        };
+        SessionOutcome::classify(end, self.highest_version.load(Ordering::SeqCst), self.no_fill.load(Ordering::SeqCst))
+    }
+
+    fn write_debug_bundle(&self, trace: &SessionTrace, record: &SessionRecord, duration: Duration) {
+        let Some(mut bundle) = trace.bundle(record.session_id) else { return };
+        bundle.request_id = record.request_id.clone();
+        bundle.labels = record.labels.0.clone();
+        bundle.started_at_unix_ms = record.started_at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        bundle.duration_ms = duration.as_millis() as u64;
+        bundle.highest_version_sent = self.highest_version.load(Ordering::SeqCst);
+        bundle.history = self.history.lock().unwrap().clone();
+        match trace.write(&bundle) {
+            Ok(path) => error!(
+                session_id = record.session_id,
+                request_id = %record.request_id,
+                error = %bundle.error,
+                debug_bundle = %path.display(),
+                "Session failed - wrote debug bundle"
+            ),
+            Err(e) => warn!(session_id = record.session_id, error = %e, "Failed to write debug bundle"),
+        }
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let active = self.active_sessions.fetch_sub(1, Ordering::SeqCst) - 1;
+        self.metrics.set_gauge("active_sessions", &[], active as i64);
+        let failed = self.failed.load(Ordering::SeqCst);
+        self.slo.record_session(!failed);
+        let Some(record) = self.record.get() else { return };
+        let duration = record.start.elapsed();
+        self.work_ledger.finish(&record.request_id, std::mem::take(&mut *self.work.lock().unwrap()));
+        let outcome = if failed { "failed" } else { "ok" };
+        let mut labels: Vec<(&str, &str)> = vec![("outcome", outcome)];
+        labels.extend(record.metric_labels.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        self.metrics.inc("sessions_finished_total", &labels);
+        self.metrics.observe_ms_traced("session_duration_ms", &labels[1..], duration, record.trace_id.as_deref());
+        let session_outcome = self.outcome();
+        labels[0] = ("outcome", session_outcome.name());
+        self.metrics.inc("session_outcomes_total", &labels);
+        if let (true, Some(trace)) = (failed, &self.trace) {
+            self.write_debug_bundle(trace, record, duration);
+        }
+        if let Some(journal) = &self.journal {
+            let undelivered = self.undelivered.lock().unwrap().take();
+            journal.record(&JournalEntry {
+                session_id: record.session_id,
+                request_id: record.request_id.clone(),
+                labels: record.labels.0.clone(),
+                started_at_unix_ms: record.started_at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64),
+                duration_ms: duration.as_millis() as u64,
+                failed,
+                outcome: session_outcome.name().to_string(),
+                history: std::mem::take(&mut *self.history.lock().unwrap()),
+                undelivered_version: undelivered.as_ref().map(|(version, _)| *version),
+                undelivered_ad_ids: undelivered.map(|(_, ad_ids)| ad_ids).unwrap_or_default(),
+            });
+        }
+    }
+}
+
+/// State of one logical ads session. A plain stream carries a single session on
+/// channel 0; multiplexed clients interleave several sessions keyed by channel_id.
+#[derive(Debug, Default)]
+struct ChannelState {
+    context_count: u32,
+    // Contexts received, unlike context_count not advanced by an informed start
+    received: u32,
+    last_context: Option<Context>,
+    // Seed negotiated by the first Context; drives all stochastic generation in the session
+    seed: u64,
+    last_context_at: Option<Instant>,
+    // Budget of the latest Context, re-anchored on each one since the client sends what it has left
+    budget: Option<LatencyBudget>,
+    // Checkpoint this channel resumes from, until the client has caught up with it
+    resumed: Option<checkpoint::Checkpoint>,
+    // Session memory scoring considers on top of the latest Context
+    history: ContextHistory,
+    // Set in strict protocol mode
+    contract: Option<ContractChecker>,
+}
+
+/// Count and log a channel's contract violations and fail the stream with them
+async fn fail_contract(
+    metrics: &Metrics,
+    session_id: u64,
+    channel_id: u32,
+    violations: &[strict::Violation],
+    tx: &backpressure::AdsSender,
+    session_guard: &SessionGuard,
+) {
+    for violation in violations {
+        metrics.inc("protocol_violations_total", &[("kind", violation.code())]);
+    }
+    let status = strict::violation_status(channel_id, violations);
+    warn!(
+        session_id = session_id,
+        channel_id = channel_id,
+        violations = %status.message(),
+        "Protocol contract violated - failing stream"
+    );
+    session_guard.mark_failed(&status);
+    let _ = tx.send(Err(status)).await;
+}
+
+/// Pause before the refined version 3 of a bidirectional session
+const REFINEMENT_DELAY: Duration = Duration::from_millis(50);
+
+/// Counts one delayed refinement task in `refinement_tasks_outstanding` until it
+/// finishes, panics or is aborted
+struct RefinementTask {
+    outstanding: Arc<AtomicUsize>,
+    metrics: Arc<Metrics>,
+}
+
+impl RefinementTask {
+    fn start(outstanding: Arc<AtomicUsize>, metrics: Arc<Metrics>) -> Self {
+        let count = outstanding.fetch_add(1, Ordering::SeqCst) + 1;
+        metrics.set_gauge("refinement_tasks_outstanding", &[], count as i64);
+        RefinementTask { outstanding, metrics }
+    }
+}
+
+impl Drop for RefinementTask {
+    fn drop(&mut self) {
+        let count = self.outstanding.fetch_sub(1, Ordering::SeqCst) - 1;
+        self.metrics.set_gauge("refinement_tasks_outstanding", &[], count as i64);
+    }
+}
+
+/// Per-version rules of a session, applied to every AdsList after ranking
+#[derive(Debug, Clone)]
+struct VersionHooks {
+    slot_constraints: Option<Arc<SlotConstraints>>,
+    test_case: Option<TestCase>,
+    faults: Arc<FaultInjector>,
+}
+
+impl VersionHooks {
+    /// Apply the rules to `version`; an error is sent in its place and fails the stream
+    async fn apply(&self, ads_list: &mut AdsList, session_id: u64, version: u32) -> Result<(), Status> {
+        if let Some(constraints) = &self.slot_constraints {
+            constraints.apply_list(ads_list, session_id, version);
+        }
+        if let Some(test_case) = self.test_case {
+            test_case.apply(ads_list);
+            if let Some(status) = test_case.failure_for(version) {
+                warn!(session_id = session_id, test_case = test_case.name(), version = version, "Test hook failing stream");
+                return Err(status);
+            }
+            if let Some(delay) = test_case.delay_for(version) {
+                sleep(delay).await;
+            }
+        }
+        match self.faults.decide(session_id, version) {
+            Some(FaultAction::Fail) => return Err(injected_failure(version)),
+            Some(FaultAction::Delay(delay)) => sleep(delay).await,
+            // Tamper rules act after signing, in the output channel
+            Some(FaultAction::Tamper) | None => {}
+        }
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl AdsService for AdsServiceImpl {
+    type GetAdsStream = Pin<Box<dyn Stream<Item = Result<AdsList, Status>> + Send>>;
+
+    async fn get_ads(
+        &self,
+        request: Request<Streaming<Context>>,
+    ) -> Result<Response<Self::GetAdsStream>, Status> {
+        let (tx, output) = backpressure::channel(
+            self.output_channel_capacity,
+            self.overflow_policy,
+            self.metrics.clone(),
+            self.signer.clone(),
+            self.faults.clone(),
+        );
+        
+        // A retry carrying the key of a still-active session is resolved before admission
+        // control: attaching to it generates nothing and takes no session slot
+        let idempotency_key = request
+            .metadata()
+            .get(ads_proto::IDEMPOTENCY_KEY_METADATA_KEY)
+            .and_then(|value| value.to_str().ok())
+            .filter(|key| !key.is_empty())
+            .map(str::to_string);
+        let duplicate = idempotency_key
+            .as_deref()
+            .and_then(|key| self.session_registry.active(key).map(|shared| (key, shared)));
+        if let Some((key, shared)) = &duplicate {
+            let policy = self.duplicate_policy;
+            self.metrics.inc("duplicate_sessions_total", &[("policy", policy.name())]);
+            info!(idempotency_key = %key, policy = policy.name(), "Duplicate session for active idempotency key");
+            match policy {
+                DuplicatePolicy::Attach => {
+                    let out_stream = ReceiverStream::new(shared.subscribe());
+                    return Ok(Response::new(Box::pin(out_stream) as Self::GetAdsStream));
+                }
+                DuplicatePolicy::Reject => {
+                    return Err(Status::already_exists(format!(
+                        "session with idempotency key {} is still active",
+                        key
+                    )));
+                }
+                DuplicatePolicy::Regenerate => {}
+            }
+        }
+        
+        let priority = PriorityClass::from_metadata(request.metadata(), self.default_priority);
+        // Attaching to an active session above is still allowed: it is part of draining
+        let session_guard = self.admit_session(priority)?;
+        
+        // Only an admitted session registers its key, so no retry attaches to a refused one
+        let out_stream: Self::GetAdsStream = match &idempotency_key {
+            Some(key) if duplicate.is_none() => match self.session_registry.register(key, output) {
+                // Keyed sessions publish through the registry so attached retries see the same AdsLists
+                Registration::New(shared) => Box::pin(ReceiverStream::new(shared.subscribe())),
+                // Another stream with the key was admitted meanwhile; this one runs on its own
+                Registration::Existing(output) => Box::pin(output),
+            },
+            _ => Box::pin(output),
+        };
+        
+        let session_id = self.session_counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let session_start = Instant::now();
+        let labels = SessionLabels::from_metadata(request.metadata());
+        let mut metric_labels = self.label_policy.metric_labels(&labels);
+        metric_labels.push(("priority".to_string(), priority.name().to_string()));
+        let metric_label_refs: Vec<(&str, &str)> =
+            metric_labels.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        self.metrics.inc("sessions_started_total", &metric_label_refs);
+        
+        let debug_session = self.debug_sessions.admit(session_id, request.metadata());
+        let span = span!(Level::INFO, "session", session_id = session_id, labels = %labels, debug_session = debug_session);
+        let _enter = span.enter();
+        
+        let record = SessionRecord::new(request.metadata(), session_id, labels.clone(), metric_labels, session_start);
+        let request_id = record.request_id.clone();
+        let trace_id = record.trace_id.clone();
+        let _ = session_guard.record.set(record);
+        info!(
+            session_id = session_id,
+            request_id = %request_id,
+            trace_id = trace_id.as_deref(),
+            labels = %labels,
+            idempotency_key = idempotency_key.as_deref(),
+            priority = priority.name(),
+            thread = ?std::thread::current().id(),
+            "New bidirectional stream opened"
+        );
+        let generator_variant = self.session_variant(session_id, request.metadata());
+        self.work_ledger.open(&request_id, Treatment::new(self.plugin.as_deref(), generator_variant, self.coalescer.ranking()));
+        
+        // Snapshot the hot-reloadable knobs once so a session sees a consistent config
+        let runtime = self.config_store.current();
+        
+        // An invalid test hook fails the admitted session, not just the call
+        let hooks = self
+            .version_hooks(&runtime, request.metadata(), session_id)
+            .inspect_err(|status| session_guard.mark_failed(status))?;
+        
+        // With checkpoints on, every stream gets a token it can later be resumed with
+        let resume_token = request
+            .metadata()
+            .get(ads_proto::RESUME_TOKEN_METADATA_KEY)
+            .and_then(|value| value.to_str().ok())
+            .filter(|token| !token.is_empty())
+            .map(str::to_string);
+        let checkpoints = self.checkpoints.clone();
+        let session_token = checkpoints.as_ref().map(|store| store.session_token(resume_token.as_deref()));
+        let ack_contexts = request
+            .metadata()
+            .get(ads_proto::ACK_CONTEXTS_METADATA_KEY)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| matches!(value.trim().to_ascii_lowercase().as_str(), "true" | "1"));
+        let resumed = resume_token.is_some() && resume_token == session_token;
+        let response_token = session_token.clone();
+        
+        let mut in_stream = request.into_inner();
+        let watchdog = Arc::new(
+            OrderWatchdog::new(session_id, self.quality_gate, self.metrics.clone()).with_score_drift(self.score_drift.clone()),
+        );
+        let metrics = self.metrics.clone();
+        let overload = self.overload.clone();
+        let catalog = self.catalog.clone();
+        let plugin = self.plugin.clone();
+        let feature_log = self.feature_log.clone();
+        let coalescer = self.coalescer.clone();
+        let slo = self.slo.clone();
+        let score_normalization = self.score_normalization;
+        let min_score = self.min_score;
+        let session_limits = self.session_limits.clone();
+        let min_context_gap = Duration::from_millis(runtime.min_context_gap_ms);
+        let max_context_gap = Duration::from_millis(runtime.max_context_gap_ms);
+        let context_history_window = self.context_history_window;
+        let strict_protocol = self.strict_protocol;
+        let sanitizer = self.sanitizer.clone();
+        let normalizer = self.normalizer.clone();
+        let refinement_tasks = self.refinement_tasks.clone();
+        let max_refinement_tasks = self.max_refinement_tasks;
+        let disconnect_policy = self.disconnect_policy;
+        let disconnect_grace = self.disconnect_grace;
+        
+        containment::spawn_session_task(session_id, tx.clone(), metrics.clone(), async move {
+            // Refinement tasks take their own clone of the guard so the slot is held until they finish
+            let session_guard = session_guard;
+            let mut total_contexts = 0;
+            let mut channels: HashMap<u32, ChannelState> = HashMap::new();
+            let mut context_gaps_ms: Vec<u64> = Vec::new();
+            // Delayed version 3 tasks of this session, awaited before it ends
+            let mut refinements: JoinSet<()> = JoinSet::new();
+            // Woken by a client's flush control message to cut the refinement delays short
+            let flush = Arc::new(Notify::new());
+            
+            while let Some(context_result) = in_stream.next().await {
+                match context_result {
+                    Ok(mut context) => {
+                        if context.flush {
+                            metrics.inc("flush_requests_total", &[]);
+                            info!(
+                                session_id = session_id,
+                                outstanding = refinements.len(),
+                                "Client flushed the stream - sending pending refinements now"
+                            );
+                            flush.notify_waiters();
+                            continue;
+                        }
+                        let received = ack_contexts.then(|| (context.query.clone(), context.understanding.clone()));
+                        let mut normalizations = Vec::new();
+                        if let Some(sanitizer) = &sanitizer {
+                            normalizations.extend(sanitizer.sanitize(&mut context));
+                        }
+                        if let Some(normalizer) = &normalizer {
+                            normalizations.extend(normalizer.normalize(&mut context));
+                        }
+                        total_contexts += 1;
+                        let within_limits = session_limits
+                            .check_contexts(total_contexts, &session_guard.limit_warnings)
+                            .and_then(|()| session_limits.check_understanding(&context, &session_guard.limit_warnings));
+                        if let Err(status) = within_limits {
+                            warn!(session_id = session_id, reason = %status.message(), "Session limit exceeded - failing stream");
+                            session_guard.mark_failed(&status);
+                            let _ = tx.send(Err(status)).await;
+                            break;
+                        }
+                        let context_processing_start = Instant::now();
+                        
+                        let channel_id = context.channel_id;
+                        if channel_id != 0 && !channels.contains_key(&channel_id) {
+                            info!(
+                                session_id = session_id,
+                                channel_id = channel_id,
+                                open_channels = channels.len() + 1,
+                                "Opened multiplexed channel"
+                            );
+                            metrics.inc("multiplexed_channels_total", &[]);
+                        }
+                        let new_channel = !channels.contains_key(&channel_id);
+                        let channel = channels.entry(channel_id).or_insert_with(|| ChannelState {
+                            history: ContextHistory::new(context_history_window),
+                            contract: strict_protocol.map(ContractChecker::new),
+                            ..Default::default()
+                        });
+                        if new_channel && resumed {
+                            if let (Some(store), Some(token)) = (&checkpoints, &session_token) {
+                                channel.resumed = store.resume(token, channel_id);
+                                if let Some(checkpoint) = &channel.resumed {
+                                    channel.seed = checkpoint.seed;
+                                }
+                            }
+                        }
+                        channel.context_count += 1;
+                        channel.received += 1;
+                        // A channel that starts fully informed (single-context clients) has
+                        // no uninformed phase: its first Context is answered as the
+                        // refinement, and version 3 is scheduled from it as usual
+                        let informed_start = channel.context_count == 1 && !context.understanding.is_empty();
+                        if informed_start {
+                            channel.context_count = REFINED_VERSION;
+                            metrics.inc("informed_starts_total", &[]);
+                            info!(
+                                session_id = session_id,
+                                channel_id = channel_id,
+                                "Channel starts fully informed - answering with version 2"
+                            );
+                        }
+                        let context_count = channel.context_count;
+                        
+                        // Gap since the previous Context of this logical session
+                        if let Some(previous) = channel.last_context_at.replace(context_processing_start) {
+                            let gap = context_processing_start - previous;
+                            metrics.observe_ms("context_interarrival_ms", &[], gap);
+                            context_gaps_ms.push(gap.as_millis() as u64);
+                            let anomaly = if gap < min_context_gap {
+                                Some("too_fast")
+                            } else if gap > max_context_gap {
+                                Some("too_slow")
+                            } else {
+                                None
+                            };
+                            if let Some(kind) = anomaly {
+                                metrics.inc("context_gap_anomalies_total", &[("kind", kind)]);
+                                warn!(
+                                    session_id = session_id,
+                                    channel_id = channel_id,
+                                    context_number = context_count,
+                                    gap_ms = gap.as_millis() as u64,
+                                    kind = kind,
+                                    "Unusual gap between Context messages"
+                                );
+                            }
+                        }
+                        
+                        if (context_count == 1 || informed_start) && context.seed != 0 {
+                            channel.seed = context.seed;
+                            info!(
+                                session_id = session_id,
+                                channel_id = channel_id,
+                                seed = channel.seed,
+                                "Using client-supplied session seed"
+                            );
+                        }
+                        let session_seed = channel.seed;
+                        if let Some(budget) = LatencyBudget::from_context(&context, context_processing_start) {
+                            channel.budget = Some(budget);
+                        }
+                        let budget = channel.budget;
+                        let transition = channel.history.record(&context);
+                        if transition != Transition::Same {
+                            metrics.inc("context_transitions_total", &[("kind", transition.name())]);
+                        }
+                        let trajectory = channel.history.trajectory();
+                        
+                        info!(
+                            session_id = session_id,
+                            channel_id = channel_id,
+                            context_number = context_count,
+                            query = %context.query,
+                            batched_queries = context.queries.len(),
+                            asin_id = %context.asin_id,
+                            request_type = ?context.request_type(),
+                            understanding_length = context.understanding.len(),
+                            understanding_empty = context.understanding.is_empty(),
+                            session_elapsed_ms = session_start.elapsed().as_millis() as u64,
+                            latency_budget_ms = context.latency_budget_ms,
+                            transition = transition.name(),
+                            trajectory = ?trajectory,
+                            "Received Context message"
+                        );
+                        session_guard.trace_context(&context, context_count, transition);
+                        
+                        if let Some(contract) = &mut channel.contract {
+                            let violations = contract.check(&context, context_processing_start);
+                            if !violations.is_empty() {
+                                fail_contract(&metrics, session_id, channel_id, &violations, &tx, &session_guard).await;
+                                break;
+                            }
+                        }
+                        
+                        if let Some((query, understanding)) = received {
+                            let ack = AckContext {
+                                sequence: channel.received,
+                                normalizations,
+                                query: if context.query != query { context.query.clone() } else { String::new() },
+                                understanding: if context.understanding != understanding {
+                                    context.understanding.clone()
+                                } else {
+                                    String::new()
+                                },
+                            };
+                            debug!(
+                                session_id = session_id,
+                                channel_id = channel_id,
+                                sequence = ack.sequence,
+                                normalizations = ?ack.normalizations,
+                                "Acknowledging Context"
+                            );
+                            metrics.inc("context_acks_total", &[]);
+                            let ack = AdsList { channel_id, control: Some(ads_list::Control::Ack(ack)), ..Default::default() };
+                            if tx.send(Ok(ack)).await.is_err() {
+                                debug!(session_id = session_id, "Client went away before the Context was acknowledged");
+                                break;
+                            }
+                        }
+                        
+                        // A resumed channel skips or resends what its checkpoint already covers
+                        let candidate_hash = checkpoints
+                            .is_some()
+                            .then(|| checkpoint::candidate_hash(&context, session_seed, &catalog.snapshot()));
+                        let replay = channel
+                            .resumed
+                            .as_ref()
+                            .zip(candidate_hash)
+                            .map(|(checkpoint, hash)| checkpoint.replay(context_count, hash));
+                        let mut replayed = None;
+                        match replay {
+                            Some(Replay::Skip) => {
+                                metrics.inc("checkpoint_replays_total", &[("action", "skip")]);
+                                info!(
+                                    session_id = session_id,
+                                    channel_id = channel_id,
+                                    context_number = context_count,
+                                    "Skipping version covered by checkpoint"
+                                );
+                                channel.last_context = Some(context);
+                                // A refinement finished after the client dropped (grace
+                                // policy) is resent instead of being scheduled again
+                                let missed = context_count == REFINED_VERSION
+                                    && channel.resumed.as_ref().is_some_and(|checkpoint| checkpoint.last_version == FINAL_VERSION);
+                                if missed {
+                                    if let Some(checkpoint) = channel.resumed.take() {
+                                        metrics.inc("checkpoint_replays_total", &[("action", "resend")]);
+                                        let no_fill = nofill::is_no_fill(&checkpoint.ads_list);
+                                        if let Ok(true) = watchdog.send(&tx, checkpoint.ads_list).await {
+                                            session_guard.record_sent(FINAL_VERSION, no_fill);
+                                        }
+                                    }
+                                }
+                                continue;
+                            }
+                            Some(Replay::Resend(ads_list)) => {
+                                metrics.inc("checkpoint_replays_total", &[("action", "resend")]);
+                                channel.resumed = None;
+                                replayed = Some(*ads_list);
+                            }
+                            Some(Replay::Generate) => channel.resumed = None,
+                            Some(Replay::Stale) => {
+                                metrics.inc("checkpoint_replays_total", &[("action", "stale")]);
+                                info!(
+                                    session_id = session_id,
+                                    channel_id = channel_id,
+                                    "Candidate set changed since checkpoint - regenerating"
+                                );
+                                channel.resumed = None;
+                                if let (Some(store), Some(token)) = (&checkpoints, &session_token) {
+                                    store.discard(token, channel_id);
+                                }
+                            }
+                            None => {}
+                        }
+                        
+                        // Generate and send AdsList based on context count
+                        let ad_gen_start = Instant::now();
+                        let generated = replayed.is_none();
+                        let ads_list = if let Some(ads_list) = replayed {
+                            ads_list
+                        } else {
+                            let mut ads_list = match coalescer.generate(
+                                &context, &trajectory, context_count, session_seed, catalog.snapshot(), plugin.as_deref(), session_id, feature_log.as_deref(), generator_variant, priority,
+                            ).await {
+                                Ok(ads_list) => ads_list,
+                                Err(status) => {
+                                    session_guard.mark_failed(&status);
+                                    let _ = tx.send(Err(status)).await;
+                                    break;
+                                }
+                            };
+                            ads_list.channel_id = channel_id;
+                            nofill::apply(&mut ads_list, min_score, &metrics);
+                            session_limits.apply_ads_per_list(&mut ads_list, &session_guard.limit_warnings);
+                            normalize_list(&mut ads_list, score_normalization);
+                            if let Err(status) = hooks.apply(&mut ads_list, session_id, context_count).await {
+                                session_guard.mark_failed(&status);
+                                let _ = tx.send(Err(status)).await;
+                                break;
+                            }
+                            if let Some(budget) = &budget {
+                                budget.charge(&metrics, session_id, context_count, "generation", ad_gen_start.elapsed());
+                                budget.annotate(&metrics, session_id, &mut ads_list);
+                            }
+                            ads_list
+                        };
+                        let generation_ms = ad_gen_start.elapsed().as_millis() as u64;
+                        let context_processing_ms = context_processing_start.elapsed().as_millis() as u64;
+                        session_guard.trace_version(&ads_list, generation_ms);
+                        
+                        info!(
+                            session_id = session_id,
+                            channel_id = channel_id,
+                            version = context_count,
+                            ads_count = ads_list.ads.len(),
+                            query_partitions = ads_list.query_results.len(),
+                            placements = ads_list.placement_results.len(),
+                            generation_ms = generation_ms,
+                            context_processing_ms = context_processing_ms,
+                            "Sending AdsList"
+                        );
+                        
+                        // Log debug details about the ads if debug level is enabled
+                        for (i, ad) in ads_list.ads.iter().enumerate() {
+                            debug!(
+                                session_id = session_id,
+                                version = context_count,
+                                ad_index = i,
+                                ad = %ad,
+                                "Generated ad details"
+                            );
+                        }
+                        
+                        if let (Some(store), Some(token), Some(hash)) = (&checkpoints, &session_token, candidate_hash) {
+                            store.save(token, channel_id, session_seed, hash, &ads_list);
+                        }
+                        let no_fill = nofill::is_no_fill(&ads_list);
+                        let candidates = nofill::ad_count(&ads_list);
+                        let sent = watchdog.send(&tx, ads_list).await;
+                        if generated {
+                            session_guard.record_work(context_count, ad_gen_start.elapsed(), candidates, matches!(sent, Ok(true)));
+                        }
+                        match sent {
+                            Ok(sent) => {
+                                if sent {
+                                    session_guard.record_sent(context_count, no_fill);
+                                }
+                            }
+                            Err(_) => {
+                                warn!(
+                                    session_id = session_id,
+                                    context_number = context_count,
+                                    "Failed to send AdsList - receiver dropped"
+                                );
+                                session_guard.mark_cancelled();
+                                break;
+                            }
+                        }
+                        if context_count == 1 || informed_start {
+                            slo.record_first_version(session_start.elapsed());
+                        }
+                        overload.observe(context_processing_start.elapsed());
+                        
+                        channel.last_context = Some(context);
+                        while let Some(joined) = refinements.try_join_next() {
+                            containment::session_task_joined(session_id, joined, &tx, &metrics).await;
+                        }
+                        
+                        // Skip the extra refinement round while overloaded to protect tail latency
+                        if context_count == 2 && overload.sheds(priority) {
+                            metrics.inc("refinements_skipped_total", &[("reason", "overload"), ("priority", priority.name())]);
+                            warn!(
+                                session_id = session_id,
+                                channel_id = channel_id,
+                                "Skipping delayed version 3 AdsList - server overloaded"
+                            );
+                        } else if context_count == 2 && budget.is_some_and(|b| b.remaining() < REFINEMENT_DELAY) {
+                            // The client would give up before the refinement arrives
+                            metrics.inc("refinements_skipped_total", &[("reason", "budget"), ("priority", priority.name())]);
+                            info!(
+                                session_id = session_id,
+                                channel_id = channel_id,
+                                remaining_budget_ms = budget.map(|b| b.remaining().as_millis() as u64),
+                                "Skipping delayed version 3 AdsList - latency budget too small"
+                            );
+                        } else if context_count == 2 && refinements.len() >= max_refinement_tasks {
+                            metrics.inc("refinements_skipped_total", &[("reason", "task_limit"), ("priority", priority.name())]);
+                            warn!(
+                                session_id = session_id,
+                                channel_id = channel_id,
+                                outstanding = refinements.len(),
+                                "Skipping delayed version 3 AdsList - session refinement task limit reached"
+                            );
+                        } else if context_count == 2 {
+                            // If this is the second context, schedule the delayed third response
+                            info!(
+                                session_id = session_id,
+                                channel_id = channel_id,
+                                delay_ms = REFINEMENT_DELAY.as_millis() as u64,
+                                "Scheduling delayed version 3 AdsList"
+                            );
+                            
+                            let tx_clone = tx.clone();
+                            let watchdog = watchdog.clone();
+                            let context_clone = channel.last_context.clone().unwrap();
+                            let session_start_clone = session_start;
+                            let session_guard = session_guard.clone();
+                            let hooks = hooks.clone();
+                            let catalog = catalog.clone();
+                            let plugin = plugin.clone();
+                            let feature_log = feature_log.clone();
+                            let coalescer = coalescer.clone();
+                            let session_limits = session_limits.clone();
+                            let metrics = metrics.clone();
+                            let checkpoints = checkpoints.clone();
+                            let session_token = session_token.clone();
+                            let watched_tx = tx_clone.clone();
+                            let watch_metrics = metrics.clone();
+                            let flush = flush.clone();
+                            let task = RefinementTask::start(refinement_tasks.clone(), metrics.clone());
+                            containment::spawn_tracked_session_task(&mut refinements, async move {
+                                let _task = task;
+                                let refinement = async move {
+                                    let session_guard = session_guard;
+                                    let delay_start = Instant::now();
+                                    tokio::select! {
+                                        _ = sleep(REFINEMENT_DELAY) => {}
+                                        _ = flush.notified() => {}
+                                    }
+                                    if let Some(budget) = &budget {
+                                        budget.charge(&metrics, session_id, 3, "refinement_delay", delay_start.elapsed());
+                                    }
+                                    
+                                    let final_ad_gen_start = Instant::now();
+                                    let mut ads_list = match coalescer.generate(
+                                        &context_clone, &trajectory, FINAL_VERSION, session_seed, catalog.snapshot(), plugin.as_deref(), session_id, feature_log.as_deref(), generator_variant, priority,
+                                    ).await {
+                                        Ok(ads_list) => ads_list,
+                                        Err(status) => {
+                                            session_guard.mark_failed(&status);
+                                            let _ = tx_clone.send(Err(status)).await;
+                                            return;
+                                        }
+                                    };
+                                    ads_list.channel_id = channel_id;
+                                    nofill::apply(&mut ads_list, min_score, &metrics);
+                                    session_limits.apply_ads_per_list(&mut ads_list, &session_guard.limit_warnings);
+                                    normalize_list(&mut ads_list, score_normalization);
+                                    if let Err(status) = hooks.apply(&mut ads_list, session_id, FINAL_VERSION).await {
+                                        session_guard.mark_failed(&status);
+                                        let _ = tx_clone.send(Err(status)).await;
+                                        return;
+                                    }
+                                    let generation_ms = final_ad_gen_start.elapsed().as_millis() as u64;
+                                    if let Some(budget) = &budget {
+                                        budget.charge(&metrics, session_id, 3, "refinement_generation", final_ad_gen_start.elapsed());
+                                        budget.annotate(&metrics, session_id, &mut ads_list);
+                                    }
+                                    session_guard.trace_version(&ads_list, generation_ms);
+                                    
+                                    info!(
+                                        session_id = session_id,
+                                        channel_id = channel_id,
+                                        version = 3,
+                                        ads_count = ads_list.ads.len(),
+                                        query_partitions = ads_list.query_results.len(),
+                                        placements = ads_list.placement_results.len(),
+                                        generation_ms = generation_ms,
+                                        session_elapsed_ms = session_start_clone.elapsed().as_millis() as u64,
+                                        "Sending delayed AdsList"
+                                    );
+                                    
+                                    // Log debug details about the ads if debug level is enabled
+                                    for (i, ad) in ads_list.ads.iter().enumerate() {
+                                        debug!(
+                                            session_id = session_id,
+                                            version = 3,
+                                            ad_index = i,
+                                            ad = %ad,
+                                            "Generated ad details"
+                                        );
+                                    }
+                                    
+                                    if tx_clone.is_closed() {
+                                        // Only reached under the finish and grace policies
+                                        session_guard.record_undelivered(&ads_list);
+                                        if let (Some(store), Some(token), Some(hash)) = (&checkpoints, &session_token, candidate_hash) {
+                                            store.save_undelivered(token, channel_id, session_seed, hash, &ads_list);
+                                        }
+                                    }
+                                    let no_fill = nofill::is_no_fill(&ads_list);
+                                    let candidates = nofill::ad_count(&ads_list);
+                                    let sent = watchdog.send(&tx_clone, ads_list).await;
+                                    session_guard.record_work(FINAL_VERSION, final_ad_gen_start.elapsed(), candidates, matches!(sent, Ok(true)));
+                                    match sent {
+                                        Ok(sent) => {
+                                            if sent {
+                                                session_guard.record_sent(FINAL_VERSION, no_fill);
+                                            }
+                                            info!(
+                                                session_id = session_id,
+                                                channel_id = channel_id,
+                                                total_contexts = context_count,
+                                                total_duration_ms = session_start_clone.elapsed().as_millis() as u64,
+                                                "Stream completed successfully"
+                                            );
+                                        }
+                                        Err(_) => {
+                                            warn!(
+                                                session_id = session_id,
+                                                "Failed to send delayed AdsList - receiver dropped"
+                                            );
+                                            session_guard.mark_cancelled();
+                                        }
+                                    }
+                                    // Close the channel after sending the third response
+                                    drop(tx_clone);
+                                };
+                                disconnect_policy.watch(disconnect_grace, &watched_tx, &watch_metrics, session_id, refinement).await;
+                            });
+                        }
+                    }
+                    Err(e) => {
+                        error!(
+                            session_id = session_id,
+                            contexts_processed = total_contexts,
+                            error = %e,
+                            session_elapsed_ms = session_start.elapsed().as_millis() as u64,
+                            "Error in bidirectional stream"
+                        );
+                        if e.code() == tonic::Code::Cancelled {
+                            session_guard.mark_cancelled();
+                        } else {
+                            session_guard.mark_failed(&e);
+                        }
+                        let _ = tx.send(Err(e)).await;
+                        break;
+                    }
+                }
+            }
+            
+            // The loop also ends when the server fails the stream (test hooks, fault
+            // rules, limits) or the client goes away; only a drained stream was half-closed
+            let elapsed_ms = session_start.elapsed().as_millis() as u64;
+            if let Some(failure) = session_guard.failure.get() {
+                warn!(
+                    session_id = session_id,
+                    contexts_received = total_contexts,
+                    channels = channels.len(),
+                    error = %failure,
+                    session_elapsed_ms = elapsed_ms,
+                    "Stream failed - no further Contexts read"
+                );
+            } else if session_guard.cancelled.load(Ordering::SeqCst) {
+                info!(
+                    session_id = session_id,
+                    contexts_received = total_contexts,
+                    channels = channels.len(),
+                    session_elapsed_ms = elapsed_ms,
+                    "Client went away - no further Contexts read"
+                );
+            } else {
+                info!(
+                    session_id = session_id,
+                    contexts_received = total_contexts,
+                    channels = channels.len(),
+                    context_gaps_ms = ?context_gaps_ms,
+                    session_elapsed_ms = elapsed_ms,
+                    "Client half-closed stream"
+                );
+            }
+            // A failed stream gets no further versions, unless the client is gone and
+            // the disconnect policy keeps its refinements; otherwise the pending
+            // refinements finish before the session does
+            if session_guard.is_failed() && (disconnect_policy == DisconnectPolicy::Cancel || !tx.is_closed()) {
+                refinements.abort_all();
+            } else if !refinements.is_empty() {
+                debug!(session_id = session_id, outstanding = refinements.len(), "Awaiting delayed refinements");
+            }
+            while let Some(joined) = refinements.join_next().await {
+                containment::session_task_joined(session_id, joined, &tx, &metrics).await;
+            }
+            if !session_guard.is_failed() {
+                let mut channel_ids: Vec<u32> = channels.keys().copied().collect();
+                channel_ids.sort();
+                for channel_id in channel_ids {
+                    let Some(contract) = &channels[&channel_id].contract else { continue };
+                    let violations = contract.finish();
+                    if !violations.is_empty() {
+                        fail_contract(&metrics, session_id, channel_id, &violations, &tx, &session_guard).await;
+                        break;
+                    }
+                }
+            }
+            let mut history: Vec<HistorySummary> =
+                channels.iter().filter_map(|(channel_id, channel)| channel.history.summary(*channel_id)).collect();
+            history.sort_by_key(|summary| summary.channel_id);
+            *session_guard.history.lock().unwrap() = history;
+            // Ending the stream with an OK status puts its metadata in the trailers
+            if !session_guard.is_failed() {
+                if let Some(status) = session_guard.limit_warnings.trailer_status() {
+                    debug!(session_id = session_id, "Sending limit warnings in trailers");
+                    let _ = tx.send(Err(status)).await;
+                }
+            }
+        });
+        
+        let mut response = Response::new(out_stream);
+        if let Some(token) = response_token.and_then(|token| token.parse().ok()) {
+            response.metadata_mut().insert(ads_proto::SESSION_TOKEN_METADATA_KEY, token);
+        }
+        Ok(response)
+    }
+
+    type GetAdsServerStreamingStream = Pin<Box<dyn Stream<Item = Result<AdsList, Status>> + Send>>;
+
+    async fn get_ads_server_streaming(
+        &self,
+        request: Request<Context>,
+    ) -> Result<Response<Self::GetAdsServerStreamingStream>, Status> {
+        let priority = PriorityClass::from_metadata(request.metadata(), self.default_priority);
+        let session_guard = self.admit_session(priority)?;
+        
+        let session_id = self.session_counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let session_start = Instant::now();
+        let labels = SessionLabels::from_metadata(request.metadata());
+        let mut metric_labels = self.label_policy.metric_labels(&labels);
+        metric_labels.push(("priority".to_string(), priority.name().to_string()));
+        let metric_label_refs: Vec<(&str, &str)> =
+            metric_labels.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        self.metrics.inc("sessions_started_total", &metric_label_refs);
+        let record = SessionRecord::new(request.metadata(), session_id, labels.clone(), metric_labels, session_start);
+        let request_id = record.request_id.clone();
+        let _ = session_guard.record.set(record);
+        let generator_variant = self.session_variant(session_id, request.metadata());
+        self.work_ledger.open(&request_id, Treatment::new(self.plugin.as_deref(), generator_variant, self.coalescer.ranking()));
+        let debug_session = self.debug_sessions.admit(session_id, request.metadata());
+        let span = span!(Level::INFO, "session", session_id = session_id, labels = %labels, debug_session = debug_session);
+        let runtime = self.config_store.current();
+        let hooks = self
+            .version_hooks(&runtime, request.metadata(), session_id)
+            .inspect_err(|status| session_guard.mark_failed(status))?;
+        let mut context = request.into_inner();
+        if let Some(sanitizer) = &self.sanitizer {
+            sanitizer.sanitize(&mut context);
+        }
+        if let Some(normalizer) = &self.normalizer {
+            normalizer.normalize(&mut context);
+        }
+        let budget = LatencyBudget::from_context(&context, session_start);
+        if let Err(status) = self.session_limits.check_understanding(&context, &session_guard.limit_warnings) {
+            session_guard.mark_failed(&status);
+            return Err(status);
+        }
+        
+        info!(
+            session_id = session_id,
+            query = %context.query,
+            asin_id = %context.asin_id,
+            understanding_length = context.understanding.len(),
+            labels = %labels,
+            "New server-streaming session opened"
+        );
+        
+        let (tx, out_stream) = backpressure::channel(4, self.overflow_policy, self.metrics.clone(), self.signer.clone(), self.faults.clone());
+        let watchdog =
+            OrderWatchdog::new(session_id, self.quality_gate, self.metrics.clone()).with_score_drift(self.score_drift.clone());
+        let metrics = self.metrics.clone();
+        let catalog = self.catalog.clone();
+        let plugin = self.plugin.clone();
+        let feature_log = self.feature_log.clone();
+        let coalescer = self.coalescer.clone();
+        let score_normalization = self.score_normalization;
+        let min_score = self.min_score;
+        let session_limits = self.session_limits.clone();
+        containment::spawn_session_task(session_id, tx.clone(), metrics.clone(), async move {
+            // Holds the session slot until the last version is out
+            let session_guard = session_guard;
+            // Versions 1 and 2 mirror the two Contexts of the bidirectional flow, version 3
+            // follows after the refinement delay
+            let initial = Context {
+                understanding: String::new(),
+                ..context.clone()
+            };
+            for (version, version_context) in [(INITIAL_VERSION, &initial), (REFINED_VERSION, &context), (FINAL_VERSION, &context)] {
+                if version == FINAL_VERSION {
+                    sleep(REFINEMENT_DELAY).await;
+                }
+                let ad_gen_start = Instant::now();
+                let mut ads_list = match coalescer.generate(
+                    version_context, &[], version, context.seed, catalog.snapshot(), plugin.as_deref(), session_id, feature_log.as_deref(), generator_variant, priority,
+                ).await {
+                    Ok(ads_list) => ads_list,
+                    Err(status) => {
+                        session_guard.mark_failed(&status);
+                        let _ = tx.send(Err(status)).await;
+                        return;
+                    }
+                };
+                nofill::apply(&mut ads_list, min_score, &metrics);
+                session_limits.apply_ads_per_list(&mut ads_list, &session_guard.limit_warnings);
+                normalize_list(&mut ads_list, score_normalization);
+                if let Err(status) = hooks.apply(&mut ads_list, session_id, version).await {
+                    session_guard.mark_failed(&status);
+                    let _ = tx.send(Err(status)).await;
+                    return;
+                }
+                if let Some(budget) = &budget {
+                    budget.annotate(&metrics, session_id, &mut ads_list);
+                }
+                info!(
+                    session_id = session_id,
+                    version = version,
+                    ads_count = ads_list.ads.len(),
+                    session_elapsed_ms = session_start.elapsed().as_millis() as u64,
+                    "Sending AdsList"
+                );
+                let no_fill = nofill::is_no_fill(&ads_list);
+                let candidates = nofill::ad_count(&ads_list);
+                let sent = watchdog.send(&tx, ads_list).await;
+                session_guard.record_work(version, ad_gen_start.elapsed(), candidates, matches!(sent, Ok(true)));
+                match sent {
+                    Ok(sent) => {
+                        if sent {
+                            session_guard.record_sent(version, no_fill);
+                        }
+                    }
+                    Err(_) => {
+                        warn!(session_id = session_id, version = version, "Failed to send AdsList - receiver dropped");
+                        session_guard.mark_cancelled();
+                        return;
+                    }
+                }
+            }
+            if let Some(status) = session_guard.limit_warnings.trailer_status() {
+                let _ = tx.send(Err(status)).await;
+            }
+        }.instrument(span));
+        
+        Ok(Response::new(Box::pin(out_stream) as Self::GetAdsServerStreamingStream))
+    }
+
+    async fn get_ads_unary(&self, request: Request<Context>) -> Result<Response<AdsList>, Status> {
+        let priority = PriorityClass::from_metadata(request.metadata(), self.default_priority);
+        // Held until the response is built, so a unary call takes a session slot like a stream
+        let session_guard = self.admit_session(priority)?;
+        
+        let session_id = self.session_counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let session_start = Instant::now();
+        let labels = SessionLabels::from_metadata(request.metadata());
+        let mut metric_labels = self.label_policy.metric_labels(&labels);
+        metric_labels.push(("priority".to_string(), priority.name().to_string()));
+        let metric_label_refs: Vec<(&str, &str)> =
+            metric_labels.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        self.metrics.inc("sessions_started_total", &metric_label_refs);
+        let record = SessionRecord::new(request.metadata(), session_id, labels.clone(), metric_labels, session_start);
+        let request_id = record.request_id.clone();
+        let _ = session_guard.record.set(record);
+        let generator_variant = self.session_variant(session_id, request.metadata());
+        self.work_ledger.open(&request_id, Treatment::new(self.plugin.as_deref(), generator_variant, self.coalescer.ranking()));
+        let debug_session = self.debug_sessions.admit(session_id, request.metadata());
+        let span = span!(Level::INFO, "session", session_id = session_id, labels = %labels, debug_session = debug_session);
+        let runtime = self.config_store.current();
+        let hooks = self
+            .version_hooks(&runtime, request.metadata(), session_id)
+            .inspect_err(|status| session_guard.mark_failed(status))?;
+        let mut context = request.into_inner();
+        if let Some(sanitizer) = &self.sanitizer {
+            sanitizer.sanitize(&mut context);
+        }
+        if let Some(normalizer) = &self.normalizer {
+            normalizer.normalize(&mut context);
+        }
+        let budget = LatencyBudget::from_context(&context, session_start);
+        if let Err(status) = self.session_limits.check_understanding(&context, &session_guard.limit_warnings) {
+            session_guard.mark_failed(&status);
+            return Err(status);
+        }
+        
+        let mut ads_list = self.coalescer.generate(
+            &context, &[], FINAL_VERSION, context.seed, self.catalog.snapshot(), self.plugin.as_deref(), session_id, self.feature_log.as_deref(), generator_variant, priority,
+        ).instrument(span).await.inspect_err(|status| session_guard.mark_failed(status))?;
+        nofill::apply(&mut ads_list, self.min_score, &self.metrics);
+        self.session_limits.apply_ads_per_list(&mut ads_list, &session_guard.limit_warnings);
+        normalize_list(&mut ads_list, self.score_normalization);
+        hooks
+            .apply(&mut ads_list, session_id, FINAL_VERSION)
+            .await
+            .inspect_err(|status| session_guard.mark_failed(status))?;
+        if let Some(budget) = &budget {
+            budget.charge(&self.metrics, session_id, 3, "generation", session_start.elapsed());
+            budget.annotate(&self.metrics, session_id, &mut ads_list);
+        }
+        // Unary responses skip the output channel, so guard, stamp and sign them here
+        containment::guard_scores(&mut ads_list, "send", &self.metrics);
+        ads_list.server_sent_unix_us = ads_proto::unix_us();
+        if let Some(signer) = &self.signer {
+            signer.sign(&mut ads_list);
+        }
+        self.faults.tamper(&mut ads_list);
+        if let Some(score_drift) = &self.score_drift {
+            score_drift.record(&ads_list);
+        }
+        info!(
+            session_id = session_id,
+            query = %context.query,
+            asin_id = %context.asin_id,
+            ads_count = ads_list.ads.len(),
+            labels = %labels,
+            generation_ms = session_start.elapsed().as_millis() as u64,
+            "Sending unary AdsList"
+        );
+        session_guard.record_work(FINAL_VERSION, session_start.elapsed(), nofill::ad_count(&ads_list), true);
+        session_guard.record_sent(FINAL_VERSION, nofill::is_no_fill(&ads_list));
+        let mut response = Response::new(ads_list);
+        if let Some(warnings) = session_guard.limit_warnings.metadata_value() {
+            response.metadata_mut().insert(ads_proto::LIMIT_WARNINGS_METADATA_KEY, warnings);
+        }
+        Ok(response)
+    }
+
+    async fn handshake(&self, request: Request<HandshakeRequest>) -> Result<Response<HandshakeResponse>, Status> {
+        let server_receive_unix_us = ads_proto::unix_us();
+        Ok(Response::new(HandshakeResponse {
+            client_send_unix_us: request.into_inner().client_send_unix_us,
+            server_receive_unix_us,
+            server_send_unix_us: ads_proto::unix_us(),
+        }))
+    }
+
+    async fn report_feedback(&self, request: Request<SessionFeedback>) -> Result<Response<SessionFeedbackResponse>, Status> {
+        let feedback = request.into_inner();
+        let treatment = self.work_ledger.feedback(&feedback.request_id, feedback.selected_version);
+        let matched = treatment.is_some();
+        self.metrics.inc("session_feedback_total", &[("matched", if matched { "true" } else { "false" })]);
+        if let Some(treatment) = treatment {
+            treatment.record_feedback(&feedback, &self.metrics);
+        }
+        debug!(
+            request_id = %feedback.request_id,
+            selected_version = feedback.selected_version,
+            matched = matched,
+            "Session feedback"
+        );
+        Ok(Response::new(SessionFeedbackResponse { matched }))
+    }
+}
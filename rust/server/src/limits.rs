@@ -0,0 +1,101 @@
+use ads_common::{Error, Result};
+use tracing::{info, warn};
+
+/// File descriptors kept in reserve for listeners, logs and the runtime itself
+const FD_HEADROOM: u64 = 64;
+
+/// Largest listen backlog worth asking the kernel for
+const MAX_USEFUL_BACKLOG: u64 = 4096;
+
+/// Inspect process and kernel limits that bound how many concurrent sessions the
+/// server can actually hold. Each session may arrive on its own TCP connection, so
+/// the fd soft limit must cover `max_sessions` plus headroom, and the accept backlog
+/// must absorb connection bursts from load generators.
+///
+/// Problems are logged as warnings; with `strict` they are returned as an error
+/// instead. With `raise_fd_limit` the soft fd limit is raised (up to the hard limit)
+/// before checking.
+pub fn check_startup_limits(max_sessions: u64, strict: bool, raise_fd_limit: bool) -> Result<()> {
+    let mut problems = Vec::new();
+    let needed_fds = max_sessions + FD_HEADROOM;
+
+    match fd_limits() {
+        Some((mut soft, hard)) => {
+            if soft < needed_fds && raise_fd_limit {
+                let target = needed_fds.min(hard);
+                if set_fd_soft_limit(target, hard) {
+                    info!(old_soft = soft, new_soft = target, hard = hard, "Raised file descriptor soft limit");
+                    soft = target;
+                } else {
+                    warn!(target = target, "Failed to raise file descriptor soft limit");
+                }
+            }
+            if soft < needed_fds {
+                problems.push(format!(
+                    "file descriptor limit {} is below the {} needed for {} concurrent sessions (hard limit {}; try `ulimit -n {}` or --raise-fd-limit)",
+                    soft, needed_fds, max_sessions, hard, needed_fds.min(hard)
+                ));
+            } else {
+                info!(soft = soft, hard = hard, needed = needed_fds, "File descriptor limit OK");
+            }
+        }
+        None => warn!("Unable to read file descriptor limits on this platform"),
+    }
+
+    if let Some(somaxconn) = read_somaxconn() {
+        let wanted = max_sessions.min(MAX_USEFUL_BACKLOG);
+        if somaxconn < wanted {
+            problems.push(format!(
+                "net.core.somaxconn is {} but bursts of {} connections are expected (try `sysctl -w net.core.somaxconn={}`)",
+                somaxconn, wanted, wanted
+            ));
+        } else {
+            info!(somaxconn = somaxconn, "Socket backlog limit OK");
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+    if strict {
+        return Err(Error::config(problems.join("; ")));
+    }
+    for problem in &problems {
+        warn!("Startup limit check: {}", problem);
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn fd_limits() -> Option<(u64, u64)> {
+    let mut rlim = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: getrlimit only writes into the provided struct
+    let rc = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlim) };
+    (rc == 0).then_some((rlim.rlim_cur, rlim.rlim_max))
+}
+
+#[cfg(not(unix))]
+fn fd_limits() -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(unix)]
+fn set_fd_soft_limit(soft: u64, hard: u64) -> bool {
+    let rlim = libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    // SAFETY: setrlimit only reads the provided struct
+    unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &rlim) == 0 }
+}
+
+#[cfg(not(unix))]
+fn set_fd_soft_limit(_soft: u64, _hard: u64) -> bool {
+    false
+}
+
+fn read_somaxconn() -> Option<u64> {
+    std::fs::read_to_string("/proc/sys/net/core/somaxconn")
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+}
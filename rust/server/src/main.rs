@@ -1,275 +1,354 @@
-use std::pin::Pin;
-use std::sync::atomic::{AtomicU64, Ordering};
+// tonic::Status is large, and Result<_, Status> (or an error wrapping it) is what
+// every handler and client call returns; boxing it at each call site buys nothing
+#![allow(clippy::result_large_err)]
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
+use clap::Parser;
 use tokio::time::sleep;
-use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
-use tonic::{transport::Server, Request, Response, Status, Streaming};
-use tracing::{info, warn, debug, error, span, Level};
+use tonic::codec::CompressionEncoding;
+use tonic::transport::{Identity, Server, ServerTlsConfig};
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+use ads_common::outcome::SessionOutcome;
+use ads_common::report::{ShutdownReport, WorkReport, WorkSplit};
+use ads_proto::admin::admin_service_server::AdminServiceServer;
+use ads_server::ads::ads_service_server::AdsServiceServer;
+use ads_server::admin::AdminServiceImpl;
+use ads_server::bundle::{DebugBundle, DebugBundles};
+use ads_server::catalog::Catalog;
+use ads_server::checkpoint::CheckpointStore;
+use ads_server::config::{Cli, Command, DebugBundleCommand, JournalCommand, MetricsFormat, ServerConfig};
+use ads_server::connections::{ConnectionLimits, ConnectionTracker};
+use ads_server::debugsession::DEBUG_SESSION_DIRECTIVE;
+use ads_server::drift::{DriftConfig, ScoreDriftMonitor};
+use ads_server::faults::FaultInjector;
+use ads_server::features::FeatureLog;
+use ads_server::journal::{self, SessionJournal};
+use ads_server::maintenance::Maintenance;
+use ads_server::metrics::{Metrics, MetricsSnapshot};
+use ads_server::plugin::GeneratorPlugin;
+use ads_server::runtime_config::{self, ConfigStore, RuntimeConfig};
+use ads_server::signing::ResponseSigner;
+use ads_server::slo::{SloConfig, SloTracker};
+use ads_server::{
+    announce, batch, catalog_load, dictionary, dryrun, limits, shutdown, stub, topk, transport_bench, AdsServiceImpl,
+};
 
-// Include the generated protobuf code
-pub mod ads {
-    tonic::include_proto!("ads");
+/// TLS settings from --tls-cert and --tls-key; None serves plaintext
+fn tls_config(config: &ServerConfig) -> ads_common::Result<Option<ServerTlsConfig>> {
+    let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) else { return Ok(None) };
+    let identity = Identity::from_pem(std::fs::read(cert)?, std::fs::read(key)?);
+    info!(cert = %cert.display(), key = %key.display(), "Serving over TLS");
+    Ok(Some(ServerTlsConfig::new().identity(identity)))
 }
 
-use ads::{ads_service_server::{AdsService, AdsServiceServer}, Ad, AdsList, Context};
+/// Final report of a server run from its metrics. Latency percentiles are the
+/// upper bounds of the session_duration_ms buckets they fall in.
+fn shutdown_report(snapshot: &MetricsSnapshot, uptime: Duration, exit: &str) -> ShutdownReport {
+    let mut report = ShutdownReport::new("ads-server", exit, uptime);
+    report.sessions = snapshot.counter_total("sessions_started_total");
+    if let Some(failed) = snapshot.counter_by_label("sessions_finished_total", "outcome").remove("failed") {
+        report.errors.insert("failed".to_string(), failed);
+    }
+    for (reason, count) in snapshot.counter_by_label("sessions_rejected_total", "reason") {
+        report.errors.insert(format!("rejected_{}", reason), count);
+    }
+    for (name, count) in snapshot.counter_by_label("session_outcomes_total", "outcome") {
+        if let Some(outcome) = SessionOutcome::from_name(&name) {
+            report.outcomes.add(outcome, count);
+        }
+    }
+    let durations = snapshot.histogram_total("session_duration_ms");
+    if durations.count > 0 {
+        report.latency_ms = ads_common::report::REPORT_QUANTILES.iter().map(|q| (*q, durations.quantile(*q))).collect();
+    }
+    let work_us = snapshot.counter_by_label("generation_work_us_total", "use");
+    if !work_us.is_empty() {
+        let candidates = snapshot.counter_by_label("generation_candidates_total", "use");
+        let split = |counts: &BTreeMap<String, u64>| WorkSplit {
+            used: counts.get("used").copied().unwrap_or(0),
+            wasted: counts.get("wasted").copied().unwrap_or(0),
+            unreported: counts.get("unreported").copied().unwrap_or(0),
+        };
+        report.work = Some(WorkReport { generation_us: split(&work_us), candidates: split(&candidates) });
+    }
+    report
+}
 
-#[derive(Debug, Default)]
-pub struct AdsServiceImpl {
-    session_counter: AtomicU64,
+/// Human-readable logs by default; ADS_LOG_FORMAT=json emits one JSON object per line
+/// (the input format of the logsum tool)
+fn init_logging() {
+    // RUST_LOG (INFO by default), plus DEBUG inside sessions granted x-debug-session
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("info"))
+        .add_directive(DEBUG_SESSION_DIRECTIVE.parse().expect("valid debug session directive"));
+    if std::env::var("ADS_LOG_FORMAT").as_deref() == Ok("json") {
+        tracing_subscriber::fmt().json().with_env_filter(filter).init();
+    } else {
+        tracing_subscriber::fmt().with_env_filter(filter).init();
+    }
 }
 
-#[tonic::async_trait]
-impl AdsService for AdsServiceImpl {
-    type GetAdsStream = Pin<Box<dyn Stream<Item = Result<AdsList, Status>> + Send>>;
+fn run_journal_command(command: JournalCommand) -> std::io::Result<()> {
+    match command {
+        JournalCommand::Export { journal: journal_path, captures, out } => {
+            let header = journal::export_archive(&journal_path, captures.as_deref(), &out)?;
+            println!(
+                "Exported {} sessions and {} captures to {} (schema v{})",
+                header.sessions, header.captures, out.display(), header.schema_version
+            );
+        }
+        JournalCommand::Import { archive, journal: journal_path, captures } => {
+            let archive = journal::read_archive(&archive)?;
+            let (sessions, written_captures) = journal::import_archive(&archive, &journal_path, captures.as_deref())?;
+            println!("Imported {} sessions and {} captures", sessions, written_captures);
+            if captures.is_none() && !archive.captures.is_empty() {
+                println!("Skipped {} captures (no --captures path)", archive.captures.len());
+            }
+        }
+        JournalCommand::Inspect { archive } => {
+            print!("{}", journal::read_archive(&archive)?.render());
+        }
+    }
+    Ok(())
+}
 
-    async fn get_ads(
-        &self,
-        request: Request<Streaming<Context>>,
-    ) -> Result<Response<Self::GetAdsStream>, Status> {
-        let session_id = self.session_counter.fetch_add(1, Ordering::SeqCst) + 1;
-        let session_start = Instant::now();
-        
-        let span = span!(Level::INFO, "session", session_id = session_id);
-        let _enter = span.enter();
-        
+#[tokio::main]
+async fn main() -> ads_common::Result<()> {
+    // Initialize tracing
+    init_logging();
+    
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::MetricsDiff { before, after }) => {
+            let before = MetricsSnapshot::read_json(&before)?;
+            let after = MetricsSnapshot::read_json(&after)?;
+            let changes = before.diff(&after);
+            if changes.is_empty() {
+                println!("No metric changes");
+            }
+            for change in changes {
+                println!("{}", change);
+            }
+            return Ok(());
+        }
+        Some(Command::Journal(command)) => {
+            run_journal_command(command)?;
+            return Ok(());
+        }
+        Some(Command::DebugBundle(DebugBundleCommand::Inspect { bundle })) => {
+            print!("{}", DebugBundle::load(&bundle)?.render());
+            return Ok(());
+        }
+        Some(Command::ScoreBatch(args)) => {
+            batch::run(&args)?;
+            return Ok(());
+        }
+        Some(Command::BenchRanking(args)) => {
+            topk::run(&args)?;
+            return Ok(());
+        }
+        Some(Command::BenchTransport(args)) => {
+            transport_bench::run(&args).await?;
+            return Ok(());
+        }
+        Some(Command::TrainDictionary(args)) => {
+            dictionary::train(&args)?;
+            return Ok(());
+        }
+        None => {}
+    }
+    
+    let started = Instant::now();
+    let config = cli.config;
+    if config.dry_run {
+        dryrun::run(&config)?;
+        return Ok(());
+    }
+    limits::check_startup_limits(config.max_concurrent_sessions, config.strict_limits, config.raise_fd_limit)?;
+    let addr: std::net::SocketAddr = format!("127.0.0.1:{}", config.port).parse()?;
+    let tls = tls_config(&config)?;
+    if let Some(path) = &config.stub {
+        return stub::serve(path, addr, config.port_file.as_deref(), tls).await;
+    }
+    let metrics = Arc::new(Metrics::default());
+    let config_store = Arc::new(ConfigStore::new(
+        RuntimeConfig::from_server_config(&config),
+        config.config_audit_size,
+    ));
+    if let Some(path) = &config.config_file {
+        config_store.reload_from_file(path)?;
+        runtime_config::spawn_file_watcher(config_store.clone(), path.clone(), Duration::from_secs(2));
+    }
+    let faults = Arc::new(FaultInjector::new(metrics.clone()));
+    let catalog = Arc::new(Catalog::default());
+    if let Some(path) = &config.catalog_file {
+        catalog.load(catalog_load::load(
+            path,
+            config.catalog_load_threads,
+            config.catalog_index_cache.as_deref(),
+            &metrics,
+        )?);
+    }
+    let plugin = match &config.generator_plugin {
+        Some(path) => Some(Arc::new(GeneratorPlugin::load(path)?)),
+        None => None,
+    };
+    let slo = Arc::new(SloTracker::new(SloConfig::from_server_config(&config), metrics.clone()));
+    let maintenance = Arc::new(Maintenance::default());
+    if config.slo_eval_interval_secs > 0 {
+        slo.clone().spawn_evaluator(Duration::from_secs(config.slo_eval_interval_secs));
+    }
+    let mut ads_service = AdsServiceImpl::new(
+        &config,
+        metrics.clone(),
+        config_store.clone(),
+        faults.clone(),
+        catalog.clone(),
+        plugin,
+        slo,
+        maintenance.clone(),
+    );
+    if let Some(path) = &config.session_journal {
+        ads_service = ads_service.with_journal(SessionJournal::open(path)?);
+        info!(path = %path.display(), "Recording sessions to journal");
+    }
+    if let Some(dir) = &config.debug_bundle_dir {
+        ads_service = ads_service.with_debug_bundles(DebugBundles::open(dir)?);
+        info!(dir = %dir.display(), "Writing debug bundles for failed sessions");
+    }
+    if config.score_drift {
+        let score_drift = Arc::new(ScoreDriftMonitor::new(DriftConfig::from_server_config(&config), metrics.clone()));
+        score_drift.clone().spawn_evaluator(Duration::from_secs(config.score_drift_eval_interval_secs));
+        ads_service = ads_service.with_score_drift(score_drift);
+        info!(window = config.score_drift_window, "Tracking score drift per version");
+    }
+    if let Some(path) = &config.signing_key {
+        let signer = ResponseSigner::load(path)?;
+        info!(public_key = %signer.public_key_hex(), "Signing AdsLists");
+        ads_service = ads_service.with_signer(signer);
+    }
+    if let Some(path) = &config.zstd_dictionary {
+        ads_proto::codec::install_dictionary(&std::fs::read(path)?, ads_proto::codec::DictionaryMode::Respond)?;
+        info!(path = %path.display(), "Loaded zstd dictionary");
+    }
+    if let Some(path) = &config.feature_log {
+        if config.generator_plugin.is_some() {
+            warn!("Feature logging covers the built-in generator only - plugin sessions are not logged");
+        }
+        ads_service = ads_service.with_feature_log(FeatureLog::open(
+            path,
+            config.feature_log_format,
+            config.feature_log_sample_rate,
+        )?);
         info!(
-            session_id = session_id,
-            thread = ?std::thread::current().id(),
-            "New bidirectional stream opened"
+            path = %path.display(),
+            sample_rate = config.feature_log_sample_rate,
+            "Recording scoring features"
         );
-        
-        let mut in_stream = request.into_inner();
-        let (tx, rx) = tokio::sync::mpsc::channel(128);
-        
+    }
+    if config.session_checkpoints {
+        ads_service = ads_service.with_checkpoints(CheckpointStore::new(
+            Duration::from_millis(config.checkpoint_interval_ms),
+            Duration::from_secs(config.checkpoint_max_age_secs),
+            metrics.clone(),
+        ));
+        info!(max_age_secs = config.checkpoint_max_age_secs, "Checkpointing bidirectional sessions");
+    }
+    let work_ledger = ads_service.work_ledger();
+    let admin_service =
+        AdminServiceImpl::new(config_store, faults, catalog, maintenance, ads_service.active_sessions());
+    
+    // Bind up front so port 0 resolves to a real port that can be announced
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+    announce::announce_port(local_addr, config.port_file.as_deref())?;
+    info!("Starting Rust Ads server on {}", local_addr);
+    let connection_limits = ConnectionLimits::from_server_config(&config);
+    if connection_limits.max_per_peer > 0 || connection_limits.idle_timeout.is_some() {
+        info!(
+            max_per_peer = connection_limits.max_per_peer,
+            idle_timeout_secs = config.idle_connection_timeout_secs,
+            "Limiting connections"
+        );
+    }
+    let connections = ConnectionTracker::new(connection_limits, metrics.clone());
+    
+    if config.metrics_interval_secs > 0 {
+        let interval = Duration::from_secs(config.metrics_interval_secs);
+        let metrics = metrics.clone();
         tokio::spawn(async move {
-            let mut context_count = 0;
-            let mut last_context: Option<Context> = None;
-            
-            while let Some(context_result) = in_stream.next().await {
-                match context_result {
-                    Ok(context) => {
-                        context_count += 1;
-                        let context_processing_start = Instant::now();
-                        
-                        info!(
-                            session_id = session_id,
-                            context_number = context_count,
-                            query = %context.query,
-                            asin_id = %context.asin_id,
-                            understanding_length = context.understanding.len(),
-                            understanding_empty = context.understanding.is_empty(),
-                            session_elapsed_ms = session_start.elapsed().as_millis() as u64,
-                            "Received Context message"
-                        );
-                        
-                        // Generate and send AdsList based on context count
-                        let ad_gen_start = Instant::now();
-                        let ads_list = generate_ads(&context, context_count);
-                        let generation_ms = ad_gen_start.elapsed().as_millis() as u64;
-                        let context_processing_ms = context_processing_start.elapsed().as_millis() as u64;
-                        
-                        info!(
-                            session_id = session_id,
-                            version = context_count,
-                            ads_count = ads_list.ads.len(),
-                            generation_ms = generation_ms,
-                            context_processing_ms = context_processing_ms,
-                            "Sending AdsList"
-                        );
-                        
-                        // Log debug details about the ads if debug level is enabled
-                        for (i, ad) in ads_list.ads.iter().enumerate() {
-                            debug!(
-                                session_id = session_id,
-                                version = context_count,
-                                ad_index = i,
-                                asin_id = %ad.asin_id,
-                                ad_id = %ad.ad_id,
-                                score = format!("{:.3}", ad.score),
-                                "Generated ad details"
-                            );
-                        }
-                        
-                        if let Err(_) = tx.send(Ok(ads_list)).await {
-                            warn!(
-                                session_id = session_id,
-                                context_number = context_count,
-                                "Failed to send AdsList - receiver dropped"
-                            );
-                            break;
-                        }
-                        
-                        last_context = Some(context);
-                        
-                        // If this is the second context, schedule the delayed third response
-                        if context_count == 2 {
-                            info!(
-                                session_id = session_id,
-                                delay_ms = 50,
-                                "Scheduling delayed version 3 AdsList"
-                            );
-                            
-                            let tx_clone = tx.clone();
-                            let context_clone = last_context.clone().unwrap();
-                            let session_start_clone = session_start;
-                            tokio::spawn(async move {
-                                sleep(Duration::from_millis(50)).await;
-                                
-                                let final_ad_gen_start = Instant::now();
-                                let ads_list = generate_ads(&context_clone, 3);
-                                let generation_ms = final_ad_gen_start.elapsed().as_millis() as u64;
-                                
-                                info!(
-                                    session_id = session_id,
-                                    version = 3,
-                                    ads_count = ads_list.ads.len(),
-                                    generation_ms = generation_ms,
-                                    session_elapsed_ms = session_start_clone.elapsed().as_millis() as u64,
-                                    "Sending delayed AdsList"
-                                );
-                                
-                                // Log debug details about the ads if debug level is enabled
-                                for (i, ad) in ads_list.ads.iter().enumerate() {
-                                    debug!(
-                                        session_id = session_id,
-                                        version = 3,
-                                        ad_index = i,
-                                        asin_id = %ad.asin_id,
-                                        ad_id = %ad.ad_id,
-                                        score = format!("{:.3}", ad.score),
-                                        "Generated ad details"
-                                    );
-                                }
-                                
-                                if let Err(_) = tx_clone.send(Ok(ads_list)).await {
-                                    warn!(
-                                        session_id = session_id,
-                                        "Failed to send delayed AdsList - receiver dropped"
-                                    );
-                                } else {
-                                    info!(
-                                        session_id = session_id,
-                                        total_contexts = context_count,
-                                        total_duration_ms = session_start_clone.elapsed().as_millis() as u64,
-                                        "Stream completed successfully"
-                                    );
-                                }
-                                // Close the channel after sending the third response
-                                drop(tx_clone);
-                            });
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            session_id = session_id,
-                            contexts_processed = context_count,
-                            error = %e,
-                            session_elapsed_ms = session_start.elapsed().as_millis() as u64,
-                            "Error in bidirectional stream"
-                        );
-                        let _ = tx.send(Err(e)).await;
-                        break;
-                    }
-                }
+            loop {
+                sleep(interval).await;
+                metrics.log_snapshot();
             }
-            
-            info!(
-                session_id = session_id,
-                contexts_received = context_count,
-                session_elapsed_ms = session_start.elapsed().as_millis() as u64,
-                "Client half-closed stream"
-            );
         });
-        
-        let out_stream = ReceiverStream::new(rx);
-        Ok(Response::new(Box::pin(out_stream) as Self::GetAdsStream))
     }
-}
-
-// Mock ad generation with Context-based scoring and progressive refinement
-fn generate_ads(context: &Context, version: u32) -> AdsList {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-    use rand::{Rng, SeedableRng};
-    use rand::rngs::StdRng;
-    
-    // Create a deterministic seed based on context for reproducible results
-    let mut hasher = DefaultHasher::new();
-    context.query.hash(&mut hasher);
-    context.asin_id.hash(&mut hasher);
-    let seed = hasher.finish();
-    let mut rng = StdRng::seed_from_u64(seed);
     
-    // Generate 5-10 mock ads as per requirement 2.5
-    let num_ads = rng.gen_range(5..=10);
-    let mut ads = Vec::with_capacity(num_ads);
+    // Reflection lets schema-agnostic clients (ads-client --dynamic) discover the services
+    let reflection_service = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(ads_proto::FILE_DESCRIPTOR_SET)
+        .build()
+        .map_err(|e| ads_common::Error::config(format!("reflection service: {}", e)))?;
     
-    for i in 0..num_ads {
-        // Base score calculation using hash of query + asin_id
-        let mut ad_hasher = DefaultHasher::new();
-        context.query.hash(&mut ad_hasher);
-        context.asin_id.hash(&mut ad_hasher);
-        i.hash(&mut ad_hasher); // Add index for variation
-        let base_hash = ad_hasher.finish();
-        let mut base_score = (base_hash % 1000) as f64 / 1000.0; // 0.0 to 1.0
-        
-        // Understanding boost - additional scoring when understanding is provided (requirement 2.6)
-        if !context.understanding.is_empty() {
-            let mut understanding_hasher = DefaultHasher::new();
-            context.understanding.hash(&mut understanding_hasher);
-            let understanding_boost = (understanding_hasher.finish() % 200) as f64 / 1000.0; // 0.0 to 0.2 boost
-            base_score += understanding_boost;
-        }
-        
-        // Version refinement - progressive improvement across versions
-        let version_multiplier = match version {
-            1 => 0.7, // Initial results are less refined
-            2 => 0.9, // Better results with complete context
-            3 => 1.1, // Best results after processing delay
-            _ => 1.0,
-        };
-        base_score *= version_multiplier;
-        
-        // Add controlled randomness for realistic variation
-        let randomness = rng.gen_range(-0.1..=0.1);
-        base_score += randomness;
-        
-        // Clamp score to valid range [0.0, 1.0]
-        base_score = base_score.max(0.0).min(1.0);
-        
-        // Generate realistic ad_id
-        let ad_id = format!("ad_{}_{}_v{}", context.asin_id, i + 1, version);
-        
-        ads.push(Ad {
-            asin_id: context.asin_id.clone(),
-            ad_id,
-            score: base_score,
-        });
+    let mut server = Server::builder();
+    if let Some(tls) = tls {
+        server = server.tls_config(tls)?;
     }
     
-    // Sort ads by score in descending order for better user experience
-    ads.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    // HTTP/1.1 + grpc-web lets browser clients (ads-client `web` feature) reach the
+    // server-streaming RPC directly
+    let served = server
+        .accept_http1(true)
+        .add_service(tonic_web::enable(
+            AdsServiceServer::new(ads_service)
+                .accept_compressed(CompressionEncoding::Gzip)
+                .send_compressed(CompressionEncoding::Gzip),
+        ))
+        .add_service(AdminServiceServer::new(admin_service))
+        .add_service(reflection_service)
+        .serve_with_incoming_shutdown(connections.incoming(listener), shutdown::signal())
+        .await;
     
-    AdsList {
-        ads,
-        version,
+    if let Some(path) = &config.port_file {
+        let _ = std::fs::remove_file(path);
     }
-}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing
-    tracing_subscriber::fmt::init();
     
-    let addr = "127.0.0.1:50051".parse()?;
-    let ads_service = AdsServiceImpl::default();
+    work_ledger.settle_pending();
+    let report = shutdown_report(&metrics.snapshot(), started.elapsed(), if served.is_ok() { "signal" } else { "error" });
+    info!(report = %report.to_json(), "Shutdown report");
+    if let Some(path) = &config.shutdown_report {
+        match report.write(path) {
+            Ok(()) => info!(path = %path.display(), "Wrote shutdown report"),
+            Err(e) => warn!(path = %path.display(), error = %e, "Could not write shutdown report"),
+        }
+    }
     
-    info!("Starting Rust Ads server on {}", addr);
+    if config.zstd_dictionary.is_some() {
+        let wire = ads_proto::codec::wire_stats();
+        metrics.set_gauge("zstd_dictionary_raw_bytes", &[], wire.raw_bytes as i64);
+        metrics.set_gauge("zstd_dictionary_wire_bytes", &[], wire.wire_bytes as i64);
+        info!(
+            messages = wire.messages,
+            raw_bytes = wire.raw_bytes,
+            wire_bytes = wire.wire_bytes,
+            ratio = %format!("{:.3}", wire.ratio()),
+            "zstd dictionary wire size"
+        );
+    }
     
-    Server::builder()
-        .add_service(AdsServiceServer::new(ads_service))
-        .serve(addr)
-        .await?;
+    if let Some(path) = &config.metrics_dump {
+        match config.metrics_dump_format {
+            MetricsFormat::Json => metrics.snapshot().write_json(path)?,
+            MetricsFormat::Openmetrics => std::fs::write(path, metrics.snapshot().to_openmetrics())?,
+        }
+        info!(path = %path.display(), "Wrote metrics snapshot");
+    }
     
+    // A failed serve still leaves its reports and metrics behind
+    served?;
     Ok(())
 }
@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Status};
+use tracing::{info, warn};
+
+use ads_proto::REDIRECT_METADATA_KEY;
+
+#[derive(Debug, Default)]
+struct State {
+    enabled_at: Option<Instant>,
+    redirect_endpoint: String,
+}
+
+/// Maintenance toggle set through the admin service. While enabled, new ads
+/// sessions are refused with UNAVAILABLE (plus the redirect endpoint, if any, as
+/// metadata) and sessions already running are left to finish.
+#[derive(Debug, Default)]
+pub struct Maintenance {
+    state: RwLock<State>,
+    refused: AtomicU64,
+}
+
+impl Maintenance {
+    pub fn set(&self, enabled: bool, redirect_endpoint: String) {
+        let mut state = self.state.write().unwrap();
+        if enabled {
+            if state.enabled_at.is_none() {
+                state.enabled_at = Some(Instant::now());
+                self.refused.store(0, Ordering::SeqCst);
+            }
+            info!(redirect_endpoint = %redirect_endpoint, "Maintenance mode enabled - refusing new sessions");
+        } else if state.enabled_at.take().is_some() {
+            info!(refused_sessions = self.refused.load(Ordering::SeqCst), "Maintenance mode disabled");
+        }
+        state.redirect_endpoint = if enabled { redirect_endpoint } else { String::new() };
+    }
+
+    /// (enabled for, redirect endpoint, sessions refused); None when not in maintenance
+    pub fn status(&self) -> Option<(Duration, String, u64)> {
+        let state = self.state.read().unwrap();
+        state
+            .enabled_at
+            .map(|at| (at.elapsed(), state.redirect_endpoint.clone(), self.refused.load(Ordering::SeqCst)))
+    }
+
+    /// The status to refuse a new session with, if maintenance is enabled
+    pub fn refusal(&self) -> Option<Status> {
+        let state = self.state.read().unwrap();
+        state.enabled_at?;
+        self.refused.fetch_add(1, Ordering::SeqCst);
+        let mut metadata = MetadataMap::new();
+        let message = match state.redirect_endpoint.parse() {
+            Ok(value) if !state.redirect_endpoint.is_empty() => {
+                metadata.insert(REDIRECT_METADATA_KEY, value);
+                format!("server in maintenance, use {}", state.redirect_endpoint)
+            }
+            _ => "server in maintenance, retry later".to_string(),
+        };
+        warn!(redirect_endpoint = %state.redirect_endpoint, "Refusing new session - maintenance mode");
+        Some(Status::with_metadata(Code::Unavailable, message, metadata))
+    }
+}
@@ -0,0 +1,305 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+/// Upper bounds (ms) of the latency histogram buckets; the last bucket is unbounded
+const BUCKET_BOUNDS_MS: [f64; 10] = [1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0];
+
+/// A traced observation kept as an example of its bucket, so a slow bucket leads to
+/// the trace of a session that landed in it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exemplar {
+    pub trace_id: String,
+    pub value: f64,
+    pub unix_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Histogram {
+    pub buckets: [u64; BUCKET_BOUNDS_MS.len() + 1],
+    pub count: u64,
+    pub sum: f64,
+    /// Latest traced observation per bucket
+    #[serde(default)]
+    pub exemplars: [Option<Exemplar>; BUCKET_BOUNDS_MS.len() + 1],
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64, trace_id: Option<&str>) {
+        let idx = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+        self.buckets[idx] += 1;
+        self.count += 1;
+        self.sum += value;
+        if let Some(trace_id) = trace_id {
+            let unix_ms = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+            self.exemplars[idx] = Some(Exemplar { trace_id: trace_id.to_string(), value, unix_ms });
+        }
+    }
+
+    /// Estimate a quantile (0.0..=1.0) from the bucket upper bounds
+    pub fn quantile(&self, q: f64) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let rank = (q * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (i, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return BUCKET_BOUNDS_MS.get(i).copied().unwrap_or(f64::INFINITY);
+            }
+        }
+        f64::INFINITY
+    }
+}
+
+/// Point-in-time copy of every registered metric
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub counters: BTreeMap<String, u64>,
+    pub gauges: BTreeMap<String, i64>,
+    pub histograms: BTreeMap<String, Histogram>,
+}
+
+impl MetricsSnapshot {
+    pub fn write_json(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+    }
+
+    pub fn read_json(path: &Path) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// A counter summed over all its label sets
+    pub fn counter_total(&self, name: &str) -> u64 {
+        self.counters.iter().filter(|(key, _)| split_key(key).0 == name).map(|(_, value)| value).sum()
+    }
+
+    /// A counter summed per value of one of its labels; series without the label are left out
+    pub fn counter_by_label(&self, name: &str, label: &str) -> BTreeMap<String, u64> {
+        let prefix = format!("{}=\"", label);
+        let mut by_value = BTreeMap::new();
+        for (key, value) in &self.counters {
+            let (series, labels) = split_key(key);
+            if series != name {
+                continue;
+            }
+            let found = labels.split(',').find_map(|pair| pair.strip_prefix(prefix.as_str()));
+            if let Some(label_value) = found.map(|quoted| quoted.trim_end_matches('"')) {
+                *by_value.entry(label_value.to_string()).or_insert(0) += value;
+            }
+        }
+        by_value
+    }
+
+    /// A histogram merged over all its label sets (without exemplars)
+    pub fn histogram_total(&self, name: &str) -> Histogram {
+        let mut total = Histogram::default();
+        for (key, hist) in &self.histograms {
+            if split_key(key).0 != name {
+                continue;
+            }
+            for (bucket, count) in total.buckets.iter_mut().zip(hist.buckets) {
+                *bucket += count;
+            }
+            total.count += hist.count;
+            total.sum += hist.sum;
+        }
+        total
+    }
+
+    /// Describe every series whose value differs between `self` (before) and `after`
+    pub fn diff(&self, after: &MetricsSnapshot) -> Vec<String> {
+        let mut lines = Vec::new();
+        diff_series(&self.counters, &after.counters, "counter", &mut lines);
+        diff_series(&self.gauges, &after.gauges, "gauge", &mut lines);
+
+        let mut keys: Vec<&String> = self.histograms.keys().chain(after.histograms.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        let empty = Histogram::default();
+        for key in keys {
+            let before = self.histograms.get(key).unwrap_or(&empty);
+            let now = after.histograms.get(key).unwrap_or(&empty);
+            if before.count == now.count && before.buckets == now.buckets {
+                continue;
+            }
+            lines.push(format!(
+                "histogram {}: count {} -> {}, p50 {}ms -> {}ms, p99 {}ms -> {}ms",
+                key,
+                before.count,
+                now.count,
+                before.quantile(0.5),
+                now.quantile(0.5),
+                before.quantile(0.99),
+                now.quantile(0.99)
+            ));
+        }
+        lines
+    }
+
+    /// OpenMetrics text exposition, with bucket exemplars carrying their trace ids
+    pub fn to_openmetrics(&self) -> String {
+        let mut out = String::new();
+        let mut family = String::new();
+        let mut type_line = |out: &mut String, name: &str, kind: &str| {
+            if family != name {
+                let _ = writeln!(out, "# TYPE {} {}", name, kind);
+                family = name.to_string();
+            }
+        };
+        for (key, value) in &self.counters {
+            let (name, labels) = split_key(key);
+            let name = name.strip_suffix("_total").unwrap_or(name);
+            type_line(&mut out, name, "counter");
+            let _ = writeln!(out, "{}_total{} {}", name, braced(labels, None), value);
+        }
+        for (key, value) in &self.gauges {
+            let (name, labels) = split_key(key);
+            type_line(&mut out, name, "gauge");
+            let _ = writeln!(out, "{}{} {}", name, braced(labels, None), value);
+        }
+        for (key, hist) in &self.histograms {
+            let (name, labels) = split_key(key);
+            type_line(&mut out, name, "histogram");
+            let mut cumulative = 0;
+            for (i, count) in hist.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = BUCKET_BOUNDS_MS.get(i).map_or("+Inf".to_string(), |bound| format!("{:?}", bound));
+                let _ = write!(out, "{}_bucket{} {}", name, braced(labels, Some(&le)), cumulative);
+                if let Some(exemplar) = &hist.exemplars[i] {
+                    let _ = write!(
+                        out,
+                        " # {{trace_id=\"{}\"}} {} {:.3}",
+                        exemplar.trace_id,
+                        exemplar.value,
+                        exemplar.unix_ms as f64 / 1000.0
+                    );
+                }
+                out.push('\n');
+            }
+            let _ = writeln!(out, "{}_count{} {}", name, braced(labels, None), hist.count);
+            let _ = writeln!(out, "{}_sum{} {}", name, braced(labels, None), hist.sum);
+        }
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+/// Name and label list of a series key, e.g. `("x_total", "reason=\"overload\"")`
+fn split_key(key: &str) -> (&str, &str) {
+    match key.split_once('{') {
+        Some((name, labels)) => (name, labels.strip_suffix('}').unwrap_or(labels)),
+        None => (key, ""),
+    }
+}
+
+fn braced(labels: &str, le: Option<&str>) -> String {
+    let mut all: Vec<String> = Vec::new();
+    if !labels.is_empty() {
+        all.push(labels.to_string());
+    }
+    if let Some(le) = le {
+        all.push(format!("le=\"{}\"", le));
+    }
+    if all.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", all.join(","))
+    }
+}
+
+fn diff_series<V>(before: &BTreeMap<String, V>, after: &BTreeMap<String, V>, kind: &str, lines: &mut Vec<String>)
+where
+    V: Copy + Default + PartialEq + Into<i128> + std::fmt::Display,
+{
+    let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    for key in keys {
+        let old = before.get(key).copied().unwrap_or_default();
+        let new = after.get(key).copied().unwrap_or_default();
+        if old != new {
+            let delta: i128 = new.into() - old.into();
+            lines.push(format!("{} {}: {} -> {} ({:+})", kind, key, old, new, delta));
+        }
+    }
+}
+
+/// Minimal in-process metrics registry. Series are keyed by name plus
+/// Prometheus-style labels, e.g. `sessions_rejected_total{reason="overload"}`.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    inner: Mutex<MetricsSnapshot>,
+}
+
+fn series_key(name: &str, labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    let labels: Vec<String> = labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, v)).collect();
+    format!("{}{{{}}}", name, labels.join(","))
+}
+
+impl Metrics {
+    pub fn inc(&self, name: &str, labels: &[(&str, &str)]) {
+        self.add(name, labels, 1);
+    }
+
+    pub fn add(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.counters.entry(series_key(name, labels)).or_insert(0) += value;
+    }
+
+    pub fn set_gauge(&self, name: &str, labels: &[(&str, &str)], value: i64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.gauges.insert(series_key(name, labels), value);
+    }
+
+    pub fn observe_ms(&self, name: &str, labels: &[(&str, &str)], value: Duration) {
+        self.observe_ms_traced(name, labels, value, None);
+    }
+
+    /// `observe_ms`, keeping the observation as its bucket's exemplar when the session is traced
+    pub fn observe_ms_traced(&self, name: &str, labels: &[(&str, &str)], value: Duration, trace_id: Option<&str>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .histograms
+            .entry(series_key(name, labels))
+            .or_default()
+            .observe(value.as_secs_f64() * 1000.0, trace_id);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.inner.lock().unwrap().clone()
+    }
+
+    /// Log every series at INFO level
+    pub fn log_snapshot(&self) {
+        let snapshot = self.snapshot();
+        for (key, value) in &snapshot.counters {
+            info!(metric = %key, value = value, "counter");
+        }
+        for (key, value) in &snapshot.gauges {
+            info!(metric = %key, value = value, "gauge");
+        }
+        for (key, hist) in &snapshot.histograms {
+            info!(
+                metric = %key,
+                count = hist.count,
+                p50_ms = hist.quantile(0.5),
+                p99_ms = hist.quantile(0.99),
+                "histogram"
+            );
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Minimum score threshold (`--min-score`) and explicit no-fill. Right after
+//! generation, before scores are normalized, ads scoring below the threshold are
+//! dropped from a list and from each of its partitions. A list left without a
+//! single ad is sent empty with `no_fill_reason` saying why, rather than padded with
+//! ads nobody should see: `BELOW_MIN_SCORE` when the threshold removed every
+//! candidate, `NO_CANDIDATES` when generation found none. Clients tell such a
+//! session (outcome `no_fill`) apart from one that timed out. Dropped ads count in
+//! `ads_below_min_score_total` and no-fill lists in `no_fill_total{reason}`.
+
+use tracing::debug;
+
+use crate::ads::{Ad, AdsList, NoFillReason};
+use crate::metrics::Metrics;
+
+/// Apply `min_score` (None = no threshold) to a generated list and mark the list as
+/// a no-fill if it is left empty
+pub fn apply(ads_list: &mut AdsList, min_score: Option<f64>, metrics: &Metrics) {
+    let candidates = ad_count(ads_list);
+    if let Some(min_score) = min_score {
+        let mut dropped = drop_below(&mut ads_list.ads, min_score);
+        for partition in &mut ads_list.query_results {
+            dropped += drop_below(&mut partition.ads, min_score);
+        }
+        for partition in &mut ads_list.placement_results {
+            dropped += drop_below(&mut partition.ads, min_score);
+        }
+        if dropped > 0 {
+            metrics.add("ads_below_min_score_total", &[], dropped as u64);
+            debug!(version = ads_list.version, dropped = dropped, min_score = min_score, "Dropped ads below minimum score");
+        }
+    }
+    if ad_count(ads_list) > 0 {
+        return;
+    }
+    let reason = if candidates > 0 { NoFillReason::BelowMinScore } else { NoFillReason::NoCandidates };
+    ads_list.set_no_fill_reason(reason);
+    metrics.inc("no_fill_total", &[("reason", name(reason))]);
+}
+
+pub fn is_no_fill(ads_list: &AdsList) -> bool {
+    ads_list.no_fill_reason() != NoFillReason::Unspecified
+}
+
+pub fn name(reason: NoFillReason) -> &'static str {
+    match reason {
+        NoFillReason::Unspecified => "unspecified",
+        NoFillReason::BelowMinScore => "below_min_score",
+        NoFillReason::NoCandidates => "no_candidates",
+    }
+}
+
+/// Ads in a list, across its query and placement partitions
+pub fn ad_count(ads_list: &AdsList) -> usize {
+    ads_list.ads.len()
+        + ads_list.query_results.iter().map(|partition| partition.ads.len()).sum::<usize>()
+        + ads_list.placement_results.iter().map(|partition| partition.ads.len()).sum::<usize>()
+}
+
+// NaN scores fail the comparison and go too
+fn drop_below(ads: &mut Vec<Ad>, min_score: f64) -> usize {
+    let before = ads.len();
+    ads.retain(|ad| ad.score >= min_score);
+    before - ads.len()
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::Mutex;
+use tonic::Status;
+use tracing::{info, warn};
+
+use crate::ads::AdsList;
+use crate::backpressure::AdsSender;
+use crate::drift::ScoreDriftMonitor;
+use crate::metrics::Metrics;
+use crate::quality::QualityGate;
+
+/// Watches that a session emits AdsLists in increasing version order per channel.
+///
+/// The delayed version-3 task sends on the same stream as the Context loop, so a
+/// client that keeps sending Contexts can see v4 overtake the delayed v3. The
+/// check and the send happen under one lock, so what the watchdog records is the
+/// order the client receives. Violations are counted and logged, never fatal.
+///
+/// With a quality gate, the watchdog also keeps each channel's last sent AdsList and
+/// suppresses a later version that does not improve on it.
+#[derive(Debug)]
+pub struct OrderWatchdog {
+    session_id: u64,
+    last_sent: Mutex<HashMap<u32, u32>>,
+    quality_gate: Option<QualityGate>,
+    last_lists: Mutex<HashMap<u32, AdsList>>,
+    score_drift: Option<Arc<ScoreDriftMonitor>>,
+    metrics: Arc<Metrics>,
+}
+
+impl OrderWatchdog {
+    pub fn new(session_id: u64, quality_gate: Option<QualityGate>, metrics: Arc<Metrics>) -> Self {
+        OrderWatchdog {
+            session_id,
+            last_sent: Mutex::new(HashMap::new()),
+            quality_gate,
+            last_lists: Mutex::new(HashMap::new()),
+            score_drift: None,
+            metrics,
+        }
+    }
+
+    /// Record the scores of every AdsList sent in `score_drift`
+    pub fn with_score_drift(mut self, score_drift: Option<Arc<ScoreDriftMonitor>>) -> Self {
+        self.score_drift = score_drift;
+        self
+    }
+
+    /// Send `ads_list` on `tx`, recording it against the channel's last emitted
+    /// version. Returns whether it went out: false when the quality gate suppressed it.
+    pub async fn send(&self, tx: &AdsSender, ads_list: AdsList) -> Result<bool, SendError<Result<AdsList, Status>>> {
+        let mut last_sent = self.last_sent.lock().await;
+        let channel_id = ads_list.channel_id;
+        let version = ads_list.version;
+        if let Some(gate) = &self.quality_gate {
+            let mut last_lists = self.last_lists.lock().await;
+            if let Some(previous) = last_lists.get(&channel_id).filter(|previous| previous.version < version) {
+                let delta = gate.delta(previous, &ads_list);
+                let admitted = gate.admits(delta);
+                let outcome = if admitted { "sent" } else { "suppressed" };
+                self.metrics.inc("quality_gate_total", &[("metric", gate.metric.name()), ("outcome", outcome)]);
+                if !admitted {
+                    info!(
+                        session_id = self.session_id,
+                        channel_id = channel_id,
+                        version = version,
+                        previous_version = previous.version,
+                        metric = gate.metric.name(),
+                        delta = delta,
+                        min_delta = gate.min_delta,
+                        "Suppressed AdsList version - not better than the previous one"
+                    );
+                    return Ok(false);
+                }
+            }
+            last_lists.insert(channel_id, ads_list.clone());
+        }
+        if let Some(&last) = last_sent.get(&channel_id) {
+            if version <= last {
+                let kind = if version == last { "duplicate" } else { "regression" };
+                self.metrics.inc("version_order_violations_total", &[("kind", kind)]);
+                warn!(
+                    session_id = self.session_id,
+                    channel_id = channel_id,
+                    version = version,
+                    last_sent_version = last,
+                    kind = kind,
+                    "AdsList emitted out of version order"
+                );
+            }
+        }
+        if let Some(score_drift) = &self.score_drift {
+            score_drift.record(&ads_list);
+        }
+        tx.send(Ok(ads_list)).await?;
+        let last = last_sent.entry(channel_id).or_insert(version);
+        *last = (*last).max(version);
+        Ok(true)
+    }
+}
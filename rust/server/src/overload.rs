@@ -0,0 +1,136 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::metrics::Metrics;
+use crate::priority::PriorityClass;
+
+/// CoDel-style overload detector. Tracks the minimum generation sojourn time
+/// (Context received -> AdsList queued) over each interval; when even the fastest
+/// session in an interval exceeded the target, the server is considered overloaded
+/// until an interval completes with a minimum back under target. Batch traffic is
+/// shed earlier, once the minimum passes the lower batch target.
+#[derive(Debug)]
+pub struct OverloadController {
+    target: Duration,
+    batch_target: Duration,
+    interval: Duration,
+    metrics: Arc<Metrics>,
+    state: Mutex<WindowState>,
+}
+
+#[derive(Debug)]
+struct WindowState {
+    window_start: Instant,
+    window_min: Option<Duration>,
+    overloaded: bool,
+    // Minimum of the last completed interval was above the batch target
+    shedding_batch: bool,
+}
+
+impl OverloadController {
+    pub fn new(target: Duration, interval: Duration, metrics: Arc<Metrics>) -> Self {
+        metrics.set_gauge("overload_state", &[], 0);
+        OverloadController {
+            target,
+            batch_target: target,
+            interval,
+            metrics,
+            state: Mutex::new(WindowState {
+                window_start: Instant::now(),
+                window_min: None,
+                overloaded: false,
+                shedding_batch: false,
+            }),
+        }
+    }
+
+    /// Shed batch traffic once the interval minimum passes `fraction` of the target
+    pub fn with_batch_target_fraction(mut self, fraction: f64) -> Self {
+        self.batch_target = self.target.mul_f64(fraction.clamp(0.0, 1.0));
+        self
+    }
+
+    /// Record how long a Context waited before its AdsList was queued
+    pub fn observe(&self, sojourn: Duration) {
+        self.metrics.observe_ms("generation_sojourn_ms", &[], sojourn);
+
+        let mut state = self.state.lock().unwrap();
+        state.window_min = Some(state.window_min.map_or(sojourn, |min| min.min(sojourn)));
+        if state.window_start.elapsed() < self.interval {
+            return;
+        }
+
+        let window_min = state.window_min.unwrap_or_default();
+        self.transition(&mut state, window_min > self.target, window_min);
+        self.set_shedding_batch(&mut state, window_min > self.batch_target);
+        state.window_start = Instant::now();
+        state.window_min = None;
+    }
+
+    pub fn is_overloaded(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        // Nothing completed generation for a whole interval, so there is no queue left to protect
+        let idle = state.window_min.is_none() && state.window_start.elapsed() >= self.interval;
+        if idle && (state.overloaded || state.shedding_batch) {
+            self.transition(&mut state, false, Duration::ZERO);
+            self.set_shedding_batch(&mut state, false);
+            state.window_start = Instant::now();
+        }
+        state.overloaded
+    }
+
+    /// Whether new work of `class` is turned away: interactive only while overloaded,
+    /// batch already above the batch target
+    pub fn sheds(&self, class: PriorityClass) -> bool {
+        let overloaded = self.is_overloaded();
+        match class {
+            PriorityClass::Interactive => overloaded,
+            PriorityClass::Batch => overloaded || self.state.lock().unwrap().shedding_batch,
+        }
+    }
+
+    /// When a shed client should try again: the rest of the current interval, the
+    /// earliest the overload state can change
+    pub fn retry_after(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        let remaining = self.interval.saturating_sub(state.window_start.elapsed());
+        if remaining.is_zero() {
+            self.interval
+        } else {
+            remaining
+        }
+    }
+
+    fn set_shedding_batch(&self, state: &mut WindowState, shedding: bool) {
+        if shedding != state.shedding_batch {
+            self.metrics.set_gauge("overload_batch_shedding", &[], shedding as i64);
+            state.shedding_batch = shedding;
+        }
+    }
+
+    fn transition(&self, state: &mut WindowState, overloaded: bool, window_min: Duration) {
+        if overloaded == state.overloaded {
+            return;
+        }
+        if overloaded {
+            warn!(
+                window_min_ms = window_min.as_millis() as u64,
+                target_ms = self.target.as_millis() as u64,
+                "Entering overload state - shedding new sessions and refinements"
+            );
+        } else {
+            info!(
+                window_min_ms = window_min.as_millis() as u64,
+                target_ms = self.target.as_millis() as u64,
+                "Leaving overload state"
+            );
+        }
+        self.metrics.inc(
+            "overload_transitions_total",
+            &[("to", if overloaded { "overloaded" } else { "normal" })],
+        );
+        self.metrics.set_gauge("overload_state", &[], overloaded as i64);
+        state.overloaded = overloaded;
+    }
+}
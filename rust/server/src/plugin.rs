@@ -0,0 +1,104 @@
+use std::ffi::CStr;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use ads_common::{Error, Result};
+use ads_plugin_api::{
+    AbiVersionFn, FreeFn, GenerateFn, NameFn, ABI_VERSION, ABI_VERSION_SYMBOL, FREE_SYMBOL, GENERATE_SYMBOL,
+    NAME_SYMBOL, STATUS_DECODE_ERROR, STATUS_OK, STATUS_PANIC,
+};
+use libloading::Library;
+use prost::Message;
+use tonic::Status;
+use tracing::info;
+
+use crate::ads::{AdsList, Context};
+
+/// Ad generator loaded from a shared library implementing the `ads-plugin-api` ABI.
+/// When configured it replaces the built-in generator for every session.
+pub struct GeneratorPlugin {
+    name: String,
+    path: PathBuf,
+    generate: GenerateFn,
+    free: FreeFn,
+    // Keeps the code behind the function pointers above mapped; must outlive them
+    _library: Library,
+}
+
+impl fmt::Debug for GeneratorPlugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeneratorPlugin")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl GeneratorPlugin {
+    /// Load and validate a plugin; fails if a symbol is missing or the ABI version differs
+    pub fn load(path: &Path) -> Result<Self> {
+        let error =
+            |what: &str, e: &dyn fmt::Display| Error::config(format!("generator plugin {}: {}: {}", path.display(), what, e));
+        // SAFETY: loading runs the library's initializers; plugins are trusted code
+        // supplied by the operator on the command line
+        let library = unsafe { Library::new(path) }.map_err(|e| error("load failed", &e))?;
+        // SAFETY: the symbol types are fixed by the plugin ABI
+        let (abi_version, name, generate, free) = unsafe {
+            let abi_version = *library
+                .get::<AbiVersionFn>(ABI_VERSION_SYMBOL)
+                .map_err(|e| error("missing ads_plugin_abi_version", &e))?;
+            let name = *library.get::<NameFn>(NAME_SYMBOL).map_err(|e| error("missing ads_plugin_name", &e))?;
+            let generate = *library
+                .get::<GenerateFn>(GENERATE_SYMBOL)
+                .map_err(|e| error("missing ads_plugin_generate", &e))?;
+            let free = *library.get::<FreeFn>(FREE_SYMBOL).map_err(|e| error("missing ads_plugin_free", &e))?;
+            (abi_version(), CStr::from_ptr(name()).to_string_lossy().into_owned(), generate, free)
+        };
+        if abi_version != ABI_VERSION {
+            return Err(error(
+                "incompatible ABI",
+                &format!("plugin implements version {}, server expects {}", abi_version, ABI_VERSION),
+            ));
+        }
+        info!(plugin = %name, path = %path.display(), abi_version = abi_version, "Loaded generator plugin");
+        Ok(GeneratorPlugin {
+            name,
+            path: path.to_path_buf(),
+            generate,
+            free,
+            _library: library,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn generate(&self, context: &Context, version: u32, session_seed: u64) -> Result<AdsList, Status> {
+        let encoded = context.encode_to_vec();
+        let mut out: *mut u8 = ptr::null_mut();
+        let mut out_len = 0usize;
+        // SAFETY: input points to `encoded`, which outlives the call; out/out_len are valid
+        let status = unsafe {
+            (self.generate)(encoded.as_ptr(), encoded.len(), version, session_seed, &mut out, &mut out_len)
+        };
+        match status {
+            STATUS_OK => {}
+            STATUS_DECODE_ERROR => return Err(self.failure("could not decode Context")),
+            STATUS_PANIC => return Err(self.failure("generator panicked")),
+            other => return Err(self.failure(&format!("unknown status {}", other))),
+        }
+        // SAFETY: on STATUS_OK the plugin handed us out_len bytes at out, released below via its own free
+        let decoded = unsafe {
+            let decoded = AdsList::decode(std::slice::from_raw_parts(out, out_len));
+            (self.free)(out, out_len);
+            decoded
+        };
+        decoded.map_err(|e| self.failure(&format!("invalid AdsList: {}", e)))
+    }
+
+    fn failure(&self, reason: &str) -> Status {
+        Status::internal(format!("generator plugin {}: {}", self.name, reason))
+    }
+}
@@ -0,0 +1,149 @@
+//! Request priority classes. A session names its class with `x-priority` metadata
+//! (`interactive` or `batch`; anything else gets `--default-priority`), and every
+//! place the server turns work away or makes it wait prefers interactive traffic:
+//! batch sessions are refused once `--batch-session-share` of the concurrent
+//! session limit is taken, are shed (sessions and refinements) as soon as the
+//! minimum generation sojourn passes `--batch-overload-target-fraction` of the
+//! overload target, and queue behind interactive ones for the
+//! `--generation-workers` generation slots. Session, rejection, skipped-refinement
+//! and queue-wait metrics carry a `priority` label so the difference shows under load.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use clap::ValueEnum;
+use tokio::sync::oneshot;
+use tonic::metadata::MetadataMap;
+
+use ads_proto::PRIORITY_METADATA_KEY;
+
+use crate::metrics::Metrics;
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PriorityClass {
+    /// A user is waiting on the result
+    #[default]
+    Interactive,
+    /// Offline or bulk traffic that can wait or be retried later
+    Batch,
+}
+
+impl PriorityClass {
+    pub fn name(self) -> &'static str {
+        match self {
+            PriorityClass::Interactive => "interactive",
+            PriorityClass::Batch => "batch",
+        }
+    }
+
+    /// Class named by a session's request `metadata`, or `default`
+    pub fn from_metadata(metadata: &MetadataMap, default: PriorityClass) -> Self {
+        metadata
+            .get(PRIORITY_METADATA_KEY)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|name| PriorityClass::from_str(name.trim(), true).ok())
+            .unwrap_or(default)
+    }
+}
+
+/// Bounded pool of generation slots handed out interactive-first. Waiters of a class
+/// are served in arrival order.
+#[derive(Debug)]
+pub struct GenerationScheduler {
+    workers: usize,
+    state: Mutex<SchedulerState>,
+    metrics: Arc<Metrics>,
+}
+
+#[derive(Debug, Default)]
+struct SchedulerState {
+    busy: usize,
+    interactive: VecDeque<oneshot::Sender<GenerationSlot>>,
+    batch: VecDeque<oneshot::Sender<GenerationSlot>>,
+}
+
+impl SchedulerState {
+    fn queue(&mut self, class: PriorityClass) -> &mut VecDeque<oneshot::Sender<GenerationSlot>> {
+        match class {
+            PriorityClass::Interactive => &mut self.interactive,
+            PriorityClass::Batch => &mut self.batch,
+        }
+    }
+}
+
+/// One generation slot, returned to the scheduler when dropped
+#[derive(Debug)]
+pub struct GenerationSlot {
+    // None once handed back, or for a waiter whose scheduler went away
+    scheduler: Option<Arc<GenerationScheduler>>,
+}
+
+impl Drop for GenerationSlot {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.take() {
+            scheduler.release();
+        }
+    }
+}
+
+impl GenerationScheduler {
+    /// None for 0 workers, so generation stays unbounded and unqueued
+    pub fn new(workers: usize, metrics: Arc<Metrics>) -> Option<Arc<Self>> {
+        (workers > 0).then(|| Arc::new(GenerationScheduler { workers, state: Mutex::default(), metrics }))
+    }
+
+    /// Wait for a free slot; interactive waiters are always served before batch ones
+    pub async fn acquire(self: &Arc<Self>, class: PriorityClass) -> GenerationSlot {
+        let waiting = {
+            let mut state = self.state.lock().unwrap();
+            if state.busy < self.workers {
+                state.busy += 1;
+                return GenerationSlot { scheduler: Some(self.clone()) };
+            }
+            let (tx, rx) = oneshot::channel();
+            state.queue(class).push_back(tx);
+            self.export(&mut state);
+            rx
+        };
+        let start = Instant::now();
+        let slot = waiting.await.unwrap_or(GenerationSlot { scheduler: None });
+        self.metrics.observe_ms("generation_queue_wait_ms", &[("priority", class.name())], start.elapsed());
+        slot
+    }
+
+    /// Busy slots and the interactive and batch waiters, in that order
+    pub fn load(&self) -> (usize, usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.busy, state.interactive.len(), state.batch.len())
+    }
+
+    // Hand the freed slot to the next live waiter, interactive first
+    fn release(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let next = match state.interactive.pop_front() {
+                Some(waiter) => Some(waiter),
+                None => state.batch.pop_front(),
+            };
+            let Some(waiter) = next else {
+                state.busy -= 1;
+                break;
+            };
+            match waiter.send(GenerationSlot { scheduler: Some(self.clone()) }) {
+                Ok(()) => break,
+                // The waiter gave up; disarm the slot and try the next one
+                Err(mut slot) => slot.scheduler = None,
+            }
+        }
+        self.export(&mut state);
+    }
+
+    fn export(&self, state: &mut SchedulerState) {
+        for class in [PriorityClass::Interactive, PriorityClass::Batch] {
+            let queued = state.queue(class).len() as i64;
+            self.metrics.set_gauge("generation_queued", &[("priority", class.name())], queued);
+        }
+        self.metrics.set_gauge("generation_workers_busy", &[], state.busy as i64);
+    }
+}
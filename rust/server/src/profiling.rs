@@ -0,0 +1,80 @@
+//! CPU profiles of the running server on demand, through the admin `CaptureProfile`
+//! RPC: the whole process is sampled with pprof for a bounded duration and the
+//! result comes back as a flamegraph SVG, so a load run can profile the generator
+//! under the load it applies. Sampling needs the `profiling` feature
+//! (`cargo build -p ads-server --features profiling`); without it the RPC answers
+//! UNIMPLEMENTED.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use tonic::Status;
+use tracing::info;
+
+/// Longest profile one request can ask for
+pub const MAX_PROFILE_DURATION: Duration = Duration::from_secs(60);
+
+const DEFAULT_FREQUENCY_HZ: u32 = 99;
+
+#[derive(Debug)]
+pub struct Profile {
+    pub flamegraph_svg: Vec<u8>,
+    pub samples: u64,
+    pub duration: Duration,
+}
+
+/// Runs one profile at a time; a second request while one runs is refused
+#[derive(Debug, Default)]
+pub struct Profiler {
+    running: AtomicBool,
+}
+
+impl Profiler {
+    pub async fn capture(&self, duration: Duration, frequency_hz: u32) -> Result<Profile, Status> {
+        if duration.is_zero() {
+            return Err(Status::invalid_argument("duration_ms must be positive"));
+        }
+        let duration = duration.min(MAX_PROFILE_DURATION);
+        let frequency_hz = if frequency_hz == 0 { DEFAULT_FREQUENCY_HZ } else { frequency_hz.min(1000) };
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Err(Status::failed_precondition("a profile is already being captured"));
+        }
+        let profile = tokio::task::spawn_blocking(move || sample(duration, frequency_hz))
+            .await
+            .map_err(|e| Status::internal(format!("profiler task: {}", e)))
+            .and_then(|profile| profile);
+        self.running.store(false, Ordering::SeqCst);
+        if let Ok(profile) = &profile {
+            info!(
+                duration_ms = duration.as_millis() as u64,
+                frequency_hz = frequency_hz,
+                samples = profile.samples,
+                svg_bytes = profile.flamegraph_svg.len(),
+                "CPU profile captured"
+            );
+        }
+        profile
+    }
+}
+
+#[cfg(feature = "profiling")]
+fn sample(duration: Duration, frequency_hz: u32) -> Result<Profile, Status> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(frequency_hz as i32)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+        .map_err(|e| Status::internal(format!("start profiler: {}", e)))?;
+    std::thread::sleep(duration);
+    let report = guard.report().build().map_err(|e| Status::internal(format!("build profile: {}", e)))?;
+    let samples = report.data.values().map(|count| (*count).max(0) as u64).sum();
+    let mut flamegraph_svg = Vec::new();
+    report
+        .flamegraph(&mut flamegraph_svg)
+        .map_err(|e| Status::internal(format!("render flamegraph: {}", e)))?;
+    Ok(Profile { flamegraph_svg, samples, duration })
+}
+
+#[cfg(not(feature = "profiling"))]
+fn sample(_duration: Duration, _frequency_hz: u32) -> Result<Profile, Status> {
+    Err(Status::unimplemented("server built without the profiling feature"))
+}
@@ -0,0 +1,84 @@
+//! Quality gate on refinement versions. With `--quality-gate`, a channel's AdsList
+//! is only sent when it scores measurably better than the version the client
+//! already holds; otherwise it is suppressed and logged, saving the bandwidth of a
+//! refinement that would not change what the client shows. The first version of a
+//! channel is always sent.
+
+use clap::ValueEnum;
+use std::collections::HashMap;
+
+use crate::ads::AdsList;
+
+/// How one version is compared with the previous one
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityMetric {
+    /// Change in the mean score of the listed ads
+    MeanScore,
+    /// Change in NDCG@k, with each ad's latest score as its gain and the best
+    /// order of both versions' ads as the ideal
+    Ndcg,
+}
+
+impl QualityMetric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            QualityMetric::MeanScore => "mean_score",
+            QualityMetric::Ndcg => "ndcg",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityGate {
+    pub metric: QualityMetric,
+    /// Smallest improvement that lets a version through
+    pub min_delta: f64,
+    /// Cut-off rank of the NDCG metric
+    pub k: usize,
+}
+
+impl QualityGate {
+    /// Improvement of `next` over `previous` under the gate's metric
+    pub fn delta(&self, previous: &AdsList, next: &AdsList) -> f64 {
+        match self.metric {
+            QualityMetric::MeanScore => mean_score(next) - mean_score(previous),
+            QualityMetric::Ndcg => {
+                // Ads of both versions, the refined score winning for ads in both
+                let mut gains: HashMap<&str, f64> =
+                    previous.ads.iter().map(|ad| (ad.ad_id.as_str(), gain(ad.score))).collect();
+                gains.extend(next.ads.iter().map(|ad| (ad.ad_id.as_str(), gain(ad.score))));
+                let mut ideal: Vec<f64> = gains.values().copied().collect();
+                ideal.sort_by(|a, b| b.total_cmp(a));
+                let ideal_dcg = dcg(ideal.into_iter(), self.k);
+                if ideal_dcg <= 0.0 {
+                    return 0.0;
+                }
+                let ndcg = |list: &AdsList| dcg(list.ads.iter().map(|ad| gains[ad.ad_id.as_str()]), self.k) / ideal_dcg;
+                ndcg(next) - ndcg(previous)
+            }
+        }
+    }
+
+    pub fn admits(&self, delta: f64) -> bool {
+        delta >= self.min_delta
+    }
+}
+
+fn gain(score: f64) -> f64 {
+    if score.is_finite() {
+        score.max(0.0)
+    } else {
+        0.0
+    }
+}
+
+fn mean_score(list: &AdsList) -> f64 {
+    if list.ads.is_empty() {
+        return 0.0;
+    }
+    list.ads.iter().map(|ad| gain(ad.score)).sum::<f64>() / list.ads.len() as f64
+}
+
+fn dcg(gains: impl Iterator<Item = f64>, k: usize) -> f64 {
+    gains.take(k).enumerate().map(|(rank, gain)| gain / (rank as f64 + 2.0).log2()).sum()
+}
@@ -0,0 +1,183 @@
+use ads_common::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use crate::config::ServerConfig;
+use crate::config_schema::{self, ConfigFile, CONFIG_SCHEMA_VERSION};
+
+/// Server knobs that can change while the server is running. Initial values come
+/// from the command line; a `--config-file` overrides any subset of them and is
+/// re-read whenever it changes on disk. Its layout is versioned, see `config_schema`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeConfig {
+    pub enable_test_hooks: bool,
+    pub slot_constraints: bool,
+    pub min_context_gap_ms: u64,
+    pub max_context_gap_ms: u64,
+}
+
+impl RuntimeConfig {
+    pub fn from_server_config(config: &ServerConfig) -> Self {
+        RuntimeConfig {
+            enable_test_hooks: config.enable_test_hooks,
+            slot_constraints: config.slot_constraints,
+            min_context_gap_ms: config.min_context_gap_ms,
+            max_context_gap_ms: config.max_context_gap_ms,
+        }
+    }
+
+    /// Migrate and validate a JSON config file, then overlay the keys it sets onto `self`
+    fn overlay(&self, file_json: serde_json::Value) -> Result<(Self, ConfigFile)> {
+        let mut merged = serde_json::to_value(self)?;
+        let Some(target) = merged.as_object_mut() else {
+            return Err(Error::config("runtime config must serialize to a JSON object"));
+        };
+        let file = config_schema::load(file_json, target).map_err(|issues| {
+            let lines: Vec<String> = issues.iter().map(|issue| format!("  {}", issue)).collect();
+            Error::config(format!("config file has {} problem(s):\n{}", issues.len(), lines.join("\n")))
+        })?;
+        for (key, value) in &file.overrides {
+            target.insert(key.clone(), value.clone());
+        }
+        Ok((serde_json::from_value(merged)?, file))
+    }
+}
+
+/// A single key that changed between two configs, values JSON-encoded
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigChange {
+    pub key: String,
+    pub old_value: String,
+    pub new_value: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReloadRecord {
+    pub timestamp_ms: u64,
+    pub source: String,
+    pub changes: Vec<ConfigChange>,
+}
+
+/// Compute the per-key differences between two configs
+pub fn diff(old: &RuntimeConfig, new: &RuntimeConfig) -> Vec<ConfigChange> {
+    let old = serde_json::to_value(old).unwrap_or_default();
+    let new = serde_json::to_value(new).unwrap_or_default();
+    let (Some(old), Some(new)) = (old.as_object(), new.as_object()) else {
+        return Vec::new();
+    };
+    new.iter()
+        .filter(|(key, value)| old.get(*key) != Some(*value))
+        .map(|(key, value)| ConfigChange {
+            key: key.clone(),
+            old_value: old.get(key).map(|v| v.to_string()).unwrap_or_default(),
+            new_value: value.to_string(),
+        })
+        .collect()
+}
+
+/// Holds the current runtime config plus an audit trail of the last reloads
+#[derive(Debug)]
+pub struct ConfigStore {
+    base: RuntimeConfig,
+    current: RwLock<RuntimeConfig>,
+    audit: Mutex<VecDeque<ReloadRecord>>,
+    audit_size: usize,
+}
+
+impl ConfigStore {
+    pub fn new(base: RuntimeConfig, audit_size: usize) -> Self {
+        ConfigStore {
+            current: RwLock::new(base.clone()),
+            base,
+            audit: Mutex::new(VecDeque::with_capacity(audit_size)),
+            audit_size,
+        }
+    }
+
+    pub fn current(&self) -> RuntimeConfig {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn audit_trail(&self) -> Vec<ReloadRecord> {
+        self.audit.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Replace the current config, logging and recording what changed
+    pub fn apply(&self, new: RuntimeConfig, source: &str) -> Vec<ConfigChange> {
+        let changes = {
+            let mut current = self.current.write().unwrap();
+            let changes = diff(&current, &new);
+            *current = new;
+            changes
+        };
+        if changes.is_empty() {
+            return changes;
+        }
+        for change in &changes {
+            info!(
+                source = source,
+                key = %change.key,
+                old_value = %change.old_value,
+                new_value = %change.new_value,
+                "Config value changed"
+            );
+        }
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut audit = self.audit.lock().unwrap();
+        while audit.len() >= self.audit_size.max(1) {
+            audit.pop_front();
+        }
+        audit.push_back(ReloadRecord {
+            timestamp_ms,
+            source: source.to_string(),
+            changes: changes.clone(),
+        });
+        changes
+    }
+
+    /// Load a config file on top of the command-line base config
+    pub fn reload_from_file(&self, path: &Path) -> Result<Vec<ConfigChange>> {
+        let text = std::fs::read_to_string(path)?;
+        let json: serde_json::Value = serde_json::from_str(&text)?;
+        let (new, file) = self.base.overlay(json)?;
+        if file.migrated() {
+            warn!(
+                path = %path.display(),
+                schema_version = file.schema_version,
+                current_schema_version = CONFIG_SCHEMA_VERSION,
+                "Config file uses an older schema - migrated on load; set schema_version to upgrade it"
+            );
+        }
+        Ok(self.apply(new, &format!("file:{}", path.display())))
+    }
+}
+
+/// Re-read the config file whenever its modification time changes
+pub fn spawn_file_watcher(store: Arc<ConfigStore>, path: PathBuf, poll_interval: Duration) {
+    tokio::spawn(async move {
+        let mut last_modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        loop {
+            tokio::time::sleep(poll_interval).await;
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+            match store.reload_from_file(&path) {
+                Ok(changes) => info!(
+                    path = %path.display(),
+                    changed_keys = changes.len(),
+                    "Config file reloaded"
+                ),
+                Err(e) => warn!(path = %path.display(), error = %e, "Config reload failed - keeping previous config"),
+            }
+        }
+    });
+}
@@ -0,0 +1,148 @@
+//! Context sanitization (`--sanitize`): scrub request content before it reaches
+//! generation, logs, the session journal or the feature log. The rules apply to the
+//! query, the batched queries and the understanding of every incoming Context, in
+//! this order: `normalize` (Unicode NFKC, control characters other than whitespace
+//! dropped), `email` and `phone` (matches replaced with `[email]` / `[phone]`), then
+//! `truncate` (to `--sanitize-max-query-chars` / `--sanitize-max-understanding-chars`).
+//! Every rule that changes a field counts in `context_scrubs_total{rule,field}`.
+
+use std::sync::Arc;
+
+use clap::ValueEnum;
+use regex::{Captures, Regex};
+use tracing::debug;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::ads::Context;
+use crate::metrics::Metrics;
+
+/// Digits a phone number match needs, so years, quantities and model numbers survive
+const MIN_PHONE_DIGITS: usize = 9;
+const MAX_PHONE_DIGITS: usize = 15;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SanitizeRule {
+    Normalize,
+    Email,
+    Phone,
+    Truncate,
+}
+
+impl SanitizeRule {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SanitizeRule::Normalize => "normalize",
+            SanitizeRule::Email => "email",
+            SanitizeRule::Phone => "phone",
+            SanitizeRule::Truncate => "truncate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Query,
+    Understanding,
+}
+
+impl Field {
+    fn name(&self) -> &'static str {
+        match self {
+            Field::Query => "query",
+            Field::Understanding => "understanding",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sanitizer {
+    rules: Vec<SanitizeRule>,
+    max_query_chars: usize,
+    max_understanding_chars: usize,
+    email: Regex,
+    phone: Regex,
+    metrics: Arc<Metrics>,
+}
+
+impl Sanitizer {
+    /// None without rules, so unsanitized servers skip the pass entirely
+    pub fn new(
+        rules: &[SanitizeRule],
+        max_query_chars: usize,
+        max_understanding_chars: usize,
+        metrics: Arc<Metrics>,
+    ) -> Option<Self> {
+        if rules.is_empty() {
+            return None;
+        }
+        // Applied in a fixed order whatever the order on the command line
+        let rules = [SanitizeRule::Normalize, SanitizeRule::Email, SanitizeRule::Phone, SanitizeRule::Truncate]
+            .into_iter()
+            .filter(|rule| rules.contains(rule))
+            .collect();
+        Some(Sanitizer {
+            rules,
+            max_query_chars,
+            max_understanding_chars,
+            email: Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}").unwrap(),
+            phone: Regex::new(r"\+?\(?\d[\d\s().-]{6,}\d").unwrap(),
+            metrics,
+        })
+    }
+
+    /// Scrub the Context in place. Returns the rules that changed it, as
+    /// `<rule>:<field>`, each once.
+    pub fn sanitize(&self, context: &mut Context) -> Vec<String> {
+        let mut applied = Vec::new();
+        let mut scrubs = self.scrub(&mut context.query, Field::Query, &mut applied);
+        for query in &mut context.queries {
+            scrubs += self.scrub(query, Field::Query, &mut applied);
+        }
+        scrubs += self.scrub(&mut context.understanding, Field::Understanding, &mut applied);
+        if scrubs > 0 {
+            debug!(scrubs = scrubs, "Sanitized Context");
+        }
+        applied
+    }
+
+    fn scrub(&self, text: &mut String, field: Field, applied_rules: &mut Vec<String>) -> usize {
+        if text.is_empty() {
+            return 0;
+        }
+        let mut applied = 0;
+        for rule in &self.rules {
+            let scrubbed = match rule {
+                SanitizeRule::Normalize => text.nfkc().filter(|c| !c.is_control() || c.is_whitespace()).collect(),
+                SanitizeRule::Email => self.email.replace_all(text, "[email]").into_owned(),
+                SanitizeRule::Phone => self
+                    .phone
+                    .replace_all(text, |caps: &Captures| {
+                        let digits = caps[0].chars().filter(char::is_ascii_digit).count();
+                        if (MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits) {
+                            "[phone]".to_string()
+                        } else {
+                            caps[0].to_string()
+                        }
+                    })
+                    .into_owned(),
+                SanitizeRule::Truncate => {
+                    let max = match field {
+                        Field::Query => self.max_query_chars,
+                        Field::Understanding => self.max_understanding_chars,
+                    };
+                    text.chars().take(max).collect()
+                }
+            };
+            if scrubbed != *text {
+                self.metrics.inc("context_scrubs_total", &[("rule", rule.name()), ("field", field.name())]);
+                *text = scrubbed;
+                applied += 1;
+                let name = format!("{}:{}", rule.name(), field.name());
+                if !applied_rules.contains(&name) {
+                    applied_rules.push(name);
+                }
+            }
+        }
+        applied
+    }
+}
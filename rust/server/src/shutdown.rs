@@ -0,0 +1,25 @@
+//! Shutdown trigger of the server and the stub server. Ctrl-C (SIGINT) and SIGTERM,
+//! what container runtimes and process supervisors send, take the same path, so the
+//! shutdown report and the metrics dump are written either way.
+
+use tracing::{info, warn};
+
+/// Resolve once SIGINT or (on Unix) SIGTERM arrives
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => info!(signal = "SIGINT", "Shutdown signal received"),
+                    _ = sigterm.recv() => info!(signal = "SIGTERM", "Shutdown signal received"),
+                }
+                return;
+            }
+            Err(e) => warn!(error = %e, "Could not listen for SIGTERM - only Ctrl-C shuts down"),
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+    info!(signal = "SIGINT", "Shutdown signal received");
+}
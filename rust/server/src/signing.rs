@@ -0,0 +1,39 @@
+//! Application-layer integrity for AdsLists. With `--signing-key` the server signs
+//! every AdsList it sends (ed25519 over `AdsList::signed_bytes`), so a client holding
+//! the public key can tell a list altered on the way, by a proxy or by the `tamper`
+//! fault action, from one the server produced.
+
+use std::path::Path;
+
+use ads_common::{Error, Result};
+use ed25519_dalek::{Signer, SigningKey};
+
+use crate::ads::AdsList;
+
+#[derive(Debug)]
+pub struct ResponseSigner {
+    key: SigningKey,
+}
+
+impl ResponseSigner {
+    /// Load the key from a file holding its 32-byte seed as 64 hex digits, e.g. the
+    /// output of `openssl rand -hex 32`
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::config(format!("signing key {}: {}", path.display(), e)))?;
+        let seed: [u8; 32] = hex::decode(text.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| Error::config(format!("signing key {}: expected 64 hex digits", path.display())))?;
+        Ok(ResponseSigner { key: SigningKey::from_bytes(&seed) })
+    }
+
+    /// Public key clients verify with (`ads-client --verify-key`), as hex
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.key.verifying_key().as_bytes())
+    }
+
+    pub fn sign(&self, ads_list: &mut AdsList) {
+        ads_list.signature = self.key.sign(&ads_list.signed_bytes()).to_bytes().to_vec();
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::ServerConfig;
+use crate::metrics::Metrics;
+
+/// Upper bound on remembered events per objective so a long load test can't grow memory unbounded
+const MAX_EVENTS: usize = 200_000;
+
+/// Service level objectives evaluated over rolling windows
+#[derive(Debug, Clone)]
+pub struct SloConfig {
+    /// First-version latency threshold: a session is "good" if its first AdsList is sent within it
+    pub first_version_latency: Duration,
+    /// Fraction of sessions that must meet the latency threshold (e.g. 0.99 for a p99 objective)
+    pub latency_target: f64,
+    /// Fraction of sessions that must complete without an error status
+    pub success_target: f64,
+    /// Long evaluation window; the short window is 1/12 of it
+    pub window: Duration,
+    /// Burn rate (budget consumption speed, 1.0 = exactly on budget) that raises an alert
+    /// when exceeded in both windows
+    pub burn_rate_alert: f64,
+}
+
+impl SloConfig {
+    pub fn from_server_config(config: &ServerConfig) -> Self {
+        SloConfig {
+            first_version_latency: Duration::from_millis(config.slo_first_version_ms),
+            latency_target: config.slo_latency_target,
+            success_target: config.slo_success_target,
+            window: Duration::from_secs(config.slo_window_secs),
+            burn_rate_alert: config.slo_burn_rate_alert,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Objective {
+    name: &'static str,
+    target: f64,
+    // (when, good)
+    events: VecDeque<(Instant, bool)>,
+}
+
+/// Compliance of one objective over one window
+#[derive(Debug, Clone, Copy)]
+pub struct WindowStats {
+    pub events: usize,
+    pub compliance: f64,
+    /// Error rate divided by the error budget (1 - target)
+    pub burn_rate: f64,
+}
+
+impl Objective {
+    fn new(name: &'static str, target: f64) -> Self {
+        Objective { name, target, events: VecDeque::new() }
+    }
+
+    fn record(&mut self, good: bool, max_age: Duration) {
+        let now = Instant::now();
+        self.events.push_back((now, good));
+        while self.events.len() > MAX_EVENTS
+            || self.events.front().is_some_and(|(at, _)| now.duration_since(*at) > max_age)
+        {
+            self.events.pop_front();
+        }
+    }
+
+    fn stats(&self, window: Duration) -> WindowStats {
+        let now = Instant::now();
+        let (mut total, mut good) = (0usize, 0usize);
+        for (at, ok) in self.events.iter().rev() {
+            if now.duration_since(*at) > window {
+                break;
+            }
+            total += 1;
+            good += *ok as usize;
+        }
+        let compliance = if total == 0 { 1.0 } else { good as f64 / total as f64 };
+        let budget = (1.0 - self.target).max(f64::EPSILON);
+        WindowStats { events: total, compliance, burn_rate: (1.0 - compliance) / budget }
+    }
+}
+
+/// Tracks session latency and success SLOs and alerts on fast error-budget burn,
+/// so load tests evaluate themselves
+#[derive(Debug)]
+pub struct SloTracker {
+    config: SloConfig,
+    latency: Mutex<Objective>,
+    success: Mutex<Objective>,
+    metrics: Arc<Metrics>,
+}
+
+impl SloTracker {
+    pub fn new(config: SloConfig, metrics: Arc<Metrics>) -> Self {
+        SloTracker {
+            latency: Mutex::new(Objective::new("first_version_latency", config.latency_target)),
+            success: Mutex::new(Objective::new("session_success", config.success_target)),
+            config,
+            metrics,
+        }
+    }
+
+    pub fn record_first_version(&self, latency: Duration) {
+        let good = latency <= self.config.first_version_latency;
+        self.latency.lock().unwrap().record(good, self.config.window);
+    }
+
+    pub fn record_session(&self, success: bool) {
+        self.success.lock().unwrap().record(success, self.config.window);
+    }
+
+    /// Compute compliance for every objective, export it as gauges and log violations
+    pub fn evaluate(&self) {
+        let short_window = self.config.window / 12;
+        for objective in [&self.latency, &self.success] {
+            let objective = objective.lock().unwrap();
+            let long = objective.stats(self.config.window);
+            let short = objective.stats(short_window);
+            let labels = [("slo", objective.name)];
+            self.metrics.set_gauge("slo_compliance_ppm", &labels, (long.compliance * 1e6) as i64);
+            self.metrics.set_gauge(
+                "slo_burn_rate_milli",
+                &[("slo", objective.name), ("window", "long")],
+                (long.burn_rate * 1000.0) as i64,
+            );
+            self.metrics.set_gauge(
+                "slo_burn_rate_milli",
+                &[("slo", objective.name), ("window", "short")],
+                (short.burn_rate * 1000.0) as i64,
+            );
+
+            if long.events == 0 {
+                continue;
+            }
+            if long.compliance < objective.target {
+                self.metrics.inc("slo_violations_total", &labels);
+                warn!(
+                    slo = objective.name,
+                    target = objective.target,
+                    compliance = format!("{:.4}", long.compliance),
+                    events = long.events,
+                    window_secs = self.config.window.as_secs(),
+                    "SLO violated"
+                );
+            } else {
+                info!(
+                    slo = objective.name,
+                    target = objective.target,
+                    compliance = format!("{:.4}", long.compliance),
+                    events = long.events,
+                    "SLO met"
+                );
+            }
+            // Multi-window burn-rate alert: the long window shows the burn is significant,
+            // the short one that it is still happening
+            if long.burn_rate > self.config.burn_rate_alert && short.burn_rate > self.config.burn_rate_alert {
+                self.metrics.inc("slo_alerts_total", &labels);
+                warn!(
+                    slo = objective.name,
+                    long_burn_rate = format!("{:.2}", long.burn_rate),
+                    short_burn_rate = format!("{:.2}", short.burn_rate),
+                    threshold = self.config.burn_rate_alert,
+                    "SLO burn-rate alert - error budget burning fast"
+                );
+            }
+        }
+    }
+
+    pub fn spawn_evaluator(self: Arc<Self>, interval: Duration) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                self.evaluate();
+            }
+        });
+    }
+}
@@ -0,0 +1,164 @@
+//! Session limits (`--max-ads-per-list`, `--max-understanding-chars`,
+//! `--max-contexts-per-session`) and how each one is enforced. A limit is hard by
+//! default: an understanding over the limit or a Context past the cap fails the
+//! stream with INVALID_ARGUMENT, and a list (or partition) over the cap is cut to its
+//! best ads. A limit named in `--soft-limits` only warns: the request goes through
+//! untouched and the response carries the excess in `x-limit-warnings`, so an
+//! experiment can measure how often a limit would bite before enforcing it. Streams
+//! get the warnings in their trailers, unary calls in the response metadata. Either
+//! way every excess counts in `limit_exceeded_total{limit,enforcement}`.
+//!
+//! The understanding is measured after `--sanitize`, so with the `truncate` rule an
+//! understanding longer than `--sanitize-max-understanding-chars` never gets here.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use clap::ValueEnum;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::{Code, Status};
+use tracing::debug;
+
+use crate::ads::{AdsList, Context};
+use crate::metrics::Metrics;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Limit {
+    AdsPerList,
+    UnderstandingChars,
+    ContextsPerSession,
+}
+
+impl Limit {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Limit::AdsPerList => "ads_per_list",
+            Limit::UnderstandingChars => "understanding_chars",
+            Limit::ContextsPerSession => "contexts_per_session",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SessionLimits {
+    max_ads_per_list: usize,
+    max_understanding_chars: usize,
+    max_contexts_per_session: usize,
+    soft: Vec<Limit>,
+    metrics: Arc<Metrics>,
+}
+
+impl SessionLimits {
+    /// A max of 0 leaves that limit off
+    pub fn new(
+        max_ads_per_list: usize,
+        max_understanding_chars: usize,
+        max_contexts_per_session: usize,
+        soft: &[Limit],
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        SessionLimits { max_ads_per_list, max_understanding_chars, max_contexts_per_session, soft: soft.to_vec(), metrics }
+    }
+
+    fn max(&self, limit: Limit) -> usize {
+        match limit {
+            Limit::AdsPerList => self.max_ads_per_list,
+            Limit::UnderstandingChars => self.max_understanding_chars,
+            Limit::ContextsPerSession => self.max_contexts_per_session,
+        }
+    }
+
+    /// Check the understanding of an incoming Context; the error fails the stream
+    pub fn check_understanding(&self, context: &Context, warnings: &LimitWarnings) -> Result<(), Status> {
+        self.check(Limit::UnderstandingChars, context.understanding.chars().count(), warnings)
+    }
+
+    /// Check the number of Contexts a stream has sent, the latest included
+    pub fn check_contexts(&self, received: usize, warnings: &LimitWarnings) -> Result<(), Status> {
+        self.check(Limit::ContextsPerSession, received, warnings)
+    }
+
+    /// Cut the list and each of its partitions to the ads-per-list cap, keeping the
+    /// best ranked ads, or only warn about them
+    pub fn apply_ads_per_list(&self, ads_list: &mut AdsList, warnings: &LimitWarnings) {
+        let max = self.max_ads_per_list;
+        if max == 0 {
+            return;
+        }
+        let version = ads_list.version;
+        let pools = std::iter::once(&mut ads_list.ads)
+            .chain(ads_list.query_results.iter_mut().map(|partition| &mut partition.ads))
+            .chain(ads_list.placement_results.iter_mut().map(|partition| &mut partition.ads));
+        for ads in pools {
+            if ads.len() > max && self.exceeded(Limit::AdsPerList, ads.len(), warnings) {
+                debug!(version = version, ads = ads.len(), max = max, "Cut list to the ads-per-list limit");
+                ads.truncate(max);
+            }
+        }
+    }
+
+    fn check(&self, limit: Limit, observed: usize, warnings: &LimitWarnings) -> Result<(), Status> {
+        let max = self.max(limit);
+        if max == 0 || observed <= max || !self.exceeded(limit, observed, warnings) {
+            return Ok(());
+        }
+        Err(Status::invalid_argument(format!("{} {} is over the limit of {}", limit.name(), observed, max)))
+    }
+
+    // Count an excess and note it if the limit only warns; true when it is enforced
+    fn exceeded(&self, limit: Limit, observed: usize, warnings: &LimitWarnings) -> bool {
+        let soft = self.soft.contains(&limit);
+        let enforcement = if soft { "warn" } else { "enforce" };
+        self.metrics.inc("limit_exceeded_total", &[("limit", limit.name()), ("enforcement", enforcement)]);
+        if soft {
+            warnings.record(limit, observed, self.max(limit));
+        }
+        !soft
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Warning {
+    // Worst value seen
+    observed: usize,
+    max: usize,
+    count: u64,
+}
+
+/// Soft limits one session went over, worst excess per limit
+#[derive(Debug, Default)]
+pub struct LimitWarnings(Mutex<BTreeMap<Limit, Warning>>);
+
+impl LimitWarnings {
+    fn record(&self, limit: Limit, observed: usize, max: usize) {
+        let mut warnings = self.0.lock().unwrap();
+        let warning = warnings.entry(limit).or_insert(Warning { observed, max, count: 0 });
+        warning.observed = warning.observed.max(observed);
+        warning.count += 1;
+    }
+
+    /// `x-limit-warnings` value, one `<limit>;observed=<n>;max=<n>;count=<n>` entry
+    /// per limit, comma-separated; None when no soft limit was exceeded
+    pub fn metadata_value(&self) -> Option<MetadataValue<Ascii>> {
+        let warnings = self.0.lock().unwrap();
+        if warnings.is_empty() {
+            return None;
+        }
+        let value = warnings
+            .iter()
+            .map(|(limit, warning)| {
+                format!("{};observed={};max={};count={}", limit.name(), warning.observed, warning.max, warning.count)
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        value.parse().ok()
+    }
+
+    /// OK status ending a stream with the warnings in its trailers
+    pub fn trailer_status(&self) -> Option<Status> {
+        let value = self.metadata_value()?;
+        let mut status = Status::new(Code::Ok, "");
+        status.metadata_mut().insert(ads_proto::LIMIT_WARNINGS_METADATA_KEY, value);
+        Some(status)
+    }
+}
@@ -0,0 +1,146 @@
+//! Strict protocol mode: the server checks every session channel against the
+//! canonical playground contract and fails the stream, listing each violation,
+//! instead of answering whatever it receives. Conformance runs point a client
+//! implementation at a strict server to find out whether it follows the contract:
+//!
+//! 1. exactly two Contexts per channel;
+//! 2. the first without understanding, the second with a non-empty one;
+//! 3. both for the same query, queries, ASIN, request type and placements;
+//! 4. the second no later than the configured window after the first.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use tonic::Status;
+
+use crate::ads::Context;
+
+/// Trailing metadata listing the violated rules by code, comma-separated
+pub const VIOLATIONS_METADATA_KEY: &str = "x-protocol-violations";
+
+/// One way a channel broke the contract
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// A Context beyond the second
+    ExtraContext { context_number: u32 },
+    /// The channel ended after fewer than two Contexts
+    MissingContext { received: u32 },
+    /// The first Context already carried an understanding
+    EarlyUnderstanding,
+    /// The second Context came without understanding
+    MissingUnderstanding,
+    /// The second Context changed a field the first one fixed
+    ChangedField { field: &'static str },
+    /// The second Context arrived after the window
+    LateRefinement { gap: Duration, window: Duration },
+}
+
+impl Violation {
+    /// Stable code for metrics and the violations metadata
+    pub fn code(&self) -> &'static str {
+        match self {
+            Violation::ExtraContext { .. } => "extra_context",
+            Violation::MissingContext { .. } => "missing_context",
+            Violation::EarlyUnderstanding => "early_understanding",
+            Violation::MissingUnderstanding => "missing_understanding",
+            Violation::ChangedField { .. } => "changed_field",
+            Violation::LateRefinement { .. } => "late_refinement",
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::ExtraContext { context_number } => {
+                write!(f, "Context {} sent; the contract allows exactly 2", context_number)
+            }
+            Violation::MissingContext { received } => {
+                write!(f, "stream ended after {} Context(s); the contract requires 2", received)
+            }
+            Violation::EarlyUnderstanding => write!(f, "first Context carries an understanding; it must be empty"),
+            Violation::MissingUnderstanding => write!(f, "second Context has an empty understanding"),
+            Violation::ChangedField { field } => write!(f, "second Context changed {} from the first", field),
+            Violation::LateRefinement { gap, window } => write!(
+                f,
+                "second Context arrived {}ms after the first; the window is {}ms",
+                gap.as_millis(),
+                window.as_millis()
+            ),
+        }
+    }
+}
+
+/// Contract state of one session channel
+#[derive(Debug, Clone)]
+pub struct ContractChecker {
+    window: Duration,
+    received: u32,
+    first: Option<(Context, Instant)>,
+}
+
+impl ContractChecker {
+    pub fn new(window: Duration) -> Self {
+        ContractChecker { window, received: 0, first: None }
+    }
+
+    /// Check the next Context of the channel, received at `at`
+    pub fn check(&mut self, context: &Context, at: Instant) -> Vec<Violation> {
+        self.received += 1;
+        let mut violations = Vec::new();
+        match &self.first {
+            None => {
+                if !context.understanding.is_empty() {
+                    violations.push(Violation::EarlyUnderstanding);
+                }
+                self.first = Some((context.clone(), at));
+            }
+            Some(_) if self.received > 2 => violations.push(Violation::ExtraContext { context_number: self.received }),
+            Some((first, first_at)) => {
+                if context.understanding.is_empty() {
+                    violations.push(Violation::MissingUnderstanding);
+                }
+                let changed = [
+                    ("query", first.query != context.query),
+                    ("queries", first.queries != context.queries),
+                    ("asin_id", first.asin_id != context.asin_id),
+                    ("request_type", first.request_type != context.request_type),
+                    ("placements", first.placements != context.placements),
+                ];
+                violations.extend(
+                    changed.iter().filter(|(_, changed)| *changed).map(|(field, _)| Violation::ChangedField { field }),
+                );
+                let gap = at.saturating_duration_since(*first_at);
+                if gap > self.window {
+                    violations.push(Violation::LateRefinement { gap, window: self.window });
+                }
+            }
+        }
+        violations
+    }
+
+    /// Check the channel once the client half-closed the stream
+    pub fn finish(&self) -> Vec<Violation> {
+        if self.received < 2 {
+            vec![Violation::MissingContext { received: self.received }]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// `FailedPrecondition` naming every violation of `channel_id`, with their codes in
+/// the `x-protocol-violations` metadata for conformance tooling
+pub fn violation_status(channel_id: u32, violations: &[Violation]) -> Status {
+    let details: Vec<String> = violations.iter().map(|v| format!("{}: {}", v.code(), v)).collect();
+    let mut status = Status::failed_precondition(format!(
+        "protocol violation on channel {}: {}",
+        channel_id,
+        details.join("; ")
+    ));
+    let codes: Vec<&str> = violations.iter().map(Violation::code).collect();
+    if let Ok(value) = codes.join(",").parse() {
+        status.metadata_mut().insert(VIOLATIONS_METADATA_KEY, value);
+    }
+    status
+}
@@ -0,0 +1,319 @@
+//! Stub mode (`--stub fixtures.yaml`): serve scripted AdsLists instead of generating
+//! them, for testing client behaviour against exact content and timing and for
+//! demos that need stable output. A session's first Context picks the first
+//! fixture whose `query` (and `asin_id`, if given) match it; a fixture naming
+//! neither matches every Context. Its steps are then played in order, each
+//! `delay_ms` after the previous one (the first after the Context arrived): an
+//! AdsList, or an error status that ends the stream. A Context no fixture matches
+//! fails with NOT_FOUND.
+//!
+//! ```yaml
+//! fixtures:
+//!   - query: running shoes
+//!     steps:
+//!       - version: 1
+//!         delay_ms: 20
+//!         ads:
+//!           - { ad_id: stub_1, asin_id: B000000001, score: 0.9, advertiser_id: adv_1 }
+//!       - version: 2
+//!         delay_ms: 400
+//!         ads: []
+//!       - error: { code: unavailable, message: scripted failure }
+//! ```
+//!
+//! The server-streaming RPC plays the same script. The unary RPC answers with the
+//! script's highest version once the delays up to it have passed.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ads_common::{Error, Result};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::{Server, ServerTlsConfig};
+use tonic::{Code, Request, Response, Status, Streaming};
+use tracing::{debug, info, warn};
+
+use crate::ads::ads_service_server::{AdsService, AdsServiceServer};
+use crate::ads::{Ad, AdsList, Context, HandshakeRequest, HandshakeResponse, SessionFeedback, SessionFeedbackResponse};
+use crate::announce;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FixtureFile {
+    fixtures: Vec<Fixture>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fixture {
+    #[serde(default)]
+    pub query: Option<String>,
+    #[serde(default)]
+    pub asin_id: Option<String>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    #[serde(default)]
+    pub delay_ms: u64,
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub ads: Vec<StubAd>,
+    /// Ends the stream with this status instead of sending an AdsList
+    #[serde(default)]
+    pub error: Option<StubError>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StubAd {
+    pub ad_id: String,
+    #[serde(default)]
+    pub asin_id: String,
+    #[serde(default)]
+    pub score: f64,
+    #[serde(default)]
+    pub advertiser_id: String,
+    #[serde(default = "default_category")]
+    pub category: String,
+}
+
+fn default_category() -> String {
+    "sponsored_products".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StubError {
+    /// gRPC status code name, e.g. `unavailable` or `deadline_exceeded`
+    pub code: String,
+    #[serde(default)]
+    pub message: String,
+}
+
+impl Fixture {
+    fn matches(&self, context: &Context) -> bool {
+        self.query.as_ref().is_none_or(|query| *query == context.query)
+            && self.asin_id.as_ref().is_none_or(|asin_id| *asin_id == context.asin_id)
+    }
+
+    fn describe(&self) -> String {
+        match (&self.query, &self.asin_id) {
+            (None, None) => "*".to_string(),
+            (query, asin_id) => format!(
+                "query={:?} asin_id={:?}",
+                query.as_deref().unwrap_or("*"),
+                asin_id.as_deref().unwrap_or("*")
+            ),
+        }
+    }
+}
+
+impl Step {
+    fn ads_list(&self, channel_id: u32) -> AdsList {
+        AdsList {
+            ads: self
+                .ads
+                .iter()
+                .map(|ad| Ad {
+                    asin_id: ad.asin_id.clone(),
+                    ad_id: ad.ad_id.clone(),
+                    score: ad.score,
+                    advertiser_id: ad.advertiser_id.clone(),
+                    category: ad.category.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+            version: self.version,
+            channel_id,
+            server_sent_unix_us: ads_proto::unix_us(),
+            ..Default::default()
+        }
+    }
+
+    fn status(&self) -> Option<Status> {
+        self.error.as_ref().map(|error| Status::new(code(&error.code).unwrap_or(Code::Unknown), error.message.clone()))
+    }
+}
+
+/// `unavailable`, `DEADLINE_EXCEEDED`, `resource-exhausted`, ...
+fn code(name: &str) -> Option<Code> {
+    let wanted = name.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_ascii_lowercase();
+    (0..=16).map(Code::from_i32).find(|code| format!("{:?}", code).to_ascii_lowercase() == wanted)
+}
+
+/// Read and check a fixture file
+pub fn load(path: &Path) -> Result<Vec<Fixture>> {
+    let text = std::fs::read_to_string(path)?;
+    let file: FixtureFile = serde_yaml::from_str(&text)
+        .map_err(|e| Error::config(format!("stub fixtures {}: {}", path.display(), e)))?;
+    let mut problems = Vec::new();
+    for (i, fixture) in file.fixtures.iter().enumerate() {
+        if fixture.steps.is_empty() {
+            problems.push(format!("fixtures[{}]: no steps", i));
+        }
+        for (j, step) in fixture.steps.iter().enumerate() {
+            match &step.error {
+                Some(error) if code(&error.code).is_none() => {
+                    problems.push(format!("fixtures[{}].steps[{}]: unknown status code {:?}", i, j, error.code));
+                }
+                Some(_) if !step.ads.is_empty() || step.version != 0 => {
+                    problems.push(format!("fixtures[{}].steps[{}]: an error step has no version or ads", i, j));
+                }
+                None if step.version == 0 => problems.push(format!("fixtures[{}].steps[{}]: version is required", i, j)),
+                _ => {}
+            }
+        }
+    }
+    if !problems.is_empty() {
+        return Err(Error::config(format!("stub fixtures {}: {}", path.display(), problems.join("; "))));
+    }
+    Ok(file.fixtures)
+}
+
+/// AdsService answering from fixtures
+#[derive(Debug, Clone)]
+pub struct StubAdsService {
+    fixtures: Arc<Vec<Fixture>>,
+}
+
+impl StubAdsService {
+    pub fn new(fixtures: Vec<Fixture>) -> Self {
+        StubAdsService { fixtures: Arc::new(fixtures) }
+    }
+
+    fn fixture(&self, context: &Context) -> Result<Fixture, Status> {
+        match self.fixtures.iter().find(|fixture| fixture.matches(context)) {
+            Some(fixture) => {
+                debug!(query = %context.query, asin_id = %context.asin_id, fixture = %fixture.describe(), "Playing stub fixture");
+                Ok(fixture.clone())
+            }
+            None => {
+                warn!(query = %context.query, asin_id = %context.asin_id, "No stub fixture matches");
+                Err(Status::not_found(format!(
+                    "no stub fixture matches query {:?} asin_id {:?}",
+                    context.query, context.asin_id
+                )))
+            }
+        }
+    }
+
+    // Send the fixture's steps on a new stream, in the background
+    fn play(fixture: Fixture, channel_id: u32) -> ReceiverStream<Result<AdsList, Status>> {
+        let (tx, rx) = mpsc::channel(fixture.steps.len().max(1));
+        tokio::spawn(async move {
+            for step in &fixture.steps {
+                sleep(Duration::from_millis(step.delay_ms)).await;
+                let item = match step.status() {
+                    Some(status) => Err(status),
+                    None => Ok(step.ads_list(channel_id)),
+                };
+                let last = item.is_err();
+                if tx.send(item).await.is_err() || last {
+                    return;
+                }
+            }
+        });
+        ReceiverStream::new(rx)
+    }
+}
+
+type AdsListStream = Pin<Box<dyn Stream<Item = Result<AdsList, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl AdsService for StubAdsService {
+    type GetAdsStream = AdsListStream;
+
+    async fn get_ads(&self, request: Request<Streaming<Context>>) -> Result<Response<Self::GetAdsStream>, Status> {
+        let mut inbound = request.into_inner();
+        let context = match inbound.next().await {
+            Some(context) => context?,
+            None => return Err(Status::invalid_argument("stream closed before the first Context")),
+        };
+        let stream = Self::play(self.fixture(&context)?, context.channel_id);
+        // Later Contexts do not change the script, but are read so the client is not held up
+        tokio::spawn(async move { while let Some(Ok(_)) = inbound.next().await {} });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    type GetAdsServerStreamingStream = AdsListStream;
+
+    async fn get_ads_server_streaming(
+        &self,
+        request: Request<Context>,
+    ) -> Result<Response<Self::GetAdsServerStreamingStream>, Status> {
+        let context = request.into_inner();
+        let stream = Self::play(self.fixture(&context)?, context.channel_id);
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_ads_unary(&self, request: Request<Context>) -> Result<Response<AdsList>, Status> {
+        let context = request.into_inner();
+        let fixture = self.fixture(&context)?;
+        let last = fixture
+            .steps
+            .iter()
+            .enumerate()
+            .filter(|(_, step)| step.error.is_none())
+            .max_by_key(|(_, step)| step.version)
+            .map_or(fixture.steps.len() - 1, |(i, _)| i);
+        // Play the timing up to the answer, stopping at an earlier error
+        for step in &fixture.steps[..=last] {
+            sleep(Duration::from_millis(step.delay_ms)).await;
+            if let Some(status) = step.status() {
+                return Err(status);
+            }
+        }
+        Ok(Response::new(fixture.steps[last].ads_list(context.channel_id)))
+    }
+
+    async fn handshake(&self, request: Request<HandshakeRequest>) -> Result<Response<HandshakeResponse>, Status> {
+        let server_receive_unix_us = ads_proto::unix_us();
+        Ok(Response::new(HandshakeResponse {
+            client_send_unix_us: request.into_inner().client_send_unix_us,
+            server_receive_unix_us,
+            server_send_unix_us: ads_proto::unix_us(),
+        }))
+    }
+
+    // Fixtures are not generated, so there is no work to account for
+    async fn report_feedback(
+        &self,
+        _request: Request<SessionFeedback>,
+    ) -> Result<Response<SessionFeedbackResponse>, Status> {
+        Ok(Response::new(SessionFeedbackResponse { matched: false }))
+    }
+}
+
+/// Serve the fixtures of `path` on `addr` until Ctrl-C or SIGTERM
+pub async fn serve(path: &Path, addr: SocketAddr, port_file: Option<&Path>, tls: Option<ServerTlsConfig>) -> Result<()> {
+    let fixtures = load(path)?;
+    info!(path = %path.display(), fixtures = fixtures.len(), "Stub mode: serving scripted AdsLists");
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+    announce::announce_port(local_addr, port_file)?;
+    info!("Starting Rust Ads stub server on {}", local_addr);
+    let mut server = Server::builder();
+    if let Some(tls) = tls {
+        server = server.tls_config(tls)?;
+    }
+    server
+        .accept_http1(true)
+        .add_service(tonic_web::enable(AdsServiceServer::new(StubAdsService::new(fixtures))))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), crate::shutdown::signal())
+        .await?;
+    if let Some(path) = port_file {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(())
+}
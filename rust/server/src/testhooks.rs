@@ -0,0 +1,84 @@
+use std::time::Duration;
+use tonic::{metadata::MetadataMap, Status};
+
+use crate::ads::AdsList;
+
+/// Metadata key integration tests use to select a scripted server behavior
+pub const TEST_CASE_METADATA_KEY: &str = "x-test-case";
+
+/// Predefined, deterministic server behaviors selectable per stream via the
+/// `x-test-case` metadata value. Only honored when test hooks are enabled in config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestCase {
+    /// Send version 1, then fail the stream instead of sending later versions
+    FailAfterVersion1,
+    /// Hold version 2 back for 200ms before sending it
+    DelayVersion2,
+    /// Send every version with an empty ads list
+    EmptyLists,
+}
+
+impl TestCase {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "fail-after-v1" => Some(TestCase::FailAfterVersion1),
+            "delay-v2" => Some(TestCase::DelayVersion2),
+            "empty-lists" => Some(TestCase::EmptyLists),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TestCase::FailAfterVersion1 => "fail-after-v1",
+            TestCase::DelayVersion2 => "delay-v2",
+            TestCase::EmptyLists => "empty-lists",
+        }
+    }
+
+    /// Read the requested test case from request metadata, rejecting unknown names
+    pub fn from_metadata(metadata: &MetadataMap) -> Result<Option<Self>, Status> {
+        let Some(value) = metadata.get(TEST_CASE_METADATA_KEY) else {
+            return Ok(None);
+        };
+        let value = value
+            .to_str()
+            .map_err(|_| Status::invalid_argument("x-test-case must be ASCII"))?;
+        TestCase::parse(value)
+            .map(Some)
+            .ok_or_else(|| Status::invalid_argument(format!("unknown x-test-case: {}", value)))
+    }
+
+    /// Error to send in place of `version`, if this test case fails the stream there
+    pub fn failure_for(&self, version: u32) -> Option<Status> {
+        match self {
+            TestCase::FailAfterVersion1 if version > 1 => Some(Status::internal(format!(
+                "test hook {}: failing before version {}",
+                self.name(),
+                version
+            ))),
+            _ => None,
+        }
+    }
+
+    /// Extra delay to apply before sending `version`
+    pub fn delay_for(&self, version: u32) -> Option<Duration> {
+        match self {
+            TestCase::DelayVersion2 if version == 2 => Some(Duration::from_millis(200)),
+            _ => None,
+        }
+    }
+
+    /// Rewrite a generated AdsList according to the test case
+    pub fn apply(&self, ads_list: &mut AdsList) {
+        if *self == TestCase::EmptyLists {
+            ads_list.ads.clear();
+            for partition in &mut ads_list.query_results {
+                partition.ads.clear();
+            }
+            for placement in &mut ads_list.placement_results {
+                placement.ads.clear();
+            }
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! Locale-aware query normalization (`--normalize-text`). The query, the batched
+//! queries and the understanding of every incoming Context are rewritten before
+//! matching so that differently typed forms of one query rank the same: Unicode
+//! NFKC (full-width and compatibility forms folded), lowercasing with the locale's
+//! case rules (Turkish and Azerbaijani dotted and dotless I), then tokenization on
+//! anything but letters and digits. Han characters, which Chinese and Japanese do
+//! not separate with spaces, become one token each, and a change of script starts a
+//! new token. Languages listed in `--stem` additionally lose their common
+//! inflectional suffixes. The locale comes from the Context's `locale` field and
+//! falls back to `--default-locale`. Every changed field counts in
+//! `text_normalized_total{language,field}`.
+
+use std::sync::Arc;
+
+use clap::ValueEnum;
+use tracing::debug;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::ads::Context;
+use crate::metrics::Metrics;
+
+/// Stem length a suffix may not cut below, so short words are left alone
+const MIN_STEM_CHARS: usize = 3;
+
+/// Languages with a light suffix stemmer
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StemLanguage {
+    En,
+    De,
+}
+
+/// Primary language of a locale, as far as normalization tells languages apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    English,
+    German,
+    Turkish,
+    Chinese,
+    Japanese,
+    Other,
+}
+
+impl Language {
+    /// Language of a BCP 47 tag such as "de-DE" or "tr_TR"; unknown tags are `Other`
+    pub fn from_locale(locale: &str) -> Self {
+        let primary = locale.split(['-', '_']).next().unwrap_or("").to_ascii_lowercase();
+        match primary.as_str() {
+            "en" => Language::English,
+            "de" => Language::German,
+            "tr" | "az" => Language::Turkish,
+            "zh" => Language::Chinese,
+            "ja" => Language::Japanese,
+            _ => Language::Other,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+            Language::Turkish => "tr",
+            Language::Chinese => "zh",
+            Language::Japanese => "ja",
+            Language::Other => "other",
+        }
+    }
+
+    fn stemmer(&self) -> Option<StemLanguage> {
+        match self {
+            Language::English => Some(StemLanguage::En),
+            Language::German => Some(StemLanguage::De),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct QueryNormalizer {
+    default_language: Language,
+    stem: Vec<StemLanguage>,
+    metrics: Arc<Metrics>,
+}
+
+impl QueryNormalizer {
+    /// None unless enabled, so servers without normalization skip the pass entirely
+    pub fn new(enabled: bool, default_locale: &str, stem: &[StemLanguage], metrics: Arc<Metrics>) -> Option<Self> {
+        enabled.then(|| QueryNormalizer {
+            default_language: Language::from_locale(default_locale),
+            stem: stem.to_vec(),
+            metrics,
+        })
+    }
+
+    /// Normalize the Context's text fields in place for its locale. Returns the
+    /// fields that changed, as `text:<field>`, each once.
+    pub fn normalize(&self, context: &mut Context) -> Vec<String> {
+        let language =
+            if context.locale.is_empty() { self.default_language } else { Language::from_locale(&context.locale) };
+        let stem = language.stemmer().filter(|stemmer| self.stem.contains(stemmer));
+        let mut changed = self.rewrite(&mut context.query, language, stem, "query");
+        for query in &mut context.queries {
+            changed += self.rewrite(query, language, stem, "query");
+        }
+        let query_changed = changed;
+        changed += self.rewrite(&mut context.understanding, language, stem, "understanding");
+        if changed > 0 {
+            debug!(language = language.name(), fields = changed, "Normalized Context text");
+        }
+        let mut applied = Vec::new();
+        if query_changed > 0 {
+            applied.push("text:query".to_string());
+        }
+        if changed > query_changed {
+            applied.push("text:understanding".to_string());
+        }
+        applied
+    }
+
+    fn rewrite(&self, text: &mut String, language: Language, stem: Option<StemLanguage>, field: &str) -> usize {
+        let normalized = normalize_text(text, language, stem);
+        // Text of nothing but punctuation is kept, so validation still sees what was sent
+        if normalized.is_empty() || normalized == *text {
+            return 0;
+        }
+        self.metrics.inc("text_normalized_total", &[("language", language.name()), ("field", field)]);
+        *text = normalized;
+        1
+    }
+}
+
+/// NFKC, locale-aware lowercasing, tokenization and optional stemming; tokens are
+/// joined with single spaces
+pub fn normalize_text(text: &str, language: Language, stem: Option<StemLanguage>) -> String {
+    let folded: String = text.nfkc().collect();
+    let lowered = lowercase(&folded, language);
+    tokenize(&lowered, language)
+        .into_iter()
+        .map(|token| match stem {
+            Some(stemmer) => stem_token(&token, stemmer),
+            None => token,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn lowercase(text: &str, language: Language) -> String {
+    if language != Language::Turkish {
+        return text.to_lowercase();
+    }
+    // Turkish I lowercases to dotless ı and İ to i; the generic mapping would turn
+    // İ into "i̇" (i plus a combining dot) and I into a dotted i
+    let mut lowered = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            'I' => lowered.push('ı'),
+            'İ' => lowered.push('i'),
+            c => lowered.extend(c.to_lowercase()),
+        }
+    }
+    lowered
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Han,
+    Kana,
+    Hangul,
+    Other,
+}
+
+fn script(c: char) -> Script {
+    match c as u32 {
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FA1F => Script::Han,
+        0x3040..=0x30FF | 0x31F0..=0x31FF => Script::Kana,
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Script::Hangul,
+        _ => Script::Other,
+    }
+}
+
+fn tokenize(text: &str, language: Language) -> Vec<String> {
+    let split_han = matches!(language, Language::Chinese | Language::Japanese);
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut current_script = Script::Other;
+    for c in text.chars() {
+        // Combining marks stay with the letter they modify
+        if !c.is_alphanumeric() && (!is_mark(c) || current.is_empty()) {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        let c_script = script(c);
+        let boundary = c_script != current_script || (split_han && c_script == Script::Han);
+        if boundary && !current.is_empty() && !is_mark(c) {
+            tokens.push(std::mem::take(&mut current));
+        }
+        if !is_mark(c) {
+            current_script = c_script;
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+// Combining diacritics, dakuten and other marks NFKC leaves uncomposed
+fn is_mark(c: char) -> bool {
+    matches!(c as u32, 0x0300..=0x036F | 0x1AB0..=0x1AFF | 0x20D0..=0x20FF | 0x3099..=0x309A)
+}
+
+fn stem_token(token: &str, stemmer: StemLanguage) -> String {
+    if token.chars().any(|c| c.is_numeric()) {
+        return token.to_string();
+    }
+    match stemmer {
+        StemLanguage::En => stem_english(token),
+        StemLanguage::De => stem_german(token),
+    }
+}
+
+// Strip `suffix` when what remains is still a plausible stem
+fn strip<'a>(token: &'a str, suffix: &str) -> Option<&'a str> {
+    token.strip_suffix(suffix).filter(|stem| stem.chars().count() >= MIN_STEM_CHARS)
+}
+
+/// Light English stemmer: plurals, -ing and -ed
+fn stem_english(token: &str) -> String {
+    if let Some(stem) = strip(token, "ies") {
+        return format!("{}y", stem);
+    }
+    for suffix in ["sses", "shes", "ches", "xes", "zes"] {
+        if let Some(stem) = strip(token, suffix) {
+            return format!("{}{}", stem, &suffix[..suffix.len() - 2]);
+        }
+    }
+    if token.ends_with('s') && !token.ends_with("ss") && !token.ends_with("us") && !token.ends_with("is") {
+        if let Some(stem) = strip(token, "s") {
+            return stem.to_string();
+        }
+    }
+    for suffix in ["ing", "ed"] {
+        if let Some(stem) = strip(token, suffix) {
+            return stem.to_string();
+        }
+    }
+    token.to_string()
+}
+
+/// Light German stemmer: the common noun and adjective endings, longest first
+fn stem_german(token: &str) -> String {
+    for suffix in ["ern", "em", "en", "er", "es", "e", "n", "s"] {
+        if let Some(stem) = strip(token, suffix) {
+            return stem.to_string();
+        }
+    }
+    token.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(cases: &[(&str, Language, Option<StemLanguage>, &str)]) {
+        for &(input, language, stem, expected) in cases {
+            assert_eq!(normalize_text(input, language, stem), expected, "{} normalization of {:?}", language.name(), input);
+        }
+    }
+
+    #[test]
+    fn full_width_and_punctuation_fold_to_plain_tokens() {
+        check(&[
+            ("Ｃｏｆｆｅｅ　Ｍａｋｅｒ", Language::English, None, "coffee maker"),
+            ("Coffee-Maker, 12 Cups!", Language::English, None, "coffee maker 12 cups"),
+        ]);
+    }
+
+    #[test]
+    fn stemmers_strip_common_suffixes() {
+        check(&[
+            ("coffee makers brewing", Language::English, Some(StemLanguage::En), "coffee maker brew"),
+            ("Kaffeemaschinen Filtern", Language::German, Some(StemLanguage::De), "kaffeemaschin filt"),
+        ]);
+    }
+
+    #[test]
+    fn german_keeps_umlauts_and_sharp_s() {
+        check(&[("KAFFEEMASCHINE für Straße", Language::German, None, "kaffeemaschine für straße")]);
+    }
+
+    #[test]
+    fn turkish_lowercases_dotted_and_dotless_i() {
+        check(&[
+            ("İSTANBUL KAHVE MAKİNESİ", Language::Turkish, None, "istanbul kahve makinesi"),
+            ("DIŞ", Language::Turkish, None, "dış"),
+        ]);
+    }
+
+    #[test]
+    fn han_characters_are_one_token_each() {
+        check(&[
+            ("咖啡机", Language::Chinese, None, "咖 啡 机"),
+            ("ｺｰﾋｰメーカー 珈琲", Language::Japanese, None, "コーヒーメーカー 珈 琲"),
+        ]);
+    }
+
+    #[test]
+    fn decomposed_and_precomposed_accents_meet() {
+        assert_eq!(normalize_text("cafe\u{301}", Language::Other, None), normalize_text("Café", Language::Other, None));
+    }
+}
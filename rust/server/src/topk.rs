@@ -0,0 +1,137 @@
+//! Correctness tests and benchmark of top-K candidate selection
+//! (`ads_proto::score::top_k_ads`, used with `--ranking-top-k`) against the full sort
+//! it replaces. `ads-server bench-ranking` times both on a synthetic pool the size
+//! of a large catalog.
+
+use std::time::{Duration, Instant};
+
+use ads_common::{Error, Result};
+use ads_proto::score::{self, TieBreak};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::ads::Ad;
+use crate::config::BenchRankingArgs;
+
+/// `n` candidates with scores rounded to three decimals, so large pools are full of
+/// ties and the tie-break decides much of the order
+pub fn synthetic_candidates(n: usize, seed: u64) -> Vec<Ad> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..n)
+        .map(|i| Ad {
+            asin_id: format!("B{:09}", i),
+            ad_id: format!("cand_{}", i),
+            score: (rng.gen_range(0.0..=1.0f64) * 1000.0).round() / 1000.0,
+            advertiser_id: format!("adv_{}", i % 4 + 1),
+            category: "sponsored_products".to_string(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+// Stand-in for catalog revisions: a few candidates share each recency
+fn recency(ad: &Ad) -> u64 {
+    ad.ad_id.bytes().fold(0u64, |acc, b| acc.wrapping_mul(31).wrapping_add(b as u64)) % 7
+}
+
+fn full_sort(mut ads: Vec<Ad>, k: usize, tie_break: TieBreak, seed: u64) -> Vec<Ad> {
+    score::sort_ads(&mut ads, tie_break, seed, recency);
+    ads.truncate(k);
+    ads
+}
+
+/// `bench-ranking`: time the full sort and top-K selection over the same pools and
+/// fail if they ever disagree
+pub fn run(args: &BenchRankingArgs) -> Result<()> {
+    let tie_break: TieBreak = args.tie_break.into();
+    let mut sort_times = Vec::with_capacity(args.iterations);
+    let mut top_k_times = Vec::with_capacity(args.iterations);
+    for iteration in 0..args.iterations {
+        let pool = synthetic_candidates(args.candidates, args.seed.wrapping_add(iteration as u64));
+
+        let input = pool.clone();
+        let start = Instant::now();
+        let expected = full_sort(input, args.top_k, tie_break, args.seed);
+        sort_times.push(start.elapsed());
+
+        let start = Instant::now();
+        let selected = score::top_k_ads(pool, args.top_k, tie_break, args.seed, recency);
+        top_k_times.push(start.elapsed());
+
+        if selected != expected {
+            return Err(Error::invalid(format!(
+                "iteration {}: top-{} selection differs from the full sort",
+                iteration, args.top_k
+            )));
+        }
+    }
+    let (sort_p50, sort_mean) = summarize(&mut sort_times);
+    let (top_k_p50, top_k_mean) = summarize(&mut top_k_times);
+    println!(
+        "{} candidates, k={}, {:?} tie-break, {} iterations",
+        args.candidates, args.top_k, tie_break, args.iterations
+    );
+    println!("{:<10} {:>10} {:>10}", "method", "p50_ms", "mean_ms");
+    println!("{:<10} {:>10.3} {:>10.3}", "full-sort", ms(sort_p50), ms(sort_mean));
+    println!("{:<10} {:>10.3} {:>10.3}", "top-k", ms(top_k_p50), ms(top_k_mean));
+    if !top_k_mean.is_zero() {
+        println!("speedup (mean): {:.2}x", sort_mean.as_secs_f64() / top_k_mean.as_secs_f64());
+    }
+    println!("outputs identical in every iteration");
+    Ok(())
+}
+
+fn summarize(times: &mut [Duration]) -> (Duration, Duration) {
+    if times.is_empty() {
+        return (Duration::ZERO, Duration::ZERO);
+    }
+    times.sort();
+    let mean = times.iter().sum::<Duration>() / times.len() as u32;
+    (times[times.len() / 2], mean)
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIE_BREAKS: [TieBreak; 3] = [TieBreak::AdId, TieBreak::Recency, TieBreak::Seeded];
+
+    /// Top-K selection must return exactly the first `k` ads of the full sort, for
+    /// every tie-break policy
+    #[test]
+    fn top_k_matches_full_sort() {
+        let pool = synthetic_candidates(2000, 7);
+        for tie_break in TIE_BREAKS {
+            for k in [1, 10, 500] {
+                let expected = full_sort(pool.clone(), k, tie_break, 42);
+                let selected = score::top_k_ads(pool.clone(), k, tie_break, 42, recency);
+                assert_eq!(selected, expected, "top-{} with {:?} tie-break", k, tie_break);
+            }
+        }
+    }
+
+    #[test]
+    fn top_k_at_or_above_pool_size_keeps_every_ad() {
+        let pool = synthetic_candidates(200, 11);
+        for tie_break in TIE_BREAKS {
+            for k in [pool.len(), pool.len() + 1] {
+                let selected = score::top_k_ads(pool.clone(), k, tie_break, 42, recency);
+                assert_eq!(selected.len(), pool.len());
+                assert_eq!(selected, full_sort(pool.clone(), k, tie_break, 42), "top-{} with {:?} tie-break", k, tie_break);
+            }
+        }
+    }
+
+    #[test]
+    fn synthetic_pools_are_full_of_ties() {
+        let pool = synthetic_candidates(2000, 7);
+        let mut scores: Vec<u64> = pool.iter().map(|ad| (ad.score * 1000.0).round() as u64).collect();
+        scores.sort_unstable();
+        scores.dedup();
+        assert!(scores.len() < pool.len());
+    }
+}
@@ -0,0 +1,271 @@
+//! `bench-transport`: run one workload over loopback TCP, a Unix domain socket and
+//! an in-process channel (an in-memory duplex pipe, no socket at all), and compare
+//! latency and throughput. The server side is the stub service (`stub.rs`)
+//! answering from a fixture without delays, so the numbers measure transport and
+//! gRPC overhead rather than generation.
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ads_common::{Error, Result};
+use clap::ValueEnum;
+use tokio::io::DuplexStream;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::{Channel, Endpoint, Server, Uri};
+use tonic::Status;
+
+use crate::ads::ads_service_client::AdsServiceClient;
+use crate::ads::ads_service_server::{AdsService, AdsServiceServer};
+use crate::ads::Context;
+use crate::config::BenchTransportArgs;
+use crate::stub::{self, Fixture, Step, StubAd, StubAdsService};
+
+/// Buffer of each direction of an in-process connection
+const IN_PROCESS_BUFFER: usize = 64 * 1024;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Tcp,
+    Uds,
+    InProcess,
+}
+
+impl Transport {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transport::Tcp => "tcp",
+            Transport::Uds => "uds",
+            Transport::InProcess => "in-process",
+        }
+    }
+}
+
+/// RPC each benchmark session makes
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchRpc {
+    /// GetAds: the initial and refined Contexts, then every version
+    Bidi,
+    /// GetAdsUnary: one Context, the final version
+    Unary,
+}
+
+/// Results of one transport
+#[derive(Debug)]
+struct Measurement {
+    transport: Transport,
+    latencies: Vec<Duration>,
+    errors: usize,
+    elapsed: Duration,
+}
+
+impl Measurement {
+    fn throughput(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    fn quantile_ms(&self, q: f64) -> f64 {
+        if self.latencies.is_empty() {
+            return 0.0;
+        }
+        let index = ((self.latencies.len() - 1) as f64 * q).round() as usize;
+        self.latencies[index].as_secs_f64() * 1000.0
+    }
+
+    fn mean_ms(&self) -> f64 {
+        if self.latencies.is_empty() {
+            return 0.0;
+        }
+        self.latencies.iter().sum::<Duration>().as_secs_f64() * 1000.0 / self.latencies.len() as f64
+    }
+}
+
+pub async fn run(args: &BenchTransportArgs) -> Result<()> {
+    if args.transports.is_empty() || args.sessions == 0 {
+        return Err(Error::invalid("bench-transport needs at least one transport and one session"));
+    }
+    let fixtures = match &args.fixtures {
+        Some(path) => stub::load(path)?,
+        None => vec![bench_fixture(args.ads)],
+    };
+    let context = Context {
+        query: args.query.clone(),
+        asin_id: "B000000001".to_string(),
+        understanding: "benchmark understanding".to_string(),
+        ..Default::default()
+    };
+
+    let mut measurements = Vec::with_capacity(args.transports.len());
+    for &transport in &args.transports {
+        let (channel, stop) = start(transport, StubAdsService::new(fixtures.clone())).await?;
+        measure(transport, &channel, args.rpc, &context, args.warm_up, args.concurrency).await;
+        let measurement = measure(transport, &channel, args.rpc, &context, args.sessions, args.concurrency).await;
+        let _ = stop.send(());
+        if transport == Transport::Uds {
+            let _ = std::fs::remove_file(uds_path());
+        }
+        measurements.push(measurement);
+    }
+
+    println!(
+        "{} {:?} sessions per transport, concurrency {}, {} warm-up sessions",
+        args.sessions, args.rpc, args.concurrency, args.warm_up
+    );
+    println!(
+        "{:<11} {:>8} {:>7} {:>9} {:>9} {:>9} {:>11} {:>9}",
+        "transport", "ok", "errors", "mean_ms", "p50_ms", "p99_ms", "sessions/s", "relative"
+    );
+    let baseline = measurements[0].throughput();
+    for measurement in &measurements {
+        println!(
+            "{:<11} {:>8} {:>7} {:>9.3} {:>9.3} {:>9.3} {:>11.0} {:>8.2}x",
+            measurement.transport.name(),
+            measurement.latencies.len(),
+            measurement.errors,
+            measurement.mean_ms(),
+            measurement.quantile_ms(0.5),
+            measurement.quantile_ms(0.99),
+            measurement.throughput(),
+            measurement.throughput() / baseline.max(f64::EPSILON),
+        );
+    }
+    Ok(())
+}
+
+// Three versions of `ads` ads each, sent as soon as they are due
+fn bench_fixture(ads: usize) -> Fixture {
+    let list: Vec<StubAd> = (0..ads)
+        .map(|i| StubAd {
+            ad_id: format!("bench_{}", i),
+            asin_id: format!("B{:09}", i),
+            score: 1.0 - i as f64 / ads as f64,
+            advertiser_id: format!("adv_{}", i % 4 + 1),
+            category: "sponsored_products".to_string(),
+        })
+        .collect();
+    Fixture {
+        query: None,
+        asin_id: None,
+        steps: (1..=3).map(|version| Step { delay_ms: 0, version, ads: list.clone(), error: None }).collect(),
+    }
+}
+
+fn uds_path() -> PathBuf {
+    std::env::temp_dir().join(format!("ads-bench-{}.sock", std::process::id()))
+}
+
+/// Serve `service` on `transport` and connect a channel to it; the sender stops the server
+pub async fn start<S: AdsService>(transport: Transport, service: S) -> Result<(Channel, oneshot::Sender<()>)> {
+    let (stop, stopped) = oneshot::channel::<()>();
+    let shutdown = async move {
+        let _ = stopped.await;
+    };
+    let router = Server::builder().add_service(AdsServiceServer::new(service));
+    let channel = match transport {
+        Transport::Tcp => {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            tokio::spawn(router.serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown));
+            Endpoint::from_shared(format!("http://{}", addr))?.tcp_nodelay(true).connect().await?
+        }
+        #[cfg(unix)]
+        Transport::Uds => {
+            let path = uds_path();
+            let _ = std::fs::remove_file(&path);
+            let listener = tokio::net::UnixListener::bind(&path)?;
+            tokio::spawn(
+                router.serve_with_incoming_shutdown(tokio_stream::wrappers::UnixListenerStream::new(listener), shutdown),
+            );
+            Endpoint::from_static("http://uds.invalid")
+                .connect_with_connector(tower::service_fn(move |_: Uri| tokio::net::UnixStream::connect(path.clone())))
+                .await?
+        }
+        #[cfg(not(unix))]
+        Transport::Uds => return Err(Error::config("Unix domain sockets need a Unix platform")),
+        Transport::InProcess => {
+            // Every connection the channel opens is a fresh duplex pipe, its server
+            // half handed to the server as an accepted connection
+            let (connections, incoming) = mpsc::channel::<io::Result<DuplexStream>>(16);
+            tokio::spawn(router.serve_with_incoming_shutdown(ReceiverStream::new(incoming), shutdown));
+            Endpoint::from_static("http://in-process.invalid")
+                .connect_with_connector(tower::service_fn(move |_: Uri| {
+                    let connections = connections.clone();
+                    async move {
+                        let (client, server) = tokio::io::duplex(IN_PROCESS_BUFFER);
+                        connections
+                            .send(Ok(server))
+                            .await
+                            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "in-process server stopped"))?;
+                        Ok::<_, io::Error>(client)
+                    }
+                }))
+                .await?
+        }
+    };
+    Ok((channel, stop))
+}
+
+// Run `sessions` sessions over `concurrency` workers sharing the channel
+async fn measure(
+    transport: Transport,
+    channel: &Channel,
+    rpc: BenchRpc,
+    context: &Context,
+    sessions: usize,
+    concurrency: usize,
+) -> Measurement {
+    let remaining = Arc::new(AtomicUsize::new(sessions));
+    let start = Instant::now();
+    let mut workers = JoinSet::new();
+    for _ in 0..concurrency.max(1) {
+        let mut client = AdsServiceClient::new(channel.clone());
+        let remaining = remaining.clone();
+        let context = context.clone();
+        workers.spawn(async move {
+            let mut latencies = Vec::new();
+            let mut errors = 0;
+            while remaining.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok() {
+                let start = Instant::now();
+                match session(&mut client, rpc, &context).await {
+                    Ok(()) => latencies.push(start.elapsed()),
+                    Err(_) => errors += 1,
+                }
+            }
+            (latencies, errors)
+        });
+    }
+    let mut measurement =
+        Measurement { transport, latencies: Vec::with_capacity(sessions), errors: 0, elapsed: Duration::ZERO };
+    while let Some(joined) = workers.join_next().await {
+        match joined {
+            Ok((latencies, errors)) => {
+                measurement.latencies.extend(latencies);
+                measurement.errors += errors;
+            }
+            Err(_) => measurement.errors += 1,
+        }
+    }
+    measurement.elapsed = start.elapsed();
+    measurement.latencies.sort();
+    measurement
+}
+
+async fn session(client: &mut AdsServiceClient<Channel>, rpc: BenchRpc, context: &Context) -> Result<(), Status> {
+    match rpc {
+        BenchRpc::Bidi => {
+            let initial = Context { understanding: String::new(), ..context.clone() };
+            let outbound = tokio_stream::iter(vec![initial, context.clone()]);
+            let mut inbound = client.get_ads(outbound).await?.into_inner();
+            while inbound.message().await?.is_some() {}
+        }
+        BenchRpc::Unary => {
+            client.get_ads_unary(context.clone()).await?;
+        }
+    }
+    Ok(())
+}
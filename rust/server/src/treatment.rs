@@ -0,0 +1,79 @@
+//! Experiment treatment tags, so ranking experiments can be evaluated per arm. Every
+//! generated ad is tagged with the arm that produced it (`experiment_arm`: the
+//! generator variant, or `plugin`) and the scoring pipeline that ranked it
+//! (`pipeline_version`: see `Ranking::pipeline_version`, or the plugin's name).
+//! Clients keep the tags through selection and send the ads they served back with
+//! ReportFeedback, along with the session outcome. The outcome is attributed to the
+//! treatment the stream was generated with, so an arm whose sessions time out before
+//! a single ad arrives still pays for it: `treatment_sessions_total{arm,pipeline,outcome}`.
+//! Served ads count by their own tags in `treatment_ads_served_total{arm,pipeline}`;
+//! tags this server did not issue for the stream count as `other`, so a client cannot
+//! grow the label sets.
+
+use ads_common::outcome::SessionOutcome;
+use tracing::debug;
+
+use crate::ads::{AdsList, SessionFeedback};
+use crate::generator::Ranking;
+use crate::metrics::Metrics;
+use crate::plugin::GeneratorPlugin;
+use crate::variant::GeneratorVariant;
+
+/// Arm of ads from a generator plugin, whatever the session's variant
+const PLUGIN_ARM: &str = "plugin";
+/// Label value of tags the stream was not generated with
+const OTHER: &str = "other";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Treatment {
+    pub arm: String,
+    pub pipeline_version: String,
+}
+
+impl Treatment {
+    /// Treatment of what `variant` (or the plugin, when one is loaded) generates
+    pub fn new(plugin: Option<&GeneratorPlugin>, variant: GeneratorVariant, ranking: Ranking) -> Self {
+        match plugin {
+            Some(plugin) => Treatment { arm: PLUGIN_ARM.to_string(), pipeline_version: plugin.name().to_string() },
+            None => Treatment { arm: variant.name().to_string(), pipeline_version: ranking.pipeline_version() },
+        }
+    }
+
+    /// Tag every ad of the list and its partitions
+    pub fn tag(&self, ads_list: &mut AdsList) {
+        let ads = ads_list
+            .ads
+            .iter_mut()
+            .chain(ads_list.query_results.iter_mut().flat_map(|partition| partition.ads.iter_mut()))
+            .chain(ads_list.placement_results.iter_mut().flat_map(|partition| partition.ads.iter_mut()));
+        for ad in ads {
+            ad.experiment_arm.clone_from(&self.arm);
+            ad.pipeline_version.clone_from(&self.pipeline_version);
+        }
+    }
+
+    /// Aggregate the feedback of a stream generated with this treatment
+    pub fn record_feedback(&self, feedback: &SessionFeedback, metrics: &Metrics) {
+        let outcome = SessionOutcome::from_name(&feedback.outcome).map_or("unknown", |outcome| outcome.name());
+        let labels = [("arm", self.arm.as_str()), ("pipeline", self.pipeline_version.as_str())];
+        metrics.inc("treatment_sessions_total", &[labels[0], labels[1], ("outcome", outcome)]);
+        let mut foreign = 0;
+        for ad in &feedback.served_ads {
+            if ad.experiment_arm == self.arm && ad.pipeline_version == self.pipeline_version {
+                metrics.inc("treatment_ads_served_total", &labels);
+            } else {
+                foreign += 1;
+                metrics.inc("treatment_ads_served_total", &[("arm", OTHER), ("pipeline", OTHER)]);
+            }
+        }
+        debug!(
+            request_id = %feedback.request_id,
+            arm = %self.arm,
+            pipeline_version = %self.pipeline_version,
+            outcome = outcome,
+            served_ads = feedback.served_ads.len(),
+            foreign_tags = foreign,
+            "Recorded treatment feedback"
+        );
+    }
+}
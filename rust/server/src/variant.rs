@@ -0,0 +1,82 @@
+//! Generator variants a client can pick per session with `x-generator` metadata, for
+//! side-by-side manual comparisons against one running server. The server only
+//! honors variants enabled in its config; anything else falls back to the default
+//! variant, so a stray header never changes what production traffic sees.
+
+use clap::ValueEnum;
+use tonic::metadata::MetadataMap;
+
+use ads_proto::GENERATOR_METADATA_KEY;
+
+/// Built-in ranking used for a session. A loaded generator plugin replaces all of them.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum GeneratorVariant {
+    /// Synthetic candidates only; admin catalog entries are left out
+    Mock,
+    /// Synthetic candidates ranked together with the admin catalog
+    #[default]
+    Catalog,
+    /// Like `catalog`, with relevance from query/candidate embedding similarity
+    /// instead of a hash of the pair
+    Embedding,
+}
+
+impl GeneratorVariant {
+    pub fn name(self) -> &'static str {
+        match self {
+            GeneratorVariant::Mock => "mock",
+            GeneratorVariant::Catalog => "catalog",
+            GeneratorVariant::Embedding => "embedding",
+        }
+    }
+}
+
+/// Why a session runs the variant it runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    /// No `x-generator` metadata
+    Default,
+    /// The requested variant is enabled
+    Requested,
+    /// The requested variant exists but is not enabled
+    Disabled,
+    /// The metadata names no variant
+    Unknown,
+}
+
+impl Choice {
+    pub fn name(self) -> &'static str {
+        match self {
+            Choice::Default => "default",
+            Choice::Requested => "requested",
+            Choice::Disabled => "disabled",
+            Choice::Unknown => "unknown",
+        }
+    }
+}
+
+/// Variants clients may request; the default is always allowed
+#[derive(Debug, Clone)]
+pub struct VariantPolicy {
+    default: GeneratorVariant,
+    enabled: Vec<GeneratorVariant>,
+}
+
+impl VariantPolicy {
+    pub fn new(default: GeneratorVariant, enabled: Vec<GeneratorVariant>) -> Self {
+        VariantPolicy { default, enabled }
+    }
+
+    /// Variant for a session with request `metadata`
+    pub fn resolve(&self, metadata: &MetadataMap) -> (GeneratorVariant, Choice) {
+        let Some(requested) = metadata.get(GENERATOR_METADATA_KEY) else {
+            return (self.default, Choice::Default);
+        };
+        let parsed = requested.to_str().ok().and_then(|name| GeneratorVariant::from_str(name.trim(), true).ok());
+        match parsed {
+            None => (self.default, Choice::Unknown),
+            Some(variant) if variant == self.default || self.enabled.contains(&variant) => (variant, Choice::Requested),
+            Some(_) => (self.default, Choice::Disabled),
+        }
+    }
+}
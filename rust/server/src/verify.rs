@@ -0,0 +1,462 @@
+//! `ads-verify`: one command telling a contributor that a protocol-affecting change
+//! kept the playground contract. Building the binary compiles ads.proto and
+//! admin.proto; it then
+//!
+//! 1. lints the compiled descriptors: enum value prefixes, snake_case field names,
+//!    the AdsService method shapes and the wire numbers and types other
+//!    implementations rely on;
+//! 2. runs conformance scenarios against the real service, strict protocol on,
+//!    served in-process (no socket) through the generated client;
+//! 3. generates every version of each request type as `--dry-run` does, then runs
+//!    the generator property tests (the unit tests of ads-proto and ads-server)
+//!    through cargo;
+//! 4. checks the config file schema and its migrations, and validates any config
+//!    files given.
+
+use std::future::Future;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ads_common::limits::{FINAL_VERSION, INITIAL_VERSION, REFINED_VERSION};
+use ads_common::{Error, Result};
+use clap::Parser;
+use prost::Message;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, EnumDescriptorProto, FileDescriptorSet};
+use tonic::metadata::MetadataValue;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Status};
+
+use crate::ads::ads_list::Control;
+use crate::ads::ads_service_client::AdsServiceClient;
+use crate::ads::{AdsList, Context, HandshakeRequest};
+use crate::catalog::Catalog;
+use crate::config::{Cli, ServerConfig, VerifyArgs};
+use crate::config_schema;
+use crate::dryrun;
+use crate::faults::FaultInjector;
+use crate::maintenance::Maintenance;
+use crate::metrics::Metrics;
+use crate::nofill;
+use crate::runtime_config::{ConfigStore, RuntimeConfig};
+use crate::slo::{SloConfig, SloTracker};
+use crate::strict::VIOLATIONS_METADATA_KEY;
+use crate::transport_bench::{self, Transport};
+use crate::AdsServiceImpl;
+
+/// AdsService methods: (name, input, output, client streaming, server streaming)
+const METHODS: [(&str, &str, &str, bool, bool); 5] = [
+    ("GetAds", ".ads.Context", ".ads.AdsList", true, true),
+    ("GetAdsServerStreaming", ".ads.Context", ".ads.AdsList", false, true),
+    ("GetAdsUnary", ".ads.Context", ".ads.AdsList", false, false),
+    ("Handshake", ".ads.HandshakeRequest", ".ads.HandshakeResponse", false, false),
+    ("ReportFeedback", ".ads.SessionFeedback", ".ads.SessionFeedbackResponse", false, false),
+];
+
+/// Fields every client and server implementation encodes: (message, field, number,
+/// type, repeated). Renumbering or retyping one breaks the wire format.
+const WIRE_FIELDS: [(&str, &str, i32, Type, bool); 14] = [
+    ("Context", "query", 1, Type::String, false),
+    ("Context", "asin_id", 2, Type::String, false),
+    ("Context", "understanding", 3, Type::String, false),
+    ("Context", "seed", 4, Type::Uint64, false),
+    ("Context", "request_type", 5, Type::Enum, false),
+    ("Context", "channel_id", 6, Type::Uint32, false),
+    ("Context", "queries", 7, Type::String, true),
+    ("Ad", "asin_id", 1, Type::String, false),
+    ("Ad", "ad_id", 2, Type::String, false),
+    ("Ad", "score", 3, Type::Double, false),
+    ("AdsList", "ads", 1, Type::Message, true),
+    ("AdsList", "version", 2, Type::Uint32, false),
+    ("AdsList", "channel_id", 3, Type::Uint32, false),
+    ("HandshakeRequest", "client_send_unix_us", 1, Type::Uint64, false),
+];
+
+pub async fn run(args: &VerifyArgs) -> Result<()> {
+    // The service as a plain start would build it, strict protocol on
+    let config = Cli::try_parse_from(["ads-server", "--strict-protocol"])
+        .map_err(|e| Error::config(format!("verify server config: {}", e)))?
+        .config;
+    let mut problems = Vec::new();
+
+    let steps: [(&str, Vec<String>); 5] = [
+        ("Proto lint", lint_descriptors()),
+        ("Conformance", conformance(&config, Duration::from_millis(args.scenario_timeout_ms)).await?),
+        ("Generator self-test", dryrun::self_test(None, config.ranking())),
+        ("Generator properties", property_tests()),
+        ("Config schema", config_schema_checks(&config, args)),
+    ];
+    for (step, failures) in steps {
+        println!("{}: {}", step, if failures.is_empty() { "passed" } else { "FAILED" });
+        problems.extend(failures.into_iter().map(|failure| format!("{}: {}", step.to_lowercase(), failure)));
+    }
+
+    if problems.is_empty() {
+        println!("Verify OK");
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("PROBLEM: {}", problem);
+    }
+    Err(Error::config(format!("verify found {} problem(s)", problems.len())))
+}
+
+fn lint_descriptors() -> Vec<String> {
+    let descriptors = match FileDescriptorSet::decode(ads_proto::FILE_DESCRIPTOR_SET) {
+        Ok(descriptors) => descriptors,
+        Err(e) => return vec![format!("embedded descriptor set does not decode: {}", e)],
+    };
+    let mut failures = Vec::new();
+    for file in &descriptors.file {
+        for enumeration in &file.enum_type {
+            lint_enum(file.name(), enumeration, &mut failures);
+        }
+        for message in &file.message_type {
+            lint_message(file.name(), message, &mut failures);
+        }
+    }
+
+    let Some(ads) = descriptors.file.iter().find(|file| file.package() == "ads") else {
+        failures.push("no file declares package ads".to_string());
+        return failures;
+    };
+    match ads.service.iter().find(|service| service.name() == "AdsService") {
+        Some(service) => {
+            for (name, input, output, client_streaming, server_streaming) in METHODS {
+                let Some(method) = service.method.iter().find(|method| method.name() == name) else {
+                    failures.push(format!("AdsService.{} is missing", name));
+                    continue;
+                };
+                let expected = (input, output, client_streaming, server_streaming);
+                let found =
+                    (method.input_type(), method.output_type(), method.client_streaming(), method.server_streaming());
+                if found != expected {
+                    failures.push(format!("AdsService.{} is {:?}, expected {:?}", name, found, expected));
+                }
+            }
+        }
+        None => failures.push("service AdsService is missing".to_string()),
+    }
+    for (message_name, field_name, number, field_type, repeated) in WIRE_FIELDS {
+        let field = ads
+            .message_type
+            .iter()
+            .find(|message| message.name() == message_name)
+            .and_then(|message| message.field.iter().find(|field| field.name() == field_name));
+        let Some(field) = field else {
+            failures.push(format!("{}.{} is missing", message_name, field_name));
+            continue;
+        };
+        let expected = (number, field_type, repeated);
+        let found = (field.number(), field.r#type(), field.label() == Label::Repeated);
+        if found != expected {
+            failures.push(format!(
+                "{}.{} is (number, type, repeated) {:?}, expected {:?}",
+                message_name, field_name, found, expected
+            ));
+        }
+    }
+    failures
+}
+
+// Values are prefixed with the enum's name in SCREAMING_SNAKE_CASE, since proto3
+// enum values share their package's scope, and the default comes first
+fn lint_enum(file: &str, enumeration: &EnumDescriptorProto, failures: &mut Vec<String>) {
+    let prefix = format!("{}_", screaming_snake(enumeration.name()));
+    for value in &enumeration.value {
+        if !value.name().starts_with(&prefix) {
+            failures.push(format!("{}: {}.{} lacks the {} prefix", file, enumeration.name(), value.name(), prefix));
+        }
+    }
+    if enumeration.value.first().map(|value| value.number()) != Some(0) {
+        failures.push(format!("{}: {} does not start with its 0 value", file, enumeration.name()));
+    }
+}
+
+fn lint_message(file: &str, message: &DescriptorProto, failures: &mut Vec<String>) {
+    for field in &message.field {
+        let snake_case = field.name().chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !snake_case {
+            failures.push(format!("{}: {}.{} is not snake_case", file, message.name(), field.name()));
+        }
+    }
+    for enumeration in &message.enum_type {
+        lint_enum(file, enumeration, failures);
+    }
+    for nested in &message.nested_type {
+        lint_message(file, nested, failures);
+    }
+}
+
+fn screaming_snake(name: &str) -> String {
+    let mut out = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.push(c.to_ascii_uppercase());
+    }
+    out
+}
+
+async fn conformance(config: &ServerConfig, timeout: Duration) -> Result<Vec<String>> {
+    let metrics = Arc::new(Metrics::default());
+    let service = AdsServiceImpl::new(
+        config,
+        metrics.clone(),
+        Arc::new(ConfigStore::new(RuntimeConfig::from_server_config(config), config.config_audit_size)),
+        Arc::new(FaultInjector::new(metrics.clone())),
+        Arc::new(Catalog::default()),
+        None,
+        Arc::new(SloTracker::new(SloConfig::from_server_config(config), metrics.clone())),
+        Arc::new(Maintenance::default()),
+    );
+    let (channel, stop) = transport_bench::start(Transport::InProcess, service).await?;
+    let client = AdsServiceClient::new(channel);
+
+    let mut failures = Vec::new();
+    scenario("canonical session", timeout, canonical_session(client.clone()), &mut failures).await;
+    scenario("multiplexed channels", timeout, multiplexed_channels(client.clone()), &mut failures).await;
+    scenario("acknowledged Contexts", timeout, acknowledged_contexts(client.clone()), &mut failures).await;
+    scenario("strict violation", timeout, strict_violation(client.clone()), &mut failures).await;
+    scenario("server streaming", timeout, server_streaming(client.clone()), &mut failures).await;
+    scenario("unary", timeout, unary(client.clone()), &mut failures).await;
+    scenario("handshake", timeout, handshake(client), &mut failures).await;
+    let _ = stop.send(());
+    Ok(failures)
+}
+
+async fn scenario(
+    name: &str,
+    timeout: Duration,
+    run: impl Future<Output = Result<(), String>>,
+    failures: &mut Vec<String>,
+) {
+    match tokio::time::timeout(timeout, run).await {
+        Ok(Ok(())) => {}
+        Ok(Err(failure)) => failures.push(format!("{}: {}", name, failure)),
+        Err(_) => failures.push(format!("{}: no result within {} ms", name, timeout.as_millis())),
+    }
+}
+
+// The two Contexts of a session: the initial one, then the refined one
+fn contexts(channel_id: u32, query: &str) -> [Context; 2] {
+    let initial = Context {
+        query: query.to_string(),
+        asin_id: "B000123".to_string(),
+        seed: 42,
+        channel_id,
+        ..Default::default()
+    };
+    let refined = Context { understanding: format!("refined understanding of {}", query), ..initial.clone() };
+    [initial, refined]
+}
+
+fn describe(status: &Status) -> String {
+    format!("{:?}: {}", status.code(), status.message())
+}
+
+// An AdsList answering `channel_id`: filled with distinct, finite-scored ads, or
+// explicitly empty
+fn check_list(ads_list: &AdsList, channel_id: u32) -> Result<(), String> {
+    let version = ads_list.version;
+    if ads_list.channel_id != channel_id {
+        return Err(format!("version {} on channel {}, sent on {}", version, ads_list.channel_id, channel_id));
+    }
+    if ads_list.ads.is_empty() && !nofill::is_no_fill(ads_list) {
+        return Err(format!("version {} is empty without a no_fill_reason", version));
+    }
+    if let Some(ad) = ads_list.ads.iter().find(|ad| !ad.score.is_finite()) {
+        return Err(format!("version {}: ad {} has score {}", version, ad.ad_id, ad.score));
+    }
+    let mut ad_ids: Vec<&str> = ads_list.ads.iter().map(|ad| ad.ad_id.as_str()).collect();
+    ad_ids.sort_unstable();
+    if ad_ids.windows(2).any(|pair| pair[0] == pair[1]) {
+        return Err(format!("version {} repeats an ad_id", version));
+    }
+    Ok(())
+}
+
+fn check_versions(versions: &[u32], channel_id: u32) -> Result<(), String> {
+    let expected = [INITIAL_VERSION, REFINED_VERSION, FINAL_VERSION];
+    if versions != expected {
+        return Err(format!("channel {} got versions {:?}, expected {:?}", channel_id, versions, expected));
+    }
+    Ok(())
+}
+
+async fn canonical_session(mut client: AdsServiceClient<Channel>) -> Result<(), String> {
+    let outbound = tokio_stream::iter(contexts(0, "coffee maker"));
+    let mut inbound = client.get_ads(outbound).await.map_err(|status| describe(&status))?.into_inner();
+    let mut versions = Vec::new();
+    while let Some(ads_list) = inbound.message().await.map_err(|status| describe(&status))? {
+        if ads_list.control.is_some() {
+            return Err("control message on a session that asked for none".to_string());
+        }
+        check_list(&ads_list, 0)?;
+        versions.push(ads_list.version);
+    }
+    check_versions(&versions, 0)
+}
+
+// Two channels interleaved on one stream get their versions independently, in order
+async fn multiplexed_channels(mut client: AdsServiceClient<Channel>) -> Result<(), String> {
+    let [first_initial, first_refined] = contexts(1, "coffee maker");
+    let [second_initial, second_refined] = contexts(2, "espresso");
+    let outbound = tokio_stream::iter([first_initial, second_initial, first_refined, second_refined]);
+    let mut inbound = client.get_ads(outbound).await.map_err(|status| describe(&status))?.into_inner();
+    let mut versions: [Vec<u32>; 2] = Default::default();
+    while let Some(ads_list) = inbound.message().await.map_err(|status| describe(&status))? {
+        let Some(channel_versions) = versions.get_mut((ads_list.channel_id as usize).wrapping_sub(1)) else {
+            return Err(format!("AdsList on unknown channel {}", ads_list.channel_id));
+        };
+        check_list(&ads_list, ads_list.channel_id)?;
+        channel_versions.push(ads_list.version);
+    }
+    check_versions(&versions[0], 1)?;
+    check_versions(&versions[1], 2)
+}
+
+// Each Context is acknowledged, in order, before the version answering it
+async fn acknowledged_contexts(mut client: AdsServiceClient<Channel>) -> Result<(), String> {
+    let mut request = Request::new(tokio_stream::iter(contexts(0, "coffee maker")));
+    request.metadata_mut().insert(ads_proto::ACK_CONTEXTS_METADATA_KEY, MetadataValue::from_static("true"));
+    let mut inbound = client.get_ads(request).await.map_err(|status| describe(&status))?.into_inner();
+    let mut acknowledged = 0;
+    let mut versions = Vec::new();
+    while let Some(ads_list) = inbound.message().await.map_err(|status| describe(&status))? {
+        match &ads_list.control {
+            Some(Control::Ack(ack)) => {
+                if ack.sequence != acknowledged + 1 {
+                    return Err(format!("acknowledgement {} after {}", ack.sequence, acknowledged));
+                }
+                acknowledged = ack.sequence;
+            }
+            None => {
+                check_list(&ads_list, 0)?;
+                if ads_list.version <= REFINED_VERSION && acknowledged < ads_list.version {
+                    return Err(format!("version {} before its Context was acknowledged", ads_list.version));
+                }
+                versions.push(ads_list.version);
+            }
+        }
+    }
+    if acknowledged != 2 {
+        return Err(format!("{} of 2 Contexts acknowledged", acknowledged));
+    }
+    check_versions(&versions, 0)
+}
+
+// An understanding on the first Context fails the stream, naming the rule
+async fn strict_violation(mut client: AdsServiceClient<Channel>) -> Result<(), String> {
+    let [_, refined] = contexts(0, "coffee maker");
+    let outbound = tokio_stream::iter([refined.clone(), refined]);
+    let status = match client.get_ads(outbound).await {
+        Err(status) => status,
+        Ok(response) => {
+            let mut inbound = response.into_inner();
+            loop {
+                match inbound.message().await {
+                    Ok(Some(_)) => {}
+                    Ok(None) => return Err("stream ended without reporting the violation".to_string()),
+                    Err(status) => break status,
+                }
+            }
+        }
+    };
+    if status.code() != Code::FailedPrecondition {
+        return Err(format!("failed with {}, expected FailedPrecondition", describe(&status)));
+    }
+    let codes = status.metadata().get(VIOLATIONS_METADATA_KEY).and_then(|value| value.to_str().ok()).unwrap_or("");
+    if !codes.split(',').any(|code| code == "early_understanding") {
+        return Err(format!("{} is {:?}, expected early_understanding", VIOLATIONS_METADATA_KEY, codes));
+    }
+    Ok(())
+}
+
+async fn server_streaming(mut client: AdsServiceClient<Channel>) -> Result<(), String> {
+    let [_, context] = contexts(0, "coffee maker");
+    let mut inbound =
+        client.get_ads_server_streaming(context).await.map_err(|status| describe(&status))?.into_inner();
+    let mut versions = Vec::new();
+    while let Some(ads_list) = inbound.message().await.map_err(|status| describe(&status))? {
+        check_list(&ads_list, 0)?;
+        versions.push(ads_list.version);
+    }
+    check_versions(&versions, 0)
+}
+
+async fn unary(mut client: AdsServiceClient<Channel>) -> Result<(), String> {
+    let [_, context] = contexts(0, "coffee maker");
+    let ads_list = client.get_ads_unary(context).await.map_err(|status| describe(&status))?.into_inner();
+    check_list(&ads_list, 0)?;
+    if ads_list.version != FINAL_VERSION {
+        return Err(format!("version {}, expected {}", ads_list.version, FINAL_VERSION));
+    }
+    Ok(())
+}
+
+async fn handshake(mut client: AdsServiceClient<Channel>) -> Result<(), String> {
+    let client_send_unix_us = ads_proto::unix_us();
+    let response = client
+        .handshake(HandshakeRequest { client_send_unix_us })
+        .await
+        .map_err(|status| describe(&status))?
+        .into_inner();
+    if response.client_send_unix_us != client_send_unix_us {
+        return Err(format!("echoed {}, sent {}", response.client_send_unix_us, client_send_unix_us));
+    }
+    if response.server_receive_unix_us > response.server_send_unix_us {
+        return Err("server sent the response before receiving the request".to_string());
+    }
+    Ok(())
+}
+
+/// `cargo test` of the packages whose unit tests cover the generator: ranking and
+/// merging invariants, golden bytes, request modes, tie-breaks and top-K selection
+fn property_tests() -> Vec<String> {
+    // Set by `cargo run`; a binary started on its own uses the cargo on PATH
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("../Cargo.toml");
+    let output = Command::new(cargo)
+        .arg("test")
+        .arg("--manifest-path")
+        .arg(&manifest)
+        .args(["--package", "ads-proto", "--package", "ads-server", "--lib", "--quiet"])
+        .output();
+    let output = match output {
+        Ok(output) if output.status.success() => return Vec::new(),
+        Ok(output) => output,
+        Err(e) => return vec![format!("could not run cargo test: {}", e)],
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let failed: Vec<String> = stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("---- ")?.strip_suffix(" stdout ----"))
+        .map(|test| format!("{} failed", test))
+        .collect();
+    if !failed.is_empty() {
+        return failed;
+    }
+    // Failed before running any test, e.g. a compile error
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let error = stderr.lines().find(|line| line.starts_with("error")).unwrap_or("cargo test failed");
+    vec![error.to_string()]
+}
+
+fn config_schema_checks(config: &ServerConfig, args: &VerifyArgs) -> Vec<String> {
+    let runtime = RuntimeConfig::from_server_config(config);
+    let mut failures = match serde_json::to_value(&runtime) {
+        Ok(serde_json::Value::Object(defaults)) => config_schema::check_schema(&defaults),
+        _ => vec!["runtime config does not serialize to a JSON object".to_string()],
+    };
+    let config_store = ConfigStore::new(runtime, config.config_audit_size);
+    for path in &args.config_files {
+        if let Err(e) = config_store.reload_from_file(path) {
+            failures.push(format!("{}: {}", path.display(), e));
+        }
+    }
+    failures
+}
@@ -0,0 +1,176 @@
+//! Wasted-work accounting: how much generation went into versions the client never
+//! used. Every GetAds call carrying an `x-request-id`, whatever its shape, is opened
+//! in the ledger, and when the session finishes its generation work (wall time and
+//! candidates of each version) is filed under that id. The client settles it by
+//! reporting the version it used through ReportFeedback: work on that version and
+//! below counts as used, work on later versions (they arrived after its timeout or
+//! cancellation) as wasted. Work without feedback `--feedback-wait-secs` after the
+//! session finished counts as unreported. A version finished but never delivered is
+//! wasted either way. Totals are in `generation_work_us_total{use}` and
+//! `generation_candidates_total{use}`, and the shutdown report carries the run's
+//! wasted-work ratio. The ledger also keeps each stream's experiment treatment, so
+//! its feedback is attributed to the arm that served it.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use crate::metrics::Metrics;
+use crate::treatment::Treatment;
+
+/// Generation of one version of a session
+#[derive(Debug, Clone, Copy)]
+pub struct VersionWork {
+    pub version: u32,
+    pub generation: Duration,
+    /// Ads ranked into the version, across partitions
+    pub candidates: u64,
+    /// Sent to the client, rather than finished after it left or held back
+    pub delivered: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WorkUse {
+    Used,
+    Wasted,
+    Unreported,
+}
+
+impl WorkUse {
+    fn name(self) -> &'static str {
+        match self {
+            WorkUse::Used => "used",
+            WorkUse::Wasted => "wasted",
+            WorkUse::Unreported => "unreported",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    // Filed when the session finished
+    work: Option<Vec<VersionWork>>,
+    // Reported by the client, possibly while the session was still generating
+    selected_version: Option<u32>,
+    treatment: Treatment,
+}
+
+#[derive(Debug, Default)]
+struct Ledger {
+    pending: HashMap<String, Pending>,
+    // Finished sessions awaiting feedback, oldest first
+    finished: VecDeque<(Instant, String)>,
+}
+
+#[derive(Debug)]
+pub struct WorkLedger {
+    ledger: Mutex<Ledger>,
+    feedback_wait: Duration,
+    metrics: Arc<Metrics>,
+}
+
+impl WorkLedger {
+    pub fn new(feedback_wait: Duration, metrics: Arc<Metrics>) -> Self {
+        WorkLedger { ledger: Mutex::new(Ledger::default()), feedback_wait, metrics }
+    }
+
+    /// Start waiting for the work and feedback of a stream generated with `treatment`
+    pub fn open(&self, request_id: &str, treatment: Treatment) {
+        if !request_id.is_empty() {
+            self.ledger.lock().unwrap().pending.entry(request_id.to_string()).or_default().treatment = treatment;
+        }
+    }
+
+    /// File a finished session's work, settling it if the client already reported
+    pub fn finish(&self, request_id: &str, work: Vec<VersionWork>) {
+        let mut ledger = self.ledger.lock().unwrap();
+        self.expire(&mut ledger);
+        let Some(pending) = ledger.pending.get_mut(request_id) else {
+            self.settle(request_id, &work, None);
+            return;
+        };
+        match pending.selected_version {
+            Some(selected_version) => {
+                ledger.pending.remove(request_id);
+                self.settle(request_id, &work, Some(selected_version));
+            }
+            None => {
+                pending.work = Some(work);
+                ledger.finished.push_back((Instant::now(), request_id.to_string()));
+            }
+        }
+    }
+
+    /// Record the version the client used and return the stream's treatment; None
+    /// when the stream is unknown, was settled as unreported already or was reported
+    /// before
+    pub fn feedback(&self, request_id: &str, selected_version: u32) -> Option<Treatment> {
+        let mut ledger = self.ledger.lock().unwrap();
+        self.expire(&mut ledger);
+        let pending = ledger.pending.get_mut(request_id)?;
+        if pending.selected_version.is_some() {
+            return None;
+        }
+        let treatment = pending.treatment.clone();
+        match pending.work.take() {
+            Some(work) => {
+                ledger.pending.remove(request_id);
+                self.settle(request_id, &work, Some(selected_version));
+            }
+            None => pending.selected_version = Some(selected_version),
+        }
+        Some(treatment)
+    }
+
+    /// Count the work of every finished session still waiting for feedback as
+    /// unreported, so a run's totals are complete at shutdown
+    pub fn settle_pending(&self) {
+        let mut ledger = self.ledger.lock().unwrap();
+        for (_, request_id) in std::mem::take(&mut ledger.finished) {
+            if let Some(Pending { work: Some(work), .. }) = ledger.pending.remove(&request_id) {
+                self.settle(&request_id, &work, None);
+            }
+        }
+    }
+
+    // Settle finished sessions whose feedback is overdue
+    fn expire(&self, ledger: &mut Ledger) {
+        while let Some((finished_at, _)) = ledger.finished.front() {
+            if finished_at.elapsed() < self.feedback_wait {
+                break;
+            }
+            let Some((_, request_id)) = ledger.finished.pop_front() else { break };
+            // Already settled when the feedback arrived in time
+            if let Some(Pending { work: Some(work), .. }) = ledger.pending.remove(&request_id) {
+                self.settle(&request_id, &work, None);
+            }
+        }
+    }
+
+    fn settle(&self, request_id: &str, work: &[VersionWork], selected_version: Option<u32>) {
+        let mut wasted = Duration::ZERO;
+        for version in work {
+            let work_use = match selected_version {
+                _ if !version.delivered => WorkUse::Wasted,
+                Some(selected) if version.version > selected => WorkUse::Wasted,
+                Some(_) => WorkUse::Used,
+                None => WorkUse::Unreported,
+            };
+            if work_use == WorkUse::Wasted {
+                wasted += version.generation;
+            }
+            let labels = [("use", work_use.name())];
+            self.metrics.add("generation_work_us_total", &labels, version.generation.as_micros() as u64);
+            self.metrics.add("generation_candidates_total", &labels, version.candidates);
+        }
+        debug!(
+            request_id = %request_id,
+            selected_version = selected_version,
+            versions = work.len(),
+            wasted_us = wasted.as_micros() as u64,
+            "Settled session generation work"
+        );
+    }
+}
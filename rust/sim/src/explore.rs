@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+
+use crate::model::{Event, Scenario, Session, Step};
+
+/// How one run of a scenario ended
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub scenario: Scenario,
+    pub outcome: Option<u32>,
+    pub history: Vec<Step>,
+}
+
+/// Every run of `scenario`: events due at the same instant fire in every possible
+/// order, so each run is one distinct interleaving
+pub fn runs(scenario: Scenario) -> Vec<Run> {
+    let mut runs = Vec::new();
+    let mut stack = vec![Session::new(scenario)];
+    while let Some(session) = stack.pop() {
+        if let Some(outcome) = session.outcome() {
+            runs.push(Run { scenario, outcome, history: session.history().to_vec() });
+            continue;
+        }
+        let choices = session.choices();
+        if choices.is_empty() {
+            // Unreachable while the client deadline is pending; counts as no result
+            runs.push(Run { scenario, outcome: None, history: session.history().to_vec() });
+            continue;
+        }
+        for key in choices {
+            let mut next = session.clone();
+            next.fire(key);
+            stack.push(next);
+        }
+    }
+    runs
+}
+
+/// Order of client-visible events up to selection, which is what decides the outcome
+pub fn signature(run: &Run) -> Vec<Event> {
+    run.history
+        .iter()
+        .map(|step| step.event)
+        .filter(|event| matches!(event, Event::ClientSendContext(_) | Event::ClientReceiveAdsList(_) | Event::ClientDeadline))
+        .collect()
+}
+
+/// Runs that ended without an AdsList, grouped by interleaving signature
+#[derive(Debug, Default)]
+pub struct Report {
+    pub scenarios: usize,
+    pub runs: usize,
+    pub by_version: BTreeMap<Option<u32>, usize>,
+    /// Signature -> (runs, first run seen)
+    pub none: BTreeMap<Vec<Event>, (usize, Run)>,
+}
+
+impl Report {
+    pub fn add(&mut self, scenario_runs: Vec<Run>) {
+        self.scenarios += 1;
+        for run in scenario_runs {
+            self.runs += 1;
+            *self.by_version.entry(run.outcome).or_default() += 1;
+            if run.outcome.is_none() {
+                self.none.entry(signature(&run)).or_insert_with(|| (0, run)).0 += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Discrete-event simulation of an ads session: the client's Context sends, result
+//! selection timeout and early exit against the server's sequential generation and
+//! delayed refinement, on a virtual clock with no network. Every combination of the
+//! timing grids is run, and events falling on the same instant are fired in every
+//! order, so each interleaving is explored once. Interleavings in which the client
+//! ends up with no AdsList are reported grouped by the order of client-side events.
+
+use clap::Parser;
+
+use ads_client::context::DEFAULT_UNDERSTANDING_DELAY;
+use ads_client::selection::EarlyExit;
+
+mod explore;
+mod model;
+mod scheduler;
+
+use explore::Report;
+use model::Scenario;
+
+#[derive(Parser, Debug)]
+#[command(name = "ads-sim", about = "Explore session timing interleavings that leave the client without an AdsList")]
+struct Args {
+    /// Client -> server transit times to try (ms)
+    #[arg(long, value_delimiter = ',', default_value = "1,10,40")]
+    uplink_ms: Vec<u64>,
+
+    /// Server -> client transit times to try (ms)
+    #[arg(long, value_delimiter = ',', default_value = "1,10,40")]
+    downlink_ms: Vec<u64>,
+
+    /// Understanding delays between Context 1 and Context 2 to try (ms)
+    #[arg(long, value_delimiter = ',')]
+    understanding_delay_ms: Vec<u64>,
+
+    /// Client selection timeouts to try (ms); the client draws from 30-120
+    #[arg(long, value_delimiter = ',', default_value = "30,45,60,75,90,105,120")]
+    timeout_ms: Vec<u64>,
+
+    /// Generation times to try for each version independently (ms)
+    #[arg(long, value_delimiter = ',', default_value = "0,10,30,60,100")]
+    generation_ms: Vec<u64>,
+
+    /// Send the client's latency budget, letting the server skip version 3
+    #[arg(long)]
+    budget: bool,
+
+    /// Client early exit at this version (see the client's --min-acceptable-version)
+    #[arg(long)]
+    min_acceptable_version: Option<u32>,
+
+    /// List the None interleavings without an example timeline for each
+    #[arg(long)]
+    no_timelines: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+    let understanding_delays = if args.understanding_delay_ms.is_empty() {
+        vec![DEFAULT_UNDERSTANDING_DELAY.as_millis() as u64]
+    } else {
+        args.understanding_delay_ms.clone()
+    };
+    let early_exit = args.min_acceptable_version.map(|min_version| EarlyExit { min_version, min_ads: 1 });
+
+    let mut report = Report::default();
+    for &uplink_ms in &args.uplink_ms {
+        for &downlink_ms in &args.downlink_ms {
+            for &understanding_delay_ms in &understanding_delays {
+                for &timeout_ms in &args.timeout_ms {
+                    for &g1 in &args.generation_ms {
+                        for &g2 in &args.generation_ms {
+                            for &g3 in &args.generation_ms {
+                                let scenario = Scenario {
+                                    uplink_ms,
+                                    downlink_ms,
+                                    understanding_delay_ms,
+                                    timeout_ms,
+                                    generation_ms: [g1, g2, g3],
+                                    budget: args.budget,
+                                    early_exit,
+                                };
+                                report.add(explore::runs(scenario));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    println!("{} scenarios, {} interleavings", report.scenarios, report.runs);
+    for (outcome, count) in &report.by_version {
+        let outcome = outcome.map_or("None".to_string(), |version| format!("v{}", version));
+        println!("  {:<5} {:>8}  ({:.1}%)", outcome, count, *count as f64 * 100.0 / report.runs.max(1) as f64);
+    }
+    if report.none.is_empty() {
+        println!("No interleaving leaves the client without an AdsList");
+        return;
+    }
+    println!("\n{} distinct interleavings end with None:", report.none.len());
+    for (signature, (count, run)) in &report.none {
+        let order: Vec<String> = signature.iter().map(|event| event.label()).collect();
+        println!("\n[{} runs] {}", count, order.join(" -> "));
+        if args.no_timelines {
+            continue;
+        }
+        let s = run.scenario;
+        println!(
+            "  e.g. uplink {}ms, downlink {}ms, understanding delay {}ms, timeout {}ms, generation {:?}ms",
+            s.uplink_ms, s.downlink_ms, s.understanding_delay_ms, s.timeout_ms, s.generation_ms
+        );
+        for step in &run.history {
+            println!("  {:>6}ms  {}", step.at_ms, step.event.label());
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use ads_client::ads::AdsList;
+use ads_proto::testing::AdsListFixture;
+use ads_client::selection::EarlyExit;
+
+use crate::scheduler::Scheduler;
+
+/// Delay before the server generates version 3 after answering Context 2
+/// (REFINEMENT_DELAY in the server)
+pub const REFINEMENT_DELAY_MS: u64 = 50;
+
+/// Timing of one simulated session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scenario {
+    /// Client -> server transit of each Context
+    pub uplink_ms: u64,
+    /// Server -> client transit of each AdsList
+    pub downlink_ms: u64,
+    /// Client wait between Context 1 and Context 2 (the understanding delay)
+    pub understanding_delay_ms: u64,
+    /// Client result selection timeout, counted from the half-close after Context 2
+    pub timeout_ms: u64,
+    /// Server generation time of versions 1, 2 and 3
+    pub generation_ms: [u64; 3],
+    /// Contexts carry the client's latency budget, so the server may skip version 3
+    pub budget: bool,
+    pub early_exit: Option<EarlyExit>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Event {
+    ClientSendContext(u32),
+    ServerReceiveContext(u32),
+    ServerGenerated(u32),
+    ClientReceiveAdsList(u32),
+    ClientDeadline,
+}
+
+impl Event {
+    pub fn label(&self) -> String {
+        match self {
+            Event::ClientSendContext(n) => format!("client sends Context {}", n),
+            Event::ServerReceiveContext(n) => format!("server receives Context {}", n),
+            Event::ServerGenerated(v) => format!("server sends v{}", v),
+            Event::ClientReceiveAdsList(v) => format!("client receives v{}", v),
+            Event::ClientDeadline => "client timeout".to_string(),
+        }
+    }
+}
+
+/// One fired event of a run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub at_ms: u64,
+    pub event: Event,
+}
+
+/// Client and server state of one session under the scheduler. Mirrors the client's
+/// bidirectional session (`get_ads_keyed`) and the server's per-stream loop: the
+/// server handles Contexts one at a time and schedules version 3 after answering
+/// Context 2 unless the budget left is below the refinement delay.
+#[derive(Debug, Clone)]
+pub struct Session {
+    scenario: Scenario,
+    scheduler: Scheduler<Event>,
+    history: Vec<Step>,
+    // Client
+    half_closed: bool,
+    buffer: BTreeMap<u32, AdsList>,
+    selected: Option<Option<u32>>,
+    // Server
+    generating: bool,
+    queued: VecDeque<u32>,
+    context_received_at: BTreeMap<u32, u64>,
+}
+
+impl Session {
+    pub fn new(scenario: Scenario) -> Self {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule_in(0, Event::ClientSendContext(1));
+        Session {
+            scenario,
+            scheduler,
+            history: Vec::new(),
+            half_closed: false,
+            buffer: BTreeMap::new(),
+            selected: None,
+            generating: false,
+            queued: VecDeque::new(),
+            context_received_at: BTreeMap::new(),
+        }
+    }
+
+    /// Events that could fire next, all due at the same instant
+    pub fn choices(&self) -> Vec<(u64, u64)> {
+        if self.selected.is_some() {
+            return Vec::new();
+        }
+        self.scheduler.due()
+    }
+
+    /// The version the client returned (None = no AdsList), once the session ended
+    pub fn outcome(&self) -> Option<Option<u32>> {
+        self.selected
+    }
+
+    pub fn history(&self) -> &[Step] {
+        &self.history
+    }
+
+    pub fn fire(&mut self, key: (u64, u64)) {
+        let Some(event) = self.scheduler.fire(key) else { return };
+        self.history.push(Step { at_ms: self.scheduler.now_ms(), event });
+        let scenario = self.scenario;
+        match event {
+            Event::ClientSendContext(n) => {
+                self.scheduler.schedule_in(scenario.uplink_ms, Event::ServerReceiveContext(n));
+                if n == 1 {
+                    self.scheduler.schedule_in(scenario.understanding_delay_ms, Event::ClientSendContext(2));
+                } else {
+                    // Half-close starts result selection; AdsLists that arrived earlier
+                    // are read from the stream right away
+                    self.half_closed = true;
+                    self.scheduler.schedule_in(scenario.timeout_ms, Event::ClientDeadline);
+                    if self.buffer.values().any(|list| self.acceptable(list)) {
+                        self.select();
+                    }
+                }
+            }
+            Event::ServerReceiveContext(n) => {
+                self.context_received_at.insert(n, self.scheduler.now_ms());
+                self.queued.push_back(n);
+                self.start_generation();
+            }
+            Event::ServerGenerated(version) => {
+                self.scheduler.schedule_in(scenario.downlink_ms, Event::ClientReceiveAdsList(version));
+                if version == 3 {
+                    return;
+                }
+                self.generating = false;
+                if version == 2 && !self.skips_refinement() {
+                    self.scheduler
+                        .schedule_in(REFINEMENT_DELAY_MS + scenario.generation_ms[2], Event::ServerGenerated(3));
+                }
+                self.start_generation();
+            }
+            Event::ClientReceiveAdsList(version) => {
+                let list = AdsListFixture::with_n_ads(3).version(version).build();
+                let acceptable = self.acceptable(&list);
+                self.buffer.insert(version, list);
+                if self.half_closed && acceptable {
+                    self.select();
+                }
+            }
+            Event::ClientDeadline => self.select(),
+        }
+    }
+
+    fn start_generation(&mut self) {
+        if self.generating {
+            return;
+        }
+        if let Some(n) = self.queued.pop_front() {
+            self.generating = true;
+            self.scheduler.schedule_in(self.scenario.generation_ms[n as usize - 1], Event::ServerGenerated(n));
+        }
+    }
+
+    /// Context 2 carries what is left of the client's budget when sent, which is the
+    /// selection timeout; the server measures it from when it received the Context
+    fn skips_refinement(&self) -> bool {
+        if !self.scenario.budget {
+            return false;
+        }
+        let received_at = self.context_received_at.get(&2).copied().unwrap_or_default();
+        let spent = self.scheduler.now_ms() - received_at;
+        self.scenario.timeout_ms.saturating_sub(spent) < REFINEMENT_DELAY_MS
+    }
+
+    fn acceptable(&self, list: &AdsList) -> bool {
+        self.scenario.early_exit.is_some_and(|rule| rule.is_satisfied_by(list))
+    }
+
+    fn select(&mut self) {
+        self.selected = Some(self.buffer.keys().max().copied());
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+
+/// Virtual clock plus the events pending on it. Nothing sleeps: firing an event
+/// jumps the clock to its due time. Events due at the same instant are all reported
+/// by `due`, so the caller decides (and can explore) the order they fire in.
+#[derive(Debug, Clone)]
+pub struct Scheduler<E> {
+    now_ms: u64,
+    next_id: u64,
+    /// (due time, id) -> event; ids keep same-instant events apart in scheduling order
+    pending: BTreeMap<(u64, u64), E>,
+}
+
+impl<E> Default for Scheduler<E> {
+    fn default() -> Self {
+        Scheduler { now_ms: 0, next_id: 0, pending: BTreeMap::new() }
+    }
+}
+
+impl<E: Clone> Scheduler<E> {
+    pub fn now_ms(&self) -> u64 {
+        self.now_ms
+    }
+
+    pub fn schedule_in(&mut self, delay_ms: u64, event: E) {
+        self.pending.insert((self.now_ms + delay_ms, self.next_id), event);
+        self.next_id += 1;
+    }
+
+    /// Keys of the events due at the earliest pending instant, in scheduling order
+    pub fn due(&self) -> Vec<(u64, u64)> {
+        let Some((&(at_ms, _), _)) = self.pending.iter().next() else { return Vec::new() };
+        self.pending.range((at_ms, 0)..=(at_ms, u64::MAX)).map(|(key, _)| *key).collect()
+    }
+
+    /// Remove one of the `due` events and advance the clock to it
+    pub fn fire(&mut self, key: (u64, u64)) -> Option<E> {
+        let event = self.pending.remove(&key)?;
+        self.now_ms = key.0;
+        Some(event)
+    }
+}
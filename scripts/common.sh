@@ -19,4 +19,19 @@ print_status() {
 # Function to check if command exists
 command_exists() {
     command -v "$1" >/dev/null 2>&1
-}
\ No newline at end of file
+}
+# Function to wait for a server started with --port-file to announce its port.
+# Prints the port; returns non-zero after the timeout (seconds, default 10)
+wait_for_port_file() {
+    local port_file="$1"
+    local timeout="${2:-10}"
+    local waited=0
+    while [ ! -s "$port_file" ]; do
+        if [ "$waited" -ge "$((timeout * 10))" ]; then
+            return 1
+        fi
+        sleep 0.1
+        waited=$((waited + 1))
+    done
+    cat "$port_file"
+}
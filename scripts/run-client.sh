@@ -120,7 +120,7 @@ run_rust_client() {
     
     # Run the client
     print_status "green" "Rust client connecting to $host:$port..."
-    cargo run --bin ads-client -- "$host" "$port" "$query" "$asin" "$understanding"
+    cargo run --bin ads-client -- "http://$host:$port" "$query" "$asin" "$understanding"
 }
 
 # Function to run client with language detection
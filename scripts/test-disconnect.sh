@@ -0,0 +1,130 @@
+#!/bin/bash
+
+# Early-disconnect test for the Rust server
+# A client that stops at version 2 (--min-acceptable-version 2) disconnects while
+# the delayed version 3 is pending. Each --disconnect-policy must handle it its
+# own way: cancel drops the refinement, finish generates it and journals it as
+# undelivered, grace keeps generating within the grace period for a reconnect.
+# The client's selection timeout is fixed well above the refinement delay, so the
+# latency budget it sends never makes the server skip version 3.
+
+set -e
+
+# Source common utilities
+source "$(dirname "$0")/common.sh"
+
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+PROJECT_ROOT="$(cd "$SCRIPT_DIR/.." && pwd)"
+
+LOG_DIR=$(mktemp -d)
+SERVER_PID=""
+
+stop_server() {
+    if [ -n "$SERVER_PID" ]; then
+        kill "$SERVER_PID" 2>/dev/null || true
+        wait "$SERVER_PID" 2>/dev/null || true
+        SERVER_PID=""
+    fi
+}
+trap stop_server EXIT
+
+FAILURES=0
+
+check() {
+    local description="$1"
+    shift
+    if "$@"; then
+        print_status "green" "$description"
+    else
+        print_status "red" "$description"
+        FAILURES=$((FAILURES + 1))
+    fi
+}
+
+# Start a server with the given policy (and extra flags) on a free port; sets SERVER_ADDR.
+# Its metrics are dumped to $LOG_DIR/<name>.metrics when it stops.
+start_server() {
+    local name="$1"
+    shift
+    RUST_LOG=info ./target/debug/ads-server 0 --port-file "$LOG_DIR/$name.port" \
+        --metrics-dump "$LOG_DIR/$name.metrics" --metrics-dump-format openmetrics "$@" \
+        > "$LOG_DIR/$name.log" 2>&1 &
+    SERVER_PID=$!
+    local port
+    if ! port=$(wait_for_port_file "$LOG_DIR/$name.port"); then
+        print_status "red" "Server did not announce its port - see $LOG_DIR/$name.log"
+        exit 1
+    fi
+    SERVER_ADDR="http://127.0.0.1:$port"
+}
+
+# One session that disconnects as soon as version 2 arrives, then time for the
+# server to finish (or cancel) the refinement
+early_disconnect() {
+    ./target/debug/ads-client "$SERVER_ADDR" --min-acceptable-version 2 --selection-timeout-ms 1000 \
+        > "$LOG_DIR/$1.out" 2> "$LOG_DIR/$1.err" || true
+    sleep 2
+}
+
+cd "$PROJECT_ROOT/rust"
+if ! command_exists cargo; then
+    print_status "red" "Cargo not found. Please install Rust and Cargo."
+    exit 1
+fi
+
+print_status "blue" "Building Rust server and client..."
+cargo build --bin ads-server --bin ads-client
+
+# Exactly one early disconnect counted under the given policy
+counted_once() {
+    grep -q "^early_disconnects_total{policy=\"$2\"} 1\$" "$LOG_DIR/$1.metrics"
+}
+
+# 1. cancel (the default): the pending refinement is dropped
+start_server cancel --disconnect-policy cancel --session-journal "$LOG_DIR/cancel.jsonl"
+early_disconnect cancel
+stop_server
+check "cancel: refinement cancelled on disconnect" \
+    grep -q "Client disconnected mid-refinement - cancelling it" "$LOG_DIR/cancel.log"
+check "cancel: nothing journaled as undelivered" \
+    bash -c "! grep -q undelivered_version '$LOG_DIR/cancel.jsonl'"
+check "cancel: early disconnect counted" counted_once cancel cancel
+
+# 2. finish: version 3 is generated anyway and journaled as undelivered
+start_server finish --disconnect-policy finish --session-journal "$LOG_DIR/finish.jsonl"
+early_disconnect finish
+stop_server
+check "finish: refinement finished after disconnect" \
+    grep -q "Client disconnected mid-refinement - finishing it for the journal" "$LOG_DIR/finish.log"
+check "finish: journal records the undelivered version 3" \
+    grep -q '"undelivered_version":3' "$LOG_DIR/finish.jsonl"
+check "finish: early disconnect counted" counted_once finish finish
+
+# 3. grace: the refinement finishes within the grace period and is checkpointed
+start_server grace --disconnect-policy grace --disconnect-grace-ms 5000 --session-checkpoints \
+    --session-journal "$LOG_DIR/grace.jsonl"
+early_disconnect grace
+stop_server
+check "grace: refinement kept for a reconnect" \
+    grep -q "Client disconnected mid-refinement - finishing it for a reconnect" "$LOG_DIR/grace.log"
+check "grace: finished within the grace period" \
+    bash -c "! grep -q 'Disconnect grace period expired' '$LOG_DIR/grace.log'"
+check "grace: journal records the undelivered version 3" \
+    grep -q '"undelivered_version":3' "$LOG_DIR/grace.jsonl"
+check "grace: early disconnect counted" counted_once grace grace
+
+# 4. grace shorter than the refinement delay: the refinement is cut off
+start_server grace_expired --disconnect-policy grace --disconnect-grace-ms 1 --session-checkpoints
+early_disconnect grace_expired
+stop_server
+check "grace: expired grace period cancels the refinement" \
+    grep -q "Disconnect grace period expired - refinement cancelled" "$LOG_DIR/grace_expired.log"
+check "grace: expired early disconnect counted" counted_once grace_expired grace
+
+if [ "$FAILURES" -eq 0 ]; then
+    print_status "green" "All disconnect tests passed"
+    rm -rf "$LOG_DIR"
+else
+    print_status "red" "$FAILURES disconnect test(s) failed - logs kept in $LOG_DIR"
+    exit 1
+fi
@@ -0,0 +1,84 @@
+#!/bin/bash
+
+# Session feedback test for the Rust server
+# An --auto client probes each RPC shape in turn (bidirectional, server-streaming,
+# unary) and reports the version each session used. Every report must match a
+# session the server opened in its work ledger, and every session's work must be
+# settled by its report rather than counted as unreported.
+
+set -e
+
+# Source common utilities
+source "$(dirname "$0")/common.sh"
+
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+PROJECT_ROOT="$(cd "$SCRIPT_DIR/.." && pwd)"
+
+LOG_DIR=$(mktemp -d)
+SERVER_PID=""
+
+stop_server() {
+    if [ -n "$SERVER_PID" ]; then
+        kill "$SERVER_PID" 2>/dev/null || true
+        wait "$SERVER_PID" 2>/dev/null || true
+        SERVER_PID=""
+    fi
+}
+trap stop_server EXIT
+
+FAILURES=0
+
+check() {
+    local description="$1"
+    shift
+    if "$@"; then
+        print_status "green" "$description"
+    else
+        print_status "red" "$description"
+        FAILURES=$((FAILURES + 1))
+    fi
+}
+
+cd "$PROJECT_ROOT/rust"
+if ! command_exists cargo; then
+    print_status "red" "Cargo not found. Please install Rust and Cargo."
+    exit 1
+fi
+
+print_status "blue" "Building Rust server and client..."
+cargo build --bin ads-server --bin ads-client
+
+RUST_LOG=info ./target/debug/ads-server 0 --port-file "$LOG_DIR/server.port" \
+    --metrics-dump "$LOG_DIR/metrics.txt" --metrics-dump-format openmetrics \
+    > "$LOG_DIR/server.log" 2>&1 &
+SERVER_PID=$!
+if ! port=$(wait_for_port_file "$LOG_DIR/server.port"); then
+    print_status "red" "Server did not announce its port - see $LOG_DIR/server.log"
+    exit 1
+fi
+
+# The first three --auto sessions probe one shape each
+./target/debug/ads-client "http://127.0.0.1:$port" --auto --sessions 3 --report-feedback \
+    > "$LOG_DIR/client.out" 2> "$LOG_DIR/client.err" || true
+# Let the last session finish its refinement before the metrics are dumped
+sleep 1
+stop_server
+
+check "server ran a server-streaming session" \
+    grep -q "New server-streaming session opened" "$LOG_DIR/server.log"
+check "server ran a unary session" \
+    grep -q "Sending unary AdsList" "$LOG_DIR/server.log"
+check "feedback of all three sessions matched" \
+    grep -q '^session_feedback_total{matched="true"} 3$' "$LOG_DIR/metrics.txt"
+check "no feedback went unmatched" \
+    bash -c "! grep -q 'session_feedback_total{matched=\"false\"}' '$LOG_DIR/metrics.txt'"
+check "no session work left unreported" \
+    bash -c "! grep -q 'generation_work_us_total{use=\"unreported\"}' '$LOG_DIR/metrics.txt'"
+
+if [ "$FAILURES" -eq 0 ]; then
+    print_status "green" "All feedback tests passed"
+    rm -rf "$LOG_DIR"
+else
+    print_status "red" "$FAILURES feedback test(s) failed - logs kept in $LOG_DIR"
+    exit 1
+fi
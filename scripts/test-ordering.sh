@@ -0,0 +1,92 @@
+#!/bin/bash
+
+# Response ordering test for the Rust server
+# Reproduces the delayed-version race: a client that keeps sending Contexts gets
+# versions 3 and 4 immediately, then the delayed version 3 scheduled after
+# Context 2 arrives last. The server's order watchdog must flag it (without
+# failing the stream) and the plain two-Context flow must stay in order.
+
+set -e
+
+# Source common utilities
+source "$(dirname "$0")/common.sh"
+
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+PROJECT_ROOT="$(cd "$SCRIPT_DIR/.." && pwd)"
+
+# Port 0 by default: the server picks a free port and announces it, so parallel runs don't collide
+TEST_PORT=${TEST_PORT:-0}
+LOG_DIR=$(mktemp -d)
+SERVER_PID=""
+
+cleanup() {
+    if [ -n "$SERVER_PID" ]; then
+        kill "$SERVER_PID" 2>/dev/null || true
+        wait "$SERVER_PID" 2>/dev/null || true
+    fi
+}
+trap cleanup EXIT
+
+FAILURES=0
+
+check() {
+    local description="$1"
+    shift
+    if "$@"; then
+        print_status "green" "$description"
+    else
+        print_status "red" "$description"
+        FAILURES=$((FAILURES + 1))
+    fi
+}
+
+# Versions in the order the client printed them (dynamic mode prints one JSON AdsList per line)
+received_versions() {
+    grep -o '"version":[0-9]*' "$1" | cut -d: -f2 | tr '\n' ' ' | sed 's/ $//'
+}
+
+cd "$PROJECT_ROOT/rust"
+if ! command_exists cargo; then
+    print_status "red" "Cargo not found. Please install Rust and Cargo."
+    exit 1
+fi
+
+print_status "blue" "Building Rust server and client..."
+cargo build --bin ads-server --bin ads-client
+
+print_status "blue" "Starting Rust server on port $TEST_PORT"
+RUST_LOG=info ./target/debug/ads-server "$TEST_PORT" --port-file "$LOG_DIR/server.port" > "$LOG_DIR/server.log" 2>&1 &
+SERVER_PID=$!
+if ! TEST_PORT=$(wait_for_port_file "$LOG_DIR/server.port"); then
+    print_status "red" "Server did not announce its port - see $LOG_DIR/server.log"
+    exit 1
+fi
+SERVER_ADDR="http://127.0.0.1:$TEST_PORT"
+print_status "blue" "Server listening on port $TEST_PORT"
+
+# 1. Normal two-Context session: versions must arrive as 1 2 3
+./target/debug/ads-client "$SERVER_ADDR" --dynamic GetAds --request-json \
+    '[{"query":"coffee maker","asin_id":"B000123"},{"query":"coffee maker","asin_id":"B000123","understanding":"espresso"}]' \
+    > "$LOG_DIR/two_contexts.out" 2> "$LOG_DIR/two_contexts.err"
+check "Two-Context session arrives in version order (got: $(received_versions "$LOG_DIR/two_contexts.out"))" \
+    test "$(received_versions "$LOG_DIR/two_contexts.out")" = "1 2 3"
+
+# 2. Four Contexts back to back: v3 and v4 overtake the delayed v3
+./target/debug/ads-client "$SERVER_ADDR" --dynamic GetAds --request-json \
+    '[{"query":"coffee maker","asin_id":"B000123"},{"query":"coffee maker","asin_id":"B000123","understanding":"espresso"},{"query":"coffee maker","asin_id":"B000123"},{"query":"coffee maker","asin_id":"B000123"}]' \
+    > "$LOG_DIR/four_contexts.out" 2> "$LOG_DIR/four_contexts.err"
+versions=$(received_versions "$LOG_DIR/four_contexts.out")
+check "Race session still delivers every version (got: $versions)" \
+    test "$(echo "$versions" | wc -w)" -eq 5
+check "Delayed version 3 arrives after version 4" \
+    test "${versions##* }" = "3"
+check "Server watchdog flagged the out-of-order emission" \
+    grep -q "AdsList emitted out of version order" "$LOG_DIR/server.log"
+
+if [ "$FAILURES" -eq 0 ]; then
+    print_status "green" "All ordering tests passed"
+    rm -rf "$LOG_DIR"
+else
+    print_status "red" "$FAILURES ordering test(s) failed - logs kept in $LOG_DIR"
+    exit 1
+fi